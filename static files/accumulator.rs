@@ -0,0 +1,219 @@
+//! Pre-merge header accumulator over the Headers segment, as used by the Portal network to prove
+//! historical headers.
+
+use alloy_primitives::{BlockNumber, B256, U256};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Number of headers in an epoch of the accumulator.
+pub const EPOCH_SIZE: u64 = 8192;
+
+/// First post-merge block of mainnet. The accumulator covers all blocks before it.
+pub const MERGE_BLOCK: BlockNumber = 15_537_394;
+
+/// Depth of the merkle tree of an epoch.
+const EPOCH_DEPTH: usize = EPOCH_SIZE.trailing_zeros() as usize;
+
+/// File name of the epoch roots sidecar in the static files directory.
+pub const EPOCH_ROOTS_FILE_NAME: &str = "headers_epoch_roots";
+
+/// Returns the epoch of the block.
+pub const fn epoch(block: BlockNumber) -> u64 {
+    block / EPOCH_SIZE
+}
+
+/// Returns the last block of the epoch, capped at the last pre-merge block.
+pub fn epoch_end(epoch: u64) -> BlockNumber {
+    ((epoch + 1) * EPOCH_SIZE).min(MERGE_BLOCK) - 1
+}
+
+/// Header record of an epoch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderRecord {
+    /// Hash of the header.
+    pub block_hash: B256,
+    /// Total difficulty at the header.
+    pub total_difficulty: U256,
+}
+
+impl HeaderRecord {
+    /// Returns the SSZ hash tree root of the record.
+    pub fn root(&self) -> B256 {
+        hash_pair(&self.block_hash, &total_difficulty_chunk(self.total_difficulty))
+    }
+}
+
+/// Accumulator of the header records of a single epoch.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EpochAccumulator {
+    records: Vec<HeaderRecord>,
+}
+
+impl EpochAccumulator {
+    /// Creates a new [`EpochAccumulator`] from the header records of an epoch, in block order.
+    pub fn new(records: Vec<HeaderRecord>) -> Self {
+        debug_assert!(records.len() as u64 <= EPOCH_SIZE);
+        Self { records }
+    }
+
+    /// Returns the SSZ hash tree root of the epoch.
+    pub fn root(&self) -> B256 {
+        let mut layer = self.records.iter().map(HeaderRecord::root).collect::<Vec<_>>();
+        for zero in zero_hashes().iter().take(EPOCH_DEPTH) {
+            layer = next_layer(&layer, zero);
+        }
+        hash_pair(
+            &layer.first().copied().unwrap_or(zero_hashes()[EPOCH_DEPTH]),
+            &self.length_chunk(),
+        )
+    }
+
+    /// Returns the proof of the header at the index within the epoch.
+    pub fn proof(&self, index: usize) -> Option<HeaderProof> {
+        let record = self.records.get(index)?;
+
+        let mut proof = Vec::with_capacity(EPOCH_DEPTH + 2);
+        proof.push(total_difficulty_chunk(record.total_difficulty));
+
+        let mut layer = self.records.iter().map(HeaderRecord::root).collect::<Vec<_>>();
+        let mut position = index;
+        for zero in zero_hashes().iter().take(EPOCH_DEPTH) {
+            proof.push(layer.get(position ^ 1).copied().unwrap_or(*zero));
+            layer = next_layer(&layer, zero);
+            position /= 2;
+        }
+        proof.push(self.length_chunk());
+
+        Some(HeaderProof { index, block_hash: record.block_hash, proof })
+    }
+
+    fn length_chunk(&self) -> B256 {
+        let mut chunk = B256::ZERO;
+        chunk[..8].copy_from_slice(&(self.records.len() as u64).to_le_bytes());
+        chunk
+    }
+}
+
+/// Merkle proof of a header against the root of its epoch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderProof {
+    /// Index of the header within the epoch.
+    pub index: usize,
+    /// Hash of the proven header.
+    pub block_hash: B256,
+    /// Total difficulty chunk, siblings from the leaf up, and the length of the epoch.
+    pub proof: Vec<B256>,
+}
+
+impl HeaderProof {
+    /// Returns `true` if the proof of the header matches the epoch root.
+    pub fn verify(&self, epoch_root: B256) -> bool {
+        let [total_difficulty, siblings @ .., length] = self.proof.as_slice() else { return false };
+        if siblings.len() != EPOCH_DEPTH {
+            return false
+        }
+
+        let mut node = hash_pair(&self.block_hash, total_difficulty);
+        for (depth, sibling) in siblings.iter().enumerate() {
+            node = if (self.index >> depth) & 1 == 1 {
+                hash_pair(sibling, &node)
+            } else {
+                hash_pair(&node, sibling)
+            };
+        }
+        hash_pair(&node, length) == epoch_root
+    }
+}
+
+/// Reads the epoch roots sidecar from the static files directory. Returns no roots if the sidecar
+/// doesn't exist.
+pub fn read_epoch_roots(directory: &Path) -> io::Result<Vec<B256>> {
+    let data = match std::fs::read(directory.join(EPOCH_ROOTS_FILE_NAME)) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(data.chunks_exact(32).map(B256::from_slice).collect())
+}
+
+/// Appends epoch roots to the sidecar in the static files directory.
+pub fn append_epoch_roots(directory: &Path, roots: &[B256]) -> io::Result<()> {
+    let mut file =
+        OpenOptions::new().create(true).append(true).open(directory.join(EPOCH_ROOTS_FILE_NAME))?;
+    for root in roots {
+        file.write_all(root.as_slice())?;
+    }
+    file.sync_all()
+}
+
+fn hash_pair(left: &B256, right: &B256) -> B256 {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    B256::from_slice(&hasher.finalize())
+}
+
+fn total_difficulty_chunk(total_difficulty: U256) -> B256 {
+    B256::from(total_difficulty.to_le_bytes::<32>())
+}
+
+/// Hashes pairs of nodes, padding the layer with the zero hash of its depth.
+fn next_layer(layer: &[B256], zero: &B256) -> Vec<B256> {
+    layer.chunks(2).map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(zero))).collect()
+}
+
+/// Returns the roots of empty subtrees, by depth.
+fn zero_hashes() -> [B256; EPOCH_DEPTH + 1] {
+    let mut hashes = [B256::ZERO; EPOCH_DEPTH + 1];
+    for depth in 1..=EPOCH_DEPTH {
+        hashes[depth] = hash_pair(&hashes[depth - 1], &hashes[depth - 1]);
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_proof() {
+        let records = (0..100u64)
+            .map(|block| HeaderRecord {
+                block_hash: B256::with_last_byte(block as u8),
+                total_difficulty: U256::from(block * 1_000),
+            })
+            .collect::<Vec<_>>();
+        let accumulator = EpochAccumulator::new(records);
+        let root = accumulator.root();
+
+        for index in [0, 1, 42, 99] {
+            let proof = accumulator.proof(index).unwrap();
+            assert_eq!(proof.proof.len(), 15);
+            assert!(proof.verify(root));
+
+            let mut tampered = proof.clone();
+            tampered.block_hash = B256::repeat_byte(0xff);
+            assert!(!tampered.verify(root));
+        }
+        assert_eq!(accumulator.proof(100), None);
+        assert_eq!(epoch_end(epoch(MERGE_BLOCK - 1)), MERGE_BLOCK - 1);
+    }
+
+    #[test]
+    fn epoch_roots_sidecar() {
+        let directory = tempfile::tempdir().unwrap();
+        assert!(read_epoch_roots(directory.path()).unwrap().is_empty());
+
+        append_epoch_roots(directory.path(), &[B256::repeat_byte(1)]).unwrap();
+        append_epoch_roots(directory.path(), &[B256::repeat_byte(2), B256::repeat_byte(3)])
+            .unwrap();
+        assert_eq!(
+            read_epoch_roots(directory.path()).unwrap(),
+            vec![B256::repeat_byte(1), B256::repeat_byte(2), B256::repeat_byte(3)]
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Transaction boundaries of every block, stored in a sidecar next to every Transactions and
+//! Receipts static file.
+
+use crate::{sidecar::read_u64, Sidecar, SidecarWriter};
+use alloy_primitives::{BlockNumber, TxNumber};
+use reth_static_file_types::StaticFileSegment;
+use std::{
+    io::{self, Read},
+    ops::{Range, RangeInclusive},
+};
+
+/// Extension of the block boundaries sidecar.
+pub const BLOCK_BOUNDARIES_EXTENSION: &str = "blocks";
+
+/// Builds [`BlockBoundaries`] sidecars while receipts are copied to static files.
+pub type BlockBoundariesWriter = SidecarWriter<BlockBoundaries>;
+
+/// Builds [`TransactionBoundaries`] sidecars while transactions are copied to static files.
+pub type TransactionBoundariesWriter = SidecarWriter<TransactionBoundaries>;
+
+/// Transaction numbers of every block of a single Receipts static file, so the receipts of a
+/// block are found without the block body indices of the database.
+///
+/// Blocks of a static file are consecutive, so only the first block and transaction are stored,
+/// followed by the number of transactions of every block.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockBoundaries {
+    /// First indexed block.
+    first_block: BlockNumber,
+    /// First transaction number of every indexed block, starting at `first_block`.
+    tx_starts: Vec<TxNumber>,
+    /// Transaction number following the last transaction of the last indexed block.
+    tx_end: TxNumber,
+}
+
+impl BlockBoundaries {
+    /// Adds the transactions of the block to the index.
+    ///
+    /// Blocks must be added in ascending order without gaps. Blocks that are already indexed are
+    /// skipped.
+    pub fn add(&mut self, block: BlockNumber, first_tx: TxNumber, tx_count: u64) {
+        if self.tx_starts.is_empty() {
+            self.first_block = block;
+        } else if block < self.first_block + self.tx_starts.len() as u64 {
+            return
+        }
+        debug_assert_eq!(block, self.first_block + self.tx_starts.len() as u64);
+
+        self.tx_starts.push(first_tx);
+        self.tx_end = first_tx + tx_count;
+    }
+
+    /// Returns the indexed blocks, or `None` if nothing is indexed.
+    pub fn blocks(&self) -> Option<RangeInclusive<BlockNumber>> {
+        let len = self.tx_starts.len() as u64;
+        (len > 0).then(|| self.first_block..=self.first_block + len - 1)
+    }
+
+    /// Returns the transaction numbers of the block, or `None` if it's not indexed.
+    pub fn tx_range(&self, block: BlockNumber) -> Option<Range<TxNumber>> {
+        let index = block.checked_sub(self.first_block)? as usize;
+        let start = *self.tx_starts.get(index)?;
+        let end = self.tx_starts.get(index + 1).copied().unwrap_or(self.tx_end);
+        Some(start..end)
+    }
+}
+
+impl Sidecar for BlockBoundaries {
+    const SEGMENT: StaticFileSegment = StaticFileSegment::Receipts;
+    const EXTENSION: &'static str = BLOCK_BOUNDARIES_EXTENSION;
+
+    fn extend(&mut self, other: Self) {
+        let Some(blocks) = other.blocks() else { return };
+        for block in blocks {
+            if let Some(tx_range) = other.tx_range(block) {
+                self.add(block, tx_range.start, tx_range.end - tx_range.start);
+            }
+        }
+    }
+
+    fn truncate(&mut self, last_block: BlockNumber) {
+        let len = (last_block + 1).saturating_sub(self.first_block) as usize;
+        if len < self.tx_starts.len() {
+            self.tx_end = self.tx_starts[len];
+            self.tx_starts.truncate(len);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.tx_starts.is_empty()
+    }
+
+    /// Encodes the first block, the first transaction and the number of blocks as 64-bit
+    /// integers, followed by the number of transactions of every block as 32-bit integers, all
+    /// in little endian.
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.first_block.to_le_bytes());
+        buf.extend_from_slice(&self.tx_starts.first().copied().unwrap_or_default().to_le_bytes());
+        buf.extend_from_slice(&(self.tx_starts.len() as u64).to_le_bytes());
+        let ends = self.tx_starts.iter().skip(1).chain(std::iter::once(&self.tx_end));
+        for (start, end) in self.tx_starts.iter().zip(ends) {
+            buf.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        }
+    }
+
+    fn decode(mut buf: &[u8]) -> io::Result<Self> {
+        let first_block = read_u64(&mut buf)?;
+        let mut tx_end = read_u64(&mut buf)?;
+        let len = read_u64(&mut buf)?;
+
+        let mut tx_starts = Vec::with_capacity(len.min(buf.len() as u64 / 4) as usize);
+        for _ in 0..len {
+            let mut bytes = [0; 4];
+            buf.read_exact(&mut bytes)?;
+            tx_starts.push(tx_end);
+            tx_end += u32::from_le_bytes(bytes) as u64;
+        }
+        Ok(Self { first_block, tx_starts, tx_end })
+    }
+}
+
+/// [`BlockBoundaries`] of a single Transactions static file, so the transactions of a block are
+/// found without the block body indices of the database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionBoundaries(BlockBoundaries);
+
+impl TransactionBoundaries {
+    /// Adds the transactions of the block to the index, see [`BlockBoundaries::add`].
+    pub fn add(&mut self, block: BlockNumber, first_tx: TxNumber, tx_count: u64) {
+        self.0.add(block, first_tx, tx_count);
+    }
+
+    /// Returns the boundaries of the indexed blocks.
+    pub const fn boundaries(&self) -> &BlockBoundaries {
+        &self.0
+    }
+}
+
+impl Sidecar for TransactionBoundaries {
+    const SEGMENT: StaticFileSegment = StaticFileSegment::Transactions;
+    const EXTENSION: &'static str = BLOCK_BOUNDARIES_EXTENSION;
+
+    fn extend(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    fn truncate(&mut self, last_block: BlockNumber) {
+        self.0.truncate(last_block);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+
+    fn decode(buf: &[u8]) -> io::Result<Self> {
+        BlockBoundaries::decode(buf).map(Self)
+    }
+}
+
+impl BlockBoundariesWriter {
+    /// Adds the transactions of the block, flushing the pending index once the block belongs to
+    /// the next static file.
+    pub fn add(&mut self, block: BlockNumber, first_tx: TxNumber, tx_count: u64) -> io::Result<()> {
+        self.sidecar(block)?.add(block, first_tx, tx_count);
+        Ok(())
+    }
+}
+
+impl TransactionBoundariesWriter {
+    /// Adds the transactions of the block, flushing the pending index once the block belongs to
+    /// the next static file.
+    pub fn add(&mut self, block: BlockNumber, first_tx: TxNumber, tx_count: u64) -> io::Result<()> {
+        self.sidecar(block)?.add(block, first_tx, tx_count);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sidecar::static_files_in_range;
+
+    #[test]
+    fn block_boundaries_sidecar() {
+        let directory = tempfile::tempdir().unwrap();
+
+        let mut writer = BlockBoundariesWriter::new(directory.path());
+        writer.add(1, 0, 2).unwrap();
+        writer.add(2, 2, 0).unwrap();
+        writer.flush().unwrap();
+
+        // Appended blocks are merged, and already indexed blocks are skipped
+        writer.add(2, 2, 0).unwrap();
+        writer.add(3, 2, 3).unwrap();
+        writer.flush().unwrap();
+
+        let entries =
+            static_files_in_range(directory.path(), StaticFileSegment::Receipts, &(0..=10));
+        let boundaries = BlockBoundaries::read(&entries[0]).unwrap();
+        assert_eq!(boundaries.blocks(), Some(1..=3));
+        assert_eq!(boundaries.tx_range(0), None);
+        assert_eq!(boundaries.tx_range(1), Some(0..2));
+        assert_eq!(boundaries.tx_range(2), Some(2..2));
+        assert_eq!(boundaries.tx_range(3), Some(2..5));
+        assert_eq!(boundaries.tx_range(4), None);
+        assert!(entries[0].companion_path(BLOCK_BOUNDARIES_EXTENSION).exists());
+
+        // Transactions static files get their own sidecar
+        let mut writer = TransactionBoundariesWriter::new(directory.path());
+        writer.add(1, 0, 2).unwrap();
+        writer.flush().unwrap();
+        let entries =
+            static_files_in_range(directory.path(), StaticFileSegment::Transactions, &(0..=10));
+        let boundaries = TransactionBoundaries::read(&entries[0]).unwrap();
+        assert_eq!(boundaries.boundaries().tx_range(1), Some(0..2));
+        assert_eq!(boundaries.boundaries().tx_range(2), None);
+    }
+}
@@ -0,0 +1,447 @@
+//! Sources of blocks that static files are produced from instead of the local database, so an
+//! archive can be built on a machine that never ran a full sync.
+
+use alloy_primitives::{BlockHash, BlockNumber, U256};
+use reth_primitives::{
+    proofs::{calculate_receipt_root_no_memo, calculate_transaction_root},
+    Block, Header, Receipt, SealedHeader, TransactionSigned, TxType,
+};
+use reth_rpc_types::{Block as RpcBlock, ReceiptEnvelope, TransactionReceipt};
+use reth_static_file_types::StaticFileSegment;
+use serde_json::{json, Value};
+use std::{fmt, io, ops::RangeInclusive};
+
+/// Default number of blocks requested from the [`RpcTransport`] in a single batch.
+pub const DEFAULT_RPC_BATCH_SIZE: usize = 100;
+
+/// Block fetched from a [`BlockSource`], with the parts needed by a single segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceBlock {
+    /// Sealed header of the block.
+    pub header: SealedHeader,
+    /// Total difficulty of the chain up to and including the block, if the source knows it.
+    ///
+    /// It's never copied as is, but checked against the total difficulty accumulated from the
+    /// difficulties of the headers.
+    pub total_difficulty: Option<U256>,
+    /// Transactions of the block. Only fetched for [`StaticFileSegment::Transactions`].
+    pub transactions: Vec<TransactionSigned>,
+    /// Receipts of the transactions of the block. Only fetched for
+    /// [`StaticFileSegment::Receipts`].
+    pub receipts: Vec<Receipt>,
+}
+
+/// Block trusted by the producer, that the blocks fetched from a [`BlockSource`] are chained to
+/// when static files don't have the block before them.
+///
+/// Either the block before the first copied block, or the first copied block itself, e.g. the
+/// genesis block of the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedCheckpoint {
+    /// Number of the block.
+    pub number: BlockNumber,
+    /// Hash of the block.
+    pub hash: BlockHash,
+    /// Total difficulty of the chain up to and including the block. Only needed to copy the
+    /// headers following a block other than the genesis block.
+    pub total_difficulty: Option<U256>,
+}
+
+/// Source of blocks that static files are produced from instead of the local database, set with
+/// [`StaticFileProducerInner::set_block_source`](crate::StaticFileProducerInner::set_block_source).
+///
+/// Fetched blocks aren't trusted: they're checked with [`verify_blocks`] before being copied.
+pub trait BlockSource: Send + Sync + fmt::Debug {
+    /// Fetches the blocks of the range in ascending order, with the transactions or receipts
+    /// needed by the segment.
+    fn blocks(
+        &self,
+        block_range: RangeInclusive<BlockNumber>,
+        segment: StaticFileSegment,
+    ) -> Result<Vec<SourceBlock>, BlockSourceError>;
+}
+
+/// Error returned by a [`BlockSource`], or by [`verify_blocks`].
+#[derive(Debug)]
+pub enum BlockSourceError {
+    /// Transport error.
+    Io(io::Error),
+    /// The source returned an error for a request.
+    Rpc(String),
+    /// The response of the source couldn't be decoded.
+    InvalidResponse(String),
+    /// The source doesn't have the block.
+    MissingBlock(BlockNumber),
+    /// Block doesn't match its hash, its parent, the trusted checkpoint, the roots of its header
+    /// or its total difficulty.
+    Unverified {
+        /// Number of the block.
+        block: BlockNumber,
+        /// What didn't match.
+        reason: &'static str,
+    },
+}
+
+impl From<io::Error> for BlockSourceError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl fmt::Display for BlockSourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::Rpc(err) => write!(f, "block source returned an error: {err}"),
+            Self::InvalidResponse(err) => write!(f, "invalid block source response: {err}"),
+            Self::MissingBlock(block) => write!(f, "block source doesn't have block {block}"),
+            Self::Unverified { block, reason } => {
+                write!(f, "block {block} from the block source failed verification: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockSourceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Verifies the blocks fetched for the segment: they have to be the blocks of the range in
+/// order, match their hashes, and be chained to each other and to the `parent` hash, if known.
+/// The block of the `checkpoint`, if in the range, has to have its hash.
+///
+/// Transactions are verified against the transactions root of their header, and receipts
+/// against its receipts root. Receipts predating the status field can't be represented, so their
+/// blocks are never verified.
+pub fn verify_blocks(
+    block_range: RangeInclusive<BlockNumber>,
+    blocks: &[SourceBlock],
+    segment: StaticFileSegment,
+    mut parent: Option<BlockHash>,
+    checkpoint: Option<&TrustedCheckpoint>,
+) -> Result<(), BlockSourceError> {
+    let mut blocks = blocks.iter();
+    for number in block_range {
+        let Some(block) = blocks.next() else { return Err(BlockSourceError::MissingBlock(number)) };
+        let unverified = |reason| Err(BlockSourceError::Unverified { block: number, reason });
+
+        let header = &block.header;
+        if header.number != number {
+            return unverified("unexpected block number")
+        }
+        if header.header().hash_slow() != header.hash() {
+            return unverified("header hash")
+        }
+        if parent.is_some_and(|parent| parent != header.parent_hash) {
+            return unverified("parent hash")
+        }
+        if checkpoint.is_some_and(|checkpoint| {
+            checkpoint.number == number && checkpoint.hash != header.hash()
+        }) {
+            return unverified("checkpoint hash")
+        }
+        parent = Some(header.hash());
+
+        match segment {
+            StaticFileSegment::Headers => {}
+            StaticFileSegment::Transactions => {
+                if calculate_transaction_root(&block.transactions) != header.transactions_root {
+                    return unverified("transactions root")
+                }
+            }
+            StaticFileSegment::Receipts => {
+                let receipts = block.receipts.iter().collect::<Vec<_>>();
+                if calculate_receipt_root_no_memo(&receipts) != header.receipts_root {
+                    return unverified("receipts root")
+                }
+            }
+        }
+    }
+
+    match blocks.next() {
+        Some(block) => Err(BlockSourceError::Unverified {
+            block: block.header.number,
+            reason: "unexpected block number",
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Transport of JSON-RPC requests to an upstream node, e.g. over HTTP.
+pub trait RpcTransport: Send + Sync {
+    /// Sends the batch of JSON-RPC requests, returning their responses in any order.
+    fn send_batch(&self, requests: &[Value]) -> io::Result<Vec<Value>>;
+}
+
+/// [`BlockSource`] fetching blocks from an upstream node with `eth_getBlockByNumber` and
+/// `eth_getBlockReceipts`, in batches of up to [`DEFAULT_RPC_BATCH_SIZE`] blocks.
+pub struct RpcBlockSource<T> {
+    /// Transport of the requests.
+    transport: T,
+    /// Number of blocks requested in a single batch.
+    batch_size: usize,
+}
+
+impl<T: RpcTransport> RpcBlockSource<T> {
+    /// Creates a new [`RpcBlockSource`] with [`DEFAULT_RPC_BATCH_SIZE`].
+    pub const fn new(transport: T) -> Self {
+        Self { transport, batch_size: DEFAULT_RPC_BATCH_SIZE }
+    }
+
+    /// Sets the number of blocks requested in a single batch.
+    pub const fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Calls the method once for every parameters, in a single batch, returning the results in
+    /// the order of the parameters.
+    fn call_batch(&self, method: &str, params: Vec<Value>) -> Result<Vec<Value>, BlockSourceError> {
+        let requests = params
+            .into_iter()
+            .enumerate()
+            .map(|(id, params)| json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .collect::<Vec<_>>();
+
+        let mut results = vec![None; requests.len()];
+        for mut response in self.transport.send_batch(&requests)? {
+            if let Some(error) = response.get("error") {
+                return Err(BlockSourceError::Rpc(error.to_string()))
+            }
+            let id = response.get("id").and_then(Value::as_u64);
+            let Some(result) = id.and_then(|id| results.get_mut(id as usize)) else {
+                return Err(BlockSourceError::InvalidResponse(format!(
+                    "unexpected response id {id:?}"
+                )))
+            };
+            *result = Some(response["result"].take());
+        }
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.ok_or_else(|| {
+                    BlockSourceError::InvalidResponse(format!("missing response to {method}"))
+                })
+            })
+            .collect()
+    }
+}
+
+impl<T> fmt::Debug for RpcBlockSource<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RpcBlockSource")
+            .field("batch_size", &self.batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: RpcTransport> BlockSource for RpcBlockSource<T> {
+    fn blocks(
+        &self,
+        block_range: RangeInclusive<BlockNumber>,
+        segment: StaticFileSegment,
+    ) -> Result<Vec<SourceBlock>, BlockSourceError> {
+        let numbers = block_range.collect::<Vec<_>>();
+        let full = segment == StaticFileSegment::Transactions;
+
+        let mut blocks = Vec::with_capacity(numbers.len());
+        for batch in numbers.chunks(self.batch_size.max(1)) {
+            let responses = self.call_batch(
+                "eth_getBlockByNumber",
+                batch.iter().map(|number| json!([format!("{number:#x}"), full])).collect(),
+            )?;
+            let mut receipts = if segment.is_receipts() {
+                self.call_batch(
+                    "eth_getBlockReceipts",
+                    batch.iter().map(|number| json!([format!("{number:#x}")])).collect(),
+                )?
+            } else {
+                vec![Value::Null; batch.len()]
+            };
+
+            for ((number, response), receipts) in batch.iter().zip(responses).zip(&mut receipts) {
+                if response.is_null() {
+                    return Err(BlockSourceError::MissingBlock(*number))
+                }
+                let block =
+                    serde_json::from_value::<RpcBlock>(response).map_err(invalid_response)?;
+                blocks.push(source_block(block, full, receipts.take())?);
+            }
+        }
+
+        Ok(blocks)
+    }
+}
+
+/// Converts the RPC block, with full transactions if `full` is set, and its receipts, if they
+/// were fetched, into a [`SourceBlock`].
+fn source_block(
+    block: RpcBlock,
+    full: bool,
+    receipts: Value,
+) -> Result<SourceBlock, BlockSourceError> {
+    let total_difficulty = block.header.total_difficulty;
+    let hash = block
+        .header
+        .hash
+        .ok_or_else(|| BlockSourceError::InvalidResponse("block without hash".to_string()))?;
+
+    let (header, transactions) = if full {
+        let block = Block::try_from(block).map_err(invalid_response)?;
+        (block.header, block.body)
+    } else {
+        (Header::try_from(block.header).map_err(invalid_response)?, Vec::new())
+    };
+
+    let receipts = if receipts.is_null() {
+        Vec::new()
+    } else {
+        serde_json::from_value::<Vec<TransactionReceipt>>(receipts)
+            .map_err(invalid_response)?
+            .iter()
+            .map(receipt)
+            .collect::<Result<_, _>>()?
+    };
+
+    Ok(SourceBlock {
+        header: SealedHeader::new(header, hash),
+        total_difficulty,
+        transactions,
+        receipts,
+    })
+}
+
+/// Converts the RPC receipt into a [`Receipt`].
+fn receipt(receipt: &TransactionReceipt) -> Result<Receipt, BlockSourceError> {
+    let tx_type = match &receipt.inner {
+        ReceiptEnvelope::Legacy(_) => TxType::Legacy,
+        ReceiptEnvelope::Eip2930(_) => TxType::Eip2930,
+        ReceiptEnvelope::Eip1559(_) => TxType::Eip1559,
+        ReceiptEnvelope::Eip4844(_) => TxType::Eip4844,
+        _ => return Err(BlockSourceError::InvalidResponse("unsupported receipt type".to_string())),
+    };
+    let Some(inner) = receipt.inner.as_receipt() else {
+        return Err(BlockSourceError::InvalidResponse("unsupported receipt type".to_string()))
+    };
+    // Receipts only keep their status, so the post-state root of older receipts would be lost
+    if !inner.status.is_eip658() {
+        return Err(BlockSourceError::InvalidResponse(
+            "receipt without status, predating EIP-658".to_string(),
+        ))
+    }
+
+    Ok(Receipt {
+        tx_type,
+        success: inner.status.coerce_status(),
+        cumulative_gas_used: inner.cumulative_gas_used as u64,
+        logs: inner.logs.iter().map(|log| log.inner.clone()).collect(),
+        ..Default::default()
+    })
+}
+
+/// Maps a decoding error into [`BlockSourceError::InvalidResponse`].
+fn invalid_response(err: impl fmt::Display) -> BlockSourceError {
+    BlockSourceError::InvalidResponse(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::fixture_blocks;
+    use alloy_primitives::B256;
+    use reth_primitives::SealedBlock;
+    use reth_rpc_types::BlockTransactionsKind;
+    use reth_rpc_types_compat::block::from_block;
+
+    /// Upstream node serving the fixture blocks, answering batches in reverse order.
+    struct FixtureNode(Vec<SealedBlock>);
+
+    impl RpcTransport for FixtureNode {
+        fn send_batch(&self, requests: &[Value]) -> io::Result<Vec<Value>> {
+            Ok(requests
+                .iter()
+                .rev()
+                .map(|request| {
+                    let number = request["params"][0].as_str().unwrap();
+                    let number = u64::from_str_radix(number.trim_start_matches("0x"), 16).unwrap();
+                    let full = request["params"][1].as_bool().unwrap();
+                    let result = self.0.get(number as usize).map(|block| {
+                        let kind = if full {
+                            BlockTransactionsKind::Full
+                        } else {
+                            BlockTransactionsKind::Hashes
+                        };
+                        let block = block.clone().seal_with_senders().unwrap().unseal();
+                        let hash = block.header.hash_slow();
+                        from_block(block, U256::from(number), kind, Some(hash)).unwrap()
+                    });
+                    json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn fetches_verified_blocks() {
+        let blocks = fixture_blocks(0..=3, B256::ZERO, 1..3);
+        let source = RpcBlockSource::new(FixtureNode(blocks.clone())).with_batch_size(3);
+
+        let fetched = source.blocks(0..=3, StaticFileSegment::Transactions).unwrap();
+        assert_eq!(
+            fetched.iter().map(|block| block.header.clone()).collect::<Vec<_>>(),
+            blocks.iter().map(|block| block.header.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(fetched[1].transactions, blocks[1].body);
+        assert_eq!(fetched[3].total_difficulty, Some(U256::from(3)));
+        let checkpoint =
+            TrustedCheckpoint { number: 0, hash: blocks[0].hash(), total_difficulty: None };
+        verify_blocks(
+            0..=3,
+            &fetched,
+            StaticFileSegment::Transactions,
+            Some(B256::ZERO),
+            Some(&checkpoint),
+        )
+        .unwrap();
+
+        // Headers are fetched without transactions
+        let headers = source.blocks(1..=2, StaticFileSegment::Headers).unwrap();
+        assert!(headers[0].transactions.is_empty());
+        verify_blocks(1..=2, &headers, StaticFileSegment::Headers, Some(blocks[0].hash()), None)
+            .unwrap();
+
+        // Tampered blocks are rejected
+        let mut tampered = fetched.clone();
+        tampered[2].transactions.clear();
+        assert!(matches!(
+            verify_blocks(0..=3, &tampered, StaticFileSegment::Transactions, None, None),
+            Err(BlockSourceError::Unverified { block: 2, reason: "transactions root" })
+        ));
+        assert!(matches!(
+            verify_blocks(1..=3, &fetched[1..], StaticFileSegment::Headers, Some(B256::ZERO), None),
+            Err(BlockSourceError::Unverified { block: 1, reason: "parent hash" })
+        ));
+        assert!(matches!(
+            verify_blocks(0..=3, &fetched[..3], StaticFileSegment::Headers, None, None),
+            Err(BlockSourceError::MissingBlock(3))
+        ));
+        // Blocks of another chain don't match the checkpoint
+        let checkpoint = TrustedCheckpoint { hash: B256::ZERO, ..checkpoint };
+        assert!(matches!(
+            verify_blocks(0..=3, &fetched, StaticFileSegment::Headers, None, Some(&checkpoint)),
+            Err(BlockSourceError::Unverified { block: 0, reason: "checkpoint hash" })
+        ));
+
+        // Blocks the node doesn't have
+        assert!(matches!(
+            source.blocks(3..=4, StaticFileSegment::Headers),
+            Err(BlockSourceError::MissingBlock(4))
+        ));
+    }
+}
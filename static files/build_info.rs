@@ -0,0 +1,10 @@
+//! Version and build of the static file producer, recorded in the static files and manifests it
+//! produces.
+
+use reth_static_file_types::BuildMetadata;
+
+/// Returns the [`BuildMetadata`] of this build of the producer. The git commit is taken from the
+/// `VERGEN_GIT_SHA` environment variable at build time, if set.
+pub fn build_metadata() -> BuildMetadata {
+    BuildMetadata::new(env!("CARGO_PKG_VERSION"), option_env!("VERGEN_GIT_SHA"))
+}
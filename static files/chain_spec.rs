@@ -0,0 +1,87 @@
+//! Chain specification the static file producer makes fork-aware decisions with.
+
+use crate::{list_static_files, StaticFileProducerError};
+use reth_chainspec::ChainSpec;
+use reth_nippy_jar::NippyJar;
+use reth_static_file_types::{SegmentHeader, SegmentRangeInclusive, StaticFileSegment};
+use reth_storage_errors::provider::ProviderError;
+use std::{fmt, path::Path};
+
+/// Parameters of the chain that static files are produced for, passed to segments with
+/// [`SegmentProgress::chain_spec`](crate::SegmentProgress::chain_spec).
+///
+/// Implemented for [`ChainSpec`], and small enough to implement for chains without one.
+pub trait StaticFileChainSpec: Send + Sync + fmt::Debug {
+    /// Returns the id of the chain, recorded in every produced static file.
+    fn chain_id(&self) -> u64;
+
+    /// Returns `true` if Cancun is active at the block timestamp, i.e. blocks may carry blob
+    /// transactions.
+    fn is_cancun_active(&self, timestamp: u64) -> bool;
+
+    /// Returns `true` if receipts of the chain may be deposit receipts, i.e. the chain is an OP
+    /// chain.
+    fn has_deposit_receipts(&self) -> bool;
+}
+
+impl StaticFileChainSpec for ChainSpec {
+    fn chain_id(&self) -> u64 {
+        self.chain.id()
+    }
+
+    fn is_cancun_active(&self, timestamp: u64) -> bool {
+        self.is_cancun_active_at_timestamp(timestamp)
+    }
+
+    fn has_deposit_receipts(&self) -> bool {
+        self.chain.is_optimism()
+    }
+}
+
+/// Static file of another chain than the one static files are produced for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainMismatch {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file.
+    pub block_range: SegmentRangeInclusive,
+    /// Id of the chain static files are produced for.
+    pub expected: u64,
+    /// Id of the chain recorded in the static file.
+    pub found: u64,
+}
+
+impl fmt::Display for ChainMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} static file {} belongs to chain {}, expected chain {}",
+            self.segment, self.block_range, self.found, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ChainMismatch {}
+
+/// Checks that every static file in the directory belongs to the chain. Static files without a
+/// recorded chain id predate it, and are assumed to belong to the chain. Returns the number of
+/// checked static files.
+pub fn check_chain_id(directory: &Path, chain_id: u64) -> Result<usize, StaticFileProducerError> {
+    let entries = list_static_files(directory)?;
+    for entry in &entries {
+        let jar = NippyJar::<SegmentHeader>::load(&entry.path)
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        match jar.user_header().chain_id() {
+            Some(found) if found != chain_id => {
+                return Err(StaticFileProducerError::ChainMismatch(ChainMismatch {
+                    segment: entry.segment,
+                    block_range: entry.block_range,
+                    expected: chain_id,
+                    found,
+                }))
+            }
+            _ => {}
+        }
+    }
+    Ok(entries.len())
+}
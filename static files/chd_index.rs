@@ -0,0 +1,79 @@
+//! [`ChdIndex`] perfect hashing function of static files, stored in a sidecar next to the data
+//! file, as `NippyJar` only builds the fmph functions.
+
+use reth_static_file_types::ChdIndex;
+use std::{
+    ffi::OsString,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Extension of the [`ChdIndex`] sidecar.
+pub const CHD_INDEX_EXTENSION: &str = "chd";
+
+/// Returns the path of the [`ChdIndex`] sidecar of the static file with the data file.
+fn chd_index_path(data_path: &Path) -> PathBuf {
+    let mut path = OsString::from(data_path);
+    path.push(".");
+    path.push(CHD_INDEX_EXTENSION);
+    path.into()
+}
+
+/// Builds the [`ChdIndex`] of the keys of the rows of the static file, in row order, and writes
+/// it next to its data file, replacing it atomically.
+pub(crate) fn write_chd_index<K: AsRef<[u8]>>(data_path: &Path, keys: &[K]) -> io::Result<()> {
+    let index =
+        ChdIndex::build(keys).map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    let path = chd_index_path(data_path);
+    let tmp_path = path.with_extension(format!("{CHD_INDEX_EXTENSION}.tmp"));
+
+    let mut buf = Vec::new();
+    index.encode(&mut buf);
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(&buf)?;
+    file.sync_all()?;
+    std::fs::rename(tmp_path, path)
+}
+
+/// Reads the [`ChdIndex`] of the static file with the data file.
+pub(crate) fn read_chd_index(data_path: &Path) -> io::Result<ChdIndex> {
+    let buf = std::fs::read(chd_index_path(data_path))?;
+    ChdIndex::decode(&buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Removes the [`ChdIndex`] of the static file with the data file, if any, e.g. once it's
+/// indexed with another perfect hashing function.
+pub(crate) fn remove_chd_index(data_path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(chd_index_path(data_path)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StaticFileEntry;
+    use reth_static_file_types::{find_fixed_range, StaticFileSegment};
+
+    #[test]
+    fn chd_index_sidecar() {
+        let directory = tempfile::tempdir().unwrap();
+        let block_range = find_fixed_range(0);
+        let entry = StaticFileEntry {
+            segment: StaticFileSegment::Receipts,
+            block_range,
+            path: directory.path().join(StaticFileSegment::Receipts.filename(&block_range)),
+        };
+
+        let keys = [[1u8; 32], [2; 32], [3; 32]];
+        write_chd_index(&entry.path, &keys).unwrap();
+        assert!(entry.companion_path(CHD_INDEX_EXTENSION).exists());
+        let index = read_chd_index(&entry.path).unwrap();
+        assert_eq!(index.row(&[3; 32]), Some(2));
+
+        remove_chd_index(&entry.path).unwrap();
+        remove_chd_index(&entry.path).unwrap();
+        assert!(read_chd_index(&entry.path).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Chunked archives of sealed static files, for HTTP mirrors and CDNs capping the size of hosted
+//! objects.
+//!
+//! Every file of a static file is split into fixed-size chunks named after it, e.g.
+//! `static_file_receipts_0_499999.part00003`, and listed with their hashes in a
+//! [`ChunkedArchive`] manifest, so the chunks can be verified before they're reassembled.
+//!
+//! The archive manifest is hosted next to the chunks, so it's only trusted once it hashes to the
+//! [`ChunkedArchive::root`] obtained from the producer through a trusted channel.
+
+use crate::{
+    files::is_plain_file_name,
+    manifest::{truncated_hash, write_json},
+    ChunkHashes, ManifestError, StaticFileEntry, COMPANION_EXTENSIONS,
+};
+use alloy_primitives::B256;
+use reth_static_file_types::{SegmentRangeInclusive, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/// Default size of the chunks of a [`ChunkedArchive`], below the object size cap of common CDNs.
+pub const DEFAULT_ARCHIVE_CHUNK_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// File of a static file, split into chunks in a [`ChunkedArchive`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    /// Name of the file in the static files directory: the data file, or a companion file.
+    pub file_name: String,
+    /// Hashes of the chunks of the file.
+    pub chunks: ChunkHashes,
+}
+
+/// Manifest of a sealed static file split into fixed-size chunks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkedArchive {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file.
+    pub block_range: SegmentRangeInclusive,
+    /// Data file followed by the companion files of the static file.
+    pub files: Vec<ArchivedFile>,
+}
+
+impl ChunkedArchive {
+    /// Splits the data and companion files of the static file into chunks of `chunk_size` bytes
+    /// in the `destination` directory, and writes the archive manifest next to them, named with
+    /// [`ChunkedArchive::manifest_file_name`].
+    ///
+    /// Files are read once, chunks are hashed while they're written.
+    pub fn export(
+        entry: &StaticFileEntry,
+        destination: &Path,
+        chunk_size: u64,
+    ) -> io::Result<Self> {
+        let chunk_size = chunk_size.max(1);
+        std::fs::create_dir_all(destination)?;
+
+        let mut files = Vec::new();
+        for path in entry.paths() {
+            let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+            let mut file = BufReader::new(File::open(&path)?);
+            let data_size = file.get_ref().metadata()?.len();
+
+            let mut hashes = Vec::new();
+            for chunk in 0..data_size.div_ceil(chunk_size) {
+                let len = (data_size - chunk * chunk_size).min(chunk_size);
+                let mut writer = HashingWriter {
+                    writer: BufWriter::new(File::create(
+                        destination.join(Self::chunk_file_name(file_name, chunk as usize)),
+                    )?),
+                    hasher: blake3::Hasher::new(),
+                };
+                io::copy(&mut (&mut file).take(len), &mut writer)?;
+                writer.flush()?;
+                hashes.push(truncated_hash(&writer.hasher));
+            }
+
+            files.push(ArchivedFile {
+                file_name: file_name.to_string(),
+                chunks: ChunkHashes { chunk_size, data_size, hashes },
+            });
+        }
+
+        let archive = Self { segment: entry.segment, block_range: entry.block_range, files };
+        archive.write(
+            &destination.join(Self::manifest_file_name(entry.segment, &entry.block_range)),
+        )?;
+        Ok(archive)
+    }
+
+    /// Returns the blake3 hash of the compact JSON encoding of the archive manifest, to be handed
+    /// to importers through a trusted channel.
+    pub fn root(&self) -> B256 {
+        let payload = serde_json::to_vec(self).expect("archive serialization is infallible");
+        B256::from(*blake3::hash(&payload).as_bytes())
+    }
+
+    /// Verifies the archive against the trusted `root` and the chunks in the `source` directory
+    /// against the archive, and reassembles them into the static files directory. Returns the
+    /// imported static file.
+    ///
+    /// Only the data file of the static file of the archive and its companion files can be
+    /// listed, so the archive can't write outside of the static files directory. All chunks are
+    /// verified before any file is reassembled, so a failed import leaves the static files
+    /// directory untouched. The data file is moved into place last, so the static file isn't
+    /// listed before its companion files are complete.
+    pub fn import(
+        &self,
+        root: B256,
+        source: &Path,
+        static_files_dir: &Path,
+    ) -> Result<StaticFileEntry, ManifestError> {
+        let actual = self.root();
+        if actual != root {
+            return Err(ManifestError::RootMismatch { expected: root, actual })
+        }
+        self.check_file_names()?;
+
+        for file in &self.files {
+            for (chunk, expected) in file.chunks.hashes.iter().enumerate() {
+                let chunk_name = Self::chunk_file_name(&file.file_name, chunk);
+                let path = source.join(&chunk_name);
+                let range = file.chunks.range(chunk);
+
+                let mut hasher = blake3::Hasher::new();
+                let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+                if size != range.end - range.start || truncated_hash(&hasher) != *expected {
+                    return Err(ManifestError::ContentMismatch { file_name: chunk_name })
+                }
+            }
+        }
+
+        std::fs::create_dir_all(static_files_dir)?;
+        for file in self.files.iter().rev() {
+            let path = static_files_dir.join(&file.file_name);
+            let tmp_path = static_files_dir.join(format!("{}.tmp", file.file_name));
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            for chunk in 0..file.chunks.hashes.len() {
+                let chunk_path = source.join(Self::chunk_file_name(&file.file_name, chunk));
+                io::copy(&mut File::open(chunk_path)?, &mut writer)?;
+            }
+            writer.flush()?;
+            drop(writer);
+            std::fs::rename(tmp_path, path)?;
+        }
+
+        Ok(StaticFileEntry {
+            segment: self.segment,
+            block_range: self.block_range,
+            path: static_files_dir.join(self.files.first().map_or_else(
+                || self.segment.filename(&self.block_range),
+                |file| file.file_name.clone(),
+            )),
+        })
+    }
+
+    /// Checks that the first listed file is the data file of the static file of the archive, and
+    /// the others are distinct companion files of it.
+    fn check_file_names(&self) -> Result<(), ManifestError> {
+        let Some((data_file, companions)) = self.files.split_first() else { return Ok(()) };
+        let invalid = |file: &ArchivedFile| ManifestError::InvalidFileName {
+            file_name: file.file_name.clone(),
+        };
+
+        if !is_plain_file_name(&data_file.file_name) ||
+            data_file.file_name.contains('.') ||
+            StaticFileSegment::parse_filename(&data_file.file_name) !=
+                Some((self.segment, self.block_range))
+        {
+            return Err(invalid(data_file))
+        }
+
+        let mut extensions = Vec::new();
+        for file in companions {
+            let extension = file
+                .file_name
+                .strip_prefix(data_file.file_name.as_str())
+                .and_then(|name| name.strip_prefix('.'))
+                .filter(|extension| COMPANION_EXTENSIONS.contains(extension))
+                .filter(|extension| !extensions.contains(extension))
+                .ok_or_else(|| invalid(file))?;
+            extensions.push(extension);
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the chunk of the file, e.g. `static_file_receipts_0_499999.part00003`.
+    pub fn chunk_file_name(file_name: &str, chunk: usize) -> String {
+        format!("{file_name}.part{chunk:05}")
+    }
+
+    /// Returns the name of the archive manifest of the static file, e.g.
+    /// `static_file_receipts_0_499999.chunks.json`.
+    pub fn manifest_file_name(
+        segment: StaticFileSegment,
+        block_range: &SegmentRangeInclusive,
+    ) -> String {
+        format!("{}.chunks.json", segment.filename(block_range))
+    }
+
+    /// Reads the archive manifest from a JSON file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes the archive manifest to a JSON file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_json(self, path)
+    }
+}
+
+/// Writer hashing the bytes it writes.
+struct HashingWriter<W> {
+    /// Underlying writer.
+    writer: W,
+    /// Hasher of the written bytes.
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::list_static_files;
+
+    #[test]
+    fn splits_and_reassembles_static_files() {
+        let node = tempfile::tempdir().unwrap();
+        for (name, content) in [
+            ("static_file_receipts_0_499999", b"receipts data".as_slice()),
+            ("static_file_receipts_0_499999.off", b"offsets"),
+        ] {
+            std::fs::write(node.path().join(name), content).unwrap();
+        }
+        let entry = list_static_files(node.path()).unwrap().remove(0);
+
+        let mirror = tempfile::tempdir().unwrap();
+        let archive = ChunkedArchive::export(&entry, mirror.path(), 4).unwrap();
+        assert_eq!(archive.files.len(), 2);
+        assert_eq!(archive.files[0].chunks.hashes.len(), 4);
+        assert_eq!(
+            std::fs::read(mirror.path().join("static_file_receipts_0_499999.part00003")).unwrap(),
+            b"a"
+        );
+        let manifest_path = mirror
+            .path()
+            .join(ChunkedArchive::manifest_file_name(entry.segment, &entry.block_range));
+        assert_eq!(ChunkedArchive::read(&manifest_path).unwrap(), archive);
+
+        let imported = tempfile::tempdir().unwrap();
+        let imported_entry =
+            archive.import(archive.root(), mirror.path(), imported.path()).unwrap();
+        assert_eq!(list_static_files(imported.path()).unwrap(), vec![imported_entry.clone()]);
+        assert_eq!(std::fs::read(&imported_entry.path).unwrap(), b"receipts data");
+        assert_eq!(std::fs::read(imported_entry.companion_path("off")).unwrap(), b"offsets");
+
+        // Corrupted chunk is rejected before anything is reassembled
+        std::fs::write(mirror.path().join("static_file_receipts_0_499999.off.part00001"), b"etz")
+            .unwrap();
+        let rejected = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            archive.import(archive.root(), mirror.path(), rejected.path()),
+            Err(ManifestError::ContentMismatch { file_name })
+                if file_name == "static_file_receipts_0_499999.off.part00001"
+        ));
+        assert!(std::fs::read_dir(rejected.path()).unwrap().next().is_none());
+
+        // Archive that doesn't hash to the trusted root is rejected
+        let mut tampered = archive.clone();
+        tampered.files[1].file_name = "../static_file_receipts_0_499999.off".to_string();
+        assert!(matches!(
+            tampered.import(archive.root(), mirror.path(), rejected.path()),
+            Err(ManifestError::RootMismatch { expected, actual })
+                if expected == archive.root() && actual == tampered.root()
+        ));
+
+        // Files escaping the static files directory or foreign to the static file are rejected,
+        // even if the archive hashes to the root
+        for file_name in [
+            "../static_file_receipts_0_499999.off",
+            "static_file_receipts_0_499999.off/../../x",
+            "static_file_receipts_0_499999.unknown",
+            "static_file_headers_0_499999.off",
+            "static_file_receipts_0_499999",
+        ] {
+            tampered.files[1].file_name = file_name.to_string();
+            assert!(matches!(
+                tampered.import(tampered.root(), mirror.path(), rejected.path()),
+                Err(ManifestError::InvalidFileName { file_name: invalid }) if invalid == file_name
+            ));
+        }
+        tampered.files[0].file_name = "/tmp/static_file_receipts_0_499999".to_string();
+        assert!(matches!(
+            tampered.import(tampered.root(), mirror.path(), rejected.path()),
+            Err(ManifestError::InvalidFileName { .. })
+        ));
+        assert!(std::fs::read_dir(rejected.path()).unwrap().next().is_none());
+    }
+}
@@ -0,0 +1,333 @@
+//! Merkle commitments over the rows of static files, so individual headers, transactions and
+//! receipts can be served with inclusion proofs against the published root of their static file.
+//!
+//! Every row is hashed into a leaf, and leaves are hashed pairwise up to the root of a binary tree
+//! padded with zero hashes, which is then mixed with the number of rows. The root is recorded in
+//! the [`SegmentHeader`] of a static file when it's sealed, see
+//! [`StaticFileProducerInner::set_row_commitments`](crate::StaticFileProducerInner::set_row_commitments),
+//! and listed in manifests, see
+//! [`StaticFileManifest::with_row_roots`](crate::StaticFileManifest::with_row_roots).
+
+use crate::{files::rewrite_config, StaticFileEntry};
+use alloy_primitives::{keccak256, B256};
+use reth_nippy_jar::{NippyJar, NippyJarCursor};
+use reth_static_file_types::SegmentHeader;
+use std::io;
+
+/// Domain of the hash of a row.
+const LEAF_DOMAIN: u8 = 0;
+
+/// Domain of the hash of a pair of nodes.
+const NODE_DOMAIN: u8 = 1;
+
+/// Merkle tree over the hashes of the rows of a static file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowCommitment {
+    /// Layers of the tree, from the leaves up to the root.
+    layers: Vec<Vec<B256>>,
+}
+
+impl Default for RowCommitment {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl RowCommitment {
+    /// Creates a new [`RowCommitment`] from the hashes of the rows, in row order, see
+    /// [`row_leaf`].
+    pub fn new(leaves: Vec<B256>) -> Self {
+        let depth = tree_depth(leaves.len() as u64);
+        let mut layers = Vec::with_capacity(depth + 1);
+        layers.push(leaves);
+        for zero in zero_hashes(depth).iter().take(depth) {
+            let layer = next_layer(&layers[layers.len() - 1], zero);
+            layers.push(layer);
+        }
+        Self { layers }
+    }
+
+    /// Hashes every row of the static file.
+    pub fn from_static_file(entry: &StaticFileEntry) -> io::Result<Self> {
+        let jar = NippyJar::<SegmentHeader>::load(&entry.path).map_err(io::Error::other)?;
+        Self::from_jar(&jar)
+    }
+
+    /// Hashes every row of the loaded static file.
+    fn from_jar(jar: &NippyJar<SegmentHeader>) -> io::Result<Self> {
+        let mut leaves = Vec::with_capacity(jar.rows());
+        for_each_leaf(jar, |leaf| leaves.push(leaf))?;
+        Ok(Self::new(leaves))
+    }
+
+    /// Returns the number of committed rows.
+    pub fn rows(&self) -> u64 {
+        self.layers[0].len() as u64
+    }
+
+    /// Returns the root of the tree, mixed with the number of rows.
+    pub fn root(&self) -> B256 {
+        let top = self.layers[self.layers.len() - 1].first().copied();
+        mix_rows(top.unwrap_or(B256::ZERO), self.rows())
+    }
+
+    /// Returns the inclusion proof of the row, numbered from the first row of the static file.
+    pub fn prove_row(&self, row: u64) -> Option<RowProof> {
+        self.layers[0].get(row as usize)?;
+
+        let depth = self.layers.len() - 1;
+        let mut position = row as usize;
+        let siblings = zero_hashes(depth)
+            .iter()
+            .zip(&self.layers[..depth])
+            .map(|(zero, layer)| {
+                let sibling = layer.get(position ^ 1).copied().unwrap_or(*zero);
+                position /= 2;
+                sibling
+            })
+            .collect();
+
+        Some(RowProof { row, rows: self.rows(), siblings })
+    }
+}
+
+/// Computes the root of a [`RowCommitment`] from leaves pushed in row order, keeping only the
+/// left nodes waiting for their sibling instead of every leaf.
+#[derive(Debug, Default)]
+struct RowRootBuilder {
+    /// Left nodes waiting for their sibling, by depth.
+    pending: Vec<Option<B256>>,
+    /// Number of pushed leaves.
+    rows: u64,
+}
+
+impl RowRootBuilder {
+    /// Pushes the hash of the next row, hashing every subtree it completes.
+    fn push(&mut self, leaf: B256) {
+        self.rows += 1;
+        let mut node = leaf;
+        for slot in &mut self.pending {
+            match slot.take() {
+                Some(left) => node = hash_pair(&left, &node),
+                None => {
+                    *slot = Some(node);
+                    return
+                }
+            }
+        }
+        self.pending.push(Some(node));
+    }
+
+    /// Returns the root of the tree over the pushed leaves, mixed with the number of rows.
+    fn root(self) -> B256 {
+        let depth = tree_depth(self.rows);
+        let zeros = zero_hashes(depth);
+        let pending = |depth: usize| self.pending.get(depth).copied().flatten();
+
+        // Root of the incomplete subtree on the right edge of the tree, padded with zero hashes
+        let mut right = None;
+        for (depth, zero) in zeros.iter().enumerate().take(depth) {
+            right = match (pending(depth), right) {
+                (Some(left), right) => Some(hash_pair(&left, &right.unwrap_or(*zero))),
+                (None, Some(left)) => Some(hash_pair(&left, zero)),
+                (None, None) => None,
+            };
+        }
+        let top = pending(depth).or(right).unwrap_or(zeros[depth]);
+        mix_rows(top, self.rows)
+    }
+}
+
+/// Merkle proof of a row against the root of its static file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowProof {
+    /// Number of the proven row, from the first row of the static file.
+    pub row: u64,
+    /// Number of rows of the static file.
+    pub rows: u64,
+    /// Siblings of the row, from the leaf up.
+    pub siblings: Vec<B256>,
+}
+
+/// Returns `true` if the columns of the row, as stored in the static file, are proven by the
+/// proof against the root of the static file.
+pub fn verify_row_proof(root: B256, columns: &[&[u8]], proof: &RowProof) -> bool {
+    if proof.row >= proof.rows || proof.siblings.len() != tree_depth(proof.rows) {
+        return false
+    }
+
+    let mut node = row_leaf(columns);
+    for (depth, sibling) in proof.siblings.iter().enumerate() {
+        node = if (proof.row >> depth) & 1 == 1 {
+            hash_pair(sibling, &node)
+        } else {
+            hash_pair(&node, sibling)
+        };
+    }
+    mix_rows(node, proof.rows) == root
+}
+
+/// Returns the hash of a row from its columns. Columns are length-prefixed, so rows whose
+/// concatenated columns are equal don't collide.
+pub fn row_leaf(columns: &[&[u8]]) -> B256 {
+    let mut buf = vec![LEAF_DOMAIN];
+    for column in columns {
+        buf.extend_from_slice(&(column.len() as u32).to_le_bytes());
+        buf.extend_from_slice(column);
+    }
+    keccak256(buf)
+}
+
+/// Computes the row root of the sealed static file and records it in its header, returning it.
+///
+/// The configuration is rewritten with [`rewrite_config`], so providers that loaded the static
+/// file must drop it to see the root.
+pub(crate) fn record_row_root(entry: &StaticFileEntry) -> io::Result<B256> {
+    let mut jar = NippyJar::<SegmentHeader>::load(&entry.path).map_err(io::Error::other)?;
+    let root = stream_row_root(&jar)?;
+    jar.user_header_mut().set_row_root(Some(root));
+    rewrite_config(&jar)?;
+    Ok(root)
+}
+
+/// Returns the row root recorded in the header of the static file, or computes it from its rows
+/// if it wasn't recorded.
+pub(crate) fn row_root(entry: &StaticFileEntry) -> io::Result<B256> {
+    let jar = NippyJar::<SegmentHeader>::load(&entry.path).map_err(io::Error::other)?;
+    match jar.user_header().row_root() {
+        Some(root) => Ok(root),
+        None => stream_row_root(&jar),
+    }
+}
+
+/// Computes the row root of the loaded static file, without holding the hashes of all its rows.
+fn stream_row_root(jar: &NippyJar<SegmentHeader>) -> io::Result<B256> {
+    let mut builder = RowRootBuilder::default();
+    for_each_leaf(jar, |leaf| builder.push(leaf))?;
+    Ok(builder.root())
+}
+
+/// Hashes every row of the loaded static file, in row order.
+fn for_each_leaf(jar: &NippyJar<SegmentHeader>, mut f: impl FnMut(B256)) -> io::Result<()> {
+    let mut cursor = NippyJarCursor::new(jar).map_err(io::Error::other)?;
+    for row in 0..jar.rows() {
+        let columns = cursor
+            .row_by_number(row)
+            .map_err(io::Error::other)?
+            .ok_or_else(|| io::Error::other(format!("row {row} is missing")))?;
+        f(row_leaf(&columns));
+    }
+    Ok(())
+}
+
+fn hash_pair(left: &B256, right: &B256) -> B256 {
+    let mut buf = [0; 65];
+    buf[0] = NODE_DOMAIN;
+    buf[1..33].copy_from_slice(left.as_slice());
+    buf[33..].copy_from_slice(right.as_slice());
+    keccak256(buf)
+}
+
+/// Mixes the number of rows into the root of the tree, so trees padded to the same depth with a
+/// different number of rows don't share their root.
+fn mix_rows(node: B256, rows: u64) -> B256 {
+    let mut buf = [0; 40];
+    buf[..32].copy_from_slice(node.as_slice());
+    buf[32..].copy_from_slice(&rows.to_le_bytes());
+    keccak256(buf)
+}
+
+/// Returns the depth of the tree over the number of rows.
+fn tree_depth(rows: u64) -> usize {
+    rows.next_power_of_two().trailing_zeros() as usize
+}
+
+/// Hashes pairs of nodes, padding the layer with the zero hash of its depth.
+fn next_layer(layer: &[B256], zero: &B256) -> Vec<B256> {
+    layer.chunks(2).map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(zero))).collect()
+}
+
+/// Returns the roots of empty subtrees, by depth.
+fn zero_hashes(depth: usize) -> Vec<B256> {
+    let mut hashes = vec![B256::ZERO; depth + 1];
+    for depth in 1..=depth {
+        hashes[depth] = hash_pair(&hashes[depth - 1], &hashes[depth - 1]);
+    }
+    hashes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        list_static_files, test_utils::StaticFileTestHarness, NamingScheme, StaticFileManifest,
+    };
+    use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+
+    #[test]
+    fn streamed_row_root() {
+        for rows in 0u64..=17 {
+            let leaves =
+                (0..rows).map(|row| row_leaf(&[&row.to_le_bytes()[..]])).collect::<Vec<_>>();
+            let mut builder = RowRootBuilder::default();
+            leaves.iter().for_each(|leaf| builder.push(*leaf));
+            assert_eq!(builder.root(), RowCommitment::new(leaves).root(), "{rows} rows");
+        }
+    }
+
+    #[test]
+    fn row_proofs() {
+        for rows in [1u64, 2, 5, 8] {
+            let leaves =
+                (0..rows).map(|row| row_leaf(&[&row.to_le_bytes()[..]])).collect::<Vec<_>>();
+            let commitment = RowCommitment::new(leaves);
+            let root = commitment.root();
+            assert_eq!(commitment.prove_row(rows), None);
+
+            for row in 0..rows {
+                let proof = commitment.prove_row(row).unwrap();
+                assert!(verify_row_proof(root, &[&row.to_le_bytes()[..]], &proof));
+                assert!(!verify_row_proof(root, &[&(row + 1).to_le_bytes()[..]], &proof));
+
+                // Proofs don't verify against trees with another number of rows
+                let tampered = RowProof { rows: proof.rows + 1, ..proof };
+                assert!(!verify_row_proof(root, &[&row.to_le_bytes()[..]], &tampered));
+            }
+        }
+        assert_ne!(row_leaf(&[&b"ab"[..], b"c"]), row_leaf(&[&b"a"[..], b"bc"]));
+
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let entries = list_static_files(harness.static_files_dir.path()).unwrap();
+        let receipts =
+            entries.iter().find(|entry| entry.segment == StaticFileSegment::Receipts).unwrap();
+
+        // Rows are proven against the root recorded in the header
+        let root = record_row_root(receipts).unwrap();
+        let jar = NippyJar::<SegmentHeader>::load(&receipts.path).unwrap();
+        assert_eq!(jar.user_header().row_root(), Some(root));
+        assert!(!receipts.companion_path("conf.tmp").exists());
+        let commitment = RowCommitment::from_static_file(receipts).unwrap();
+        assert_eq!(commitment.rows(), jar.rows() as u64);
+        let mut cursor = NippyJarCursor::new(&jar).unwrap();
+        for row in 0..jar.rows() {
+            let columns = cursor.row_by_number(row).unwrap().unwrap();
+            let proof = commitment.prove_row(row as u64).unwrap();
+            assert!(verify_row_proof(root, &columns, &proof));
+        }
+
+        let sealed = HighestStaticFiles {
+            headers: Some(u64::MAX),
+            receipts: Some(u64::MAX),
+            transactions: Some(u64::MAX),
+        };
+        let manifest = StaticFileManifest::new(&entries, sealed, NamingScheme::Plain)
+            .unwrap()
+            .with_row_roots(&entries)
+            .unwrap();
+        for (file, entry) in manifest.files.iter().zip(&entries) {
+            let expected = RowCommitment::from_static_file(entry).unwrap().root();
+            assert_eq!(file.row_root, Some(expected));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Last committed row of every segment, published after every commit, so readers of a static file
+//! that's still being appended to never observe a torn append, e.g. rows whose offsets aren't
+//! written yet.
+//!
+//! Readers in other processes, e.g. an indexer mapping the static files of a running node, poll the
+//! published rows with [`StaticFileReader::refresh`](crate::StaticFileReader::refresh). Clamping
+//! protects them from torn appends only: a rollback truncates the last static files in place, so
+//! their mappings must not be read while the producer may roll back.
+
+use crate::manifest::write_json;
+use alloy_primitives::{BlockNumber, TxNumber};
+use parking_lot::{const_mutex, Mutex};
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::StaticFileSegment;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+/// Name of the file in the static files directory that [`CommittedRows`] are published to.
+pub const COMMITTED_ROWS_FILE_NAME: &str = "committed_rows.json";
+
+/// Serializes the publishing of segments that are committed in parallel.
+static PUBLISH_LOCK: Mutex<()> = const_mutex(());
+
+/// Last committed row of every segment: a block number for the Headers segment, and a transaction
+/// number for the Transactions and Receipts segments.
+///
+/// Published to [`COMMITTED_ROWS_FILE_NAME`] after every commit, with an incremented
+/// [`epoch`](CommittedRows::epoch). Rows after the last committed one may be partially written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommittedRows {
+    /// Sequence number of the last published commit.
+    pub epoch: u64,
+    /// Last committed block of the Headers segment.
+    pub headers: Option<BlockNumber>,
+    /// Last committed transaction of the Transactions segment.
+    pub transactions: Option<TxNumber>,
+    /// Last committed transaction of the Receipts segment.
+    pub receipts: Option<TxNumber>,
+}
+
+impl CommittedRows {
+    /// Returns the last committed row of the segment, if any.
+    pub const fn get(&self, segment: StaticFileSegment) -> Option<u64> {
+        match segment {
+            StaticFileSegment::Headers => self.headers,
+            StaticFileSegment::Transactions => self.transactions,
+            StaticFileSegment::Receipts => self.receipts,
+        }
+    }
+
+    /// Returns a mutable reference to the last committed row of the segment.
+    fn as_mut(&mut self, segment: StaticFileSegment) -> &mut Option<u64> {
+        match segment {
+            StaticFileSegment::Headers => &mut self.headers,
+            StaticFileSegment::Transactions => &mut self.transactions,
+            StaticFileSegment::Receipts => &mut self.receipts,
+        }
+    }
+
+    /// Returns `true` if the row of the segment was committed.
+    pub fn is_committed(&self, segment: StaticFileSegment, row: u64) -> bool {
+        self.get(segment).is_some_and(|last| row <= last)
+    }
+
+    /// Reads the committed rows published in the static files directory. Returns `None` if
+    /// nothing was published yet.
+    pub fn read(directory: &Path) -> io::Result<Option<Self>> {
+        let path = directory.join(COMMITTED_ROWS_FILE_NAME);
+        if !path.exists() {
+            return Ok(None)
+        }
+        Ok(Some(serde_json::from_reader(BufReader::new(File::open(path)?))?))
+    }
+
+    /// Updates the published committed rows and increments their epoch. Readers see either the
+    /// previous or the updated rows, never a partially written file.
+    fn publish(directory: &Path, update: impl FnOnce(&mut Self)) -> io::Result<Self> {
+        let _lock = PUBLISH_LOCK.lock();
+        let mut committed = Self::read(directory)?.unwrap_or_default();
+        update(&mut committed);
+        committed.epoch += 1;
+
+        let path = directory.join(COMMITTED_ROWS_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+        write_json(&committed, &tmp_path)?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(committed)
+    }
+}
+
+/// Publishes the last committed rows of the segments, as indexed by the static file provider after
+/// their commit.
+pub(crate) fn publish_committed_rows(
+    static_file_provider: &StaticFileProvider,
+    segments: impl IntoIterator<Item = StaticFileSegment>,
+) -> io::Result<CommittedRows> {
+    CommittedRows::publish(static_file_provider.directory(), |committed| {
+        for segment in segments {
+            *committed.as_mut(segment) = match segment {
+                StaticFileSegment::Headers => {
+                    static_file_provider.get_highest_static_file_block(segment)
+                }
+                StaticFileSegment::Transactions | StaticFileSegment::Receipts => {
+                    static_file_provider.get_highest_static_file_tx(segment)
+                }
+            };
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn publish() {
+        let directory = tempfile::tempdir().unwrap();
+        assert_eq!(CommittedRows::read(directory.path()).unwrap(), None);
+
+        CommittedRows::publish(directory.path(), |committed| committed.headers = Some(10)).unwrap();
+        let committed = CommittedRows::publish(directory.path(), |committed| {
+            committed.receipts = Some(25);
+        })
+        .unwrap();
+        assert_eq!(
+            committed,
+            CommittedRows { epoch: 2, headers: Some(10), transactions: None, receipts: Some(25) }
+        );
+        assert_eq!(CommittedRows::read(directory.path()).unwrap(), Some(committed));
+
+        assert!(committed.is_committed(StaticFileSegment::Headers, 10));
+        assert!(!committed.is_committed(StaticFileSegment::Headers, 11));
+        assert!(!committed.is_committed(StaticFileSegment::Transactions, 0));
+    }
+}
@@ -0,0 +1,266 @@
+//! Serializable configuration of the static file producer, so embedders can keep it in a TOML
+//! file and reload it between runs.
+
+use crate::{PreallocationConfig, RetentionPolicy, RunOrder, WorkersConfig};
+use reth_static_file_types::{Compression, FilterHash, Filters, SegmentConfig, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{io, path::Path};
+
+/// Configuration of the static file producer, applied with
+/// [`StaticFileProducerInner::reload`](crate::StaticFileProducerInner::reload).
+///
+/// Missing fields are set to their defaults, e.g.
+///
+/// ```toml
+/// run_order = "parallel"
+/// throttle_blocks_per_second = 5000
+/// read_tx_renewal_blocks = 10000
+///
+/// [segments.receipts]
+/// enabled = false
+/// filters = "without_filters"
+/// compression = "zstd"
+///
+/// [retention]
+/// headers = 100000
+/// sink = { move_to = "/mnt/cold" }
+///
+/// [workers]
+/// pin_to_cores = [12, 13, 14]
+///
+/// [preallocation]
+/// enabled = true
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProducerConfig {
+    /// Configuration of every segment.
+    pub segments: SegmentsConfig,
+    /// Order in which segments are copied.
+    pub run_order: RunOrder,
+    /// Maximum number of blocks copied per second by every segment. If `None`, segments are
+    /// copied as fast as possible.
+    pub throttle_blocks_per_second: Option<u64>,
+    /// Number of blocks after which segments renew their database read transaction, so long
+    /// copies don't block page reclamation of the database. If `None`, every segment is copied
+    /// within one read transaction.
+    pub read_tx_renewal_blocks: Option<u64>,
+    /// Retention policy applied after every run. If `None`, all static files are kept.
+    pub retention: Option<RetentionPolicy>,
+    /// Worker threads copying segments in parallel.
+    pub workers: WorkersConfig,
+    /// Preallocation of the files of the copied static files.
+    pub preallocation: PreallocationConfig,
+}
+
+impl ProducerConfig {
+    /// Parses the configuration from TOML.
+    pub fn from_toml(toml: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(toml)
+    }
+
+    /// Serializes the configuration to TOML.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Reads the configuration from a TOML file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Configuration of every segment of the static file producer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SegmentsConfig {
+    /// Configuration of the headers segment.
+    pub headers: SegmentProducerConfig,
+    /// Configuration of the transactions segment.
+    pub transactions: SegmentProducerConfig,
+    /// Configuration of the receipts segment.
+    pub receipts: SegmentProducerConfig,
+}
+
+impl SegmentsConfig {
+    /// Returns the configuration of the segment.
+    pub const fn get(&self, segment: StaticFileSegment) -> &SegmentProducerConfig {
+        match segment {
+            StaticFileSegment::Headers => &self.headers,
+            StaticFileSegment::Transactions => &self.transactions,
+            StaticFileSegment::Receipts => &self.receipts,
+        }
+    }
+
+    /// Returns the mutable configuration of the segment.
+    pub fn get_mut(&mut self, segment: StaticFileSegment) -> &mut SegmentProducerConfig {
+        match segment {
+            StaticFileSegment::Headers => &mut self.headers,
+            StaticFileSegment::Transactions => &mut self.transactions,
+            StaticFileSegment::Receipts => &mut self.receipts,
+        }
+    }
+
+    /// Returns `true` if the segment is produced.
+    pub const fn is_enabled(&self, segment: StaticFileSegment) -> bool {
+        self.get(segment).enabled
+    }
+}
+
+impl Default for SegmentsConfig {
+    fn default() -> Self {
+        Self {
+            headers: SegmentProducerConfig::new(StaticFileSegment::Headers),
+            transactions: SegmentProducerConfig::new(StaticFileSegment::Transactions),
+            receipts: SegmentProducerConfig::new(StaticFileSegment::Receipts),
+        }
+    }
+}
+
+/// Configuration of a single segment of the static file producer.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SegmentProducerConfig {
+    /// Whether the segment is produced. Disabled segments get no targets, e.g. receipts on a
+    /// pruned node.
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    /// Inclusion filters used on the segment.
+    pub filters: Filters,
+    /// Compression used on the segment.
+    pub compression: Compression,
+    /// Target false positive rate of the inclusion filter. If `None`, the filter is sized to
+    /// hold exactly the rows of the static file.
+    #[serde(default)]
+    pub filter_fpp: Option<f64>,
+    /// Hash applied to the keys of the inclusion filter, e.g. keyed hashing so filter
+    /// collisions can't be crafted. Only the kind of hash is configured, never its secret.
+    #[serde(default)]
+    pub filter_hash: FilterHash,
+}
+
+impl SegmentProducerConfig {
+    /// Returns the enabled default configuration of the segment, see
+    /// [`StaticFileSegment::config`].
+    pub const fn new(segment: StaticFileSegment) -> Self {
+        let config = segment.config();
+        Self {
+            enabled: true,
+            filters: config.filters,
+            compression: config.compression,
+            filter_fpp: config.filter_fpp,
+            filter_hash: config.filter_hash,
+        }
+    }
+
+    /// Returns the [`SegmentConfig`] used when creating static files of the segment. The secret
+    /// of its filter hash is not part of the configuration, see
+    /// [`StaticFileProducerInner::set_filter_key`](crate::StaticFileProducerInner::set_filter_key).
+    pub const fn config(&self) -> SegmentConfig {
+        SegmentConfig {
+            filters: self.filters,
+            compression: self.compression,
+            filter_fpp: self.filter_fpp,
+            filter_hash: self.filter_hash,
+            filter_key: None,
+        }
+    }
+}
+
+/// Segments are enabled unless configured otherwise.
+const fn enabled() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RetentionSink;
+    use reth_static_file_types::{InclusionFilter, PerfectHashingFunction};
+
+    #[test]
+    fn toml_roundtrip() {
+        let config = ProducerConfig::from_toml(
+            r#"
+            run_order = { interleaved = { chunk = 100 } }
+            throttle_blocks_per_second = 5000
+            read_tx_renewal_blocks = 10000
+
+            [segments.receipts]
+            enabled = false
+            filters = { with_filters = ["cuckoo", "gofmph"] }
+            compression = "zstd-dict"
+            filter_fpp = 0.001
+            filter_hash = "xxh3"
+
+            [retention]
+            headers = 100000
+            sink = { move_to = "/mnt/cold" }
+
+            [workers]
+            thread_name_prefix = "static-files"
+            pin_to_cores = [2, 3]
+            filter_threads = 4
+
+            [preallocation]
+            enabled = true
+            max_file_bytes = 1000000000
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.run_order, RunOrder::Interleaved { chunk: 100 });
+        assert_eq!(config.throttle_blocks_per_second, Some(5000));
+        assert_eq!(config.read_tx_renewal_blocks, Some(10000));
+        assert_eq!(
+            config.segments.get(StaticFileSegment::Headers),
+            &SegmentProducerConfig {
+                enabled: true,
+                filters: Filters::WithFilters(
+                    InclusionFilter::Cuckoo,
+                    PerfectHashingFunction::Fmph
+                ),
+                compression: Compression::Lz4,
+                filter_fpp: None,
+                filter_hash: FilterHash::Identity,
+            }
+        );
+        assert_eq!(
+            config.segments.get(StaticFileSegment::Receipts),
+            &SegmentProducerConfig {
+                enabled: false,
+                filters: Filters::WithFilters(
+                    InclusionFilter::Cuckoo,
+                    PerfectHashingFunction::GoFmph
+                ),
+                compression: Compression::ZstdWithDictionary,
+                filter_fpp: Some(0.001),
+                filter_hash: FilterHash::Xxh3,
+            }
+        );
+        assert_eq!(
+            config.retention,
+            Some(RetentionPolicy {
+                headers: Some(100_000),
+                sink: RetentionSink::MoveTo("/mnt/cold".into()),
+                ..Default::default()
+            })
+        );
+
+        assert_eq!(
+            config.workers,
+            WorkersConfig {
+                thread_name_prefix: "static-files".to_string(),
+                pin_to_cores: vec![2, 3],
+                filter_threads: Some(4),
+            }
+        );
+        assert_eq!(
+            config.preallocation,
+            PreallocationConfig { enabled: true, max_file_bytes: Some(1_000_000_000) }
+        );
+
+        assert_eq!(ProducerConfig::from_toml(&config.to_toml().unwrap()).unwrap(), config);
+        assert_eq!(ProducerConfig::from_toml("").unwrap(), ProducerConfig::default());
+    }
+}
@@ -0,0 +1,197 @@
+//! Coordination of the producers of several networks in one process, e.g. an L1 and several L2s,
+//! each producing the static files of its own chain to its own directory.
+//!
+//! Producers attached to the same [`ProducerCoordinator`] build filters on a shared thread pool,
+//! write within a global IO budget, and take turns for their disk-heavy phases: commits with the
+//! post-commit work that follows them, filter rebuilds and rewrites of sealed static files.
+
+use crate::WorkersConfig;
+use parking_lot::{Mutex, MutexGuard};
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Coordinator shared by the [`StaticFileProducer`](crate::StaticFileProducer)s of a process,
+/// see
+/// [`StaticFileProducerInner::set_coordinator`](crate::StaticFileProducerInner::set_coordinator).
+///
+/// Cloning the coordinator shares it.
+#[derive(Debug, Clone)]
+pub struct ProducerCoordinator {
+    inner: Arc<CoordinatorInner>,
+}
+
+#[derive(Debug)]
+struct CoordinatorInner {
+    /// Thread pool building the filters of every producer. If `None`, every producer builds them
+    /// within its own [`WorkersConfig`].
+    thread_pool: Option<rayon::ThreadPool>,
+    /// Budget of the bytes written by every producer, if limited.
+    io_throttle: Option<Arc<IoThrottle>>,
+    /// Held by the producer in a disk-heavy phase.
+    disk_phase: Mutex<()>,
+    /// Static files directories of the attached producers.
+    directories: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl ProducerCoordinator {
+    /// Creates a new [`ProducerCoordinator`], with a shared thread pool of `threads` threads
+    /// building filters, and a global IO budget of `io_bytes_per_second` bytes written per
+    /// second. `None` keeps the threads of every producer, or doesn't limit the IO.
+    pub fn new(threads: Option<usize>, io_bytes_per_second: Option<u64>) -> io::Result<Self> {
+        let thread_pool = threads
+            .map(|threads| {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads.max(1))
+                    .thread_name(|index| format!("sf-shared-{index}"))
+                    .build()
+                    .map_err(io::Error::other)
+            })
+            .transpose()?;
+        Ok(Self {
+            inner: Arc::new(CoordinatorInner {
+                thread_pool,
+                io_throttle: io_bytes_per_second.map(|bytes| Arc::new(IoThrottle::new(bytes))),
+                disk_phase: Mutex::new(()),
+                directories: Mutex::new(BTreeSet::new()),
+            }),
+        })
+    }
+
+    /// Returns the static files directories of the attached producers.
+    pub fn directories(&self) -> Vec<PathBuf> {
+        self.inner.directories.lock().iter().cloned().collect()
+    }
+
+    /// Attaches the producer of the static files directory, until the returned membership is
+    /// dropped. Returns `None` if a producer of the same directory is already attached.
+    pub(crate) fn attach(&self, directory: &Path) -> Option<CoordinatorMembership> {
+        self.inner.directories.lock().insert(directory.to_path_buf()).then(|| {
+            CoordinatorMembership { coordinator: self.clone(), directory: directory.to_path_buf() }
+        })
+    }
+}
+
+/// Producer attached to a [`ProducerCoordinator`], detached when dropped.
+#[derive(Debug)]
+pub(crate) struct CoordinatorMembership {
+    /// Coordinator the producer is attached to.
+    coordinator: ProducerCoordinator,
+    /// Static files directory of the producer.
+    directory: PathBuf,
+}
+
+impl CoordinatorMembership {
+    /// Runs `f` on the shared thread pool, or on the thread pool of the [`WorkersConfig`] if the
+    /// coordinator has none.
+    pub(crate) fn install_filters<T: Send>(
+        &self,
+        workers: &WorkersConfig,
+        f: impl FnOnce() -> T + Send,
+    ) -> io::Result<T> {
+        match &self.coordinator.inner.thread_pool {
+            Some(thread_pool) => Ok(thread_pool.install(f)),
+            None => workers.install_filters(f),
+        }
+    }
+
+    /// Returns the global IO budget, if limited.
+    pub(crate) fn io_throttle(&self) -> Option<Arc<IoThrottle>> {
+        self.coordinator.inner.io_throttle.clone()
+    }
+
+    /// Waits for the other producers to leave their disk-heavy phase, and enters one until the
+    /// guard is dropped.
+    pub(crate) fn disk_phase(&self) -> MutexGuard<'_, ()> {
+        self.coordinator.inner.disk_phase.lock()
+    }
+}
+
+impl Drop for CoordinatorMembership {
+    fn drop(&mut self) {
+        self.coordinator.inner.directories.lock().remove(&self.directory);
+    }
+}
+
+/// Budget of bytes written per second, shared by the segments of every producer.
+#[derive(Debug)]
+pub(crate) struct IoThrottle {
+    /// Bytes written per second.
+    bytes_per_second: u64,
+    /// Time the bytes written so far are within the budget at.
+    drained_at: Mutex<Option<Instant>>,
+}
+
+impl IoThrottle {
+    /// Creates a new [`IoThrottle`] of `bytes_per_second` bytes written per second.
+    pub(crate) fn new(bytes_per_second: u64) -> Self {
+        Self { bytes_per_second: bytes_per_second.max(1), drained_at: Mutex::new(None) }
+    }
+
+    /// Charges the budget with `bytes` just written, and returns the time to wait until the
+    /// bytes written before them are within the budget, if any.
+    pub(crate) fn charge(&self, bytes: u64) -> Option<Duration> {
+        let now = Instant::now();
+        let mut drained_at = self.drained_at.lock();
+        let start = drained_at.map_or(now, |drained_at| drained_at.max(now));
+        *drained_at =
+            Some(start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64));
+        Some(start.duration_since(now)).filter(|delay| !delay.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::StaticFileTestHarness, StaticFileProducerError};
+    use reth_provider::StaticFileProviderFactory;
+    use reth_static_file_types::StaticFileSegment;
+
+    #[test]
+    fn shared_coordinator() {
+        let coordinator = ProducerCoordinator::new(Some(2), None).unwrap();
+        let (l1, l2) = (StaticFileTestHarness::new(3, 1..3), StaticFileTestHarness::new(5, 1..2));
+        let (mut l1_producer, mut l2_producer) = (l1.producer(), l2.producer());
+        l1_producer.set_coordinator(Some(coordinator.clone())).unwrap();
+        l2_producer.set_coordinator(Some(coordinator.clone())).unwrap();
+        assert_eq!(coordinator.directories().len(), 2);
+
+        // Directories are produced to by a single producer
+        let l1_directory = l1.provider_factory.static_file_provider().directory().to_path_buf();
+        let mut duplicate = l1.producer();
+        assert!(matches!(
+            duplicate.set_coordinator(Some(coordinator.clone())),
+            Err(StaticFileProducerError::DirectoryInUse(directory)) if directory == l1_directory
+        ));
+
+        let (l1_produced, l2_produced) = std::thread::scope(|scope| {
+            let l1_run = scope.spawn(|| l1.run_with(&l1_producer));
+            let l2_run = scope.spawn(|| l2.run_with(&l2_producer));
+            (l1_run.join().unwrap().unwrap(), l2_run.join().unwrap().unwrap())
+        });
+        assert_eq!(l1_produced.target(StaticFileSegment::Headers), Some(&(0..=3)));
+        assert_eq!(l2_produced.target(StaticFileSegment::Headers), Some(&(0..=5)));
+        assert_eq!(
+            l2.provider_factory
+                .static_file_provider()
+                .get_highest_static_file_block(StaticFileSegment::Receipts),
+            Some(5)
+        );
+
+        // Dropped producers are detached
+        drop(l1_producer);
+        duplicate.set_coordinator(Some(coordinator.clone())).unwrap();
+        duplicate.set_coordinator(None).unwrap();
+        drop(l2_producer);
+        assert!(coordinator.directories().is_empty());
+
+        // Bytes written before have to be within the budget
+        let io_throttle = IoThrottle::new(1_000);
+        assert_eq!(io_throttle.charge(500), None);
+        assert!(io_throttle.charge(500).is_some_and(|delay| delay > Duration::from_millis(400)));
+    }
+}
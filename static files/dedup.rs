@@ -0,0 +1,507 @@
+//! Best-effort deduplication of repeated byte chunks in the input of transactions, stored in a
+//! dedup table sidecar next to every Transactions static file.
+//!
+//! The input of a copied transaction is split into content-defined chunks, so equal runs of
+//! bytes, e.g. the bytecode of a contract deployed again and again, are split the same way
+//! wherever they start. The first occurrence of a chunk in a static file is stored as is, later
+//! ones are replaced by a reference to the chunk in the [`DedupTable`] of the static file.
+//! Deduplicated inputs start with [`DEDUP_MAGIC`] and are reassembled transparently by the
+//! [`StaticFileReader`](crate::StaticFileReader) and the
+//! [`MultiSegmentReader`](crate::MultiSegmentReader).
+//!
+//! Deduplicated static files are [marked](reth_static_file_types::SegmentHeader::deduplicated) and
+//! get no filters, so the [`StaticFileProvider`](reth_provider::providers::StaticFileProvider)
+//! finds no rows in them instead of returning the deduplicated inputs. They're only created whole,
+//! see [`DeduplicatedTransactions`](crate::segments::DeduplicatedTransactions), in directories no
+//! node reads from. The [`StaticFileReader`](crate::StaticFileReader) reads their rows directly,
+//! falls back to the static files with a dedup table to find them by hash, and
+//! [`MultiSegmentReader::find_by_hash`](crate::MultiSegmentReader::find_by_hash) reassembles rows
+//! before hashing them.
+
+use crate::sidecar::read_u64;
+use alloy_primitives::{BlockNumber, TxNumber, B256};
+use parking_lot::Mutex;
+use reth_primitives::TransactionSignedNoHash;
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::{find_fixed_range, SegmentRangeInclusive, StaticFileSegment};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    io::{self, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Extension of the dedup table sidecar.
+pub const DEDUP_EXTENSION: &str = "dedup";
+
+/// Prefix of the deduplicated inputs of transactions.
+pub const DEDUP_MAGIC: [u8; 4] = *b"\0dup";
+
+/// Smallest chunk that's deduplicated, shorter ones aren't worth a reference.
+const MIN_CHUNK_SIZE: usize = 64;
+
+/// Largest chunk, cut regardless of its content.
+const MAX_CHUNK_SIZE: usize = 4096;
+
+/// Mask of the rolling hash cutting a chunk, for chunks of 512 bytes on average.
+const CHUNK_MASK: u64 = (1 << 9) - 1;
+
+/// Highest number of chunk hashes remembered by a [`DedupWriter`], past which they're
+/// forgotten, so memory stays bounded.
+const MAX_SEEN_CHUNKS: usize = 1 << 20;
+
+/// Tag of bytes of a deduplicated input that are stored as is.
+const LITERAL: u8 = 0;
+
+/// Tag of a reference to a chunk of the [`DedupTable`].
+const REFERENCE: u8 = 1;
+
+/// Random values of the bytes in the gear rolling hash, generated with `SplitMix64`.
+const GEAR: [u64; 256] = gear_table();
+
+/// Dedup table of a single Transactions static file, holding the chunks referenced by its
+/// deduplicated rows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DedupTable {
+    /// Chunks, referenced by their index.
+    chunks: Vec<Vec<u8>>,
+    /// Index of every chunk by its hash. Not encoded, rebuilt when decoding.
+    ids: HashMap<B256, u32>,
+    /// Transactions copied with deduplication. Rows out of them are always stored as is.
+    tx_ranges: Vec<RangeInclusive<TxNumber>>,
+}
+
+impl DedupTable {
+    /// Returns the number of chunks.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns `true` if there are no chunks.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Returns `true` if the transaction was copied with deduplication.
+    pub fn covers(&self, tx_number: TxNumber) -> bool {
+        self.tx_ranges.iter().any(|range| range.contains(&tx_number))
+    }
+
+    /// Reassembles the input of the transaction, if it was deduplicated. Other transactions are
+    /// left as is.
+    pub fn reassemble(
+        &self,
+        tx_number: TxNumber,
+        transaction: &mut TransactionSignedNoHash,
+    ) -> io::Result<()> {
+        if !is_deduplicated(transaction) || !self.covers(tx_number) {
+            return Ok(())
+        }
+
+        let mut encoded = &transaction.transaction.input()[DEDUP_MAGIC.len()..];
+        let mut input = Vec::with_capacity(encoded.len());
+        while let Some((&tag, rest)) = encoded.split_first() {
+            encoded = rest;
+            let value = read_u32(&mut encoded)? as usize;
+            match tag {
+                LITERAL => {
+                    if encoded.len() < value {
+                        return Err(io::ErrorKind::UnexpectedEof.into())
+                    }
+                    let (literal, rest) = encoded.split_at(value);
+                    input.extend_from_slice(literal);
+                    encoded = rest;
+                }
+                REFERENCE => input.extend_from_slice(self.chunks.get(value).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, format!("unknown chunk {value}"))
+                })?),
+                tag => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unknown tag {tag} of deduplicated input"),
+                    ))
+                }
+            }
+        }
+        transaction.transaction.set_input(input.into());
+        Ok(())
+    }
+
+    /// Reads the dedup table of the static file with the data file. Returns an empty table if it
+    /// doesn't exist.
+    pub fn read(data_path: &Path) -> io::Result<Self> {
+        match std::fs::read(dedup_path(data_path)) {
+            Ok(data) => Self::decode(&data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the dedup table of the static file with the data file, replacing it atomically.
+    pub fn write(&self, data_path: &Path) -> io::Result<()> {
+        let path = dedup_path(data_path);
+        let tmp_path = path.with_extension(format!("{DEDUP_EXTENSION}.tmp"));
+
+        let mut buf = Vec::new();
+        self.encode(&mut buf);
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    /// Encodes the chunks, then the transaction ranges.
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.chunks.len() as u64).to_le_bytes());
+        for chunk in &self.chunks {
+            buf.extend_from_slice(&(chunk.len() as u64).to_le_bytes());
+            buf.extend_from_slice(chunk);
+        }
+        buf.extend_from_slice(&(self.tx_ranges.len() as u64).to_le_bytes());
+        for range in &self.tx_ranges {
+            buf.extend_from_slice(&range.start().to_le_bytes());
+            buf.extend_from_slice(&range.end().to_le_bytes());
+        }
+    }
+
+    /// Decodes a table encoded with [`DedupTable::encode`].
+    fn decode(mut buf: &[u8]) -> io::Result<Self> {
+        let mut table = Self::default();
+        for _ in 0..read_u64(&mut buf)? {
+            let len = read_u64(&mut buf)? as usize;
+            if buf.len() < len {
+                return Err(io::ErrorKind::UnexpectedEof.into())
+            }
+            let (chunk, rest) = buf.split_at(len);
+            table.insert(chunk_hash(chunk), chunk);
+            buf = rest;
+        }
+        for _ in 0..read_u64(&mut buf)? {
+            let start = read_u64(&mut buf)?;
+            table.tx_ranges.push(start..=read_u64(&mut buf)?);
+        }
+        Ok(table)
+    }
+
+    /// Adds the chunk with its hash, returning its id.
+    fn insert(&mut self, hash: B256, chunk: &[u8]) -> u32 {
+        let id = self.chunks.len() as u32;
+        self.chunks.push(chunk.to_vec());
+        self.ids.insert(hash, id);
+        id
+    }
+
+    /// Records the transaction as copied with deduplication.
+    fn cover(&mut self, tx_number: TxNumber) {
+        match self.tx_ranges.last_mut() {
+            Some(range) if range.contains(&tx_number) => {}
+            Some(range) if range.end().checked_add(1) == Some(tx_number) => {
+                *range = *range.start()..=tx_number
+            }
+            _ => self.tx_ranges.push(tx_number..=tx_number),
+        }
+    }
+
+    /// Returns the input with repeated chunks replaced by references to the table, or `None` if
+    /// it's stored as is.
+    fn encode_input(&mut self, seen: &mut HashSet<B256>, input: &[u8]) -> Option<Vec<u8>> {
+        let mut encoded = DEDUP_MAGIC.to_vec();
+        let mut deduplicated = false;
+        // Start of the bytes that aren't encoded yet, and end of the last chunk
+        let (mut literal_start, mut position) = (0, 0);
+        for chunk in chunks(input) {
+            let chunk_start = position;
+            position += chunk.len();
+            if chunk.len() < MIN_CHUNK_SIZE {
+                continue
+            }
+
+            let hash = chunk_hash(chunk);
+            let id = match self.ids.get(&hash) {
+                Some(&id) => id,
+                None if seen.contains(&hash) => self.insert(hash, chunk),
+                None => {
+                    if seen.len() >= MAX_SEEN_CHUNKS {
+                        seen.clear();
+                    }
+                    seen.insert(hash);
+                    continue
+                }
+            };
+            push_literal(&mut encoded, &input[literal_start..chunk_start]);
+            encoded.push(REFERENCE);
+            encoded.extend_from_slice(&id.to_le_bytes());
+            literal_start = position;
+            deduplicated = true;
+        }
+
+        // Inputs starting with the magic are always encoded, so they aren't mistaken for
+        // deduplicated ones
+        if !deduplicated && !input.starts_with(&DEDUP_MAGIC) {
+            return None
+        }
+        push_literal(&mut encoded, &input[literal_start..]);
+        Some(encoded)
+    }
+}
+
+/// Deduplicates the inputs of transactions while static files are created, building the
+/// [`DedupTable`] of every static file.
+///
+/// Static files are created whole, so their tables start empty, replacing the ones of static files
+/// created before in the same directory. Chunks are only deduplicated within a static file.
+#[derive(Debug)]
+pub struct DedupWriter {
+    /// Static files directory.
+    directory: PathBuf,
+    /// Block range and data file of the static file being copied to, and its table.
+    current: Option<(SegmentRangeInclusive, PathBuf, DedupTable)>,
+    /// Hashes of the chunks seen once in the static file, deduplicated when seen again.
+    seen: HashSet<B256>,
+    /// Whether the table changed since it was written.
+    dirty: bool,
+}
+
+impl DedupWriter {
+    /// Creates a new [`DedupWriter`] for the static files directory.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: directory.into(), current: None, seen: HashSet::new(), dirty: false }
+    }
+
+    /// Deduplicates the input of the transaction of the block, writing the table of the previous
+    /// static file once the block belongs to the next one.
+    pub fn dedup(
+        &mut self,
+        block: BlockNumber,
+        tx_number: TxNumber,
+        transaction: &mut TransactionSignedNoHash,
+    ) -> io::Result<()> {
+        let block_range = find_fixed_range(block);
+        if self.current.as_ref().is_some_and(|(range, _, _)| *range != block_range) {
+            self.flush()?;
+            self.current = None;
+            self.seen.clear();
+        }
+        if self.current.is_none() {
+            let path = self.directory.join(StaticFileSegment::Transactions.filename(&block_range));
+            self.current = Some((block_range, path, DedupTable::default()));
+        }
+        let Some((_, _, table)) = &mut self.current else { return Ok(()) };
+
+        table.cover(tx_number);
+        self.dirty = true;
+        if let Some(encoded) = table.encode_input(&mut self.seen, transaction.transaction.input()) {
+            transaction.transaction.set_input(encoded.into());
+        }
+        Ok(())
+    }
+
+    /// Writes the table of the static file that's created, if it changed. Called once its rows are
+    /// written.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let Some((_, path, table)) = &self.current else { return Ok(()) };
+        if self.dirty {
+            table.write(path)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+/// Dedup tables of Transactions static files, read on first use and shared between clones.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DedupTables(Arc<Mutex<HashMap<SegmentRangeInclusive, Arc<DedupTable>>>>);
+
+impl DedupTables {
+    /// Reassembles the input of the transaction read from the static files, if it was
+    /// deduplicated.
+    ///
+    /// The table of a static file that's appended to is read again if the cached one doesn't
+    /// hold the chunks of the transaction yet.
+    pub(crate) fn reassemble(
+        &self,
+        provider: &StaticFileProvider,
+        tx_number: TxNumber,
+        transaction: &mut TransactionSignedNoHash,
+    ) -> ProviderResult<()> {
+        if !is_deduplicated(transaction) {
+            return Ok(())
+        }
+
+        let segment = StaticFileSegment::Transactions;
+        let jar_provider =
+            provider.get_segment_provider_from_transaction(segment, tx_number, None)?;
+        let block_range = find_fixed_range(jar_provider.user_header().expected_block_start());
+        let cached = self.0.lock().get(&block_range).cloned();
+        if let Some(table) = cached.filter(|table| table.covers(tx_number)) {
+            // The transaction is only modified once it's fully reassembled
+            if table.reassemble(tx_number, transaction).is_ok() {
+                return Ok(())
+            }
+        }
+
+        let path = provider.directory().join(segment.filename(&block_range));
+        let table =
+            DedupTable::read(&path).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        table
+            .reassemble(tx_number, transaction)
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        self.0.lock().insert(block_range, Arc::new(table));
+        Ok(())
+    }
+
+    /// Forgets all tables, e.g. once static files changed.
+    pub(crate) fn clear(&self) {
+        self.0.lock().clear();
+    }
+}
+
+/// Returns the path of the dedup table of the static file with the data file.
+fn dedup_path(data_path: &Path) -> PathBuf {
+    let mut path = OsString::from(data_path);
+    path.push(".");
+    path.push(DEDUP_EXTENSION);
+    path.into()
+}
+
+/// Returns `true` if the input of the transaction may be deduplicated. Only transactions covered
+/// by the [`DedupTable`] of their static file are.
+pub(crate) fn is_deduplicated(transaction: &TransactionSignedNoHash) -> bool {
+    transaction.transaction.input().starts_with(&DEDUP_MAGIC)
+}
+
+/// Splits the bytes into content-defined chunks, cut where the gear rolling hash of the last
+/// bytes matches [`CHUNK_MASK`].
+fn chunks(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None
+        }
+
+        let mut len = rest.len().min(MAX_CHUNK_SIZE);
+        let mut hash = 0u64;
+        for (i, byte) in rest[..len].iter().enumerate() {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if i + 1 >= MIN_CHUNK_SIZE && hash & CHUNK_MASK == 0 {
+                len = i + 1;
+                break
+            }
+        }
+        let (chunk, tail) = rest.split_at(len);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+/// Returns the hash identifying a chunk.
+fn chunk_hash(chunk: &[u8]) -> B256 {
+    B256::from(*blake3::hash(chunk).as_bytes())
+}
+
+/// Appends the bytes to the encoded input as a literal, unless they're empty.
+fn push_literal(encoded: &mut Vec<u8>, literal: &[u8]) {
+    if literal.is_empty() {
+        return
+    }
+    encoded.push(LITERAL);
+    encoded.extend_from_slice(&(literal.len() as u32).to_le_bytes());
+    encoded.extend_from_slice(literal);
+}
+
+/// Reads a little endian 32-bit integer.
+fn read_u32(buf: &mut &[u8]) -> io::Result<u32> {
+    if buf.len() < 4 {
+        return Err(io::ErrorKind::UnexpectedEof.into())
+    }
+    let (bytes, rest) = buf.split_at(4);
+    *buf = rest;
+    Ok(u32::from_le_bytes(bytes.try_into().expect("4 bytes")))
+}
+
+/// Generates the [`GEAR`] table.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < table.len() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StaticFileEntry;
+    use alloy_primitives::Bytes;
+    use reth_primitives::{Signature, Transaction, TxLegacy};
+
+    fn transaction(input: Vec<u8>) -> TransactionSignedNoHash {
+        TransactionSignedNoHash {
+            signature: Signature::default(),
+            transaction: Transaction::Legacy(TxLegacy {
+                input: Bytes::from(input),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn dedup_table_sidecar() {
+        let directory = tempfile::tempdir().unwrap();
+        let bytecode = (0..8192u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8);
+        let bytecode = bytecode.collect::<Vec<_>>();
+        let inputs = [
+            bytecode.clone(),
+            [b"prefix".as_slice(), &bytecode].concat(),
+            [DEDUP_MAGIC.as_slice(), b"calldata"].concat(),
+            b"short".to_vec(),
+        ];
+
+        let mut writer = DedupWriter::new(directory.path());
+        let mut stored = Vec::new();
+        for (tx_number, input) in inputs.iter().enumerate() {
+            let mut transaction = transaction(input.clone());
+            writer.dedup(1, tx_number as u64, &mut transaction).unwrap();
+            stored.push(transaction);
+        }
+        writer.flush().unwrap();
+
+        // The first occurrence is stored as is, the repeated one references its chunks
+        assert_eq!(stored[0], transaction(inputs[0].clone()));
+        assert!(is_deduplicated(&stored[1]));
+        assert!(stored[1].transaction.input().len() < inputs[1].len() / 2);
+        // Inputs starting with the magic are escaped
+        assert!(is_deduplicated(&stored[2]));
+        assert_eq!(stored[3], transaction(inputs[3].clone()));
+
+        let block_range = find_fixed_range(1);
+        let entry = StaticFileEntry {
+            segment: StaticFileSegment::Transactions,
+            block_range,
+            path: directory.path().join(StaticFileSegment::Transactions.filename(&block_range)),
+        };
+        assert!(entry.companion_path(DEDUP_EXTENSION).exists());
+        let table = DedupTable::read(&entry.path).unwrap();
+        assert!(!table.is_empty());
+        assert!(table.covers(3) && !table.covers(4));
+        for (tx_number, (mut transaction, input)) in stored.into_iter().zip(inputs).enumerate() {
+            table.reassemble(tx_number as u64, &mut transaction).unwrap();
+            assert_eq!(transaction.transaction.input().as_ref(), input.as_slice());
+        }
+
+        // Transactions copied without deduplication are never reassembled
+        let mut raw = transaction([DEDUP_MAGIC.as_slice(), &[7]].concat());
+        table.reassemble(4, &mut raw).unwrap();
+        assert_eq!(raw.transaction.input().len(), DEDUP_MAGIC.len() + 1);
+    }
+}
@@ -0,0 +1,365 @@
+//! Zstd dictionaries shared by static files, stored once under their content hash in the
+//! [`DICTIONARIES_DIR_NAME`] directory.
+//!
+//! The header of a static file compressed with
+//! [`Compression::ZstdWithDictionary`](reth_static_file_types::Compression::ZstdWithDictionary)
+//! references its dictionaries by id, one per column, see [`SegmentHeader::dictionary_ids`].
+//! References are listed in the [`StaticFileManifest`], so every dictionary is published and
+//! imported once however many static files share it, and dictionaries no longer referenced are
+//! removed by [`DictionaryStore::gc`].
+//!
+//! `NippyJar` only decompresses with the dictionaries embedded in its configuration file, so
+//! sealed static files keep their copy for the static file provider. The shared copies are read by
+//! decoders of static files from bytes, see
+//! [`JarRows::with_dictionaries`](reth_static_file_types::JarRows::with_dictionaries).
+
+use crate::{files::rewrite_config, manifest::truncated_hash, StaticFileEntry, StaticFileManifest};
+use alloy_primitives::{hex, B64};
+use reth_nippy_jar::NippyJar;
+use reth_static_file_types::{JarConfig, SegmentHeader};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Name of the directory within the static files directory holding the shared dictionaries.
+pub const DICTIONARIES_DIR_NAME: &str = "dictionaries";
+
+/// Extension of the dictionary files.
+const DICTIONARY_EXTENSION: &str = "dict";
+
+/// Content-addressed store of the zstd dictionaries shared by the static files of a directory.
+#[derive(Debug, Clone)]
+pub struct DictionaryStore {
+    /// Directory of the dictionaries.
+    directory: PathBuf,
+}
+
+impl DictionaryStore {
+    /// Creates a new [`DictionaryStore`] of the static files directory.
+    pub fn new(static_files_dir: &Path) -> Self {
+        Self { directory: static_files_dir.join(DICTIONARIES_DIR_NAME) }
+    }
+
+    /// Returns the path of the dictionary with the id.
+    pub fn path(&self, id: &B64) -> PathBuf {
+        self.directory.join(dictionary_file_name(id))
+    }
+
+    /// Stores the dictionary, unless it's stored already, and returns its id: the truncated
+    /// blake3 hash of its bytes.
+    pub fn put(&self, dictionary: &[u8]) -> io::Result<B64> {
+        let id = dictionary_id(dictionary);
+        let path = self.path(&id);
+        if path.exists() {
+            return Ok(id)
+        }
+
+        std::fs::create_dir_all(&self.directory)?;
+        let tmp_path = path.with_extension(format!("{DICTIONARY_EXTENSION}.tmp"));
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(dictionary)?;
+        file.sync_all()?;
+        std::fs::rename(tmp_path, path)?;
+        Ok(id)
+    }
+
+    /// Reads the dictionary with the id, checking it against its hash.
+    pub fn get(&self, id: &B64) -> io::Result<Vec<u8>> {
+        let dictionary = std::fs::read(self.path(id))?;
+        if dictionary_id(&dictionary) != *id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("dictionary {} doesn't match its hash", hex::encode(id)),
+            ))
+        }
+        Ok(dictionary)
+    }
+
+    /// Reads the shared dictionaries of the columns of the static file. Returns no dictionaries
+    /// if it references none.
+    pub fn dictionaries(&self, entry: &StaticFileEntry) -> io::Result<Vec<Vec<u8>>> {
+        dictionary_refs(entry)?.iter().map(|id| self.get(id)).collect()
+    }
+
+    /// Stores the dictionaries embedded in the configuration file of the static file and records
+    /// their ids in its header. Returns the ids, or none if the static file isn't compressed with
+    /// dictionaries.
+    ///
+    /// The configuration is rewritten with [`rewrite_config`], so providers that loaded the static
+    /// file must drop it to see the ids.
+    pub fn share(&self, entry: &StaticFileEntry) -> io::Result<Vec<B64>> {
+        let config = std::fs::read(entry.companion_path("conf"))?;
+        let dictionaries = JarConfig::decode_dictionaries(&config)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if dictionaries.is_empty() {
+            return Ok(Vec::new())
+        }
+
+        let ids = dictionaries
+            .iter()
+            .map(|dictionary| self.put(dictionary))
+            .collect::<io::Result<Vec<_>>>()?;
+        let mut jar = NippyJar::<SegmentHeader>::load(&entry.path).map_err(io::Error::other)?;
+        if jar.user_header().dictionary_ids() != Some(ids.as_slice()) {
+            jar.user_header_mut().set_dictionary_ids(Some(ids.clone()));
+            rewrite_config(&jar)?;
+        }
+        Ok(ids)
+    }
+
+    /// Removes the dictionaries that are neither referenced by the static files nor listed in
+    /// the manifests, returning their ids.
+    ///
+    /// Dictionaries stored less than `min_age` ago are kept, so a dictionary that's stored but
+    /// not recorded in a header yet isn't removed under a concurrent producer. Fails without
+    /// removing anything if the references of a static file can't be read.
+    pub fn gc(
+        &self,
+        entries: &[StaticFileEntry],
+        manifests: &[&StaticFileManifest],
+        min_age: Duration,
+    ) -> io::Result<Vec<B64>> {
+        let mut references = BTreeMap::<B64, usize>::new();
+        for entry in entries {
+            for id in dictionary_refs(entry)? {
+                *references.entry(id).or_default() += 1;
+            }
+        }
+        for manifest in manifests {
+            for (id, count) in manifest.dictionary_refs() {
+                *references.entry(id).or_default() += count;
+            }
+        }
+
+        let dir_entries = match std::fs::read_dir(&self.directory) {
+            Ok(dir_entries) => dir_entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let now = SystemTime::now();
+        let mut removed = Vec::new();
+        for dir_entry in dir_entries {
+            let dir_entry = dir_entry?;
+            let Some(id) = parse_dictionary_file_name(&dir_entry.file_name().to_string_lossy())
+            else {
+                continue
+            };
+            if references.contains_key(&id) {
+                continue
+            }
+            let modified = dir_entry.metadata()?.modified()?;
+            if now.duration_since(modified).unwrap_or_default() < min_age {
+                continue
+            }
+
+            std::fs::remove_file(dir_entry.path())?;
+            removed.push(id);
+        }
+        removed.sort_unstable();
+        Ok(removed)
+    }
+}
+
+/// Reads the ids of the shared dictionaries recorded in the header of the static file, one per
+/// column. Returns no ids if the static file has no configuration file or references none.
+pub fn dictionary_refs(entry: &StaticFileEntry) -> io::Result<Vec<B64>> {
+    let config = match std::fs::read(entry.companion_path("conf")) {
+        Ok(config) => config,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let config = JarConfig::decode(&config)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    Ok(config.header.dictionary_ids().map(<[_]>::to_vec).unwrap_or_default())
+}
+
+/// Returns the file name of the dictionary with the id, within [`DICTIONARIES_DIR_NAME`].
+pub fn dictionary_file_name(id: &B64) -> String {
+    format!("{}.{DICTIONARY_EXTENSION}", hex::encode(id))
+}
+
+/// Returns the id of the dictionary file name, or `None` if it's not one.
+fn parse_dictionary_file_name(file_name: &str) -> Option<B64> {
+    file_name.strip_suffix(&format!(".{DICTIONARY_EXTENSION}"))?.parse().ok()
+}
+
+/// Returns the id of the dictionary: the truncated blake3 hash of its bytes.
+fn dictionary_id(dictionary: &[u8]) -> B64 {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(dictionary);
+    truncated_hash(&hasher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        import_static_files, list_static_files,
+        segments::{Receipts, Segment},
+        test_utils::StaticFileTestHarness,
+        ManifestError, NamingScheme, TrustedManifest,
+    };
+    use reth_nippy_jar::NippyJarCursor;
+    use reth_static_file_types::{
+        find_fixed_range, Compression, HighestStaticFiles, JarRows, Offsets, SegmentConfig,
+        StaticFileSegment,
+    };
+
+    #[test]
+    fn shared_dictionaries() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let config = SegmentConfig {
+            compression: Compression::ZstdWithDictionary,
+            ..StaticFileSegment::Receipts.config()
+        };
+        Receipts::default()
+            .create_static_file_file(&provider, directory.path(), config, 0..=3)
+            .unwrap();
+        let entry = list_static_files(directory.path()).unwrap().remove(0);
+        let store = DictionaryStore::new(directory.path());
+        assert!(dictionary_refs(&entry).unwrap().is_empty());
+
+        let ids = store.share(&entry).unwrap();
+        assert!(!ids.is_empty());
+        assert_eq!(dictionary_refs(&entry).unwrap(), ids);
+        assert_eq!(store.share(&entry).unwrap(), ids);
+
+        // Rows decode from bytes with the shared dictionaries, as NippyJar reads them
+        let jar = NippyJar::<SegmentHeader>::load(&entry.path).unwrap();
+        let config =
+            JarConfig::decode(&std::fs::read(entry.companion_path("conf")).unwrap()).unwrap();
+        let offsets = std::fs::read(entry.companion_path("off")).unwrap();
+        let data = std::fs::read(&entry.path).unwrap();
+        let dictionaries = store.dictionaries(&entry).unwrap();
+        let rows = JarRows::new(
+            &data,
+            Offsets::new(&offsets).unwrap(),
+            config.columns,
+            Compression::ZstdWithDictionary,
+        )
+        .with_dictionaries(dictionaries.iter().map(Vec::as_slice).collect());
+        let mut cursor = NippyJarCursor::new(&jar).unwrap();
+        for row in 0..jar.rows() {
+            let columns = cursor.row_by_number(row).unwrap().unwrap();
+            assert_eq!(
+                rows.row(row).unwrap(),
+                columns.iter().map(|column| column.to_vec()).collect::<Vec<_>>()
+            );
+        }
+
+        // A second static file referencing the same dictionaries
+        let next_range = find_fixed_range(500_000);
+        let next = StaticFileEntry {
+            path: directory.path().join(StaticFileSegment::Receipts.filename(&next_range)),
+            block_range: next_range,
+            ..entry.clone()
+        };
+        std::fs::copy(&entry.path, &next.path).unwrap();
+        for extension in ["conf", "off"] {
+            std::fs::copy(entry.companion_path(extension), next.companion_path(extension)).unwrap();
+        }
+        let entries = list_static_files(directory.path()).unwrap();
+        let highest = HighestStaticFiles { receipts: Some(1_000_000), ..Default::default() };
+        let manifest = StaticFileManifest::new(&entries, highest, NamingScheme::Plain).unwrap();
+        assert_eq!(manifest.files[1].dictionaries, ids);
+        assert!(manifest
+            .dictionary_refs()
+            .iter()
+            .all(|(id, count)| ids.contains(id) && *count == 2));
+
+        // Every dictionary is published and imported once
+        let mirror = tempfile::tempdir().unwrap();
+        manifest.publish(&entries, mirror.path()).unwrap();
+        let published = std::fs::read_dir(mirror.path().join(DICTIONARIES_DIR_NAME)).unwrap();
+        assert_eq!(published.count(), manifest.dictionary_refs().len());
+        let imported = tempfile::tempdir().unwrap();
+        let root = manifest.root();
+        import_static_files(
+            TrustedManifest::Pinned { manifest: &manifest, root },
+            mirror.path(),
+            imported.path(),
+        )
+        .unwrap();
+        let imported_entries = list_static_files(imported.path()).unwrap();
+        assert_eq!(
+            DictionaryStore::new(imported.path()).dictionaries(&imported_entries[1]).unwrap(),
+            dictionaries
+        );
+
+        // Tampered dictionaries are rejected
+        std::fs::write(DictionaryStore::new(mirror.path()).path(&ids[0]), b"tampered").unwrap();
+        let rejected = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            import_static_files(
+                TrustedManifest::Pinned { manifest: &manifest, root },
+                mirror.path(),
+                rejected.path()
+            ),
+            Err(ManifestError::ContentMismatch { .. })
+        ));
+
+        // Dictionaries that were just stored are kept, referenced ones are never removed
+        let unused = store.put(b"unused dictionary").unwrap();
+        assert!(store.gc(&entries, &[], Duration::from_secs(3600)).unwrap().is_empty());
+        assert_eq!(store.gc(&entries, &[], Duration::ZERO).unwrap(), vec![unused]);
+        assert!(ids.iter().all(|id| store.path(id).exists()));
+
+        // Dictionaries listed in a manifest are kept even without static files referencing them
+        assert!(store.gc(&[], &[&manifest], Duration::ZERO).unwrap().is_empty());
+        let mut removed = ids.clone();
+        removed.sort_unstable();
+        removed.dedup();
+        assert_eq!(store.gc(&[], &[], Duration::ZERO).unwrap(), removed);
+    }
+
+    #[test]
+    fn missing_dictionary() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        let config = SegmentConfig {
+            compression: Compression::ZstdWithDictionary,
+            ..StaticFileSegment::Receipts.config()
+        };
+        Receipts::default()
+            .create_static_file_file(&provider, directory.path(), config, 0..=3)
+            .unwrap();
+        let entries = list_static_files(directory.path()).unwrap();
+        let store = DictionaryStore::new(directory.path());
+        let ids = store.share(&entries[0]).unwrap();
+        let highest = HighestStaticFiles { receipts: Some(500_000), ..Default::default() };
+        let manifest = StaticFileManifest::new(&entries, highest, NamingScheme::Plain).unwrap();
+        let mirror = tempfile::tempdir().unwrap();
+        manifest.publish(&entries, mirror.path()).unwrap();
+
+        // Imports fail before anything is copied if the mirror lacks a dictionary
+        std::fs::remove_file(DictionaryStore::new(mirror.path()).path(&ids[0])).unwrap();
+        let imported = tempfile::tempdir().unwrap();
+        let static_files_dir = imported.path().join("static_files");
+        let err = import_static_files(
+            TrustedManifest::Pinned { manifest: &manifest, root: manifest.root() },
+            mirror.path(),
+            &static_files_dir,
+        )
+        .unwrap_err();
+        assert!(matches!(err, ManifestError::Io(err) if err.kind() == io::ErrorKind::NotFound));
+        assert!(!static_files_dir.exists());
+
+        // Reads and publishes of static files referencing a removed dictionary fail, naming it
+        std::fs::remove_file(store.path(&ids[0])).unwrap();
+        assert_eq!(store.dictionaries(&entries[0]).unwrap_err().kind(), io::ErrorKind::NotFound);
+        let err = manifest.publish(&entries, tempfile::tempdir().unwrap().path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains(&dictionary_file_name(&ids[0])));
+
+        // The static file itself is still read with its embedded copy
+        let jar = NippyJar::<SegmentHeader>::load(&entries[0].path).unwrap();
+        let mut cursor = NippyJarCursor::new(&jar).unwrap();
+        assert!(cursor.row_by_number(0).unwrap().is_some());
+    }
+}
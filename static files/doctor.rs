@@ -0,0 +1,515 @@
+//! Doctor detecting corrupt static files and moving them to quarantine, so they can be produced
+//! again.
+
+use crate::{
+    content_hash, manifest::write_json, scan_static_files, tiering::move_cold_to,
+    DirectoryScanError, ManifestEntry, ScanPolicy, StaticFileEntry, StaticFileManifest,
+};
+use alloy_primitives::B64;
+use reth_db_api::table::Decompress;
+use reth_nippy_jar::{NippyJar, NippyJarCursor};
+use reth_primitives::{Header, Receipt, TransactionSignedNoHash};
+use reth_static_file_types::{SegmentHeader, SegmentRangeInclusive, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufReader},
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+/// Name of the directory within the static files directory, that corrupt static files are moved
+/// to.
+pub const QUARANTINE_DIR_NAME: &str = "quarantine";
+
+/// Name of the [`QuarantineReport`] file within the quarantine directory.
+pub const QUARANTINE_REPORT_FILE_NAME: &str = "report.json";
+
+/// Default interval of the rows decoded by [`VerificationLevel::Sampled`].
+pub const DEFAULT_SAMPLE_INTERVAL: usize = 10_000;
+
+/// Default number of random rows decoded by [`VerificationLevel::Sampled`].
+pub const DEFAULT_RANDOM_SAMPLES: usize = 64;
+
+/// Rows of a static file decoded when it's verified.
+///
+/// Decoding every row of multi-gigabyte static files is slow, so only a sample of them is decoded
+/// by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "level", rename_all = "snake_case")]
+pub enum VerificationLevel {
+    /// Every `interval`th row, the first and last rows, and `random` more rows picked with the
+    /// `seed`, so the same rows are decoded again with the same seed.
+    Sampled {
+        /// Interval of the decoded rows.
+        interval: usize,
+        /// Number of rows picked at random.
+        random: usize,
+        /// Seed of the rows picked at random.
+        seed: u64,
+    },
+    /// Every row.
+    Full,
+}
+
+impl VerificationLevel {
+    /// Returns the sampled level with the default interval and number of random rows, picked
+    /// with the seed.
+    pub const fn sampled(seed: u64) -> Self {
+        Self::Sampled { interval: DEFAULT_SAMPLE_INTERVAL, random: DEFAULT_RANDOM_SAMPLES, seed }
+    }
+
+    /// Returns the numbers of the rows to decode out of `rows`, in ascending order.
+    pub fn rows(&self, rows: usize) -> Box<dyn Iterator<Item = usize>> {
+        let Self::Sampled { interval, random, seed } = *self else { return Box::new(0..rows) };
+        if rows == 0 {
+            return Box::new(std::iter::empty())
+        }
+
+        let mut sampled = (0..rows).step_by(interval.max(1)).collect::<BTreeSet<_>>();
+        sampled.insert(rows - 1);
+        let mut state = seed;
+        for _ in 0..random {
+            sampled.insert((splitmix64(&mut state) % rows as u64) as usize);
+        }
+        Box::new(sampled.into_iter())
+    }
+}
+
+impl Default for VerificationLevel {
+    fn default() -> Self {
+        Self::sampled(0)
+    }
+}
+
+/// Corruption of a static file found by the doctor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Corruption {
+    /// The static file couldn't be opened, e.g. because its configuration or offsets are missing
+    /// or truncated.
+    Unreadable {
+        /// Error returned when opening the static file.
+        error: String,
+    },
+    /// The header of the static file doesn't match its file name.
+    HeaderMismatch {
+        /// Segment recorded in the header.
+        segment: StaticFileSegment,
+        /// Fixed block range recorded in the header.
+        block_range: SegmentRangeInclusive,
+    },
+    /// A sampled row of the static file couldn't be decoded.
+    UndecodableRow {
+        /// Number of the row within the static file.
+        row: usize,
+        /// Error returned when decoding the row.
+        error: String,
+    },
+    /// The size of the static file doesn't match the manifest.
+    SizeMismatch {
+        /// Size listed in the manifest.
+        expected: u64,
+        /// Size of the data and companion files.
+        got: u64,
+    },
+    /// The content hash of the static file doesn't match the manifest.
+    ChecksumMismatch {
+        /// Content hash listed in the manifest.
+        expected: B64,
+        /// Content hash of the data and companion files.
+        got: B64,
+    },
+}
+
+/// Static file moved to quarantine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantinedFile {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file, to produce again.
+    pub block_range: SegmentRangeInclusive,
+    /// Name of the data file in the quarantine directory. Companion files share the name, with
+    /// an extension.
+    pub file_name: String,
+    /// Corruption the static file was quarantined for.
+    pub corruption: Corruption,
+}
+
+/// Report of all static files moved to quarantine, stored as [`QUARANTINE_REPORT_FILE_NAME`] in
+/// the quarantine directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineReport {
+    /// Quarantined static files, in the order they were quarantined in.
+    pub files: Vec<QuarantinedFile>,
+}
+
+impl QuarantineReport {
+    /// Reads the report from a JSON file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes the report to a JSON file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_json(self, path)
+    }
+}
+
+/// Metadata of a static file, returned by [`dump_header`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeaderDump {
+    /// Path to the data file.
+    pub path: PathBuf,
+    /// Number of rows.
+    pub rows: usize,
+    /// Number of columns of every row.
+    pub columns: usize,
+    /// Size of the data file in bytes.
+    pub data_size: u64,
+    /// Header of the segment, with all its metadata.
+    pub header: SegmentHeader,
+}
+
+impl HeaderDump {
+    /// Returns the metadata as indented JSON, with the header in the format of
+    /// [`SegmentHeader::to_pretty_json`].
+    pub fn to_pretty_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("header dump serializes to JSON")
+    }
+}
+
+/// Outcome of a [`scan`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanOutcome {
+    /// Number of scanned static files.
+    pub scanned: usize,
+    /// Rows decoded in every scanned static file.
+    pub verification: VerificationLevel,
+    /// Static files moved to quarantine by the scan.
+    pub quarantined: Vec<QuarantinedFile>,
+}
+
+/// Scans all static files in the directory, moving corrupt ones with their companion files to the
+/// [`QUARANTINE_DIR_NAME`] directory and recording them in its [`QuarantineReport`].
+///
+/// Every static file is opened, its header is checked against its file name, and a sample of its
+/// rows is decoded, see [`VerificationLevel::default`].
+///
+/// The static file provider has to reload its index after static files were quarantined.
+/// Inconsistencies of the directory are handled with [`ScanPolicy::Permissive`].
+pub fn scan(directory: &Path) -> io::Result<ScanOutcome> {
+    Ok(scan_with_manifest(directory, None, ScanPolicy::Permissive, VerificationLevel::default())?)
+}
+
+/// Same as [`scan`], failing on unknown files and static files with duplicate or overlapping
+/// block ranges if the policy is [`ScanPolicy::Strict`].
+pub fn scan_with_policy(
+    directory: &Path,
+    policy: ScanPolicy,
+) -> Result<ScanOutcome, DirectoryScanError> {
+    scan_with_manifest(directory, None, policy, VerificationLevel::default())
+}
+
+/// Same as [`scan_with_policy`], decoding the rows of the [`VerificationLevel`], e.g.
+/// [`VerificationLevel::Full`] to decode every row.
+pub fn scan_with_level(
+    directory: &Path,
+    policy: ScanPolicy,
+    level: VerificationLevel,
+) -> Result<ScanOutcome, DirectoryScanError> {
+    scan_with_manifest(directory, None, policy, level)
+}
+
+/// Same as [`scan`], additionally verifying the size and content hash of the static files
+/// listed in the manifest.
+pub fn scan_against(directory: &Path, manifest: &StaticFileManifest) -> io::Result<ScanOutcome> {
+    Ok(scan_with_manifest(
+        directory,
+        Some(manifest),
+        ScanPolicy::Permissive,
+        VerificationLevel::default(),
+    )?)
+}
+
+/// Scans the static files, decoding the rows of the level and verifying them against the
+/// manifest if it's given.
+fn scan_with_manifest(
+    directory: &Path,
+    manifest: Option<&StaticFileManifest>,
+    policy: ScanPolicy,
+    level: VerificationLevel,
+) -> Result<ScanOutcome, DirectoryScanError> {
+    let entries = scan_static_files(directory, policy)?.entries;
+
+    let mut quarantined = Vec::new();
+    for entry in &entries {
+        let manifest_entry = manifest.and_then(|manifest| {
+            manifest
+                .files
+                .iter()
+                .find(|file| file.segment == entry.segment && file.block_range == entry.block_range)
+        });
+        if let Some(corruption) = diagnose_with_level(entry, manifest_entry, level)? {
+            quarantined.push(quarantine(directory, entry, corruption)?);
+        }
+    }
+
+    if !quarantined.is_empty() {
+        let path = directory.join(QUARANTINE_DIR_NAME).join(QUARANTINE_REPORT_FILE_NAME);
+        let mut report = if path.exists() {
+            QuarantineReport::read(&path)?
+        } else {
+            QuarantineReport::default()
+        };
+        report.files.extend(quarantined.iter().cloned());
+        report.write(&path)?;
+    }
+
+    Ok(ScanOutcome { scanned: entries.len(), verification: level, quarantined })
+}
+
+/// Diagnoses the static file, decoding a sample of its rows, see [`VerificationLevel::default`].
+/// Returns its corruption, if any.
+///
+/// If the static file is listed in a manifest, its size and content hash are verified too.
+pub fn diagnose(
+    entry: &StaticFileEntry,
+    manifest_entry: Option<&ManifestEntry>,
+) -> io::Result<Option<Corruption>> {
+    diagnose_with_level(entry, manifest_entry, VerificationLevel::default())
+}
+
+/// Same as [`diagnose`], decoding the rows of the [`VerificationLevel`].
+pub fn diagnose_with_level(
+    entry: &StaticFileEntry,
+    manifest_entry: Option<&ManifestEntry>,
+    level: VerificationLevel,
+) -> io::Result<Option<Corruption>> {
+    let jar = match NippyJar::<SegmentHeader>::load(&entry.path) {
+        Ok(jar) => jar,
+        Err(err) => return Ok(Some(Corruption::Unreadable { error: err.to_string() })),
+    };
+
+    let header = jar.user_header();
+    let block_range =
+        SegmentRangeInclusive::new(header.expected_block_start(), header.expected_block_end());
+    if header.segment() != entry.segment || block_range != entry.block_range {
+        return Ok(Some(Corruption::HeaderMismatch { segment: header.segment(), block_range }))
+    }
+
+    let mut cursor = match NippyJarCursor::new(&jar) {
+        Ok(cursor) => cursor,
+        Err(err) => return Ok(Some(Corruption::Unreadable { error: err.to_string() })),
+    };
+    for row in level.rows(jar.rows()) {
+        let decoded = match cursor.row_by_number(row) {
+            Ok(Some(columns)) => decode_row(header, &columns),
+            Ok(None) => Err("row is missing".to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(error) = decoded {
+            return Ok(Some(Corruption::UndecodableRow { row, error }))
+        }
+    }
+
+    if let Some(manifest_entry) = manifest_entry {
+        let mut size = 0;
+        for path in entry.paths() {
+            size += path.metadata()?.len();
+        }
+        if size != manifest_entry.size {
+            return Ok(Some(Corruption::SizeMismatch { expected: manifest_entry.size, got: size }))
+        }
+
+        if let Some(expected) = manifest_entry.content_hash {
+            let got = content_hash(entry)?;
+            if got != expected {
+                return Ok(Some(Corruption::ChecksumMismatch { expected, got }))
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads all metadata of the static file at the path, to be printed with
+/// [`HeaderDump::to_pretty_json`], without checking its rows.
+pub fn dump_header(path: &Path) -> io::Result<HeaderDump> {
+    let jar = NippyJar::<SegmentHeader>::load(path).map_err(io::Error::other)?;
+    Ok(HeaderDump {
+        path: path.to_path_buf(),
+        rows: jar.rows(),
+        columns: jar.columns(),
+        data_size: path.metadata()?.len(),
+        header: jar.user_header().clone(),
+    })
+}
+
+/// Returns the numbers of the first, middle and last rows.
+pub(crate) fn sampled_rows(rows: usize) -> Vec<usize> {
+    let mut sampled = vec![0, rows / 2, rows.saturating_sub(1)];
+    sampled.dedup();
+    sampled.retain(|row| *row < rows);
+    sampled
+}
+
+/// Advances the splitmix64 generator, returning its next value.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Decodes the first column of the row of the static file with the header, holding the header,
+/// transaction or receipt.
+pub(crate) fn decode_row(header: &SegmentHeader, columns: &[&[u8]]) -> Result<(), String> {
+    let Some(value) = columns.first().copied() else {
+        return Err("row has no columns".to_string())
+    };
+
+    // Compact decoding panics on some malformed values instead of returning an error
+    let decoded = catch_unwind(AssertUnwindSafe(|| match header.segment() {
+        StaticFileSegment::Headers => {
+            Header::decompress(value).map(drop).map_err(|err| err.to_string())
+        }
+        StaticFileSegment::Transactions => {
+            TransactionSignedNoHash::decompress(value).map(drop).map_err(|err| err.to_string())
+        }
+        StaticFileSegment::Receipts => {
+            Receipt::decompress(value).map(drop).map_err(|err| err.to_string())
+        }
+    }));
+    decoded.unwrap_or_else(|_| Err("decoding panicked".to_string()))
+}
+
+/// Moves the data and companion files of the static file to the quarantine directory. Files of
+/// static files moved to the cold directory are moved from there, instead of their links.
+fn quarantine(
+    directory: &Path,
+    entry: &StaticFileEntry,
+    corruption: Corruption,
+) -> io::Result<QuarantinedFile> {
+    let quarantine_dir = directory.join(QUARANTINE_DIR_NAME);
+    std::fs::create_dir_all(&quarantine_dir)?;
+    if !move_cold_to(directory, entry, &quarantine_dir)? {
+        for path in entry.paths() {
+            if let Some(file_name) = path.file_name() {
+                std::fs::rename(&path, quarantine_dir.join(file_name))?;
+            }
+        }
+    }
+
+    Ok(QuarantinedFile {
+        segment: entry.segment,
+        block_range: entry.block_range,
+        file_name: entry.segment.filename(&entry.block_range),
+        corruption,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_static_files, test_utils::StaticFileTestHarness, ScanIssue};
+
+    #[test]
+    fn quarantines_corrupt_static_files() {
+        let directory = tempfile::tempdir().unwrap();
+        // Data and offsets without a configuration can't be opened
+        std::fs::write(directory.path().join("static_file_headers_0_499999"), b"rows").unwrap();
+        std::fs::write(directory.path().join("static_file_headers_0_499999.off"), b"offsets")
+            .unwrap();
+        std::fs::write(directory.path().join("other"), b"other").unwrap();
+
+        let outcome = scan(directory.path()).unwrap();
+        assert_eq!(outcome.scanned, 1);
+        assert_eq!(outcome.quarantined.len(), 1);
+        assert_eq!(outcome.quarantined[0].segment, StaticFileSegment::Headers);
+        assert!(matches!(outcome.quarantined[0].corruption, Corruption::Unreadable { .. }));
+
+        let quarantine_dir = directory.path().join(QUARANTINE_DIR_NAME);
+        assert!(quarantine_dir.join("static_file_headers_0_499999").exists());
+        assert!(quarantine_dir.join("static_file_headers_0_499999.off").exists());
+        assert!(list_static_files(directory.path()).unwrap().is_empty());
+        assert!(directory.path().join("other").exists());
+        assert_eq!(
+            QuarantineReport::read(&quarantine_dir.join(QUARANTINE_REPORT_FILE_NAME))
+                .unwrap()
+                .files,
+            outcome.quarantined
+        );
+
+        // Nothing left to quarantine
+        assert_eq!(scan(directory.path()).unwrap(), ScanOutcome::default());
+    }
+
+    #[test]
+    fn strict_scan() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("other"), b"other").unwrap();
+
+        assert!(matches!(
+            scan_with_policy(directory.path(), ScanPolicy::Strict),
+            Err(DirectoryScanError::Inconsistent(ScanIssue::UnknownFile(_)))
+        ));
+        assert_eq!(
+            scan_with_policy(directory.path(), ScanPolicy::Permissive).unwrap(),
+            ScanOutcome::default()
+        );
+    }
+
+    #[test]
+    fn dumps_header() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let entry = list_static_files(harness.static_files_dir.path()).unwrap().remove(0);
+
+        let dump = dump_header(&entry.path).unwrap();
+        assert_eq!(dump.header.segment(), StaticFileSegment::Headers);
+        assert_eq!(dump.rows as u64, harness.tip() + 1);
+        let json = serde_json::from_str::<serde_json::Value>(&dump.to_pretty_json()).unwrap();
+        assert_eq!(json["header"]["expected_block_range"], "0..=499999");
+
+        assert!(dump_header(&harness.static_files_dir.path().join("missing")).is_err());
+    }
+
+    #[test]
+    fn verification_levels() {
+        assert_eq!(VerificationLevel::Full.rows(3).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(VerificationLevel::default().rows(0).count(), 0);
+
+        // Every interval, the first and last rows, and the same random rows for the same seed
+        let level = VerificationLevel::Sampled { interval: 4, random: 2, seed: 7 };
+        let rows = level.rows(10).collect::<Vec<_>>();
+        assert!([0, 4, 8, 9].iter().all(|row| rows.contains(row)));
+        assert!(rows.len() <= 6 && rows.windows(2).all(|rows| rows[0] < rows[1]));
+        assert_eq!(level.rows(10).collect::<Vec<_>>(), rows);
+        assert!(VerificationLevel::default().rows(1_000_000).count() <= 164);
+
+        // Healthy static files pass both levels, and scans report the level they decoded
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let entry = list_static_files(harness.static_files_dir.path()).unwrap().remove(0);
+        let level = VerificationLevel::Sampled { interval: 4, random: 0, seed: 0 };
+        for level in [level, VerificationLevel::Full] {
+            assert_eq!(diagnose_with_level(&entry, None, level).unwrap(), None);
+        }
+        let outcome =
+            scan_with_level(harness.static_files_dir.path(), ScanPolicy::Permissive, level)
+                .unwrap();
+        assert_eq!(outcome.verification, level);
+        assert!(outcome.quarantined.is_empty());
+    }
+
+    #[test]
+    fn samples_rows() {
+        assert_eq!(super::sampled_rows(0), Vec::<usize>::new());
+        assert_eq!(super::sampled_rows(1), vec![0]);
+        assert_eq!(super::sampled_rows(2), vec![0, 1]);
+        assert_eq!(super::sampled_rows(10), vec![0, 5, 9]);
+    }
+}
@@ -0,0 +1,789 @@
+//! Resumable downloads of static files from mirrors.
+
+use crate::{
+    doctor::{decode_row, sampled_rows},
+    manifest::{content_hash, truncated_hash},
+    rollback::sync_directory,
+    ChunkHashes, ManifestEntry, ManifestError, StaticFileEntry, StaticFileProducerEvent,
+    TrustedManifest, COMPANION_EXTENSIONS,
+};
+use parking_lot::Mutex;
+use reth_nippy_jar::{NippyJar, NippyJarCursor};
+use reth_static_file_types::{
+    decompress, Compression, JarConfig, Offsets, SegmentHeader, StaticFileSegment,
+};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+};
+
+/// Default number of parallel connections per downloaded file.
+pub const DEFAULT_DOWNLOAD_CONNECTIONS: usize = 4;
+
+/// Source of static files, e.g. an HTTP mirror serving ranged requests.
+pub trait RangeFetcher: Sync {
+    /// Fetches the byte range of the file.
+    fn fetch_range(&self, file_name: &str, range: Range<u64>) -> io::Result<Vec<u8>>;
+
+    /// Fetches the whole file, returning `None` if it doesn't exist.
+    fn fetch(&self, file_name: &str) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// Error returned by [`download_static_file`].
+#[derive(Debug)]
+pub enum DownloadError {
+    /// Filesystem or transport error.
+    Io(io::Error),
+    /// The manifest isn't trusted.
+    Manifest(ManifestError),
+    /// The static file isn't listed in the trusted manifest.
+    NotInManifest {
+        /// Name of the static file.
+        file_name: String,
+    },
+    /// The manifest has no [`ChunkHashes`](crate::ChunkHashes) for the file.
+    MissingChunkHashes {
+        /// Name of the static file.
+        file_name: String,
+    },
+    /// Downloaded chunk doesn't match its hash.
+    ChunkMismatch {
+        /// Name of the static file.
+        file_name: String,
+        /// Index of the chunk.
+        chunk: usize,
+    },
+    /// The offsets or configuration file doesn't match the data file.
+    InvalidOffsets {
+        /// Name of the static file.
+        file_name: String,
+    },
+    /// A sampled row of the data file can't be decoded.
+    InvalidRow {
+        /// Name of the static file.
+        file_name: String,
+        /// Number of the row.
+        row: usize,
+        /// Decoding error.
+        error: String,
+    },
+    /// The data and companion files don't add up to the size listed in the manifest.
+    SizeMismatch {
+        /// Name of the static file.
+        file_name: String,
+        /// Size listed in the manifest.
+        expected: u64,
+        /// Size of the downloaded files.
+        got: u64,
+    },
+    /// The data and companion files don't match the content hash listed in the manifest.
+    ContentHashMismatch {
+        /// Name of the static file.
+        file_name: String,
+    },
+}
+
+impl From<io::Error> for DownloadError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ManifestError> for DownloadError {
+    fn from(value: ManifestError) -> Self {
+        Self::Manifest(value)
+    }
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::Manifest(err) => fmt::Display::fmt(err, f),
+            Self::NotInManifest { file_name } => {
+                write!(f, "static file {file_name} isn't listed in the trusted manifest")
+            }
+            Self::MissingChunkHashes { file_name } => {
+                write!(f, "manifest has no chunk hashes for static file {file_name}")
+            }
+            Self::ChunkMismatch { file_name, chunk } => {
+                write!(f, "chunk {chunk} of static file {file_name} doesn't match the manifest")
+            }
+            Self::InvalidOffsets { file_name } => {
+                write!(f, "offsets of static file {file_name} don't match its data file")
+            }
+            Self::InvalidRow { file_name, row, error } => {
+                write!(f, "row {row} of static file {file_name} can't be decoded: {error}")
+            }
+            Self::SizeMismatch { file_name, expected, got } => {
+                write!(
+                    f,
+                    "static file {file_name} has {got} bytes, but the manifest lists {expected}"
+                )
+            }
+            Self::ContentHashMismatch { file_name } => {
+                write!(f, "static file {file_name} doesn't match its content hash in the manifest")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Manifest(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Downloads the static file listed in the manifest into the directory, under its manifest name,
+/// fetching chunks of the data file over `connections` parallel connections.
+///
+/// The manifest is verified first, and the static file has to be listed in it, so the chunk
+/// hashes it's checked against can be trusted.
+///
+/// The data and companion files are staged in a `.part` directory first, and only moved into the
+/// directory once they match the size and content hash listed in the manifest. Chunks of the data
+/// file already staged by an interrupted download are verified against their hashes and skipped,
+/// so only missing or corrupted chunks are fetched again. Returns the path to the downloaded data
+/// file.
+///
+/// See [`download_static_file_with_events`] for the verification done while downloading.
+pub fn download_static_file(
+    fetcher: &(impl RangeFetcher + ?Sized),
+    manifest: TrustedManifest<'_>,
+    file: &ManifestEntry,
+    directory: &Path,
+    connections: usize,
+) -> Result<PathBuf, DownloadError> {
+    download_static_file_with_events(fetcher, manifest, file, directory, connections, &|_| {})
+}
+
+/// Downloads the static file listed in the manifest like [`download_static_file`], emitting
+/// [`StaticFileProducerEvent::VerificationProgress`] whenever chunks of the data file were
+/// verified.
+///
+/// Every chunk is verified against its hash as soon as it's fetched. Companion files are
+/// fetched first and checked against the size listed in the manifest, so the first, middle and
+/// last rows of the data file are decoded as soon as the chunks holding them are verified, and
+/// the download is aborted at the first chunk or row that doesn't match, without fetching the
+/// rest of the data file. Rows compressed with [`Compression::ZstdWithDictionary`] are decoded
+/// once the data file is complete, since their dictionaries are only in the NippyJar
+/// configuration. Rows of static files whose compression is not in their manifest name are only
+/// checked against their offsets.
+pub fn download_static_file_with_events(
+    fetcher: &(impl RangeFetcher + ?Sized),
+    manifest: TrustedManifest<'_>,
+    file: &ManifestEntry,
+    directory: &Path,
+    connections: usize,
+    on_event: &(dyn Fn(StaticFileProducerEvent) + Sync),
+) -> Result<PathBuf, DownloadError> {
+    if !manifest.verify()?.files.contains(file) {
+        return Err(DownloadError::NotInManifest { file_name: file.file_name.clone() })
+    }
+    let Some(chunks) = &file.chunks else {
+        return Err(DownloadError::MissingChunkHashes { file_name: file.file_name.clone() })
+    };
+
+    let staging = directory.join(format!("{}.part", file.file_name));
+    std::fs::create_dir_all(&staging)?;
+    let staged = StaticFileEntry {
+        segment: file.segment,
+        block_range: file.block_range,
+        path: staging.join(&file.file_name),
+    };
+    let part_path = &staged.path;
+
+    let mut size = chunks.data_size;
+    for extension in COMPANION_EXTENSIONS {
+        let path = staged.companion_path(extension);
+        match fetcher.fetch(&format!("{}.{extension}", file.file_name))? {
+            Some(data) => {
+                size += data.len() as u64;
+                File::create(path)?.write_all(&data)?;
+            }
+            // Companion files staged by an interrupted download may no longer be served
+            None => remove_if_exists(&path)?,
+        }
+    }
+    if size != file.size {
+        return Err(DownloadError::SizeMismatch {
+            file_name: file.file_name.clone(),
+            expected: file.size,
+            got: size,
+        })
+    }
+    let mut sampler = RowSampler::new(file, &staged, chunks.data_size)?;
+
+    let mut part =
+        OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&part_path)?;
+    part.set_len(chunks.data_size)?;
+
+    // Chunks that are missing or corrupted in the partial download
+    let mut pending = Vec::new();
+    let mut verified_chunks = vec![false; chunks.hashes.len()];
+    for (chunk, hash) in chunks.hashes.iter().enumerate() {
+        let range = chunks.range(chunk);
+        let mut hasher = blake3::Hasher::new();
+        part.seek(SeekFrom::Start(range.start))?;
+        io::copy(&mut (&mut part).take(range.end - range.start), &mut hasher)?;
+        if truncated_hash(&hasher) == *hash {
+            verified_chunks[chunk] = true;
+        } else {
+            pending.push(chunk);
+        }
+    }
+    if let Some(sampler) = &mut sampler {
+        sampler.sample(file, &mut part, chunks, &verified_chunks)?;
+    }
+
+    let verified = AtomicU64::new(
+        (0..chunks.hashes.len())
+            .filter(|chunk| verified_chunks[*chunk])
+            .map(|chunk| chunks.range(chunk).end - chunks.range(chunk).start)
+            .sum(),
+    );
+    let notify_progress = |verified: u64| {
+        on_event(StaticFileProducerEvent::VerificationProgress {
+            file_name: file.file_name.clone(),
+            verified,
+            total: chunks.data_size,
+        })
+    };
+    notify_progress(verified.load(Ordering::Relaxed));
+
+    let state = Mutex::new((part, verified_chunks, sampler));
+    let next = AtomicUsize::new(0);
+    let download_chunk = |chunk: usize| -> Result<(), DownloadError> {
+        let range = chunks.range(chunk);
+        let data = fetcher.fetch_range(&file.file_name, range.clone())?;
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&data);
+        if data.len() as u64 != range.end - range.start ||
+            truncated_hash(&hasher) != chunks.hashes[chunk]
+        {
+            return Err(DownloadError::ChunkMismatch { file_name: file.file_name.clone(), chunk })
+        }
+
+        let mut state = state.lock();
+        let (part, verified_chunks, sampler) = &mut *state;
+        part.seek(SeekFrom::Start(range.start))?;
+        part.write_all(&data)?;
+        verified_chunks[chunk] = true;
+        if let Some(sampler) = sampler {
+            sampler.sample(file, part, chunks, verified_chunks)?;
+        }
+        drop(state);
+
+        notify_progress(
+            verified.fetch_add(data.len() as u64, Ordering::Relaxed) + data.len() as u64,
+        );
+        Ok(())
+    };
+    let download_chunks = || -> Result<(), DownloadError> {
+        while let Some(&chunk) = pending.get(next.fetch_add(1, Ordering::Relaxed)) {
+            if let Err(err) = download_chunk(chunk) {
+                // Other connections stop at their next chunk
+                next.store(pending.len(), Ordering::Relaxed);
+                return Err(err)
+            }
+        }
+        Ok(())
+    };
+
+    std::thread::scope(|scope| {
+        let workers = (0..connections.clamp(1, pending.len().max(1)))
+            .map(|_| scope.spawn(download_chunks))
+            .collect::<Vec<_>>();
+        workers.into_iter().try_for_each(|worker| worker.join().expect("download worker panicked"))
+    })?;
+
+    let (part, _, sampler) = state.into_inner();
+    part.sync_all()?;
+    drop(part);
+    if let Some(sampler) = &sampler {
+        sampler.finish(file, part_path)?;
+    }
+    if let Some(expected) = file.content_hash {
+        if content_hash(&staged)? != expected {
+            return Err(DownloadError::ContentHashMismatch { file_name: file.file_name.clone() })
+        }
+    }
+
+    // Companion files are moved first, and stale ones of a previous download removed, so the
+    // data file is only in place once all of them are
+    let path = directory.join(&file.file_name);
+    for extension in COMPANION_EXTENSIONS {
+        let staged_path = staged.companion_path(extension);
+        let companion = directory.join(format!("{}.{extension}", file.file_name));
+        if staged_path.exists() {
+            File::open(&staged_path)?.sync_all()?;
+            std::fs::rename(staged_path, companion)?;
+        } else {
+            remove_if_exists(&companion)?;
+        }
+    }
+    std::fs::rename(part_path, &path)?;
+    sync_directory(directory)?;
+    std::fs::remove_dir(&staging)?;
+
+    Ok(path)
+}
+
+/// Removes the file, unless it doesn't exist.
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Rows of a data file decoded while it's downloaded, as soon as all chunks holding them are
+/// verified.
+#[derive(Debug)]
+struct RowSampler {
+    /// Header of the static file.
+    header: SegmentHeader,
+    /// Compression of the static file, if it's in its manifest name.
+    compression: Option<Compression>,
+    /// Sampled rows that aren't decoded yet, with the byte range of their first column.
+    pending: Vec<(usize, Range<u64>)>,
+    /// Sampled rows that are only decoded once the data file is complete.
+    deferred: Vec<usize>,
+}
+
+impl RowSampler {
+    /// Creates a new [`RowSampler`] of the first, middle and last rows, with the staged
+    /// configuration and offsets files. Returns `None` if the static file has none of them.
+    fn new(
+        file: &ManifestEntry,
+        staged: &StaticFileEntry,
+        data_size: u64,
+    ) -> Result<Option<Self>, DownloadError> {
+        let read = |extension: &str| -> io::Result<Option<Vec<u8>>> {
+            match std::fs::read(staged.companion_path(extension)) {
+                Ok(data) => Ok(Some(data)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            }
+        };
+        let (Some(config), Some(offsets)) = (read("conf")?, read("off")?) else { return Ok(None) };
+
+        let invalid = || DownloadError::InvalidOffsets { file_name: file.file_name.clone() };
+        let config = JarConfig::decode(&config).map_err(|_| invalid())?;
+        let offsets = Offsets::new(&offsets).map_err(|_| invalid())?;
+        let columns = config.columns.max(1);
+        if offsets.len() != config.rows * columns + 1 ||
+            offsets.get(offsets.len() - 1) != Some(data_size)
+        {
+            return Err(invalid())
+        }
+
+        let mut pending = Vec::new();
+        for row in sampled_rows(config.rows) {
+            let (Some(start), Some(end)) =
+                (offsets.get(row * columns), offsets.get(row * columns + 1))
+            else {
+                return Err(invalid())
+            };
+            if start > end || end > data_size {
+                return Err(invalid())
+            }
+            pending.push((row, start..end));
+        }
+
+        let compression = StaticFileSegment::parse_configured_filename(&file.file_name)
+            .and_then(|(_, _, config)| config)
+            .map(|config| config.compression);
+        let deferred = if compression == Some(Compression::ZstdWithDictionary) {
+            pending.drain(..).map(|(row, _)| row).collect()
+        } else {
+            Vec::new()
+        };
+        Ok(Some(Self { header: config.header, compression, pending, deferred }))
+    }
+
+    /// Decodes the pending rows held by verified chunks of the partial data file.
+    fn sample(
+        &mut self,
+        file: &ManifestEntry,
+        part: &mut File,
+        chunks: &ChunkHashes,
+        verified_chunks: &[bool],
+    ) -> Result<(), DownloadError> {
+        let chunk_size = chunks.chunk_size.max(1);
+        let is_verified = |range: &Range<u64>| {
+            let last = range.end.saturating_sub(1).max(range.start);
+            (range.start / chunk_size..=last / chunk_size)
+                .all(|chunk| verified_chunks.get(chunk as usize).copied().unwrap_or(true))
+        };
+
+        let mut index = 0;
+        while let Some((row, range)) = self.pending.get(index).cloned() {
+            if !is_verified(&range) {
+                index += 1;
+                continue
+            }
+            self.pending.swap_remove(index);
+
+            // Without a known compression, the row is only checked against its offsets
+            let Some(compression) = self.compression else { continue };
+            let mut column = vec![0; (range.end - range.start) as usize];
+            part.seek(SeekFrom::Start(range.start))?;
+            part.read_exact(&mut column)?;
+            let decoded = decompress(compression, &column, None)
+                .map_err(|err| err.to_string())
+                .and_then(|value| decode_row(&self.header, &[&value]));
+            if let Err(error) = decoded {
+                return Err(DownloadError::InvalidRow {
+                    file_name: file.file_name.clone(),
+                    row,
+                    error,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the deferred rows of the complete data file at the path, with the dictionaries of
+    /// its NippyJar configuration.
+    fn finish(&self, file: &ManifestEntry, path: &Path) -> Result<(), DownloadError> {
+        let Some(&first) = self.deferred.first() else { return Ok(()) };
+        let invalid_row = |row, error| DownloadError::InvalidRow {
+            file_name: file.file_name.clone(),
+            row,
+            error,
+        };
+
+        let jar = NippyJar::<SegmentHeader>::load(path)
+            .map_err(|err| invalid_row(first, err.to_string()))?;
+        let mut cursor =
+            NippyJarCursor::new(&jar).map_err(|err| invalid_row(first, err.to_string()))?;
+        for &row in &self.deferred {
+            let decoded = match cursor.row_by_number(row) {
+                Ok(Some(columns)) => decode_row(&self.header, &columns),
+                Ok(None) => Err("row is missing".to_string()),
+                Err(err) => Err(err.to_string()),
+            };
+            if let Err(error) = decoded {
+                return Err(invalid_row(row, error))
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        list_static_files, test_utils::StaticFileTestHarness, ChunkHashes, StaticFileManifest,
+    };
+    use reth_static_file_types::{Filters, SegmentRangeInclusive, StaticFileSegment};
+    use std::collections::HashMap;
+
+    /// Serves files from memory, failing ranged requests starting at `fail_at`.
+    struct MemoryFetcher {
+        files: HashMap<String, Vec<u8>>,
+        fail_at: Option<u64>,
+        fetched: Mutex<Vec<u64>>,
+    }
+
+    impl RangeFetcher for MemoryFetcher {
+        fn fetch_range(&self, file_name: &str, range: Range<u64>) -> io::Result<Vec<u8>> {
+            if self.fail_at == Some(range.start) {
+                return Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset"))
+            }
+            self.fetched.lock().push(range.start);
+            Ok(self.files[file_name][range.start as usize..range.end as usize].to_vec())
+        }
+
+        fn fetch(&self, file_name: &str) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.files.get(file_name).cloned())
+        }
+    }
+
+    /// Returns a manifest listing only the static file.
+    fn manifest_of(file: &ManifestEntry) -> StaticFileManifest {
+        StaticFileManifest { files: vec![file.clone()], ..Default::default() }
+    }
+
+    /// Pins the manifest to its own root.
+    fn pinned(manifest: &StaticFileManifest) -> TrustedManifest<'_> {
+        TrustedManifest::Pinned { manifest, root: manifest.root() }
+    }
+
+    /// Returns the manifest entry of 100 bytes of data with an offsets file, and a fetcher
+    /// serving them.
+    fn memory_static_file(source: &Path) -> (ManifestEntry, MemoryFetcher) {
+        let data = (0..100u8).collect::<Vec<_>>();
+        let data_path = source.join("static_file_receipts_0_499999");
+        std::fs::write(&data_path, &data).unwrap();
+
+        let file = ManifestEntry {
+            segment: StaticFileSegment::Receipts,
+            block_range: SegmentRangeInclusive::new(0, 499_999),
+            file_name: "static_file_receipts_0_499999".to_string(),
+            content_hash: None,
+            size: 104,
+            chunks: Some(ChunkHashes::new(&data_path, 16).unwrap()),
+            dictionaries: Vec::new(),
+            verification: None,
+            row_root: None,
+        };
+        let fetcher = MemoryFetcher {
+            files: HashMap::from([
+                (file.file_name.clone(), data),
+                (format!("{}.off", file.file_name), vec![1, 2, 3, 4]),
+            ]),
+            fail_at: None,
+            fetched: Mutex::new(Vec::new()),
+        };
+        (file, fetcher)
+    }
+
+    /// Returns the content-hashed manifest entry of the headers static file of the harness,
+    /// named with the compression, and a fetcher serving it.
+    fn served_headers(
+        harness: &StaticFileTestHarness,
+        compression: Compression,
+    ) -> (ManifestEntry, MemoryFetcher) {
+        let entry = list_static_files(harness.static_files_dir.path())
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.segment == StaticFileSegment::Headers)
+            .unwrap();
+        let file_name = entry.segment.filename_with_configuration(
+            Filters::WithoutFilters,
+            compression,
+            &entry.block_range,
+        );
+
+        let mut files = HashMap::from([(file_name.clone(), std::fs::read(&entry.path).unwrap())]);
+        for extension in COMPANION_EXTENSIONS {
+            if let Ok(data) = std::fs::read(entry.companion_path(extension)) {
+                files.insert(format!("{file_name}.{extension}"), data);
+            }
+        }
+        let file = ManifestEntry {
+            segment: entry.segment,
+            block_range: entry.block_range,
+            file_name,
+            content_hash: Some(content_hash(&entry).unwrap()),
+            size: files.values().map(|data| data.len() as u64).sum(),
+            chunks: Some(ChunkHashes::new(&entry.path, 64).unwrap()),
+            dictionaries: Vec::new(),
+            verification: None,
+            row_root: None,
+        };
+        (file, MemoryFetcher { files, fail_at: None, fetched: Mutex::new(Vec::new()) })
+    }
+
+    #[test]
+    fn resumes_interrupted_download() {
+        let source = tempfile::tempdir().unwrap();
+        let data = (0..100u8).collect::<Vec<_>>();
+        let data_path = source.path().join("static_file_receipts_0_499999");
+        std::fs::write(&data_path, &data).unwrap();
+
+        let file = ManifestEntry {
+            segment: StaticFileSegment::Receipts,
+            block_range: SegmentRangeInclusive::new(0, 499_999),
+            file_name: "static_file_receipts_0_499999".to_string(),
+            content_hash: None,
+            size: 104,
+            chunks: Some(ChunkHashes::new(&data_path, 16).unwrap()),
+            dictionaries: Vec::new(),
+            verification: None,
+            row_root: None,
+        };
+        let mut fetcher = MemoryFetcher {
+            files: HashMap::from([
+                (file.file_name.clone(), data.clone()),
+                (format!("{}.off", file.file_name), vec![1, 2, 3, 4]),
+            ]),
+            fail_at: Some(48),
+            fetched: Mutex::new(Vec::new()),
+        };
+
+        let manifest = manifest_of(&file);
+
+        // Single connection fetches chunks in order, until the connection is reset
+        let directory = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            download_static_file(&fetcher, pinned(&manifest), &file, directory.path(), 1),
+            Err(DownloadError::Io(_))
+        ));
+        assert_eq!(*fetcher.fetched.lock(), vec![0, 16, 32]);
+
+        // Only the missing chunks are fetched when resuming
+        fetcher.fail_at = None;
+        fetcher.fetched.lock().clear();
+        let path =
+            download_static_file(&fetcher, pinned(&manifest), &file, directory.path(), 2).unwrap();
+        let mut fetched = fetcher.fetched.into_inner();
+        fetched.sort_unstable();
+        assert_eq!(fetched, vec![48, 64, 80, 96]);
+        assert_eq!(std::fs::read(path).unwrap(), data);
+        assert_eq!(
+            std::fs::read(directory.path().join(format!("{}.off", file.file_name))).unwrap(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(!directory.path().join(format!("{}.part", file.file_name)).exists());
+    }
+
+    #[test]
+    fn reports_verification_progress() {
+        let source = tempfile::tempdir().unwrap();
+        let (file, fetcher) = memory_static_file(source.path());
+
+        let progress = Mutex::new(Vec::new());
+        let on_event = |event: StaticFileProducerEvent| {
+            if let StaticFileProducerEvent::VerificationProgress { verified, total, .. } = event {
+                assert_eq!(total, 100);
+                progress.lock().push(verified);
+            }
+        };
+        let directory = tempfile::tempdir().unwrap();
+        download_static_file_with_events(
+            &fetcher,
+            pinned(&manifest_of(&file)),
+            &file,
+            directory.path(),
+            1,
+            &on_event,
+        )
+        .unwrap();
+        assert_eq!(progress.into_inner(), vec![0, 16, 32, 48, 64, 80, 96, 100]);
+    }
+
+    #[test]
+    fn rejects_untrusted_manifests() {
+        let source = tempfile::tempdir().unwrap();
+        let (file, fetcher) = memory_static_file(source.path());
+        let manifest = manifest_of(&file);
+        let directory = tempfile::tempdir().unwrap();
+
+        // Manifest that doesn't hash to the pinned root
+        let mut tampered = manifest.clone();
+        tampered.files[0].size += 1;
+        assert!(matches!(
+            download_static_file(
+                &fetcher,
+                TrustedManifest::Pinned { manifest: &tampered, root: manifest.root() },
+                &tampered.files[0],
+                directory.path(),
+                1
+            ),
+            Err(DownloadError::Manifest(ManifestError::RootMismatch { .. }))
+        ));
+
+        // Static file that isn't listed in the trusted manifest
+        let mut unlisted = file.clone();
+        unlisted.chunks = None;
+        assert!(matches!(
+            download_static_file(&fetcher, pinned(&manifest), &unlisted, directory.path(), 1),
+            Err(DownloadError::NotInManifest { .. })
+        ));
+        assert!(fetcher.fetched.lock().is_empty());
+        assert!(!directory.path().join(&file.file_name).exists());
+    }
+
+    #[test]
+    fn verifies_companion_files() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let (file, mut fetcher) = served_headers(&harness, Compression::Uncompressed);
+        let manifest = manifest_of(&file);
+        let idx = format!("{}.idx", file.file_name);
+
+        // Companion files that aren't in the manifest are rejected before fetching any chunk
+        let directory = tempfile::tempdir().unwrap();
+        fetcher.files.insert(idx.clone(), b"index".to_vec());
+        assert!(matches!(
+            download_static_file(&fetcher, pinned(&manifest), &file, directory.path(), 1),
+            Err(DownloadError::SizeMismatch { got, expected, .. }) if got == expected + 5
+        ));
+        assert!(fetcher.fetched.lock().is_empty());
+
+        // Nothing is moved into the directory unless the content hash matches
+        let mut resized = file.clone();
+        resized.size += 5;
+        assert!(matches!(
+            download_static_file(
+                &fetcher,
+                pinned(&manifest_of(&resized)),
+                &resized,
+                directory.path(),
+                1
+            ),
+            Err(DownloadError::ContentHashMismatch { .. })
+        ));
+        assert!(!directory.path().join(&file.file_name).exists());
+        assert!(!directory.path().join(&idx).exists());
+
+        // Stale companion files staged by the failed download are replaced
+        fetcher.files.remove(&idx);
+        let path =
+            download_static_file(&fetcher, pinned(&manifest), &file, directory.path(), 1).unwrap();
+        for (file_name, data) in &fetcher.files {
+            assert_eq!(&std::fs::read(directory.path().join(file_name)).unwrap(), data);
+        }
+        assert_eq!(path, directory.path().join(&file.file_name));
+        assert!(!directory.path().join(&idx).exists());
+        assert!(!directory.path().join(format!("{}.part", file.file_name)).exists());
+    }
+
+    #[test]
+    fn rejects_invalid_offsets() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let (mut file, mut fetcher) = served_headers(&harness, Compression::Uncompressed);
+        file.content_hash = None;
+
+        // Offsets that don't end at the data size, even if the manifest lists their size
+        let offsets = fetcher.files.get_mut(&format!("{}.off", file.file_name)).unwrap();
+        offsets.pop();
+        file.size -= 1;
+        let manifest = manifest_of(&file);
+
+        let directory = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            download_static_file(&fetcher, pinned(&manifest), &file, directory.path(), 1),
+            Err(DownloadError::InvalidOffsets { .. })
+        ));
+        assert!(fetcher.fetched.lock().is_empty());
+        assert!(!directory.path().join(&file.file_name).exists());
+    }
+
+    #[test]
+    fn rejects_undecodable_rows() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+
+        // Rows of the uncompressed static file aren't valid zstd frames
+        let (file, fetcher) = served_headers(&harness, Compression::Zstd);
+        let manifest = manifest_of(&file);
+        let directory = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            download_static_file(&fetcher, pinned(&manifest), &file, directory.path(), 1),
+            Err(DownloadError::InvalidRow { row: 0, .. })
+        ));
+        assert!(!directory.path().join(&file.file_name).exists());
+
+        // Rows compressed with dictionaries are decoded by NippyJar once the data file is
+        // complete, with the compression of its configuration
+        let (file, fetcher) = served_headers(&harness, Compression::ZstdWithDictionary);
+        let manifest = manifest_of(&file);
+        download_static_file(&fetcher, pinned(&manifest), &file, directory.path(), 1).unwrap();
+        assert_eq!(fetcher.fetched.lock().len(), file.chunks.as_ref().unwrap().hashes.len());
+    }
+}
@@ -0,0 +1,380 @@
+//! Import of history from era1 archives, so operators migrating from another client can produce
+//! static files from downloaded archives instead of resyncing.
+//!
+//! [`Era1Source`] serves the blocks of the archives as a [`BlockSource`], so they're verified with
+//! [`verify_blocks`](crate::verify_blocks) while being copied, and the accumulator root of every
+//! archive can be checked with [`Era1Source::verify_accumulators`].
+
+use crate::{BlockSource, BlockSourceError, EpochAccumulator, HeaderRecord, SourceBlock};
+use alloy_primitives::{BlockNumber, B256, U256};
+use alloy_rlp::Decodable;
+use reth_primitives::{Header, Receipt, ReceiptWithBloom, TransactionSigned};
+use reth_static_file_types::StaticFileSegment;
+use snap::read::FrameDecoder;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// Extension of era1 archives.
+pub const ERA1_EXTENSION: &str = "era1";
+
+/// Type of the version record, starting every archive.
+const VERSION: u16 = 0x3265;
+/// Type of the record of a snappy-framed RLP header.
+const COMPRESSED_HEADER: u16 = 0x03;
+/// Type of the record of a snappy-framed RLP block body.
+const COMPRESSED_BODY: u16 = 0x04;
+/// Type of the record of snappy-framed RLP receipts of a block.
+const COMPRESSED_RECEIPTS: u16 = 0x05;
+/// Type of the record of the little-endian total difficulty of a block.
+const TOTAL_DIFFICULTY: u16 = 0x06;
+/// Type of the record of the epoch accumulator root of the archive.
+const ACCUMULATOR: u16 = 0x07;
+/// Type of the record of the block index, ending every archive.
+const BLOCK_INDEX: u16 = 0x3266;
+
+/// Length of the header of a record: type, data length and reserved bytes.
+const RECORD_HEADER_LEN: u64 = 8;
+
+/// Era1 archive of consecutive blocks, located through its block index.
+#[derive(Debug, Clone)]
+struct Era1File {
+    /// Path to the archive.
+    path: PathBuf,
+    /// First block of the archive.
+    start: BlockNumber,
+    /// Position of the first record of every block.
+    positions: Vec<u64>,
+    /// Epoch accumulator root recorded in the archive.
+    accumulator: B256,
+}
+
+impl Era1File {
+    /// Opens the archive, reading its block index and accumulator root.
+    fn open(path: PathBuf) -> Result<Self, BlockSourceError> {
+        let mut file = File::open(&path)?;
+        let len = file.metadata()?.len();
+
+        // The block index ends with the number of blocks
+        let mut count = [0; 8];
+        file.seek(SeekFrom::Start(len.checked_sub(8).ok_or_else(|| malformed(&path))?))?;
+        file.read_exact(&mut count)?;
+        let count = u64::from_le_bytes(count);
+        let index_position = count
+            .checked_mul(8)
+            .and_then(|offsets| offsets.checked_add(16 + RECORD_HEADER_LEN))
+            .and_then(|index_len| len.checked_sub(index_len))
+            .filter(|_| count > 0)
+            .ok_or_else(|| malformed(&path))?;
+
+        file.seek(SeekFrom::Start(index_position))?;
+        let index = read_record(&mut file, BLOCK_INDEX)?;
+        if index.len() as u64 != 16 + count * 8 {
+            return Err(malformed(&path))
+        }
+        let start = u64::from_le_bytes(index[..8].try_into().expect("8 bytes"));
+        let positions = index[8..index.len() - 8]
+            .chunks_exact(8)
+            .map(|offset| {
+                let offset = i64::from_le_bytes(offset.try_into().expect("8 bytes"));
+                index_position.checked_add_signed(offset).ok_or_else(|| malformed(&path))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The accumulator root is recorded right before the block index
+        file.seek(SeekFrom::Start(
+            index_position.checked_sub(RECORD_HEADER_LEN + 32).ok_or_else(|| malformed(&path))?,
+        ))?;
+        let accumulator = read_record(&mut file, ACCUMULATOR)?;
+        if accumulator.len() != 32 {
+            return Err(malformed(&path))
+        }
+        let accumulator = B256::from_slice(&accumulator);
+
+        Ok(Self { path, start, positions, accumulator })
+    }
+
+    /// Returns the last block of the archive.
+    fn end(&self) -> BlockNumber {
+        self.start + self.positions.len() as u64 - 1
+    }
+
+    /// Reads the blocks of the range from the archive, with the transactions or receipts needed
+    /// by the segment.
+    fn read_blocks(
+        &self,
+        block_range: RangeInclusive<BlockNumber>,
+        segment: StaticFileSegment,
+        blocks: &mut Vec<SourceBlock>,
+    ) -> Result<(), BlockSourceError> {
+        let mut file = BufReader::new(File::open(&self.path)?);
+        for number in block_range {
+            file.seek(SeekFrom::Start(self.positions[(number - self.start) as usize]))?;
+
+            let header = decode_snappy_frames(&read_record(&mut file, COMPRESSED_HEADER)?)?;
+            let header = Header::decode(&mut header.as_slice()).map_err(invalid)?.seal_slow();
+            // Bodies and receipts are only decompressed if the segment needs them
+            let body = read_record(&mut file, COMPRESSED_BODY)?;
+            let transactions = if segment == StaticFileSegment::Transactions {
+                decode_transactions(&decode_snappy_frames(&body)?).map_err(invalid)?
+            } else {
+                Vec::new()
+            };
+            let receipts = read_record(&mut file, COMPRESSED_RECEIPTS)?;
+            let receipts = if segment.is_receipts() {
+                Vec::<ReceiptWithBloom>::decode(&mut decode_snappy_frames(&receipts)?.as_slice())
+                    .map_err(invalid)?
+                    .into_iter()
+                    .map(|receipt| receipt.receipt)
+                    .collect::<Vec<Receipt>>()
+            } else {
+                Vec::new()
+            };
+            let total_difficulty =
+                U256::try_from_le_slice(&read_record(&mut file, TOTAL_DIFFICULTY)?)
+                    .ok_or_else(|| malformed(&self.path))?;
+
+            blocks.push(SourceBlock {
+                header,
+                total_difficulty: Some(total_difficulty),
+                transactions,
+                receipts,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// [`BlockSource`] serving the blocks of era1 archives in a directory.
+///
+/// Erigon snapshots are not supported, as their format isn't specified outside of Erigon.
+#[derive(Debug, Clone)]
+pub struct Era1Source {
+    /// Archives, sorted by their first block.
+    files: Vec<Era1File>,
+}
+
+impl Era1Source {
+    /// Opens every archive with the [`ERA1_EXTENSION`] in the directory, reading their block
+    /// indices.
+    pub fn open(directory: &Path) -> Result<Self, BlockSourceError> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == ERA1_EXTENSION) {
+                files.push(Era1File::open(path)?);
+            }
+        }
+        files.sort_unstable_by_key(|file| file.start);
+        Ok(Self { files })
+    }
+
+    /// Returns the range from the first to the last block of the archives, if there are any.
+    /// Archives may not cover all blocks of the range.
+    pub fn block_range(&self) -> Option<RangeInclusive<BlockNumber>> {
+        Some(self.files.first()?.start..=self.files.last()?.end())
+    }
+
+    /// Verifies the epoch accumulator root recorded in every archive against the hashes and
+    /// total difficulties of its headers. Returns the number of verified archives.
+    pub fn verify_accumulators(&self) -> Result<usize, BlockSourceError> {
+        for file in &self.files {
+            let mut blocks = Vec::with_capacity(file.positions.len());
+            file.read_blocks(file.start..=file.end(), StaticFileSegment::Headers, &mut blocks)?;
+            let records = blocks
+                .iter()
+                .map(|block| HeaderRecord {
+                    block_hash: block.header.hash(),
+                    total_difficulty: block.total_difficulty.unwrap_or_default(),
+                })
+                .collect();
+            if EpochAccumulator::new(records).root() != file.accumulator {
+                return Err(BlockSourceError::Unverified {
+                    block: file.start,
+                    reason: "accumulator root",
+                })
+            }
+        }
+        Ok(self.files.len())
+    }
+}
+
+impl BlockSource for Era1Source {
+    fn blocks(
+        &self,
+        block_range: RangeInclusive<BlockNumber>,
+        segment: StaticFileSegment,
+    ) -> Result<Vec<SourceBlock>, BlockSourceError> {
+        let mut blocks = Vec::new();
+        let mut next = *block_range.start();
+        while next <= *block_range.end() {
+            let Some(file) =
+                self.files.iter().find(|file| (file.start..=file.end()).contains(&next))
+            else {
+                return Err(BlockSourceError::MissingBlock(next))
+            };
+            let end = file.end().min(*block_range.end());
+            file.read_blocks(next..=end, segment, &mut blocks)?;
+            next = end + 1;
+        }
+        Ok(blocks)
+    }
+}
+
+/// Reads the next record, failing if it's not of the expected type.
+fn read_record(reader: &mut impl Read, expected: u16) -> Result<Vec<u8>, BlockSourceError> {
+    let mut header = [0; RECORD_HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    let kind = u16::from_le_bytes([header[0], header[1]]);
+    if kind != expected {
+        return Err(BlockSourceError::InvalidResponse(format!(
+            "expected era1 record {expected:#06x}, found {kind:#06x}"
+        )))
+    }
+
+    let len = u32::from_le_bytes(header[2..6].try_into().expect("4 bytes"));
+    let mut data = vec![0; len as usize];
+    reader.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// Decodes the transactions of an RLP block body, skipping its ommers and withdrawals.
+fn decode_transactions(body: &[u8]) -> alloy_rlp::Result<Vec<TransactionSigned>> {
+    let buf = &mut &body[..];
+    if !alloy_rlp::Header::decode(buf)?.list {
+        return Err(alloy_rlp::Error::UnexpectedString)
+    }
+    Vec::<TransactionSigned>::decode(buf)
+}
+
+/// Decodes data in the snappy framing format.
+fn decode_snappy_frames(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    FrameDecoder::new(data).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+/// Returns an error for the malformed archive.
+fn malformed(path: &Path) -> BlockSourceError {
+    BlockSourceError::InvalidResponse(format!("malformed era1 archive {}", path.display()))
+}
+
+/// Maps an RLP decoding error into [`BlockSourceError::InvalidResponse`].
+fn invalid(err: alloy_rlp::Error) -> BlockSourceError {
+    BlockSourceError::InvalidResponse(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{fixture_blocks, fixture_receipts},
+        verify_blocks,
+    };
+    use alloy_rlp::Encodable;
+    use std::io::Write;
+
+    /// Appends the record to the archive.
+    fn record(archive: &mut Vec<u8>, kind: u16, data: &[u8]) {
+        archive.extend(kind.to_le_bytes());
+        archive.extend((data.len() as u32).to_le_bytes());
+        archive.extend([0; 2]);
+        archive.extend(data);
+    }
+
+    /// Compresses the data in the snappy framing format.
+    fn snappy_framed(data: &[u8]) -> Vec<u8> {
+        let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+        encoder.write_all(data).unwrap();
+        encoder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn reads_era1_archives() {
+        let blocks = fixture_blocks(0..=3, B256::ZERO, 1..3);
+        let mut receipts = fixture_receipts(&blocks, 0, 1).into_iter().map(|(_, receipt)| receipt);
+
+        let mut archive = Vec::new();
+        record(&mut archive, VERSION, &[]);
+        let (mut positions, mut records) = (Vec::new(), Vec::new());
+        for block in &blocks {
+            positions.push(archive.len() as u64);
+            record(
+                &mut archive,
+                COMPRESSED_HEADER,
+                &snappy_framed(&alloy_rlp::encode(block.header.header())),
+            );
+
+            let mut payload = Vec::new();
+            block.body.encode(&mut payload);
+            block.ommers.encode(&mut payload);
+            let mut body = Vec::new();
+            alloy_rlp::Header { list: true, payload_length: payload.len() }.encode(&mut body);
+            body.extend(payload);
+            record(&mut archive, COMPRESSED_BODY, &snappy_framed(&body));
+
+            let block_receipts = receipts
+                .by_ref()
+                .take(block.body.len())
+                .map(|receipt| receipt.with_bloom())
+                .collect::<Vec<_>>();
+            record(
+                &mut archive,
+                COMPRESSED_RECEIPTS,
+                &snappy_framed(&alloy_rlp::encode(&block_receipts)),
+            );
+
+            let total_difficulty = U256::from(block.number + 1);
+            record(&mut archive, TOTAL_DIFFICULTY, &total_difficulty.to_le_bytes::<32>());
+            records.push(HeaderRecord { block_hash: block.hash(), total_difficulty });
+        }
+        let root = EpochAccumulator::new(records).root();
+        record(&mut archive, ACCUMULATOR, root.as_slice());
+        let index_position = archive.len() as i64;
+        let mut index = 0u64.to_le_bytes().to_vec();
+        for position in positions {
+            index.extend((position as i64 - index_position).to_le_bytes());
+        }
+        index.extend((blocks.len() as u64).to_le_bytes());
+        record(&mut archive, BLOCK_INDEX, &index);
+
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("mainnet-00000-00000000.era1"), &archive).unwrap();
+        let source = Era1Source::open(directory.path()).unwrap();
+        assert_eq!(source.block_range(), Some(0..=3));
+        assert_eq!(source.verify_accumulators().unwrap(), 1);
+
+        let fetched = source.blocks(1..=3, StaticFileSegment::Transactions).unwrap();
+        assert_eq!(fetched[0].header, blocks[1].header);
+        assert_eq!(fetched[2].transactions, blocks[3].body);
+        assert_eq!(fetched[2].total_difficulty, Some(U256::from(4)));
+        verify_blocks(
+            1..=3,
+            &fetched,
+            StaticFileSegment::Transactions,
+            Some(blocks[0].hash()),
+            None,
+        )
+        .unwrap();
+
+        let fetched = source.blocks(0..=0, StaticFileSegment::Receipts).unwrap();
+        assert_eq!(fetched[0].receipts.len(), blocks[0].body.len());
+
+        assert!(matches!(
+            source.blocks(3..=4, StaticFileSegment::Headers),
+            Err(BlockSourceError::MissingBlock(4))
+        ));
+
+        // Archives with another accumulator root are rejected
+        let root_position = archive.len() - index.len() - 8 - 32;
+        archive[root_position] ^= 1;
+        std::fs::write(directory.path().join("mainnet-00000-00000000.era1"), &archive).unwrap();
+        assert!(matches!(
+            Era1Source::open(directory.path()).unwrap().verify_accumulators(),
+            Err(BlockSourceError::Unverified { block: 0, reason: "accumulator root" })
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{repair::RepairError, rewrite::RewriteError, ChainMismatch, QuotaViolation};
+use alloy_primitives::BlockNumber;
+use reth_static_file_types::StaticFileSegment;
+use reth_storage_errors::provider::ProviderError;
+use std::{fmt, io, path::PathBuf, time::Duration};
+
+/// Error returned by [`StaticFileProducerInner::try_run`](crate::StaticFileProducerInner::try_run).
+#[derive(Debug)]
+pub enum StaticFileProducerError {
+    /// Error while reading from the database or writing to static files.
+    Provider(ProviderError),
+    /// Filesystem error while managing static files directly, e.g. during retention.
+    Io(io::Error),
+    /// Error while watching the static files directory for external changes.
+    Watcher(notify::Error),
+    /// The run was refused, because the produced static files would exceed the
+    /// [`DiskQuota`](crate::DiskQuota). Nothing is written in this case.
+    QuotaExceeded(QuotaViolation),
+    /// The run was refused, because the targets include a segment disabled in the
+    /// [`ProducerConfig`](crate::ProducerConfig). Nothing is written in this case.
+    SegmentDisabled(StaticFileSegment),
+    /// Error while repairing a quarantined static file from a
+    /// [`RepairMirror`](crate::RepairMirror).
+    Repair(RepairError),
+    /// Error while rewriting a sealed static file with
+    /// [`StaticFileProducerInner::force_rewrite`](crate::StaticFileProducerInner::force_rewrite).
+    Rewrite(RewriteError),
+    /// The run was cancelled by the [`StallWatchdog`](crate::StallWatchdog), because the segment
+    /// made no progress for too long. Nothing is committed to static files in this case, except
+    /// for blocks committed at the commit interval before the stall.
+    Stalled {
+        /// Segment that made no progress.
+        segment: StaticFileSegment,
+        /// Last block of the segment copied before the stall, if any.
+        last_block: Option<BlockNumber>,
+        /// Time since the last progress of the segment.
+        since: Duration,
+    },
+    /// The run was refused, because the static files directory holds static files of another
+    /// chain. Nothing is written in this case.
+    ChainMismatch(ChainMismatch),
+    /// Pruning of the segment was confirmed past the highest block of its static files, which
+    /// the database may be pruned up to. The prune checkpoint isn't updated in this case.
+    NotPrunable {
+        /// Segment the pruning was confirmed for.
+        segment: StaticFileSegment,
+        /// Block the pruning was confirmed up to.
+        block: BlockNumber,
+        /// Highest block of the segment that may be pruned, if any.
+        prunable_up_to: Option<BlockNumber>,
+    },
+    /// The producer wasn't attached to the [`ProducerCoordinator`](crate::ProducerCoordinator),
+    /// because a producer of the same static files directory already is.
+    DirectoryInUse(PathBuf),
+}
+
+impl From<ProviderError> for StaticFileProducerError {
+    fn from(value: ProviderError) -> Self {
+        Self::Provider(value)
+    }
+}
+
+impl From<io::Error> for StaticFileProducerError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<notify::Error> for StaticFileProducerError {
+    fn from(value: notify::Error) -> Self {
+        Self::Watcher(value)
+    }
+}
+
+impl From<RepairError> for StaticFileProducerError {
+    fn from(value: RepairError) -> Self {
+        Self::Repair(value)
+    }
+}
+
+impl From<RewriteError> for StaticFileProducerError {
+    fn from(value: RewriteError) -> Self {
+        Self::Rewrite(value)
+    }
+}
+
+impl fmt::Display for StaticFileProducerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Provider(err) => fmt::Display::fmt(err, f),
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::Watcher(err) => fmt::Display::fmt(err, f),
+            Self::QuotaExceeded(violation) => fmt::Display::fmt(violation, f),
+            Self::SegmentDisabled(segment) => {
+                write!(f, "static file production of {segment} is disabled")
+            }
+            Self::Repair(err) => fmt::Display::fmt(err, f),
+            Self::Rewrite(err) => fmt::Display::fmt(err, f),
+            Self::Stalled { segment, last_block, since } => write!(
+                f,
+                "static file production of {segment} stalled for {since:?} after block {last_block:?}"
+            ),
+            Self::ChainMismatch(mismatch) => fmt::Display::fmt(mismatch, f),
+            Self::NotPrunable { segment, block, prunable_up_to } => write!(
+                f,
+                "{segment} can't be pruned up to block {block}, only up to {prunable_up_to:?}"
+            ),
+            Self::DirectoryInUse(directory) => write!(
+                f,
+                "static files directory {} is already produced to by another producer",
+                directory.display()
+            ),
+        }
+    }
+}
+
+/// Converts the error for [`StaticFileProducerInner::run`](crate::StaticFileProducerInner::run),
+/// which returns a [`ProviderError`]. Errors of the producer itself are kept as their message.
+impl From<StaticFileProducerError> for ProviderError {
+    fn from(value: StaticFileProducerError) -> Self {
+        match value {
+            StaticFileProducerError::Provider(err) => err,
+            err => Self::NippyJar(err.to_string()),
+        }
+    }
+}
+
+impl std::error::Error for StaticFileProducerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Provider(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::Watcher(err) => Some(err),
+            Self::Repair(err) => Some(err),
+            Self::Rewrite(err) => Some(err),
+            Self::ChainMismatch(err) => Some(err),
+            Self::QuotaExceeded(_) |
+            Self::SegmentDisabled(_) |
+            Self::Stalled { .. } |
+            Self::NotPrunable { .. } |
+            Self::DirectoryInUse(_) => None,
+        }
+    }
+}
@@ -1,5 +1,15 @@
-use crate::StaticFileTargets;
-use std::time::Duration;
+use crate::{DataGap, ExternalChangeKind, QuotaViolation, StaticFileTargets};
+use alloy_primitives::BlockNumber;
+use parking_lot::{Condvar, Mutex};
+use reth_static_file_types::{SegmentRangeInclusive, StaticFileSegment};
+use reth_tokio_util::{EventSender, EventStream};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
 
 /// An event emitted by a [`StaticFileProducer`][crate::StaticFileProducer].
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -8,12 +18,325 @@ pub enum StaticFileProducerEvent {
     Started {
         /// Targets that will be moved to static files.
         targets: StaticFileTargets,
+        /// Transaction ranges of the targets of the segments keyed by transaction number, see
+        /// [`StaticFileTargets::tx_ranges`].
+        tx_ranges: Vec<(StaticFileSegment, SegmentRangeInclusive)>,
     },
     /// Emitted when static file producer finished running.
     Finished {
         /// Targets that were moved to static files.
         targets: StaticFileTargets,
+        /// Transaction ranges of the targets of the segments keyed by transaction number, see
+        /// [`StaticFileTargets::tx_ranges`].
+        tx_ranges: Vec<(StaticFileSegment, SegmentRangeInclusive)>,
         /// Time it took to run the static file producer.
         elapsed: Duration,
+        /// Breakdown of the elapsed time, if the timing summary is enabled with
+        /// [`StaticFileProducerInner::set_timing_summary`](crate::StaticFileProducerInner::set_timing_summary).
+        timings: Option<RunTimings>,
+        /// Blocks whose data was missing from the database, that segments stopped at or skipped
+        /// according to the [`MissingDataPolicy`](crate::MissingDataPolicy).
+        gaps: Vec<DataGap>,
     },
+    /// Emitted when a segment made no progress for longer than the configured
+    /// [`StallWatchdog`](crate::StallWatchdog) timeout.
+    Stalled {
+        /// Segment that made no progress.
+        segment: StaticFileSegment,
+        /// Last block of the segment copied before the stall, if any.
+        last_block: Option<BlockNumber>,
+        /// Time since the last progress of the segment.
+        since: Duration,
+    },
+    /// Emitted when copied blocks of a segment were committed to static files, either at the
+    /// configured commit interval or at the end of the run.
+    Committed {
+        /// Segment that was committed.
+        segment: StaticFileSegment,
+        /// Last committed block of the segment.
+        block: BlockNumber,
+    },
+    /// Emitted by the [`StaticFileWatcher`](crate::StaticFileWatcher) when a static file was
+    /// modified or removed outside of the producer.
+    ExternalChange {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+        /// Path to the data file of the static file.
+        path: PathBuf,
+        /// Kind of the change.
+        kind: ExternalChangeKind,
+    },
+    /// Emitted when static file producer failed after it started running.
+    Failed {
+        /// Targets that were being moved to static files.
+        targets: StaticFileTargets,
+        /// Kind of the failure.
+        kind: FailureKind,
+    },
+    /// Emitted when two static files cover the same block range of a segment, e.g.
+    /// `static_file_headers_0_499999_none_lz4` and `static_file_headers_0_499999_none_zstd`.
+    DuplicateRange {
+        /// Segment of the static files.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static files.
+        block_range: SegmentRangeInclusive,
+        /// Path to the data file of the static file that takes precedence.
+        kept: PathBuf,
+        /// Path to the data file of the static file that lost, which can be removed with
+        /// [`StaticFileProducerInner::remove_duplicate_ranges`](crate::StaticFileProducerInner::remove_duplicate_ranges).
+        skipped: PathBuf,
+    },
+    /// Emitted when a run was refused, because the produced static files would exceed the
+    /// [`DiskQuota`](crate::DiskQuota).
+    QuotaExceeded {
+        /// Violated limit of the quota.
+        violation: QuotaViolation,
+    },
+    /// Emitted by [`download_static_file_with_events`](crate::download_static_file_with_events)
+    /// whenever chunks of the downloaded data file were verified against the manifest.
+    VerificationProgress {
+        /// Manifest name of the static file.
+        file_name: String,
+        /// Bytes of the data file verified so far.
+        verified: u64,
+        /// Size of the data file.
+        total: u64,
+    },
+    /// Emitted by
+    /// [`StaticFileProducerInner::on_reorg`](crate::StaticFileProducerInner::on_reorg) when the
+    /// static files of a segment were unwound past a reorg.
+    Unwound {
+        /// Segment that was unwound.
+        segment: StaticFileSegment,
+        /// Highest block of the segment before the unwind.
+        from: BlockNumber,
+        /// Highest block of the segment after the unwind, the last block kept by the reorg.
+        to: BlockNumber,
+    },
+    /// Emitted to an [`EventReceiver`] in place of the events it lost, because it didn't keep up
+    /// with the producer and its queue overflowed.
+    Lost {
+        /// Number of events lost since the previous [`StaticFileProducerEvent::Lost`].
+        count: u64,
+    },
+}
+
+/// Kind of a failure of a [`StaticFileProducer`][crate::StaticFileProducer] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// The disk filled up while writing static files. Static files were rolled back to their last
+    /// commit.
+    DiskFull,
+    /// A segment was cancelled by the [`StallWatchdog`](crate::StallWatchdog).
+    Stalled,
+    /// Any other error while copying to static files.
+    Error,
+}
+
+/// Breakdown of the time spent in a [`StaticFileProducer`][crate::StaticFileProducer] run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunTimings {
+    /// Time spent copying each segment, in the order the segments were copied in. Segments
+    /// copied in parallel overlap.
+    pub segments: Vec<(StaticFileSegment, Duration)>,
+    /// Time spent committing the static file provider, updating its index and publishing the
+    /// committed rows.
+    pub commit: Duration,
+    /// Time spent after the commit: recording prunable blocks, notifying seal hooks, recording
+    /// epoch roots and applying the retention policy, among others.
+    pub post_commit: Duration,
+}
+
+/// What a full [`EventReceiver`] queue does with a new event. The producer never blocks on slow
+/// subscribers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drops the oldest queued event to make room for the new one, so the subscriber sees the
+    /// latest events. The lost events are reported before the queued ones.
+    #[default]
+    DropOldest,
+    /// Drops the new event, so the subscriber sees the events in order up to the overflow. The
+    /// lost events are reported once the queued ones were received.
+    DropNewest,
+}
+
+/// Sender of [`StaticFileProducerEvent`]s to broadcast listeners and to bounded
+/// [`EventReceiver`]s.
+///
+/// Broadcast listeners lagging behind skip events silently, bounded subscribers are told how
+/// many events they lost with [`StaticFileProducerEvent::Lost`].
+#[derive(Debug, Clone, Default)]
+pub struct StaticFileEventSender {
+    /// Sender of the broadcast listeners.
+    broadcast: EventSender<StaticFileProducerEvent>,
+    /// Queues of the bounded subscribers. Queues of dropped receivers are removed on the next
+    /// event.
+    subscribers: Arc<Mutex<Vec<Weak<EventQueue>>>>,
+}
+
+impl StaticFileEventSender {
+    /// Sends the event to all listeners and subscribers.
+    pub fn notify(&self, event: StaticFileProducerEvent) {
+        self.subscribers.lock().retain(|queue| {
+            let Some(queue) = queue.upgrade() else { return false };
+            queue.push(event.clone());
+            true
+        });
+        self.broadcast.notify(event);
+    }
+
+    /// Creates a new broadcast listener.
+    pub fn new_listener(&self) -> EventStream<StaticFileProducerEvent> {
+        self.broadcast.new_listener()
+    }
+
+    /// Creates a new subscriber queueing up to `capacity` events, at least one, and handling
+    /// overflows with the policy.
+    pub fn subscribe(&self, capacity: usize, policy: OverflowPolicy) -> EventReceiver {
+        let queue = Arc::new(EventQueue {
+            capacity: capacity.max(1),
+            policy,
+            state: Mutex::new(QueueState::default()),
+            available: Condvar::new(),
+        });
+        self.subscribers.lock().push(Arc::downgrade(&queue));
+        EventReceiver { queue }
+    }
+}
+
+/// Bounded queue of a subscriber.
+#[derive(Debug)]
+struct EventQueue {
+    /// Maximum number of queued events.
+    capacity: usize,
+    /// What happens to new events when the queue is full.
+    policy: OverflowPolicy,
+    /// Queued events and the number of lost ones.
+    state: Mutex<QueueState>,
+    /// Notified when an event was queued.
+    available: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    /// Queued events, oldest first.
+    events: VecDeque<StaticFileProducerEvent>,
+    /// Number of events lost since the last [`StaticFileProducerEvent::Lost`].
+    lost: u64,
+}
+
+impl EventQueue {
+    fn push(&self, event: StaticFileProducerEvent) {
+        let mut state = self.state.lock();
+        if state.events.len() >= self.capacity {
+            state.lost += 1;
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    state.events.pop_front();
+                }
+                OverflowPolicy::DropNewest => return,
+            }
+        }
+        state.events.push_back(event);
+        drop(state);
+        self.available.notify_one();
+    }
+
+    fn pop(&self, state: &mut QueueState) -> Option<StaticFileProducerEvent> {
+        let report_lost = match self.policy {
+            OverflowPolicy::DropOldest => true,
+            OverflowPolicy::DropNewest => state.events.is_empty(),
+        };
+        if state.lost > 0 && report_lost {
+            return Some(StaticFileProducerEvent::Lost { count: std::mem::take(&mut state.lost) })
+        }
+        state.events.pop_front()
+    }
+}
+
+/// Receiver of a bounded subscriber, created with [`StaticFileEventSender::subscribe`].
+///
+/// Unsubscribes when dropped.
+#[derive(Debug)]
+pub struct EventReceiver {
+    /// Queue of the subscriber, shared with the sender.
+    queue: Arc<EventQueue>,
+}
+
+impl EventReceiver {
+    /// Returns the next event, or `None` if there's none queued.
+    pub fn try_recv(&self) -> Option<StaticFileProducerEvent> {
+        self.queue.pop(&mut self.queue.state.lock())
+    }
+
+    /// Returns the next event, waiting up to `timeout` for one. Returns `None` on timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<StaticFileProducerEvent> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.queue.state.lock();
+        loop {
+            if let Some(event) = self.queue.pop(&mut state) {
+                return Some(event)
+            }
+            if self.queue.available.wait_until(&mut state, deadline).timed_out() {
+                return self.queue.pop(&mut state)
+            }
+        }
+    }
+
+    /// Returns the number of queued events, not counting lost ones.
+    pub fn len(&self) -> usize {
+        self.queue.state.lock().events.len()
+    }
+
+    /// Returns `true` if no events are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn committed(block: BlockNumber) -> StaticFileProducerEvent {
+        StaticFileProducerEvent::Committed { segment: StaticFileSegment::Headers, block }
+    }
+
+    #[test]
+    fn bounded_subscribers() {
+        let sender = StaticFileEventSender::default();
+        let oldest = sender.subscribe(2, OverflowPolicy::DropOldest);
+        let newest = sender.subscribe(2, OverflowPolicy::DropNewest);
+        let dropped = sender.subscribe(1, OverflowPolicy::DropOldest);
+        drop(dropped);
+
+        for block in 0..5 {
+            sender.notify(committed(block));
+        }
+        assert_eq!(sender.subscribers.lock().len(), 2);
+
+        // Lost events are reported before the latest ones
+        assert_eq!(oldest.len(), 2);
+        assert_eq!(oldest.try_recv(), Some(StaticFileProducerEvent::Lost { count: 3 }));
+        assert_eq!(oldest.try_recv(), Some(committed(3)));
+        assert_eq!(oldest.try_recv(), Some(committed(4)));
+        assert!(oldest.is_empty());
+        assert_eq!(oldest.recv_timeout(Duration::from_millis(10)), None);
+
+        // Lost events are reported after the first ones
+        assert_eq!(newest.try_recv(), Some(committed(0)));
+        assert_eq!(newest.try_recv(), Some(committed(1)));
+        assert_eq!(newest.try_recv(), Some(StaticFileProducerEvent::Lost { count: 3 }));
+        assert_eq!(newest.try_recv(), None);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                std::thread::sleep(Duration::from_millis(10));
+                sender.notify(committed(5));
+            });
+            assert_eq!(oldest.recv_timeout(Duration::from_secs(10)), Some(committed(5)));
+        });
+    }
 }
@@ -0,0 +1,275 @@
+//! Export of static file rows to line-delimited JSON and CSV, so specific block ranges can be
+//! inspected by humans, e.g. auditors, without running a node.
+//!
+//! Rows are serialized with the RPC types, the same format as `eth_getBlockByNumber`,
+//! `eth_getTransactionByHash` and the envelopes of `eth_getBlockReceipts`. Rows of the
+//! Transactions and Receipts segments are found through their
+//! [`TransactionBoundaries`](crate::TransactionBoundaries) and
+//! [`BlockBoundaries`](crate::BlockBoundaries) sidecars.
+
+pub mod seekable;
+
+use crate::{StaticFileReader, StaticFileReaderError};
+use alloy_primitives::{BlockNumber, Log, TxNumber};
+use reth_primitives::{Receipt, SealedHeader, TxType};
+use reth_provider::{HeaderProvider, ReceiptProvider, TransactionsProvider};
+use reth_rpc_types::{
+    Header as RpcHeader, Receipt as RpcReceipt, ReceiptEnvelope, ReceiptWithBloom,
+    Transaction as RpcTransaction,
+};
+use reth_rpc_types_compat::{block::from_primitive_with_hash, transaction::from_recovered};
+use reth_static_file_types::{SegmentRangeInclusive, StaticFileSegment};
+use reth_storage_errors::provider::ProviderError;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    ops::{Range, RangeInclusive},
+};
+
+/// Columns of the CSV written by [`headers_csv`].
+pub const HEADERS_CSV_COLUMNS: [&str; 13] = [
+    "number",
+    "hash",
+    "parent_hash",
+    "timestamp",
+    "gas_used",
+    "gas_limit",
+    "base_fee_per_gas",
+    "difficulty",
+    "total_difficulty",
+    "beneficiary",
+    "state_root",
+    "transactions_root",
+    "receipts_root",
+];
+
+/// Line of the JSONL export: the row, with its block and its transaction number, if the segment
+/// is keyed by transaction numbers.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Line {
+    block: BlockNumber,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tx_number: Option<TxNumber>,
+    #[serde(flatten)]
+    row: Row,
+}
+
+/// Row of any segment, serialized with its RPC type.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Row {
+    Header(Box<RpcHeader>),
+    Transaction(Box<RpcTransaction>),
+    Receipt(ReceiptEnvelope<Log>),
+}
+
+/// Writes the rows of the segment for the block range to the writer as line-delimited JSON, one
+/// row per line. Returns the number of written rows.
+///
+/// Fails with [`StaticFileReaderError::MissingStaticFiles`] at the first block that's not in
+/// static files, or whose rows can't be found through the sidecars of the segment.
+pub fn jsonl(
+    reader: &StaticFileReader,
+    segment: StaticFileSegment,
+    block_range: RangeInclusive<BlockNumber>,
+    mut writer: impl Write,
+) -> Result<u64, StaticFileReaderError> {
+    let mut rows = 0;
+    for block in block_range {
+        for line in block_lines(reader, segment, block)? {
+            serde_json::to_writer(&mut writer, &line).map_err(io::Error::from)?;
+            writer.write_all(b"\n")?;
+            rows += 1;
+        }
+    }
+
+    writer.flush()?;
+    Ok(rows)
+}
+
+/// Returns the rows of the segment for the block, as written by [`jsonl`].
+pub(crate) fn block_lines(
+    reader: &StaticFileReader,
+    segment: StaticFileSegment,
+    block: BlockNumber,
+) -> Result<Vec<Line>, StaticFileReaderError> {
+    let line = |tx_number, row| Line { block, tx_number, row };
+    match segment {
+        StaticFileSegment::Headers => {
+            let mut header = from_primitive_with_hash(sealed_header(reader, block)?);
+            header.total_difficulty = reader.header_td_by_number(block)?;
+            Ok(vec![line(None, Row::Header(Box::new(header)))])
+        }
+        StaticFileSegment::Transactions => {
+            let mut lines = Vec::new();
+            for (index, tx_number) in tx_range(reader, segment, block)?.enumerate() {
+                let transaction = reader
+                    .transaction_by_id(tx_number)?
+                    .ok_or(ProviderError::TransactionNotFound(tx_number.into()))?
+                    .into_ecrecovered()
+                    .ok_or(ProviderError::SenderRecoveryError)?;
+                let mut transaction = from_recovered(transaction);
+                transaction.block_number = Some(block);
+                transaction.transaction_index = Some(index as u64);
+                lines.push(line(Some(tx_number), Row::Transaction(Box::new(transaction))));
+            }
+            Ok(lines)
+        }
+        StaticFileSegment::Receipts => {
+            let mut lines = Vec::new();
+            for tx_number in tx_range(reader, segment, block)? {
+                let receipt = reader
+                    .receipt(tx_number)?
+                    .ok_or(ProviderError::ReceiptNotFound(tx_number.into()))?;
+                lines.push(line(Some(tx_number), Row::Receipt(receipt_envelope(receipt))));
+            }
+            Ok(lines)
+        }
+    }
+}
+
+/// Writes the headers of the block range to the writer as CSV, with the
+/// [`HEADERS_CSV_COLUMNS`] in the first line. Optional values are left empty if they're not
+/// set. Returns the number of written headers.
+///
+/// Fails with [`StaticFileReaderError::MissingStaticFiles`] at the first block that's not in
+/// static files.
+pub fn headers_csv(
+    reader: &StaticFileReader,
+    block_range: RangeInclusive<BlockNumber>,
+    mut writer: impl Write,
+) -> Result<u64, StaticFileReaderError> {
+    writeln!(writer, "{}", HEADERS_CSV_COLUMNS.join(","))?;
+
+    let mut rows = 0;
+    for block in block_range {
+        let header = sealed_header(reader, block)?;
+        let total_difficulty = reader.header_td_by_number(block)?;
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            header.number,
+            header.hash(),
+            header.parent_hash,
+            header.timestamp,
+            header.gas_used,
+            header.gas_limit,
+            header.base_fee_per_gas.map(|fee| fee.to_string()).unwrap_or_default(),
+            header.difficulty,
+            total_difficulty.map(|td| td.to_string()).unwrap_or_default(),
+            header.beneficiary,
+            header.state_root,
+            header.transactions_root,
+            header.receipts_root,
+        )?;
+        rows += 1;
+    }
+
+    writer.flush()?;
+    Ok(rows)
+}
+
+/// Returns the sealed header of the block, failing if it's not in static files.
+fn sealed_header(
+    reader: &StaticFileReader,
+    block: BlockNumber,
+) -> Result<SealedHeader, StaticFileReaderError> {
+    reader.sealed_header(block)?.ok_or_else(|| missing(StaticFileSegment::Headers, block))
+}
+
+/// Returns the transaction numbers of the block in the segment, failing if they can't be found
+/// through the sidecar of the segment.
+fn tx_range(
+    reader: &StaticFileReader,
+    segment: StaticFileSegment,
+    block: BlockNumber,
+) -> Result<Range<TxNumber>, StaticFileReaderError> {
+    reader.block_tx_range(segment, block)?.ok_or_else(|| missing(segment, block))
+}
+
+/// Returns [`StaticFileReaderError::MissingStaticFiles`] for the block of the segment.
+fn missing(segment: StaticFileSegment, block: BlockNumber) -> StaticFileReaderError {
+    StaticFileReaderError::MissingStaticFiles {
+        segment,
+        gap: SegmentRangeInclusive::new(block, block),
+    }
+}
+
+/// Converts the receipt into its typed envelope.
+fn receipt_envelope(receipt: Receipt) -> ReceiptEnvelope<Log> {
+    let logs_bloom = receipt.bloom_slow();
+    let inner = ReceiptWithBloom {
+        receipt: RpcReceipt {
+            status: receipt.success.into(),
+            cumulative_gas_used: receipt.cumulative_gas_used as u128,
+            logs: receipt.logs,
+        },
+        logs_bloom,
+    };
+    match receipt.tx_type {
+        TxType::Legacy => ReceiptEnvelope::Legacy(inner),
+        TxType::Eip2930 => ReceiptEnvelope::Eip2930(inner),
+        TxType::Eip1559 => ReceiptEnvelope::Eip1559(inner),
+        TxType::Eip4844 => ReceiptEnvelope::Eip4844(inner),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_provider::StaticFileProviderFactory;
+    use serde_json::Value;
+
+    #[test]
+    fn exports_block_ranges() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+
+        let mut headers = Vec::new();
+        assert_eq!(jsonl(&reader, StaticFileSegment::Headers, 1..=2, &mut headers).unwrap(), 2);
+        let headers = headers
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(headers[1]["block"], 2);
+        assert_eq!(headers[1]["hash"], harness.blocks[2].hash().to_string());
+
+        let transactions = harness.blocks.iter().flat_map(|block| &block.body).collect::<Vec<_>>();
+        let mut exported = Vec::new();
+        assert_eq!(
+            jsonl(&reader, StaticFileSegment::Transactions, 0..=3, &mut exported).unwrap(),
+            transactions.len() as u64
+        );
+        let last = exported.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).last();
+        let last = serde_json::from_slice::<Value>(last.unwrap()).unwrap();
+        assert_eq!(last["txNumber"], transactions.len() - 1);
+        assert_eq!(last["hash"], transactions.last().unwrap().hash().to_string());
+
+        let mut receipts = Vec::new();
+        assert_eq!(
+            jsonl(&reader, StaticFileSegment::Receipts, 0..=3, &mut receipts).unwrap(),
+            transactions.len() as u64
+        );
+
+        let mut csv = Vec::new();
+        assert_eq!(headers_csv(&reader, 0..=3, &mut csv).unwrap(), 4);
+        let csv = String::from_utf8(csv).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], HEADERS_CSV_COLUMNS.join(","));
+        assert!(lines[1].starts_with(&format!("0,{},", harness.blocks[0].hash())));
+
+        // Blocks that are not in static files
+        assert!(matches!(
+            jsonl(&reader, StaticFileSegment::Headers, 3..=4, io::sink()),
+            Err(StaticFileReaderError::MissingStaticFiles {
+                segment: StaticFileSegment::Headers,
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,369 @@
+//! Writer and reader of the [zstd seekable format], so third parties can random-access block
+//! ranges inside a single compressed export with any zstd seekable implementation.
+//!
+//! The archive is a sequence of independent zstd frames, followed by a seek table in a skippable
+//! frame listing the compressed and decompressed size of every frame. Exports written with
+//! [`jsonl_seekable`] put a fixed number of blocks in every frame, so the frames of a block range
+//! are found from the seek table alone.
+//!
+//! [zstd seekable format]: https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+
+use crate::{export::jsonl, StaticFileReader, StaticFileReaderError};
+use alloy_primitives::BlockNumber;
+use reth_static_file_types::StaticFileSegment;
+use std::{
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::{Range, RangeInclusive},
+};
+
+/// Magic number of the skippable frame holding the seek table.
+const SKIPPABLE_MAGIC_NUMBER: u32 = 0x184D2A5E;
+
+/// Magic number closing the seek table.
+const SEEKABLE_MAGIC_NUMBER: u32 = 0x8F92EAB1;
+
+/// Size of the seek table footer: number of frames, descriptor and magic number.
+const FOOTER_SIZE: u64 = 9;
+
+/// Size of a seek table entry without checksum: compressed and decompressed size.
+const ENTRY_SIZE: u64 = 8;
+
+/// Flag of the seek table descriptor set if entries have a checksum.
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// Default maximum decompressed size of a frame written by [`SeekableWriter`].
+pub const DEFAULT_FRAME_SIZE: usize = 1024 * 1024;
+
+/// Frame of a seekable archive, as listed in its seek table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeekableFrame {
+    /// Offset of the frame in the archive.
+    pub compressed_offset: u64,
+    /// Size of the compressed frame.
+    pub compressed_size: u32,
+    /// Offset of the frame content in the decompressed data.
+    pub decompressed_offset: u64,
+    /// Size of the decompressed frame content.
+    pub decompressed_size: u32,
+}
+
+/// Writer compressing data into the zstd seekable format, starting a new frame whenever the
+/// frame size is reached or [`SeekableWriter::finish_frame`] is called.
+///
+/// The seek table is only written by [`SeekableWriter::finish`].
+#[derive(Debug)]
+pub struct SeekableWriter<W: Write> {
+    /// Writer of the archive.
+    writer: W,
+    /// Compression level of the frames.
+    level: i32,
+    /// Maximum decompressed size of a frame.
+    frame_size: usize,
+    /// Content of the current frame.
+    buffer: Vec<u8>,
+    /// Compressed and decompressed sizes of the written frames.
+    frames: Vec<(u32, u32)>,
+}
+
+impl<W: Write> SeekableWriter<W> {
+    /// Creates a new [`SeekableWriter`] compressing frames of at most `frame_size` bytes, e.g.
+    /// [`DEFAULT_FRAME_SIZE`], at the zstd compression level. The frame size is clamped to
+    /// `u32::MAX`.
+    pub fn new(writer: W, level: i32, frame_size: usize) -> Self {
+        let frame_size = frame_size.clamp(1, u32::MAX as usize);
+        Self { writer, level, frame_size, buffer: Vec::new(), frames: Vec::new() }
+    }
+
+    /// Compresses the buffered data into a frame, so the data written next starts a new frame.
+    /// Does nothing if no data is buffered.
+    pub fn finish_frame(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(())
+        }
+
+        let compressed = zstd::bulk::compress(&self.buffer, self.level)?;
+        let compressed_size = u32::try_from(compressed.len())
+            .map_err(|_| io::Error::other("compressed frame exceeds 4 GiB"))?;
+        self.writer.write_all(&compressed)?;
+        self.frames.push((compressed_size, self.buffer.len() as u32));
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Compresses the buffered data, writes the seek table and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.finish_frame()?;
+
+        let table_size = self.frames.len() as u64 * ENTRY_SIZE + FOOTER_SIZE;
+        let table_size =
+            u32::try_from(table_size).map_err(|_| io::Error::other("seek table exceeds 4 GiB"))?;
+        self.writer.write_all(&SKIPPABLE_MAGIC_NUMBER.to_le_bytes())?;
+        self.writer.write_all(&table_size.to_le_bytes())?;
+        for (compressed_size, decompressed_size) in &self.frames {
+            self.writer.write_all(&compressed_size.to_le_bytes())?;
+            self.writer.write_all(&decompressed_size.to_le_bytes())?;
+        }
+        self.writer.write_all(&(self.frames.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&[0])?;
+        self.writer.write_all(&SEEKABLE_MAGIC_NUMBER.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for SeekableWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.frame_size - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+        if self.buffer.len() == self.frame_size {
+            self.finish_frame()?;
+        }
+        Ok(len)
+    }
+
+    /// Flushes the underlying writer. The buffered data stays in the current frame.
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reader of an archive in the zstd seekable format, decompressing only the frames of the
+/// requested ranges.
+#[derive(Debug)]
+pub struct SeekableReader<R> {
+    /// Reader of the archive.
+    reader: R,
+    /// Frames listed in the seek table.
+    frames: Vec<SeekableFrame>,
+}
+
+impl<R: Read + Seek> SeekableReader<R> {
+    /// Creates a new [`SeekableReader`], reading the seek table at the end of the archive.
+    /// Checksums of the seek table entries are skipped, not verified.
+    ///
+    /// The seek table must fit in the archive, and its frames must add up to the archive before
+    /// it, so a corrupted table fails here instead of when its frames are read.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let archive_size = reader.seek(SeekFrom::End(0))?;
+        if archive_size < FOOTER_SIZE {
+            return Err(invalid_data("archive is smaller than the zstd seekable footer"))
+        }
+        let mut footer = [0; FOOTER_SIZE as usize];
+        reader.seek(SeekFrom::Start(archive_size - FOOTER_SIZE))?;
+        reader.read_exact(&mut footer)?;
+        if u32::from_le_bytes(footer[5..9].try_into().expect("4 bytes")) != SEEKABLE_MAGIC_NUMBER {
+            return Err(invalid_data("missing zstd seekable magic number"))
+        }
+        let frame_count = u32::from_le_bytes(footer[..4].try_into().expect("4 bytes")) as u64;
+        let entry_size = if footer[4] & CHECKSUM_FLAG != 0 { ENTRY_SIZE + 4 } else { ENTRY_SIZE };
+
+        // Seek table entries, preceded by the skippable frame header
+        let table_size = frame_count * entry_size;
+        let Some(frames_size) = archive_size.checked_sub(table_size + FOOTER_SIZE + 8) else {
+            return Err(invalid_data("zstd seekable table exceeds the archive"))
+        };
+        reader.seek(SeekFrom::Start(frames_size))?;
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        if u32::from_le_bytes(header[..4].try_into().expect("4 bytes")) != SKIPPABLE_MAGIC_NUMBER {
+            return Err(invalid_data("missing zstd seekable skippable frame"))
+        }
+        if u32::from_le_bytes(header[4..].try_into().expect("4 bytes")) as u64 !=
+            table_size + FOOTER_SIZE
+        {
+            return Err(invalid_data("zstd seekable skippable frame size doesn't match its table"))
+        }
+        let mut table = vec![0; table_size as usize];
+        reader.read_exact(&mut table)?;
+
+        let (mut compressed_offset, mut decompressed_offset) = (0, 0);
+        let frames = table
+            .chunks_exact(entry_size as usize)
+            .map(|entry| {
+                let frame = SeekableFrame {
+                    compressed_offset,
+                    compressed_size: u32::from_le_bytes(entry[..4].try_into().expect("4 bytes")),
+                    decompressed_offset,
+                    decompressed_size: u32::from_le_bytes(entry[4..8].try_into().expect("4 bytes")),
+                };
+                compressed_offset += frame.compressed_size as u64;
+                decompressed_offset += frame.decompressed_size as u64;
+                frame
+            })
+            .collect();
+        if compressed_offset != frames_size {
+            return Err(invalid_data("zstd seekable frames don't add up to the archive"))
+        }
+
+        Ok(Self { reader, frames })
+    }
+
+    /// Returns the frames listed in the seek table.
+    pub fn frames(&self) -> &[SeekableFrame] {
+        &self.frames
+    }
+
+    /// Returns the size of the decompressed data.
+    pub fn decompressed_size(&self) -> u64 {
+        self.frames
+            .last()
+            .map_or(0, |frame| frame.decompressed_offset + frame.decompressed_size as u64)
+    }
+
+    /// Decompresses the frame, failing if its content doesn't have the size listed in the seek
+    /// table.
+    pub fn read_frame(&mut self, frame: usize) -> io::Result<Vec<u8>> {
+        let frame = *self
+            .frames
+            .get(frame)
+            .ok_or_else(|| invalid_data(format!("frame {frame} is out of the archive")))?;
+        let mut compressed = vec![0; frame.compressed_size as usize];
+        self.reader.seek(SeekFrom::Start(frame.compressed_offset))?;
+        self.reader.read_exact(&mut compressed)?;
+
+        // The listed size isn't trusted to preallocate the content
+        let mut content = Vec::new();
+        zstd::stream::read::Decoder::new(compressed.as_slice())?
+            .take(frame.decompressed_size as u64 + 1)
+            .read_to_end(&mut content)?;
+        if content.len() != frame.decompressed_size as usize {
+            return Err(invalid_data("zstd seekable frame doesn't match its size in the seek table"))
+        }
+        Ok(content)
+    }
+
+    /// Decompresses the byte range of the decompressed data, clamped to its size, reading only
+    /// the frames overlapping it.
+    pub fn read_range(&mut self, range: Range<u64>) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        for index in 0..self.frames.len() {
+            let frame = self.frames[index];
+            let frame_end = frame.decompressed_offset + frame.decompressed_size as u64;
+            if frame_end <= range.start || frame.decompressed_offset >= range.end {
+                continue
+            }
+
+            let content = self.read_frame(index)?;
+            let start = range.start.saturating_sub(frame.decompressed_offset) as usize;
+            let end = (range.end.min(frame_end) - frame.decompressed_offset) as usize;
+            data.extend_from_slice(&content[start..end]);
+        }
+        Ok(data)
+    }
+}
+
+/// Writes the rows of the segment for the block range to the writer as line-delimited JSON, like
+/// [`jsonl`], compressed in the zstd seekable format with `blocks_per_frame` blocks in every
+/// frame. Returns the number of written rows, and the writer.
+///
+/// Frame `i` holds the blocks from `block_range.start() + i * blocks_per_frame`, so consumers
+/// find the frames of a block range from the seek table alone.
+pub fn jsonl_seekable<W: Write>(
+    reader: &StaticFileReader,
+    segment: StaticFileSegment,
+    block_range: RangeInclusive<BlockNumber>,
+    blocks_per_frame: u64,
+    level: i32,
+    writer: W,
+) -> Result<(u64, W), StaticFileReaderError> {
+    let blocks_per_frame = blocks_per_frame.max(1);
+    // Frames are only finished at block boundaries
+    let mut writer = SeekableWriter::new(writer, level, u32::MAX as usize);
+
+    let mut rows = 0;
+    let mut start = *block_range.start();
+    while start <= *block_range.end() {
+        let end = start.saturating_add(blocks_per_frame - 1).min(*block_range.end());
+        rows += jsonl(reader, segment, start..=end, &mut writer)?;
+        writer.finish_frame()?;
+        start = match end.checked_add(1) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    Ok((rows, writer.finish()?))
+}
+
+/// Returns an [`io::ErrorKind::InvalidData`] error with the message.
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_provider::StaticFileProviderFactory;
+    use std::io::Cursor;
+
+    #[test]
+    fn random_access() {
+        let data = (0..10_000u32).flat_map(|n| n.to_le_bytes()).collect::<Vec<_>>();
+        let mut writer = SeekableWriter::new(Vec::new(), 3, 1000);
+        writer.write_all(&data).unwrap();
+        let archive = writer.finish().unwrap();
+
+        // Archive is a regular zstd stream, skippable frame included
+        assert_eq!(zstd::decode_all(archive.as_slice()).unwrap(), data);
+
+        let mut reader = SeekableReader::new(Cursor::new(archive)).unwrap();
+        assert_eq!(reader.frames().len(), 40);
+        assert_eq!(reader.decompressed_size(), data.len() as u64);
+        assert_eq!(reader.read_range(1_500..2_700).unwrap(), &data[1_500..2_700]);
+        assert_eq!(reader.read_range(39_990..50_000).unwrap(), &data[39_990..]);
+    }
+
+    #[test]
+    fn rejects_corrupted_seek_table() {
+        let data = (0..1_000u32).flat_map(|n| n.to_le_bytes()).collect::<Vec<_>>();
+        let mut writer = SeekableWriter::new(Vec::new(), 3, 1000);
+        writer.write_all(&data).unwrap();
+        let archive = writer.finish().unwrap();
+        let footer = archive.len() - FOOTER_SIZE as usize;
+        let new = |archive: Vec<u8>| SeekableReader::new(Cursor::new(archive));
+        let is_invalid = |result: io::Result<()>| {
+            result.is_err_and(|err| err.kind() == io::ErrorKind::InvalidData)
+        };
+
+        // Frame counts whose table exceeds the archive
+        for frame_count in [5, u32::MAX] {
+            let mut tampered = archive.clone();
+            tampered[footer..footer + 4].copy_from_slice(&frame_count.to_le_bytes());
+            assert!(is_invalid(new(tampered).map(drop)));
+        }
+        assert!(is_invalid(new(archive[footer..].to_vec()).map(drop)));
+        assert!(is_invalid(new(archive[..4].to_vec()).map(drop)));
+
+        // Frames that don't add up to the archive
+        let first_entry = archive.len() - (4 * ENTRY_SIZE + FOOTER_SIZE) as usize;
+        let mut tampered = archive.clone();
+        tampered[first_entry] ^= 1;
+        assert!(is_invalid(new(tampered).map(drop)));
+
+        // Decompressed sizes that don't match the frames
+        let mut tampered = archive.clone();
+        tampered[first_entry + 4..first_entry + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut reader = new(tampered).unwrap();
+        assert!(is_invalid(reader.read_frame(0).map(drop)));
+        assert_eq!(reader.read_frame(1).unwrap(), &data[1000..2000]);
+    }
+
+    #[test]
+    fn block_aligned_frames() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+
+        let (rows, archive) =
+            jsonl_seekable(&reader, StaticFileSegment::Headers, 0..=3, 2, 3, Vec::new()).unwrap();
+        assert_eq!(rows, 4);
+
+        let mut expected = Vec::new();
+        jsonl(&reader, StaticFileSegment::Headers, 2..=3, &mut expected).unwrap();
+        let mut archive = SeekableReader::new(Cursor::new(archive)).unwrap();
+        assert_eq!(archive.frames().len(), 2);
+        assert_eq!(archive.read_frame(1).unwrap(), expected);
+    }
+}
@@ -0,0 +1,290 @@
+//! C bindings of the [`StaticFileReader`], so indexers written in other languages, e.g. Go or
+//! Python through cffi, can read static files without reimplementing their decoding.
+//!
+//! Rows are returned as line-delimited JSON in the format of [`export::jsonl`], one row per line.
+//! The ABI is:
+//!
+//! ```c
+//! typedef struct sf_reader sf_reader;
+//! typedef struct { uint8_t *data; size_t len; } sf_buffer;
+//!
+//! sf_reader *sf_open(const char *path);
+//! void sf_close(sf_reader *reader);
+//! int32_t sf_get_header(const sf_reader *reader, uint64_t block, sf_buffer *out);
+//! int32_t sf_get_receipt_range(const sf_reader *reader, uint64_t start_block,
+//!                              uint64_t end_block, sf_buffer *out);
+//! void sf_buffer_free(sf_buffer buffer);
+//! const char *sf_last_error(void);
+//! ```
+//!
+//! Functions returning a status return [`SF_OK`], [`SF_NOT_FOUND`] or [`SF_ERROR`]. The message
+//! of the last error of the calling thread is returned by [`sf_last_error`]. Panics never unwind
+//! into the caller: they're reported like errors, with [`SF_ERROR`] or null.
+
+use crate::{export, StaticFileReader, StaticFileReaderError};
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::StaticFileSegment;
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    ops::RangeInclusive,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+/// Status of a successful call.
+pub const SF_OK: i32 = 0;
+
+/// Status of a call whose rows are not in static files.
+pub const SF_NOT_FOUND: i32 = 1;
+
+/// Status of a failed call, see [`sf_last_error`].
+pub const SF_ERROR: i32 = -1;
+
+thread_local! {
+    /// Message of the last error of the thread, returned by [`sf_last_error`].
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque reader handle returned by [`sf_open`].
+#[derive(Debug)]
+pub struct SfReader(StaticFileReader);
+
+/// Bytes returned to the caller, freed with [`sf_buffer_free`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct SfBuffer {
+    /// Pointer to the bytes, null if empty.
+    pub data: *mut u8,
+    /// Number of bytes.
+    pub len: usize,
+}
+
+impl SfBuffer {
+    /// Hands the bytes over to the caller.
+    fn new(bytes: Vec<u8>) -> Self {
+        if bytes.is_empty() {
+            return Self { data: ptr::null_mut(), len: 0 }
+        }
+        let len = bytes.len();
+        Self { data: Box::into_raw(bytes.into_boxed_slice()).cast(), len }
+    }
+}
+
+/// Opens the static files directory read-only. Returns null on error.
+///
+/// # Safety
+///
+/// `path` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn sf_open(path: *const c_char) -> *mut SfReader {
+    catch_panic(ptr::null_mut(), || {
+        if path.is_null() {
+            set_last_error("path is null");
+            return ptr::null_mut()
+        }
+        // SAFETY: the caller guarantees `path` is a valid NUL-terminated string.
+        let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+            set_last_error("path is not valid UTF-8");
+            return ptr::null_mut()
+        };
+
+        let reader = StaticFileProvider::read_only(path)
+            .map_err(|err| err.to_string())
+            .and_then(|provider| StaticFileReader::new(provider).map_err(|err| err.to_string()));
+        match reader {
+            Ok(reader) => Box::into_raw(Box::new(SfReader(reader))),
+            Err(err) => {
+                set_last_error(err);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Closes the reader.
+///
+/// # Safety
+///
+/// `reader` must be null or returned by [`sf_open`], and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sf_close(reader: *mut SfReader) {
+    catch_panic((), || {
+        if !reader.is_null() {
+            // SAFETY: the caller guarantees `reader` was returned by `sf_open` and isn't closed
+            // yet.
+            drop(unsafe { Box::from_raw(reader) });
+        }
+    })
+}
+
+/// Writes the header of the block to `out`, as a single JSON line.
+///
+/// # Safety
+///
+/// `reader` must be returned by [`sf_open`], and `out` a valid pointer to an [`SfBuffer`].
+#[no_mangle]
+pub unsafe extern "C" fn sf_get_header(
+    reader: *const SfReader,
+    block: u64,
+    out: *mut SfBuffer,
+) -> i32 {
+    catch_panic(SF_ERROR, || {
+        // SAFETY: guaranteed by the caller.
+        unsafe { export_rows(reader, StaticFileSegment::Headers, block..=block, out) }
+    })
+}
+
+/// Writes the receipts of the blocks from `start_block` to `end_block` inclusive to `out`, one
+/// JSON line per receipt.
+///
+/// # Safety
+///
+/// `reader` must be returned by [`sf_open`], and `out` a valid pointer to an [`SfBuffer`].
+#[no_mangle]
+pub unsafe extern "C" fn sf_get_receipt_range(
+    reader: *const SfReader,
+    start_block: u64,
+    end_block: u64,
+    out: *mut SfBuffer,
+) -> i32 {
+    catch_panic(SF_ERROR, || {
+        // SAFETY: guaranteed by the caller.
+        unsafe { export_rows(reader, StaticFileSegment::Receipts, start_block..=end_block, out) }
+    })
+}
+
+/// Frees the bytes returned by another function.
+///
+/// # Safety
+///
+/// `buffer` must be returned by another function of this module, and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn sf_buffer_free(buffer: SfBuffer) {
+    catch_panic((), || {
+        if !buffer.data.is_null() {
+            // SAFETY: the caller guarantees the buffer was created by `SfBuffer::new`, from a
+            // boxed slice of `len` bytes.
+            drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)) });
+        }
+    })
+}
+
+/// Returns the message of the last error of the calling thread, or null if there was none. The
+/// message is valid until the next call on the thread.
+#[no_mangle]
+pub extern "C" fn sf_last_error() -> *const c_char {
+    LAST_ERROR
+        .with(|last_error| last_error.borrow().as_ref().map_or(ptr::null(), |err| err.as_ptr()))
+}
+
+/// Exports the rows of the segment for the block range to `out`.
+///
+/// # Safety
+///
+/// `reader` must be returned by [`sf_open`], and `out` a valid pointer to an [`SfBuffer`].
+unsafe fn export_rows(
+    reader: *const SfReader,
+    segment: StaticFileSegment,
+    block_range: RangeInclusive<u64>,
+    out: *mut SfBuffer,
+) -> i32 {
+    if reader.is_null() || out.is_null() {
+        set_last_error("reader or output is null");
+        return SF_ERROR
+    }
+    // SAFETY: the caller guarantees `reader` was returned by `sf_open`.
+    let reader = unsafe { &(*reader).0 };
+
+    let mut rows = Vec::new();
+    match export::jsonl(reader, segment, block_range, &mut rows) {
+        Ok(_) => {
+            // SAFETY: the caller guarantees `out` is a valid pointer.
+            unsafe { out.write(SfBuffer::new(rows)) };
+            SF_OK
+        }
+        Err(err @ StaticFileReaderError::MissingStaticFiles { .. }) => {
+            set_last_error(err.to_string());
+            SF_NOT_FOUND
+        }
+        Err(err) => {
+            set_last_error(err.to_string());
+            SF_ERROR
+        }
+    }
+}
+
+/// Runs the body of a function of the C ABI, returning `on_panic` if it panics, as unwinding into
+/// the caller is undefined behavior. The panic message is recorded as the last error.
+fn catch_panic<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            set_last_error(format!("panicked: {message}"));
+            on_panic
+        }
+    }
+}
+
+/// Records the message as the last error of the thread.
+fn set_last_error(message: impl Into<String>) {
+    let message = CString::new(message.into().replace('\0', " ")).expect("NUL bytes were replaced");
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_provider::StaticFileProviderFactory;
+
+    #[test]
+    fn reads_through_c_abi() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let directory = harness.provider_factory.static_file_provider().directory().to_path_buf();
+        let path = CString::new(directory.to_str().unwrap()).unwrap();
+
+        // SAFETY: the pointers passed are valid, and the buffers are freed once.
+        unsafe {
+            let reader = sf_open(path.as_ptr());
+            assert!(!reader.is_null());
+
+            let mut out = SfBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(sf_get_header(reader, 2, &mut out), SF_OK);
+            let line = std::slice::from_raw_parts(out.data, out.len);
+            let header = serde_json::from_slice::<serde_json::Value>(line).unwrap();
+            assert_eq!(header["hash"], harness.blocks[2].hash().to_string());
+            sf_buffer_free(out);
+
+            let mut out = SfBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(sf_get_receipt_range(reader, 0, 3, &mut out), SF_OK);
+            let lines = std::slice::from_raw_parts(out.data, out.len);
+            let receipts = harness.blocks.iter().map(|block| block.body.len()).sum::<usize>();
+            assert_eq!(lines.iter().filter(|byte| **byte == b'\n').count(), receipts);
+            sf_buffer_free(out);
+
+            let mut out = SfBuffer { data: ptr::null_mut(), len: 0 };
+            assert_eq!(sf_get_header(reader, 4, &mut out), SF_NOT_FOUND);
+            assert!(!sf_last_error().is_null());
+            sf_close(reader);
+
+            assert!(sf_open(ptr::null()).is_null());
+        }
+    }
+
+    #[test]
+    fn panics_are_reported_as_errors() {
+        assert_eq!(catch_panic(SF_ERROR, || panic!("corrupted row")), SF_ERROR);
+        // SAFETY: the last error is valid until the next call on the thread.
+        let message = unsafe { CStr::from_ptr(sf_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "panicked: corrupted row");
+
+        assert_eq!(catch_panic(SF_ERROR, || SF_OK), SF_OK);
+    }
+}
@@ -0,0 +1,641 @@
+//! Listing of static files in a directory.
+
+use crate::{
+    accumulator::EPOCH_ROOTS_FILE_NAME, rollback::sync_directory, StaticFileManifest,
+    BLOCK_BOUNDARIES_EXTENSION, CHD_INDEX_EXTENSION, COMMITTED_ROWS_FILE_NAME, DEDUP_EXTENSION,
+    LOG_INDEX_EXTENSION, METRICS_TEXTFILE_NAME, PENDING_SEALED_FILE_NAME,
+    PRUNE_CHECKPOINTS_FILE_NAME, PRUNE_CHECKPOINTS_LOCK_FILE_NAME, RUN_HISTORY_FILE_NAME,
+    SENDER_INDEX_EXTENSION, SHARD_MANIFEST_FILE_NAME, TIER_LOCATIONS_FILE_NAME,
+    TRICKLE_PROGRESS_FILE_NAME,
+};
+use reth_nippy_jar::NippyJar;
+use reth_static_file_types::{
+    HighestStaticFiles, LowestStaticFiles, SegmentConfig, SegmentHeader, SegmentRangeInclusive,
+    StaticFileSegment,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+use tracing::warn;
+
+/// Extensions of the files accompanying a static data file, in the order they're hashed for
+/// content-addressed naming: offsets, index, configuration, the index sidecars, the CHD
+/// perfect hashing function and the dedup table.
+pub const COMPANION_EXTENSIONS: [&str; 8] = [
+    "off",
+    "idx",
+    "conf",
+    LOG_INDEX_EXTENSION,
+    SENDER_INDEX_EXTENSION,
+    BLOCK_BOUNDARIES_EXTENSION,
+    CHD_INDEX_EXTENSION,
+    DEDUP_EXTENSION,
+];
+
+/// Static file found in a static files directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticFileEntry {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file, as encoded in its file name.
+    pub block_range: SegmentRangeInclusive,
+    /// Path to the data file. Offsets, configuration and filters live next to it, with the same
+    /// file name and an extension.
+    pub path: PathBuf,
+}
+
+impl StaticFileEntry {
+    /// Returns the paths of the data file and the existing companion files, in the order of
+    /// [`COMPANION_EXTENSIONS`].
+    pub fn paths(&self) -> Vec<PathBuf> {
+        std::iter::once(self.path.clone())
+            .chain(COMPANION_EXTENSIONS.iter().map(|extension| self.companion_path(extension)))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    /// Returns `true` if the static file is sealed, i.e. blocks after its range were already
+    /// moved to static files, so it won't be appended to anymore.
+    pub fn is_sealed(&self, highest_static_files: &HighestStaticFiles) -> bool {
+        highest_static_files
+            .highest(self.segment)
+            .is_some_and(|highest| highest > self.block_range.end())
+    }
+
+    /// Returns the filters and compression of the static file, if they're encoded in its file
+    /// name. The static file isn't opened.
+    pub fn configuration(&self) -> Option<SegmentConfig> {
+        let name = self.path.file_name()?.to_str()?;
+        StaticFileSegment::parse_configured_filename(name)?.2
+    }
+
+    /// Removes the data file and the existing companion files of the static file, e.g. the loser
+    /// of a [`ScanIssue::DuplicateRange`].
+    ///
+    /// The static file provider has to reload its index after static files were removed.
+    pub fn remove(&self) -> io::Result<()> {
+        for path in self.paths() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the companion file with the extension.
+    pub fn companion_path(&self, extension: &str) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(".");
+        path.push(extension);
+        path.into()
+    }
+}
+
+/// Lists all static files in the directory, sorted by segment and block range.
+///
+/// Only data files are listed: companion files (offsets, configuration, filters) and files that
+/// don't follow the static file naming scheme are skipped.
+pub fn list_static_files(directory: impl AsRef<Path>) -> io::Result<Vec<StaticFileEntry>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        // Links are followed, as static files moved to the cold directory are linked
+        let path = entry.path();
+        if !path.is_file() {
+            continue
+        }
+
+        // Data files have no extension
+        if path.extension().is_some() {
+            continue
+        }
+
+        let Some((segment, block_range)) =
+            entry.file_name().to_str().and_then(StaticFileSegment::parse_filename)
+        else {
+            continue
+        };
+        entries.push(StaticFileEntry { segment, block_range, path });
+    }
+
+    entries.sort_unstable_by_key(|entry| (entry.segment, entry.block_range.start()));
+    Ok(entries)
+}
+
+/// Files other than static files and their companion files that are expected in a static files
+/// directory, the metrics textfile if it's written there, and the lock file of the static file
+/// provider.
+const KNOWN_FILE_NAMES: [&str; 11] = [
+    COMMITTED_ROWS_FILE_NAME,
+    EPOCH_ROOTS_FILE_NAME,
+    METRICS_TEXTFILE_NAME,
+    PENDING_SEALED_FILE_NAME,
+    PRUNE_CHECKPOINTS_FILE_NAME,
+    PRUNE_CHECKPOINTS_LOCK_FILE_NAME,
+    RUN_HISTORY_FILE_NAME,
+    SHARD_MANIFEST_FILE_NAME,
+    TIER_LOCATIONS_FILE_NAME,
+    TRICKLE_PROGRESS_FILE_NAME,
+    "lock",
+];
+
+/// Policy of [`scan_static_files`] for inconsistent static files directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanPolicy {
+    /// Fails at the first [`ScanIssue`].
+    Strict,
+    /// Logs a warning for every [`ScanIssue`], skipping unknown files and keeping one of the
+    /// conflicting static files, see [`scan_static_files`].
+    #[default]
+    Permissive,
+}
+
+/// Inconsistency of a static files directory found by [`scan_static_files`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanIssue {
+    /// The file is neither a static file, nor a companion or metadata file of static files.
+    UnknownFile(PathBuf),
+    /// Two static files of the segment have the same block range, e.g. because they were produced
+    /// with different configurations.
+    DuplicateRange {
+        /// Segment of the static files.
+        segment: StaticFileSegment,
+        /// Fixed block range of both static files.
+        block_range: SegmentRangeInclusive,
+        /// Static file that's kept in permissive mode.
+        kept: PathBuf,
+        /// Static file that's skipped in permissive mode.
+        skipped: PathBuf,
+    },
+    /// Two static files of the segment have overlapping, but different block ranges.
+    OverlappingRanges {
+        /// Segment of the static files.
+        segment: StaticFileSegment,
+        /// Static file with the lower block range, that's kept in permissive mode.
+        kept: PathBuf,
+        /// Static file that's skipped in permissive mode.
+        skipped: PathBuf,
+    },
+}
+
+impl fmt::Display for ScanIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFile(path) => write!(f, "unknown file {}", path.display()),
+            Self::DuplicateRange { segment, block_range, kept, skipped } => write!(
+                f,
+                "{segment} static files {} and {} both cover blocks {block_range}",
+                kept.display(),
+                skipped.display()
+            ),
+            Self::OverlappingRanges { segment, kept, skipped } => write!(
+                f,
+                "{segment} static files {} and {} overlap",
+                kept.display(),
+                skipped.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScanIssue {}
+
+/// Error returned by [`scan_static_files`].
+#[derive(Debug)]
+pub enum DirectoryScanError {
+    /// Filesystem error while listing the directory.
+    Io(io::Error),
+    /// The directory is inconsistent and the [`ScanPolicy`] is strict.
+    Inconsistent(ScanIssue),
+}
+
+impl From<io::Error> for DirectoryScanError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<DirectoryScanError> for io::Error {
+    fn from(value: DirectoryScanError) -> Self {
+        match value {
+            DirectoryScanError::Io(err) => err,
+            DirectoryScanError::Inconsistent(issue) => Self::new(io::ErrorKind::InvalidData, issue),
+        }
+    }
+}
+
+impl fmt::Display for DirectoryScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::Inconsistent(issue) => fmt::Display::fmt(issue, f),
+        }
+    }
+}
+
+impl std::error::Error for DirectoryScanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Inconsistent(issue) => Some(issue),
+        }
+    }
+}
+
+/// Static files of a directory, as scanned by [`scan_static_files`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectoryScan {
+    /// Consistent static files, sorted by segment and block range.
+    pub entries: Vec<StaticFileEntry>,
+    /// Issues found in the directory. Always empty in strict mode.
+    pub issues: Vec<ScanIssue>,
+    /// Static files skipped for another static file of the same segment and block range, see
+    /// [`ScanIssue::DuplicateRange`].
+    pub duplicates: Vec<StaticFileEntry>,
+}
+
+impl DirectoryScan {
+    /// Removes the [duplicates](DirectoryScan::duplicates) that lost against another static file
+    /// of the same range.
+    ///
+    /// The static file provider has to reload its index after static files were removed.
+    pub fn remove_duplicates(&self) -> io::Result<()> {
+        for entry in &self.duplicates {
+            entry.remove()?;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the static files in the directory like [`list_static_files`], checking that the
+/// directory holds no unknown files, and no static files with duplicate or overlapping block
+/// ranges.
+///
+/// In [`ScanPolicy::Strict`] mode the first issue is returned as an error. In
+/// [`ScanPolicy::Permissive`] mode issues are logged and returned with the entries, and unknown
+/// files are skipped. Of overlapping static files, the one with the lowest block range is kept.
+/// Of static files with the same block range, e.g. `static_file_headers_0_499999_none_lz4` and
+/// `static_file_headers_0_499999_none_zstd`, the newest one is kept, falling back to the lowest
+/// path.
+pub fn scan_static_files(
+    directory: impl AsRef<Path>,
+    policy: ScanPolicy,
+) -> Result<DirectoryScan, DirectoryScanError> {
+    scan_static_files_against(directory, policy, None)
+}
+
+/// Same as [`scan_static_files`], keeping the static file listed in the manifest among static
+/// files with the same block range, if any.
+pub fn scan_static_files_against(
+    directory: impl AsRef<Path>,
+    policy: ScanPolicy,
+    manifest: Option<&StaticFileManifest>,
+) -> Result<DirectoryScan, DirectoryScanError> {
+    let directory = directory.as_ref();
+    let mut issues = Vec::new();
+    for entry in std::fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && !is_known_file(&entry.file_name().to_string_lossy()) {
+            issues.push(ScanIssue::UnknownFile(entry.path()));
+        }
+    }
+
+    // Static files of the same range are sorted by precedence: listed in the manifest, newest,
+    // lowest path, so conflicts are resolved the same way on every scan
+    let mut listed = list_static_files(directory)?
+        .into_iter()
+        .map(|entry| {
+            let listed = manifest.is_some_and(|manifest| is_listed(manifest, &entry));
+            let modified = entry.path.metadata()?.modified()?;
+            let key = (
+                entry.segment,
+                entry.block_range.start(),
+                !listed,
+                Reverse(modified),
+                entry.path.clone(),
+            );
+            Ok((key, entry))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    listed.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+
+    let (mut entries, mut duplicates) =
+        (Vec::<StaticFileEntry>::with_capacity(listed.len()), Vec::new());
+    for (_, entry) in listed {
+        let issue = match entries.last() {
+            Some(last)
+                if last.segment == entry.segment && last.block_range == entry.block_range =>
+            {
+                let issue = ScanIssue::DuplicateRange {
+                    segment: entry.segment,
+                    block_range: entry.block_range,
+                    kept: last.path.clone(),
+                    skipped: entry.path.clone(),
+                };
+                duplicates.push(entry);
+                issue
+            }
+            Some(last)
+                if last.segment == entry.segment &&
+                    entry.block_range.start() <= last.block_range.end() =>
+            {
+                ScanIssue::OverlappingRanges {
+                    segment: entry.segment,
+                    kept: last.path.clone(),
+                    skipped: entry.path,
+                }
+            }
+            _ => {
+                entries.push(entry);
+                continue
+            }
+        };
+        issues.push(issue);
+    }
+
+    match policy {
+        ScanPolicy::Strict if !issues.is_empty() => {
+            return Err(DirectoryScanError::Inconsistent(issues.swap_remove(0)))
+        }
+        ScanPolicy::Strict => {}
+        ScanPolicy::Permissive => {
+            for issue in &issues {
+                warn!(target: "static_file", %issue, "Inconsistent static files directory");
+            }
+        }
+    }
+
+    Ok(DirectoryScan { entries, issues, duplicates })
+}
+
+/// Returns `true` if the static file is listed in the manifest under its file name.
+fn is_listed(manifest: &StaticFileManifest, entry: &StaticFileEntry) -> bool {
+    manifest.files.iter().any(|file| {
+        file.segment == entry.segment &&
+            file.block_range == entry.block_range &&
+            entry.path.file_name() == Some(OsStr::new(&file.file_name))
+    })
+}
+
+/// Returns `true` if the file name is expected in a static files directory: a static file, one of
+/// its companion files, a metadata file or a temporary file of one of them.
+fn is_known_file(name: &str) -> bool {
+    let name = name.strip_suffix(".tmp").unwrap_or(name);
+    if KNOWN_FILE_NAMES.contains(&name) {
+        return true
+    }
+
+    let path = Path::new(name);
+    let stem = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if COMPANION_EXTENSIONS.contains(&extension) => {
+            path.file_stem().and_then(|stem| stem.to_str())
+        }
+        Some(_) => None,
+        None => Some(name),
+    };
+    stem.and_then(StaticFileSegment::parse_filename).is_some()
+}
+
+/// Rewrites the configuration file of the loaded static file, e.g. after its header was updated.
+///
+/// Unlike [`NippyJar::freeze_config`], the configuration is written to a temporary file that
+/// replaces it once synced, so readers and crashes see either the previous or the updated
+/// configuration, even for sealed static files that are already served. Providers that loaded the
+/// static file keep its previous header until they load it again.
+pub(crate) fn rewrite_config(jar: &NippyJar<SegmentHeader>) -> io::Result<()> {
+    let path = jar.config_path();
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+
+    let config = bincode::serialize(jar).map_err(io::Error::other)?;
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&config)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp_path, &path)?;
+    sync_directory(path.parent().unwrap_or_else(|| Path::new(".")))
+}
+
+/// Returns `true` if the name is a single plain path component, so joining it to a directory
+/// can't escape the directory.
+pub(crate) fn is_plain_file_name(name: &str) -> bool {
+    !name.contains(['/', '\\']) &&
+        matches!(
+            Path::new(name).components().collect::<Vec<_>>()[..],
+            [Component::Normal(component)] if component == name
+        )
+}
+
+/// Returns the lowest block of every segment among the listed static files.
+pub fn lowest_static_files(entries: &[StaticFileEntry]) -> LowestStaticFiles {
+    let mut lowest = LowestStaticFiles::default();
+    for entry in entries {
+        let lowest = lowest.as_mut(entry.segment);
+        *lowest = Some(
+            lowest.map_or(entry.block_range.start(), |block| block.min(entry.block_range.start())),
+        );
+    }
+    lowest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NamingScheme;
+    use reth_static_file_types::{Compression, FilterHash, Filters};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn lists_data_files_only() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in [
+            "static_file_receipts_500000_999999",
+            "static_file_headers_500000_999999",
+            "static_file_headers_0_499999",
+            "static_file_headers_0_499999.off",
+            "static_file_headers_0_499999.conf",
+            "static_file_headers_1000000_1499999_af130000000001ff.off",
+            "static_file_transactions_0_499999_none_zstd",
+            "unrelated",
+        ] {
+            std::fs::write(directory.path().join(name), []).unwrap();
+        }
+
+        let entries = list_static_files(directory.path()).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|entry| (entry.segment, entry.block_range.start()))
+                .collect::<Vec<_>>(),
+            vec![
+                (StaticFileSegment::Headers, 0),
+                (StaticFileSegment::Headers, 500_000),
+                (StaticFileSegment::Transactions, 0),
+                (StaticFileSegment::Receipts, 500_000),
+            ]
+        );
+        assert_eq!(
+            lowest_static_files(&entries),
+            LowestStaticFiles { headers: Some(0), receipts: Some(500_000), transactions: Some(0) }
+        );
+        assert_eq!(entries[0].configuration(), None);
+        assert_eq!(
+            entries[2].configuration(),
+            Some(SegmentConfig {
+                filters: Filters::WithoutFilters,
+                compression: Compression::Zstd,
+                filter_fpp: None,
+                filter_hash: FilterHash::Identity,
+                filter_key: None,
+            })
+        );
+    }
+
+    #[test]
+    fn scan_policies() {
+        let directory = tempfile::tempdir().unwrap();
+        for name in [
+            "static_file_headers_0_499999",
+            "static_file_headers_0_499999.conf",
+            "static_file_headers_0_499999_none_zstd",
+            "static_file_headers_500000_999999",
+            "static_file_transactions_0_499999",
+            COMMITTED_ROWS_FILE_NAME,
+        ] {
+            std::fs::write(directory.path().join(name), []).unwrap();
+        }
+        set_modified(&directory.path().join("static_file_headers_0_499999_none_zstd"), UNIX_EPOCH);
+        let strict = scan_static_files(directory.path(), ScanPolicy::Strict);
+        assert!(matches!(
+            strict,
+            Err(DirectoryScanError::Inconsistent(ScanIssue::DuplicateRange { kept, skipped, .. }))
+                if kept.ends_with("static_file_headers_0_499999") &&
+                    skipped.ends_with("static_file_headers_0_499999_none_zstd")
+        ));
+
+        std::fs::write(directory.path().join("unrelated"), []).unwrap();
+        let permissive = scan_static_files(directory.path(), ScanPolicy::Permissive).unwrap();
+        assert_eq!(
+            permissive
+                .entries
+                .iter()
+                .map(|entry| entry.path.file_name().unwrap().to_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                "static_file_headers_0_499999",
+                "static_file_headers_500000_999999",
+                "static_file_transactions_0_499999",
+            ]
+        );
+        assert_eq!(permissive.issues.len(), 2);
+        assert!(permissive
+            .issues
+            .contains(&ScanIssue::UnknownFile(directory.path().join("unrelated"))));
+
+        // Consistent directories pass in strict mode
+        for name in ["unrelated", "static_file_headers_0_499999_none_zstd"] {
+            std::fs::remove_file(directory.path().join(name)).unwrap();
+        }
+        let strict = scan_static_files(directory.path(), ScanPolicy::Strict).unwrap();
+        assert_eq!(strict.entries, permissive.entries);
+        assert!(strict.issues.is_empty());
+    }
+
+    #[test]
+    fn duplicate_range_precedence() {
+        let directory = tempfile::tempdir().unwrap();
+        let lz4 = directory.path().join("static_file_headers_0_499999_none_lz4");
+        let zstd = directory.path().join("static_file_headers_0_499999_none_zstd");
+        for path in [&lz4, &zstd] {
+            std::fs::write(path, []).unwrap();
+            std::fs::write(path.with_extension("off"), []).unwrap();
+        }
+        set_modified(&lz4, UNIX_EPOCH + Duration::from_secs(2));
+        set_modified(&zstd, UNIX_EPOCH + Duration::from_secs(1));
+
+        // Newest static file wins
+        let scan = scan_static_files(directory.path(), ScanPolicy::Permissive).unwrap();
+        assert_eq!(scan.entries.len(), 1);
+        assert_eq!(scan.entries[0].path, lz4);
+        assert_eq!(scan.duplicates.len(), 1);
+        assert_eq!(scan.duplicates[0].path, zstd);
+
+        // Static file listed in the manifest wins
+        let highest = HighestStaticFiles { headers: Some(500_000), ..Default::default() };
+        let mut manifest =
+            StaticFileManifest::new(&scan.duplicates, highest, NamingScheme::Plain).unwrap();
+        manifest.files[0].file_name = "static_file_headers_0_499999_none_zstd".to_string();
+        let scan =
+            scan_static_files_against(directory.path(), ScanPolicy::Permissive, Some(&manifest))
+                .unwrap();
+        assert_eq!(scan.entries[0].path, zstd);
+        assert_eq!(
+            scan.issues,
+            vec![ScanIssue::DuplicateRange {
+                segment: StaticFileSegment::Headers,
+                block_range: SegmentRangeInclusive::new(0, 499_999),
+                kept: zstd.clone(),
+                skipped: lz4.clone(),
+            }]
+        );
+
+        // Losers are removed with their companion files
+        scan.remove_duplicates().unwrap();
+        assert!(!lz4.exists() && !lz4.with_extension("off").exists());
+        let scan = scan_static_files(directory.path(), ScanPolicy::Strict).unwrap();
+        assert_eq!(scan.entries.len(), 1);
+        assert_eq!(scan.entries[0].path, zstd);
+    }
+
+    fn set_modified(path: &Path, time: SystemTime) {
+        std::fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn sealed_static_files() {
+        let entry = StaticFileEntry {
+            segment: StaticFileSegment::Headers,
+            block_range: SegmentRangeInclusive::new(0, 499_999),
+            path: PathBuf::from("static_file_headers_0_499999"),
+        };
+        let highest = |headers| HighestStaticFiles { headers, ..Default::default() };
+
+        assert!(!entry.is_sealed(&highest(None)));
+        assert!(!entry.is_sealed(&highest(Some(499_999))));
+        assert!(entry.is_sealed(&highest(Some(500_000))));
+        assert!(
+            !entry.is_sealed(&HighestStaticFiles { receipts: Some(500_000), ..Default::default() })
+        );
+    }
+
+    #[test]
+    fn strict_scan_after_run() {
+        let harness = crate::test_utils::StaticFileTestHarness::new(3, 1..3);
+        let directory = harness.static_files_dir.path();
+        let mut producer = harness.producer();
+        producer.set_receipt_log_index(true);
+        producer.set_sender_index(true);
+        producer.set_epoch_accumulator(true);
+        producer.set_commit_interval_blocks(Some(1));
+        producer.set_run_history(Some(10));
+        producer.set_metrics_textfile_dir(Some(directory.to_path_buf()));
+        harness.run_with(&producer).unwrap();
+
+        // Every file written by the run is known to the scan
+        for name in [
+            COMMITTED_ROWS_FILE_NAME,
+            METRICS_TEXTFILE_NAME,
+            PRUNE_CHECKPOINTS_FILE_NAME,
+            RUN_HISTORY_FILE_NAME,
+        ] {
+            assert!(directory.join(name).exists(), "{name}");
+        }
+        let scan = scan_static_files(directory, ScanPolicy::Strict).unwrap();
+        assert!(scan.issues.is_empty());
+        assert_eq!(scan.entries.len(), 3);
+    }
+}
@@ -0,0 +1,223 @@
+//! Health of the [`StaticFileProducer`](crate::StaticFileProducer), backing node health endpoints
+//! and alerting.
+
+use alloy_primitives::BlockNumber;
+use parking_lot::Mutex;
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// Default number of blocks a segment may lag behind the database before the producer is
+/// [`HealthStatus::Degraded`].
+pub const DEFAULT_MAX_LAG_BLOCKS: u64 = 10_000;
+
+/// Handle evaluating the health of a producer, even while the producer is locked by the running
+/// thread. Created with
+/// [`StaticFileProducerInner::health`](crate::StaticFileProducerInner::health).
+#[derive(Debug, Clone)]
+pub struct ProducerHealth {
+    /// Static file provider of the producer, for the highest static file blocks.
+    provider: StaticFileProvider,
+    /// Segments produced when the handle was created.
+    segments: Vec<StaticFileSegment>,
+    /// Outcomes of the runs, recorded by the producer.
+    outcomes: RunOutcomes,
+    /// Number of blocks a segment may lag behind the database.
+    max_lag: u64,
+    /// Time since the last successful run after which the producer is degraded. `None` doesn't
+    /// limit it.
+    max_run_age: Option<Duration>,
+}
+
+impl ProducerHealth {
+    /// Creates a new [`ProducerHealth`] of the segments, lagging at most
+    /// [`DEFAULT_MAX_LAG_BLOCKS`].
+    pub(crate) fn new(
+        provider: StaticFileProvider,
+        segments: Vec<StaticFileSegment>,
+        outcomes: RunOutcomes,
+    ) -> Self {
+        Self { provider, segments, outcomes, max_lag: DEFAULT_MAX_LAG_BLOCKS, max_run_age: None }
+    }
+
+    /// Sets the number of blocks a segment may lag behind the database before the producer is
+    /// degraded.
+    pub const fn with_max_lag(mut self, blocks: u64) -> Self {
+        self.max_lag = blocks;
+        self
+    }
+
+    /// Sets the time since the last successful run after which the producer is degraded, e.g.
+    /// a few times the interval it's run at.
+    pub const fn with_max_run_age(mut self, max_run_age: Duration) -> Self {
+        self.max_run_age = Some(max_run_age);
+        self
+    }
+
+    /// Evaluates the health of the producer against the highest block of the database.
+    ///
+    /// The producer failed if its last run failed. It's degraded if a segment lags behind the
+    /// database by more than the maximum lag, or its last successful run is older than the
+    /// maximum run age.
+    pub fn evaluate(&self, highest_db_block: BlockNumber) -> HealthReport {
+        self.report(self.provider.get_highest_static_files(), highest_db_block, SystemTime::now())
+    }
+
+    /// Builds the report from the highest static file blocks.
+    fn report(
+        &self,
+        highest: HighestStaticFiles,
+        highest_db_block: BlockNumber,
+        now: SystemTime,
+    ) -> HealthReport {
+        let segments = self
+            .segments
+            .iter()
+            .map(|&segment| {
+                let highest = highest.highest(segment);
+                let lag = match highest {
+                    Some(highest) => highest_db_block.saturating_sub(highest),
+                    None => highest_db_block.saturating_add(1),
+                };
+                SegmentLag { segment, highest, lag }
+            })
+            .collect::<Vec<_>>();
+
+        let outcomes = *self.outcomes.0.lock();
+        let stale = self.max_run_age.is_some_and(|max_run_age| {
+            !outcomes.last_success.is_some_and(|last_success| {
+                now.duration_since(last_success).unwrap_or_default() <= max_run_age
+            })
+        });
+        let status = if outcomes.last_run_failed {
+            HealthStatus::Failed
+        } else if stale || segments.iter().any(|segment| segment.lag > self.max_lag) {
+            HealthStatus::Degraded
+        } else {
+            HealthStatus::Healthy
+        };
+
+        HealthReport { segments, last_success: outcomes.last_success, status }
+    }
+}
+
+/// Outcomes of the runs of a producer, shared with its [`ProducerHealth`] handles.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RunOutcomes(Arc<Mutex<Outcomes>>);
+
+/// Outcomes of the runs of a producer.
+#[derive(Debug, Clone, Copy, Default)]
+struct Outcomes {
+    /// Time the last successful run finished at.
+    last_success: Option<SystemTime>,
+    /// Whether the last run failed.
+    last_run_failed: bool,
+}
+
+impl RunOutcomes {
+    /// Records the outcome of a run that just finished.
+    pub(crate) fn record(&self, success: bool) {
+        let mut outcomes = self.0.lock();
+        if success {
+            outcomes.last_success = Some(SystemTime::now());
+        }
+        outcomes.last_run_failed = !success;
+    }
+}
+
+/// Health of a producer, evaluated by [`ProducerHealth::evaluate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthReport {
+    /// Lag of every produced segment behind the database.
+    pub segments: Vec<SegmentLag>,
+    /// Time the last successful run finished at, if any since the producer was created.
+    pub last_success: Option<SystemTime>,
+    /// Overall status.
+    pub status: HealthStatus,
+}
+
+impl HealthReport {
+    /// Returns `true` if the producer is [`HealthStatus::Healthy`].
+    pub fn is_healthy(&self) -> bool {
+        self.status == HealthStatus::Healthy
+    }
+
+    /// Returns `true` if any segment lags behind the database.
+    pub fn is_behind(&self) -> bool {
+        self.segments.iter().any(|segment| segment.lag > 0)
+    }
+}
+
+/// Lag of a segment behind the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentLag {
+    /// Segment of the static files.
+    pub segment: StaticFileSegment,
+    /// Highest block of the segment in static files, if any.
+    pub highest: Option<BlockNumber>,
+    /// Number of blocks of the database not in static files yet.
+    pub lag: u64,
+}
+
+/// Overall status of a producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthStatus {
+    /// Every segment is within the maximum lag and the last run succeeded.
+    Healthy,
+    /// A segment lags behind more than the maximum lag, or no run succeeded for longer than the
+    /// maximum run age.
+    Degraded,
+    /// The last run failed.
+    Failed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_provider::test_utils::create_test_provider_factory;
+
+    #[test]
+    fn health_status() {
+        let outcomes = RunOutcomes::default();
+        let health = ProducerHealth::new(
+            create_test_provider_factory().static_file_provider(),
+            vec![StaticFileSegment::Headers, StaticFileSegment::Receipts],
+            outcomes.clone(),
+        )
+        .with_max_lag(100);
+        let highest =
+            HighestStaticFiles { headers: Some(1_000), receipts: Some(950), ..Default::default() };
+        let now = SystemTime::now();
+
+        let report = health.report(highest, 1_050, now);
+        assert_eq!(report.status, HealthStatus::Healthy);
+        assert!(report.is_behind());
+        assert_eq!(
+            report.segments,
+            vec![
+                SegmentLag { segment: StaticFileSegment::Headers, highest: Some(1_000), lag: 50 },
+                SegmentLag { segment: StaticFileSegment::Receipts, highest: Some(950), lag: 100 },
+            ]
+        );
+
+        // A segment lagging behind more than the maximum lag
+        assert_eq!(health.report(highest, 1_051, now).status, HealthStatus::Degraded);
+
+        // A failed run takes precedence, until a run succeeds again
+        outcomes.record(false);
+        assert_eq!(health.report(highest, 1_051, now).status, HealthStatus::Failed);
+        outcomes.record(true);
+        let report = health.report(highest, 1_000, now);
+        assert!(report.is_healthy());
+        assert!(report.last_success.is_some());
+
+        // No successful run within the maximum run age
+        let health = health.with_max_run_age(Duration::from_secs(60));
+        assert!(health.report(highest, 1_000, now).is_healthy());
+        let later = now + Duration::from_secs(3600);
+        assert_eq!(health.report(highest, 1_000, later).status, HealthStatus::Degraded);
+    }
+}
@@ -0,0 +1,207 @@
+//! History of the [`StaticFileProducer`](crate::StaticFileProducer) runs, persisted in the static
+//! files directory so past runs can be looked into after the fact, e.g. when production got
+//! slower at some point.
+//!
+//! Every run appends a [`RunRecord`] as a JSON line to [`RUN_HISTORY_FILE_NAME`]. Once the file
+//! holds twice as many records as kept, it's compacted to the most recent ones.
+
+use crate::{DataGap, RunTimings, StaticFileTargets};
+use reth_static_file_types::{SegmentRangeInclusive, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the file in the static files directory that the run history is appended to.
+pub const RUN_HISTORY_FILE_NAME: &str = "run_history.jsonl";
+
+/// Report of a single [`StaticFileProducerInner::run`](crate::StaticFileProducerInner::run).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// Start of the run, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Block ranges requested per segment.
+    pub requested: Vec<(StaticFileSegment, SegmentRangeInclusive)>,
+    /// Block ranges produced per segment. Empty if the run failed.
+    pub produced: Vec<(StaticFileSegment, SegmentRangeInclusive)>,
+    /// Transaction ranges produced per segment keyed by transaction number, see
+    /// [`StaticFileTargets::tx_ranges`]. Empty if the run failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub produced_tx_ranges: Vec<(StaticFileSegment, SegmentRangeInclusive)>,
+    /// Time it took to run.
+    pub elapsed: Duration,
+    /// Breakdown of the elapsed time, if the timing summary is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timings: Option<RunTimings>,
+    /// Blocks whose data was missing from the database, that segments stopped at or skipped
+    /// according to their [`MissingDataPolicy`](crate::MissingDataPolicy).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gaps: Vec<DataGap>,
+    /// Error the run failed with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl RunRecord {
+    /// Creates a new [`RunRecord`] of a run that started at `started_at`.
+    pub(crate) fn new(
+        started_at: SystemTime,
+        requested: &StaticFileTargets,
+        result: Result<(&StaticFileTargets, Option<RunTimings>), String>,
+        elapsed: Duration,
+    ) -> Self {
+        let (produced, timings, error) = match result {
+            Ok((produced, timings)) => (target_ranges(produced), timings, None),
+            Err(error) => (Vec::new(), None, Some(error)),
+        };
+        Self {
+            started_at: started_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            requested: target_ranges(requested),
+            produced,
+            produced_tx_ranges: Vec::new(),
+            elapsed,
+            timings,
+            gaps: Vec::new(),
+            error,
+        }
+    }
+
+    /// Sets the transaction ranges produced per segment.
+    pub(crate) fn with_produced_tx_ranges(
+        mut self,
+        tx_ranges: Vec<(StaticFileSegment, SegmentRangeInclusive)>,
+    ) -> Self {
+        self.produced_tx_ranges = tx_ranges;
+        self
+    }
+
+    /// Sets the blocks whose data was missing from the database.
+    pub(crate) fn with_gaps(mut self, gaps: Vec<DataGap>) -> Self {
+        self.gaps = gaps;
+        self
+    }
+}
+
+/// Reads the run history persisted in the static files directory, oldest run first. Returns no
+/// records if nothing was persisted yet.
+///
+/// Lines that can't be parsed, e.g. torn by a crash in the middle of an append, are skipped.
+pub fn read_run_history(directory: &Path) -> io::Result<Vec<RunRecord>> {
+    let file = match File::open(directory.join(RUN_HISTORY_FILE_NAME)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(record) = serde_json::from_str(&line?) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Appends the record to the run history of the static files directory, compacting it to the
+/// `limit` most recent records once it holds twice as many.
+pub(crate) fn append_run_record(
+    directory: &Path,
+    record: &RunRecord,
+    limit: usize,
+) -> io::Result<()> {
+    let path = directory.join(RUN_HISTORY_FILE_NAME);
+    let mut records = read_run_history(directory)?;
+    if records.len() < limit.max(1) * 2 {
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&path)?;
+        truncate_torn_line(&mut file)?;
+        file.write_all(&encode_line(record)?)?;
+        return file.sync_data()
+    }
+
+    records.push(record.clone());
+    let tmp_path = path.with_extension("jsonl.tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    for record in &records[records.len() - limit.max(1)..] {
+        writer.write_all(&encode_line(record)?)?;
+    }
+    writer.into_inner().map_err(io::IntoInnerError::into_error)?.sync_all()?;
+    std::fs::rename(tmp_path, path)
+}
+
+/// Truncates a line torn by a crash in the middle of an append back to the last complete line, so
+/// the next record isn't appended to it.
+fn truncate_torn_line(file: &mut File) -> io::Result<()> {
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    if data.last().is_some_and(|byte| *byte != b'\n') {
+        let len = data.iter().rposition(|byte| *byte == b'\n').map_or(0, |position| position + 1);
+        file.set_len(len as u64)?;
+    }
+    Ok(())
+}
+
+/// Encodes the record as a JSON line.
+fn encode_line(record: &RunRecord) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+/// Returns the block ranges of the targets per segment.
+fn target_ranges(targets: &StaticFileTargets) -> Vec<(StaticFileSegment, SegmentRangeInclusive)> {
+    [StaticFileSegment::Headers, StaticFileSegment::Transactions, StaticFileSegment::Receipts]
+        .into_iter()
+        .filter_map(|segment| {
+            let range = targets.target(segment)?;
+            Some((segment, SegmentRangeInclusive::new(*range.start(), *range.end())))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_static_file_types::HighestStaticFiles;
+
+    #[test]
+    fn run_history_compaction() {
+        let directory = tempfile::tempdir().unwrap();
+        assert!(read_run_history(directory.path()).unwrap().is_empty());
+
+        let targets = StaticFileTargets::builder(HighestStaticFiles::default())
+            .range(StaticFileSegment::Headers, 0..=99)
+            .build()
+            .unwrap();
+        for run in 0..5 {
+            let result = if run == 2 { Err("disk full".to_string()) } else { Ok((&targets, None)) };
+            let record = RunRecord::new(
+                UNIX_EPOCH + Duration::from_secs(run),
+                &targets,
+                result,
+                Duration::from_secs(1),
+            );
+            append_run_record(directory.path(), &record, 2).unwrap();
+        }
+
+        // Compacted to the 2 most recent records once there were more than 4
+        let records = read_run_history(directory.path()).unwrap();
+        assert_eq!(records.iter().map(|record| record.started_at).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(records[1].produced, vec![(StaticFileSegment::Headers, (0..=99).into())]);
+
+        // Torn lines are skipped, and truncated before the next append
+        let path = directory.path().join(RUN_HISTORY_FILE_NAME);
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(b"{\"started_at\":").unwrap();
+        assert_eq!(read_run_history(directory.path()).unwrap(), records);
+        let record = RunRecord::new(
+            UNIX_EPOCH + Duration::from_secs(5),
+            &targets,
+            Ok((&targets, None)),
+            Duration::from_secs(1),
+        );
+        append_run_record(directory.path(), &record, 2).unwrap();
+        assert_eq!(read_run_history(directory.path()).unwrap(), [records, vec![record]].concat());
+    }
+}
@@ -0,0 +1,312 @@
+//! Hooks for integrating static file production with resource governors and downstream
+//! consumers of sealed static files.
+
+use crate::{manifest::write_json, StaticFileEntry};
+use alloy_primitives::B64;
+use parking_lot::{Condvar, Mutex};
+use reth_static_file_types::{SegmentRangeInclusive, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Default number of blocks in a batch reported to [`BatchHooks`].
+pub const DEFAULT_BATCH_SIZE: u64 = 1_000;
+
+/// Resource usage of a batch of blocks copied to static files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchStats {
+    /// Segment the batch belongs to.
+    pub segment: StaticFileSegment,
+    /// Blocks copied in the batch.
+    pub blocks: SegmentRangeInclusive,
+    /// Number of rows copied in the batch.
+    pub rows: u64,
+    /// Bytes read from the database.
+    pub bytes_read: u64,
+    /// Uncompressed bytes appended to static files.
+    pub bytes_written: u64,
+    /// Wall-clock time spent on the batch.
+    pub elapsed: Duration,
+    /// CPU time spent on the batch by the copying thread, if it's available on this platform.
+    pub cpu_time: Option<Duration>,
+}
+
+/// Callback invoked with [`BatchStats`] after every batch.
+pub type BatchHook = Arc<dyn Fn(&BatchStats) + Send + Sync>;
+
+/// Hooks invoked while segments are copied to static files, and the [`PauseHandle`] allowing to
+/// pause and resume the run.
+///
+/// Hooks are called on the thread that copied the batch, so embedders can attribute the work to
+/// the thread (e.g. move it to a dedicated cgroup).
+#[derive(Clone)]
+pub struct BatchHooks {
+    /// Callbacks invoked after every batch.
+    hooks: Vec<BatchHook>,
+    /// Number of blocks in a batch.
+    batch_size: u64,
+    /// Pause state shared with the embedder.
+    pause: PauseHandle,
+}
+
+impl BatchHooks {
+    /// Creates new [`BatchHooks`] reporting every `batch_size` blocks.
+    pub fn new(batch_size: u64) -> Self {
+        Self { hooks: Vec::new(), batch_size: batch_size.max(1), pause: PauseHandle::default() }
+    }
+
+    /// Adds a callback invoked with [`BatchStats`] after every batch.
+    pub fn on_batch(mut self, hook: impl Fn(&BatchStats) + Send + Sync + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Returns the number of blocks in a batch.
+    pub const fn batch_size(&self) -> u64 {
+        self.batch_size
+    }
+
+    /// Returns `true` if any callbacks are registered.
+    pub fn has_hooks(&self) -> bool {
+        !self.hooks.is_empty()
+    }
+
+    /// Returns the [`PauseHandle`] pausing the copy at the next block boundary.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+
+    /// Invokes all callbacks with the batch stats.
+    pub(crate) fn notify(&self, stats: &BatchStats) {
+        for hook in &self.hooks {
+            hook(stats);
+        }
+    }
+}
+
+impl Default for BatchHooks {
+    fn default() -> Self {
+        Self::new(DEFAULT_BATCH_SIZE)
+    }
+}
+
+impl fmt::Debug for BatchHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BatchHooks")
+            .field("hooks", &self.hooks.len())
+            .field("batch_size", &self.batch_size)
+            .field("pause", &self.pause)
+            .finish()
+    }
+}
+
+/// Static file that was sealed during a run, and won't be appended to anymore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedFile {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file.
+    pub block_range: SegmentRangeInclusive,
+    /// Path to the data file.
+    pub path: PathBuf,
+    /// [Content hash](crate::content_hash) of the data file and its companion files.
+    pub checksum: B64,
+}
+
+/// Callback invoked with every [`SealedFile`].
+pub type SealHook = Arc<dyn Fn(&SealedFile) + Send + Sync>;
+
+/// Hooks invoked after every run for the static files sealed by it, so downstream consumers
+/// (e.g. indexers) can pick up new immutable files without polling the static files directory.
+///
+/// Hooks are called on the thread running the producer, once the run is committed. Callbacks
+/// that need to do more work should forward the file to a channel.
+#[derive(Clone, Default)]
+pub struct SealHooks {
+    /// Callbacks invoked for every sealed static file.
+    hooks: Vec<SealHook>,
+}
+
+impl SealHooks {
+    /// Adds a callback invoked with every [`SealedFile`].
+    pub fn on_sealed(mut self, hook: impl Fn(&SealedFile) + Send + Sync + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Returns `true` if any callbacks are registered.
+    pub fn has_hooks(&self) -> bool {
+        !self.hooks.is_empty()
+    }
+
+    /// Invokes all callbacks with the sealed file.
+    pub(crate) fn notify(&self, file: &SealedFile) {
+        for hook in &self.hooks {
+            hook(file);
+        }
+    }
+}
+
+impl fmt::Debug for SealHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SealHooks").field("hooks", &self.hooks.len()).finish()
+    }
+}
+
+/// Name of the file in the static files directory that lists the sealed static files the
+/// [`SealHooks`] weren't notified about yet.
+pub const PENDING_SEALED_FILE_NAME: &str = "pending_sealed.json";
+
+/// Sealed static files the [`SealHooks`] weren't notified about, e.g. because their content hash
+/// couldn't be computed. Persisted to [`PENDING_SEALED_FILE_NAME`], so the next run notifies
+/// about them again.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct PendingSealed {
+    /// Segments and fixed block ranges of the pending static files.
+    files: Vec<(StaticFileSegment, SegmentRangeInclusive)>,
+}
+
+impl PendingSealed {
+    /// Reads the pending static files of the static files directory, if any.
+    pub(crate) fn read(directory: &Path) -> io::Result<Self> {
+        let path = directory.join(PENDING_SEALED_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes the pending static files to the static files directory, removing the file once
+    /// nothing is pending.
+    pub(crate) fn write(&self, directory: &Path) -> io::Result<()> {
+        let path = directory.join(PENDING_SEALED_FILE_NAME);
+        if self.files.is_empty() {
+            return match std::fs::remove_file(path) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            }
+        }
+        let tmp_path = path.with_extension("json.tmp");
+        write_json(self, &tmp_path)?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    /// Returns `true` if the static file is pending.
+    pub(crate) fn contains(&self, entry: &StaticFileEntry) -> bool {
+        self.files.contains(&(entry.segment, entry.block_range))
+    }
+
+    /// Adds the static file to the pending ones.
+    pub(crate) fn push(&mut self, entry: &StaticFileEntry) {
+        self.files.push((entry.segment, entry.block_range));
+    }
+}
+
+/// Handle pausing and resuming static file production at block boundaries.
+#[derive(Debug, Clone, Default)]
+pub struct PauseHandle(Arc<(Mutex<bool>, Condvar)>);
+
+impl PauseHandle {
+    /// Pauses copying at the next block boundary.
+    pub fn pause(&self) {
+        *self.0 .0.lock() = true;
+    }
+
+    /// Resumes copying.
+    pub fn resume(&self) {
+        *self.0 .0.lock() = false;
+        self.0 .1.notify_all();
+    }
+
+    /// Returns `true` if copying is paused.
+    pub fn is_paused(&self) -> bool {
+        *self.0 .0.lock()
+    }
+
+    /// Blocks the current thread while paused, or until `stop` returns `true`.
+    pub(crate) fn wait_while_paused(&self, stop: impl Fn() -> bool) {
+        let (paused, condvar) = &*self.0;
+        let mut paused = paused.lock();
+        while *paused && !stop() {
+            condvar.wait_for(&mut paused, Duration::from_millis(100));
+        }
+    }
+}
+
+/// Returns the CPU time consumed by the current thread.
+#[cfg(unix)]
+pub(crate) fn thread_cpu_time() -> Option<Duration> {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `time` is a valid pointer to a `timespec`.
+    let result = unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) };
+    (result == 0).then(|| Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+/// Returns the CPU time consumed by the current thread.
+#[cfg(not(unix))]
+pub(crate) const fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Instant,
+    };
+
+    #[test]
+    fn pause_and_resume() {
+        let handle = PauseHandle::default();
+        handle.pause();
+        assert!(handle.is_paused());
+
+        let resumed = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                handle.wait_while_paused(|| false);
+                assert!(resumed.load(Ordering::Relaxed));
+            });
+            std::thread::sleep(Duration::from_millis(50));
+            resumed.store(true, Ordering::Relaxed);
+            handle.resume();
+        });
+        assert!(!handle.is_paused());
+
+        // Stop condition unblocks a paused thread
+        handle.pause();
+        let start = Instant::now();
+        handle.wait_while_paused(|| start.elapsed() > Duration::from_millis(50));
+        assert!(handle.is_paused());
+    }
+
+    #[test]
+    fn pending_sealed() {
+        let directory = tempfile::tempdir().unwrap();
+        assert_eq!(PendingSealed::read(directory.path()).unwrap(), PendingSealed::default());
+
+        let entry = StaticFileEntry {
+            segment: StaticFileSegment::Headers,
+            block_range: SegmentRangeInclusive::new(0, 499_999),
+            path: directory.path().join("static_file_headers_0_499999"),
+        };
+        let mut pending = PendingSealed::default();
+        pending.push(&entry);
+        pending.write(directory.path()).unwrap();
+        let read = PendingSealed::read(directory.path()).unwrap();
+        assert_eq!(read, pending);
+        assert!(read.contains(&entry));
+
+        // Nothing pending removes the file
+        PendingSealed::default().write(directory.path()).unwrap();
+        assert!(!directory.path().join(PENDING_SEALED_FILE_NAME).exists());
+        PendingSealed::default().write(directory.path()).unwrap();
+    }
+}
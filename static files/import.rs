@@ -0,0 +1,226 @@
+//! Import of static files downloaded from mirrors.
+
+use crate::{
+    content_hash, dictionary_file_name, files::is_plain_file_name, DictionaryStore, ManifestError,
+    StaticFileEntry, StaticFileManifest, TrustedManifest, COMPANION_EXTENSIONS,
+};
+use reth_static_file_types::StaticFileSegment;
+use std::{io, path::Path};
+
+/// Imports static files listed in a [`TrustedManifest`] from the `source` directory, where they
+/// were downloaded under their manifest names, into the static files directory.
+///
+/// The manifest signature is verified against the trusted keys, or its root against the pinned
+/// one, and all downloaded files are checked against the manifest before any of them is copied,
+/// so a failed import leaves the static files directory untouched. Shared dictionaries referenced
+/// by the static files are checked against their ids and added to the [`DictionaryStore`].
+/// Manifests of static files produced with another schema version are rejected. Returns the
+/// imported static files.
+pub fn import_static_files(
+    manifest: TrustedManifest<'_>,
+    source: &Path,
+    static_files_dir: &Path,
+) -> Result<Vec<StaticFileEntry>, ManifestError> {
+    let manifest = manifest.verify()?;
+    if let Some(build) = &manifest.build {
+        build.check_schema().map_err(ManifestError::IncompatibleSchema)?;
+    }
+
+    Ok(VerifiedFiles::check(manifest, source)?.copy_to(static_files_dir)?)
+}
+
+/// Static files and shared dictionaries listed in a [`StaticFileManifest`], checked against it
+/// in the directory they were downloaded to under their manifest names.
+#[derive(Debug)]
+pub(crate) struct VerifiedFiles {
+    /// Downloaded static files.
+    entries: Vec<StaticFileEntry>,
+    /// Shared dictionaries referenced by the static files.
+    dictionaries: Vec<Vec<u8>>,
+}
+
+impl VerifiedFiles {
+    /// Checks the static files and shared dictionaries of the manifest in the `source`
+    /// directory. Files are only looked up under plain names of the static files they're listed
+    /// as, so the manifest can't point outside of the directory.
+    pub(crate) fn check(
+        manifest: &StaticFileManifest,
+        source: &Path,
+    ) -> Result<Self, ManifestError> {
+        let mut downloaded = Vec::with_capacity(manifest.files.len());
+        for file in &manifest.files {
+            if !is_plain_file_name(&file.file_name) ||
+                file.file_name.contains('.') ||
+                StaticFileSegment::parse_filename(&file.file_name) !=
+                    Some((file.segment, file.block_range))
+            {
+                return Err(ManifestError::InvalidFileName { file_name: file.file_name.clone() })
+            }
+
+            let entry = StaticFileEntry {
+                segment: file.segment,
+                block_range: file.block_range,
+                path: source.join(&file.file_name),
+            };
+
+            let mut size = 0;
+            for path in entry.paths() {
+                size += path.metadata()?.len();
+            }
+            let content_matches = size == file.size &&
+                match file.content_hash {
+                    Some(expected) => content_hash(&entry)? == expected,
+                    None => true,
+                };
+            if !content_matches {
+                return Err(ManifestError::ContentMismatch { file_name: file.file_name.clone() })
+            }
+
+            downloaded.push(entry);
+        }
+
+        let source_dictionaries = DictionaryStore::new(source);
+        let mut dictionaries = Vec::new();
+        for id in manifest.dictionary_refs().keys() {
+            let dictionary = source_dictionaries.get(id).map_err(|err| match err.kind() {
+                io::ErrorKind::InvalidData => {
+                    ManifestError::ContentMismatch { file_name: dictionary_file_name(id) }
+                }
+                _ => err.into(),
+            })?;
+            dictionaries.push(dictionary);
+        }
+
+        Ok(Self { entries: downloaded, dictionaries })
+    }
+
+    /// Copies the checked static files under their plain names and the shared dictionaries into
+    /// the static files directory. Returns the copied static files.
+    pub(crate) fn copy_to(self, static_files_dir: &Path) -> io::Result<Vec<StaticFileEntry>> {
+        std::fs::create_dir_all(static_files_dir)?;
+        let store = DictionaryStore::new(static_files_dir);
+        for dictionary in self.dictionaries {
+            store.put(&dictionary)?;
+        }
+        let mut imported = Vec::with_capacity(self.entries.len());
+        for entry in self.entries {
+            let path = static_files_dir.join(entry.segment.filename(&entry.block_range));
+            std::fs::copy(&entry.path, &path)?;
+
+            let imported_entry = StaticFileEntry { path, ..entry.clone() };
+            for extension in COMPANION_EXTENSIONS {
+                let companion = entry.companion_path(extension);
+                if companion.exists() {
+                    std::fs::copy(companion, imported_entry.companion_path(extension))?;
+                }
+            }
+            imported.push(imported_entry);
+        }
+
+        Ok(imported)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_static_files, NamingScheme, StaticFileManifest};
+    use ed25519_dalek::SigningKey;
+    use reth_static_file_types::HighestStaticFiles;
+
+    #[test]
+    fn imports_verified_static_files() {
+        let node = tempfile::tempdir().unwrap();
+        for (name, content) in [
+            ("static_file_receipts_0_499999", b"receipts".as_slice()),
+            ("static_file_receipts_0_499999.conf", b"config"),
+        ] {
+            std::fs::write(node.path().join(name), content).unwrap();
+        }
+        let entries = list_static_files(node.path()).unwrap();
+        let highest = HighestStaticFiles { receipts: Some(500_000), ..Default::default() };
+
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let trusted = [signing_key.verifying_key()];
+        let manifest =
+            StaticFileManifest::new(&entries, highest, NamingScheme::ContentAddressed).unwrap();
+        let mirror = tempfile::tempdir().unwrap();
+        manifest.publish(&entries, mirror.path()).unwrap();
+        let signed = manifest.sign(&signing_key);
+
+        let trusted_manifest =
+            TrustedManifest::Signed { manifest: &signed, trusted_keys: &trusted };
+        let imported = tempfile::tempdir().unwrap();
+        assert_eq!(
+            import_static_files(trusted_manifest, mirror.path(), imported.path()).unwrap(),
+            list_static_files(imported.path()).unwrap()
+        );
+        assert_eq!(
+            std::fs::read(imported.path().join("static_file_receipts_0_499999.conf")).unwrap(),
+            b"config"
+        );
+
+        // Corrupted download is rejected
+        let file_name = &signed.manifest.files[0].file_name;
+        std::fs::write(mirror.path().join(file_name), b"receiptz").unwrap();
+        let rejected = tempfile::tempdir().unwrap();
+        assert!(matches!(
+            import_static_files(trusted_manifest, mirror.path(), rejected.path()),
+            Err(ManifestError::ContentMismatch { .. })
+        ));
+        assert!(list_static_files(rejected.path()).unwrap().is_empty());
+        std::fs::write(mirror.path().join(file_name), b"receipts").unwrap();
+
+        // Unsigned manifests are only imported if they match the pinned root
+        let root = signed.manifest.root();
+        let pinned = TrustedManifest::Pinned { manifest: &signed.manifest, root };
+        let imported = tempfile::tempdir().unwrap();
+        import_static_files(pinned, mirror.path(), imported.path()).unwrap();
+        let mut tampered = signed.manifest.clone();
+        tampered.files[0].size += 1;
+        assert!(matches!(
+            import_static_files(
+                TrustedManifest::Pinned { manifest: &tampered, root },
+                mirror.path(),
+                rejected.path()
+            ),
+            Err(ManifestError::RootMismatch { expected, actual })
+                if expected == root && actual == tampered.root()
+        ));
+        assert!(list_static_files(rejected.path()).unwrap().is_empty());
+
+        // Static files produced with another schema version are rejected
+        let mut manifest = signed.manifest.clone();
+        manifest.build.as_mut().unwrap().schema_version += 1;
+        let signed_manifest = manifest.sign(&signing_key);
+        assert!(matches!(
+            import_static_files(
+                TrustedManifest::Signed { manifest: &signed_manifest, trusted_keys: &trusted },
+                mirror.path(),
+                rejected.path()
+            ),
+            Err(ManifestError::IncompatibleSchema(_))
+        ));
+
+        // Files outside of the source directory or of another static file are rejected
+        for file_name in [
+            "../static_file_receipts_0_499999",
+            "/tmp/static_file_receipts_0_499999",
+            "static_file_receipts_0_499999.conf",
+            "static_file_headers_0_499999",
+        ] {
+            let mut manifest = signed.manifest.clone();
+            manifest.files[0].file_name = file_name.to_string();
+            let signed_manifest = manifest.sign(&signing_key);
+            assert!(matches!(
+                import_static_files(
+                    TrustedManifest::Signed { manifest: &signed_manifest, trusted_keys: &trusted },
+                    mirror.path(),
+                    rejected.path()
+                ),
+                Err(ManifestError::InvalidFileName { file_name: invalid }) if invalid == file_name
+            ));
+        }
+        assert!(list_static_files(rejected.path()).unwrap().is_empty());
+    }
+}
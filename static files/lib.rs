@@ -10,20 +10,267 @@
 )]
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
 
+mod accumulator;
+mod block_boundaries;
+mod block_source;
+mod build_info;
+mod chain_spec;
+mod chd_index;
+mod chunked;
+mod commitment;
+mod committed;
+mod config;
+mod coordinator;
+mod dedup;
+mod dictionaries;
+pub mod doctor;
+mod download;
+mod era1;
+mod error;
 mod event;
+pub mod export;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod files;
+mod health;
+mod history;
+mod hooks;
+mod import;
+mod log_index;
+mod manifest;
+mod missing_data;
+mod multi_segment;
+#[cfg(feature = "opentelemetry")]
+pub mod otel;
+mod preallocation;
+mod profiling;
+mod progress;
+mod provider;
+mod prune_checkpoint;
+#[cfg(feature = "python")]
+pub mod python;
+mod quota;
+pub mod reader;
+mod readers;
+mod reorg;
+mod repair;
+mod restore;
+mod retention;
+mod rewrite;
+mod rollback;
 pub mod segments;
+mod sender_index;
+mod shadow;
+mod shard;
+mod sidecar;
+mod sink;
 mod static_file_producer;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+mod textfile;
+mod tiering;
+mod transform;
+mod trickle;
+mod tuning;
+mod warmup;
+mod watcher;
+mod workers;
+
+// Re-exports the `StaticFileProducerError` from the `error` module.
+pub use error::StaticFileProducerError;
 
 // Re-exports the `StaticFileProducerEvent` from the `event` module.
-pub use event::StaticFileProducerEvent;
+pub use event::{
+    EventReceiver, FailureKind, OverflowPolicy, RunTimings, StaticFileEventSender,
+    StaticFileProducerEvent,
+};
+
+// Re-exports listing of static files from the `files` module.
+pub use files::{
+    list_static_files, lowest_static_files, scan_static_files, scan_static_files_against,
+    DirectoryScan, DirectoryScanError, ScanIssue, ScanPolicy, StaticFileEntry,
+    COMPANION_EXTENSIONS,
+};
+
+// Re-exports the last committed rows published after every commit from the `committed` module.
+pub use committed::{CommittedRows, COMMITTED_ROWS_FILE_NAME};
+
+// Re-exports the manifest of sealed static files from the `manifest` module.
+pub use manifest::{
+    content_hash, ChunkHashes, ManifestEntry, ManifestError, NamingScheme, SignedManifest,
+    StaticFileManifest, TrustedManifest,
+};
+
+// Re-exports chunked archives of sealed static files from the `chunked` module.
+pub use chunked::{ArchivedFile, ChunkedArchive, DEFAULT_ARCHIVE_CHUNK_SIZE};
+
+// Re-exports resumable downloads of static files from the `download` module.
+pub use download::{
+    download_static_file, download_static_file_with_events, DownloadError, RangeFetcher,
+    DEFAULT_DOWNLOAD_CONNECTIONS,
+};
+
+// Re-exports verified import of downloaded static files from the `import` module.
+pub use import::import_static_files;
+
+// Re-exports the serializable producer configuration from the `config` module.
+pub use config::{ProducerConfig, SegmentProducerConfig, SegmentsConfig};
+
+// Re-exports retention of old static files from the `retention` module.
+pub use retention::{RetentionOutcome, RetentionPolicy, RetentionService, RetentionSink};
+
+// Re-exports the health of the producer from the `health` module.
+pub use health::{HealthReport, HealthStatus, ProducerHealth, SegmentLag, DEFAULT_MAX_LAG_BLOCKS};
+
+// Re-exports the registry of static file readers from the `readers` module.
+pub use readers::{pending_removals, pin_reader, ReaderGuard, RETIRED_DIR_NAME};
+
+// Re-exports the prune checkpoints handshake with the pruner from the `prune_checkpoint` module.
+pub use prune_checkpoint::{
+    PruneCheckpoint, PruneCheckpoints, PRUNE_CHECKPOINTS_FILE_NAME,
+    PRUNE_CHECKPOINTS_LOCK_FILE_NAME,
+};
+
+// Re-exports the persisted history of producer runs from the `history` module.
+pub use history::{read_run_history, RunRecord, RUN_HISTORY_FILE_NAME};
+
+// Re-exports the Prometheus textfile metrics snapshot from the `textfile` module.
+pub use textfile::{MetricsSnapshot, SegmentMetrics, METRICS_TEXTFILE_NAME};
+
+// Re-exports batch hooks for resource governors and seal hooks from the `hooks` module.
+pub use hooks::{
+    BatchHook, BatchHooks, BatchStats, PauseHandle, SealHook, SealHooks, SealedFile,
+    DEFAULT_BATCH_SIZE, PENDING_SEALED_FILE_NAME,
+};
+
+// Re-exports the pre-merge header accumulator from the `accumulator` module.
+pub use accumulator::{
+    EpochAccumulator, HeaderProof, HeaderRecord, EPOCH_ROOTS_FILE_NAME, EPOCH_SIZE, MERGE_BLOCK,
+};
+
+// Re-exports Merkle commitments over the rows of static files from the `commitment` module.
+pub use commitment::{row_leaf, verify_row_proof, RowCommitment, RowProof};
+
+// Re-exports the reader of static files and their sidecars from the `reader` module.
+pub use reader::{IndexedLog, SenderTransaction, StaticFileReader, StaticFileReaderError};
+
+// Re-exports the reader spanning all static files of a segment from the `multi_segment` module.
+pub use multi_segment::{MultiSegmentReader, SegmentRange, SegmentRow, SegmentValue};
+
+// Re-exports the block boundaries sidecar from the `block_boundaries` module.
+pub use block_boundaries::{
+    BlockBoundaries, BlockBoundariesWriter, TransactionBoundaries, TransactionBoundariesWriter,
+    BLOCK_BOUNDARIES_EXTENSION,
+};
+
+// Re-exports sources of blocks other than the database from the `block_source` module.
+pub use block_source::{
+    verify_blocks, BlockSource, BlockSourceError, RpcBlockSource, RpcTransport, SourceBlock,
+    TrustedCheckpoint, DEFAULT_RPC_BATCH_SIZE,
+};
+
+// Re-exports the era1 archive import from the `era1` module.
+pub use era1::{Era1Source, ERA1_EXTENSION};
+
+// Re-exports the chain specification from the `chain_spec` module.
+pub use chain_spec::{check_chain_id, ChainMismatch, StaticFileChainSpec};
+
+// Re-exports index sidecars from the `sidecar` module.
+pub use sidecar::{IndexRow, Sidecar, SidecarWriter};
+
+// Re-exports the receipt log index sidecar from the `log_index` module.
+pub use log_index::{LogIndex, LogIndexWriter, LOG_INDEX_EXTENSION};
+
+// Re-exports the transaction sender index sidecar from the `sender_index` module.
+pub use sender_index::{SenderIndex, SenderIndexWriter, SENDER_INDEX_EXTENSION};
+
+// Re-exports the CHD perfect hashing function sidecar from the `chd_index` module.
+pub use chd_index::CHD_INDEX_EXTENSION;
+
+// Re-exports the transaction input dedup table sidecar from the `dedup` module.
+pub use dedup::{DedupTable, DedupWriter, DEDUP_EXTENSION, DEDUP_MAGIC};
+
+// Re-exports the shared zstd dictionaries from the `dictionaries` module.
+pub use dictionaries::{
+    dictionary_file_name, dictionary_refs, DictionaryStore, DICTIONARIES_DIR_NAME,
+};
+
+// Re-exports destinations of copied rows, including the in-memory one, from the `sink` module.
+pub use sink::{InMemoryReader, InMemorySink, StaticFileSink};
+
+// Re-exports the watcher of external changes to static files from the `watcher` module.
+pub use watcher::{ExternalChangeKind, StaticFileWatcher};
+
+// Re-exports the build metadata recorded in produced static files from the `build_info` module.
+pub use build_info::build_metadata;
+
+// Re-exports the configuration of worker threads from the `workers` module.
+pub use workers::{WorkersConfig, DEFAULT_THREAD_NAME_PREFIX};
+
+// Re-exports repairs of quarantined static files from the `repair` module.
+pub use repair::{repair_static_file, RepairError, RepairMirror, RepairService, REPAIR_DIR_NAME};
+
+// Re-exports forced rewrites of sealed static files from the `rewrite` module.
+pub use rewrite::{rewrite_static_file, RewriteError, REWRITE_DIR_NAME};
+
+// Re-exports the downgrade path back into the database from the `restore` module.
+pub use restore::restore_to_db;
+
+// Re-exports self-profiling of static file reads from the `profiling` module.
+pub use profiling::{FileReadStats, ProfilingTask, ReadProfiler};
+
+// Re-exports dual reads from static files and the database from the `shadow` module.
+pub use shadow::{ShadowReadStats, ShadowReader};
+
+// Re-exports the disk quota checked before producing from the `quota` module.
+pub use quota::{estimate_bytes, DiskQuota, QuotaViolation};
+
+// Re-exports preallocation of static files from the `preallocation` module.
+pub use preallocation::{FileSizeEstimate, PreallocationConfig};
+
+// Re-exports the behavior of segments when data is missing from the `missing_data` module.
+pub use missing_data::{DataGap, MissingDataPolicy};
+
+// Re-exports the coordination of the producers of several networks from the `coordinator` module.
+pub use coordinator::ProducerCoordinator;
+
+// Re-exports sharded production of fixed ranges across hosts from the `shard` module.
+pub use shard::{
+    merge_shards, ShardAssignment, ShardError, ShardManifest, ShardProducer,
+    SHARD_MANIFEST_FILE_NAME,
+};
+
+// Re-exports segment progress tracking and the stall watchdog from the `progress` module.
+pub use progress::{CopiedRows, SegmentProgress, StallClock, StallWatchdog};
+
+// Re-exports age-based tiering of sealed static files from the `tiering` module.
+pub use tiering::{
+    TierLocation, TierLocations, TieringOutcome, TieringPolicy, TieringService,
+    TIER_LOCATIONS_FILE_NAME,
+};
+
+// Re-exports the transforms of the rows of segments from the `transform` module.
+pub use transform::{RowTransform, RowTransforms};
+
+// Re-exports the compression benchmark of segments from the `tuning` module.
+pub use tuning::{benchmark_compression, CompressionCandidate, CompressionReport};
+
+// Re-exports the daily block budget of the initial conversion from the `trickle` module.
+pub use trickle::{TrickleProgress, TrickleScheduler, TrickleStatus, TRICKLE_PROGRESS_FILE_NAME};
+
+// Re-exports the warmup of the offsets and filters of static files from the `warmup` module.
+pub use warmup::{warmup, WarmedFiles, WarmupConfig, WarmupMode, WARMUP_EXTENSIONS};
 
 // Re-exports several items from the `static_file_producer` module.
 pub use static_file_producer::{
+    RunOrder,                    // Order in which segments are copied.
     StaticFileProducer,          // Main struct for producing static files.
     StaticFileProducerInner,     // Internal structure for the producer.
     StaticFileProducerResult,    // Result type for the producer's operations.
     StaticFileProducerWithResult,// Wrapper struct for the producer with result handling.
     StaticFileTargets,           // Configuration for target static files.
+    StaticFileTargetsBuilder,    // Validating builder for target static files.
+    StaticFileTargetsError,      // Error returned when building invalid targets.
 };
 
 // Re-export all items from the `reth_static_file_types` crate for convenience.
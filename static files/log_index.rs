@@ -0,0 +1,152 @@
+//! Inverted index of receipt logs, stored in a sidecar next to every Receipts static file.
+
+use crate::{
+    sidecar::{
+        decode_rows, encode_rows, extend_rows, push_row, rows_in_range, truncate_rows, RowMap,
+    },
+    IndexRow, Sidecar, SidecarWriter,
+};
+use alloy_primitives::{Address, BlockNumber, Log, TxNumber, B256};
+use reth_static_file_types::StaticFileSegment;
+use std::{io, ops::RangeInclusive};
+
+/// Extension of the receipt log index sidecar.
+pub const LOG_INDEX_EXTENSION: &str = "logs";
+
+/// Builds [`LogIndex`] sidecars while receipts are copied to static files.
+pub type LogIndexWriter = SidecarWriter<LogIndex>;
+
+/// Inverted index of receipt logs of a single Receipts static file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogIndex {
+    /// Receipts with logs emitted by the address.
+    addresses: RowMap<Address>,
+    /// Receipts with logs with the first topic.
+    topics: RowMap<B256>,
+}
+
+impl LogIndex {
+    /// Adds the logs of the receipt to the index.
+    ///
+    /// Receipts must be added in ascending order of transaction numbers. Receipts that are
+    /// already indexed are skipped, so a range can be indexed again after an interrupted run.
+    pub fn add(&mut self, block: BlockNumber, tx_number: TxNumber, logs: &[Log]) {
+        let row = IndexRow { block, tx_number };
+        for log in logs {
+            push_row(self.addresses.entry(log.address).or_default(), row);
+            if let Some(topic) = log.topics().first() {
+                push_row(self.topics.entry(*topic).or_default(), row);
+            }
+        }
+    }
+
+    /// Returns the receipts in the block range with logs emitted by the address.
+    pub fn by_address(
+        &self,
+        address: &Address,
+        range: &RangeInclusive<BlockNumber>,
+    ) -> Vec<IndexRow> {
+        rows_in_range(self.addresses.get(address), range)
+    }
+
+    /// Returns the receipts in the block range with logs with the first topic.
+    pub fn by_topic0(&self, topic: &B256, range: &RangeInclusive<BlockNumber>) -> Vec<IndexRow> {
+        rows_in_range(self.topics.get(topic), range)
+    }
+}
+
+impl Sidecar for LogIndex {
+    const SEGMENT: StaticFileSegment = StaticFileSegment::Receipts;
+    const EXTENSION: &'static str = LOG_INDEX_EXTENSION;
+
+    fn extend(&mut self, other: Self) {
+        extend_rows(&mut self.addresses, other.addresses);
+        extend_rows(&mut self.topics, other.topics);
+    }
+
+    fn truncate(&mut self, last_block: BlockNumber) {
+        truncate_rows(&mut self.addresses, last_block);
+        truncate_rows(&mut self.topics, last_block);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.addresses.is_empty() && self.topics.is_empty()
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_rows(&self.addresses, buf);
+        encode_rows(&self.topics, buf);
+    }
+
+    fn decode(mut buf: &[u8]) -> io::Result<Self> {
+        let addresses = decode_rows(&mut buf, Address::len_bytes(), Address::from_slice)?;
+        let topics = decode_rows(&mut buf, B256::len_bytes(), B256::from_slice)?;
+        Ok(Self { addresses, topics })
+    }
+}
+
+impl LogIndexWriter {
+    /// Adds the logs of the receipt, flushing the pending index once the block belongs to the
+    /// next static file.
+    pub fn add(&mut self, block: BlockNumber, tx_number: TxNumber, logs: &[Log]) -> io::Result<()> {
+        self.sidecar(block)?.add(block, tx_number, logs);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sidecar::static_files_in_range;
+    use alloy_primitives::{Bytes, LogData};
+
+    fn log(address: u8, topic: u8) -> Log {
+        Log {
+            address: Address::with_last_byte(address),
+            data: LogData::new_unchecked(vec![B256::with_last_byte(topic)], Bytes::new()),
+        }
+    }
+
+    #[test]
+    fn log_index_sidecar() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut writer = LogIndexWriter::new(directory.path());
+        writer.add(10, 100, &[log(1, 1), log(2, 1)]).unwrap();
+        writer.add(11, 101, &[log(1, 2)]).unwrap();
+        writer.add(500_000, 200, &[log(1, 1)]).unwrap();
+        writer.flush().unwrap();
+
+        // Interrupted run is indexed again, and appended to
+        let mut writer = LogIndexWriter::new(directory.path());
+        writer.add(11, 101, &[log(1, 2)]).unwrap();
+        writer.add(12, 102, &[log(3, 3)]).unwrap();
+        writer.flush().unwrap();
+
+        let entries =
+            static_files_in_range(directory.path(), StaticFileSegment::Receipts, &(0..=600_000));
+        assert_eq!(entries.len(), 2);
+        let first = LogIndex::read(&entries[0]).unwrap();
+        assert_eq!(
+            first.by_address(&Address::with_last_byte(1), &(0..=600_000)),
+            vec![IndexRow { block: 10, tx_number: 100 }, IndexRow { block: 11, tx_number: 101 }]
+        );
+        assert_eq!(
+            first.by_address(&Address::with_last_byte(1), &(11..=11)),
+            vec![IndexRow { block: 11, tx_number: 101 }]
+        );
+        assert_eq!(
+            first.by_topic0(&B256::with_last_byte(1), &(0..=600_000)),
+            vec![IndexRow { block: 10, tx_number: 100 }]
+        );
+        assert_eq!(
+            first.by_address(&Address::with_last_byte(3), &(0..=600_000)),
+            vec![IndexRow { block: 12, tx_number: 102 }]
+        );
+        assert_eq!(
+            LogIndex::read(&entries[1])
+                .unwrap()
+                .by_address(&Address::with_last_byte(1), &(0..=600_000)),
+            vec![IndexRow { block: 500_000, tx_number: 200 }]
+        );
+    }
+}
@@ -0,0 +1,597 @@
+//! Manifest of sealed static files, for distributing them to other nodes.
+
+use crate::{
+    build_metadata,
+    commitment::row_root,
+    dictionary_file_name, dictionary_refs,
+    doctor::{diagnose_with_level, Corruption, VerificationLevel},
+    scan_static_files, DictionaryStore, DirectoryScanError, RowCommitment, ScanPolicy,
+    StaticFileEntry, COMPANION_EXTENSIONS,
+};
+use alloy_primitives::{B256, B512, B64};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use reth_static_file_types::{
+    BuildMetadata, HighestStaticFiles, IncompatibleSchemaVersion, SegmentRangeInclusive,
+    StaticFileSegment, CONTENT_HASH_LEN,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// Naming scheme of static files listed in a [`StaticFileManifest`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NamingScheme {
+    /// Static files keep their names, e.g. `static_file_headers_0_499999`.
+    #[default]
+    Plain,
+    /// Static files are suffixed with the truncated blake3 hash of their content, e.g.
+    /// `static_file_headers_0_499999_af130000000001ff`, so they can be cached as immutable by
+    /// mirrors and CDNs.
+    ContentAddressed,
+}
+
+/// Sealed static file listed in a [`StaticFileManifest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file.
+    pub block_range: SegmentRangeInclusive,
+    /// Name of the data file. Companion files share the name, with an extension.
+    pub file_name: String,
+    /// Truncated blake3 hash of the data and companion files, if content-addressed.
+    pub content_hash: Option<B64>,
+    /// Total size of the data and companion files, in bytes.
+    pub size: u64,
+    /// Hashes of the data file chunks, allowing to verify and resume partial downloads.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<ChunkHashes>,
+    /// Ids of the shared zstd dictionaries of the columns, as referenced by the header of the
+    /// static file, see [`DictionaryStore`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dictionaries: Vec<B64>,
+    /// Rows decoded when the static file was verified before being listed, if it was, see
+    /// [`StaticFileManifest::with_verification`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification: Option<VerificationLevel>,
+    /// Merkle root over the rows of the static file, if listed, see
+    /// [`StaticFileManifest::with_row_roots`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row_root: Option<B256>,
+}
+
+/// Truncated blake3 hashes of fixed-size chunks of a data file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkHashes {
+    /// Size of every chunk but the last one, in bytes.
+    pub chunk_size: u64,
+    /// Size of the data file, in bytes.
+    pub data_size: u64,
+    /// Hashes of the chunks, in order.
+    pub hashes: Vec<B64>,
+}
+
+impl ChunkHashes {
+    /// Hashes the data file in chunks of `chunk_size` bytes.
+    pub fn new(path: &Path, chunk_size: u64) -> io::Result<Self> {
+        let chunk_size = chunk_size.max(1);
+        let mut file = BufReader::new(File::open(path)?);
+        let data_size = file.get_ref().metadata()?.len();
+
+        let mut hashes = Vec::new();
+        for chunk in 0..data_size.div_ceil(chunk_size) {
+            let len = (data_size - chunk * chunk_size).min(chunk_size);
+            let mut hasher = blake3::Hasher::new();
+            io::copy(&mut (&mut file).take(len), &mut hasher)?;
+            hashes.push(truncated_hash(&hasher));
+        }
+
+        Ok(Self { chunk_size, data_size, hashes })
+    }
+
+    /// Returns the byte range of the chunk in the data file.
+    pub fn range(&self, chunk: usize) -> Range<u64> {
+        let start = chunk as u64 * self.chunk_size;
+        start..(start + self.chunk_size).min(self.data_size)
+    }
+}
+
+/// Manifest of sealed static files.
+///
+/// Only sealed static files are listed: the static file holding the highest block of a segment
+/// can still be appended to, so it's not immutable.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticFileManifest {
+    /// Naming scheme of the listed static files.
+    pub naming: NamingScheme,
+    /// Listed static files, sorted by segment and block range.
+    pub files: Vec<ManifestEntry>,
+    /// Version and build of the producer of the manifest, if recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildMetadata>,
+}
+
+impl StaticFileManifest {
+    /// Creates a new [`StaticFileManifest`] of the sealed static files among `entries`, hashing
+    /// their content if the naming scheme is [`NamingScheme::ContentAddressed`].
+    pub fn new(
+        entries: &[StaticFileEntry],
+        highest_static_files: HighestStaticFiles,
+        naming: NamingScheme,
+    ) -> io::Result<Self> {
+        let mut files = Vec::new();
+        for entry in entries {
+            if !entry.is_sealed(&highest_static_files) {
+                continue
+            }
+
+            let content_hash = match naming {
+                NamingScheme::Plain => None,
+                NamingScheme::ContentAddressed => Some(content_hash(entry)?),
+            };
+            let file_name = match &content_hash {
+                Some(content_hash) => {
+                    entry.segment.filename_with_content_hash(&entry.block_range, content_hash)
+                }
+                None => entry.segment.filename(&entry.block_range),
+            };
+            let mut size = 0;
+            for path in entry.paths() {
+                size += path.metadata()?.len();
+            }
+            // Configurations that aren't of a static file reference no shared dictionaries
+            let dictionaries = match dictionary_refs(entry) {
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => Vec::new(),
+                dictionaries => dictionaries?,
+            };
+
+            files.push(ManifestEntry {
+                segment: entry.segment,
+                block_range: entry.block_range,
+                file_name,
+                content_hash,
+                size,
+                chunks: None,
+                verification: None,
+                row_root: None,
+                dictionaries,
+            });
+        }
+
+        Ok(Self { naming, files, build: Some(build_metadata()) })
+    }
+
+    /// Creates a new [`StaticFileManifest`] of the sealed static files in the directory, scanned
+    /// with [`scan_static_files`] and the policy.
+    ///
+    /// With [`ScanPolicy::Strict`], no manifest is built of a directory with unknown files or
+    /// conflicting static files.
+    pub fn from_directory(
+        directory: &Path,
+        highest_static_files: HighestStaticFiles,
+        naming: NamingScheme,
+        policy: ScanPolicy,
+    ) -> Result<Self, DirectoryScanError> {
+        let entries = scan_static_files(directory, policy)?.entries;
+        Ok(Self::new(&entries, highest_static_files, naming)?)
+    }
+
+    /// Records the [`ChunkHashes`] of the listed data files, so downloads can be verified and
+    /// resumed chunk by chunk.
+    pub fn with_chunk_hashes(
+        mut self,
+        entries: &[StaticFileEntry],
+        chunk_size: u64,
+    ) -> io::Result<Self> {
+        for file in &mut self.files {
+            let Some(entry) = find_entry(entries, file) else { continue };
+            file.chunks = Some(ChunkHashes::new(&entry.path, chunk_size)?);
+        }
+        Ok(self)
+    }
+
+    /// Records the Merkle roots over the rows of the listed static files, so their rows can be
+    /// served with inclusion proofs against the manifest, see [`RowCommitment`]. Roots recorded
+    /// in the headers of the static files when they were sealed are reused, the others are
+    /// computed from the rows.
+    pub fn with_row_roots(mut self, entries: &[StaticFileEntry]) -> io::Result<Self> {
+        for file in &mut self.files {
+            let Some(entry) = find_entry(entries, file) else { continue };
+            file.row_root = Some(row_root(entry)?);
+        }
+        Ok(self)
+    }
+
+    /// Verifies the listed static files, decoding the rows of the [`VerificationLevel`], and
+    /// records the level in their entries. Fails with [`ManifestError::Corrupt`] at the first
+    /// corrupt static file.
+    pub fn with_verification(
+        mut self,
+        entries: &[StaticFileEntry],
+        level: VerificationLevel,
+    ) -> Result<Self, ManifestError> {
+        for file in &mut self.files {
+            let Some(entry) = find_entry(entries, file) else { continue };
+            if let Some(corruption) = diagnose_with_level(entry, None, level)? {
+                return Err(ManifestError::Corrupt { file_name: file.file_name.clone(), corruption })
+            }
+            file.verification = Some(level);
+        }
+        Ok(self)
+    }
+
+    /// Returns the number of listed static files referencing every shared dictionary.
+    pub fn dictionary_refs(&self) -> BTreeMap<B64, usize> {
+        let mut references = BTreeMap::new();
+        for id in self.files.iter().flat_map(|file| &file.dictionaries) {
+            *references.entry(*id).or_default() += 1;
+        }
+        references
+    }
+
+    /// Copies the listed static files from the static files directory into `destination`, under
+    /// their manifest names. Shared dictionaries they reference are copied once, into the
+    /// [`DictionaryStore`] of `destination`.
+    pub fn publish(&self, entries: &[StaticFileEntry], destination: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(destination)?;
+
+        for file in &self.files {
+            let Some(entry) = find_entry(entries, file) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("static file {} not found", file.file_name),
+                ))
+            };
+
+            std::fs::copy(&entry.path, destination.join(&file.file_name))?;
+            for extension in COMPANION_EXTENSIONS {
+                let path = entry.companion_path(extension);
+                if path.exists() {
+                    std::fs::copy(
+                        path,
+                        destination.join(format!("{}.{extension}", file.file_name)),
+                    )?;
+                }
+            }
+        }
+
+        // Shared dictionaries are stored next to the listed static files
+        if let Some(static_files_dir) = entries.first().and_then(|entry| entry.path.parent()) {
+            let store = DictionaryStore::new(static_files_dir);
+            let published = DictionaryStore::new(destination);
+            for id in self.dictionary_refs().keys() {
+                let dictionary = store.get(id).map_err(|err| {
+                    io::Error::new(
+                        err.kind(),
+                        format!("dictionary {}: {err}", dictionary_file_name(id)),
+                    )
+                })?;
+                published.put(&dictionary)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the manifest from a JSON file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes the manifest to a JSON file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_json(self, path)
+    }
+
+    /// Signs the manifest with the ed25519 key of the publisher.
+    pub fn sign(self, signing_key: &SigningKey) -> SignedManifest {
+        let signature = signing_key.sign(&self.signing_payload());
+        SignedManifest {
+            manifest: self,
+            public_key: signing_key.verifying_key().to_bytes().into(),
+            signature: signature.to_bytes().into(),
+        }
+    }
+
+    /// Returns the blake3 hash of the compact JSON encoding of the manifest, to pin an unsigned
+    /// manifest with [`TrustedManifest::Pinned`] once it's obtained through a trusted channel.
+    pub fn root(&self) -> B256 {
+        B256::from(*blake3::hash(&self.signing_payload()).as_bytes())
+    }
+
+    /// Returns the bytes covered by the signature: the compact JSON encoding of the manifest.
+    fn signing_payload(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("manifest serialization is infallible")
+    }
+}
+
+/// [`StaticFileManifest`] fetched from a mirror, with what it has to be trusted by before any of
+/// the static files it lists is downloaded or imported.
+#[derive(Debug, Clone, Copy)]
+pub enum TrustedManifest<'a> {
+    /// Manifest signed by one of the keys of trusted publishers.
+    Signed {
+        /// Signed manifest.
+        manifest: &'a SignedManifest,
+        /// Keys of the trusted publishers.
+        trusted_keys: &'a [VerifyingKey],
+    },
+    /// Manifest pinned to its [`StaticFileManifest::root`], obtained from the producer through a
+    /// trusted channel.
+    Pinned {
+        /// Pinned manifest.
+        manifest: &'a StaticFileManifest,
+        /// Trusted root of the manifest.
+        root: B256,
+    },
+}
+
+impl<'a> TrustedManifest<'a> {
+    /// Verifies the signature or the root of the manifest, returning it if it's trusted.
+    pub fn verify(&self) -> Result<&'a StaticFileManifest, ManifestError> {
+        match *self {
+            Self::Signed { manifest, trusted_keys } => manifest.verify(trusted_keys),
+            Self::Pinned { manifest, root } => {
+                let actual = manifest.root();
+                if actual != root {
+                    return Err(ManifestError::RootMismatch { expected: root, actual })
+                }
+                Ok(manifest)
+            }
+        }
+    }
+}
+
+/// [`StaticFileManifest`] signed with the ed25519 key of its publisher.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedManifest {
+    /// Signed manifest.
+    pub manifest: StaticFileManifest,
+    /// Public key of the publisher.
+    pub public_key: B256,
+    /// Signature of the manifest.
+    pub signature: B512,
+}
+
+impl SignedManifest {
+    /// Verifies that the manifest was signed by one of the trusted keys, returning it if so.
+    pub fn verify(
+        &self,
+        trusted_keys: &[VerifyingKey],
+    ) -> Result<&StaticFileManifest, ManifestError> {
+        let Some(key) = trusted_keys.iter().find(|key| key.as_bytes() == &self.public_key.0) else {
+            return Err(ManifestError::UntrustedKey(self.public_key))
+        };
+
+        let signature = Signature::from_bytes(&self.signature.0);
+        key.verify_strict(&self.manifest.signing_payload(), &signature)
+            .map_err(|_| ManifestError::InvalidSignature)?;
+        Ok(&self.manifest)
+    }
+
+    /// Reads the signed manifest from a JSON file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes the signed manifest to a JSON file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_json(self, path)
+    }
+}
+
+/// Error returned when verifying or importing a [`SignedManifest`].
+#[derive(Debug)]
+pub enum ManifestError {
+    /// Filesystem error.
+    Io(io::Error),
+    /// The manifest was signed with a key that's not trusted.
+    UntrustedKey(B256),
+    /// The signature doesn't match the manifest.
+    InvalidSignature,
+    /// Content of a downloaded static file doesn't match the manifest.
+    ContentMismatch {
+        /// Name of the static file.
+        file_name: String,
+    },
+    /// Static files of the manifest were produced with a schema version this build can't decode.
+    IncompatibleSchema(IncompatibleSchemaVersion),
+    /// A file name listed in the manifest isn't a plain file name of the static file it belongs
+    /// to.
+    InvalidFileName {
+        /// Listed file name.
+        file_name: String,
+    },
+    /// The manifest doesn't hash to the trusted root.
+    RootMismatch {
+        /// Trusted root.
+        expected: B256,
+        /// Root of the manifest.
+        actual: B256,
+    },
+    /// A static file failed verification before being listed.
+    Corrupt {
+        /// Name of the static file.
+        file_name: String,
+        /// Corruption found by the verification.
+        corruption: Corruption,
+    },
+}
+
+impl From<io::Error> for ManifestError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::UntrustedKey(key) => write!(f, "manifest is signed with untrusted key {key}"),
+            Self::InvalidSignature => write!(f, "invalid manifest signature"),
+            Self::ContentMismatch { file_name } => {
+                write!(f, "static file {file_name} doesn't match the manifest")
+            }
+            Self::IncompatibleSchema(err) => fmt::Display::fmt(err, f),
+            Self::InvalidFileName { file_name } => {
+                write!(f, "invalid static file name {file_name:?} in the manifest")
+            }
+            Self::RootMismatch { expected, actual } => {
+                write!(f, "manifest root {actual} doesn't match the trusted root {expected}")
+            }
+            Self::Corrupt { file_name, corruption } => {
+                write!(f, "static file {file_name} is corrupt: {corruption:?}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ManifestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::IncompatibleSchema(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the value to a pretty-printed JSON file.
+pub(crate) fn write_json(value: &impl Serialize, path: &Path) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()
+}
+
+/// Returns the truncated blake3 hash of the data file followed by its companion files.
+pub fn content_hash(entry: &StaticFileEntry) -> io::Result<B64> {
+    let mut hasher = blake3::Hasher::new();
+    for path in entry.paths() {
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+    }
+
+    Ok(truncated_hash(&hasher))
+}
+
+/// Returns the hash truncated to [`CONTENT_HASH_LEN`] bytes.
+pub(crate) fn truncated_hash(hasher: &blake3::Hasher) -> B64 {
+    let mut hash = [0; CONTENT_HASH_LEN];
+    hash.copy_from_slice(&hasher.finalize().as_bytes()[..CONTENT_HASH_LEN]);
+    hash.into()
+}
+
+/// Returns the static file listed in the manifest.
+fn find_entry<'a>(
+    entries: &'a [StaticFileEntry],
+    file: &ManifestEntry,
+) -> Option<&'a StaticFileEntry> {
+    entries
+        .iter()
+        .find(|entry| entry.segment == file.segment && entry.block_range == file.block_range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_static_files, test_utils::StaticFileTestHarness};
+
+    #[test]
+    fn content_addressed_manifest() {
+        let directory = tempfile::tempdir().unwrap();
+        for (name, content) in [
+            ("static_file_headers_0_499999", b"headers".as_slice()),
+            ("static_file_headers_0_499999.off", b"offsets"),
+            ("static_file_headers_500000_999999", b"appended"),
+        ] {
+            std::fs::write(directory.path().join(name), content).unwrap();
+        }
+        let entries = list_static_files(directory.path()).unwrap();
+        let highest = HighestStaticFiles { headers: Some(600_000), ..Default::default() };
+
+        let manifest =
+            StaticFileManifest::new(&entries, highest, NamingScheme::ContentAddressed).unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        let file = &manifest.files[0];
+        assert_eq!(file.block_range, SegmentRangeInclusive::new(0, 499_999));
+        assert_eq!(file.size, 14);
+        assert_eq!(
+            StaticFileSegment::parse_content_addressed_filename(&file.file_name),
+            Some((StaticFileSegment::Headers, file.block_range, file.content_hash))
+        );
+
+        let published = directory.path().join("published");
+        manifest.publish(&entries, &published).unwrap();
+        assert!(published.join(&file.file_name).exists());
+        assert!(published.join(format!("{}.off", file.file_name)).exists());
+
+        let path = directory.path().join("manifest.json");
+        manifest.write(&path).unwrap();
+        assert_eq!(StaticFileManifest::read(&path).unwrap(), manifest);
+    }
+
+    #[test]
+    fn signed_manifest() {
+        let signing_key = SigningKey::from_bytes(&[1; 32]);
+        let manifest = StaticFileManifest {
+            naming: NamingScheme::Plain,
+            files: vec![ManifestEntry {
+                segment: StaticFileSegment::Receipts,
+                block_range: SegmentRangeInclusive::new(0, 499_999),
+                file_name: "static_file_receipts_0_499999".to_string(),
+                content_hash: None,
+                size: 1024,
+                chunks: None,
+                dictionaries: Vec::new(),
+                verification: None,
+                row_root: None,
+            }],
+            build: None,
+        };
+        let signed = manifest.clone().sign(&signing_key);
+        let trusted = [signing_key.verifying_key()];
+        assert_eq!(signed.verify(&trusted).unwrap(), &manifest);
+
+        // Manifest was tampered with
+        let mut tampered = signed.clone();
+        tampered.manifest.files[0].size += 1;
+        assert!(matches!(tampered.verify(&trusted), Err(ManifestError::InvalidSignature)));
+
+        // Manifest was signed with another key
+        let untrusted = [SigningKey::from_bytes(&[2; 32]).verifying_key()];
+        assert!(matches!(signed.verify(&untrusted), Err(ManifestError::UntrustedKey(_))));
+    }
+
+    #[test]
+    fn verified_manifest() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let entries = list_static_files(harness.static_files_dir.path()).unwrap();
+        // Static files are sealed once blocks after their range are in static files
+        let highest = HighestStaticFiles {
+            headers: Some(500_000),
+            transactions: Some(500_000),
+            receipts: Some(500_000),
+        };
+        let manifest = StaticFileManifest::new(&entries, highest, NamingScheme::Plain).unwrap();
+        assert!(manifest.files.iter().all(|file| file.verification.is_none()));
+
+        let level = VerificationLevel::Full;
+        let verified = manifest.clone().with_verification(&entries, level).unwrap();
+        assert!(verified.files.iter().all(|file| file.verification == Some(level)));
+
+        // Corrupt static files aren't listed as verified
+        std::fs::write(entries[0].companion_path("off"), b"offsets").unwrap();
+        assert!(matches!(
+            manifest.with_verification(&entries, level),
+            Err(ManifestError::Corrupt { .. })
+        ));
+    }
+}
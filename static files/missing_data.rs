@@ -0,0 +1,216 @@
+//! Behavior of segments when data of a block in the middle of their target range is missing from
+//! the database, e.g. when the database was partially pruned.
+//!
+//! By default, the run fails with the error of the missing data. Segments can instead stop right
+//! before the block, so the blocks copied so far are committed, or skip it, if they're custom
+//! segments whose rows don't have to be contiguous. Either way, the block is reported as a
+//! [`DataGap`] in the
+//! [`StaticFileProducerEvent::Finished`](crate::StaticFileProducerEvent::Finished) event and the
+//! [`RunRecord`](crate::RunRecord) of the run.
+
+use alloy_primitives::BlockNumber;
+use reth_static_file_types::StaticFileSegment;
+use serde::{Deserialize, Serialize};
+
+/// Behavior of a segment when the data of a block of its target range is missing from the
+/// database, see
+/// [`StaticFileProducerInner::set_missing_data_policy`](crate::StaticFileProducerInner::set_missing_data_policy).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingDataPolicy {
+    /// The run fails with the error of the missing data.
+    #[default]
+    Fail,
+    /// The segment stops before the block. The blocks copied so far are committed, and the
+    /// produced target of the segment ends right before the block.
+    StopAtGap,
+    /// The block is skipped, and the segment continues with the next one.
+    ///
+    /// Only custom segments set with
+    /// [`StaticFileProducerInner::set_custom_segments`](crate::StaticFileProducerInner::set_custom_segments)
+    /// can skip blocks, if their rows don't have to be contiguous. The built-in segments stop at
+    /// the gap instead, as a skipped block would leave a hole in the rows of their static files.
+    SkipMissing,
+}
+
+/// Block of a segment whose data was missing from the database during a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataGap {
+    /// Segment the data was missing for.
+    pub segment: StaticFileSegment,
+    /// Block whose data was missing.
+    pub block: BlockNumber,
+    /// Behavior applied to the block, either [`MissingDataPolicy::StopAtGap`] or
+    /// [`MissingDataPolicy::SkipMissing`].
+    pub policy: MissingDataPolicy,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        segments::{self, Segment, SegmentProvider},
+        test_utils::StaticFileTestHarness,
+        CopiedRows, OverflowPolicy, SegmentProgress, StaticFileProducerEvent, StaticFileSink,
+        StaticFileTargets,
+    };
+    use reth_provider::StaticFileProviderFactory;
+    use reth_static_file_types::{HighestStaticFiles, SegmentConfig};
+    use reth_storage_errors::provider::{ProviderError, ProviderResult};
+    use std::{ops::RangeInclusive, path::Path, sync::Arc};
+
+    /// Custom receipts segment of chains without transactions, whose blocks can be skipped as they
+    /// have no rows.
+    struct EmptyReceipts;
+
+    impl<P: SegmentProvider> Segment<P> for EmptyReceipts {
+        fn segment(&self) -> StaticFileSegment {
+            StaticFileSegment::Receipts
+        }
+
+        fn copy_to_sink(
+            &self,
+            provider: &P,
+            sink: &mut dyn StaticFileSink,
+            block_range: RangeInclusive<BlockNumber>,
+            progress: &SegmentProgress,
+        ) -> ProviderResult<()> {
+            for block in block_range {
+                if progress.is_cancelled() {
+                    break
+                }
+                if provider.block_body_indices(block)?.is_none() {
+                    let err = ProviderError::BlockBodyIndicesNotFound(block);
+                    if !progress.missing_block(block, err, true)? {
+                        break
+                    }
+                }
+                sink.increment_block(StaticFileSegment::Receipts, block)?;
+                progress.advance(block, CopiedRows::default());
+                sink.commit_if_due(progress)?;
+            }
+            Ok(())
+        }
+
+        fn create_static_file_file(
+            &self,
+            provider: &P,
+            directory: &Path,
+            config: SegmentConfig,
+            block_range: RangeInclusive<BlockNumber>,
+        ) -> ProviderResult<()> {
+            segments::Receipts::default().create_static_file_file(
+                provider,
+                directory,
+                config,
+                block_range,
+            )
+        }
+    }
+
+    #[test]
+    fn missing_data_policies() {
+        let err = || ProviderError::BlockBodyIndicesNotFound(7);
+        let progress = SegmentProgress::new(StaticFileSegment::Receipts);
+        assert!(matches!(
+            progress.missing_block(7, err(), true),
+            Err(ProviderError::BlockBodyIndicesNotFound(7))
+        ));
+        assert!(!progress.stopped_at_gap());
+
+        // Only segments that allow it skip blocks, the others stop at the gap
+        let progress = SegmentProgress::new(StaticFileSegment::Receipts)
+            .with_missing_data(MissingDataPolicy::SkipMissing);
+        assert!(progress.missing_block(7, err(), true).unwrap());
+        assert!(!progress.stopped_at_gap());
+        assert!(!progress.missing_block(8, err(), false).unwrap());
+        assert!(progress.stopped_at_gap());
+        assert_eq!(
+            progress.gaps().iter().map(|gap| (gap.block, gap.policy)).collect::<Vec<_>>(),
+            [(7, MissingDataPolicy::SkipMissing), (8, MissingDataPolicy::StopAtGap)]
+        );
+
+        // Headers and bodies of the blocks after the tip are missing from the database
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        producer.set_run_history(Some(10));
+        producer.set_missing_data_policy(MissingDataPolicy::StopAtGap);
+        let targets = StaticFileTargets::builder(HighestStaticFiles::default())
+            .range(StaticFileSegment::Headers, 0..=5)
+            .range(StaticFileSegment::Transactions, 0..=5)
+            .range(StaticFileSegment::Receipts, 0..=5)
+            .build()
+            .unwrap();
+
+        // What was copied before the gap is committed
+        let produced = producer.run(targets).unwrap();
+        assert_eq!(produced.target(StaticFileSegment::Headers), Some(&(0..=3)));
+        assert_eq!(produced.target(StaticFileSegment::Transactions), Some(&(0..=3)));
+        assert_eq!(produced.target(StaticFileSegment::Receipts), Some(&(0..=3)));
+        assert_eq!(
+            harness.provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+        );
+        let gap = |segment| DataGap { segment, block: 4, policy: MissingDataPolicy::StopAtGap };
+        assert_eq!(
+            producer.run_history().unwrap()[0].gaps,
+            [
+                gap(StaticFileSegment::Headers),
+                gap(StaticFileSegment::Transactions),
+                gap(StaticFileSegment::Receipts)
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_segment_skips_missing_blocks() {
+        // Bodies of the blocks after the tip are missing from the database
+        let harness = StaticFileTestHarness::new(3, 0..1);
+        let mut producer = harness.producer();
+        producer.set_run_history(Some(10));
+        producer.set_missing_data_policy(MissingDataPolicy::SkipMissing);
+        producer.set_custom_segments(vec![Arc::new(EmptyReceipts)]);
+        let events = producer.subscribe_events(8, OverflowPolicy::DropOldest);
+        let targets = StaticFileTargets::builder(HighestStaticFiles::default())
+            .range(StaticFileSegment::Transactions, 0..=5)
+            .range(StaticFileSegment::Receipts, 0..=5)
+            .build()
+            .unwrap();
+
+        // The custom segment skips the blocks, the built-in one stops at the first of them
+        let produced = producer.run(targets).unwrap();
+        assert_eq!(produced.target(StaticFileSegment::Transactions), Some(&(0..=3)));
+        assert_eq!(produced.target(StaticFileSegment::Receipts), Some(&(0..=5)));
+        assert_eq!(
+            harness.provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: None, receipts: Some(5), transactions: Some(3) }
+        );
+
+        // Skipped blocks are reported along with the one stopped at
+        let gaps = [
+            DataGap {
+                segment: StaticFileSegment::Transactions,
+                block: 4,
+                policy: MissingDataPolicy::StopAtGap,
+            },
+            DataGap {
+                segment: StaticFileSegment::Receipts,
+                block: 4,
+                policy: MissingDataPolicy::SkipMissing,
+            },
+            DataGap {
+                segment: StaticFileSegment::Receipts,
+                block: 5,
+                policy: MissingDataPolicy::SkipMissing,
+            },
+        ];
+        assert_eq!(producer.run_history().unwrap()[0].gaps, gaps);
+        let finished = std::iter::from_fn(|| events.try_recv())
+            .find_map(|event| match event {
+                StaticFileProducerEvent::Finished { gaps, .. } => Some(gaps),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(finished, gaps);
+    }
+}
@@ -0,0 +1,1087 @@
+//! Reader of a segment spanning all its fixed-range static files, so block ranges can be read
+//! without handling file boundaries.
+
+use crate::{
+    chd_index::read_chd_index, list_static_files, profiling::FilterOutcome,
+    reader::check_static_file, sidecar::static_files_in_range, DedupTable, ReadProfiler,
+    StaticFileEntry, StaticFileReader, StaticFileReaderError, DEDUP_EXTENSION,
+};
+use alloy_primitives::{BlockHash, BlockNumber, TxNumber, B256, U256};
+use reth_db_api::{models::CompactU256, table::Decompress};
+use reth_nippy_jar::{InclusionFilter as _, NippyJar, NippyJarCursor, PerfectHashingFunction as _};
+use reth_primitives::{Header, Receipt, TransactionSignedNoHash};
+use reth_static_file_types::{
+    find_fixed_range, FilterKey, PerfectHashingFunction, SegmentHeader, SegmentRangeInclusive,
+    StaticFileSegment,
+};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{
+    collections::VecDeque,
+    ops::{Range, RangeInclusive},
+    path::Path,
+};
+
+/// Number of rows decoded at once by [`SegmentRange`].
+const RANGE_BATCH_SIZE: usize = 1_000;
+
+/// Row decoded from a static file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SegmentValue {
+    /// Row of the Headers segment.
+    Header {
+        /// The header.
+        header: Header,
+        /// Total difficulty of the block, or `None` if the static file stores no total
+        /// difficulties.
+        total_difficulty: Option<U256>,
+        /// Canonical hash of the block.
+        hash: BlockHash,
+    },
+    /// Row of the Transactions segment.
+    Transaction(TransactionSignedNoHash),
+    /// Row of the Receipts segment.
+    Receipt(Receipt),
+}
+
+/// Row read by a [`MultiSegmentReader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentRow {
+    /// Fixed block range of the static file holding the row.
+    pub block_range: SegmentRangeInclusive,
+    /// Number of the row: the block number for headers, the transaction number otherwise.
+    pub number: u64,
+    /// The row.
+    pub value: SegmentValue,
+}
+
+/// Reader of a segment spanning all its fixed-range static files, including the static file
+/// that's still appended to.
+///
+/// Rows after the last committed rows of the [`StaticFileReader`] are not read.
+#[derive(Debug, Clone, Copy)]
+pub struct MultiSegmentReader<'a> {
+    /// Reader of the static files.
+    reader: &'a StaticFileReader,
+    /// Segment that's read.
+    segment: StaticFileSegment,
+    /// Whether static files with filters not understood by this build are scanned by
+    /// [`MultiSegmentReader::find_by_hash`].
+    scan_unsupported_filters: bool,
+}
+
+impl<'a> MultiSegmentReader<'a> {
+    /// Creates a new [`MultiSegmentReader`] of the segment.
+    pub const fn new(reader: &'a StaticFileReader, segment: StaticFileSegment) -> Self {
+        Self { reader, segment, scan_unsupported_filters: false }
+    }
+
+    /// Sets whether [`MultiSegmentReader::find_by_hash`] scans all rows of static files whose
+    /// inclusion filter or perfect hashing function is not understood by this build, instead of
+    /// failing with [`StaticFileReaderError::UnsupportedFeature`]. Disabled by default.
+    pub const fn with_scan_unsupported_filters(mut self, enabled: bool) -> Self {
+        self.scan_unsupported_filters = enabled;
+        self
+    }
+
+    /// Returns the segment that's read.
+    pub const fn segment(&self) -> StaticFileSegment {
+        self.segment
+    }
+
+    /// Returns an iterator over the rows of the block range, in row order, across all static
+    /// files overlapping it.
+    ///
+    /// Returns [`StaticFileReaderError::MissingStaticFiles`] with the first gap if some blocks of
+    /// the range are not in static files. Transactions and receipts aren't keyed by block in
+    /// static files, so for these segments all rows of the overlapping static files are read.
+    pub fn range(
+        &self,
+        blocks: RangeInclusive<BlockNumber>,
+    ) -> Result<SegmentRange<'a>, StaticFileReaderError> {
+        let entries = self.entries(&blocks)?;
+        Ok(self.iter(RowSelection::Blocks(blocks), entries, false))
+    }
+
+    /// Returns an iterator over the rows of the block range in descending row order, e.g. for
+    /// views of the latest blocks. Missing blocks are reported as by
+    /// [`MultiSegmentReader::range`].
+    pub fn iter_blocks_rev(
+        &self,
+        blocks: RangeInclusive<BlockNumber>,
+    ) -> Result<SegmentRange<'a>, StaticFileReaderError> {
+        let entries = self.entries(&blocks)?;
+        Ok(self.iter(RowSelection::Blocks(blocks), entries, true))
+    }
+
+    /// Returns an iterator over the rows of the transaction range in descending transaction
+    /// order, e.g. for views of the latest transactions.
+    ///
+    /// Static files are opened from the highest one down, until the start of the range is
+    /// reached. Transactions after the highest static file are not returned, and the Headers
+    /// segment has no transaction rows.
+    pub fn iter_txs_rev(
+        &self,
+        txs: RangeInclusive<TxNumber>,
+    ) -> Result<SegmentRange<'a>, StaticFileReaderError> {
+        let mut entries = Vec::new();
+        if !self.segment.is_headers() {
+            entries = list_static_files(self.reader.provider().directory())?;
+            entries.retain(|entry| entry.segment == self.segment);
+        }
+        Ok(self.iter(RowSelection::Transactions(txs), entries, true))
+    }
+
+    /// Returns the rows with the numbers, in the order of `rows`: block numbers for headers,
+    /// transaction numbers otherwise. Rows that are not in static files, or not committed yet,
+    /// are returned as `None`.
+    ///
+    /// Row numbers are sorted and grouped by static file, so every static file is opened once and
+    /// its rows are decoded in one ascending pass, instead of a lookup per row.
+    pub fn get_many(&self, rows: &[u64]) -> Result<Vec<Option<SegmentRow>>, StaticFileReaderError> {
+        let mut order = (0..rows.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&i| rows[i]);
+        let mut results = vec![None; rows.len()];
+
+        let mut pending = order.as_slice();
+        for entry in list_static_files(self.reader.provider().directory())? {
+            let Some(&next) = pending.first() else { break };
+            if entry.segment != self.segment ||
+                (self.segment.is_headers() && entry.block_range.end() < rows[next])
+            {
+                continue
+            }
+
+            let jar = load_jar(&entry.path)?;
+            let Some(offset) = row_offset(self.segment, jar.user_header()) else { continue };
+            let end =
+                (offset + jar.rows() as u64).min(self.reader.committed_range(self.segment, ..).end);
+
+            // Rows below the static file are not in static files
+            pending = &pending[pending.partition_point(|&i| rows[i] < offset)..];
+            let (in_file, rest) = pending.split_at(pending.partition_point(|&i| rows[i] < end));
+            pending = rest;
+
+            let mut unique = in_file.to_vec();
+            unique.dedup_by_key(|i| rows[*i]);
+            let dedup = read_dedup(self.segment, &entry.path)?;
+            let read = read_rows(
+                &jar,
+                self.segment,
+                entry.block_range,
+                offset,
+                unique.iter().map(|&i| (rows[i] - offset) as usize),
+                &dedup,
+            )?;
+            for row in read {
+                for &i in &in_file[in_file.partition_point(|&i| rows[i] < row.number)..] {
+                    if rows[i] != row.number {
+                        break
+                    }
+                    results[i] = Some(row.clone());
+                }
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Returns the row of the block range keyed by the hash: the canonical hash for headers, the
+    /// transaction hash otherwise.
+    ///
+    /// Every static file is looked up with its own inclusion filter, perfect hashing function and
+    /// [`FilterHash`](reth_static_file_types::FilterHash), and scanned if it has no filters.
+    /// Receipts are found through the Transactions static file of the same block range, unless
+    /// their static file is keyed by
+    /// [`ReceiptKeyMode::TxHash`](reth_static_file_types::ReceiptKeyMode::TxHash).
+    ///
+    /// Fails with [`StaticFileReaderError::UnsupportedFeature`] at the first static file whose
+    /// filters are not understood by this build, unless
+    /// [scanning them](MultiSegmentReader::with_scan_unsupported_filters) is enabled.
+    pub fn find_by_hash(
+        &self,
+        blocks: RangeInclusive<BlockNumber>,
+        hash: B256,
+    ) -> Result<Option<SegmentRow>, StaticFileReaderError> {
+        let directory = self.reader.provider().directory();
+        let profiler = self.reader.active_profiler();
+        let filter_key = self.reader.filter_key();
+        for entry in self.entries(&blocks)? {
+            let jar = load_jar(&entry.path)?;
+            let transactions = StaticFileEntry {
+                segment: StaticFileSegment::Transactions,
+                path: directory.join(StaticFileSegment::Transactions.filename(&entry.block_range)),
+                ..entry.clone()
+            };
+            // Hashes of deduplicated transactions are only known once they're reassembled
+            let dedup = if self.segment.is_headers() {
+                DedupTable::default()
+            } else {
+                read_dedup(StaticFileSegment::Transactions, &transactions.path)?
+            };
+            let found = match self.segment {
+                StaticFileSegment::Headers | StaticFileSegment::Transactions => find_row(
+                    &jar,
+                    self.segment,
+                    hash,
+                    &dedup,
+                    filter_key,
+                    self.scan_unsupported_filters,
+                    profiler,
+                )?,
+                StaticFileSegment::Receipts => find_receipt_row(
+                    &jar,
+                    &transactions,
+                    hash,
+                    &dedup,
+                    filter_key,
+                    self.scan_unsupported_filters,
+                    profiler,
+                )?,
+            };
+            let Some(row) = found else { continue };
+
+            let Some(offset) = row_offset(self.segment, jar.user_header()) else { continue };
+            if !self.reader.is_committed(self.segment, offset + row as u64) {
+                continue
+            }
+            let mut rows =
+                read_rows(&jar, self.segment, entry.block_range, offset, row..row + 1, &dedup)?;
+            return Ok(rows.pop_front())
+        }
+        Ok(None)
+    }
+
+    /// Returns the transaction with the hash from the Transactions static files with a
+    /// [`DedupTable`], whose deduplicated rows the
+    /// [`StaticFileProvider`](reth_provider::providers::StaticFileProvider) can't find by hash.
+    /// Returns `None` if no such static file has the transaction, including if its filters are
+    /// not understood by this build.
+    pub(crate) fn find_deduplicated_transaction(
+        &self,
+        hash: B256,
+    ) -> Result<Option<SegmentRow>, StaticFileReaderError> {
+        let profiler = self.reader.active_profiler();
+        for entry in list_static_files(self.reader.provider().directory())? {
+            if entry.segment != StaticFileSegment::Transactions ||
+                !entry.companion_path(DEDUP_EXTENSION).exists()
+            {
+                continue
+            }
+            let jar = load_jar(&entry.path)?;
+            if jar.user_header().check_filters().is_err() {
+                continue
+            }
+
+            let dedup = read_dedup(entry.segment, &entry.path)?;
+            let filter_key = self.reader.filter_key();
+            let Some(row) =
+                find_row(&jar, entry.segment, hash, &dedup, filter_key, false, profiler)?
+            else {
+                continue
+            };
+
+            let Some(offset) = jar.user_header().tx_start() else { continue };
+            if !self.reader.is_committed(entry.segment, offset + row as u64) {
+                continue
+            }
+            let mut rows =
+                read_rows(&jar, entry.segment, entry.block_range, offset, row..row + 1, &dedup)?;
+            return Ok(rows.pop_front())
+        }
+        Ok(None)
+    }
+
+    /// Returns the receipt of the transaction with the hash from the Receipts static files keyed
+    /// by [`ReceiptKeyMode::TxHash`](reth_static_file_types::ReceiptKeyMode::TxHash), without
+    /// the transaction hash index of the database. Returns `None` if no such static file has
+    /// the receipt, including if its filters are not understood by this build.
+    pub(crate) fn find_keyed_receipt(
+        &self,
+        hash: B256,
+    ) -> Result<Option<SegmentRow>, StaticFileReaderError> {
+        let directory = self.reader.provider().directory();
+        let profiler = self.reader.active_profiler();
+        for entry in list_static_files(directory)? {
+            if entry.segment != StaticFileSegment::Receipts {
+                continue
+            }
+            let jar = load_jar(&entry.path)?;
+            let header = jar.user_header();
+            if !header.receipt_key_mode().is_tx_hash() || header.check_filters().is_err() {
+                continue
+            }
+
+            let transactions = StaticFileEntry {
+                segment: StaticFileSegment::Transactions,
+                path: directory.join(StaticFileSegment::Transactions.filename(&entry.block_range)),
+                ..entry.clone()
+            };
+            let dedup = read_dedup(StaticFileSegment::Transactions, &transactions.path)?;
+            let filter_key = self.reader.filter_key();
+            let Some(row) =
+                find_receipt_row(&jar, &transactions, hash, &dedup, filter_key, false, profiler)?
+            else {
+                continue
+            };
+
+            let Some(offset) = header.tx_start() else { continue };
+            if !self.reader.is_committed(StaticFileSegment::Receipts, offset + row as u64) {
+                continue
+            }
+            let mut rows = read_rows(
+                &jar,
+                StaticFileSegment::Receipts,
+                entry.block_range,
+                offset,
+                row..row + 1,
+                &dedup,
+            )?;
+            return Ok(rows.pop_front())
+        }
+        Ok(None)
+    }
+
+    /// Returns an iterator over the selected rows of the static files.
+    fn iter(
+        &self,
+        selection: RowSelection,
+        entries: Vec<StaticFileEntry>,
+        reverse: bool,
+    ) -> SegmentRange<'a> {
+        SegmentRange {
+            reader: self.reader,
+            segment: self.segment,
+            selection,
+            reverse,
+            entries: entries.into_iter(),
+            current: None,
+            dedup: DedupTable::default(),
+            buffered: VecDeque::new(),
+        }
+    }
+
+    /// Returns the static files overlapping the block range, checking that none of its blocks is
+    /// missing.
+    fn entries(
+        &self,
+        blocks: &RangeInclusive<BlockNumber>,
+    ) -> Result<Vec<StaticFileEntry>, StaticFileReaderError> {
+        let entries =
+            static_files_in_range(self.reader.provider().directory(), self.segment, blocks);
+
+        // First block of the range that's not found in static files yet
+        let mut next = *blocks.start();
+        for entry in &entries {
+            if !entry.path.exists() {
+                continue
+            }
+            if entry.block_range.start() > next {
+                return Err(self.gap(next, entry.block_range.start() - 1))
+            }
+
+            let jar = load_jar(&entry.path)?;
+            let mut end = jar.user_header().block_end();
+            if self.segment.is_headers() {
+                // Headers after the last committed row are not read
+                let committed = self.reader.committed_range(self.segment, ..).end;
+                end = end.and_then(|end| Some(end.min(committed.checked_sub(1)?)));
+            }
+            if let Some(end) = end {
+                next = next.max(end.saturating_add(1));
+            }
+        }
+        if next <= *blocks.end() {
+            return Err(self.gap(next, *blocks.end()))
+        }
+
+        Ok(entries)
+    }
+
+    /// Returns the error of the blocks missing in static files.
+    fn gap(&self, start: BlockNumber, end: BlockNumber) -> StaticFileReaderError {
+        StaticFileReaderError::MissingStaticFiles {
+            segment: self.segment,
+            gap: SegmentRangeInclusive::new(start, end),
+        }
+    }
+}
+
+/// Rows read by a [`SegmentRange`].
+#[derive(Debug, Clone)]
+enum RowSelection {
+    /// Rows of the block range. Transactions and receipts are read by whole static files.
+    Blocks(RangeInclusive<BlockNumber>),
+    /// Rows of the transaction range.
+    Transactions(RangeInclusive<TxNumber>),
+}
+
+/// Iterator over rows across static files, returned by [`MultiSegmentReader::range`],
+/// [`MultiSegmentReader::iter_blocks_rev`] and [`MultiSegmentReader::iter_txs_rev`].
+///
+/// Static files are opened one at a time, and their rows are decoded in batches. Batches of
+/// descending iterators are still read in ascending row order, and only returned reversed.
+#[derive(Debug)]
+pub struct SegmentRange<'a> {
+    /// Reader of the static files.
+    reader: &'a StaticFileReader,
+    /// Segment that's read.
+    segment: StaticFileSegment,
+    /// Rows that are read.
+    selection: RowSelection,
+    /// Whether rows are returned in descending order.
+    reverse: bool,
+    /// Static files that aren't opened yet.
+    entries: std::vec::IntoIter<StaticFileEntry>,
+    /// Open static file with the first row number of the file and its rows that aren't decoded
+    /// yet.
+    current: Option<(StaticFileEntry, NippyJar<SegmentHeader>, u64, Range<usize>)>,
+    /// Dedup table of the open Transactions static file.
+    dedup: DedupTable,
+    /// Decoded rows that aren't returned yet.
+    buffered: VecDeque<SegmentRow>,
+}
+
+impl SegmentRange<'_> {
+    /// Opens the next static file with selected rows, or returns `false` if there's none left.
+    fn open_next(&mut self) -> Result<bool, StaticFileReaderError> {
+        let (first, last) = match &self.selection {
+            RowSelection::Blocks(blocks) if self.segment.is_headers() => {
+                (*blocks.start(), *blocks.end())
+            }
+            RowSelection::Blocks(_) => (0, u64::MAX),
+            RowSelection::Transactions(txs) => (*txs.start(), *txs.end()),
+        };
+
+        while let Some(entry) =
+            if self.reverse { self.entries.next_back() } else { self.entries.next() }
+        {
+            let jar = load_jar(&entry.path)?;
+            let Some(offset) = row_offset(self.segment, jar.user_header()) else { continue };
+
+            let end = last
+                .saturating_add(1)
+                .min(offset + jar.rows() as u64)
+                .min(self.reader.committed_range(self.segment, ..).end);
+            let start = first.max(offset);
+            if start >= end {
+                if self.reverse && end <= first {
+                    // Static files below hold even lower rows
+                    self.entries = Vec::new().into_iter();
+                }
+                continue
+            }
+
+            self.dedup = read_dedup(self.segment, &entry.path)?;
+            let rows = (start - offset) as usize..(end - offset) as usize;
+            self.current = Some((entry, jar, offset, rows));
+            return Ok(true)
+        }
+        Ok(false)
+    }
+
+    /// Decodes the next batch of rows of the open static file.
+    fn fill(&mut self) -> ProviderResult<()> {
+        let Some((entry, jar, offset, rows)) = &mut self.current else { return Ok(()) };
+        let batch = if self.reverse {
+            let batch = rows.end.saturating_sub(RANGE_BATCH_SIZE).max(rows.start)..rows.end;
+            rows.end = batch.start;
+            batch
+        } else {
+            let batch = rows.start..rows.end.min(rows.start + RANGE_BATCH_SIZE);
+            rows.start = batch.end;
+            batch
+        };
+
+        self.buffered =
+            read_rows(jar, self.segment, entry.block_range, *offset, batch, &self.dedup)?;
+        if self.reverse {
+            self.buffered.make_contiguous().reverse();
+        }
+        if rows.is_empty() {
+            self.current = None;
+        }
+        Ok(())
+    }
+
+    /// Stops the iteration after a failed read, so no rows after it are returned.
+    fn stop(&mut self, err: impl Into<StaticFileReaderError>) -> StaticFileReaderError {
+        self.current = None;
+        self.entries = Vec::new().into_iter();
+        err.into()
+    }
+}
+
+impl Iterator for SegmentRange<'_> {
+    type Item = Result<SegmentRow, StaticFileReaderError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.buffered.pop_front() {
+                return Some(Ok(row))
+            }
+
+            if self.current.is_none() {
+                match self.open_next() {
+                    Ok(true) => {}
+                    Ok(false) => return None,
+                    Err(err) => return Some(Err(self.stop(err))),
+                }
+            }
+            if let Err(err) = self.fill() {
+                return Some(Err(self.stop(err)))
+            }
+        }
+    }
+}
+
+/// Loads the static file at the path, checking its schema version and columns with
+/// [`check_static_file`].
+fn load_jar(path: &Path) -> Result<NippyJar<SegmentHeader>, StaticFileReaderError> {
+    let jar = NippyJar::<SegmentHeader>::load(path)
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    check_static_file(jar.user_header(), jar.columns())?;
+    Ok(jar)
+}
+
+/// Reads the dedup table of the static file with the data file, if it's of the Transactions
+/// segment. Other segments get an empty table.
+fn read_dedup(segment: StaticFileSegment, data_path: &Path) -> ProviderResult<DedupTable> {
+    if segment != StaticFileSegment::Transactions {
+        return Ok(DedupTable::default())
+    }
+    DedupTable::read(data_path).map_err(|err| ProviderError::NippyJar(err.to_string()))
+}
+
+/// Returns the number of the first row of the static file, or `None` if it has no rows.
+fn row_offset(segment: StaticFileSegment, header: &SegmentHeader) -> Option<u64> {
+    if segment.is_headers() {
+        header.block_start()
+    } else {
+        header.tx_start()
+    }
+}
+
+/// Returns the row of the Headers or Transactions static file keyed by the hash.
+///
+/// Rows returned by the perfect hashing function are checked against the hash, as it maps any key
+/// to some row, and transactions are reassembled with the dedup table of the static file first.
+/// Static files whose filters are not understood by this build are scanned if
+/// `scan_unsupported_filters` is set, as are those whose keys are hashed with a missing
+/// `filter_key`. Outcomes of the filters are recorded in the profiler, if any.
+fn find_row(
+    jar: &NippyJar<SegmentHeader>,
+    segment: StaticFileSegment,
+    hash: B256,
+    dedup: &DedupTable,
+    filter_key: Option<&FilterKey>,
+    scan_unsupported_filters: bool,
+    profiler: Option<&ReadProfiler>,
+) -> Result<Option<usize>, StaticFileReaderError> {
+    let mut cursor =
+        NippyJarCursor::new(jar).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    let mut row_hash = |row: usize| row_hash(jar, &mut cursor, segment, row, dedup);
+
+    let unsupported = jar.user_header().check_filters().err();
+    if let Some(err) = unsupported.filter(|_| !scan_unsupported_filters) {
+        return Err(StaticFileReaderError::UnsupportedFeature {
+            segment,
+            block_range: find_fixed_range(jar.user_header().expected_block_start()),
+            err,
+        })
+    }
+
+    // Keys of the filters were hashed with the function recorded in the static file
+    let key = jar.user_header().filter_key(hash.as_slice(), filter_key);
+    let key = match key {
+        Some(key) if jar.user_header().filter_fpp().is_some() && unsupported.is_none() => key,
+        _ => {
+            for row in 0..jar.rows() {
+                if row_hash(row)? == Some(hash) {
+                    return Ok(Some(row))
+                }
+            }
+            return Ok(None)
+        }
+    };
+    let (found, outcome) =
+        if jar.contains(&key).map_err(|err| ProviderError::NippyJar(err.to_string()))? {
+            let found = match phf_row(jar, &key)? {
+                Some(row) => (row_hash(row as usize)? == Some(hash)).then_some(row as usize),
+                None => None,
+            };
+            let outcome = if found.is_some() {
+                FilterOutcome::TruePositive
+            } else {
+                FilterOutcome::FalsePositive
+            };
+            (found, outcome)
+        } else {
+            (None, FilterOutcome::Negative)
+        };
+
+    if let Some(profiler) = profiler {
+        let block_range = find_fixed_range(jar.user_header().expected_block_start());
+        profiler.record_filter(segment, block_range, outcome);
+    }
+    Ok(found)
+}
+
+/// Returns the row of the Receipts static file keyed by the transaction hash.
+///
+/// Static files with [`ReceiptKeyMode::TxHash`](reth_static_file_types::ReceiptKeyMode::TxHash)
+/// are looked up with their own filters, and the row
+/// is checked against the Transactions static file of the same block range if it's present.
+/// Without it, the row is trusted up to the false positive rate of the inclusion filter. Other
+/// static files, those whose filters are scanned and those whose keys are hashed with a missing
+/// `filter_key` are looked up through the Transactions static file.
+fn find_receipt_row(
+    jar: &NippyJar<SegmentHeader>,
+    transactions: &StaticFileEntry,
+    hash: B256,
+    dedup: &DedupTable,
+    filter_key: Option<&FilterKey>,
+    scan_unsupported_filters: bool,
+    profiler: Option<&ReadProfiler>,
+) -> Result<Option<usize>, StaticFileReaderError> {
+    let header = jar.user_header();
+    let unsupported = header.check_filters().err();
+    let key = header.filter_key(hash.as_slice(), filter_key);
+    let key = match key {
+        Some(key)
+            if header.receipt_key_mode().is_tx_hash() &&
+                !(unsupported.is_some() && scan_unsupported_filters) =>
+        {
+            key
+        }
+        _ => {
+            return find_row(
+                &load_jar(&transactions.path)?,
+                StaticFileSegment::Transactions,
+                hash,
+                dedup,
+                filter_key,
+                scan_unsupported_filters,
+                profiler,
+            )
+        }
+    };
+    if let Some(err) = unsupported {
+        return Err(StaticFileReaderError::UnsupportedFeature {
+            segment: StaticFileSegment::Receipts,
+            block_range: find_fixed_range(header.expected_block_start()),
+            err,
+        })
+    }
+
+    let row = if jar.contains(&key).map_err(|err| ProviderError::NippyJar(err.to_string()))? {
+        phf_row(jar, &key)?
+    } else {
+        None
+    };
+    let found = match row.map(|row| row as usize) {
+        Some(row) if transactions.path.exists() => {
+            let transactions = load_jar(&transactions.path)?;
+            let mut cursor = NippyJarCursor::new(&transactions)
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+            let row_hash =
+                row_hash(&transactions, &mut cursor, StaticFileSegment::Transactions, row, dedup)?;
+            (row_hash == Some(hash)).then_some(row)
+        }
+        row => row.filter(|row| *row < jar.rows()),
+    };
+
+    if let Some(profiler) = profiler {
+        let outcome = match (row, found) {
+            (None, _) => FilterOutcome::Negative,
+            (Some(_), Some(_)) => FilterOutcome::TruePositive,
+            (Some(_), None) => FilterOutcome::FalsePositive,
+        };
+        let block_range = find_fixed_range(header.expected_block_start());
+        profiler.record_filter(StaticFileSegment::Receipts, block_range, outcome);
+    }
+    Ok(found)
+}
+
+/// Returns the row the perfect hashing function of the static file maps the key to. Static files
+/// indexed with [`PerfectHashingFunction::Chd`] are looked up in their sidecar, the others with
+/// the function of the `NippyJar`.
+fn phf_row(jar: &NippyJar<SegmentHeader>, key: &[u8]) -> ProviderResult<Option<u64>> {
+    let phf = jar.user_header().filter_ids().map(|filter_ids| filter_ids.phf);
+    if phf == Some(PerfectHashingFunction::Chd.id()) {
+        let index = read_chd_index(jar.data_path())
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        return Ok(index.row(key))
+    }
+    jar.get_index(key).map_err(|err| ProviderError::NippyJar(err.to_string()))
+}
+
+/// Returns the hash of the row of the Headers or Transactions static file, reassembling
+/// transactions with the dedup table of the static file first.
+fn row_hash(
+    jar: &NippyJar<SegmentHeader>,
+    cursor: &mut NippyJarCursor<'_, SegmentHeader>,
+    segment: StaticFileSegment,
+    row: usize,
+    dedup: &DedupTable,
+) -> ProviderResult<Option<B256>> {
+    let Some(columns) =
+        cursor.row_by_number(row).map_err(|err| ProviderError::NippyJar(err.to_string()))?
+    else {
+        return Ok(None)
+    };
+    Ok(match segment {
+        StaticFileSegment::Headers => columns
+            .get(jar.user_header().headers_layout().block_hash_column())
+            .copied()
+            .map(B256::from_slice),
+        _ => {
+            let Some(mut transaction) =
+                columns.first().and_then(|value| TransactionSignedNoHash::decompress(value).ok())
+            else {
+                return Ok(None)
+            };
+            let tx_number = jar.user_header().tx_start().unwrap_or_default() + row as u64;
+            dedup
+                .reassemble(tx_number, &mut transaction)
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+            Some(transaction.hash())
+        }
+    })
+}
+
+/// Decodes the rows of the static file in the given order, numbering them from `offset`.
+/// Transactions are reassembled with the dedup table of the static file.
+fn read_rows(
+    jar: &NippyJar<SegmentHeader>,
+    segment: StaticFileSegment,
+    block_range: SegmentRangeInclusive,
+    offset: u64,
+    rows: impl Iterator<Item = usize>,
+    dedup: &DedupTable,
+) -> ProviderResult<VecDeque<SegmentRow>> {
+    let mut cursor =
+        NippyJarCursor::new(jar).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    rows.map(|row| {
+        let columns = cursor
+            .row_by_number(row)
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?
+            .ok_or_else(|| ProviderError::NippyJar(format!("row {row} is missing")))?;
+        let mut value = decode_value(segment, jar.user_header(), &columns).ok_or_else(|| {
+            ProviderError::NippyJar(format!("row {row} of {segment} static file can't be decoded"))
+        })?;
+        let number = offset + row as u64;
+        if let SegmentValue::Transaction(transaction) = &mut value {
+            dedup
+                .reassemble(number, transaction)
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        }
+        Ok(SegmentRow { block_range, number, value })
+    })
+    .collect()
+}
+
+/// Decodes the columns of a row of the segment, stored in the static file with the header.
+fn decode_value(
+    segment: StaticFileSegment,
+    segment_header: &SegmentHeader,
+    columns: &[&[u8]],
+) -> Option<SegmentValue> {
+    let value = *columns.first()?;
+    Some(match segment {
+        StaticFileSegment::Headers => {
+            // Without total difficulties, the column is either left empty or not stored at all
+            let total_difficulty = if segment_header.headers_layout().has_total_difficulty() {
+                Some(CompactU256::decompress(columns.get(1)?).ok()?.into())
+            } else {
+                None
+            };
+            SegmentValue::Header {
+                header: Header::decompress(value).ok()?,
+                total_difficulty,
+                hash: B256::from_slice(
+                    columns.get(segment_header.headers_layout().block_hash_column())?,
+                ),
+            }
+        }
+        StaticFileSegment::Transactions => {
+            SegmentValue::Transaction(TransactionSignedNoHash::decompress(value).ok()?)
+        }
+        StaticFileSegment::Receipts => SegmentValue::Receipt(Receipt::decompress(value).ok()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_provider::StaticFileProviderFactory;
+
+    /// Rewrites the extensions of the segment header in the configuration file of the static file,
+    /// as a newer producer would write them.
+    fn rewrite_extensions(path: &Path, rewrite: impl FnOnce(&mut serde_json::Value)) {
+        let config_path = path.with_extension("conf");
+        let mut config = std::fs::read(&config_path).unwrap();
+        let start = config.windows(13).position(|window| window == br#"{"filter_fpp""#).unwrap();
+        let len = u64::from_le_bytes(config[start - 8..start].try_into().unwrap()) as usize;
+        let mut extensions = serde_json::from_slice(&config[start..start + len]).unwrap();
+        rewrite(&mut extensions);
+        let extensions = serde_json::to_vec(&extensions).unwrap();
+        config[start - 8..start].copy_from_slice(&(extensions.len() as u64).to_le_bytes());
+        config.splice(start..start + len, extensions);
+        std::fs::write(config_path, config).unwrap();
+    }
+
+    #[test]
+    fn range_across_static_files() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+
+        let headers = MultiSegmentReader::new(&reader, StaticFileSegment::Headers);
+        let rows = headers.range(1..=3).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows.iter().map(|row| row.number).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(matches!(
+            &rows[1].value,
+            SegmentValue::Header { header, hash, .. }
+                if header == harness.blocks[2].header.header() && *hash == harness.blocks[2].hash()
+        ));
+
+        // Blocks after the highest static file are reported as a gap
+        assert!(matches!(
+            headers.range(2..=600_000),
+            Err(StaticFileReaderError::MissingStaticFiles { gap, .. })
+                if gap == SegmentRangeInclusive::new(4, 600_000)
+        ));
+
+        let tx_count = harness.blocks.iter().map(|block| block.body.len()).sum::<usize>();
+        let transactions = MultiSegmentReader::new(&reader, StaticFileSegment::Transactions);
+        assert_eq!(transactions.range(0..=3).unwrap().count(), tx_count);
+
+        // Rows are found by hash with the filters of their static file
+        let block = &harness.blocks[2];
+        assert_eq!(
+            headers.find_by_hash(0..=3, block.hash()).unwrap().map(|row| row.number),
+            Some(2)
+        );
+        let receipts = MultiSegmentReader::new(&reader, StaticFileSegment::Receipts);
+        let found = receipts.find_by_hash(0..=3, block.body[0].hash()).unwrap().unwrap();
+        assert!(matches!(found.value, SegmentValue::Receipt(_)));
+        assert_eq!(headers.find_by_hash(0..=3, B256::ZERO).unwrap(), None);
+    }
+
+    #[test]
+    fn unsupported_filters() {
+        use reth_static_file_types::StaticFileFeature;
+
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+
+        // Filters and header fields written by a newer producer
+        let path = reader
+            .provider()
+            .directory()
+            .join(StaticFileSegment::Headers.filename(&find_fixed_range(0)));
+        rewrite_extensions(&path, |extensions| {
+            extensions["filter_ids"] =
+                serde_json::json!({ "inclusion_filter": 7, "phf": 0, "version": 2 });
+            extensions["added_later"] = serde_json::json!([1, 2]);
+        });
+
+        let headers = MultiSegmentReader::new(&reader, StaticFileSegment::Headers);
+        let hash = harness.blocks[2].hash();
+        assert!(matches!(
+            headers.find_by_hash(0..=3, hash),
+            Err(StaticFileReaderError::UnsupportedFeature { err, .. })
+                if err.feature == StaticFileFeature::InclusionFilter(7) &&
+                    err.required_version == 2
+        ));
+
+        // Rows are still found by scanning the static file
+        let headers = headers.with_scan_unsupported_filters(true);
+        assert_eq!(headers.find_by_hash(0..=3, hash).unwrap().map(|row| row.number), Some(2));
+    }
+
+    #[test]
+    fn column_mismatch() {
+        use reth_static_file_types::{ColumnCodec, ColumnMismatch, HeadersLayout};
+
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        let path = reader
+            .provider()
+            .directory()
+            .join(StaticFileSegment::Headers.filename(&find_fixed_range(0)));
+        let mut jar = load_jar(&path).unwrap();
+        assert_eq!(jar.user_header().column_codecs(), Some([0, 1, 2].as_slice()));
+
+        // Codecs recorded for other columns than the ones of the static file
+        jar.user_header_mut().set_headers_layout(HeadersLayout::NoTotalDifficulty);
+        jar.user_header_mut().set_column_codecs(&[ColumnCodec::Header, ColumnCodec::BlockHash]);
+        jar.freeze_config().unwrap();
+
+        let headers = MultiSegmentReader::new(&reader, StaticFileSegment::Headers);
+        assert!(matches!(
+            headers.range(0..=3),
+            Err(StaticFileReaderError::ColumnMismatch {
+                segment: StaticFileSegment::Headers,
+                err: ColumnMismatch::Count { expected: 2, found: 3 },
+                ..
+            })
+        ));
+        assert!(matches!(
+            reader.check_schema_versions(),
+            Err(StaticFileReaderError::ColumnMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn receipts_keyed_by_hash() {
+        use reth_nippy_jar::{InclusionFilter, PerfectHashingFunction};
+        use reth_provider::ReceiptProvider;
+        use reth_static_file_types::{FilterHash, Filters};
+
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let mut reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        let hash = harness.blocks[2].body[0].hash();
+        let receipts = MultiSegmentReader::new(&reader, StaticFileSegment::Receipts);
+        assert_eq!(receipts.find_keyed_receipt(hash).unwrap(), None);
+
+        // Receipt filters are keyed by transaction hash, hashed with a secret
+        let filter_key = FilterKey::new([7; 16]);
+        let filters = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Fmph);
+        let mut producer = harness.producer();
+        producer.set_filter_key(Some(filter_key));
+        producer
+            .rebuild_filters(
+                StaticFileSegment::Receipts,
+                find_fixed_range(0),
+                filters,
+                FilterHash::KeyedSipHash,
+            )
+            .unwrap();
+
+        // Without the secret, receipts are found through the Transactions static file
+        let receipts = MultiSegmentReader::new(&reader, StaticFileSegment::Receipts);
+        let unkeyed = receipts.find_by_hash(0..=3, hash).unwrap().unwrap();
+        reader.set_filter_key(Some(filter_key));
+
+        let receipts = MultiSegmentReader::new(&reader, StaticFileSegment::Receipts);
+        let found = receipts.find_keyed_receipt(hash).unwrap().unwrap();
+        assert_eq!(found, unkeyed);
+        let SegmentValue::Receipt(receipt) = found.value else { panic!("not a receipt") };
+        assert_eq!(reader.receipt_by_hash(hash).unwrap(), Some(receipt));
+        assert_eq!(
+            receipts.find_by_hash(0..=3, hash).unwrap().map(|row| row.number),
+            Some(found.number)
+        );
+        assert_eq!(receipts.find_keyed_receipt(B256::ZERO).unwrap(), None);
+    }
+
+    #[test]
+    fn receipts_keyed_by_hash_with_chd() {
+        use crate::{CHD_INDEX_EXTENSION, COMPANION_EXTENSIONS};
+        use reth_provider::ReceiptProvider;
+        use reth_static_file_types::{FilterHash, Filters, InclusionFilter};
+
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        let hash = harness.blocks[2].body[0].hash();
+        let entry = list_static_files(reader.provider().directory())
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.segment == StaticFileSegment::Receipts)
+            .unwrap();
+        assert!(COMPANION_EXTENSIONS.contains(&CHD_INDEX_EXTENSION));
+
+        // Transactions are looked up by the static file provider, which can't read the sidecar
+        let producer = harness.producer();
+        let chd = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Chd);
+        assert!(producer
+            .rebuild_filters(
+                StaticFileSegment::Transactions,
+                find_fixed_range(0),
+                chd,
+                FilterHash::Identity
+            )
+            .is_err());
+
+        producer
+            .rebuild_filters(
+                StaticFileSegment::Receipts,
+                find_fixed_range(0),
+                chd,
+                FilterHash::Identity,
+            )
+            .unwrap();
+        assert!(entry.companion_path(CHD_INDEX_EXTENSION).exists());
+        let receipts = MultiSegmentReader::new(&reader, StaticFileSegment::Receipts);
+        let found = receipts.find_keyed_receipt(hash).unwrap().unwrap();
+        let SegmentValue::Receipt(receipt) = found.value else { panic!("not a receipt") };
+        assert_eq!(reader.receipt_by_hash(hash).unwrap(), Some(receipt));
+        assert_eq!(receipts.find_keyed_receipt(B256::ZERO).unwrap(), None);
+
+        // The sidecar is removed once the static file is indexed with another function
+        let fmph = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Fmph);
+        producer
+            .rebuild_filters(
+                StaticFileSegment::Receipts,
+                find_fixed_range(0),
+                fmph,
+                FilterHash::Identity,
+            )
+            .unwrap();
+        assert!(!entry.companion_path(CHD_INDEX_EXTENSION).exists());
+        assert_eq!(
+            receipts.find_keyed_receipt(hash).unwrap().map(|row| row.number),
+            Some(found.number)
+        );
+    }
+
+    #[test]
+    fn reverse_iteration() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        let numbers =
+            |rows: SegmentRange<'_>| rows.map(|row| row.unwrap().number).collect::<Vec<_>>();
+
+        let headers = MultiSegmentReader::new(&reader, StaticFileSegment::Headers);
+        assert_eq!(numbers(headers.iter_blocks_rev(1..=3).unwrap()), vec![3, 2, 1]);
+        assert_eq!(numbers(headers.iter_txs_rev(0..=3).unwrap()), Vec::<u64>::new());
+
+        let transactions = MultiSegmentReader::new(&reader, StaticFileSegment::Transactions);
+        assert_eq!(numbers(transactions.iter_txs_rev(1..=3).unwrap()), vec![3, 2, 1]);
+        let forward = numbers(transactions.range(0..=3).unwrap());
+        let mut reversed = numbers(transactions.iter_blocks_rev(0..=3).unwrap());
+        reversed.reverse();
+        assert_eq!(forward, reversed);
+    }
+
+    #[test]
+    fn get_many() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+
+        for segment in [StaticFileSegment::Headers, StaticFileSegment::Receipts] {
+            let rows = MultiSegmentReader::new(&reader, segment)
+                .get_many(&[3, 0, 3, 1_000_000, 1])
+                .unwrap();
+            assert_eq!(
+                rows.iter().map(|row| row.as_ref().map(|row| row.number)).collect::<Vec<_>>(),
+                vec![Some(3), Some(0), Some(3), None, Some(1)]
+            );
+        }
+    }
+}
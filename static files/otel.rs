@@ -0,0 +1,138 @@
+//! Export of the spans and run metrics of the producer to OpenTelemetry, so static file runs
+//! show up in central tracing alongside the sync stages.
+//!
+//! Spans of the producer are emitted through `tracing` with the `static_file` target, and are
+//! exported by adding the [`tracing_layer`] to the subscriber of the node. Run metrics are
+//! recorded with an OpenTelemetry [`Meter`] through [`OtelMetrics`], see
+//! [`StaticFileProducerInner::set_otel_metrics`](crate::StaticFileProducerInner::set_otel_metrics).
+
+use crate::StaticFileTargets;
+use opentelemetry::{
+    metrics::{Counter, Gauge, Histogram, Meter},
+    trace::Tracer,
+    KeyValue,
+};
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use std::{fmt, time::Duration};
+use tracing::{Level, Subscriber};
+use tracing_opentelemetry::PreSampledTracer;
+use tracing_subscriber::{filter::Targets, registry::LookupSpan, Layer};
+
+/// Returns a `tracing` layer exporting the spans of the producer, and only them, with the tracer,
+/// e.g. to be added next to the layers of the node:
+///
+/// ```ignore
+/// let subscriber = tracing_subscriber::registry()
+///     .with(fmt_layer)
+///     .with(reth_static_file::otel::tracing_layer(tracer));
+/// ```
+pub fn tracing_layer<S, T>(tracer: T) -> impl Layer<S>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+    T: Tracer + PreSampledTracer + 'static,
+{
+    // Spans of the producer are at the debug level, see `StaticFileProducerInner::run`
+    tracing_opentelemetry::layer()
+        .with_tracer(tracer)
+        .with_filter(Targets::new().with_target("static_file", Level::DEBUG))
+}
+
+/// Instruments of the run metrics of the producer, recorded after every
+/// [`StaticFileProducerInner::run`](crate::StaticFileProducerInner::run).
+#[derive(Clone)]
+pub struct OtelMetrics {
+    /// Number of runs, with their outcome.
+    runs: Counter<u64>,
+    /// Duration of the runs, in seconds.
+    run_duration: Histogram<f64>,
+    /// Number of blocks produced per segment.
+    produced_blocks: Counter<u64>,
+    /// Highest block in the static files of every segment.
+    highest_block: Gauge<u64>,
+}
+
+impl OtelMetrics {
+    /// Creates the instruments of the run metrics with the meter.
+    pub fn new(meter: &Meter) -> Self {
+        Self {
+            runs: meter
+                .u64_counter("reth_static_files_runs")
+                .with_description("Number of static file producer runs, by outcome.")
+                .init(),
+            run_duration: meter
+                .f64_histogram("reth_static_files_run_duration")
+                .with_description("Time static file producer runs took.")
+                .with_unit("s")
+                .init(),
+            produced_blocks: meter
+                .u64_counter("reth_static_files_produced_blocks")
+                .with_description("Number of blocks moved to static files, by segment.")
+                .init(),
+            highest_block: meter
+                .u64_gauge("reth_static_files_highest_block")
+                .with_description("Highest block in the static files, by segment.")
+                .init(),
+        }
+    }
+
+    /// Records a run that took `elapsed`, with the targets it produced, `None` if it failed, and
+    /// the highest static file blocks after it.
+    pub(crate) fn record_run(
+        &self,
+        produced: Option<&StaticFileTargets>,
+        elapsed: Duration,
+        highest_static_files: &HighestStaticFiles,
+    ) {
+        let outcome =
+            KeyValue::new("outcome", if produced.is_some() { "success" } else { "failure" });
+        self.runs.add(1, &[outcome.clone()]);
+        self.run_duration.record(elapsed.as_secs_f64(), &[outcome]);
+
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            let attributes = [KeyValue::new("segment", segment.to_string())];
+            if let Some(range) = produced.and_then(|produced| produced.target(segment)) {
+                self.produced_blocks.add(range.end() - range.start() + 1, &attributes);
+            }
+            if let Some(highest_block) = highest_static_files.highest(segment) {
+                self.highest_block.record(highest_block, &attributes);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for OtelMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OtelMetrics").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use opentelemetry::trace::noop::NoopTracer;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn otel_export() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        producer.set_otel_metrics(Some(OtelMetrics::new(&opentelemetry::global::meter("test"))));
+        let targets = producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(harness.tip()),
+                receipts: Some(harness.tip()),
+                transactions: Some(harness.tip()),
+            })
+            .unwrap();
+
+        // Runs are exported without affecting their outcome
+        let subscriber = tracing_subscriber::registry().with(tracing_layer(NoopTracer::new()));
+        let produced = tracing::subscriber::with_default(subscriber, || producer.run(targets));
+        assert_eq!(produced.unwrap().target(StaticFileSegment::Headers), Some(&(0..=3)));
+    }
+}
@@ -0,0 +1,218 @@
+//! Preallocation of the data and offsets files of static files to their estimated final size,
+//! before rows are appended to them.
+//!
+//! Space is reserved past the end of the files, so their size, and the consistency checks of the
+//! static file writer, are unaffected. Files are less fragmented, and a full disk fails the copy
+//! when a static file is started, instead of in the middle of a write. Space left over once a
+//! static file is sealed is released. Files are only preallocated on Linux, and filesystems that
+//! don't support it are written to as usual.
+
+use crate::StaticFileEntry;
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{io, path::Path};
+
+/// Configuration of the preallocation of static files, see
+/// [`StaticFileProducerInner::set_preallocation`](crate::StaticFileProducerInner::set_preallocation).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreallocationConfig {
+    /// Whether the data and offsets files are preallocated. Disabled by default.
+    pub enabled: bool,
+    /// Maximum number of bytes preallocated for a file. If `None`, files are preallocated to
+    /// their whole estimated size.
+    pub max_file_bytes: Option<u64>,
+}
+
+/// Estimated size of the data and offsets files of a static file per block, from the sealed
+/// static files of its segment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileSizeEstimate {
+    /// Bytes of the data file per block.
+    pub data_bytes_per_block: u64,
+    /// Bytes of the offsets file per block.
+    pub offsets_bytes_per_block: u64,
+    /// Maximum number of bytes preallocated for a file, if limited.
+    pub max_file_bytes: Option<u64>,
+}
+
+impl FileSizeEstimate {
+    /// Estimates the size of the files of the segment per block from its sealed static files,
+    /// which hold all blocks of their range. Returns `None` if the segment has none yet.
+    pub fn from_static_files(
+        entries: &[StaticFileEntry],
+        highest_static_files: &HighestStaticFiles,
+        segment: StaticFileSegment,
+    ) -> io::Result<Option<Self>> {
+        let (mut blocks, mut data_bytes, mut offsets_bytes) = (0u64, 0u64, 0u64);
+        for entry in entries
+            .iter()
+            .filter(|entry| entry.segment == segment && entry.is_sealed(highest_static_files))
+        {
+            blocks += entry.block_range.len();
+            data_bytes += entry.path.metadata()?.len();
+            let offsets_path = entry.companion_path("off");
+            if offsets_path.exists() {
+                offsets_bytes += offsets_path.metadata()?.len();
+            }
+        }
+
+        if blocks == 0 {
+            return Ok(None)
+        }
+        Ok(Some(Self {
+            data_bytes_per_block: data_bytes / blocks,
+            offsets_bytes_per_block: offsets_bytes / blocks,
+            max_file_bytes: None,
+        }))
+    }
+
+    /// Sets the maximum number of bytes preallocated for a file.
+    pub const fn with_max_file_bytes(mut self, max_file_bytes: Option<u64>) -> Self {
+        self.max_file_bytes = max_file_bytes;
+        self
+    }
+
+    /// Returns the estimated sizes of the data and offsets files of a static file with the
+    /// number of blocks, capped to the maximum.
+    pub fn file_bytes(&self, blocks: u64) -> (u64, u64) {
+        let cap = |bytes: u64| self.max_file_bytes.map_or(bytes, |max| bytes.min(max));
+        (
+            cap(self.data_bytes_per_block.saturating_mul(blocks)),
+            cap(self.offsets_bytes_per_block.saturating_mul(blocks)),
+        )
+    }
+
+    /// Preallocates the data file and its offsets file for the number of blocks. Files that
+    /// don't exist are skipped.
+    pub(crate) fn preallocate(&self, data_path: &Path, blocks: u64) -> io::Result<()> {
+        let (data_bytes, offsets_bytes) = self.file_bytes(blocks);
+        reserve(data_path, data_bytes)?;
+        reserve(&data_path.with_extension("off"), offsets_bytes)
+    }
+}
+
+/// Releases the space preallocated past the end of the data file and its offsets file, once the
+/// static file is sealed. Files that don't exist are skipped.
+pub(crate) fn release_preallocated(data_path: &Path) -> io::Result<()> {
+    release(data_path)?;
+    release(&data_path.with_extension("off"))
+}
+
+/// Reserves space for the file up to `len` bytes, without changing its size.
+#[cfg(target_os = "linux")]
+fn reserve(path: &Path, len: u64) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    if len == 0 || !path.exists() {
+        return Ok(())
+    }
+    let file = std::fs::OpenOptions::new().write(true).open(path)?;
+    // SAFETY: the descriptor is owned by `file`, which outlives the call.
+    let result = unsafe {
+        libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len as libc::off_t)
+    };
+    check_fallocate(result)
+}
+
+/// Reserves space for the file up to `len` bytes, without changing its size.
+#[cfg(not(target_os = "linux"))]
+fn reserve(_path: &Path, _len: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// Releases the space allocated past the end of the file.
+#[cfg(target_os = "linux")]
+fn release(path: &Path) -> io::Result<()> {
+    use std::os::{fd::AsRawFd, unix::fs::MetadataExt};
+
+    if !path.exists() {
+        return Ok(())
+    }
+    let file = std::fs::OpenOptions::new().write(true).open(path)?;
+    let metadata = file.metadata()?;
+    // Blocks are counted in 512 byte units, whatever the block size of the filesystem
+    let allocated = metadata.blocks().saturating_mul(512);
+    if allocated <= metadata.len() {
+        return Ok(())
+    }
+    // Space past the end of the file can only be preallocated, so the rows are left as is
+    // SAFETY: the descriptor is owned by `file`, which outlives the call.
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            metadata.len() as libc::off_t,
+            allocated as libc::off_t,
+        )
+    };
+    check_fallocate(result)
+}
+
+/// Releases the space allocated past the end of the file.
+#[cfg(not(target_os = "linux"))]
+fn release(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Checks the result of `fallocate`, ignoring filesystems that don't support it.
+#[cfg(target_os = "linux")]
+fn check_fallocate(result: libc::c_int) -> io::Result<()> {
+    if result == 0 {
+        return Ok(())
+    }
+    let err = io::Error::last_os_error();
+    if err.raw_os_error() == Some(libc::EOPNOTSUPP) {
+        return Ok(())
+    }
+    Err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::list_static_files;
+
+    #[test]
+    fn preallocation() {
+        let directory = tempfile::tempdir().unwrap();
+        let sealed = directory.path().join("static_file_headers_0_499999");
+        std::fs::write(&sealed, vec![0; 1_000_000]).unwrap();
+        std::fs::write(sealed.with_extension("off"), vec![0; 4_000_000]).unwrap();
+        let appended = directory.path().join("static_file_headers_500000_999999");
+        std::fs::write(&appended, [0; 10]).unwrap();
+        let entries = list_static_files(directory.path()).unwrap();
+        let highest = HighestStaticFiles { headers: Some(500_001), ..Default::default() };
+
+        // Only the sealed static file is representative of the whole range
+        let estimate =
+            FileSizeEstimate::from_static_files(&entries, &highest, StaticFileSegment::Headers)
+                .unwrap()
+                .unwrap();
+        assert_eq!((estimate.data_bytes_per_block, estimate.offsets_bytes_per_block), (2, 8));
+        assert_eq!(
+            FileSizeEstimate::from_static_files(&entries, &highest, StaticFileSegment::Receipts)
+                .unwrap(),
+            None
+        );
+        let capped = estimate.with_max_file_bytes(Some(3_000_000));
+        assert_eq!(capped.file_bytes(500_000), (1_000_000, 3_000_000));
+
+        // Preallocated space doesn't change the size of the file, and is released once sealed
+        capped.preallocate(&appended, 500_000).unwrap();
+        assert_eq!(appended.metadata().unwrap().len(), 10);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let allocated = || appended.metadata().unwrap().blocks() * 512;
+            let reserved = allocated();
+            release_preallocated(&appended).unwrap();
+            // Filesystems without preallocation reserve nothing in the first place
+            if reserved >= 1_000_000 {
+                assert!(allocated() < 1_000_000);
+            }
+        }
+        assert_eq!(appended.metadata().unwrap().len(), 10);
+    }
+}
@@ -0,0 +1,277 @@
+//! Self-profiling of the reads served by a [`StaticFileReader`], sampling read latencies and
+//! false positive rates of inclusion filters per static file, e.g. to decide which static files
+//! are worth recompressing or rebuilding with other filters.
+
+use crate::StaticFileReader;
+use alloy_primitives::BlockNumber;
+use parking_lot::Mutex;
+use reth_static_file_types::{SegmentRangeInclusive, StaticFileSegment};
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+use tracing::debug;
+
+/// Read statistics of a static file, aggregated from the reads sampled by a [`ReadProfiler`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileReadStats {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file.
+    pub block_range: SegmentRangeInclusive,
+    /// Sampled reads of rows.
+    pub reads: u64,
+    /// Total latency of the sampled reads.
+    pub total_latency: Duration,
+    /// Highest latency of the sampled reads.
+    pub max_latency: Duration,
+    /// Lookups by hash rejected by the inclusion filter.
+    pub filter_negatives: u64,
+    /// Lookups by hash passing the inclusion filter, that found their row.
+    pub filter_true_positives: u64,
+    /// Lookups by hash passing the inclusion filter, that didn't find their row.
+    pub filter_false_positives: u64,
+}
+
+impl FileReadStats {
+    /// Creates empty statistics of the static file.
+    const fn new(segment: StaticFileSegment, block_range: SegmentRangeInclusive) -> Self {
+        Self {
+            segment,
+            block_range,
+            reads: 0,
+            total_latency: Duration::ZERO,
+            max_latency: Duration::ZERO,
+            filter_negatives: 0,
+            filter_true_positives: 0,
+            filter_false_positives: 0,
+        }
+    }
+
+    /// Returns the mean latency of the sampled reads, if any.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        (self.reads > 0).then(|| self.total_latency / self.reads.try_into().unwrap_or(u32::MAX))
+    }
+
+    /// Returns the share of lookups of missing keys that passed the inclusion filter, if any
+    /// missing key was looked up.
+    pub fn false_positive_rate(&self) -> Option<f64> {
+        let missing = self.filter_negatives + self.filter_false_positives;
+        (missing > 0).then(|| self.filter_false_positives as f64 / missing as f64)
+    }
+}
+
+/// Outcome of a lookup by hash through the inclusion filter of a static file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FilterOutcome {
+    /// The key was rejected by the filter.
+    Negative,
+    /// The key passed the filter and its row was found.
+    TruePositive,
+    /// The key passed the filter, but its row wasn't found.
+    FalsePositive,
+}
+
+/// State shared between clones of a [`ReadProfiler`].
+#[derive(Debug, Default)]
+struct ProfilerState {
+    /// Whether reads are sampled.
+    active: AtomicBool,
+    /// Statistics per static file, by segment and first block.
+    files: Mutex<BTreeMap<(StaticFileSegment, BlockNumber), FileReadStats>>,
+}
+
+/// Profiler of the reads served by a [`StaticFileReader`], set with
+/// [`StaticFileReader::set_profiler`]. Clones share their statistics.
+///
+/// Reads are only sampled while the profiler is active, either toggled with
+/// [`ReadProfiler::set_active`], or periodically by a [`ProfilingTask`], so the overhead of
+/// profiling is limited to short windows.
+#[derive(Debug, Clone, Default)]
+pub struct ReadProfiler {
+    state: Arc<ProfilerState>,
+}
+
+impl ReadProfiler {
+    /// Creates a new inactive [`ReadProfiler`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if reads are sampled.
+    pub fn is_active(&self) -> bool {
+        self.state.active.load(Ordering::Relaxed)
+    }
+
+    /// Starts or stops sampling reads.
+    pub fn set_active(&self, active: bool) {
+        self.state.active.store(active, Ordering::Relaxed);
+    }
+
+    /// Returns the statistics of every static file read while the profiler was active, sorted by
+    /// segment and block range.
+    pub fn stats(&self) -> Vec<FileReadStats> {
+        self.state.files.lock().values().copied().collect()
+    }
+
+    /// Clears the statistics.
+    pub fn reset(&self) {
+        self.state.files.lock().clear();
+    }
+
+    /// Spawns a background task activating the profiler for `window` after every `interval`,
+    /// until the returned task is dropped.
+    pub fn spawn(&self, interval: Duration, window: Duration) -> io::Result<ProfilingTask> {
+        let (stop, stopped) = channel::<()>();
+        let profiler = self.clone();
+        let handle = std::thread::Builder::new().name("static-file-profiler".to_string()).spawn(
+            move || loop {
+                // Nothing is ever sent, so the task is only woken up when it's dropped
+                if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                    break
+                }
+
+                profiler.set_active(true);
+                let result = stopped.recv_timeout(window);
+                profiler.set_active(false);
+                debug!(target: "static_file", files = profiler.stats().len(), "Finished static file profiling window");
+                if result != Err(RecvTimeoutError::Timeout) {
+                    break
+                }
+            },
+        )?;
+
+        Ok(ProfilingTask { stop: Some(stop), handle: Some(handle) })
+    }
+
+    /// Records the latency of a sampled read of the static file.
+    pub(crate) fn record_read(
+        &self,
+        segment: StaticFileSegment,
+        block_range: SegmentRangeInclusive,
+        latency: Duration,
+    ) {
+        self.update(segment, block_range, |stats| {
+            stats.reads += 1;
+            stats.total_latency += latency;
+            stats.max_latency = stats.max_latency.max(latency);
+        });
+    }
+
+    /// Records the outcome of a lookup through the inclusion filter of the static file.
+    pub(crate) fn record_filter(
+        &self,
+        segment: StaticFileSegment,
+        block_range: SegmentRangeInclusive,
+        outcome: FilterOutcome,
+    ) {
+        self.update(segment, block_range, |stats| match outcome {
+            FilterOutcome::Negative => stats.filter_negatives += 1,
+            FilterOutcome::TruePositive => stats.filter_true_positives += 1,
+            FilterOutcome::FalsePositive => stats.filter_false_positives += 1,
+        });
+    }
+
+    /// Updates the statistics of the static file.
+    fn update(
+        &self,
+        segment: StaticFileSegment,
+        block_range: SegmentRangeInclusive,
+        f: impl FnOnce(&mut FileReadStats),
+    ) {
+        let mut files = self.state.files.lock();
+        f(files
+            .entry((segment, block_range.start()))
+            .or_insert_with(|| FileReadStats::new(segment, block_range)));
+    }
+}
+
+/// Background task of a [`ReadProfiler`] spawned with [`ReadProfiler::spawn`]. Stops on drop,
+/// leaving the profiler inactive.
+#[derive(Debug)]
+pub struct ProfilingTask {
+    /// Sender whose drop wakes up and stops the task.
+    stop: Option<Sender<()>>,
+    /// Thread of the task.
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for ProfilingTask {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_provider::{HeaderProvider, StaticFileProviderFactory};
+    use reth_static_file_types::find_fixed_range;
+    use std::time::Instant;
+
+    #[test]
+    fn samples_reads_while_active() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let mut reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        let profiler = ReadProfiler::new();
+        reader.set_profiler(Some(profiler.clone()));
+
+        // Reads are not sampled while inactive
+        assert!(reader.header_by_number(1).unwrap().is_some());
+        assert!(profiler.stats().is_empty());
+
+        profiler.set_active(true);
+        for block in 0..=harness.tip() {
+            assert!(reader.header_by_number(block).unwrap().is_some());
+        }
+        let stats = profiler.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].block_range, find_fixed_range(0));
+        assert_eq!(stats[0].reads, 4);
+        assert!(stats[0].mean_latency().unwrap() <= stats[0].max_latency);
+
+        let block_range = find_fixed_range(0);
+        for outcome in [
+            FilterOutcome::Negative,
+            FilterOutcome::Negative,
+            FilterOutcome::FalsePositive,
+            FilterOutcome::TruePositive,
+        ] {
+            profiler.record_filter(StaticFileSegment::Transactions, block_range, outcome);
+        }
+        let stats = profiler.stats();
+        assert_eq!(stats[1].segment, StaticFileSegment::Transactions);
+        assert_eq!(stats[1].false_positive_rate(), Some(1.0 / 3.0));
+
+        profiler.reset();
+        assert!(profiler.stats().is_empty());
+    }
+
+    #[test]
+    fn periodic_profiling_task() {
+        let profiler = ReadProfiler::new();
+        let task = profiler.spawn(Duration::from_millis(1), Duration::from_secs(3600)).unwrap();
+
+        let start = Instant::now();
+        while !profiler.is_active() {
+            assert!(start.elapsed() < Duration::from_secs(10), "profiling window never started");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        drop(task);
+        assert!(!profiler.is_active());
+    }
+}
@@ -0,0 +1,746 @@
+//! Progress tracking of segments being copied to static files.
+
+use crate::{
+    coordinator::IoThrottle, hooks::thread_cpu_time, rollback::TailSnapshot, BatchHooks,
+    BatchStats, DataGap, FileSizeEstimate, MissingDataPolicy, RowTransforms, StaticFileChainSpec,
+    StaticFileEventSender, StaticFileProducerEvent,
+};
+use alloy_primitives::BlockNumber;
+use parking_lot::Mutex;
+use reth_static_file_types::{SegmentRangeInclusive, StaticFileSegment};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// Rows and bytes copied for a single block, reported with [`SegmentProgress::advance`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopiedRows {
+    /// Number of rows copied.
+    pub rows: u64,
+    /// Bytes read from the database.
+    pub bytes_read: u64,
+    /// Uncompressed bytes appended to static files.
+    pub bytes_written: u64,
+}
+
+impl CopiedRows {
+    /// Records a single row of `len` bytes, copied as is from the database to static files.
+    pub fn add_row(&mut self, len: usize) {
+        self.rows += 1;
+        self.bytes_read += len as u64;
+        self.bytes_written += len as u64;
+    }
+}
+
+/// Progress of a single segment during [`StaticFileProducerInner::run`].
+///
+/// Segments report every copied block with [`SegmentProgress::advance`], and check
+/// [`SegmentProgress::is_cancelled`] between blocks to stop early. Copied blocks are accumulated
+/// into batches reported to [`BatchHooks`], and [`SegmentProgress::advance`] blocks while the run
+/// is paused. With a commit interval, segments commit their static file writer every time
+/// [`SegmentProgress::is_commit_due`] returns `true`.
+///
+/// [`StaticFileProducerInner::run`]: crate::StaticFileProducerInner::run
+#[derive(Debug)]
+pub struct SegmentProgress {
+    /// Segment being copied.
+    segment: StaticFileSegment,
+    /// Last copied block and the time it was copied at.
+    state: Mutex<ProgressState>,
+    /// Set while the segment is being copied.
+    running: AtomicBool,
+    /// Set when the segment should stop copying at the next block boundary.
+    cancelled: AtomicBool,
+    /// Hooks notified about copied batches.
+    hooks: BatchHooks,
+    /// Number of copied blocks after which the segment commits. `None` commits only at the end
+    /// of the run.
+    commit_interval: Option<u64>,
+    /// Maximum number of blocks copied per second. `None` doesn't limit the copy.
+    throttle: Option<u64>,
+    /// Number of copied blocks after which the database read transaction is renewed. `None`
+    /// keeps one transaction for the whole copy.
+    read_tx_renewal: Option<u64>,
+    /// Event sender notified about commits.
+    events: Option<StaticFileEventSender>,
+    /// Chain the segment is copied for, if known.
+    chain_spec: Option<Arc<dyn StaticFileChainSpec>>,
+    /// Transforms applied to the copied rows.
+    row_transforms: RowTransforms,
+    /// Estimated size of the static files the segment is copied to, preallocated when they're
+    /// started. `None` doesn't preallocate them.
+    preallocation: Option<FileSizeEstimate>,
+    /// Behavior when the data of a block is missing from the database.
+    missing_data: MissingDataPolicy,
+    /// Budget of the bytes written, shared with the producers of other networks. `None` doesn't
+    /// limit the IO.
+    io_throttle: Option<Arc<IoThrottle>>,
+    /// Whether the sizes of the values of the copied rows are tallied per column in the headers
+    /// of the static files.
+    size_histograms: bool,
+    /// Clock the time of the last progress is recorded with.
+    clock: StallClock,
+}
+
+#[derive(Debug)]
+struct ProgressState {
+    last_block: Option<BlockNumber>,
+    last_progress_at: Instant,
+    /// Time the segment was last started at, while it's being copied.
+    started_at: Option<Instant>,
+    /// Total time spent copying the segment, across all chunks.
+    copy_time: Duration,
+    /// Number of blocks copied since the segment was last started.
+    started_blocks: u64,
+    batch: Batch,
+    uncommitted: u64,
+    /// Static files of the segment at the last commit, to roll back to.
+    tail: Option<TailSnapshot>,
+    /// Blocks whose data was missing from the database, in the order they were met.
+    gaps: Vec<DataGap>,
+}
+
+/// Blocks copied since the last batch was reported.
+#[derive(Debug)]
+struct Batch {
+    blocks: Option<SegmentRangeInclusive>,
+    copied: CopiedRows,
+    started_at: Instant,
+    cpu_time_at_start: Option<Duration>,
+}
+
+impl Batch {
+    fn new() -> Self {
+        Self {
+            blocks: None,
+            copied: CopiedRows::default(),
+            started_at: Instant::now(),
+            cpu_time_at_start: thread_cpu_time(),
+        }
+    }
+
+    fn add(&mut self, block: BlockNumber, copied: CopiedRows) {
+        let start = self.blocks.map_or(block, |blocks| blocks.start());
+        self.blocks = Some(SegmentRangeInclusive::new(start, block));
+        self.copied.rows += copied.rows;
+        self.copied.bytes_read += copied.bytes_read;
+        self.copied.bytes_written += copied.bytes_written;
+    }
+
+    fn len(&self) -> u64 {
+        self.blocks.map_or(0, |blocks| blocks.len())
+    }
+
+    /// Returns the stats of the batch, if any blocks were copied.
+    fn into_stats(self, segment: StaticFileSegment) -> Option<BatchStats> {
+        Some(BatchStats {
+            segment,
+            blocks: self.blocks?,
+            rows: self.copied.rows,
+            bytes_read: self.copied.bytes_read,
+            bytes_written: self.copied.bytes_written,
+            elapsed: self.started_at.elapsed(),
+            cpu_time: thread_cpu_time()
+                .zip(self.cpu_time_at_start)
+                .map(|(now, start)| now.saturating_sub(start)),
+        })
+    }
+}
+
+impl SegmentProgress {
+    /// Creates a new [`SegmentProgress`] for the segment, with no blocks copied yet.
+    pub fn new(segment: StaticFileSegment) -> Self {
+        Self {
+            segment,
+            state: Mutex::new(ProgressState {
+                last_block: None,
+                last_progress_at: Instant::now(),
+                started_at: None,
+                copy_time: Duration::ZERO,
+                started_blocks: 0,
+                batch: Batch::new(),
+                uncommitted: 0,
+                tail: None,
+                gaps: Vec::new(),
+            }),
+            running: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            hooks: BatchHooks::default(),
+            commit_interval: None,
+            throttle: None,
+            read_tx_renewal: None,
+            events: None,
+            chain_spec: None,
+            row_transforms: RowTransforms::default(),
+            preallocation: None,
+            missing_data: MissingDataPolicy::default(),
+            io_throttle: None,
+            size_histograms: false,
+            clock: StallClock::default(),
+        }
+    }
+
+    /// Sets the [`BatchHooks`] notified about copied batches.
+    pub fn with_hooks(mut self, hooks: BatchHooks) -> Self {
+        self.hooks = hooks;
+        self
+    }
+
+    /// Sets the number of copied blocks after which the segment commits. `None` commits only at
+    /// the end of the run.
+    pub fn with_commit_interval(mut self, commit_interval: Option<u64>) -> Self {
+        self.commit_interval = commit_interval.map(|interval| interval.max(1));
+        self
+    }
+
+    /// Sets the maximum number of blocks copied per second. `None` doesn't limit the copy.
+    pub fn with_throttle(mut self, blocks_per_second: Option<u64>) -> Self {
+        self.throttle = blocks_per_second.map(|blocks| blocks.max(1));
+        self
+    }
+
+    /// Sets the number of copied blocks after which segments renew their database read
+    /// transaction, see [`SegmentProgress::read_tx_renewal_blocks`].
+    pub fn with_read_tx_renewal(mut self, blocks: Option<u64>) -> Self {
+        self.read_tx_renewal = blocks.map(|blocks| blocks.max(1));
+        self
+    }
+
+    /// Returns the number of copied blocks after which segments renew their database read
+    /// transaction, so long copies don't hold one transaction that blocks page reclamation of
+    /// the database. `None` keeps one transaction for the whole copy.
+    pub const fn read_tx_renewal_blocks(&self) -> Option<u64> {
+        self.read_tx_renewal
+    }
+
+    /// Sets the event sender notified with [`StaticFileProducerEvent::Committed`] about commits.
+    pub fn with_events(mut self, events: StaticFileEventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Sets the chain the segment is copied for, so segments can make fork-aware decisions and
+    /// record the chain id in the static files.
+    pub fn with_chain_spec(mut self, chain_spec: Option<Arc<dyn StaticFileChainSpec>>) -> Self {
+        self.chain_spec = chain_spec;
+        self
+    }
+
+    /// Returns the chain the segment is copied for, if known.
+    pub fn chain_spec(&self) -> Option<&dyn StaticFileChainSpec> {
+        self.chain_spec.as_deref()
+    }
+
+    /// Sets the [`RowTransforms`] applied to the rows before they're appended to the sink.
+    pub fn with_row_transforms(mut self, row_transforms: RowTransforms) -> Self {
+        self.row_transforms = row_transforms;
+        self
+    }
+
+    /// Returns the [`RowTransforms`] applied to the copied rows.
+    pub const fn row_transforms(&self) -> &RowTransforms {
+        &self.row_transforms
+    }
+
+    /// Sets the estimated size of the static files the segment is copied to, so their files are
+    /// preallocated when they're started. `None` doesn't preallocate them.
+    pub const fn with_preallocation(mut self, preallocation: Option<FileSizeEstimate>) -> Self {
+        self.preallocation = preallocation;
+        self
+    }
+
+    /// Returns the estimated size of the static files the segment is copied to, if they're
+    /// preallocated.
+    pub const fn preallocation(&self) -> Option<FileSizeEstimate> {
+        self.preallocation
+    }
+
+    /// Sets whether the sizes of the values of the copied rows are tallied per column in the
+    /// [`SizeHistogram`](reth_static_file_types::SizeHistogram)s of the headers of the static
+    /// files.
+    pub const fn with_size_histograms(mut self, enabled: bool) -> Self {
+        self.size_histograms = enabled;
+        self
+    }
+
+    /// Returns `true` if the sizes of the values of the copied rows are tallied per column.
+    pub const fn size_histograms(&self) -> bool {
+        self.size_histograms
+    }
+
+    /// Sets the behavior when the data of a block is missing from the database.
+    pub const fn with_missing_data(mut self, missing_data: MissingDataPolicy) -> Self {
+        self.missing_data = missing_data;
+        self
+    }
+
+    /// Sets the budget of the bytes written shared with the producers of other networks, see
+    /// [`ProducerCoordinator`](crate::ProducerCoordinator).
+    pub(crate) fn with_io_throttle(mut self, io_throttle: Option<Arc<IoThrottle>>) -> Self {
+        self.io_throttle = io_throttle;
+        self
+    }
+
+    /// Sets the [`StallClock`] the time of the last progress is recorded with, which must be the
+    /// clock of the [`StallWatchdog`] watching the segment.
+    pub fn with_clock(mut self, clock: StallClock) -> Self {
+        self.state.get_mut().last_progress_at = clock.now();
+        self.clock = clock;
+        self
+    }
+
+    /// Applies the [`MissingDataPolicy`] to a block whose data is missing from the database,
+    /// `err` being the error reporting it.
+    ///
+    /// Returns the error with [`MissingDataPolicy::Fail`]. Otherwise, records the block as a
+    /// [`DataGap`], and returns `true` if the segment should skip it and continue, or `false` if
+    /// it should stop before it. Blocks are only skipped if the segment allows it with
+    /// `skippable`, as its rows have to be contiguous otherwise.
+    pub fn missing_block(
+        &self,
+        block: BlockNumber,
+        err: ProviderError,
+        skippable: bool,
+    ) -> ProviderResult<bool> {
+        let policy = match self.missing_data {
+            MissingDataPolicy::Fail => return Err(err),
+            MissingDataPolicy::SkipMissing if skippable => MissingDataPolicy::SkipMissing,
+            _ => MissingDataPolicy::StopAtGap,
+        };
+        warn!(target: "static_file", segment = %self.segment, block, %err, ?policy, "Data of the block is missing from the database");
+        self.state.lock().gaps.push(DataGap { segment: self.segment, block, policy });
+        Ok(policy == MissingDataPolicy::SkipMissing)
+    }
+
+    /// Returns the blocks whose data was missing from the database so far.
+    pub fn gaps(&self) -> Vec<DataGap> {
+        self.state.lock().gaps.clone()
+    }
+
+    /// Returns `true` if the segment stopped before a block whose data was missing from the
+    /// database.
+    pub fn stopped_at_gap(&self) -> bool {
+        self.state.lock().gaps.iter().any(|gap| gap.policy == MissingDataPolicy::StopAtGap)
+    }
+
+    /// Returns the segment being copied.
+    pub const fn segment(&self) -> StaticFileSegment {
+        self.segment
+    }
+
+    /// Marks the segment as being copied, resetting the time of the last recorded progress.
+    ///
+    /// Can be called again after [`SegmentProgress::finish`], when the segment is copied in
+    /// multiple chunks.
+    pub(crate) fn start(&self) {
+        let mut state = self.state.lock();
+        state.last_progress_at = self.clock.now();
+        state.started_at = Some(Instant::now());
+        state.started_blocks = 0;
+        state.batch = Batch::new();
+        self.running.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the segment is being copied.
+    pub(crate) fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Records that the block was fully copied.
+    ///
+    /// Reports the batch to [`BatchHooks`] once it's full, and blocks while the run is paused or
+    /// ahead of the throttle or the IO budget.
+    pub fn advance(&self, block: BlockNumber, copied: CopiedRows) {
+        let (full_batch, throttle_delay) = {
+            let mut state = self.state.lock();
+            state.last_block = Some(block);
+            state.last_progress_at = self.clock.now();
+            state.uncommitted += 1;
+            state.started_blocks += 1;
+
+            // Time left until the copied blocks are within the throttle
+            let throttle_delay = self.throttle.zip(state.started_at).and_then(|(throttle, at)| {
+                Duration::from_secs_f64(state.started_blocks as f64 / throttle as f64)
+                    .checked_sub(at.elapsed())
+            });
+
+            let full_batch = if self.hooks.has_hooks() {
+                state.batch.add(block, copied);
+                (state.batch.len() >= self.hooks.batch_size())
+                    .then(|| std::mem::replace(&mut state.batch, Batch::new()))
+            } else {
+                None
+            };
+            (full_batch, throttle_delay)
+        };
+
+        if let Some(stats) = full_batch.and_then(|batch| batch.into_stats(self.segment)) {
+            self.hooks.notify(&stats);
+        }
+
+        // Time left until the bytes written before, by any producer sharing the IO budget, are
+        // within it
+        let io_delay = self.io_throttle.as_ref().and_then(|io| io.charge(copied.bytes_written));
+        if let Some(delay) = throttle_delay.max(io_delay) {
+            std::thread::sleep(delay);
+            self.state.lock().last_progress_at = self.clock.now();
+        }
+
+        let pause = self.hooks.pause_handle();
+        if pause.is_paused() {
+            // Not being watched while paused
+            self.running.store(false, Ordering::Relaxed);
+            pause.wait_while_paused(|| self.is_cancelled());
+            self.state.lock().last_progress_at = self.clock.now();
+            self.running.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns `true` if the commit interval elapsed since the last commit of the segment.
+    pub fn is_commit_due(&self) -> bool {
+        self.commit_interval.is_some_and(|interval| self.state.lock().uncommitted >= interval)
+    }
+
+    /// Records that all copied blocks were committed, notifying
+    /// [`StaticFileProducerEvent::Committed`] if any blocks were copied since the last commit.
+    pub fn committed(&self) {
+        let block = {
+            let mut state = self.state.lock();
+            if state.uncommitted == 0 {
+                return
+            }
+            state.uncommitted = 0;
+            state.last_block
+        };
+
+        if let Some((events, block)) = self.events.as_ref().zip(block) {
+            events.notify(StaticFileProducerEvent::Committed { segment: self.segment, block });
+        }
+    }
+
+    /// Sets the snapshot of the static files of the segment at the last commit, and logs it to
+    /// the write-ahead log before any row is written after it.
+    pub(crate) fn set_tail(&self, tail: TailSnapshot) -> std::io::Result<()> {
+        tail.log()?;
+        self.state.lock().tail = Some(tail);
+        Ok(())
+    }
+
+    /// Takes the snapshot of the static files of the segment at the last commit, if any.
+    pub(crate) fn take_tail(&self) -> Option<TailSnapshot> {
+        self.state.lock().tail.take()
+    }
+
+    /// Returns the last fully copied block, if any.
+    pub fn last_block(&self) -> Option<BlockNumber> {
+        self.state.lock().last_block
+    }
+
+    /// Returns the total time spent copying the segment, excluding the chunk being copied.
+    pub fn copy_time(&self) -> Duration {
+        self.state.lock().copy_time
+    }
+
+    /// Returns the time elapsed since the last recorded progress, as measured by its
+    /// [`StallClock`].
+    pub fn stalled_for(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.state.lock().last_progress_at)
+    }
+
+    /// Requests the segment to stop copying at the next block boundary.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the segment was requested to stop copying.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Marks the segment as not being copied, so it's not watched until started again. Reports
+    /// the last incomplete batch to [`BatchHooks`].
+    pub(crate) fn finish(&self) {
+        self.running.store(false, Ordering::Relaxed);
+
+        let batch = {
+            let mut state = self.state.lock();
+            if let Some(started_at) = state.started_at.take() {
+                state.copy_time += started_at.elapsed();
+            }
+            std::mem::replace(&mut state.batch, Batch::new())
+        };
+        if let Some(stats) = batch.into_stats(self.segment) {
+            self.hooks.notify(&stats);
+        }
+    }
+}
+
+/// Clock measuring the progress of segments for the [`StallWatchdog`]: the monotonic system clock
+/// by default, or a manual clock that only moves when advanced, e.g. to stall segments in tests
+/// without waiting for the timeout.
+#[derive(Debug, Clone, Default)]
+pub struct StallClock {
+    /// Current time of the manual clock, `None` for the system clock.
+    manual: Option<Arc<Mutex<Instant>>>,
+}
+
+impl StallClock {
+    /// Creates a manual clock, starting at the current time of the system clock.
+    pub fn manual() -> Self {
+        Self { manual: Some(Arc::new(Mutex::new(Instant::now()))) }
+    }
+
+    /// Returns the current time of the clock.
+    pub fn now(&self) -> Instant {
+        self.manual.as_ref().map_or_else(Instant::now, |now| *now.lock())
+    }
+
+    /// Advances a manual clock by the duration. The system clock isn't affected.
+    pub fn advance(&self, duration: Duration) {
+        if let Some(now) = &self.manual {
+            *now.lock() += duration;
+        }
+    }
+}
+
+impl PartialEq for StallClock {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.manual, &other.manual) {
+            (None, None) => true,
+            (Some(now), Some(other_now)) => Arc::ptr_eq(now, other_now),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for StallClock {}
+
+/// Watchdog detecting segments that made no progress for a configured duration, e.g. when a
+/// database cursor is blocked by a long-running writer.
+///
+/// Cancellation is cooperative: a segment stops at the next block boundary, so a cursor that
+/// never returns still blocks the run, but the [`StaticFileProducerEvent::Stalled`] event is
+/// emitted regardless.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StallWatchdog {
+    /// Duration without progress after which a segment is considered stalled.
+    pub timeout: Duration,
+    /// Whether to cancel the run once a segment is stalled.
+    pub cancel: bool,
+    /// Clock the progress of segments is measured with.
+    pub clock: StallClock,
+}
+
+impl StallWatchdog {
+    /// Creates a new [`StallWatchdog`] that only reports stalled segments, measuring their
+    /// progress with the system clock.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, cancel: false, clock: StallClock::default() }
+    }
+
+    /// Sets whether the run should be cancelled once a segment is stalled.
+    pub const fn with_cancel(mut self, cancel: bool) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Sets the [`StallClock`] the progress of segments is measured with.
+    pub fn with_clock(mut self, clock: StallClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Watches the segments until `done` is disconnected, calling `notify` once per stall.
+    ///
+    /// A segment that makes progress again after being reported can be reported again.
+    pub(crate) fn watch(
+        &self,
+        segments: &[SegmentProgress],
+        done: Receiver<()>,
+        notify: impl Fn(StaticFileProducerEvent),
+    ) {
+        let poll_interval =
+            (self.timeout / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+        let mut reported = vec![None; segments.len()];
+
+        loop {
+            match done.recv_timeout(poll_interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+            self.check(segments, &mut reported, &notify);
+        }
+    }
+
+    /// Reports the running segments that made no progress for the timeout, and cancels them if
+    /// configured. `reported` holds the last block of every segment when it was last reported,
+    /// so a stall is reported once.
+    fn check(
+        &self,
+        segments: &[SegmentProgress],
+        reported: &mut [Option<Option<BlockNumber>>],
+        notify: &impl Fn(StaticFileProducerEvent),
+    ) {
+        for (progress, reported) in segments.iter().zip(reported.iter_mut()) {
+            if !progress.is_running() || progress.is_cancelled() {
+                continue
+            }
+
+            let last_block = progress.last_block();
+            let since = progress.stalled_for();
+            if since < self.timeout || *reported == Some(last_block) {
+                continue
+            }
+
+            *reported = Some(last_block);
+            notify(StaticFileProducerEvent::Stalled {
+                segment: progress.segment(),
+                last_block,
+                since,
+            });
+            if self.cancel {
+                progress.cancel();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc::channel, Arc};
+
+    #[test]
+    fn watchdog_reports_and_cancels_stalled_segment() {
+        let clock = StallClock::manual();
+        let segments = [
+            SegmentProgress::new(StaticFileSegment::Headers).with_clock(clock.clone()),
+            SegmentProgress::new(StaticFileSegment::Transactions).with_clock(clock.clone()),
+        ];
+        segments[0].start();
+        segments[0].advance(10, CopiedRows::default());
+        segments[1].start();
+        segments[1].finish();
+
+        let watchdog =
+            StallWatchdog::new(Duration::from_secs(60)).with_cancel(true).with_clock(clock.clone());
+        let events = Mutex::new(Vec::new());
+        let mut reported = vec![None; segments.len()];
+        let mut check =
+            || watchdog.check(&segments, &mut reported, &|event| events.lock().push(event));
+
+        // Nothing is stalled before the timeout
+        clock.advance(Duration::from_secs(59));
+        check();
+        assert!(events.lock().is_empty());
+
+        // Stalls are reported once
+        clock.advance(Duration::from_secs(1));
+        check();
+        clock.advance(Duration::from_secs(60));
+        check();
+
+        // The watchdog stops once the run is done
+        let (tx, rx) = channel();
+        drop(tx);
+        watchdog.watch(&segments, rx, |_| unreachable!("the run is done"));
+
+        let events = events.into_inner();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            StaticFileProducerEvent::Stalled {
+                segment: StaticFileSegment::Headers,
+                last_block: Some(10),
+                ..
+            }
+        ));
+        assert!(segments[0].is_cancelled());
+        assert!(!segments[1].is_cancelled());
+    }
+
+    #[test]
+    fn reports_batches() {
+        let batches = Arc::new(Mutex::new(Vec::new()));
+        let hooks = BatchHooks::new(2).on_batch({
+            let batches = batches.clone();
+            move |stats| batches.lock().push((stats.blocks, stats.rows, stats.bytes_read))
+        });
+
+        let progress = SegmentProgress::new(StaticFileSegment::Receipts).with_hooks(hooks);
+        progress.start();
+        for block in 10..=14 {
+            progress.advance(block, CopiedRows { rows: 2, bytes_read: 100, bytes_written: 100 });
+        }
+        progress.finish();
+
+        assert_eq!(
+            *batches.lock(),
+            vec![
+                (SegmentRangeInclusive::new(10, 11), 4, 200),
+                (SegmentRangeInclusive::new(12, 13), 4, 200),
+                (SegmentRangeInclusive::new(14, 14), 2, 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn commit_interval() {
+        let progress =
+            SegmentProgress::new(StaticFileSegment::Headers).with_commit_interval(Some(2));
+        progress.start();
+
+        progress.advance(0, CopiedRows::default());
+        assert!(!progress.is_commit_due());
+        progress.advance(1, CopiedRows::default());
+        assert!(progress.is_commit_due());
+        progress.committed();
+        assert!(!progress.is_commit_due());
+
+        progress.advance(2, CopiedRows::default());
+        assert!(!progress.is_commit_due());
+
+        // Without an interval, commits are never due
+        let progress = SegmentProgress::new(StaticFileSegment::Headers);
+        progress.advance(0, CopiedRows::default());
+        assert!(!progress.is_commit_due());
+    }
+
+    #[test]
+    fn copy_time() {
+        let progress = SegmentProgress::new(StaticFileSegment::Headers);
+        assert_eq!(progress.copy_time(), Duration::ZERO);
+
+        progress.start();
+        std::thread::sleep(Duration::from_millis(10));
+        progress.finish();
+        let copy_time = progress.copy_time();
+        assert!(copy_time >= Duration::from_millis(10));
+
+        // The chunk being copied is only counted once finished
+        progress.start();
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(progress.copy_time(), copy_time);
+        progress.finish();
+        assert!(progress.copy_time() >= copy_time + Duration::from_millis(10));
+    }
+
+    #[test]
+    fn throttle() {
+        let progress = SegmentProgress::new(StaticFileSegment::Headers).with_throttle(Some(100));
+        progress.start();
+
+        let start = Instant::now();
+        for block in 0..5 {
+            progress.advance(block, CopiedRows::default());
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,349 @@
+//! Read-only provider traits implemented on top of the [`StaticFileReader`], so tooling can serve
+//! data from a static files directory without a database.
+//!
+//! Lookups that need data kept only in the database, e.g. the transactions of a block without
+//! a block boundaries sidecar, return
+//! [`ProviderError::UnsupportedProvider`](reth_storage_errors::provider::ProviderError::UnsupportedProvider).
+//!
+//! Lookups by block or transaction number are clamped to the
+//! [committed rows](StaticFileReader::committed_rows) of the reader, and the
+//! [`RowTransforms`](crate::RowTransforms) of the reader are inverted on the rows they return.
+//! Transactions deduplicated into a [`DedupTable`](crate::DedupTable) are reassembled, both when
+//! read by number and when looked up by hash, and their senders are recovered from them.
+//! Static files of another schema version, or with other columns, fail the lookups reading them
+//! instead of being misdecoded.
+//! Receipts are looked up by transaction hash in the Receipts static files keyed by it, before
+//! falling back to the transaction hash index of the database. Headers of static files without the
+//! total difficulty column are read from them directly, both when read by number and when looked
+//! up by hash.
+
+use crate::{
+    reader::{
+        block_hash, canonical_hashes_range, header_by_number, header_td_by_number,
+        transaction_by_id_no_hash, transactions_by_tx_range,
+    },
+    MultiSegmentReader, SegmentRow, SegmentValue, StaticFileReader, StaticFileReaderError,
+};
+use alloy_primitives::{Address, BlockHash, BlockNumber, TxHash, TxNumber, B256, U256};
+use reth_primitives::{
+    BlockHashOrNumber, ChainInfo, Header, Receipt, SealedHeader, TransactionMeta,
+    TransactionSigned, TransactionSignedNoHash,
+};
+use reth_provider::{
+    BlockHashReader, BlockNumReader, HeaderProvider, ReceiptProvider, TransactionsProvider,
+};
+use reth_static_file_types::StaticFileSegment;
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::ops::{Range, RangeBounds};
+
+impl HeaderProvider for StaticFileReader {
+    fn header(&self, block_hash: &BlockHash) -> ProviderResult<Option<Header>> {
+        // Headers are found with the filters of every static file, as static files may store
+        // their hashes in another column
+        let row = MultiSegmentReader::new(self, StaticFileSegment::Headers)
+            .find_by_hash(0..=BlockNumber::MAX, *block_hash)
+            .map_err(|err| match err {
+                StaticFileReaderError::Provider(err) => err,
+                err => ProviderError::NippyJar(err.to_string()),
+            })?;
+        let Some(SegmentRow { value: SegmentValue::Header { mut header, .. }, .. }) = row else {
+            return Ok(None)
+        };
+        self.row_transforms().invert_header(&mut header)?;
+        Ok(Some(header))
+    }
+
+    fn header_by_number(&self, num: BlockNumber) -> ProviderResult<Option<Header>> {
+        if !self.is_committed(StaticFileSegment::Headers, num) {
+            return Ok(None)
+        }
+        let header = self
+            .profile(StaticFileSegment::Headers, num, || header_by_number(self.provider(), num))?;
+        let Some(mut header) = header else { return Ok(None) };
+        self.row_transforms().invert_header(&mut header)?;
+        Ok(Some(header))
+    }
+
+    fn header_td(&self, block_hash: &BlockHash) -> ProviderResult<Option<U256>> {
+        match self.header(block_hash)? {
+            Some(header) => self.header_td_by_number(header.number),
+            None => Ok(None),
+        }
+    }
+
+    fn header_td_by_number(&self, number: BlockNumber) -> ProviderResult<Option<U256>> {
+        if !self.is_committed(StaticFileSegment::Headers, number) {
+            return Ok(None)
+        }
+        header_td_by_number(self.provider(), number)
+    }
+
+    fn headers_range(&self, range: impl RangeBounds<BlockNumber>) -> ProviderResult<Vec<Header>> {
+        // Headers are read one by one, as the static file provider finds no rows in static files
+        // without the total difficulty column
+        let mut headers = self
+            .committed_range(StaticFileSegment::Headers, range)
+            .map_while(|number| header_by_number(self.provider(), number).transpose())
+            .collect::<ProviderResult<Vec<_>>>()?;
+        for header in &mut headers {
+            self.row_transforms().invert_header(header)?;
+        }
+        Ok(headers)
+    }
+
+    fn sealed_header(&self, number: BlockNumber) -> ProviderResult<Option<SealedHeader>> {
+        if !self.is_committed(StaticFileSegment::Headers, number) {
+            return Ok(None)
+        }
+        // Hashes are read separately, as their column depends on the layout of the static file
+        let Some(mut header) = header_by_number(self.provider(), number)? else { return Ok(None) };
+        self.row_transforms().invert_header(&mut header)?;
+        Ok(block_hash(self.provider(), number)?.map(|hash| header.seal(hash)))
+    }
+
+    fn sealed_headers_while(
+        &self,
+        range: impl RangeBounds<BlockNumber>,
+        mut predicate: impl FnMut(&SealedHeader) -> bool,
+    ) -> ProviderResult<Vec<SealedHeader>> {
+        let mut headers = Vec::new();
+        for number in self.committed_range(StaticFileSegment::Headers, range) {
+            match self.sealed_header(number)? {
+                Some(header) if predicate(&header) => headers.push(header),
+                _ => break,
+            }
+        }
+        Ok(headers)
+    }
+}
+
+impl BlockHashReader for StaticFileReader {
+    fn block_hash(&self, number: BlockNumber) -> ProviderResult<Option<B256>> {
+        if !self.is_committed(StaticFileSegment::Headers, number) {
+            return Ok(None)
+        }
+        block_hash(self.provider(), number)
+    }
+
+    fn canonical_hashes_range(
+        &self,
+        start: BlockNumber,
+        end: BlockNumber,
+    ) -> ProviderResult<Vec<B256>> {
+        canonical_hashes_range(
+            self.provider(),
+            self.committed_range(StaticFileSegment::Headers, start..end),
+        )
+    }
+}
+
+impl BlockNumReader for StaticFileReader {
+    fn chain_info(&self) -> ProviderResult<ChainInfo> {
+        self.provider().chain_info()
+    }
+
+    fn best_block_number(&self) -> ProviderResult<BlockNumber> {
+        self.provider().best_block_number()
+    }
+
+    fn last_block_number(&self) -> ProviderResult<BlockNumber> {
+        self.provider().last_block_number()
+    }
+
+    fn block_number(&self, hash: B256) -> ProviderResult<Option<BlockNumber>> {
+        self.provider().block_number(hash)
+    }
+}
+
+impl TransactionsProvider for StaticFileReader {
+    fn transaction_id(&self, tx_hash: TxHash) -> ProviderResult<Option<TxNumber>> {
+        if let Some(id) = self.provider().transaction_id(tx_hash)? {
+            return Ok(Some(id))
+        }
+        Ok(self.find_deduplicated_transaction(tx_hash)?.map(|row| row.number))
+    }
+
+    fn transaction_by_id(&self, id: TxNumber) -> ProviderResult<Option<TransactionSigned>> {
+        Ok(self.transaction_by_id_no_hash(id)?.map(TransactionSignedNoHash::with_hash))
+    }
+
+    fn transaction_by_id_no_hash(
+        &self,
+        id: TxNumber,
+    ) -> ProviderResult<Option<TransactionSignedNoHash>> {
+        if !self.is_committed(StaticFileSegment::Transactions, id) {
+            return Ok(None)
+        }
+        self.check_rows(StaticFileSegment::Transactions, id..id + 1)?;
+        let transaction = self.profile(StaticFileSegment::Transactions, id, || {
+            transaction_by_id_no_hash(self.provider(), id)
+        })?;
+        let Some(mut transaction) = transaction else { return Ok(None) };
+        self.row_transforms().invert_transaction(&mut transaction)?;
+        self.reassemble(id, &mut transaction)?;
+        Ok(Some(transaction))
+    }
+
+    fn transaction_by_hash(&self, hash: TxHash) -> ProviderResult<Option<TransactionSigned>> {
+        if let Some(transaction) = self.provider().transaction_by_hash(hash)? {
+            return Ok(Some(transaction))
+        }
+        let row = self.find_deduplicated_transaction(hash)?;
+        let Some(SegmentRow { value: SegmentValue::Transaction(mut transaction), .. }) = row else {
+            return Ok(None)
+        };
+        self.row_transforms().invert_transaction(&mut transaction)?;
+        Ok(Some(transaction.with_hash()))
+    }
+
+    fn transaction_by_hash_with_meta(
+        &self,
+        hash: TxHash,
+    ) -> ProviderResult<Option<(TransactionSigned, TransactionMeta)>> {
+        self.provider().transaction_by_hash_with_meta(hash)
+    }
+
+    fn transaction_block(&self, id: TxNumber) -> ProviderResult<Option<BlockNumber>> {
+        self.provider().transaction_block(id)
+    }
+
+    fn transactions_by_block(
+        &self,
+        block: BlockHashOrNumber,
+    ) -> ProviderResult<Option<Vec<TransactionSigned>>> {
+        self.rows_by_block(
+            StaticFileSegment::Transactions,
+            block,
+            |tx_range| {
+                let transactions = self.transactions_by_tx_range(tx_range)?;
+                Ok(transactions.into_iter().map(TransactionSignedNoHash::with_hash).collect())
+            },
+            || self.provider().transactions_by_block(block),
+        )
+    }
+
+    fn transactions_by_block_range(
+        &self,
+        range: impl RangeBounds<BlockNumber>,
+    ) -> ProviderResult<Vec<Vec<TransactionSigned>>> {
+        self.provider().transactions_by_block_range(range)
+    }
+
+    fn transactions_by_tx_range(
+        &self,
+        range: impl RangeBounds<TxNumber>,
+    ) -> ProviderResult<Vec<TransactionSignedNoHash>> {
+        let range = self.committed_range(StaticFileSegment::Transactions, range);
+        self.check_rows(StaticFileSegment::Transactions, range.clone())?;
+        let mut transactions = transactions_by_tx_range(self.provider(), range.clone())?;
+        for (tx_number, transaction) in range.zip(&mut transactions) {
+            self.row_transforms().invert_transaction(transaction)?;
+            self.reassemble(tx_number, transaction)?;
+        }
+        Ok(transactions)
+    }
+
+    fn senders_by_tx_range(
+        &self,
+        range: impl RangeBounds<TxNumber>,
+    ) -> ProviderResult<Vec<Address>> {
+        // Signatures cover the inputs of the transactions before they were deduplicated
+        let transactions = self.transactions_by_tx_range(range)?;
+        Ok(transactions.iter().map_while(TransactionSignedNoHash::recover_signer).collect())
+    }
+
+    fn transaction_sender(&self, id: TxNumber) -> ProviderResult<Option<Address>> {
+        let transaction = self.transaction_by_id_no_hash(id)?;
+        Ok(transaction.and_then(|transaction| transaction.recover_signer()))
+    }
+}
+
+impl ReceiptProvider for StaticFileReader {
+    fn receipt(&self, id: TxNumber) -> ProviderResult<Option<Receipt>> {
+        if !self.is_committed(StaticFileSegment::Receipts, id) {
+            return Ok(None)
+        }
+        self.check_rows(StaticFileSegment::Receipts, id..id + 1)?;
+        let receipt =
+            self.profile(StaticFileSegment::Receipts, id, || self.provider().receipt(id))?;
+        let Some(mut receipt) = receipt else { return Ok(None) };
+        self.row_transforms().invert_receipt(&mut receipt)?;
+        Ok(Some(receipt))
+    }
+
+    fn receipt_by_hash(&self, hash: TxHash) -> ProviderResult<Option<Receipt>> {
+        // Receipts keyed by transaction hash are found without the database index
+        let row = MultiSegmentReader::new(self, StaticFileSegment::Receipts)
+            .find_keyed_receipt(hash)
+            .map_err(|err| match err {
+                StaticFileReaderError::Provider(err) => err,
+                err => ProviderError::NippyJar(err.to_string()),
+            })?;
+        let Some(SegmentRow { value: SegmentValue::Receipt(mut receipt), .. }) = row else {
+            return self.provider().receipt_by_hash(hash)
+        };
+        self.row_transforms().invert_receipt(&mut receipt)?;
+        Ok(Some(receipt))
+    }
+
+    fn receipts_by_block(&self, block: BlockHashOrNumber) -> ProviderResult<Option<Vec<Receipt>>> {
+        self.rows_by_block(
+            StaticFileSegment::Receipts,
+            block,
+            |tx_range| self.receipts_by_tx_range(tx_range),
+            || self.provider().receipts_by_block(block),
+        )
+    }
+
+    fn receipts_by_tx_range(
+        &self,
+        range: impl RangeBounds<TxNumber>,
+    ) -> ProviderResult<Vec<Receipt>> {
+        let range = self.committed_range(StaticFileSegment::Receipts, range);
+        self.check_rows(StaticFileSegment::Receipts, range.clone())?;
+        let mut receipts = self.provider().receipts_by_tx_range(range)?;
+        for receipt in &mut receipts {
+            self.row_transforms().invert_receipt(receipt)?;
+        }
+        Ok(receipts)
+    }
+}
+
+impl StaticFileReader {
+    /// Returns the row of the transaction with the hash from the Transactions static files with a
+    /// [`DedupTable`](crate::DedupTable). The static file provider hashes the rows as they're
+    /// stored, so it doesn't find deduplicated transactions.
+    fn find_deduplicated_transaction(&self, hash: TxHash) -> ProviderResult<Option<SegmentRow>> {
+        MultiSegmentReader::new(self, StaticFileSegment::Transactions)
+            .find_deduplicated_transaction(hash)
+            .map_err(|err| match err {
+                StaticFileReaderError::Provider(err) => err,
+                err => ProviderError::NippyJar(err.to_string()),
+            })
+    }
+
+    /// Returns the rows of the block in the Transactions or Receipts segment, read with `rows`
+    /// from the transaction numbers in the block boundaries sidecar of its static file.
+    ///
+    /// Static files without the sidecar need the database to find the rows of the block, so
+    /// they're looked up with `fallback`. Returns `None` if the rows of the block are not
+    /// committed yet.
+    fn rows_by_block<T>(
+        &self,
+        segment: StaticFileSegment,
+        block: BlockHashOrNumber,
+        rows: impl FnOnce(Range<TxNumber>) -> ProviderResult<Vec<T>>,
+        fallback: impl FnOnce() -> ProviderResult<Option<Vec<T>>>,
+    ) -> ProviderResult<Option<Vec<T>>> {
+        let Some(number) = self.convert_hash_or_number(block)? else { return Ok(None) };
+        let tx_range = self
+            .block_tx_range(segment, number)
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+
+        let Some(tx_range) = tx_range else { return fallback() };
+        if !tx_range.is_empty() && !self.is_committed(segment, tx_range.end - 1) {
+            return Ok(None)
+        }
+        rows(tx_range).map(Some)
+    }
+}
@@ -0,0 +1,263 @@
+//! Handshake between the [`StaticFileProducer`](crate::StaticFileProducer) and the pruner of the
+//! database, persisted in the static files directory.
+//!
+//! After every commit, the producer records up to which block every segment is in static files,
+//! and may be pruned from the database. Once the pruner removed the rows, it confirms with
+//! [`StaticFileProducerInner::confirm_pruned`](crate::StaticFileProducerInner::confirm_pruned),
+//! advancing the pruned block. A crash between the copy and the prune leaves the blocks in
+//! between [pending](PruneCheckpoint::pending), instead of ambiguous.
+
+use crate::{manifest::write_json, rollback::sync_directory};
+use alloy_primitives::BlockNumber;
+use parking_lot::{const_mutex, Mutex, MutexGuard};
+use reth_static_file_types::StaticFileSegment;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader},
+    ops::RangeInclusive,
+    path::Path,
+};
+
+/// Name of the file in the static files directory that [`PruneCheckpoints`] are persisted to.
+pub const PRUNE_CHECKPOINTS_FILE_NAME: &str = "prune_checkpoints.json";
+
+/// Name of the file in the static files directory that's locked while [`PruneCheckpoints`] are
+/// updated, serializing the updates of the producer and the pruner across processes. Files are
+/// only locked on Unix, elsewhere updates are serialized within the process only.
+pub const PRUNE_CHECKPOINTS_LOCK_FILE_NAME: &str = "prune_checkpoints.lock";
+
+/// Serializes the updates of the producer and the pruner within the process, on platforms where
+/// the lock file isn't locked.
+static UPDATE_LOCK: Mutex<()> = const_mutex(());
+
+/// [`PruneCheckpoint`] of every segment, `None` for segments without static files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruneCheckpoints {
+    /// Checkpoint of the Headers segment.
+    pub headers: Option<PruneCheckpoint>,
+    /// Checkpoint of the Transactions segment.
+    pub transactions: Option<PruneCheckpoint>,
+    /// Checkpoint of the Receipts segment.
+    pub receipts: Option<PruneCheckpoint>,
+}
+
+impl PruneCheckpoints {
+    /// Returns the checkpoint of the segment, if any.
+    pub const fn get(&self, segment: StaticFileSegment) -> Option<PruneCheckpoint> {
+        match segment {
+            StaticFileSegment::Headers => self.headers,
+            StaticFileSegment::Transactions => self.transactions,
+            StaticFileSegment::Receipts => self.receipts,
+        }
+    }
+
+    /// Returns a mutable reference to the checkpoint of the segment.
+    fn as_mut(&mut self, segment: StaticFileSegment) -> &mut Option<PruneCheckpoint> {
+        match segment {
+            StaticFileSegment::Headers => &mut self.headers,
+            StaticFileSegment::Transactions => &mut self.transactions,
+            StaticFileSegment::Receipts => &mut self.receipts,
+        }
+    }
+
+    /// Reads the prune checkpoints persisted in the static files directory. Returns no
+    /// checkpoints if nothing was persisted yet.
+    pub fn read(directory: &Path) -> io::Result<Self> {
+        let path = directory.join(PRUNE_CHECKPOINTS_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Updates the persisted prune checkpoints. Readers see either the previous or the updated
+    /// checkpoints, never a partially written file, and the update survives a crash once it
+    /// returns.
+    fn update<T>(directory: &Path, update: impl FnOnce(&mut Self) -> T) -> io::Result<T> {
+        let _lock = lock_updates(directory)?;
+        let mut checkpoints = Self::read(directory)?;
+        let result = update(&mut checkpoints);
+
+        let path = directory.join(PRUNE_CHECKPOINTS_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+        write_json(&checkpoints, &tmp_path)?;
+        File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(tmp_path, path)?;
+        sync_directory(directory)?;
+        Ok(result)
+    }
+}
+
+/// Locks the prune checkpoints of the static files directory for an update. The lock is released
+/// once the returned guard and file are dropped.
+fn lock_updates(directory: &Path) -> io::Result<(MutexGuard<'static, ()>, File)> {
+    let guard = UPDATE_LOCK.lock();
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(directory.join(PRUNE_CHECKPOINTS_LOCK_FILE_NAME))?;
+    lock_exclusive(&file)?;
+    Ok((guard, file))
+}
+
+/// Blocks until the file is exclusively locked. The lock is held by the open file, so it also
+/// serializes threads of the same process opening the file separately.
+#[cfg(unix)]
+fn lock_exclusive(file: &File) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    loop {
+        // SAFETY: the descriptor is owned by `file`, which outlives the call.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(())
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err)
+        }
+    }
+}
+
+/// Blocks until the file is exclusively locked. Files are only locked on Unix, so updates of other
+/// processes aren't serialized.
+#[cfg(not(unix))]
+fn lock_exclusive(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Prune checkpoint of a segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruneCheckpoint {
+    /// Highest block of the segment committed to static files, which the database may be pruned
+    /// up to.
+    pub prunable_up_to: BlockNumber,
+    /// Highest block the pruner confirmed to have pruned from the database, if any.
+    pub pruned_up_to: Option<BlockNumber>,
+}
+
+impl PruneCheckpoint {
+    /// Returns the blocks that may be pruned from the database, but weren't confirmed to be, if
+    /// any.
+    pub fn pending(&self) -> Option<RangeInclusive<BlockNumber>> {
+        let start = self.pruned_up_to.map_or(0, |pruned| pruned + 1);
+        (start <= self.prunable_up_to).then(|| start..=self.prunable_up_to)
+    }
+}
+
+/// Records that the segments are in static files up to their highest blocks, and may be pruned
+/// from the database up to them. Segments without static files lose their checkpoint.
+///
+/// The pruned block is kept, even if static files were unwound below it, since pruned rows can't
+/// be restored in the database.
+pub(crate) fn record_prunable(
+    directory: &Path,
+    segments: impl IntoIterator<Item = (StaticFileSegment, Option<BlockNumber>)>,
+) -> io::Result<PruneCheckpoints> {
+    PruneCheckpoints::update(directory, |checkpoints| {
+        for (segment, highest) in segments {
+            let checkpoint = checkpoints.as_mut(segment);
+            *checkpoint = highest.map(|prunable_up_to| PruneCheckpoint {
+                prunable_up_to,
+                pruned_up_to: checkpoint.and_then(|checkpoint| checkpoint.pruned_up_to),
+            });
+        }
+        *checkpoints
+    })
+}
+
+/// Advances the pruned block of the segment to `block`, once the pruner removed its rows up to
+/// it from the database. Returns the updated checkpoint, or `Ok(None)` without updating it if
+/// the block isn't prunable yet.
+pub(crate) fn confirm_pruned(
+    directory: &Path,
+    segment: StaticFileSegment,
+    block: BlockNumber,
+) -> io::Result<Option<PruneCheckpoint>> {
+    PruneCheckpoints::update(directory, |checkpoints| {
+        let Some(checkpoint) =
+            checkpoints.as_mut(segment).as_mut().filter(|c| block <= c.prunable_up_to)
+        else {
+            return None
+        };
+        checkpoint.pruned_up_to = checkpoint.pruned_up_to.max(Some(block));
+        Some(*checkpoint)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prune_handshake() {
+        let directory = tempfile::tempdir().unwrap();
+        assert_eq!(PruneCheckpoints::read(directory.path()).unwrap(), PruneCheckpoints::default());
+        let segment = StaticFileSegment::Receipts;
+
+        // Nothing is prunable before it's in static files
+        assert_eq!(confirm_pruned(directory.path(), segment, 0).unwrap(), None);
+
+        let checkpoints = record_prunable(directory.path(), [(segment, Some(99))]).unwrap();
+        assert_eq!(checkpoints.get(segment).unwrap().pending(), Some(0..=99));
+        assert_eq!(checkpoints.get(StaticFileSegment::Headers), None);
+
+        // Confirmed up to the prunable block only, and never backwards
+        assert_eq!(confirm_pruned(directory.path(), segment, 100).unwrap(), None);
+        confirm_pruned(directory.path(), segment, 49).unwrap();
+        let checkpoint = confirm_pruned(directory.path(), segment, 30).unwrap().unwrap();
+        assert_eq!(checkpoint, PruneCheckpoint { prunable_up_to: 99, pruned_up_to: Some(49) });
+        assert_eq!(checkpoint.pending(), Some(50..=99));
+
+        // Pruned block survives the next commit
+        record_prunable(directory.path(), [(segment, Some(199))]).unwrap();
+        let checkpoint = confirm_pruned(directory.path(), segment, 199).unwrap().unwrap();
+        assert_eq!(checkpoint.pending(), None);
+        assert_eq!(
+            PruneCheckpoints::read(directory.path()).unwrap().get(segment),
+            Some(checkpoint)
+        );
+    }
+
+    #[test]
+    fn concurrent_updates() {
+        let directory = tempfile::tempdir().unwrap();
+        let segment = StaticFileSegment::Receipts;
+        record_prunable(directory.path(), [(segment, Some(999))]).unwrap();
+
+        // Updates of separate threads aren't lost
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let directory = directory.path();
+                scope.spawn(move || {
+                    for block in (thread..100).step_by(4) {
+                        confirm_pruned(directory, segment, block).unwrap();
+                    }
+                });
+            }
+        });
+        let checkpoint = PruneCheckpoints::read(directory.path()).unwrap().get(segment).unwrap();
+        assert_eq!(checkpoint.pruned_up_to, Some(99));
+        assert!(directory.path().join(PRUNE_CHECKPOINTS_LOCK_FILE_NAME).exists());
+    }
+
+    #[test]
+    fn failed_record_after_commit() {
+        let harness = crate::test_utils::StaticFileTestHarness::new(3, 1..3);
+        let directory = harness.static_files_dir.path();
+        std::fs::create_dir(directory.join(PRUNE_CHECKPOINTS_FILE_NAME)).unwrap();
+
+        // The run committed its static files, so failing to record them as prunable only keeps
+        // the database from being pruned
+        harness.run().unwrap();
+        assert!(harness.producer().prune_checkpoints().is_err());
+        assert_eq!(
+            harness
+                .provider_factory
+                .static_file_provider()
+                .get_highest_static_file_block(StaticFileSegment::Headers),
+            Some(harness.tip())
+        );
+    }
+}
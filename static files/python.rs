@@ -0,0 +1,236 @@
+//! Python bindings of the [`StaticFileReader`], so static files can be read from Python, e.g. by
+//! data scientists, without round-tripping through exports.
+//!
+//! Rows are converted to Python objects directly, as dictionaries in the format of
+//! [`export::jsonl`]:
+//!
+//! ```python
+//! from reth_static_file import Reader
+//!
+//! reader = Reader("/path/to/static_files")
+//! header = reader.header(17_000_000)
+//! for receipt in reader.rows("receipts", 17_000_000, 17_000_099):
+//!     print(receipt["txNumber"], receipt["status"])
+//! ```
+
+use crate::{
+    export::{self, Line},
+    list_static_files, FileReadStats, ReadProfiler, StaticFileReader, StaticFileReaderError,
+};
+use alloy_primitives::BlockNumber;
+use pyo3::{
+    exceptions::{PyIOError, PyLookupError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+use pythonize::pythonize;
+use reth_nippy_jar::NippyJar;
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::{SegmentHeader, StaticFileSegment};
+use std::{collections::VecDeque, ops::RangeInclusive, path::PathBuf, sync::Arc};
+
+/// Reader of the static files of a directory, opened read-only.
+#[pyclass(name = "Reader", frozen)]
+#[derive(Debug)]
+pub struct PyReader {
+    /// Reader of the static files, shared with the row iterators.
+    reader: Arc<StaticFileReader>,
+    /// Profiler of the reads, inactive unless enabled with `set_profiling`.
+    profiler: ReadProfiler,
+}
+
+#[pymethods]
+impl PyReader {
+    /// Opens the static files directory read-only.
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Self> {
+        let provider = StaticFileProvider::read_only(path)
+            .map_err(|err| PyIOError::new_err(err.to_string()))?;
+        let mut reader = StaticFileReader::new(provider)?;
+        let profiler = ReadProfiler::new();
+        reader.set_profiler(Some(profiler.clone()));
+        Ok(Self { reader: Arc::new(reader), profiler })
+    }
+
+    /// Returns the header of the block as a dictionary, or `None` if it's not in static files.
+    fn header(&self, py: Python<'_>, block: BlockNumber) -> PyResult<Option<PyObject>> {
+        let mut rows =
+            RowIterator::new(self.reader.clone(), StaticFileSegment::Headers, block..=block);
+        match rows.next_line(py) {
+            Err(err) if err.is_instance_of::<PyLookupError>(py) => Ok(None),
+            result => result,
+        }
+    }
+
+    /// Returns an iterator over the rows of the segment, `headers`, `transactions` or `receipts`,
+    /// for the blocks from `start` to `end` inclusive. Rows are read one block at a time.
+    fn rows(&self, segment: &str, start: BlockNumber, end: BlockNumber) -> PyResult<RowIterator> {
+        let segment = segment
+            .parse::<StaticFileSegment>()
+            .map_err(|_| PyValueError::new_err(format!("unknown segment: {segment}")))?;
+        Ok(RowIterator::new(self.reader.clone(), segment, start..=end))
+    }
+
+    /// Returns the statistics of every static file of the directory, sorted by segment and block
+    /// range: segment, block and transaction ranges, number of rows and size in bytes. Receipts
+    /// static files also have their gas used, logs and contract creations, if they were tallied.
+    fn stats(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        let mut stats = Vec::new();
+        for entry in list_static_files(self.reader.provider().directory())? {
+            let jar = NippyJar::<SegmentHeader>::load(&entry.path)
+                .map_err(|err| PyIOError::new_err(err.to_string()))?;
+            let header = jar.user_header();
+            let size = entry
+                .paths()
+                .iter()
+                .filter_map(|path| path.metadata().ok())
+                .map(|metadata| metadata.len());
+
+            let file = PyDict::new_bound(py);
+            file.set_item("segment", entry.segment.as_str())?;
+            file.set_item("block_start", entry.block_range.start())?;
+            file.set_item("block_end", entry.block_range.end())?;
+            file.set_item("tx_start", header.tx_start())?;
+            file.set_item("tx_end", header.tx_end())?;
+            file.set_item("rows", jar.rows())?;
+            file.set_item("size", size.sum::<u64>())?;
+            if let Some(receipt_stats) = header.receipt_stats() {
+                file.set_item("gas_used", receipt_stats.gas_used)?;
+                file.set_item("logs", receipt_stats.logs)?;
+                file.set_item("contract_creations", receipt_stats.contract_creations)?;
+            }
+            stats.push(file.into_any().unbind());
+        }
+        Ok(stats)
+    }
+
+    /// Starts or stops sampling the latencies of reads, returned by `read_stats`.
+    fn set_profiling(&self, active: bool) {
+        self.profiler.set_active(active);
+    }
+
+    /// Returns the read statistics of every static file read while profiling, sorted by segment
+    /// and block range.
+    fn read_stats(&self, py: Python<'_>) -> PyResult<Vec<PyObject>> {
+        self.profiler.stats().iter().map(|stats| read_stats_dict(py, stats)).collect()
+    }
+}
+
+/// Iterator over the rows of a block range, returned by `Reader.rows`.
+#[pyclass(name = "RowIterator")]
+#[derive(Debug)]
+pub struct RowIterator {
+    /// Reader of the static files.
+    reader: Arc<StaticFileReader>,
+    /// Segment that's read.
+    segment: StaticFileSegment,
+    /// Blocks that aren't read yet.
+    blocks: RangeInclusive<BlockNumber>,
+    /// Rows of the last read block that aren't returned yet.
+    buffered: VecDeque<Line>,
+}
+
+impl RowIterator {
+    /// Creates a new [`RowIterator`] over the rows of the segment for the block range.
+    const fn new(
+        reader: Arc<StaticFileReader>,
+        segment: StaticFileSegment,
+        blocks: RangeInclusive<BlockNumber>,
+    ) -> Self {
+        Self { reader, segment, blocks, buffered: VecDeque::new() }
+    }
+
+    /// Returns the next row, reading the next blocks until one has rows.
+    fn next_line(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let line = loop {
+            if let Some(line) = self.buffered.pop_front() {
+                break line
+            }
+            let Some(block) = self.blocks.next() else { return Ok(None) };
+            let (reader, segment) = (&self.reader, self.segment);
+            self.buffered =
+                py.allow_threads(|| export::block_lines(reader, segment, block))?.into();
+        };
+
+        Ok(Some(pythonize(py, &line)?))
+    }
+}
+
+#[pymethods]
+impl RowIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        self.next_line(py)
+    }
+}
+
+/// Returns the read statistics as a dictionary, with latencies in seconds.
+fn read_stats_dict(py: Python<'_>, stats: &FileReadStats) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    dict.set_item("segment", stats.segment.as_str())?;
+    dict.set_item("block_start", stats.block_range.start())?;
+    dict.set_item("block_end", stats.block_range.end())?;
+    dict.set_item("reads", stats.reads)?;
+    dict.set_item("mean_latency", stats.mean_latency().map(|latency| latency.as_secs_f64()))?;
+    dict.set_item("max_latency", stats.max_latency.as_secs_f64())?;
+    dict.set_item("false_positive_rate", stats.false_positive_rate())?;
+    Ok(dict.into_any().unbind())
+}
+
+impl From<StaticFileReaderError> for PyErr {
+    fn from(err: StaticFileReaderError) -> Self {
+        match err {
+            StaticFileReaderError::MissingStaticFiles { .. } => {
+                PyLookupError::new_err(err.to_string())
+            }
+            err => PyIOError::new_err(err.to_string()),
+        }
+    }
+}
+
+/// Python module of the bindings, importable as `reth_static_file`.
+#[pymodule]
+fn reth_static_file(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyReader>()?;
+    module.add_class::<RowIterator>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_provider::StaticFileProviderFactory;
+
+    #[test]
+    fn reads_from_python() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let directory = harness.provider_factory.static_file_provider().directory().to_path_buf();
+
+        Python::with_gil(|py| {
+            let reader = PyReader::new(directory).unwrap();
+            let header = reader.header(py, 2).unwrap().unwrap();
+            let hash = header.bind(py).get_item("hash").unwrap().extract::<String>().unwrap();
+            assert_eq!(hash, harness.blocks[2].hash().to_string());
+            assert!(reader.header(py, 4).unwrap().is_none());
+
+            let receipts = harness.blocks.iter().map(|block| block.body.len()).sum::<usize>();
+            let mut rows = reader.rows("receipts", 0, 3).unwrap();
+            let mut count = 0;
+            while let Some(receipt) = rows.next_line(py).unwrap() {
+                let tx_number = receipt.bind(py).get_item("txNumber").unwrap();
+                assert_eq!(tx_number.extract::<u64>().unwrap(), count as u64);
+                count += 1;
+            }
+            assert_eq!(count, receipts);
+            assert!(reader.rows("bodies", 0, 3).is_err());
+
+            let stats = reader.stats(py).unwrap();
+            assert_eq!(stats.len(), 3);
+        });
+    }
+}
@@ -0,0 +1,196 @@
+//! Disk quota enforced before producing static files.
+
+use crate::{lowest_static_files, StaticFileEntry};
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use std::{fmt, io, path::Path};
+
+/// Disk budget of the static files directory, checked before every
+/// [`StaticFileProducerInner::run`](crate::StaticFileProducerInner::run), so the run is refused
+/// up front instead of failing in the middle of a write when the disk is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskQuota {
+    /// Maximum size of all static files in the directory, in bytes.
+    pub max_disk_bytes: Option<u64>,
+    /// Minimum free space to leave on the filesystem of the directory, in bytes.
+    pub min_free_bytes: Option<u64>,
+}
+
+impl DiskQuota {
+    /// Sets the maximum size of all static files in the directory, in bytes.
+    pub const fn with_max_disk_bytes(mut self, max_disk_bytes: u64) -> Self {
+        self.max_disk_bytes = Some(max_disk_bytes);
+        self
+    }
+
+    /// Sets the minimum free space to leave on the filesystem of the directory, in bytes.
+    pub const fn with_min_free_bytes(mut self, min_free_bytes: u64) -> Self {
+        self.min_free_bytes = Some(min_free_bytes);
+        self
+    }
+
+    /// Checks whether writing `estimated_bytes` more to the static files directory stays within
+    /// the quota. Returns the violated limit, if any.
+    ///
+    /// Free space is only checked on Unix platforms.
+    pub fn check(
+        &self,
+        directory: &Path,
+        entries: &[StaticFileEntry],
+        estimated_bytes: u64,
+    ) -> io::Result<Option<QuotaViolation>> {
+        if let Some(max_disk_bytes) = self.max_disk_bytes {
+            let used_bytes = static_files_size(entries)?;
+            if used_bytes.saturating_add(estimated_bytes) > max_disk_bytes {
+                return Ok(Some(QuotaViolation::MaxDiskBytes {
+                    used_bytes,
+                    estimated_bytes,
+                    max_disk_bytes,
+                }))
+            }
+        }
+
+        if let Some(min_free_bytes) = self.min_free_bytes {
+            if let Some(available_bytes) = available_space(directory)? {
+                if available_bytes < estimated_bytes.saturating_add(min_free_bytes) {
+                    return Ok(Some(QuotaViolation::MinFreeBytes {
+                        available_bytes,
+                        estimated_bytes,
+                        min_free_bytes,
+                    }))
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Limit of a [`DiskQuota`] that a run would violate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaViolation {
+    /// Static files would exceed the maximum size of the directory.
+    MaxDiskBytes {
+        /// Size of the static files before the run.
+        used_bytes: u64,
+        /// Estimated size written by the run.
+        estimated_bytes: u64,
+        /// Maximum size of the directory.
+        max_disk_bytes: u64,
+    },
+    /// The filesystem would have less than the minimum free space left.
+    MinFreeBytes {
+        /// Free space on the filesystem before the run.
+        available_bytes: u64,
+        /// Estimated size written by the run.
+        estimated_bytes: u64,
+        /// Minimum free space to leave.
+        min_free_bytes: u64,
+    },
+}
+
+impl fmt::Display for QuotaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MaxDiskBytes { used_bytes, estimated_bytes, max_disk_bytes } => write!(
+                f,
+                "static files would use {used_bytes} + {estimated_bytes} bytes, exceeding the budget of {max_disk_bytes} bytes"
+            ),
+            Self::MinFreeBytes { available_bytes, estimated_bytes, min_free_bytes } => write!(
+                f,
+                "writing {estimated_bytes} bytes of static files would leave less than {min_free_bytes} of {available_bytes} free bytes"
+            ),
+        }
+    }
+}
+
+/// Estimates the size of `blocks` more blocks of the segment, from the average size of a block
+/// in the existing static files of the segment.
+///
+/// Returns zero if the segment has no static files yet.
+pub fn estimate_bytes(
+    entries: &[StaticFileEntry],
+    highest_static_files: &HighestStaticFiles,
+    segment: StaticFileSegment,
+    blocks: u64,
+) -> io::Result<u64> {
+    let Some(available) =
+        lowest_static_files(entries).available_range(segment, highest_static_files)
+    else {
+        return Ok(0)
+    };
+
+    let size = static_files_size(entries.iter().filter(|entry| entry.segment == segment))?;
+    Ok((size / available.len().max(1)).saturating_mul(blocks))
+}
+
+/// Returns the total size of the data and companion files of the static files.
+pub(crate) fn static_files_size<'a>(
+    entries: impl IntoIterator<Item = &'a StaticFileEntry>,
+) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in entries {
+        for path in entry.paths() {
+            size += path.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Returns the space available to unprivileged users on the filesystem of the path.
+#[cfg(unix)]
+fn available_space(path: &Path) -> io::Result<Option<u64>> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `stat` is zeroed and only read after `statvfs` filled it.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is a valid NUL-terminated string and `stat` a valid pointer to a `statvfs`.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64)))
+}
+
+/// Returns the space available to unprivileged users on the filesystem of the path.
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::list_static_files;
+
+    #[test]
+    fn disk_quota() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("static_file_headers_0_499999"), [0; 900]).unwrap();
+        std::fs::write(directory.path().join("static_file_headers_0_499999.off"), [0; 100])
+            .unwrap();
+        let entries = list_static_files(directory.path()).unwrap();
+        let highest = HighestStaticFiles { headers: Some(99), ..Default::default() };
+
+        // 1000 bytes for 100 blocks
+        let estimated = estimate_bytes(&entries, &highest, StaticFileSegment::Headers, 50).unwrap();
+        assert_eq!(estimated, 500);
+        assert_eq!(estimate_bytes(&entries, &highest, StaticFileSegment::Receipts, 50).unwrap(), 0);
+
+        let quota = DiskQuota::default().with_max_disk_bytes(1_500);
+        assert_eq!(quota.check(directory.path(), &entries, estimated).unwrap(), None);
+        assert_eq!(
+            quota.check(directory.path(), &entries, estimated + 1).unwrap(),
+            Some(QuotaViolation::MaxDiskBytes {
+                used_bytes: 1_000,
+                estimated_bytes: 501,
+                max_disk_bytes: 1_500
+            })
+        );
+
+        #[cfg(unix)]
+        assert!(matches!(
+            DiskQuota::default().with_min_free_bytes(u64::MAX).check(directory.path(), &entries, 0),
+            Ok(Some(QuotaViolation::MinFreeBytes { .. }))
+        ));
+    }
+}
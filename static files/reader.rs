@@ -0,0 +1,826 @@
+//! Reader of static files and their sidecars, for serving historical data.
+
+pub mod rpc_types;
+
+use crate::{
+    accumulator::{
+        epoch, epoch_end, read_epoch_roots, EpochAccumulator, HeaderProof, HeaderRecord,
+        EPOCH_SIZE, MERGE_BLOCK,
+    },
+    dedup::DedupTables,
+    list_static_files, pin_reader,
+    sidecar::static_files_in_range,
+    BlockBoundaries, CommittedRows, IndexRow, LogIndex, ReadProfiler, ReaderGuard, RowTransforms,
+    SenderIndex, Sidecar, TransactionBoundaries,
+};
+use alloy_primitives::{Address, BlockNumber, Log, TxNumber, B256, U256};
+use reth_db_api::{models::CompactU256, table::Decompress};
+use reth_nippy_jar::NippyJar;
+use reth_primitives::{
+    Header, TransactionSigned, TransactionSignedEcRecovered, TransactionSignedNoHash,
+};
+use reth_provider::{
+    providers::{StaticFileJarProvider, StaticFileProvider},
+    BlockHashReader, HeaderProvider, ReceiptProvider, TransactionsProvider,
+};
+use reth_static_file_types::{
+    find_fixed_range, ColumnMismatch, FilterKey, IncompatibleSchemaVersion, SegmentHeader,
+    SegmentRangeInclusive, StaticFileSegment, UnsupportedFeature,
+};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{
+    fmt, io,
+    ops::{Bound, Range, RangeBounds, RangeInclusive},
+    sync::Arc,
+    time::Instant,
+};
+
+/// Number of canonical hashes read from static files at once by
+/// [`StaticFileReader::verify_against`].
+const VERIFY_CHUNK_SIZE: usize = 10_000;
+
+/// Error returned by [`StaticFileReader`].
+#[derive(Debug)]
+pub enum StaticFileReaderError {
+    /// Error while reading from static files.
+    Provider(ProviderError),
+    /// Filesystem error while reading a sidecar.
+    Io(io::Error),
+    /// The root of the epoch computed from static files doesn't match the epoch roots sidecar.
+    EpochRootMismatch {
+        /// Epoch of the accumulator.
+        epoch: u64,
+        /// Root recorded in the sidecar.
+        expected: B256,
+        /// Root computed from static files.
+        got: B256,
+    },
+    /// The canonical hash of the block in static files doesn't match the externally supplied
+    /// hash.
+    CanonicalHashMismatch {
+        /// Block of the hash.
+        block: BlockNumber,
+        /// Externally supplied hash.
+        expected: B256,
+        /// Hash in static files, if the block is in static files.
+        got: Option<B256>,
+    },
+    /// The static file was produced with a schema version whose rows can't be decoded.
+    IncompatibleSchema {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+        /// Schema versions of the static file and this build.
+        err: IncompatibleSchemaVersion,
+    },
+    /// The static file uses an inclusion filter or perfect hashing function that's not understood
+    /// by this build, e.g. because it was produced by a newer version.
+    UnsupportedFeature {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+        /// The feature and the version it requires.
+        err: UnsupportedFeature,
+    },
+    /// The columns of the static file are not the ones of its segment in this build, e.g.
+    /// because columns were added to the segment by a newer version.
+    ColumnMismatch {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+        /// Expected and found columns.
+        err: ColumnMismatch,
+    },
+    /// Blocks of the requested range are not in static files, e.g. because a static file is
+    /// missing or not filled up yet.
+    MissingStaticFiles {
+        /// Segment of the static files.
+        segment: StaticFileSegment,
+        /// First run of missing blocks.
+        gap: SegmentRangeInclusive,
+    },
+}
+
+impl From<ProviderError> for StaticFileReaderError {
+    fn from(value: ProviderError) -> Self {
+        Self::Provider(value)
+    }
+}
+
+impl From<io::Error> for StaticFileReaderError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl fmt::Display for StaticFileReaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Provider(err) => fmt::Display::fmt(err, f),
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::EpochRootMismatch { epoch, expected, got } => {
+                write!(f, "root of epoch {epoch} is {got}, but {expected} is recorded")
+            }
+            Self::CanonicalHashMismatch { block, expected, got: Some(got) } => {
+                write!(f, "canonical hash of block {block} is {got}, but {expected} is expected")
+            }
+            Self::CanonicalHashMismatch { block, expected, got: None } => {
+                write!(f, "block {block} with canonical hash {expected} is not in static files")
+            }
+            Self::IncompatibleSchema { segment, block_range, err } => {
+                write!(f, "{segment} static file {block_range}: {err}")
+            }
+            Self::UnsupportedFeature { segment, block_range, err } => {
+                write!(f, "{segment} static file {block_range}: {err}")
+            }
+            Self::ColumnMismatch { segment, block_range, err } => {
+                write!(f, "{segment} static file {block_range}: {err}")
+            }
+            Self::MissingStaticFiles { segment, gap } => {
+                write!(f, "blocks {gap} are missing in {segment} static files")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StaticFileReaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Provider(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::IncompatibleSchema { err, .. } => Some(err),
+            Self::UnsupportedFeature { err, .. } => Some(err),
+            Self::ColumnMismatch { err, .. } => Some(err),
+            Self::EpochRootMismatch { .. } |
+            Self::CanonicalHashMismatch { .. } |
+            Self::MissingStaticFiles { .. } => None,
+        }
+    }
+}
+
+/// Log found through the [`LogIndex`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedLog {
+    /// Block of the log.
+    pub block: BlockNumber,
+    /// Transaction number of the receipt with the log.
+    pub tx_number: TxNumber,
+    /// Index of the log within the receipt.
+    pub log_index: usize,
+    /// The log.
+    pub log: Log,
+}
+
+/// Transaction found through the [`SenderIndex`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SenderTransaction {
+    /// Block of the transaction.
+    pub block: BlockNumber,
+    /// Transaction number.
+    pub tx_number: TxNumber,
+    /// The transaction.
+    pub transaction: TransactionSigned,
+}
+
+/// Reader of static files and their sidecars.
+///
+/// Implements [`HeaderProvider`], [`TransactionsProvider`] and [`ReceiptProvider`], so it can
+/// serve data from a static files directory in place of a database provider.
+///
+/// The reader is [pinned](pin_reader) while it's alive, so static files removed by retention
+/// aren't deleted while its provider may still have them mapped. Refreshing the reader pins it
+/// again, releasing the static files removed before.
+#[derive(Debug, Clone)]
+pub struct StaticFileReader {
+    /// Provider of the static files.
+    provider: StaticFileProvider,
+    /// Pin of the reader, shared by its clones.
+    _guard: Arc<ReaderGuard>,
+    /// Epoch roots of the header accumulator, loaded from the sidecar.
+    epoch_roots: Vec<B256>,
+    /// Last committed rows, loaded from the static files directory. Rows after them are not
+    /// served.
+    committed_rows: Option<CommittedRows>,
+    /// Profiler sampling the reads while it's active. Disabled by default.
+    profiler: Option<ReadProfiler>,
+    /// Dedup tables of the Transactions static files read so far.
+    dedup_tables: DedupTables,
+    /// Transforms inverted on the rows read by number. None by default.
+    row_transforms: RowTransforms,
+    /// Secret of the [`FilterHash::KeyedSipHash`](reth_static_file_types::FilterHash) of the
+    /// filter keys of the static files, if any.
+    filter_key: Option<FilterKey>,
+}
+
+impl StaticFileReader {
+    /// Creates a new [`StaticFileReader`], loading the sidecars and committed rows from the
+    /// static files directory.
+    pub fn new(provider: StaticFileProvider) -> io::Result<Self> {
+        let guard = Arc::new(pin_reader(provider.directory()));
+        let epoch_roots = read_epoch_roots(provider.directory())?;
+        let committed_rows = CommittedRows::read(provider.directory())?;
+        Ok(Self {
+            provider,
+            _guard: guard,
+            epoch_roots,
+            committed_rows,
+            profiler: None,
+            dedup_tables: DedupTables::default(),
+            row_transforms: RowTransforms::default(),
+            filter_key: None,
+        })
+    }
+
+    /// Sets the [`ReadProfiler`] sampling the latencies of reads by number and the outcomes of
+    /// lookups by hash through inclusion filters. `None` disables profiling.
+    pub fn set_profiler(&mut self, profiler: Option<ReadProfiler>) {
+        self.profiler = profiler;
+    }
+
+    /// Sets the secret the filter keys of the static files were hashed with, if they're keyed
+    /// with [`FilterHash::KeyedSipHash`](reth_static_file_types::FilterHash). Without it, their
+    /// rows are found through the Transactions static files instead of their filters.
+    pub fn set_filter_key(&mut self, filter_key: Option<FilterKey>) {
+        self.filter_key = filter_key;
+    }
+
+    /// Returns the secret the filter keys of the static files were hashed with, if set.
+    pub(crate) const fn filter_key(&self) -> Option<&FilterKey> {
+        self.filter_key.as_ref()
+    }
+
+    /// Sets the [`RowTransforms`] the static files were produced with, inverted on the rows read
+    /// by block or transaction number.
+    pub fn set_row_transforms(&mut self, row_transforms: RowTransforms) {
+        self.row_transforms = row_transforms;
+    }
+
+    /// Returns the [`RowTransforms`] inverted on the rows read by number.
+    pub(crate) const fn row_transforms(&self) -> &RowTransforms {
+        &self.row_transforms
+    }
+
+    /// Returns the [`ReadProfiler`] of the reader, if it's set and active.
+    pub(crate) fn active_profiler(&self) -> Option<&ReadProfiler> {
+        self.profiler.as_ref().filter(|profiler| profiler.is_active())
+    }
+
+    /// Serves the read of the row of the segment, recording its latency in the static file of the
+    /// row if the [`ReadProfiler`] is active.
+    pub(crate) fn profile<T>(
+        &self,
+        segment: StaticFileSegment,
+        row: u64,
+        read: impl FnOnce() -> ProviderResult<T>,
+    ) -> ProviderResult<T> {
+        let Some(profiler) = self.active_profiler() else { return read() };
+
+        let start = Instant::now();
+        let value = read()?;
+        let latency = start.elapsed();
+
+        // The static file of transaction-keyed rows is looked up after the read, so it's not timed
+        let block_range = match segment {
+            StaticFileSegment::Headers => Some(find_fixed_range(row)),
+            StaticFileSegment::Transactions | StaticFileSegment::Receipts => self
+                .provider
+                .get_segment_provider_from_transaction(segment, row, None)
+                .ok()
+                .map(|jar| find_fixed_range(jar.user_header().expected_block_start())),
+        };
+        if let Some(block_range) = block_range {
+            profiler.record_read(segment, block_range, latency);
+        }
+        Ok(value)
+    }
+
+    /// Reloads the sidecars and committed rows from the static files directory, e.g. after a
+    /// producer run.
+    pub fn reload(&mut self) -> io::Result<()> {
+        self.epoch_roots = read_epoch_roots(self.provider.directory())?;
+        self.committed_rows = CommittedRows::read(self.provider.directory())?;
+        self.dedup_tables.clear();
+        Ok(())
+    }
+
+    /// Refreshes a reader of a read-only secondary process, e.g. an indexer, mapping the static
+    /// files of a producer running in another process.
+    ///
+    /// If the producer published a commit since the last refresh, the static files are indexed
+    /// and mapped again, so rows appended to the last static files become readable, and the
+    /// reader is clamped to the new committed rows. Returns `true` if a commit was published.
+    pub fn refresh(&mut self) -> io::Result<bool> {
+        let committed_rows = CommittedRows::read(self.provider.directory())?;
+        if committed_rows == self.committed_rows {
+            return Ok(false)
+        }
+
+        // Pinned again before the static files are listed, as the index is rebuilt from them
+        let guard = Arc::new(pin_reader(self.provider.directory()));
+        self.provider.initialize_index().map_err(io::Error::other)?;
+        self.epoch_roots = read_epoch_roots(self.provider.directory())?;
+        self.committed_rows = committed_rows;
+        self.dedup_tables.clear();
+        self._guard = guard;
+        Ok(true)
+    }
+
+    /// Returns the last committed rows the reader is clamped to, as of the last
+    /// [reload](StaticFileReader::reload).
+    ///
+    /// Numbered lookups of rows after them return nothing, so a static file that's being
+    /// appended to is never read in a torn state. If `None`, nothing was published by the
+    /// producer and no rows are hidden.
+    pub const fn committed_rows(&self) -> Option<CommittedRows> {
+        self.committed_rows
+    }
+
+    /// Returns `true` if the row of the segment may be served, see
+    /// [`StaticFileReader::committed_rows`].
+    pub(crate) fn is_committed(&self, segment: StaticFileSegment, row: u64) -> bool {
+        self.committed_rows.map_or(true, |committed| committed.is_committed(segment, row))
+    }
+
+    /// Checks every static file of the segment holding rows of the range with
+    /// [`check_static_file`], before the rows are decoded. Rows missing from static files are
+    /// left to the read.
+    pub(crate) fn check_rows(
+        &self,
+        segment: StaticFileSegment,
+        rows: Range<u64>,
+    ) -> ProviderResult<()> {
+        let mut row = rows.start;
+        while row < rows.end {
+            let jar = match segment {
+                StaticFileSegment::Headers => {
+                    self.provider.get_segment_provider_from_block(segment, row, None)
+                }
+                StaticFileSegment::Transactions | StaticFileSegment::Receipts => {
+                    self.provider.get_segment_provider_from_transaction(segment, row, None)
+                }
+            };
+            let jar = match jar {
+                Ok(jar) => jar,
+                Err(
+                    ProviderError::MissingStaticFileBlock(..) |
+                    ProviderError::MissingStaticFileTx(..),
+                ) => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            let header = jar.user_header();
+            check_static_file(header, jar.columns())
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+
+            // The next static file starts after the last row of this one
+            let end = if segment.is_headers() { header.block_end() } else { header.tx_end() };
+            let Some(end) = end else { return Ok(()) };
+            row = end + 1;
+        }
+        Ok(())
+    }
+
+    /// Clamps the range of rows of the segment to the last committed row, see
+    /// [`StaticFileReader::committed_rows`].
+    pub(crate) fn committed_range(
+        &self,
+        segment: StaticFileSegment,
+        range: impl RangeBounds<u64>,
+    ) -> Range<u64> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let mut end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => u64::MAX,
+        };
+        if let Some(committed) = self.committed_rows {
+            end = end.min(committed.get(segment).map_or(0, |last| last + 1));
+        }
+        start..end.max(start)
+    }
+
+    /// Reassembles the input of the transaction read from the static files, if it was
+    /// deduplicated into the [`DedupTable`](crate::DedupTable) of its static file.
+    pub(crate) fn reassemble(
+        &self,
+        tx_number: TxNumber,
+        transaction: &mut TransactionSignedNoHash,
+    ) -> ProviderResult<()> {
+        self.dedup_tables.reassemble(&self.provider, tx_number, transaction)
+    }
+
+    /// Returns the provider of the static files.
+    pub const fn provider(&self) -> &StaticFileProvider {
+        &self.provider
+    }
+
+    /// Returns the epoch roots of the header accumulator.
+    pub fn epoch_roots(&self) -> &[B256] {
+        &self.epoch_roots
+    }
+
+    /// Returns the proof of the pre-merge header against the root of its epoch.
+    ///
+    /// Returns `None` if the block is post-merge, or its epoch root isn't recorded yet.
+    pub fn prove_header(
+        &self,
+        block: BlockNumber,
+    ) -> Result<Option<HeaderProof>, StaticFileReaderError> {
+        let epoch = epoch(block);
+        let Some(&expected) = self.epoch_roots.get(epoch as usize) else { return Ok(None) };
+        if block >= MERGE_BLOCK {
+            return Ok(None)
+        }
+
+        let accumulator = epoch_accumulator(&self.provider, epoch)?;
+        let got = accumulator.root();
+        if got != expected {
+            return Err(StaticFileReaderError::EpochRootMismatch { epoch, expected, got })
+        }
+
+        Ok(accumulator.proof((block - epoch * EPOCH_SIZE) as usize))
+    }
+
+    /// Checks that every static file in the directory was produced with the current schema
+    /// version and has the columns of its segment, so none of them is opened and misdecoded.
+    /// Returns the number of checked static files, or the first incompatible one.
+    pub fn check_schema_versions(&self) -> Result<usize, StaticFileReaderError> {
+        let entries = list_static_files(self.provider.directory())?;
+        for entry in &entries {
+            let jar = NippyJar::<SegmentHeader>::load(&entry.path)
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+            check_static_file(jar.user_header(), jar.columns())?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Verifies the canonical hashes of the Headers segment against an externally supplied list,
+    /// e.g. from a checkpoint file, so imported static files can be validated without trusting
+    /// their source.
+    ///
+    /// Hashes are read from static files in chunks, one range per run of consecutive blocks, so
+    /// the list may be sparse. Returns the number of verified hashes, or the first mismatch.
+    pub fn verify_against(
+        &self,
+        hashes: impl Iterator<Item = (BlockNumber, B256)>,
+    ) -> Result<u64, StaticFileReaderError> {
+        let mut hashes = hashes.peekable();
+        let mut verified = 0;
+        while hashes.peek().is_some() {
+            let chunk = hashes.by_ref().take(VERIFY_CHUNK_SIZE).collect::<Vec<_>>();
+            for run in chunk.chunk_by(|(a, _), (b, _)| a.checked_add(1) == Some(*b)) {
+                let start = run[0].0;
+                let got = canonical_hashes_range(&self.provider, start..start + run.len() as u64)?;
+                for (i, &(block, expected)) in run.iter().enumerate() {
+                    let got = got.get(i).copied();
+                    if got != Some(expected) {
+                        return Err(StaticFileReaderError::CanonicalHashMismatch {
+                            block,
+                            expected,
+                            got,
+                        })
+                    }
+                }
+                verified += run.len() as u64;
+            }
+        }
+
+        Ok(verified)
+    }
+
+    /// Returns the logs in the block range emitted by the address, using the
+    /// [`LogIndex`] sidecars of the Receipts segment.
+    ///
+    /// Blocks of static files without a sidecar are not searched.
+    pub fn logs_by_address(
+        &self,
+        range: RangeInclusive<BlockNumber>,
+        address: Address,
+    ) -> Result<Vec<IndexedLog>, StaticFileReaderError> {
+        self.indexed_logs(
+            &range,
+            |index, range| index.by_address(&address, range),
+            |log| log.address == address,
+        )
+    }
+
+    /// Returns the logs in the block range with the first topic, using the [`LogIndex`]
+    /// sidecars of the Receipts segment.
+    ///
+    /// Blocks of static files without a sidecar are not searched.
+    pub fn logs_by_topic0(
+        &self,
+        range: RangeInclusive<BlockNumber>,
+        topic: B256,
+    ) -> Result<Vec<IndexedLog>, StaticFileReaderError> {
+        self.indexed_logs(
+            &range,
+            |index, range| index.by_topic0(&topic, range),
+            |log| log.topics().first() == Some(&topic),
+        )
+    }
+
+    /// Returns the transactions in the block range sent by the address, using the
+    /// [`SenderIndex`] sidecars of the Transactions segment.
+    ///
+    /// Blocks of static files without a sidecar are not searched.
+    pub fn transactions_by_sender(
+        &self,
+        range: RangeInclusive<BlockNumber>,
+        sender: Address,
+    ) -> Result<Vec<SenderTransaction>, StaticFileReaderError> {
+        let mut transactions = Vec::new();
+        for entry in static_files_in_range(self.provider.directory(), SenderIndex::SEGMENT, &range)
+        {
+            for row in SenderIndex::read(&entry)?.by_sender(&sender, &range) {
+                let mut transaction = transaction_by_id_no_hash(&self.provider, row.tx_number)?
+                    .ok_or(ProviderError::TransactionNotFound(row.tx_number.into()))?;
+                self.row_transforms.invert_transaction(&mut transaction)?;
+                self.reassemble(row.tx_number, &mut transaction)?;
+                transactions.push(SenderTransaction {
+                    block: row.block,
+                    tx_number: row.tx_number,
+                    transaction: transaction.with_hash(),
+                });
+            }
+        }
+        Ok(transactions)
+    }
+
+    /// Returns the transactions of the block with their transaction numbers and senders, using
+    /// the [`SenderIndex`] sidecar of the Transactions segment, so senders don't have to be
+    /// recovered.
+    ///
+    /// Returns `None` if the static file of the block has no sidecar.
+    pub fn block_transactions(
+        &self,
+        block: BlockNumber,
+    ) -> Result<Option<Vec<(TxNumber, TransactionSignedEcRecovered)>>, StaticFileReaderError> {
+        let Some(entry) = static_files_in_range(
+            self.provider.directory(),
+            SenderIndex::SEGMENT,
+            &(block..=block),
+        )
+        .pop()
+        .filter(|entry| entry.companion_path(SenderIndex::EXTENSION).exists()) else {
+            return Ok(None)
+        };
+
+        let mut transactions = Vec::new();
+        for (tx_number, sender) in SenderIndex::read(&entry)?.by_block(block) {
+            let mut transaction = transaction_by_id_no_hash(&self.provider, tx_number)?
+                .ok_or(ProviderError::TransactionNotFound(tx_number.into()))?;
+            self.row_transforms.invert_transaction(&mut transaction)?;
+            self.reassemble(tx_number, &mut transaction)?;
+            transactions.push((tx_number, transaction.with_hash().with_signer(sender)));
+        }
+        Ok(Some(transactions))
+    }
+
+    /// Returns the transaction numbers of the block, and so its rows in the Transactions or
+    /// Receipts segment, using the [`TransactionBoundaries`] or [`BlockBoundaries`] sidecar of
+    /// the segment.
+    ///
+    /// Returns `None` if the static file of the block has no sidecar, the block is not indexed
+    /// in it, or the segment is not keyed by transaction numbers.
+    pub fn block_tx_range(
+        &self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> io::Result<Option<Range<TxNumber>>> {
+        let Some(entry) =
+            static_files_in_range(self.provider.directory(), segment, &(block..=block)).pop()
+        else {
+            return Ok(None)
+        };
+        Ok(match segment {
+            StaticFileSegment::Headers => None,
+            StaticFileSegment::Transactions => {
+                TransactionBoundaries::read(&entry)?.boundaries().tx_range(block)
+            }
+            StaticFileSegment::Receipts => BlockBoundaries::read(&entry)?.tx_range(block),
+        })
+    }
+
+    /// Looks up receipts in the [`LogIndex`] sidecars, and returns their matching logs.
+    fn indexed_logs(
+        &self,
+        range: &RangeInclusive<BlockNumber>,
+        lookup: impl Fn(&LogIndex, &RangeInclusive<BlockNumber>) -> Vec<IndexRow>,
+        matches: impl Fn(&Log) -> bool,
+    ) -> Result<Vec<IndexedLog>, StaticFileReaderError> {
+        let mut logs = Vec::new();
+        for entry in static_files_in_range(self.provider.directory(), LogIndex::SEGMENT, range) {
+            for row in lookup(&LogIndex::read(&entry)?, range) {
+                let receipt = self
+                    .provider
+                    .receipt(row.tx_number)?
+                    .ok_or(ProviderError::ReceiptNotFound(row.tx_number.into()))?;
+                logs.extend(
+                    receipt.logs.into_iter().enumerate().filter(|(_, log)| matches(log)).map(
+                        |(log_index, log)| IndexedLog {
+                            block: row.block,
+                            tx_number: row.tx_number,
+                            log_index,
+                            log,
+                        },
+                    ),
+                );
+            }
+        }
+        Ok(logs)
+    }
+}
+
+/// Checks that the rows of the static file were encoded with the current schema version and that
+/// it has the columns of its segment, so none of them is misdecoded.
+pub(crate) fn check_static_file(
+    header: &SegmentHeader,
+    columns: usize,
+) -> Result<(), StaticFileReaderError> {
+    let segment = header.segment();
+    let block_range = find_fixed_range(header.expected_block_start());
+    header.check_schema().map_err(|err| StaticFileReaderError::IncompatibleSchema {
+        segment,
+        block_range,
+        err,
+    })?;
+    header.check_columns(columns).map_err(|err| StaticFileReaderError::ColumnMismatch {
+        segment,
+        block_range,
+        err,
+    })
+}
+
+/// Returns the total difficulty of the block in the Headers segment of static files.
+///
+/// Returns `None` if the static file of the block has the
+/// [`HeadersLayout::NoTotalDifficulty`](reth_static_file_types::HeadersLayout::NoTotalDifficulty).
+pub(crate) fn header_td_by_number(
+    provider: &StaticFileProvider,
+    block: BlockNumber,
+) -> ProviderResult<Option<U256>> {
+    let jar =
+        match provider.get_segment_provider_from_block(StaticFileSegment::Headers, block, None) {
+            Ok(jar) => jar,
+            Err(ProviderError::MissingStaticFileBlock(..)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+    let header = jar.user_header();
+    check_static_file(header, jar.columns())
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    if !header.headers_layout().has_total_difficulty() {
+        return Ok(None)
+    }
+    if header.needs_static_file_reader() {
+        read_column(&jar, block, 1, |column| Ok(CompactU256::decompress(column)?.into()))
+    } else {
+        provider.header_td_by_number(block)
+    }
+}
+
+/// Returns the header of the block in the Headers segment of static files.
+///
+/// Headers of static files without the total difficulty column are read from their column, as
+/// the [`StaticFileProvider`] finds none in static files that
+/// [need the static file reader](SegmentHeader::needs_static_file_reader).
+pub(crate) fn header_by_number(
+    provider: &StaticFileProvider,
+    block: BlockNumber,
+) -> ProviderResult<Option<Header>> {
+    let jar =
+        match provider.get_segment_provider_from_block(StaticFileSegment::Headers, block, None) {
+            Ok(jar) => jar,
+            Err(ProviderError::MissingStaticFileBlock(..)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+    let header = jar.user_header();
+    check_static_file(header, jar.columns())
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    if !header.needs_static_file_reader() {
+        return provider.header_by_number(block)
+    }
+    read_column(&jar, block, 0, |column| Ok(Header::decompress(column)?))
+}
+
+/// Reads and decodes the column of the row with the block or transaction number from its static
+/// file directly, bypassing the [`StaticFileProvider`], which finds no rows in static files that
+/// [need the static file reader](SegmentHeader::needs_static_file_reader).
+fn read_column<T>(
+    jar: &StaticFileJarProvider<'_>,
+    number: u64,
+    column: usize,
+    decode: impl FnOnce(&[u8]) -> ProviderResult<T>,
+) -> ProviderResult<Option<T>> {
+    let header = jar.user_header();
+    let start = match header.segment() {
+        StaticFileSegment::Headers => header.block_start(),
+        StaticFileSegment::Transactions | StaticFileSegment::Receipts => header.tx_start(),
+    };
+    let Some(row) = start.and_then(|start| number.checked_sub(start)) else { return Ok(None) };
+    let mut cursor = jar.cursor()?;
+    let columns = cursor
+        .row_by_number_with_cols(row as usize, 1 << column)
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    columns.and_then(|columns| columns.first().map(|column| decode(column))).transpose()
+}
+
+/// Returns the canonical hash of the block in the Headers segment of static files.
+///
+/// Static files with the
+/// [`HeadersLayout::NoTotalDifficulty`](reth_static_file_types::HeadersLayout::NoTotalDifficulty)
+/// store the hash in the second of their two columns, instead of the third column read by the
+/// [`StaticFileProvider`]. It's read from the last column of static files that
+/// [need the static file reader](SegmentHeader::needs_static_file_reader), in which the
+/// [`StaticFileProvider`] finds no rows.
+pub(crate) fn block_hash(
+    provider: &StaticFileProvider,
+    block: BlockNumber,
+) -> ProviderResult<Option<B256>> {
+    let jar =
+        match provider.get_segment_provider_from_block(StaticFileSegment::Headers, block, None) {
+            Ok(jar) => jar,
+            Err(ProviderError::MissingStaticFileBlock(..)) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+    let header = jar.user_header();
+    check_static_file(header, jar.columns())
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    if !header.needs_static_file_reader() {
+        return provider.block_hash(block)
+    }
+    let column = header.headers_layout().block_hash_column();
+    read_column(&jar, block, column, |hash| Ok(B256::from_slice(hash)))
+}
+
+/// Returns the canonical hashes of the blocks in the range, stopping at the first block missing
+/// from the Headers segment of static files. See [`block_hash`].
+pub(crate) fn canonical_hashes_range(
+    provider: &StaticFileProvider,
+    blocks: Range<BlockNumber>,
+) -> ProviderResult<Vec<B256>> {
+    blocks.map_while(|block| block_hash(provider, block).transpose()).collect()
+}
+
+/// Returns the transaction in the Transactions segment of static files, as it's stored.
+///
+/// Transactions of deduplicated static files are read from their column, as the
+/// [`StaticFileProvider`] finds none in them. Their inputs are left to be reassembled with the
+/// [`DedupTable`](crate::DedupTable) of the static file.
+pub(crate) fn transaction_by_id_no_hash(
+    provider: &StaticFileProvider,
+    tx_number: TxNumber,
+) -> ProviderResult<Option<TransactionSignedNoHash>> {
+    let segment = StaticFileSegment::Transactions;
+    let jar = match provider.get_segment_provider_from_transaction(segment, tx_number, None) {
+        Ok(jar) => jar,
+        Err(ProviderError::MissingStaticFileTx(..)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let header = jar.user_header();
+    check_static_file(header, jar.columns())
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    if !header.needs_static_file_reader() {
+        return provider.transaction_by_id_no_hash(tx_number)
+    }
+    read_column(&jar, tx_number, 0, |column| Ok(TransactionSignedNoHash::decompress(column)?))
+}
+
+/// Returns the transactions in the range from the Transactions segment of static files, as
+/// they're stored, stopping at the first transaction missing from them. See
+/// [`transaction_by_id_no_hash`].
+pub(crate) fn transactions_by_tx_range(
+    provider: &StaticFileProvider,
+    tx_numbers: Range<TxNumber>,
+) -> ProviderResult<Vec<TransactionSignedNoHash>> {
+    tx_numbers
+        .map_while(|tx_number| transaction_by_id_no_hash(provider, tx_number).transpose())
+        .collect()
+}
+
+/// Builds the [`EpochAccumulator`] of the epoch from the Headers segment of static files.
+pub(crate) fn epoch_accumulator(
+    provider: &StaticFileProvider,
+    epoch: u64,
+) -> ProviderResult<EpochAccumulator> {
+    let (start, end) = (epoch * EPOCH_SIZE, epoch_end(epoch));
+    let block_hashes = canonical_hashes_range(provider, start..end + 1)?;
+
+    let mut records = Vec::with_capacity(block_hashes.len());
+    for (block, block_hash) in (start..=end).zip(block_hashes) {
+        let total_difficulty = header_td_by_number(provider, block)?
+            .ok_or_else(|| ProviderError::HeaderNotFound(block.into()))?;
+        records.push(HeaderRecord { block_hash, total_difficulty });
+    }
+    if records.len() as u64 != end + 1 - start {
+        return Err(ProviderError::HeaderNotFound((start + records.len() as u64).into()))
+    }
+
+    Ok(EpochAccumulator::new(records))
+}
@@ -0,0 +1,140 @@
+//! Conversion of blocks and receipts stored in static files into RPC types, so history can be
+//! served in the format of `eth_getBlockByNumber` and `eth_getBlockReceipts` without a database.
+//!
+//! Transactions of a block are found through the [`SenderIndex`](crate::SenderIndex) sidecars.
+//! Ommers and withdrawals are not stored in static files, so blocks are returned without them.
+
+use crate::{StaticFileReader, StaticFileReaderError};
+use alloy_primitives::{BlockNumber, TxNumber};
+use reth_primitives::{
+    Block, BlockWithSenders, SealedHeader, TransactionSignedEcRecovered, TxType,
+};
+use reth_provider::{HeaderProvider, ReceiptProvider};
+use reth_rpc_types::{
+    Block as RpcBlock, BlockTransactionsKind, Log, Receipt as RpcReceipt, ReceiptEnvelope,
+    ReceiptWithBloom, TransactionReceipt,
+};
+use reth_rpc_types_compat::block::from_block;
+use reth_storage_errors::provider::ProviderError;
+
+/// Returns the block in the RPC format, with full transactions if `full` is set, or only their
+/// hashes otherwise.
+///
+/// Returns `None` if the block, or the sidecar of its transactions, is not in static files.
+pub fn block(
+    reader: &StaticFileReader,
+    number: BlockNumber,
+    full: bool,
+) -> Result<Option<RpcBlock>, StaticFileReaderError> {
+    let Some((header, transactions)) = header_and_transactions(reader, number)? else {
+        return Ok(None)
+    };
+    let total_difficulty = reader.header_td_by_number(number)?.unwrap_or_default();
+
+    let hash = header.hash();
+    let (body, senders) =
+        transactions.into_iter().map(|(_, transaction)| transaction.to_components()).unzip();
+    let block = BlockWithSenders {
+        block: Block {
+            header: header.unseal(),
+            body,
+            ommers: Vec::new(),
+            withdrawals: None,
+            requests: None,
+        },
+        senders,
+    };
+    let kind = if full { BlockTransactionsKind::Full } else { BlockTransactionsKind::Hashes };
+
+    Ok(Some(
+        from_block(block, total_difficulty, kind, Some(hash))
+            .map_err(|_| ProviderError::SenderRecoveryError)?,
+    ))
+}
+
+/// Returns the receipts of the block in the RPC format.
+///
+/// Returns `None` if the block, or the sidecar of its transactions, is not in static files.
+pub fn block_receipts(
+    reader: &StaticFileReader,
+    number: BlockNumber,
+) -> Result<Option<Vec<TransactionReceipt>>, StaticFileReaderError> {
+    let Some((header, transactions)) = header_and_transactions(reader, number)? else {
+        return Ok(None)
+    };
+
+    let mut receipts = Vec::with_capacity(transactions.len());
+    let (mut cumulative_gas_used, mut log_index) = (0, 0);
+    for (index, (tx_number, transaction)) in transactions.iter().enumerate() {
+        let receipt = reader
+            .receipt(*tx_number)?
+            .ok_or(ProviderError::ReceiptNotFound((*tx_number).into()))?;
+        let gas_used = receipt.cumulative_gas_used - cumulative_gas_used;
+        cumulative_gas_used = receipt.cumulative_gas_used;
+
+        let logs = receipt
+            .logs
+            .iter()
+            .map(|log| {
+                let log = Log {
+                    inner: log.clone(),
+                    block_hash: Some(header.hash()),
+                    block_number: Some(number),
+                    block_timestamp: Some(header.timestamp),
+                    transaction_hash: Some(transaction.hash()),
+                    transaction_index: Some(index as u64),
+                    log_index: Some(log_index),
+                    removed: false,
+                };
+                log_index += 1;
+                log
+            })
+            .collect();
+        let inner = ReceiptWithBloom {
+            receipt: RpcReceipt {
+                status: receipt.success.into(),
+                cumulative_gas_used: receipt.cumulative_gas_used as u128,
+                logs,
+            },
+            logs_bloom: receipt.bloom_slow(),
+        };
+
+        receipts.push(TransactionReceipt {
+            inner: match transaction.tx_type() {
+                TxType::Legacy => ReceiptEnvelope::Legacy(inner),
+                TxType::Eip2930 => ReceiptEnvelope::Eip2930(inner),
+                TxType::Eip1559 => ReceiptEnvelope::Eip1559(inner),
+                TxType::Eip4844 => ReceiptEnvelope::Eip4844(inner),
+            },
+            transaction_hash: transaction.hash(),
+            transaction_index: Some(index as u64),
+            block_hash: Some(header.hash()),
+            block_number: Some(number),
+            gas_used: gas_used as u128,
+            effective_gas_price: transaction.effective_gas_price(header.base_fee_per_gas),
+            blob_gas_used: transaction.blob_gas_used().map(u128::from),
+            blob_gas_price: header.blob_fee(),
+            from: transaction.signer(),
+            to: transaction.to(),
+            contract_address: transaction
+                .to()
+                .is_none()
+                .then(|| transaction.signer().create(transaction.nonce())),
+            state_root: None,
+        });
+    }
+
+    Ok(Some(receipts))
+}
+
+/// Returns the header of the block and its transactions, if both are in static files.
+fn header_and_transactions(
+    reader: &StaticFileReader,
+    number: BlockNumber,
+) -> Result<
+    Option<(SealedHeader, Vec<(TxNumber, TransactionSignedEcRecovered)>)>,
+    StaticFileReaderError,
+> {
+    let Some(header) = reader.sealed_header(number)? else { return Ok(None) };
+    Ok(reader.block_transactions(number)?.map(|transactions| (header, transactions)))
+}
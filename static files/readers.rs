@@ -0,0 +1,252 @@
+//! Registry of the readers of every static files directory, deferring the deletion of static
+//! files until no reader may still have them open.
+//!
+//! Readers [pin](pin_reader) the current epoch of the directory while they hold static files
+//! open, e.g. every [`StaticFileReader`](crate::StaticFileReader) for its lifetime. Removed static
+//! files are retired at the current epoch, which is then advanced: they're moved out of the
+//! directory right away, so they're not listed anymore, and deleted once every reader pinned at or
+//! before that epoch is dropped. Readers pinned afterwards can't have listed them, so they don't
+//! hold the deletion back. Files that can't be moved, e.g. because they're open on Windows, are
+//! deleted in place once the readers are dropped instead.
+
+use parking_lot::{const_mutex, Mutex};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// Name of the directory within the static files directory, that removed static files are moved
+/// to until no reader may still have them open.
+pub const RETIRED_DIR_NAME: &str = "retired";
+
+/// Registries of the static files directories with pinned readers or retired files.
+static REGISTRIES: Mutex<BTreeMap<PathBuf, Registry>> = const_mutex(BTreeMap::new());
+
+/// Readers and retired files of a static files directory.
+#[derive(Debug, Default)]
+struct Registry {
+    /// Current epoch, advanced by every retirement.
+    epoch: u64,
+    /// Number of readers pinned at every epoch.
+    pinned: BTreeMap<u64, usize>,
+    /// Files retired at an epoch, in epoch order.
+    retired: VecDeque<(u64, Vec<PathBuf>)>,
+}
+
+impl Registry {
+    /// Returns `true` if a reader pinned at or before the epoch is still alive.
+    fn is_pinned_since(&self, epoch: u64) -> bool {
+        self.pinned.keys().next().is_some_and(|pinned| *pinned <= epoch)
+    }
+
+    /// Removes the files retired before every reader that's still alive was pinned, and returns
+    /// them.
+    fn reclaim(&mut self) -> Vec<PathBuf> {
+        let mut reclaimed = Vec::new();
+        while let Some((epoch, _)) = self.retired.front() {
+            if self.is_pinned_since(*epoch) {
+                break
+            }
+            if let Some((_, paths)) = self.retired.pop_front() {
+                reclaimed.extend(paths);
+            }
+        }
+        reclaimed
+    }
+}
+
+/// Reader pinned to an epoch of a static files directory. Static files retired at or after the
+/// epoch are not deleted until the guard is dropped.
+#[derive(Debug)]
+#[must_use = "static files may be deleted once the guard is dropped"]
+pub struct ReaderGuard {
+    /// Static files directory of the reader.
+    directory: PathBuf,
+    /// Epoch the reader is pinned at.
+    epoch: u64,
+}
+
+impl ReaderGuard {
+    /// Returns the epoch the reader is pinned at.
+    pub const fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        let reclaimed = {
+            let mut registries = REGISTRIES.lock();
+            let Some(registry) = registries.get_mut(&self.directory) else { return };
+            if let Some(count) = registry.pinned.get_mut(&self.epoch) {
+                *count -= 1;
+                if *count == 0 {
+                    registry.pinned.remove(&self.epoch);
+                }
+            }
+            let reclaimed = registry.reclaim();
+            if registry.pinned.is_empty() && registry.retired.is_empty() {
+                registries.remove(&self.directory);
+            }
+            reclaimed
+        };
+
+        for path in reclaimed {
+            if let Err(err) = remove_file(&path) {
+                warn!(target: "static_file", ?path, %err, "Failed to delete retired static file");
+            }
+        }
+    }
+}
+
+/// Pins a reader of the static files directory at its current epoch, until the returned guard is
+/// dropped. The guard should be taken before static files are listed or opened.
+pub fn pin_reader(directory: &Path) -> ReaderGuard {
+    let mut registries = REGISTRIES.lock();
+    let registry = registries.entry(directory.to_path_buf()).or_default();
+    *registry.pinned.entry(registry.epoch).or_default() += 1;
+    ReaderGuard { directory: directory.to_path_buf(), epoch: registry.epoch }
+}
+
+/// Returns the retired files of the static files directory that are not deleted yet, as they may
+/// still be read.
+pub fn pending_removals(directory: &Path) -> Vec<PathBuf> {
+    REGISTRIES.lock().get(directory).map_or_else(Vec::new, |registry| {
+        registry.retired.iter().flat_map(|(_, paths)| paths.iter().cloned()).collect()
+    })
+}
+
+/// Removes the files of the static files directory, deleting them right away if no reader is
+/// pinned, and moving them to the [`RETIRED_DIR_NAME`] directory until the pinned readers are
+/// dropped otherwise. Files that can't be moved are registered in place, so every file is
+/// deleted once the readers are dropped.
+///
+/// Leftovers of retired files that were not deleted before a restart are deleted along with the
+/// next files removed without pinned readers.
+pub(crate) fn retire(directory: &Path, paths: Vec<PathBuf>) -> io::Result<()> {
+    let retired_dir = directory.join(RETIRED_DIR_NAME);
+    let mut registries = REGISTRIES.lock();
+    let registry = registries.entry(directory.to_path_buf()).or_default();
+    let epoch = registry.epoch;
+    registry.epoch += 1;
+
+    if registry.is_pinned_since(epoch) {
+        let created = std::fs::create_dir_all(&retired_dir);
+        if let Err(err) = &created {
+            warn!(target: "static_file", ?retired_dir, %err, "Failed to create the retired static files directory, deleting retired static files in place once their readers drop");
+        }
+        let mut retired = Vec::with_capacity(paths.len());
+        for path in paths {
+            let Some(file_name) = path.file_name() else { continue };
+            // Files retired at different epochs may share their name
+            let destination = retired_dir.join(format!("{epoch}-{}", file_name.to_string_lossy()));
+            let moved = created.is_ok() &&
+                match std::fs::rename(&path, &destination) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        warn!(target: "static_file", ?path, %err, "Failed to move retired static file, deleting it in place once its readers drop");
+                        false
+                    }
+                };
+            retired.push(if moved { destination } else { path });
+        }
+        registry.retired.push_back((epoch, retired));
+        return Ok(())
+    }
+
+    // Readers are pinned at or before the current epoch, so none is left
+    registries.remove(directory);
+    drop(registries);
+
+    for path in paths {
+        remove_file(&path)?;
+    }
+    if retired_dir.exists() {
+        std::fs::remove_dir_all(retired_dir)?;
+    }
+    Ok(())
+}
+
+/// Deletes the file, if it still exists.
+fn remove_file(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_static_files, test_utils::StaticFileTestHarness, StaticFileReader};
+
+    #[test]
+    fn deferred_deletion() {
+        let directory = tempfile::tempdir().unwrap();
+        let directory = directory.path();
+        let [first_file, second_file] = ["first", "second"].map(|name| directory.join(name));
+        std::fs::write(&first_file, b"first").unwrap();
+        std::fs::write(&second_file, b"second").unwrap();
+
+        // Retired files are moved out of the directory until the reader is dropped
+        let first = pin_reader(directory);
+        retire(directory, vec![first_file.clone()]).unwrap();
+        assert!(!first_file.exists());
+        let pending = pending_removals(directory);
+        assert_eq!(pending, vec![directory.join(RETIRED_DIR_NAME).join("0-first")]);
+
+        // Readers pinned after the retirement don't hold its deletion back
+        let second = pin_reader(directory);
+        drop(first);
+        assert!(pending_removals(directory).is_empty());
+        assert!(!pending[0].exists());
+
+        retire(directory, vec![second_file.clone()]).unwrap();
+        assert_eq!(pending_removals(directory).len(), 1);
+        drop(second);
+        assert!(pending_removals(directory).is_empty());
+        assert_eq!(std::fs::read_dir(directory.join(RETIRED_DIR_NAME)).unwrap().count(), 0);
+
+        // Without readers, files are deleted right away, along with leftovers
+        std::fs::write(&first_file, b"first").unwrap();
+        retire(directory, vec![first_file.clone()]).unwrap();
+        assert!(!first_file.exists());
+        assert!(!directory.join(RETIRED_DIR_NAME).exists());
+
+        // Files that can't be moved are deleted in place once the readers are dropped
+        std::fs::write(directory.join(RETIRED_DIR_NAME), b"not a directory").unwrap();
+        std::fs::write(&first_file, b"first").unwrap();
+        std::fs::write(&second_file, b"second").unwrap();
+        let reader = pin_reader(directory);
+        retire(directory, vec![first_file.clone(), second_file.clone()]).unwrap();
+        assert_eq!(pending_removals(directory), vec![first_file.clone(), second_file.clone()]);
+        assert!(first_file.exists());
+        drop(reader);
+        assert!(pending_removals(directory).is_empty());
+        assert!(!first_file.exists() && !second_file.exists());
+        std::fs::remove_file(directory.join(RETIRED_DIR_NAME)).unwrap();
+    }
+
+    #[test]
+    fn static_file_readers_are_pinned() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let directory = harness.static_files_dir.path();
+        let entry = list_static_files(directory).unwrap().remove(0);
+
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        let clone = reader.clone();
+        retire(directory, entry.paths()).unwrap();
+        assert!(!entry.path.exists());
+        assert!(!pending_removals(directory).is_empty());
+
+        // Removed static files are deleted once every clone of the reader is dropped
+        drop(reader);
+        assert!(!pending_removals(directory).is_empty());
+        drop(clone);
+        assert!(pending_removals(directory).is_empty());
+    }
+}
@@ -0,0 +1,183 @@
+//! Unwinding of static files past the block a reorg unwound the chain to.
+//!
+//! Static files are unwound with the rows of their static file provider, and the index sidecars
+//! of the static files that are kept are truncated to the unwound block, so the blocks copied
+//! again after the reorg are indexed from scratch.
+
+use crate::{
+    list_static_files, tiering::remove_cold, BlockBoundaries, LogIndex, SenderIndex, Sidecar,
+    StaticFileEntry, TransactionBoundaries,
+};
+use alloy_primitives::{BlockNumber, TxNumber};
+use reth_provider::{providers::StaticFileProvider, BlockReader};
+use reth_static_file_types::StaticFileSegment;
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{io, path::Path};
+
+/// Unwinds the static files of the segment to `unwound_to`, the last block kept by the reorg.
+/// Returns the highest block of the segment before the unwind, or `None` if it held no blocks
+/// after `unwound_to`.
+///
+/// Rows of the transaction-based segments are found with the block boundaries sidecar of the
+/// static file holding `unwound_to`, falling back to the block body indices of the database.
+/// The block is kept by the reorg, so this works both before and after the database is unwound.
+pub(crate) fn unwind_segment(
+    static_file_provider: &StaticFileProvider,
+    provider: &impl BlockReader,
+    segment: StaticFileSegment,
+    unwound_to: BlockNumber,
+) -> ProviderResult<Option<BlockNumber>> {
+    let Some(highest) = static_file_provider
+        .get_highest_static_file_block(segment)
+        .filter(|highest| *highest > unwound_to)
+    else {
+        return Ok(None)
+    };
+    let mut entries = list_static_files(static_file_provider.directory()).map_err(io_error)?;
+    entries.retain(|entry| entry.segment == segment && entry.block_range.end() > unwound_to);
+
+    let mut writer = static_file_provider.latest_writer(segment)?;
+    match segment {
+        StaticFileSegment::Headers => writer.prune_headers(highest - unwound_to)?,
+        StaticFileSegment::Transactions | StaticFileSegment::Receipts => {
+            let next_tx = next_tx(&entries, provider, unwound_to)?;
+            let to_delete = static_file_provider
+                .get_highest_static_file_tx(segment)
+                .map_or(0, |highest_tx| (highest_tx + 1).saturating_sub(next_tx));
+            if segment == StaticFileSegment::Transactions {
+                writer.prune_transactions(to_delete, unwound_to)?;
+            } else {
+                writer.prune_receipts(to_delete, unwound_to)?;
+            }
+        }
+    }
+    writer.commit()?;
+    drop(writer);
+
+    for entry in &entries {
+        truncate_companions(static_file_provider.directory(), entry, unwound_to)
+            .map_err(io_error)?;
+    }
+
+    Ok(Some(highest))
+}
+
+/// Returns the number of the first transaction after `unwound_to`.
+fn next_tx(
+    entries: &[StaticFileEntry],
+    provider: &impl BlockReader,
+    unwound_to: BlockNumber,
+) -> ProviderResult<TxNumber> {
+    let indexed = match entries.iter().find(|entry| entry.block_range.contains(unwound_to)) {
+        Some(entry) if entry.segment == StaticFileSegment::Transactions => {
+            TransactionBoundaries::read(entry).map_err(io_error)?.boundaries().tx_range(unwound_to)
+        }
+        Some(entry) => BlockBoundaries::read(entry).map_err(io_error)?.tx_range(unwound_to),
+        None => None,
+    };
+    if let Some(tx_range) = indexed {
+        return Ok(tx_range.end)
+    }
+
+    provider
+        .block_body_indices(unwound_to)?
+        .map(|indices| indices.next_tx_num())
+        .ok_or(ProviderError::BlockBodyIndicesNotFound(unwound_to))
+}
+
+/// Truncates the index sidecars of the static file to `unwound_to`, or removes its companion
+/// files, and its files in the cold directory, if the static file itself was removed by the
+/// unwind.
+fn truncate_companions(
+    directory: &Path,
+    entry: &StaticFileEntry,
+    unwound_to: BlockNumber,
+) -> io::Result<()> {
+    if !entry.path.exists() {
+        for path in entry.paths() {
+            std::fs::remove_file(path)?;
+        }
+        return remove_cold(directory, entry)
+    }
+
+    match entry.segment {
+        StaticFileSegment::Headers => Ok(()),
+        StaticFileSegment::Transactions => {
+            truncate_sidecar::<TransactionBoundaries>(entry, unwound_to)?;
+            truncate_sidecar::<SenderIndex>(entry, unwound_to)
+        }
+        StaticFileSegment::Receipts => {
+            truncate_sidecar::<BlockBoundaries>(entry, unwound_to)?;
+            truncate_sidecar::<LogIndex>(entry, unwound_to)
+        }
+    }
+}
+
+/// Truncates the sidecar of the static file to `unwound_to`, if it exists.
+fn truncate_sidecar<S: Sidecar>(
+    entry: &StaticFileEntry,
+    unwound_to: BlockNumber,
+) -> io::Result<()> {
+    if !entry.companion_path(S::EXTENSION).exists() {
+        return Ok(())
+    }
+    let mut sidecar = S::read(entry)?;
+    sidecar.truncate(unwound_to);
+    sidecar.write(entry)
+}
+
+/// Maps a filesystem error to a [`ProviderError`].
+fn io_error(err: io::Error) -> ProviderError {
+    ProviderError::NippyJar(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::StaticFileTestHarness, OverflowPolicy, StaticFileProducerEvent};
+
+    #[test]
+    fn unwind_past_reorg() {
+        let harness = StaticFileTestHarness::new(9, 1..3);
+        harness.run().unwrap();
+        let producer = harness.producer();
+        let events = producer.subscribe_events(8, OverflowPolicy::DropOldest);
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let provider = harness.provider_factory.provider().unwrap();
+        let next_tx = provider.block_body_indices(5).unwrap().unwrap().next_tx_num();
+
+        assert_eq!(
+            producer.on_reorg(7, 5).unwrap(),
+            vec![
+                StaticFileSegment::Headers,
+                StaticFileSegment::Transactions,
+                StaticFileSegment::Receipts
+            ]
+        );
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            assert_eq!(static_file_provider.get_highest_static_file_block(segment), Some(5));
+            assert_eq!(
+                events.try_recv(),
+                Some(StaticFileProducerEvent::Unwound { segment, from: 9, to: 5 })
+            );
+        }
+        assert_eq!(
+            static_file_provider.get_highest_static_file_tx(StaticFileSegment::Receipts),
+            Some(next_tx - 1)
+        );
+        // Nothing left to unwind
+        assert!(producer.on_reorg(7, 5).unwrap().is_empty());
+
+        // Sidecars only index the kept blocks
+        let entries = list_static_files(static_file_provider.directory()).unwrap();
+        let receipts =
+            entries.iter().find(|entry| entry.segment == StaticFileSegment::Receipts).unwrap();
+        let boundaries = BlockBoundaries::read(receipts).unwrap();
+        assert_eq!(boundaries.blocks(), Some(0..=5));
+        assert_eq!(boundaries.tx_range(5).unwrap().end, next_tx);
+    }
+}
@@ -0,0 +1,522 @@
+//! Repair of quarantined static files from a remote mirror, for ranges that were already pruned
+//! from the database.
+
+use crate::{
+    doctor::{
+        diagnose, Corruption, QuarantineReport, QuarantinedFile, QUARANTINE_DIR_NAME,
+        QUARANTINE_REPORT_FILE_NAME,
+    },
+    download_static_file, DownloadError, ManifestError, RangeFetcher, SignedManifest,
+    StaticFileEntry, StaticFileManifest, StaticFileProducerError, TrustedManifest,
+    COMPANION_EXTENSIONS, DEFAULT_DOWNLOAD_CONNECTIONS,
+};
+use alloy_primitives::{BlockHash, BlockNumber, Bloom, TxNumber, B256};
+use ed25519_dalek::VerifyingKey;
+use reth_db_api::{models::StoredBlockBodyIndices, table::Decompress};
+use reth_nippy_jar::{NippyJar, NippyJarCursor};
+use reth_primitives::{
+    proofs::calculate_transaction_root, Header, Receipt, TransactionSignedNoHash,
+};
+use reth_provider::{providers::StaticFileProvider, BlockReader};
+use reth_static_file_types::{SegmentHeader, SegmentRangeInclusive, StaticFileSegment};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{fmt, io, path::Path, sync::Arc};
+use tracing::debug;
+
+/// Name of the directory within the quarantine directory, that repaired static files are
+/// downloaded to before they're verified.
+pub const REPAIR_DIR_NAME: &str = "repair";
+
+/// Remote static file mirror that quarantined static files are fetched from.
+#[derive(Clone)]
+pub struct RepairMirror {
+    /// Source of the static files.
+    pub fetcher: Arc<dyn RangeFetcher + Send>,
+    /// Manifest of the static files served by the mirror.
+    pub manifest: StaticFileManifest,
+    /// Trusted root of the manifest, checked before every download.
+    pub root: B256,
+    /// Number of parallel connections per downloaded file.
+    pub connections: usize,
+}
+
+impl RepairMirror {
+    /// Creates a new [`RepairMirror`] with [`DEFAULT_DOWNLOAD_CONNECTIONS`], serving the static
+    /// files of the manifest pinned to the trusted root.
+    pub fn new(
+        fetcher: Arc<dyn RangeFetcher + Send>,
+        manifest: StaticFileManifest,
+        root: B256,
+    ) -> Self {
+        Self { fetcher, manifest, root, connections: DEFAULT_DOWNLOAD_CONNECTIONS }
+    }
+
+    /// Creates a new [`RepairMirror`] with [`DEFAULT_DOWNLOAD_CONNECTIONS`], serving the static
+    /// files of the signed manifest, once its signature is verified against the trusted keys.
+    pub fn from_signed(
+        fetcher: Arc<dyn RangeFetcher + Send>,
+        manifest: &SignedManifest,
+        trusted_keys: &[VerifyingKey],
+    ) -> Result<Self, ManifestError> {
+        let manifest = manifest.verify(trusted_keys)?.clone();
+        let root = manifest.root();
+        Ok(Self::new(fetcher, manifest, root))
+    }
+
+    /// Sets the number of parallel connections per downloaded file.
+    pub const fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections;
+        self
+    }
+}
+
+impl fmt::Debug for RepairMirror {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RepairMirror")
+            .field("manifest", &self.manifest)
+            .field("root", &self.root)
+            .field("connections", &self.connections)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Error returned by [`repair_static_file`].
+#[derive(Debug)]
+pub enum RepairError {
+    /// Filesystem error.
+    Io(io::Error),
+    /// Error while reading the downloaded static file or the local chain.
+    Provider(ProviderError),
+    /// Error while downloading the static file from the mirror.
+    Download(DownloadError),
+    /// The static file isn't listed in the manifest of the mirror.
+    NotInManifest {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+    },
+    /// The downloaded static file is corrupt itself.
+    Corrupt(Corruption),
+    /// The downloaded static file doesn't match the local chain at the block.
+    Mismatch {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// First block that doesn't match.
+        block: BlockNumber,
+    },
+    /// The local chain has no header to verify the downloaded static file against.
+    Unverifiable {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Block without a local header.
+        block: BlockNumber,
+    },
+}
+
+impl From<io::Error> for RepairError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ProviderError> for RepairError {
+    fn from(value: ProviderError) -> Self {
+        Self::Provider(value)
+    }
+}
+
+impl From<DownloadError> for RepairError {
+    fn from(value: DownloadError) -> Self {
+        Self::Download(value)
+    }
+}
+
+impl fmt::Display for RepairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::Provider(err) => fmt::Display::fmt(err, f),
+            Self::Download(err) => fmt::Display::fmt(err, f),
+            Self::NotInManifest { segment, block_range } => {
+                write!(f, "mirror has no {segment} static file for blocks {block_range}")
+            }
+            Self::Corrupt(corruption) => {
+                write!(f, "downloaded static file is corrupt: {corruption:?}")
+            }
+            Self::Mismatch { segment, block } => {
+                write!(f, "downloaded {segment} static file doesn't match the local block {block}")
+            }
+            Self::Unverifiable { segment, block } => {
+                write!(f, "no local header of block {block} to verify the {segment} static file")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RepairError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Provider(err) => Some(err),
+            Self::Download(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Service repairing the static files quarantined by the [doctor](crate::doctor) from a
+/// [`RepairMirror`], held by the [`StaticFileProducer`](crate::StaticFileProducer).
+#[derive(Debug, Clone)]
+pub struct RepairService {
+    mirror: RepairMirror,
+}
+
+impl RepairService {
+    /// Creates a new [`RepairService`] fetching from the mirror.
+    pub const fn new(mirror: RepairMirror) -> Self {
+        Self { mirror }
+    }
+
+    /// Returns the [`RepairMirror`] static files are fetched from.
+    pub const fn mirror(&self) -> &RepairMirror {
+        &self.mirror
+    }
+
+    /// Fetches the static files listed in the [`QuarantineReport`] from the mirror, verifying
+    /// them against the local headers of a fresh database provider each, and reloads the static
+    /// file index after every repair. Repaired static files are removed from the report.
+    ///
+    /// Headers are repaired first, so transactions and receipts of the same range can be
+    /// verified against them. Returns the repaired static files.
+    pub fn repair_quarantined<P: BlockReader>(
+        &self,
+        static_file_provider: &StaticFileProvider,
+        provider: impl Fn() -> ProviderResult<P>,
+    ) -> Result<Vec<StaticFileEntry>, StaticFileProducerError> {
+        let report_path = static_file_provider
+            .directory()
+            .join(QUARANTINE_DIR_NAME)
+            .join(QUARANTINE_REPORT_FILE_NAME);
+        if !report_path.exists() {
+            return Ok(Vec::new())
+        }
+        let mut report = QuarantineReport::read(&report_path)?;
+
+        // Headers come first in the order of segments
+        let mut files = report.files.clone();
+        files.sort_by_key(|file| (file.segment, file.block_range.start()));
+        files.dedup_by(|a, b| a.segment == b.segment && a.block_range == b.block_range);
+
+        let repair = |file| -> Result<StaticFileEntry, StaticFileProducerError> {
+            let entry = repair_static_file(
+                &self.mirror,
+                file,
+                &provider()?,
+                static_file_provider.directory(),
+            )?;
+            // Local headers are read through the index, so it's reloaded after every repair.
+            static_file_provider.initialize_index()?;
+            Ok(entry)
+        };
+        let mut repaired = Vec::new();
+        let mut result = Ok(());
+        for file in &files {
+            debug!(target: "static_file", segment = %file.segment, block_range = %file.block_range, "Repairing quarantined static file from mirror");
+            match repair(file) {
+                Ok(entry) => repaired.push(entry),
+                Err(err) => {
+                    result = Err(err);
+                    break
+                }
+            }
+        }
+
+        if !repaired.is_empty() {
+            report.files.retain(|file| {
+                !repaired.iter().any(|entry| {
+                    entry.segment == file.segment && entry.block_range == file.block_range
+                })
+            });
+            report.write(&report_path)?;
+        }
+        result.map(|()| repaired)
+    }
+}
+
+/// Fetches the quarantined static file from the mirror into the static files directory, verifying
+/// it against the manifest and the local chain first.
+///
+/// Headers are verified by their hash chain, which has to connect to the local header before or
+/// after the range. Transactions and receipts are verified against the transactions root, logs
+/// bloom and gas used of the local headers.
+///
+/// A static file that fails verification is removed, so it's downloaded again on the next
+/// attempt. Returns the repaired static file.
+pub fn repair_static_file<P: BlockReader>(
+    mirror: &RepairMirror,
+    file: &QuarantinedFile,
+    local: &P,
+    directory: &Path,
+) -> Result<StaticFileEntry, RepairError> {
+    let Some(manifest_entry) = mirror.manifest.files.iter().find(|manifest_entry| {
+        manifest_entry.segment == file.segment && manifest_entry.block_range == file.block_range
+    }) else {
+        return Err(RepairError::NotInManifest {
+            segment: file.segment,
+            block_range: file.block_range,
+        })
+    };
+
+    let staging_dir = directory.join(QUARANTINE_DIR_NAME).join(REPAIR_DIR_NAME);
+    let downloaded = StaticFileEntry {
+        segment: file.segment,
+        block_range: file.block_range,
+        path: download_static_file(
+            mirror.fetcher.as_ref(),
+            TrustedManifest::Pinned { manifest: &mirror.manifest, root: mirror.root },
+            manifest_entry,
+            &staging_dir,
+            mirror.connections,
+        )?,
+    };
+
+    let verified = match diagnose(&downloaded, Some(manifest_entry))? {
+        Some(corruption) => Err(RepairError::Corrupt(corruption)),
+        None => verify_against_local(&downloaded, local),
+    };
+    if let Err(err) = verified {
+        for path in downloaded.paths() {
+            std::fs::remove_file(path)?;
+        }
+        return Err(err)
+    }
+
+    let repaired = StaticFileEntry {
+        path: directory.join(file.segment.filename(&file.block_range)),
+        ..downloaded.clone()
+    };
+    std::fs::rename(&downloaded.path, &repaired.path)?;
+    for extension in COMPANION_EXTENSIONS {
+        let companion = downloaded.companion_path(extension);
+        if companion.exists() {
+            std::fs::rename(companion, repaired.companion_path(extension))?;
+        }
+    }
+
+    Ok(repaired)
+}
+
+/// Verifies all rows of the static file against the local chain.
+fn verify_against_local<P: BlockReader>(
+    entry: &StaticFileEntry,
+    local: &P,
+) -> Result<(), RepairError> {
+    let jar = NippyJar::<SegmentHeader>::load(&entry.path)
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    // Nothing to verify in an empty static file
+    let Some(block_range) = jar.user_header().block_range().copied() else { return Ok(()) };
+    let tx_start = jar.user_header().tx_start().unwrap_or_default();
+    let mut cursor =
+        NippyJarCursor::new(&jar).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+
+    if entry.segment.is_headers() {
+        verify_headers(&mut cursor, block_range, local)
+    } else {
+        verify_block_bodies(&mut cursor, entry.segment, block_range, tx_start, local)
+    }
+}
+
+/// Verifies the hash chain of the headers, which has to connect to the local header before or
+/// after the range.
+fn verify_headers<P: BlockReader>(
+    cursor: &mut NippyJarCursor<'_, SegmentHeader>,
+    block_range: SegmentRangeInclusive,
+    local: &P,
+) -> Result<(), RepairError> {
+    let segment = StaticFileSegment::Headers;
+    // Hash of the last verified header, starting with the local parent of the range
+    let mut parent_hash = match block_range.start().checked_sub(1) {
+        Some(parent) => local.block_hash(parent)?,
+        None => None,
+    };
+    let anchored = parent_hash.is_some();
+
+    for block in block_range.start()..=block_range.end() {
+        let row = block - block_range.start();
+        let header = decode_column::<Header>(cursor, row, 0)?;
+        let hash = decode_column::<BlockHash>(cursor, row, 2)?;
+        if header.hash_slow() != hash ||
+            parent_hash.is_some_and(|parent_hash| parent_hash != header.parent_hash)
+        {
+            return Err(RepairError::Mismatch { segment, block })
+        }
+        parent_hash = Some(hash);
+    }
+
+    let child = block_range.end() + 1;
+    match local.header_by_number(child)? {
+        Some(header) if Some(header.parent_hash) != parent_hash => {
+            Err(RepairError::Mismatch { segment, block: child })
+        }
+        None if !anchored => Err(RepairError::Unverifiable { segment, block: child }),
+        _ => Ok(()),
+    }
+}
+
+/// Verifies the transactions or receipts of every block against the transactions root, or the
+/// logs bloom and gas used of the local header.
+fn verify_block_bodies<P: BlockReader>(
+    cursor: &mut NippyJarCursor<'_, SegmentHeader>,
+    segment: StaticFileSegment,
+    block_range: SegmentRangeInclusive,
+    tx_start: TxNumber,
+    local: &P,
+) -> Result<(), RepairError> {
+    for block in block_range.start()..=block_range.end() {
+        let (header, indices) = local_block(local, segment, block)?;
+        let mut rows = Vec::with_capacity(indices.tx_count as usize);
+        for tx_number in indices.tx_num_range() {
+            let Some(row) = tx_number.checked_sub(tx_start) else {
+                return Err(RepairError::Mismatch { segment, block })
+            };
+            rows.push(row);
+        }
+
+        let matches = if segment.is_receipts() {
+            let (mut logs_bloom, mut gas_used) = (Bloom::ZERO, 0);
+            for row in rows {
+                let receipt = decode_column::<Receipt>(cursor, row, 0)?;
+                logs_bloom.accrue_bloom(&receipt.bloom_slow());
+                gas_used = receipt.cumulative_gas_used;
+            }
+            logs_bloom == header.logs_bloom && gas_used == header.gas_used
+        } else {
+            let transactions = rows
+                .into_iter()
+                .map(|row| {
+                    decode_column::<TransactionSignedNoHash>(cursor, row, 0)
+                        .map(TransactionSignedNoHash::with_hash)
+                })
+                .collect::<ProviderResult<Vec<_>>>()?;
+            calculate_transaction_root(&transactions) == header.transactions_root
+        };
+        if !matches {
+            return Err(RepairError::Mismatch { segment, block })
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the local header and body indices of the block.
+fn local_block<P: BlockReader>(
+    local: &P,
+    segment: StaticFileSegment,
+    block: BlockNumber,
+) -> Result<(Header, StoredBlockBodyIndices), RepairError> {
+    match (local.header_by_number(block)?, local.block_body_indices(block)?) {
+        (Some(header), Some(indices)) => Ok((header, indices)),
+        _ => Err(RepairError::Unverifiable { segment, block }),
+    }
+}
+
+/// Decodes the column of the row.
+fn decode_column<T: Decompress>(
+    cursor: &mut NippyJarCursor<'_, SegmentHeader>,
+    row: u64,
+    column: usize,
+) -> ProviderResult<T> {
+    let columns = cursor
+        .row_by_number(row as usize)
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?
+        .ok_or_else(|| ProviderError::NippyJar(format!("row {row} is missing")))?;
+    let value = columns
+        .get(column)
+        .ok_or_else(|| ProviderError::NippyJar(format!("row {row} has no column {column}")))?;
+    Ok(T::decompress(*value)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{list_static_files, ChunkHashes, ManifestEntry};
+    use reth_provider::test_utils::create_test_provider_factory;
+    use std::{collections::HashMap, ops::Range};
+
+    /// Serves files from memory.
+    struct MemoryFetcher(HashMap<String, Vec<u8>>);
+
+    impl RangeFetcher for MemoryFetcher {
+        fn fetch_range(&self, file_name: &str, range: Range<u64>) -> io::Result<Vec<u8>> {
+            Ok(self.0[file_name][range.start as usize..range.end as usize].to_vec())
+        }
+
+        fn fetch(&self, file_name: &str) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.0.get(file_name).cloned())
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_downloads() {
+        let directory = tempfile::tempdir().unwrap();
+        let provider_factory = create_test_provider_factory();
+        let local = provider_factory.provider().unwrap();
+        let file = QuarantinedFile {
+            segment: StaticFileSegment::Receipts,
+            block_range: SegmentRangeInclusive::new(0, 499_999),
+            file_name: "static_file_receipts_0_499999".to_string(),
+            corruption: Corruption::Unreadable { error: "missing configuration".to_string() },
+        };
+
+        // Mirror without the static file
+        let manifest = StaticFileManifest::default();
+        let root = manifest.root();
+        let mirror = RepairMirror::new(Arc::new(MemoryFetcher(HashMap::new())), manifest, root);
+        assert!(matches!(
+            repair_static_file(&mirror, &file, &local, directory.path()),
+            Err(RepairError::NotInManifest { segment: StaticFileSegment::Receipts, .. })
+        ));
+
+        // Mirror serving a static file without a configuration
+        let source = tempfile::tempdir().unwrap();
+        let data_path = source.path().join(&file.file_name);
+        std::fs::write(&data_path, b"receipts").unwrap();
+        let mut manifest = StaticFileManifest::default();
+        manifest.files.push(ManifestEntry {
+            segment: file.segment,
+            block_range: file.block_range,
+            file_name: file.file_name.clone(),
+            content_hash: None,
+            size: 8,
+            chunks: Some(ChunkHashes::new(&data_path, 4).unwrap()),
+            dictionaries: Vec::new(),
+            verification: None,
+            row_root: None,
+        });
+        let root = manifest.root();
+        let mut mirror = RepairMirror::new(
+            Arc::new(MemoryFetcher(HashMap::from([(
+                file.file_name.clone(),
+                b"receipts".to_vec(),
+            )]))),
+            manifest,
+            root,
+        );
+        assert!(matches!(
+            repair_static_file(&mirror, &file, &local, directory.path()),
+            Err(RepairError::Corrupt(Corruption::Unreadable { .. }))
+        ));
+
+        // Manifest that no longer matches its trusted root
+        mirror.manifest.files[0].size += 1;
+        assert!(matches!(
+            repair_static_file(&mirror, &file, &local, directory.path()),
+            Err(RepairError::Download(DownloadError::Manifest(ManifestError::RootMismatch { .. })))
+        ));
+        // Rejected download is removed, and nothing is added to the static files directory
+        let staging_dir = directory.path().join(QUARANTINE_DIR_NAME).join(REPAIR_DIR_NAME);
+        assert!(!staging_dir.join(&file.file_name).exists());
+        assert!(list_static_files(directory.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+//! Downgrade path copying rows of static files back into the database, for nodes rolling back to
+//! a setup without static files or re-indexing data.
+
+use crate::{
+    dedup::DedupTables,
+    reader::{block_hash, header_by_number, header_td_by_number, transaction_by_id_no_hash},
+};
+use alloy_primitives::BlockNumber;
+use reth_db::tables;
+use reth_db_api::{database::Database, transaction::DbTxMut};
+use reth_provider::{
+    DatabaseProviderRW, ReceiptProvider, StaticFileProviderFactory, TransactionsProviderExt,
+};
+use reth_static_file_types::StaticFileSegment;
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::ops::RangeInclusive;
+use tracing::debug;
+
+/// Reads the rows of the segment for the block range from static files and reinserts them into
+/// the database tables they were copied from, returning the number of restored rows.
+///
+/// Headers are restored into [`tables::Headers`], [`tables::HeaderTerminalDifficulties`],
+/// [`tables::CanonicalHeaders`] and [`tables::HeaderNumbers`], transactions into
+/// [`tables::Transactions`] and receipts into [`tables::Receipts`]. Transactions and receipts of
+/// the block range are looked up with the block body indices in the database. Headers of static
+/// files without total difficulties, see
+/// [`HeadersLayout::NoTotalDifficulty`](reth_static_file_types::HeadersLayout::NoTotalDifficulty),
+/// can't be restored.
+///
+/// Static files are left as is, and the rows are only persisted once the provider is committed.
+pub fn restore_to_db<DB: Database>(
+    segment: StaticFileSegment,
+    block_range: RangeInclusive<BlockNumber>,
+    provider_rw: &DatabaseProviderRW<DB>,
+) -> ProviderResult<u64> {
+    let static_file_provider = provider_rw.static_file_provider();
+    let tx = provider_rw.tx_ref();
+
+    let rows = match segment {
+        StaticFileSegment::Headers => {
+            for block in block_range.clone() {
+                let header = header_by_number(&static_file_provider, block)?
+                    .ok_or_else(|| ProviderError::HeaderNotFound(block.into()))?;
+                let total_difficulty = header_td_by_number(&static_file_provider, block)?
+                    .ok_or(ProviderError::TotalDifficultyNotFound(block))?;
+                let hash = block_hash(&static_file_provider, block)?
+                    .ok_or_else(|| ProviderError::HeaderNotFound(block.into()))?;
+
+                tx.put::<tables::Headers>(block, header)?;
+                tx.put::<tables::HeaderTerminalDifficulties>(block, total_difficulty.into())?;
+                tx.put::<tables::CanonicalHeaders>(block, hash)?;
+                tx.put::<tables::HeaderNumbers>(hash, block)?;
+            }
+            block_range.end().saturating_sub(*block_range.start()) + 1
+        }
+        StaticFileSegment::Transactions => {
+            let tx_range = provider_rw.transaction_range_by_block_range(block_range.clone())?;
+            let dedup_tables = DedupTables::default();
+            for tx_number in tx_range.clone() {
+                let mut transaction = transaction_by_id_no_hash(&static_file_provider, tx_number)?
+                    .ok_or(ProviderError::TransactionNotFound(tx_number.into()))?;
+                dedup_tables.reassemble(&static_file_provider, tx_number, &mut transaction)?;
+                tx.put::<tables::Transactions>(tx_number, transaction)?;
+            }
+            tx_range.count() as u64
+        }
+        StaticFileSegment::Receipts => {
+            let tx_range = provider_rw.transaction_range_by_block_range(block_range.clone())?;
+            for tx_number in tx_range.clone() {
+                let receipt = static_file_provider
+                    .receipt(tx_number)?
+                    .ok_or(ProviderError::ReceiptNotFound(tx_number.into()))?;
+                tx.put::<tables::Receipts>(tx_number, receipt)?;
+            }
+            tx_range.count() as u64
+        }
+    };
+
+    debug!(target: "static_file", %segment, ?block_range, rows, "Restored static file rows to the database");
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_db_api::transaction::DbTx;
+
+    #[test]
+    fn restore() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+
+        let provider_rw = harness.provider_factory.provider_rw().unwrap();
+        provider_rw.tx_ref().clear::<tables::Headers>().unwrap();
+        provider_rw.tx_ref().clear::<tables::Transactions>().unwrap();
+        provider_rw.tx_ref().clear::<tables::Receipts>().unwrap();
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            restore_to_db(segment, 0..=harness.tip(), &provider_rw).unwrap();
+        }
+        provider_rw.commit().unwrap();
+
+        let tx = harness.provider_factory.db_ref().tx().unwrap();
+        for block in &harness.blocks {
+            assert_eq!(
+                tx.get::<tables::Headers>(block.number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+        }
+        let tx_count = harness.blocks.iter().map(|block| block.body.len()).sum::<usize>();
+        assert_eq!(tx.entries::<tables::Transactions>().unwrap(), tx_count);
+        assert_eq!(tx.entries::<tables::Receipts>().unwrap(), tx_count);
+    }
+}
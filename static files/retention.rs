@@ -0,0 +1,235 @@
+//! Retention of old static files.
+
+use crate::{
+    files::{list_static_files, lowest_static_files},
+    readers::retire,
+    tiering::{move_cold_to, remove_cold},
+    StaticFileEntry, StaticFileProducerError,
+};
+use reth_nippy_jar::NippyJar;
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::{
+    HighestStaticFiles, LowestStaticFiles, SegmentHeader, StaticFileSegment,
+};
+use reth_storage_errors::provider::ProviderError;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// Destination of static files removed by a [`RetentionPolicy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionSink {
+    /// Static files are deleted.
+    #[default]
+    Delete,
+    /// Static files are moved into the directory, e.g. on a cold storage mount.
+    MoveTo(PathBuf),
+}
+
+/// Retention policy for sealed static files, per data segment.
+///
+/// Only whole static files entirely outside of the retention window are removed, so more blocks
+/// than configured can be kept. The static file holding the highest block is never removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Number of most recent headers to keep. If `None`, all headers are kept.
+    pub headers: Option<u64>,
+    /// Number of most recent blocks of receipts to keep. If `None`, all receipts are kept.
+    pub receipts: Option<u64>,
+    /// Number of most recent blocks of transactions to keep. If `None`, all transactions are
+    /// kept.
+    pub transactions: Option<u64>,
+    /// Destination of removed static files.
+    pub sink: RetentionSink,
+}
+
+impl RetentionPolicy {
+    /// Returns the number of most recent blocks to keep for a given segment, if it's limited.
+    pub const fn keep(&self, segment: StaticFileSegment) -> Option<u64> {
+        match segment {
+            StaticFileSegment::Headers => self.headers,
+            StaticFileSegment::Transactions => self.transactions,
+            StaticFileSegment::Receipts => self.receipts,
+        }
+    }
+
+    /// Returns the static files that are entirely outside of the retention window, which ends at
+    /// the highest static file block of their segment.
+    pub fn expired<'a>(
+        &self,
+        entries: &'a [StaticFileEntry],
+        highest_static_files: HighestStaticFiles,
+    ) -> Vec<&'a StaticFileEntry> {
+        entries
+            .iter()
+            .filter(|entry| {
+                let (Some(keep), Some(highest)) =
+                    (self.keep(entry.segment), highest_static_files.highest(entry.segment))
+                else {
+                    return false
+                };
+
+                // First block inside of the retention window
+                let window_start = (highest + 1).saturating_sub(keep);
+                entry.block_range.end() < window_start && !entry.block_range.contains(highest)
+            })
+            .collect()
+    }
+
+    /// Removes the static file according to the [`RetentionSink`]. Deleted static files are
+    /// [retired](crate::readers::retire) in the static files directory, so readers that still
+    /// have them open keep them until they're dropped. Files of static files moved to the cold
+    /// directory are deleted or moved along with their links.
+    pub(crate) fn remove(
+        &self,
+        directory: &Path,
+        entry: &StaticFileEntry,
+    ) -> Result<(), StaticFileProducerError> {
+        let jar = NippyJar::<SegmentHeader>::load(&entry.path)
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+
+        match &self.sink {
+            // Data file, offsets, configuration, filters and sidecars are all companions
+            RetentionSink::Delete => {
+                retire(directory, entry.paths())?;
+                remove_cold(directory, entry)?;
+            }
+            RetentionSink::MoveTo(destination) => {
+                std::fs::create_dir_all(destination)?;
+                if move_cold_to(directory, entry, destination)? {
+                    return Ok(())
+                }
+                for path in [
+                    jar.data_path().to_path_buf(),
+                    jar.offsets_path(),
+                    jar.index_path(),
+                    jar.config_path(),
+                ]
+                .into_iter()
+                .chain(entry.paths())
+                {
+                    if path.exists() {
+                        move_file(&path, destination)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Service applying a [`RetentionPolicy`] to the static files directory, held by the
+/// [`StaticFileProducer`](crate::StaticFileProducer) and applied after every run.
+#[derive(Debug, Clone)]
+pub struct RetentionService {
+    policy: RetentionPolicy,
+}
+
+impl RetentionService {
+    /// Creates a new [`RetentionService`] applying the policy.
+    pub const fn new(policy: RetentionPolicy) -> Self {
+        Self { policy }
+    }
+
+    /// Returns the applied [`RetentionPolicy`].
+    pub const fn policy(&self) -> &RetentionPolicy {
+        &self.policy
+    }
+
+    /// Removes the sealed static files that are entirely outside of the retention window, and
+    /// reloads the static file index afterwards.
+    pub fn apply(
+        &self,
+        static_file_provider: &StaticFileProvider,
+    ) -> Result<RetentionOutcome, StaticFileProducerError> {
+        let mut entries = list_static_files(static_file_provider.directory())?;
+        let expired = self
+            .policy
+            .expired(&entries, static_file_provider.get_highest_static_files())
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        for entry in &expired {
+            debug!(target: "static_file", segment = %entry.segment, block_range = %entry.block_range, "Removing static file out of retention window");
+            self.policy.remove(static_file_provider.directory(), entry)?;
+        }
+
+        if !expired.is_empty() {
+            entries.retain(|entry| !expired.contains(entry));
+            static_file_provider.initialize_index()?;
+        }
+
+        Ok(RetentionOutcome::new(expired, &entries))
+    }
+}
+
+/// Outcome of applying a [`RetentionPolicy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RetentionOutcome {
+    /// Static files removed from the static files directory.
+    pub removed: Vec<StaticFileEntry>,
+    /// Lowest blocks still available in static files after the removal.
+    pub lowest: LowestStaticFiles,
+}
+
+impl RetentionOutcome {
+    /// Creates a new [`RetentionOutcome`] from the removed and remaining static files.
+    pub(crate) fn new(removed: Vec<StaticFileEntry>, remaining: &[StaticFileEntry]) -> Self {
+        Self { removed, lowest: lowest_static_files(remaining) }
+    }
+}
+
+/// Moves the file into the directory, falling back to copy and delete when the directory is on
+/// another filesystem.
+pub(crate) fn move_file(path: &Path, directory: &Path) -> std::io::Result<()> {
+    let Some(file_name) = path.file_name() else { return Ok(()) };
+    let destination = directory.join(file_name);
+
+    if std::fs::rename(path, &destination).is_err() {
+        std::fs::copy(path, &destination)?;
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_static_file_types::SegmentRangeInclusive;
+
+    #[test]
+    fn expired_static_files() {
+        let entry = |segment, start: u64| StaticFileEntry {
+            segment,
+            block_range: SegmentRangeInclusive::new(start, start + 499_999),
+            path: PathBuf::default(),
+        };
+        let entries = [
+            entry(StaticFileSegment::Headers, 0),
+            entry(StaticFileSegment::Receipts, 0),
+            entry(StaticFileSegment::Receipts, 500_000),
+            entry(StaticFileSegment::Receipts, 1_000_000),
+            entry(StaticFileSegment::Receipts, 1_500_000),
+        ];
+        let highest = HighestStaticFiles {
+            headers: Some(1_700_000),
+            receipts: Some(1_700_000),
+            transactions: None,
+        };
+
+        // Window of receipts is 700_001..=1_700_000, so only the first static file is outside of it
+        let policy = RetentionPolicy { receipts: Some(1_000_000), ..Default::default() };
+        assert_eq!(policy.expired(&entries, highest), vec![&entries[1]]);
+
+        // Static file with the highest block is never removed
+        let policy = RetentionPolicy { receipts: Some(0), ..Default::default() };
+        assert_eq!(policy.expired(&entries, highest), vec![&entries[1], &entries[2], &entries[3]]);
+
+        // Segments without policy are kept
+        assert!(RetentionPolicy::default().expired(&entries, highest).is_empty());
+    }
+}
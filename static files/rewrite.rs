@@ -0,0 +1,408 @@
+//! Forced rewrites of sealed static files from the database, for block ranges that are already
+//! covered by static files, e.g. to repair a static file or to change its compression and filters.
+
+use crate::{
+    doctor::{diagnose, Corruption},
+    files::is_plain_file_name,
+    rollback::sync_directory,
+    segments::{Segment, SegmentProvider},
+    StaticFileEntry, DEDUP_EXTENSION,
+};
+use reth_nippy_jar::NippyJar;
+use reth_static_file_types::{
+    HighestStaticFiles, SegmentConfig, SegmentHeader, SegmentRangeInclusive, StaticFileSegment,
+};
+use reth_storage_errors::provider::ProviderError;
+use std::{
+    fmt,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+/// Name of the directory within the static files directory, that rewritten static files are
+/// built in before they're verified.
+pub const REWRITE_DIR_NAME: &str = "rewrite";
+
+/// Extension of the marker of a verified rewrite in the [`REWRITE_DIR_NAME`] directory, listing
+/// the files replacing the static file. Once it's written, the swap is completed even if the
+/// process crashes halfway through it, see [`complete_rewrites`].
+const SWAP_MARKER_EXTENSION: &str = "swap";
+
+/// Error returned by [`rewrite_static_file`]. The static file is left as is in every case.
+#[derive(Debug)]
+pub enum RewriteError {
+    /// Filesystem error.
+    Io(io::Error),
+    /// Error while reading the rows from the database or building the static file.
+    Provider(ProviderError),
+    /// The static file doesn't exist or isn't sealed yet, so it's still appended to.
+    NotSealed {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+    },
+    /// The rewritten static file is corrupt.
+    Corrupt(Corruption),
+    /// The rewritten static file doesn't hold the same blocks, transactions or number of rows as
+    /// the static file it would replace.
+    Mismatch {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+    },
+    /// The rewritten static file
+    /// [needs the static file reader](SegmentHeader::needs_static_file_reader), e.g. Headers
+    /// without the total difficulty column, so the static file provider would find no rows in it.
+    NeedsStaticFileReader {
+        /// Segment of the static file.
+        segment: StaticFileSegment,
+        /// Fixed block range of the static file.
+        block_range: SegmentRangeInclusive,
+    },
+}
+
+impl From<io::Error> for RewriteError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ProviderError> for RewriteError {
+    fn from(value: ProviderError) -> Self {
+        Self::Provider(value)
+    }
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::Provider(err) => fmt::Display::fmt(err, f),
+            Self::NotSealed { segment, block_range } => {
+                write!(f, "{segment} static file for blocks {block_range} is missing or not sealed")
+            }
+            Self::Corrupt(corruption) => {
+                write!(f, "rewritten static file is corrupt: {corruption:?}")
+            }
+            Self::Mismatch { segment, block_range } => write!(
+                f,
+                "rewritten {segment} static file for blocks {block_range} doesn't match the existing one"
+            ),
+            Self::NeedsStaticFileReader { segment, block_range } => write!(
+                f,
+                "rewritten {segment} static file for blocks {block_range} can't be read by the static file provider"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RewriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Provider(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Reproduces the sealed static file from the rows of its block range in the database, with the
+/// segment configuration.
+///
+/// The static file is built in its own directory within the [`REWRITE_DIR_NAME`] directory,
+/// verified to be readable and, unless the existing static file is unreadable itself, to hold the
+/// same blocks, transactions and number of rows. Only then its files are synced and a swap marker
+/// is written, before they replace the existing ones. Sidecars that aren't rebuilt are kept, as
+/// they only depend on the rows, except for the dedup table, as rewritten rows are stored as is.
+///
+/// A rewrite that fails verification is removed. A swap interrupted by a crash is completed by
+/// the next rewrite, or on startup by
+/// [`StaticFileProducerInner::recover_tails`](crate::StaticFileProducerInner::recover_tails).
+/// Returns the rewritten static file.
+pub fn rewrite_static_file<P: SegmentProvider>(
+    segment: &dyn Segment<P>,
+    provider: &P,
+    config: SegmentConfig,
+    entry: &StaticFileEntry,
+    highest_static_files: &HighestStaticFiles,
+) -> Result<StaticFileEntry, RewriteError> {
+    let not_sealed =
+        || RewriteError::NotSealed { segment: entry.segment, block_range: entry.block_range };
+    if !entry.path.exists() || !entry.is_sealed(highest_static_files) {
+        return Err(not_sealed())
+    }
+    let Some((directory, file_name)) = entry.path.parent().zip(entry.path.file_name()) else {
+        return Err(not_sealed())
+    };
+
+    // Leftovers of an interrupted rewrite would be mistaken for files of this one
+    complete_rewrites(directory)?;
+    let rewrite_dir = directory.join(REWRITE_DIR_NAME);
+    let staging_dir = rewrite_dir.join(file_name);
+    let rewritten = StaticFileEntry { path: staging_dir.join(file_name), ..entry.clone() };
+    std::fs::create_dir_all(&staging_dir)?;
+
+    let verified = segment
+        .create_static_file_file(
+            provider,
+            &staging_dir,
+            config,
+            entry.block_range.start()..=entry.block_range.end(),
+        )
+        .map_err(RewriteError::from)
+        .and_then(|()| verify_rewrite(entry, &rewritten));
+    if let Err(err) = verified {
+        std::fs::remove_dir_all(&rewrite_dir)?;
+        return Err(err)
+    }
+
+    swap_static_file(directory, &rewritten)?;
+    std::fs::remove_dir_all(rewrite_dir)?;
+    Ok(entry.clone())
+}
+
+/// Syncs the files of the static file built in its own directory within the
+/// [`REWRITE_DIR_NAME`] directory, and writes a swap marker before they replace the existing ones
+/// in the static files directory. A swap interrupted by a crash is completed by
+/// [`complete_rewrites`].
+pub(crate) fn swap_static_file(directory: &Path, staged: &StaticFileEntry) -> io::Result<()> {
+    let rewrite_dir = directory.join(REWRITE_DIR_NAME);
+    let Some((staging_dir, file_name)) = staged.path.parent().zip(staged.path.file_name()) else {
+        return Ok(())
+    };
+
+    // Companion files are listed before the data file, which replaces the existing one last
+    let mut file_names = Vec::new();
+    for path in staged.paths().into_iter().rev() {
+        File::open(&path)?.sync_all()?;
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            file_names.push(name.to_string());
+        }
+    }
+    sync_directory(staging_dir)?;
+
+    let marker_path = swap_marker_path(&rewrite_dir, file_name);
+    let tmp_path = marker_path.with_extension("tmp");
+    let mut marker = File::create(&tmp_path)?;
+    io::Write::write_all(&mut marker, file_names.join("\n").as_bytes())?;
+    marker.sync_all()?;
+    std::fs::rename(tmp_path, &marker_path)?;
+    sync_directory(&rewrite_dir)?;
+
+    complete_swap(directory, &marker_path)?;
+    Ok(())
+}
+
+/// Completes the swaps of verified rewrites interrupted by a crash, and removes the leftovers of
+/// the other rewrites, which weren't verified. Returns the data files of the completed swaps.
+pub(crate) fn complete_rewrites(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let rewrite_dir = directory.join(REWRITE_DIR_NAME);
+    if !rewrite_dir.exists() {
+        return Ok(Vec::new())
+    }
+
+    let mut completed = Vec::new();
+    for entry in std::fs::read_dir(&rewrite_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == SWAP_MARKER_EXTENSION) {
+            if let Some(data_file) = complete_swap(directory, &path)? {
+                info!(target: "static_file", ?data_file, "Completed interrupted rewrite of static file");
+                completed.push(data_file);
+            }
+        }
+    }
+    std::fs::remove_dir_all(rewrite_dir)?;
+    Ok(completed)
+}
+
+/// Moves the staged files listed in the swap marker into the static files directory, removes the
+/// filters and dedup table of the existing static file if the rewrite has none, and removes the
+/// marker. Staged files that were already moved by an interrupted swap are skipped.
+///
+/// Returns the data file of the swapped static file, or `None` if the marker isn't of a static
+/// file.
+fn complete_swap(directory: &Path, marker_path: &Path) -> io::Result<Option<PathBuf>> {
+    let Some(file_name) = marker_path.file_stem().and_then(|name| name.to_str()) else {
+        return Ok(None)
+    };
+    if file_name.contains('.') || StaticFileSegment::parse_filename(file_name).is_none() {
+        return Ok(None)
+    }
+    let staging_dir = marker_path.with_extension("");
+    let file_names = std::fs::read_to_string(marker_path)?;
+    let file_names = file_names
+        .lines()
+        .filter(|name| {
+            is_plain_file_name(name) &&
+                name.strip_prefix(file_name)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        })
+        .collect::<Vec<_>>();
+
+    // Filters and deduplicated chunks of the existing static file don't apply to the rewritten
+    // rows
+    for extension in ["idx", DEDUP_EXTENSION] {
+        let companion = format!("{file_name}.{extension}");
+        if !file_names.contains(&companion.as_str()) {
+            match std::fs::remove_file(directory.join(&companion)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+    }
+    for name in file_names {
+        let staged = staging_dir.join(name);
+        if staged.exists() {
+            std::fs::rename(staged, directory.join(name))?;
+        }
+    }
+    sync_directory(directory)?;
+
+    std::fs::remove_file(marker_path)?;
+    Ok(Some(directory.join(file_name)))
+}
+
+/// Returns the path of the swap marker of the rewrite of the static file.
+fn swap_marker_path(rewrite_dir: &Path, file_name: &std::ffi::OsStr) -> PathBuf {
+    let mut name = file_name.to_os_string();
+    name.push(".");
+    name.push(SWAP_MARKER_EXTENSION);
+    rewrite_dir.join(name)
+}
+
+/// Verifies that the rewritten static file is readable and holds the same blocks, transactions
+/// and number of rows as the existing one.
+fn verify_rewrite(
+    existing: &StaticFileEntry,
+    rewritten: &StaticFileEntry,
+) -> Result<(), RewriteError> {
+    if let Some(corruption) = diagnose(rewritten, None)? {
+        return Err(RewriteError::Corrupt(corruption))
+    }
+
+    let mismatch =
+        || RewriteError::Mismatch { segment: existing.segment, block_range: existing.block_range };
+    let load = |entry: &StaticFileEntry| NippyJar::<SegmentHeader>::load(&entry.path);
+    let rewritten = load(rewritten).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    if rewritten.user_header().block_range() != Some(&existing.block_range) {
+        return Err(mismatch())
+    }
+    // Static files in the static files directory are read by the static file provider
+    if rewritten.user_header().needs_static_file_reader() {
+        return Err(RewriteError::NeedsStaticFileReader {
+            segment: existing.segment,
+            block_range: existing.block_range,
+        })
+    }
+    // An unreadable static file is rewritten without comparing, e.g. to repair it
+    if let Ok(existing) = load(existing) {
+        if existing.user_header().tx_range() != rewritten.user_header().tx_range() ||
+            existing.rows() != rewritten.rows()
+        {
+            return Err(mismatch())
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{segments::Headers, test_utils::StaticFileTestHarness};
+    use reth_provider::StaticFileProviderFactory;
+    use reth_static_file_types::Filters;
+
+    #[test]
+    fn keeps_static_file_on_failed_rewrite() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let block_range = SegmentRangeInclusive::new(0, 499_999);
+        let entry = StaticFileEntry {
+            segment: StaticFileSegment::Headers,
+            block_range,
+            path: static_file_provider
+                .directory()
+                .join(StaticFileSegment::Headers.filename(&block_range)),
+        };
+        let data = std::fs::read(&entry.path).unwrap();
+        let provider = harness.provider_factory.provider().unwrap();
+        let config = StaticFileSegment::Headers.config();
+
+        // The last static file is still appended to
+        let highest = static_file_provider.get_highest_static_files();
+        assert!(matches!(
+            rewrite_static_file(&Headers::default(), &provider, config, &entry, &highest),
+            Err(RewriteError::NotSealed { segment: StaticFileSegment::Headers, .. })
+        ));
+
+        // Rows past the tip aren't in the database, so the rewrite doesn't cover the static file
+        let highest = HighestStaticFiles { headers: Some(500_000), ..highest };
+        assert!(
+            rewrite_static_file(&Headers::default(), &provider, config, &entry, &highest).is_err()
+        );
+        let staging_dir = static_file_provider.directory().join(REWRITE_DIR_NAME);
+        assert!(!staging_dir.join(entry.path.file_name().unwrap()).exists());
+        assert_eq!(std::fs::read(&entry.path).unwrap(), data);
+    }
+
+    #[test]
+    fn rewrites_static_file() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let directory = harness.static_files_dir.path();
+        let file_name =
+            StaticFileSegment::Headers.filename(&SegmentRangeInclusive::new(0, 499_999));
+        // The harness only has a few blocks, so the static file is sealed at the tip
+        let entry = StaticFileEntry {
+            segment: StaticFileSegment::Headers,
+            block_range: SegmentRangeInclusive::new(0, harness.tip()),
+            path: directory.join(&file_name),
+        };
+        let highest = HighestStaticFiles { headers: Some(harness.tip() + 1), ..Default::default() };
+        let provider = harness.provider_factory.provider().unwrap();
+        let rows = NippyJar::<SegmentHeader>::load(&entry.path).unwrap().rows();
+
+        // Filters are dropped along with their file
+        let config = SegmentConfig {
+            filters: Filters::WithoutFilters,
+            ..StaticFileSegment::Headers.config()
+        };
+        let rewritten =
+            rewrite_static_file(&Headers::default(), &provider, config, &entry, &highest).unwrap();
+        assert_eq!(rewritten, entry);
+        assert!(diagnose(&entry, None).unwrap().is_none());
+        let jar = NippyJar::<SegmentHeader>::load(&entry.path).unwrap();
+        assert_eq!(jar.rows(), rows);
+        assert_eq!(jar.user_header().filter_ids(), None);
+        assert!(!entry.companion_path("idx").exists());
+        assert!(!directory.join(REWRITE_DIR_NAME).exists());
+
+        // Swap interrupted after its marker was written is completed
+        let data = std::fs::read(&entry.path).unwrap();
+        let staging_dir = directory.join(REWRITE_DIR_NAME).join(&file_name);
+        std::fs::create_dir_all(&staging_dir).unwrap();
+        std::fs::write(staging_dir.join(&file_name), &data).unwrap();
+        std::fs::write(
+            directory.join(REWRITE_DIR_NAME).join(format!("{file_name}.{SWAP_MARKER_EXTENSION}")),
+            format!("{file_name}.off\n{file_name}"),
+        )
+        .unwrap();
+        std::fs::write(&entry.path, b"torn").unwrap();
+        assert_eq!(complete_rewrites(directory).unwrap(), vec![entry.path.clone()]);
+        assert_eq!(std::fs::read(&entry.path).unwrap(), data);
+        assert!(entry.companion_path("off").exists());
+        assert!(!directory.join(REWRITE_DIR_NAME).exists());
+
+        // Rewrites that weren't verified are discarded
+        std::fs::create_dir_all(&staging_dir).unwrap();
+        std::fs::write(staging_dir.join(&file_name), b"unverified").unwrap();
+        assert!(complete_rewrites(directory).unwrap().is_empty());
+        assert_eq!(std::fs::read(&entry.path).unwrap(), data);
+        assert!(!directory.join(REWRITE_DIR_NAME).exists());
+    }
+}
@@ -0,0 +1,542 @@
+//! Rollback of partially written static files, when a run fails because the disk is full or the
+//! process crashes in the middle of a commit.
+
+use crate::{
+    list_static_files, sidecar::read_u64, tiering::remove_cold, CommittedRows, StaticFileEntry,
+};
+use alloy_primitives::{BlockNumber, TxNumber};
+use reth_provider::providers::{StaticFileProvider, StaticFileWriter};
+use reth_static_file_types::StaticFileSegment;
+use reth_storage_errors::provider::ProviderResult;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Name of the write-ahead log directory in the static files directory. It holds the
+/// [`TailSnapshot`] of every segment being written to, from the snapshot taken at its last commit
+/// until the snapshot of the next commit supersedes it or the run finishes.
+pub(crate) const WAL_DIR_NAME: &str = "wal";
+
+/// Extension of the companion files that are only appended to. All other companion files are
+/// rewritten as a whole.
+const APPENDED_EXTENSIONS: [&str; 1] = ["off"];
+
+/// Snapshot of the static files of a segment at its last commit, allowing to roll back the rows
+/// written after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TailSnapshot {
+    /// Segment of the static files.
+    segment: StaticFileSegment,
+    /// Static files directory.
+    directory: PathBuf,
+    /// Static files of the segment at the time of the snapshot.
+    entries: Vec<StaticFileEntry>,
+    /// Lengths of the appended files of the last static file.
+    lengths: Vec<(PathBuf, u64)>,
+    /// Contents of the rewritten files of the last static file, e.g. its configuration.
+    contents: Vec<(PathBuf, Vec<u8>)>,
+    /// Highest block and transaction of the segment at the time of the snapshot. Logged to the
+    /// write-ahead log, so [`recover_tails`] can tell a snapshot whose rows were committed since.
+    tip: CommittedTip,
+}
+
+/// Highest block and transaction of a segment at its last commit, which its static file writer is
+/// rolled back to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct CommittedTip {
+    /// Highest committed block, if any.
+    block: Option<BlockNumber>,
+    /// Highest committed transaction, if any. Always `None` for headers.
+    tx: Option<TxNumber>,
+}
+
+impl CommittedTip {
+    /// Returns the highest block and transaction of the segment committed to the static file
+    /// provider.
+    pub(crate) fn of(
+        static_file_provider: &StaticFileProvider,
+        segment: StaticFileSegment,
+    ) -> Self {
+        Self {
+            block: static_file_provider.get_highest_static_file_block(segment),
+            tx: static_file_provider.get_highest_static_file_tx(segment),
+        }
+    }
+
+    /// Returns the last committed row of the segment, numbered like [`CommittedRows`]: a block for
+    /// the Headers segment, and a transaction for the others.
+    const fn row(&self, segment: StaticFileSegment) -> Option<u64> {
+        match segment {
+            StaticFileSegment::Headers => self.block,
+            StaticFileSegment::Transactions | StaticFileSegment::Receipts => self.tx,
+        }
+    }
+}
+
+impl TailSnapshot {
+    /// Takes a snapshot of the static files of the segment.
+    pub(crate) fn take(directory: &Path, segment: StaticFileSegment) -> io::Result<Self> {
+        let mut entries = list_static_files(directory)?;
+        entries.retain(|entry| entry.segment == segment);
+
+        let (mut lengths, mut contents) = (Vec::new(), Vec::new());
+        if let Some(last) = entries.last() {
+            for path in last.paths() {
+                let appended = path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_none_or(|extension| APPENDED_EXTENSIONS.contains(&extension));
+                if appended {
+                    lengths.push((path.clone(), path.metadata()?.len()));
+                } else {
+                    contents.push((path.clone(), std::fs::read(&path)?));
+                }
+            }
+        }
+
+        Ok(Self {
+            segment,
+            directory: directory.to_path_buf(),
+            entries,
+            lengths,
+            contents,
+            tip: CommittedTip::default(),
+        })
+    }
+
+    /// Sets the highest block and transaction of the segment at the time of the snapshot, which
+    /// [`TailSnapshot::reset_writer`] prunes the writer back to.
+    pub(crate) const fn with_tip(mut self, tip: CommittedTip) -> Self {
+        self.tip = tip;
+        self
+    }
+
+    /// Prunes the rows appended to the static file writer of the segment after the snapshot,
+    /// including the uncommitted rows it still holds, and commits the writer. Otherwise, the next
+    /// commit of the static file provider would persist them, even after the files are restored.
+    ///
+    /// Must be called before [`TailSnapshot::restore`], as the writer truncates its files to the
+    /// offsets of the rows it keeps.
+    pub(crate) fn reset_writer(
+        &self,
+        static_file_provider: &StaticFileProvider,
+    ) -> ProviderResult<()> {
+        // Number of rows numbered after the tip, up to the highest one
+        let appended = |highest: Option<u64>, tip: Option<u64>| {
+            highest.map_or(0, |highest| highest + 1).saturating_sub(tip.map_or(0, |tip| tip + 1))
+        };
+
+        let mut writer = static_file_provider.latest_writer(self.segment)?;
+        // Rows of static files started after the tip were committed by the writer when it moved
+        // on to them, so the index is checked too
+        match self.segment {
+            StaticFileSegment::Headers => {
+                let highest = writer
+                    .user_header()
+                    .block_end()
+                    .max(static_file_provider.get_highest_static_file_block(self.segment));
+                writer.prune_headers(appended(highest, self.tip.block))?;
+            }
+            StaticFileSegment::Transactions | StaticFileSegment::Receipts => {
+                let highest = writer
+                    .user_header()
+                    .tx_end()
+                    .max(static_file_provider.get_highest_static_file_tx(self.segment));
+                let to_delete = appended(highest, self.tip.tx);
+                let last_block = self.tip.block.unwrap_or_default();
+                if self.segment == StaticFileSegment::Transactions {
+                    writer.prune_transactions(to_delete, last_block)?;
+                } else {
+                    writer.prune_receipts(to_delete, last_block)?;
+                }
+            }
+        }
+        writer.commit()
+    }
+
+    /// Restores the static files of the segment to the snapshot: removes static files created
+    /// after it, truncates appended files and rewrites the other files of the last static file.
+    pub(crate) fn restore(&self) -> io::Result<()> {
+        for entry in list_static_files(&self.directory)? {
+            if entry.segment == self.segment && !self.entries.contains(&entry) {
+                for path in entry.paths() {
+                    std::fs::remove_file(path)?;
+                }
+                remove_cold(&self.directory, &entry)?;
+            }
+        }
+
+        // Companion files created after the snapshot, e.g. sidecars
+        if let Some(last) = self.entries.last() {
+            let known = self
+                .lengths
+                .iter()
+                .map(|(path, _)| path)
+                .chain(self.contents.iter().map(|(path, _)| path))
+                .collect::<Vec<_>>();
+            for path in last.paths() {
+                if !known.contains(&&path) {
+                    std::fs::remove_file(path)?;
+                }
+            }
+        }
+        for (path, len) in &self.lengths {
+            OpenOptions::new().write(true).open(path)?.set_len(*len)?;
+        }
+        for (path, contents) in &self.contents {
+            std::fs::write(path, contents)?;
+        }
+
+        Ok(())
+    }
+
+    /// Logs the snapshot to the write-ahead log before any row is written after it, replacing the
+    /// snapshot of the previous commit. If the process crashes before the next snapshot is logged,
+    /// leaving the data and offsets files inconsistent, the snapshot is restored by
+    /// [`recover_tails`].
+    pub(crate) fn log(&self) -> io::Result<()> {
+        let path = wal_path(&self.directory, self.segment);
+        let wal_directory = self.directory.join(WAL_DIR_NAME);
+        std::fs::create_dir_all(&wal_directory)?;
+
+        // Written in full before it replaces the previous entry
+        let tmp_path = path.with_extension("tmp");
+        let mut buf = Vec::new();
+        self.encode(&mut buf)?;
+        let mut file = File::create(&tmp_path)?;
+        io::Write::write_all(&mut file, &buf)?;
+        file.sync_all()?;
+        std::fs::rename(tmp_path, path)?;
+        sync_directory(&wal_directory)
+    }
+
+    /// Removes the write-ahead log entry of the segment, once the run finished and no rows are
+    /// written after its last commit.
+    pub(crate) fn clear_log(directory: &Path, segment: StaticFileSegment) -> io::Result<()> {
+        match std::fs::remove_file(wal_path(directory, segment)) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Encodes the segment, the names of the static files, the appended files with their lengths,
+    /// the rewritten files with their contents and the tip. Names and contents are prefixed with
+    /// their length, lengths and counts are 64-bit little endian integers, and the block and
+    /// transaction of the tip are prefixed with a byte telling if they're set. Files are recorded
+    /// by name, as they're all in the static files directory.
+    fn encode(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        put_bytes(buf, self.segment.as_str().as_bytes());
+        buf.extend_from_slice(&(self.entries.len() as u64).to_le_bytes());
+        for entry in &self.entries {
+            put_bytes(buf, file_name(&entry.path)?.as_bytes());
+        }
+        buf.extend_from_slice(&(self.lengths.len() as u64).to_le_bytes());
+        for (path, len) in &self.lengths {
+            put_bytes(buf, file_name(path)?.as_bytes());
+            buf.extend_from_slice(&len.to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.contents.len() as u64).to_le_bytes());
+        for (path, contents) in &self.contents {
+            put_bytes(buf, file_name(path)?.as_bytes());
+            put_bytes(buf, contents);
+        }
+        for row in [self.tip.block, self.tip.tx] {
+            put_optional_u64(buf, row);
+        }
+        Ok(())
+    }
+
+    /// Decodes a snapshot encoded with [`TailSnapshot::encode`] of the static files directory.
+    fn decode(directory: &Path, mut buf: &[u8]) -> io::Result<Self> {
+        let buf = &mut buf;
+        let segment = StaticFileSegment::from_str(get_str(buf)?).map_err(io::Error::other)?;
+
+        let mut entries = Vec::new();
+        for _ in 0..read_u64(buf)? {
+            let name = get_str(buf)?;
+            let Some((segment, block_range)) = StaticFileSegment::parse_filename(name) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid static file name {name:?}"),
+                ))
+            };
+            entries.push(StaticFileEntry { segment, block_range, path: directory.join(name) });
+        }
+        let mut lengths = Vec::new();
+        for _ in 0..read_u64(buf)? {
+            lengths.push((directory.join(get_str(buf)?), read_u64(buf)?));
+        }
+        let mut contents = Vec::new();
+        for _ in 0..read_u64(buf)? {
+            contents.push((directory.join(get_str(buf)?), get_bytes(buf)?.to_vec()));
+        }
+        let tip = CommittedTip { block: get_optional_u64(buf)?, tx: get_optional_u64(buf)? };
+
+        Ok(Self { segment, directory: directory.to_path_buf(), entries, lengths, contents, tip })
+    }
+}
+
+/// Returns the path of the write-ahead log entry of the segment.
+fn wal_path(directory: &Path, segment: StaticFileSegment) -> PathBuf {
+    directory.join(WAL_DIR_NAME).join(format!("{segment}.tail"))
+}
+
+/// Returns the name of the file in the static files directory.
+fn file_name(path: &Path) -> io::Result<&str> {
+    path.file_name().and_then(|name| name.to_str()).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("invalid file name {path:?}"))
+    })
+}
+
+/// Appends the bytes, prefixed with their length.
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+/// Writes an optional integer, prefixed with a byte telling if it's set.
+fn put_optional_u64(buf: &mut Vec<u8>, value: Option<u64>) {
+    buf.push(value.is_some() as u8);
+    buf.extend_from_slice(&value.unwrap_or_default().to_le_bytes());
+}
+
+/// Reads an optional integer written by [`put_optional_u64`].
+fn get_optional_u64(buf: &mut &[u8]) -> io::Result<Option<u64>> {
+    let Some((&set, rest)) = buf.split_first() else {
+        return Err(io::ErrorKind::UnexpectedEof.into())
+    };
+    *buf = rest;
+    let value = read_u64(buf)?;
+    Ok((set != 0).then_some(value))
+}
+
+/// Reads bytes prefixed with their length.
+fn get_bytes<'a>(buf: &mut &'a [u8]) -> io::Result<&'a [u8]> {
+    let len = read_u64(buf)?;
+    if len > buf.len() as u64 {
+        return Err(io::ErrorKind::UnexpectedEof.into())
+    }
+    let (bytes, rest) = buf.split_at(len as usize);
+    *buf = rest;
+    Ok(bytes)
+}
+
+/// Reads a plain file name or segment prefixed with its length.
+fn get_str<'a>(buf: &mut &'a [u8]) -> io::Result<&'a str> {
+    let name = std::str::from_utf8(get_bytes(buf)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    if name.contains(['/', '\\']) || name.is_empty() || name == ".." {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid name {name:?}")))
+    }
+    Ok(name)
+}
+
+/// Syncs the directory, so renames into it survive a crash.
+pub(crate) fn sync_directory(directory: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    File::open(directory)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = directory;
+    Ok(())
+}
+
+/// Restores the snapshots left in the write-ahead log by runs that never finished, rolling back
+/// the rows written after their last commit, and clears the log.
+///
+/// Snapshots whose tip is behind the [`CommittedRows`] published in the directory are stale: their
+/// run committed and published more rows after them, but failed to clear the log. They're removed
+/// without being restored.
+///
+/// Returns the segments whose static files were rolled back.
+pub(crate) fn recover_tails(directory: &Path) -> io::Result<Vec<StaticFileSegment>> {
+    let wal_directory = directory.join(WAL_DIR_NAME);
+    if !wal_directory.exists() {
+        return Ok(Vec::new())
+    }
+
+    let committed = CommittedRows::read(directory)?.unwrap_or_default();
+    let mut recovered = Vec::new();
+    for entry in std::fs::read_dir(wal_directory)? {
+        let path = entry?.path();
+        // Entries that weren't written in full never became part of the log
+        if path.extension().is_some_and(|extension| extension == "tail") {
+            let mut buf = Vec::new();
+            File::open(&path)?.read_to_end(&mut buf)?;
+            let snapshot = TailSnapshot::decode(directory, &buf)?;
+            if committed.get(snapshot.segment) <= snapshot.tip.row(snapshot.segment) {
+                snapshot.restore()?;
+                recovered.push(snapshot.segment);
+            }
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    recovered.sort_unstable();
+    Ok(recovered)
+}
+
+/// Returns `true` if the error, or any of its sources, was caused by a full disk.
+pub(crate) fn is_disk_full(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut err = Some(err);
+    while let Some(current) = err {
+        #[cfg(unix)]
+        if current
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| err.raw_os_error() == Some(libc::ENOSPC))
+        {
+            return true
+        }
+        // Errors of static files are often wrapped as strings
+        if current.to_string().contains("No space left on device") {
+            return true
+        }
+        err = current.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{manifest::write_json, COMMITTED_ROWS_FILE_NAME};
+
+    #[test]
+    fn restores_tail() {
+        let directory = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &[u8]| {
+            std::fs::write(directory.path().join(name), contents).unwrap();
+        };
+        write("static_file_headers_0_499999", b"rows");
+        write("static_file_headers_0_499999.off", b"offsets");
+        write("static_file_headers_0_499999.conf", b"config");
+        write("static_file_receipts_0_499999", b"receipts");
+
+        let snapshot = TailSnapshot::take(directory.path(), StaticFileSegment::Headers).unwrap();
+
+        // Rows written after the snapshot
+        write("static_file_headers_0_499999", b"rows and more rows");
+        write("static_file_headers_0_499999.off", b"offsets and more");
+        write("static_file_headers_0_499999.conf", b"new config");
+        write("static_file_headers_0_499999.logs", b"sidecar");
+        write("static_file_headers_500000_999999", b"next rows");
+        write("static_file_receipts_0_499999", b"more receipts");
+
+        snapshot.restore().unwrap();
+        let read = |name: &str| std::fs::read(directory.path().join(name)).unwrap();
+        assert_eq!(read("static_file_headers_0_499999"), b"rows");
+        assert_eq!(read("static_file_headers_0_499999.off"), b"offsets");
+        assert_eq!(read("static_file_headers_0_499999.conf"), b"config");
+        assert!(!directory.path().join("static_file_headers_0_499999.logs").exists());
+        assert!(!directory.path().join("static_file_headers_500000_999999").exists());
+        // Other segments are left as is
+        assert_eq!(read("static_file_receipts_0_499999"), b"more receipts");
+    }
+
+    #[test]
+    fn recovers_logged_tail() {
+        let directory = tempfile::tempdir().unwrap();
+        let data_path = directory.path().join("static_file_receipts_0_499999");
+        let offsets_path = directory.path().join("static_file_receipts_0_499999.off");
+        std::fs::write(&data_path, b"rows").unwrap();
+        std::fs::write(&offsets_path, b"offsets").unwrap();
+
+        let snapshot = TailSnapshot::take(directory.path(), StaticFileSegment::Receipts).unwrap();
+        snapshot.log().unwrap();
+        let mut buf = Vec::new();
+        snapshot.encode(&mut buf).unwrap();
+        assert_eq!(TailSnapshot::decode(directory.path(), &buf).unwrap(), snapshot);
+        assert!(TailSnapshot::decode(directory.path(), &buf[..buf.len() - 1]).is_err());
+
+        // Crash after the rows were written, but before their offsets
+        std::fs::write(&data_path, b"rows and more rows").unwrap();
+
+        assert_eq!(recover_tails(directory.path()).unwrap(), vec![StaticFileSegment::Receipts]);
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"rows");
+        assert_eq!(std::fs::read(&offsets_path).unwrap(), b"offsets");
+        assert_eq!(recover_tails(directory.path()).unwrap(), vec![]);
+
+        // Static files created after the snapshot are covered too
+        snapshot.log().unwrap();
+        let next_path = directory.path().join("static_file_receipts_500000_999999");
+        std::fs::write(&next_path, b"next rows").unwrap();
+        assert_eq!(recover_tails(directory.path()).unwrap(), vec![StaticFileSegment::Receipts]);
+        assert!(!next_path.exists());
+
+        // Finished runs leave nothing to recover
+        snapshot.log().unwrap();
+        TailSnapshot::clear_log(directory.path(), StaticFileSegment::Receipts).unwrap();
+        std::fs::write(&data_path, b"rows and more rows").unwrap();
+        assert_eq!(recover_tails(directory.path()).unwrap(), vec![]);
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"rows and more rows");
+    }
+
+    #[test]
+    fn skips_stale_tail() {
+        let directory = tempfile::tempdir().unwrap();
+        let data_path = directory.path().join("static_file_receipts_0_499999");
+        std::fs::write(&data_path, b"rows").unwrap();
+
+        let publish = |receipts| {
+            let committed =
+                CommittedRows { epoch: 1, receipts: Some(receipts), ..Default::default() };
+            write_json(&committed, &directory.path().join(COMMITTED_ROWS_FILE_NAME)).unwrap();
+        };
+
+        let snapshot = TailSnapshot::take(directory.path(), StaticFileSegment::Receipts)
+            .unwrap()
+            .with_tip(CommittedTip { block: Some(9), tx: Some(99) });
+        let mut buf = Vec::new();
+        snapshot.encode(&mut buf).unwrap();
+        assert_eq!(TailSnapshot::decode(directory.path(), &buf).unwrap(), snapshot);
+
+        // Rows up to the tip are committed, the rows after it aren't
+        snapshot.log().unwrap();
+        std::fs::write(&data_path, b"rows and more rows").unwrap();
+        publish(99);
+        assert_eq!(recover_tails(directory.path()).unwrap(), vec![StaticFileSegment::Receipts]);
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"rows");
+
+        // The run committed the rows after the tip, but failed to clear the log
+        snapshot.log().unwrap();
+        std::fs::write(&data_path, b"rows and more rows").unwrap();
+        publish(199);
+        assert_eq!(recover_tails(directory.path()).unwrap(), vec![]);
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"rows and more rows");
+        assert_eq!(recover_tails(directory.path()).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn detects_disk_full() {
+        #[cfg(unix)]
+        assert!(is_disk_full(&io::Error::from_raw_os_error(libc::ENOSPC)));
+        assert!(is_disk_full(&io::Error::other(
+            "write failed: No space left on device (os error 28)"
+        )));
+        assert!(!is_disk_full(&io::Error::from(io::ErrorKind::NotFound)));
+    }
+
+    /// Fills a quota-limited filesystem, e.g. `mount -t tmpfs -o size=1m tmpfs <dir>`, whose path
+    /// is set in `STATIC_FILES_TMPFS`.
+    #[test]
+    #[ignore = "requires a quota-limited tmpfs in STATIC_FILES_TMPFS"]
+    fn rolls_back_on_full_tmpfs() {
+        let directory = tempfile::tempdir_in(std::env::var("STATIC_FILES_TMPFS").unwrap()).unwrap();
+        let data_path = directory.path().join("static_file_headers_0_499999");
+        std::fs::write(&data_path, b"rows").unwrap();
+        let snapshot = TailSnapshot::take(directory.path(), StaticFileSegment::Headers).unwrap();
+
+        let mut file = OpenOptions::new().append(true).open(&data_path).unwrap();
+        let err = loop {
+            if let Err(err) = io::Write::write_all(&mut file, &[0; 64 * 1024]) {
+                break err
+            }
+        };
+        assert!(is_disk_full(&err));
+
+        snapshot.restore().unwrap();
+        assert_eq!(std::fs::read(&data_path).unwrap(), b"rows");
+    }
+}
@@ -0,0 +1,145 @@
+//! Rebuild of the inclusion filter and perfect hashing function of existing static files.
+
+use super::{collect_chd_keys, filter_keys, with_filters};
+use crate::{
+    chd_index::{remove_chd_index, write_chd_index},
+    DedupTable,
+};
+use alloy_primitives::B256;
+use reth_db_api::table::Decompress;
+use reth_nippy_jar::{ColumnResult, NippyJar, NippyJarCursor};
+use reth_primitives::TransactionSignedNoHash;
+use reth_provider::ProviderError;
+use reth_static_file_types::{
+    FilterHash, FilterKey, Filters, ReceiptKeyMode, SegmentConfigError, SegmentHeader,
+    SegmentRangeInclusive, StaticFileSegment,
+};
+use reth_storage_errors::provider::ProviderResult;
+use std::path::Path;
+
+/// Regenerates the inclusion filter and perfect hashing function of the static file, keyed by
+/// hashes re-derived from its rows and hashed with `filter_hash`, under `filter_key` if it's keyed.
+/// Only its configuration and index are rewritten, the compressed columns are left as is.
+///
+/// Receipts are keyed by the hashes of their transactions, so the Transactions static file of the
+/// same range has to be present. Static files that
+/// [need the static file reader](SegmentHeader::needs_static_file_reader) get no filters.
+pub(crate) fn rebuild_filters(
+    directory: &Path,
+    segment: StaticFileSegment,
+    block_range: SegmentRangeInclusive,
+    filters: Filters,
+    filter_hash: FilterHash,
+    filter_key: Option<FilterKey>,
+) -> ProviderResult<()> {
+    let Filters::WithFilters(inclusion_filter, phf) = filters else {
+        return Err(ProviderError::NippyJar(
+            "filters can't be removed from an existing static file".to_string(),
+        ))
+    };
+    if !filter_hash.is_supported_by(segment) {
+        return Err(ProviderError::NippyJar(
+            SegmentConfigError::UnsupportedFilterHash(segment).to_string(),
+        ))
+    }
+    if !phf.is_supported_by(segment) {
+        return Err(ProviderError::NippyJar(
+            SegmentConfigError::UnsupportedPerfectHashingFunction(phf, segment).to_string(),
+        ))
+    }
+
+    let mut jar = load_jar(directory, segment, block_range)?;
+    // The static file provider would look their rows up by hash with the filters
+    if jar.user_header().needs_static_file_reader() {
+        return Err(ProviderError::NippyJar(format!(
+            "filters can't be added to {segment} static file {block_range}, which is only read \
+             by the static file reader"
+        )))
+    }
+    let hashes = match segment {
+        StaticFileSegment::Headers => {
+            let column = jar.user_header().headers_layout().block_hash_column();
+            read_hashes(&jar, |_, columns| Some(B256::from_slice(columns.get(column)?)))?
+        }
+        StaticFileSegment::Transactions => transaction_hashes(&jar)?,
+        StaticFileSegment::Receipts => {
+            let transactions = load_jar(directory, StaticFileSegment::Transactions, block_range)?;
+            if transactions.user_header().tx_range() != jar.user_header().tx_range() {
+                return Err(ProviderError::NippyJar(format!(
+                    "transactions of {segment} static file {block_range} don't match its receipts"
+                )))
+            }
+            transaction_hashes(&transactions)?
+        }
+    };
+
+    let rows = jar.rows();
+    jar = with_filters(jar, inclusion_filter, phf, rows);
+    let keys = filter_keys(filter_hash, filter_key, hashes.into_iter().map(ColumnResult::Ok));
+    let (keys, chd_keys) = collect_chd_keys(filters, Some(keys))?;
+    if let Some(keys) = keys {
+        jar.prepare_index(keys, rows).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    }
+    jar.user_header_mut().set_filter_fpp(Some(inclusion_filter.fpp(rows, rows)));
+    jar.user_header_mut().set_filter_hash(filter_hash);
+    jar.user_header_mut().set_filter_ids(filters.ids());
+    if segment.is_receipts() {
+        jar.user_header_mut().set_receipt_key_mode(ReceiptKeyMode::TxHash);
+    }
+
+    // The CHD sidecar is written before the configuration recording it, and removed after the
+    // configuration no longer does
+    if let Some(keys) = &chd_keys {
+        write_chd_index(jar.data_path(), keys)
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    }
+    jar.freeze_filters().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    jar.freeze_config().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    if chd_keys.is_none() {
+        remove_chd_index(jar.data_path())
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Loads the static file of the segment with the fixed block range.
+fn load_jar(
+    directory: &Path,
+    segment: StaticFileSegment,
+    block_range: SegmentRangeInclusive,
+) -> ProviderResult<NippyJar<SegmentHeader>> {
+    NippyJar::load(&directory.join(segment.filename(&block_range)))
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))
+}
+
+/// Returns the hashes of all transactions in the static file, reassembled with its
+/// [`DedupTable`] if they were deduplicated.
+fn transaction_hashes(jar: &NippyJar<SegmentHeader>) -> ProviderResult<Vec<B256>> {
+    let dedup = DedupTable::read(jar.data_path())
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    let tx_start = jar.user_header().tx_start().unwrap_or_default();
+    read_hashes(jar, |row, columns| {
+        let mut transaction = TransactionSignedNoHash::decompress(columns.first()?).ok()?;
+        dedup.reassemble(tx_start + row as u64, &mut transaction).ok()?;
+        Some(transaction.hash())
+    })
+}
+
+/// Reads the hash of every row of the static file from its number and columns, in row order.
+fn read_hashes(
+    jar: &NippyJar<SegmentHeader>,
+    hash: impl Fn(usize, &[&[u8]]) -> Option<B256>,
+) -> ProviderResult<Vec<B256>> {
+    let mut cursor =
+        NippyJarCursor::new(jar).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    (0..jar.rows())
+        .map(|row| {
+            let columns = cursor
+                .row_by_number(row)
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?
+                .ok_or_else(|| ProviderError::NippyJar(format!("row {row} is missing")))?;
+            hash(row, &columns)
+                .ok_or_else(|| ProviderError::NippyJar(format!("row {row} can't be decoded")))
+        })
+        .collect()
+}
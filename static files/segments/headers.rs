@@ -1,139 +1,238 @@
-use crate::segments::{dataset_for_compression, prepare_jar, Segment, SegmentHeader};
-use alloy_primitives::BlockNumber;
-use reth_db::{static_file::create_static_file_T1_T2_T3, tables, RawKey, RawTable};
-use reth_db_api::{cursor::DbCursorRO, database::Database, transaction::DbTx};
-use reth_provider::{
-    providers::{StaticFileProvider, StaticFileWriter},
-    DatabaseProviderRO,
+use crate::{
+    segments::{
+        batched_rows, dataset_for_compression, encoded_column, filter_keys, freeze_jar,
+        prepare_jar, record_columns, Column, Segment, SegmentHeader, SegmentProvider,
+    },
+    CopiedRows, SegmentProgress, StaticFileSink,
 };
-use reth_static_file_types::{SegmentConfig, StaticFileSegment};
-use reth_storage_errors::provider::ProviderResult;
+use alloy_primitives::{BlockHash, BlockNumber};
+use reth_db_api::{models::CompactU256, table::Compress};
+use reth_nippy_jar::ColumnResult;
+use reth_provider::{BlockHashReader, HeaderProvider};
+use reth_static_file_types::{
+    Filters, HeaderEnvelope, HeadersLayout, SegmentConfig, StaticFileSegment,
+    HEADER_ENVELOPE_VERSION,
+};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
 use std::{ops::RangeInclusive, path::Path};
+use tracing::debug_span;
 
 /// Static File segment responsible for [`StaticFileSegment::Headers`] part of data.
+///
+/// Headers static files without total difficulty or with a [`HeaderEnvelope`] column are created
+/// by [`HeadersWithoutTotalDifficulty`](super::HeadersWithoutTotalDifficulty) and
+/// [`EnvelopedHeaders`](super::EnvelopedHeaders) instead, as the static file writer can't append
+/// to them.
 #[derive(Debug, Default)]
 pub struct Headers;
 
-impl<DB: Database> Segment<DB> for Headers {
+impl<P: SegmentProvider> Segment<P> for Headers {
     /// Returns the specific segment handled by this struct.
     fn segment(&self) -> StaticFileSegment {
         StaticFileSegment::Headers
     }
 
-    /// Copies header-related data within the specified block range to static files.
-    fn copy_to_static_files(
+    /// Copies header-related data within the specified block range to the sink.
+    fn copy_to_sink(
         &self,
-        provider: DatabaseProviderRO<DB>,
-        static_file_provider: StaticFileProvider,
+        provider: &P,
+        sink: &mut dyn StaticFileSink,
         block_range: RangeInclusive<BlockNumber>,
+        progress: &SegmentProgress,
     ) -> ProviderResult<()> {
-        // Retrieve a writer for the static file segment within the specified block range
-        let mut static_file_writer =
-            static_file_provider.get_writer(*block_range.start(), StaticFileSegment::Headers)?;
-
-        // Obtain cursors to read headers, header terminal difficulties, and canonical headers
-        let mut headers_cursor = provider.tx_ref().cursor_read::<tables::Headers>()?;
-        let headers_walker = headers_cursor.walk_range(block_range.clone())?;
-
-        let mut header_td_cursor =
-            provider.tx_ref().cursor_read::<tables::HeaderTerminalDifficulties>()?;
-        let header_td_walker = header_td_cursor.walk_range(block_range.clone())?;
-
-        let mut canonical_headers_cursor =
-            provider.tx_ref().cursor_read::<tables::CanonicalHeaders>()?;
-        let canonical_headers_walker = canonical_headers_cursor.walk_range(block_range)?;
-
-        // Iterate over the data from all three tables in sync
-        for ((header_entry, header_td_entry), canonical_header_entry) in
-            headers_walker.zip(header_td_walker).zip(canonical_headers_walker)
-        {
-            // Extract data entries from each cursor
-            let (header_block, header) = header_entry?;
-            let (header_td_block, header_td) = header_td_entry?;
-            let (canonical_header_block, canonical_header) = canonical_header_entry?;
-
-            // Assert that blocks match across all three entries
-            debug_assert_eq!(header_block, header_td_block);
-            debug_assert_eq!(header_td_block, canonical_header_block);
-
-            // Append the header to the static file and verify the resulting block number
-            let _static_file_block =
-                static_file_writer.append_header(header, header_td.0, canonical_header)?;
-            debug_assert_eq!(_static_file_block, header_block);
-        }
+        // Blocks can't be skipped, as headers have to be contiguous
+        for block in block_range {
+            if progress.is_cancelled() {
+                break
+            }
+
+            // Blocks missing their header, total difficulty or hash leave a gap
+            let (Some(header), Some(total_difficulty), Some(hash)) = (
+                provider.header_by_number(block)?,
+                provider.header_td_by_number(block)?,
+                provider.block_hash(block)?,
+            ) else {
+                let err = ProviderError::HeaderNotFound(block.into());
+                progress.missing_block(block, err, false)?;
+                return Ok(())
+            };
+
+            // Account for the bytes of all three columns as a single row
+            let sizes = match provider.stored_sizes() {
+                Some(stored) => stored.header_sizes(block)?,
+                None => [
+                    header.clone().compress().len(),
+                    CompactU256::from(total_difficulty).compress().len(),
+                    hash.len(),
+                ],
+            };
+            let mut copied = CopiedRows::default();
+            copied.add_row(sizes.iter().sum());
 
+            // Append the header to the sink and verify the resulting block number
+            let _static_file_block = sink.append_header(header, total_difficulty, hash)?;
+            sink.record_row_sizes(&sizes)?;
+            debug_assert_eq!(_static_file_block, block);
+
+            progress.advance(block, copied);
+            sink.commit_if_due(progress)?;
+        }
         Ok(())
     }
 
     /// Creates a static file for the header segment with compressed data.
     fn create_static_file_file(
         &self,
-        provider: &DatabaseProviderRO<DB>,
+        provider: &P,
         directory: &Path,
         config: SegmentConfig,
         block_range: RangeInclusive<BlockNumber>,
     ) -> ProviderResult<()> {
-        let range_len = block_range.clone().count();
-
-        // Prepare data for compression using a closure
-        let jar = prepare_jar::<DB, 3>(
+        create_headers_file(
             provider,
             directory,
-            StaticFileSegment::Headers,
             config,
-            block_range.clone(),
-            range_len,
-            || {
-                Ok([
-                    dataset_for_compression::<DB, tables::Headers>(
-                        provider,
-                        &block_range,
-                        range_len,
-                    )?,
-                    dataset_for_compression::<DB, tables::HeaderTerminalDifficulties>(
-                        provider,
-                        &block_range,
-                        range_len,
-                    )?,
-                    dataset_for_compression::<DB, tables::CanonicalHeaders>(
-                        provider,
-                        &block_range,
-                        range_len,
-                    )?,
-                ])
-            },
-        )?;
-        // Generate list of hashes for filters & PHF
-        // Retrieve hashes if filters are enabled
-        let mut cursor = provider.tx_ref().cursor_read::<RawTable<tables::CanonicalHeaders>>()?;
-        let hashes = if config.filters.has_filters() {
-            Some(
-                cursor
-                    .walk(Some(RawKey::from(*block_range.start())))?
-                    .take(range_len)
-                    .map(|row| row.map(|(_key, value)| value.into_value()).map_err(|e| e.into())),
-            )
-        } else {
-            None
-        };
-
-        // Create the static file for headers using the prepared data
-        create_static_file_T1_T2_T3::<
-            tables::Headers,
-            tables::HeaderTerminalDifficulties,
-            tables::CanonicalHeaders,
-            BlockNumber,
-            SegmentHeader,
-        >(
-            provider.tx_ref(),
             block_range,
-            None,  // No dictionary needed as it's prepared beforehand
-            None::<Vec<std::vec::IntoIter<Vec<u8>>>>,  // No additional hashes needed
-            hashes,  // Use the retrieved hashes if any
-            range_len,
-            jar,  // Use the prepared compressed data
-        )?;
+            HeadersLayout::WithTotalDifficulty,
+            false,
+        )
+    }
+}
 
-        Ok(())
+/// Creates a static file for the header segment with compressed data, with the columns of the
+/// [`HeadersLayout`] followed by a [`HeaderEnvelope`] column if `envelope` is set.
+///
+/// Static files without total difficulty
+/// [need the static file reader](SegmentHeader::needs_static_file_reader), so they're created
+/// without filters, as the static file provider would look their rows up by hash with them.
+pub(crate) fn create_headers_file<P: SegmentProvider>(
+    provider: &P,
+    directory: &Path,
+    config: SegmentConfig,
+    block_range: RangeInclusive<BlockNumber>,
+    layout: HeadersLayout,
+    envelope: bool,
+) -> ProviderResult<()> {
+    let _span = debug_span!(target: "static_file", "create_static_file", segment = %StaticFileSegment::Headers, ?block_range, filters = ?config.filters, compression = ?config.compression).entered();
+    let config = if layout.has_total_difficulty() {
+        config
+    } else {
+        SegmentConfig { filters: Filters::WithoutFilters, ..config }
+    };
+
+    let create_static_file = match layout.columns() + envelope as usize {
+        2 => create_headers_file_with_columns::<P, 2>,
+        3 => create_headers_file_with_columns::<P, 3>,
+        _ => create_headers_file_with_columns::<P, 4>,
+    };
+    create_static_file(provider, directory, config, block_range, layout, envelope)
+}
+
+/// Returns the datasets the dictionaries of the columns of the layout are trained on. The
+/// envelope column gets a dictionary of its own, trained on the headers its extra fields come
+/// with.
+fn datasets_for_compression<P: SegmentProvider>(
+    provider: &P,
+    block_range: &RangeInclusive<BlockNumber>,
+    range_len: usize,
+    layout: HeadersLayout,
+    envelope: bool,
+) -> ProviderResult<Vec<Vec<Vec<u8>>>> {
+    let headers =
+        dataset_for_compression(block_range, range_len, |range| provider.headers_range(range))?;
+    let mut datasets = vec![headers.clone()];
+    if layout.has_total_difficulty() {
+        datasets.push(dataset_for_compression(block_range, range_len, |range| {
+            total_difficulties(provider, range)
+        })?);
     }
+    datasets.push(dataset_for_compression(block_range, range_len, |range| {
+        provider.canonical_hashes_range(*range.start(), range.end() + 1)
+    })?);
+    if envelope {
+        datasets.push(headers);
+    }
+    Ok(datasets)
+}
+
+/// Returns the total difficulties of the blocks of the range, skipping blocks without one.
+fn total_difficulties(
+    provider: &impl HeaderProvider,
+    range: RangeInclusive<BlockNumber>,
+) -> ProviderResult<Vec<CompactU256>> {
+    range
+        .filter_map(|block| provider.header_td_by_number(block).transpose())
+        .map(|total_difficulty| total_difficulty.map(CompactU256::from))
+        .collect()
+}
+
+/// Creates a static file for the header segment with compressed data, with the `COLUMNS`
+/// columns of the layout and envelope.
+fn create_headers_file_with_columns<P: SegmentProvider, const COLUMNS: usize>(
+    provider: &P,
+    directory: &Path,
+    config: SegmentConfig,
+    block_range: RangeInclusive<BlockNumber>,
+    layout: HeadersLayout,
+    envelope: bool,
+) -> ProviderResult<()> {
+    let range_len = block_range.clone().count();
+
+    // Prepare data for compression using a closure
+    let mut jar = prepare_jar::<P, COLUMNS>(
+        provider,
+        directory,
+        StaticFileSegment::Headers,
+        config,
+        block_range.clone(),
+        range_len,
+        || {
+            datasets_for_compression(provider, &block_range, range_len, layout, envelope)?
+                .try_into()
+                .map_err(|datasets: Vec<_>| {
+                    ProviderError::NippyJar(format!(
+                        "expected {COLUMNS} columns of headers, found {}",
+                        datasets.len()
+                    ))
+                })
+        },
+    )?;
+    // Record the layout, envelope and codecs of the columns in the header of the static file
+    jar.user_header_mut().set_headers_layout(layout);
+    jar.user_header_mut().set_header_envelope(envelope.then_some(HEADER_ENVELOPE_VERSION));
+    let codecs = jar.user_header().expected_column_codecs();
+    record_columns(&mut jar, &codecs)?;
+    // Generate list of hashes for filters & PHF
+    // Retrieve hashes if filters are enabled
+    let hashes = config.filters.has_filters().then(|| {
+        filter_keys(config.filter_hash, config.filter_key, block_hashes(provider, &block_range))
+    });
+
+    // Columns of the layout, followed by an empty envelope column if the jar records one
+    let mut columns: Vec<Column<'_>> =
+        vec![encoded_column(batched_rows(block_range.clone(), |range| {
+            provider.headers_range(range)
+        }))];
+    if layout.has_total_difficulty() {
+        columns.push(encoded_column(batched_rows(block_range.clone(), |range| {
+            total_difficulties(provider, range)
+        })));
+    }
+    columns.push(Box::new(block_hashes(provider, &block_range).map(|hash| Ok(hash?.to_vec()))));
+    if envelope {
+        columns.push(Box::new((0..range_len).map(|_| Ok(HeaderEnvelope::new().encode()))));
+    }
+
+    freeze_jar(jar, hashes, columns, range_len)
+}
+
+/// Returns the canonical hashes of the blocks of the range, read in batches, see
+/// [`batched_rows`].
+fn block_hashes<'a>(
+    provider: &'a impl BlockHashReader,
+    block_range: &RangeInclusive<BlockNumber>,
+) -> impl Iterator<Item = ColumnResult<BlockHash>> + 'a {
+    batched_rows(block_range.clone(), |range| {
+        provider.canonical_hashes_range(*range.start(), range.end() + 1)
+    })
 }
@@ -7,54 +7,303 @@ pub use transactions::Transactions; // Export `Transactions` module
 mod headers;
 pub use headers::Headers; // Export `Headers` module
 
+mod offline;
+pub use offline::{DeduplicatedTransactions, EnvelopedHeaders, HeadersWithoutTotalDifficulty};
+
 mod receipts;
 pub use receipts::Receipts; // Export `Receipts` module
 
+mod source;
+pub use source::FromSource; // Export `FromSource` module
+
+mod filters;
+pub(crate) use filters::rebuild_filters; // Export filter rebuild of existing static files
+
 // Standard library and external crate imports
-use alloy_primitives::BlockNumber;
-use reth_db::{RawKey, RawTable}; // Database related imports
-use reth_db_api::{cursor::DbCursorRO, database::Database, table::Table, transaction::DbTx}; // Database API imports
-use reth_nippy_jar::NippyJar; // Import for NippyJar type
+use crate::{
+    build_metadata,
+    committed::publish_committed_rows,
+    preallocation::{release_preallocated, FileSizeEstimate},
+    rollback::{CommittedTip, TailSnapshot},
+    SegmentProgress, StaticFileSink,
+};
+use alloy_primitives::{BlockHash, BlockNumber, TxHash, TxNumber, U256};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use reth_db::{tables, RawKey, RawTable}; // Database related imports
+use reth_db_api::{
+    cursor::DbCursorRO,
+    database::Database,
+    table::{Compress, Table},
+    transaction::DbTx,
+}; // Database API imports
+use reth_nippy_jar::{ColumnResult, NippyJar}; // Import for NippyJar type
+use reth_primitives::{Header, Receipt, TransactionSignedNoHash};
 use reth_provider::{
-    providers::StaticFileProvider, DatabaseProviderRO, ProviderError, TransactionsProviderExt,
+    providers::{StaticFileProvider, StaticFileProviderRWRefMut, StaticFileWriter},
+    BlockReader, DatabaseProvider, DatabaseProviderRO, HeaderProvider, ProviderError,
+    ProviderFactory, ReceiptProvider, StageCheckpointReader, StaticFileProviderFactory,
+    TransactionsProvider, TransactionsProviderExt,
 }; // Provider related imports
 use reth_static_file_types::{
-    find_fixed_range, Compression, Filters, InclusionFilter, PerfectHashingFunction, SegmentConfig,
-    SegmentHeader, StaticFileSegment,
+    find_fixed_range, ColumnCodec, Compression, FilterHash, FilterKey, Filters, InclusionFilter,
+    PerfectHashingFunction, ReceiptStats, SegmentConfig, SegmentConfigError, SegmentHeader,
+    SizeHistogram, StaticFileSegment, TxTypeStats,
 }; // Static file types and configurations
 use reth_storage_errors::provider::ProviderResult; // Error handling related to providers
-use std::{ops::RangeInclusive, path::Path}; // Standard library imports
+use std::{
+    fmt,
+    ops::{Range, RangeInclusive},
+    path::{Path, PathBuf},
+    sync::Arc,
+}; // Standard library imports
+use tracing::debug_span;
 
 // Define a type alias for Rows
 pub(crate) type Rows<const COLUMNS: usize> = [Vec<Vec<u8>>; COLUMNS];
 
+/// Provider that segments read the rows they copy through, with reth's provider traits only, so
+/// storage backends other than the database can be copied from.
+///
+/// Implemented by reth's [`DatabaseProvider`], which also reports the sizes rows are stored with,
+/// see [`SegmentProvider::stored_sizes`].
+pub trait SegmentProvider:
+    BlockReader
+    + HeaderProvider
+    + TransactionsProvider
+    + TransactionsProviderExt
+    + ReceiptProvider
+    + StageCheckpointReader
+    + StaticFileProviderFactory
+{
+    /// Returns the sizes the rows segments copy are stored with, if the provider reads them
+    /// without decoding the rows. Segments account for the encoded sizes of the rows otherwise.
+    fn stored_sizes(&self) -> Option<&dyn StoredRowSizes> {
+        None
+    }
+}
+
+/// Sizes of the rows segments copy, as a provider stores them. Segments account for the bytes
+/// read with them, see [`CopiedRows`](crate::CopiedRows).
+pub trait StoredRowSizes {
+    /// Returns the stored sizes of the header, total difficulty and hash of the block. Values
+    /// that aren't stored have a size of zero.
+    fn header_sizes(&self, block: BlockNumber) -> ProviderResult<[usize; 3]>;
+
+    /// Returns the stored sizes of the transactions of the range.
+    fn transaction_sizes(&self, tx_range: Range<TxNumber>) -> ProviderResult<Vec<usize>>;
+
+    /// Returns the stored sizes of the receipts of the range.
+    fn receipt_sizes(&self, tx_range: Range<TxNumber>) -> ProviderResult<Vec<usize>>;
+}
+
+impl<TX: DbTx> SegmentProvider for DatabaseProvider<TX> {
+    fn stored_sizes(&self) -> Option<&dyn StoredRowSizes> {
+        Some(self)
+    }
+}
+
+/// Sizes of the raw values of the tables, read without decoding them.
+impl<TX: DbTx> StoredRowSizes for DatabaseProvider<TX> {
+    fn header_sizes(&self, block: BlockNumber) -> ProviderResult<[usize; 3]> {
+        let key = RawKey::new(block);
+        let tx = self.tx_ref();
+        Ok([
+            tx.get::<RawTable<tables::Headers>>(key.clone())?.map_or(0, |v| v.raw_value().len()),
+            tx.get::<RawTable<tables::HeaderTerminalDifficulties>>(key.clone())?
+                .map_or(0, |v| v.raw_value().len()),
+            tx.get::<RawTable<tables::CanonicalHeaders>>(key)?.map_or(0, |v| v.raw_value().len()),
+        ])
+    }
+
+    fn transaction_sizes(&self, tx_range: Range<TxNumber>) -> ProviderResult<Vec<usize>> {
+        raw_value_sizes::<TX, tables::Transactions>(self.tx_ref(), tx_range)
+    }
+
+    fn receipt_sizes(&self, tx_range: Range<TxNumber>) -> ProviderResult<Vec<usize>> {
+        raw_value_sizes::<TX, tables::Receipts>(self.tx_ref(), tx_range)
+    }
+}
+
+/// Returns the sizes of the raw values of the table in the range of keys.
+fn raw_value_sizes<TX: DbTx, T: Table<Key = u64>>(
+    tx: &TX,
+    range: Range<u64>,
+) -> ProviderResult<Vec<usize>> {
+    let mut cursor = tx.cursor_read::<RawTable<T>>()?;
+    let sizes = cursor
+        .walk_range(RawKey::new(range.start)..RawKey::new(range.end))?
+        .map(|entry| entry.map(|(_, value)| value.raw_value().len()))
+        .collect::<Result<_, _>>()?;
+    Ok(sizes)
+}
+
+/// Returns the sizes of the rows as the provider stores them if it reports them, see
+/// [`SegmentProvider::stored_sizes`], or as they're encoded otherwise.
+pub(crate) fn row_sizes<T: Compress + Clone>(
+    stored: Option<ProviderResult<Vec<usize>>>,
+    rows: &[T],
+) -> ProviderResult<Vec<usize>> {
+    match stored {
+        Some(sizes) => sizes,
+        None => Ok(rows.iter().map(|row| row.clone().compress().as_ref().len()).collect()),
+    }
+}
+
+/// Factory of the providers that segments and the
+/// [`StaticFileProducerInner`](crate::StaticFileProducerInner) read rows from and write them to
+/// static files through.
+///
+/// Implemented by reth's [`ProviderFactory`]. Storage backends implementing it can run the
+/// producer and its segments without a [`ProviderFactory`].
+pub trait SegmentProviderFactory: StaticFileProviderFactory + Send + Sync {
+    /// Provider the rows are read through.
+    type Provider: SegmentProvider;
+
+    /// Opens a read-only provider.
+    fn database_provider_ro(&self) -> ProviderResult<Self::Provider>;
+
+    /// Opens a read-only provider that segments copy rows with. Segments renew it every
+    /// [`SegmentProgress::read_tx_renewal_blocks`], so it may be held longer than the providers
+    /// of [`SegmentProviderFactory::database_provider_ro`].
+    fn segment_provider(&self) -> ProviderResult<Self::Provider> {
+        self.database_provider_ro()
+    }
+}
+
+impl<DB: Database> SegmentProviderFactory for ProviderFactory<DB> {
+    type Provider = DatabaseProviderRO<DB>;
+
+    fn database_provider_ro(&self) -> ProviderResult<Self::Provider> {
+        self.provider()
+    }
+
+    /// Opens a read-only provider whose read transaction may outlive the limit of long-lived
+    /// read transactions.
+    fn segment_provider(&self) -> ProviderResult<Self::Provider> {
+        Ok(self.provider()?.disable_long_read_transaction_safety())
+    }
+}
+
 /// A trait representing a segment that moves data to static files.
-pub trait Segment<DB: Database>: Send + Sync {
+///
+/// Only the methods reading rows require `P` to be a [`SegmentProvider`], so types holding
+/// segments don't need to bound it.
+pub trait Segment<P>: Send + Sync {
     /// Returns the `StaticFileSegment`.
     fn segment(&self) -> StaticFileSegment;
 
+    /// Returns `true` if the segment tallies statistics of the copied rows in the headers of the
+    /// static files: types of transactions, or gas usage of receipts.
+    fn row_stats(&self) -> bool {
+        false
+    }
+
     /// Copies data to static files for the provided block range.
+    ///
+    /// Every fully copied block is reported to `progress`. If `progress` is cancelled, copying
+    /// stops at the next block boundary without an error. Blocks whose data is missing from the
+    /// provider are handled with [`SegmentProgress::missing_block`]. Rows are written to the
+    /// static file provider of `provider_factory`, and a new
+    /// [segment provider](SegmentProviderFactory::segment_provider) is opened with it every
+    /// [`SegmentProgress::read_tx_renewal_blocks`], see [`copy_renewing_read_tx`].
     fn copy_to_static_files(
         &self,
-        provider: DatabaseProviderRO<DB>,
-        static_file_provider: StaticFileProvider,
+        provider_factory: &dyn SegmentProviderFactory<Provider = P>,
+        block_range: RangeInclusive<BlockNumber>,
+        progress: &SegmentProgress,
+    ) -> ProviderResult<()>
+    where
+        P: SegmentProvider,
+    {
+        let static_file_provider = provider_factory.static_file_provider();
+        let mut sink =
+            WriterSink::for_segment(self, &static_file_provider, *block_range.start(), progress)?;
+        copy_renewing_read_tx(self, provider_factory, &mut sink, block_range, progress)
+    }
+
+    /// Copies data to the [`StaticFileSink`] for the provided block range, with the same
+    /// progress reporting as [`Segment::copy_to_static_files`].
+    fn copy_to_sink(
+        &self,
+        provider: &P,
+        sink: &mut dyn StaticFileSink,
         block_range: RangeInclusive<BlockNumber>,
-    ) -> ProviderResult<()>;
+        progress: &SegmentProgress,
+    ) -> ProviderResult<()>
+    where
+        P: SegmentProvider;
 
     /// Creates a static file of data for the provided block range.
     fn create_static_file_file(
         &self,
-        provider: &DatabaseProviderRO<DB>,
+        provider: &P,
         directory: &Path,
         config: SegmentConfig,
         block_range: RangeInclusive<BlockNumber>,
-    ) -> ProviderResult<()>;
+    ) -> ProviderResult<()>
+    where
+        P: SegmentProvider;
+}
+
+/// Segments replacing the built-in ones of their static file segments, see
+/// [`StaticFileProducerInner::set_custom_segments`](crate::StaticFileProducerInner::set_custom_segments).
+pub(crate) struct CustomSegments<P>(Vec<Arc<dyn Segment<P>>>);
+
+impl<P> CustomSegments<P> {
+    /// Creates [`CustomSegments`] from the segments. Of several segments of the same static file
+    /// segment, the last one is used.
+    pub(crate) fn new(segments: Vec<Arc<dyn Segment<P>>>) -> Self {
+        Self(segments)
+    }
+
+    /// Returns the custom segment of the static file segment, if any.
+    pub(crate) fn get(&self, segment: StaticFileSegment) -> Option<Arc<dyn Segment<P>>> {
+        self.0.iter().rev().find(|custom| custom.segment() == segment).cloned()
+    }
+}
+
+impl<P> Default for CustomSegments<P> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<P> fmt::Debug for CustomSegments<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.iter().map(|custom| custom.segment())).finish()
+    }
+}
+
+/// Copies the block range to the sink in chunks of [`SegmentProgress::read_tx_renewal_blocks`],
+/// each read with a new [segment provider](SegmentProviderFactory::segment_provider), so no read
+/// transaction lives for the whole range and blocks page reclamation of the database. Rows are
+/// read by block, so every chunk starts at its first block, right after the last block copied
+/// with the previous provider.
+pub(crate) fn copy_renewing_read_tx<P: SegmentProvider, S: Segment<P> + ?Sized>(
+    segment: &S,
+    provider_factory: &dyn SegmentProviderFactory<Provider = P>,
+    sink: &mut dyn StaticFileSink,
+    block_range: RangeInclusive<BlockNumber>,
+    progress: &SegmentProgress,
+) -> ProviderResult<()> {
+    let mut sink = progress.row_transforms().wrap(sink);
+    let chunk = progress.read_tx_renewal_blocks().unwrap_or(u64::MAX);
+    let (mut start, end) = block_range.into_inner();
+    loop {
+        let chunk_end = start.saturating_add(chunk - 1).min(end);
+        let provider = provider_factory.segment_provider()?;
+        segment.copy_to_sink(&provider, &mut sink, start..=chunk_end, progress)?;
+        if chunk_end >= end || progress.is_cancelled() || progress.stopped_at_gap() {
+            return Ok(())
+        }
+        start = chunk_end + 1;
+    }
 }
 
 /// Prepares a `NippyJar`(NippyJar seems to encapsulate functionality related to data compression, storage, and possibly retrieval)
 /// according to the desired configuration.
-pub(crate) fn prepare_jar<DB: Database, const COLUMNS: usize>(
-    provider: &DatabaseProviderRO<DB>,
+pub(crate) fn prepare_jar<P: SegmentProvider, const COLUMNS: usize>(
+    provider: &P,
     directory: impl AsRef<Path>,
     segment: StaticFileSegment,
     segment_config: SegmentConfig,
@@ -70,11 +319,31 @@ pub(crate) fn prepare_jar<DB: Database, const COLUMNS: usize>(
         }
     };
 
+    let mut header =
+        SegmentHeader::new(block_range.clone().into(), Some(block_range.into()), tx_range, segment);
+    // Oversize the inclusion filter to reach the target false positive rate, and record the
+    // effective rate for observability
+    let filter_capacity = match segment_config.filters {
+        Filters::WithFilters(inclusion_filter, _) => {
+            let capacity = inclusion_filter.capacity(total_rows, segment_config.filter_fpp);
+            header.set_filter_fpp(Some(inclusion_filter.fpp(total_rows, capacity)));
+            capacity
+        }
+        Filters::WithoutFilters => total_rows,
+    };
+    // Readers hash lookup keys with the same function as the keys of the filter
+    segment_config
+        .check_filter_hash(segment)
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    header.set_filter_hash(segment_config.filter_hash);
+    header.set_filter_ids(segment_config.filters.ids());
+    header.set_build(Some(build_metadata()));
+
     // Initialize a `NippyJar` instance
     let mut nippy_jar = NippyJar::new(
         COLUMNS,
         &directory.as_ref().join(segment.filename(&find_fixed_range(*block_range.end())).as_str()),
-        SegmentHeader::new(block_range.clone().into(), Some(block_range.into()), tx_range, segment),
+        header,
     );
 
     // Handle compression based on segment configuration
@@ -93,27 +362,1232 @@ pub(crate) fn prepare_jar<DB: Database, const COLUMNS: usize>(
 
     // Handle inclusion filters and perfect hashing functions
     if let Filters::WithFilters(inclusion_filter, phf) = segment_config.filters {
-        nippy_jar = match inclusion_filter {
-            InclusionFilter::Cuckoo => nippy_jar.with_cuckoo_filter(total_rows),
-        };
-        nippy_jar = match phf {
-            PerfectHashingFunction::Fmph => nippy_jar.with_fmph(),
-            PerfectHashingFunction::GoFmph => nippy_jar.with_gofmph(),
-        };
+        nippy_jar = with_filters(nippy_jar, inclusion_filter, phf, filter_capacity);
     }
 
     Ok(nippy_jar)
 }
 
-/// Generates the dataset for compression using the most recent rows.
-pub(crate) fn dataset_for_compression<DB: Database, T: Table<Key = u64>>(
-    provider: &DatabaseProviderRO<DB>,
+/// Records the codecs of the columns written to the `NippyJar` in its header, checking that they
+/// match its number of columns and the columns expected from its header. Must be called once the
+/// [`HeadersLayout`](reth_static_file_types::HeadersLayout) is set.
+pub(crate) fn record_columns(
+    nippy_jar: &mut NippyJar<SegmentHeader>,
+    codecs: &[ColumnCodec],
+) -> ProviderResult<()> {
+    nippy_jar.user_header_mut().set_column_codecs(codecs);
+    nippy_jar
+        .user_header()
+        .check_columns(nippy_jar.columns())
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))
+}
+
+/// Configures the inclusion filter, sized for `capacity` rows, and the perfect hashing function
+/// of the `NippyJar`. [`PerfectHashingFunction::Chd`] isn't built by the `NippyJar`, but next to
+/// it from the same keys, see [`collect_chd_keys`].
+pub(crate) fn with_filters(
+    nippy_jar: NippyJar<SegmentHeader>,
+    inclusion_filter: InclusionFilter,
+    phf: PerfectHashingFunction,
+    capacity: usize,
+) -> NippyJar<SegmentHeader> {
+    let nippy_jar = match inclusion_filter {
+        InclusionFilter::Cuckoo => nippy_jar.with_cuckoo_filter(capacity),
+    };
+    match phf {
+        PerfectHashingFunction::Fmph => nippy_jar.with_fmph(),
+        PerfectHashingFunction::GoFmph => nippy_jar.with_gofmph(),
+        PerfectHashingFunction::Chd => nippy_jar,
+    }
+}
+
+/// Keys of the inclusion filter and perfect hashing function of a static file, see
+/// [`filter_keys`].
+pub(crate) type FilterKeys<'a> = Box<dyn Iterator<Item = ColumnResult<Vec<u8>>> + 'a>;
+
+/// Collects the keys of a static file indexed with [`PerfectHashingFunction::Chd`], returning
+/// them along with the keys of the inclusion filter of its `NippyJar`. The function is written
+/// with [`write_chd_index`](crate::chd_index::write_chd_index) once the static file is created.
+/// Keys of other static files are returned as is.
+pub(crate) fn collect_chd_keys(
+    filters: Filters,
+    keys: Option<FilterKeys<'_>>,
+) -> ProviderResult<(Option<FilterKeys<'_>>, Option<Vec<Vec<u8>>>)> {
+    match (filters, keys) {
+        (Filters::WithFilters(_, PerfectHashingFunction::Chd), Some(keys)) => {
+            let keys = keys
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+            Ok((Some(Box::new(keys.clone().into_iter().map(Ok))), Some(keys)))
+        }
+        (_, keys) => Ok((keys, None)),
+    }
+}
+
+/// [`StaticFileSink`] that appends the copied rows to the static file writer of the segment.
+pub(crate) struct WriterSink<'a> {
+    static_file_provider: &'a StaticFileProvider,
+    static_file_writer: StaticFileProviderRWRefMut<'a>,
+    /// Id of the chain of the appended rows, recorded in every static file they're appended to.
+    chain_id: Option<u64>,
+    /// Whether the appended transactions are tallied per type in every static file they're
+    /// appended to.
+    tx_type_stats: bool,
+    /// Whether the gas usage of the appended receipts is tallied in every static file they're
+    /// appended to.
+    receipt_stats: bool,
+    /// Whether the sizes of the values of the appended rows are tallied per column in every
+    /// static file they're appended to.
+    size_histograms: bool,
+    /// Estimated size of the static files, preallocated when they're started.
+    preallocation: Option<FileSizeEstimate>,
+}
+
+impl<'a> WriterSink<'a> {
+    /// Creates a new [`WriterSink`] with the static file writer of the segment, starting at the
+    /// block.
+    pub(crate) fn new(
+        static_file_provider: &'a StaticFileProvider,
+        block: BlockNumber,
+        segment: StaticFileSegment,
+    ) -> ProviderResult<Self> {
+        let mut static_file_writer = static_file_provider.get_writer(block, segment)?;
+        // Rows encoded with another schema can't be appended to
+        static_file_writer
+            .user_header()
+            .check_schema()
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        // Rows are never appended to a static file with columns of another version
+        static_file_writer
+            .user_header()
+            .check_column_codecs()
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        // The writer always appends the total difficulty column
+        if !static_file_writer.user_header().headers_layout().has_total_difficulty() {
+            return Err(ProviderError::NippyJar(format!(
+                "cannot append to a static file with {:?} headers",
+                static_file_writer.user_header().headers_layout()
+            )))
+        }
+        if static_file_writer.user_header().block_range().is_none() {
+            record_created_file(static_file_writer.user_header_mut());
+        }
+        // The writer appends no envelope column
+        if let Some(version) = static_file_writer.user_header().header_envelope() {
+            return Err(ProviderError::NippyJar(format!(
+                "cannot append headers to a static file with version {version} header envelopes"
+            )))
+        }
+        Ok(Self {
+            static_file_provider,
+            static_file_writer,
+            chain_id: None,
+            tx_type_stats: false,
+            receipt_stats: false,
+            size_histograms: false,
+            preallocation: None,
+        })
+    }
+
+    /// Creates a new [`WriterSink`] with the static file writer of the segment, starting at the
+    /// block, recording the rows as configured by the segment and its progress.
+    pub(crate) fn for_segment<P>(
+        segment: &(impl Segment<P> + ?Sized),
+        static_file_provider: &'a StaticFileProvider,
+        block: BlockNumber,
+        progress: &SegmentProgress,
+    ) -> ProviderResult<Self> {
+        let mut sink = Self::new(static_file_provider, block, segment.segment())?;
+        match segment.segment() {
+            StaticFileSegment::Headers => {}
+            StaticFileSegment::Transactions => sink.set_tx_type_stats(segment.row_stats()),
+            StaticFileSegment::Receipts => sink.set_receipt_stats(segment.row_stats()),
+        }
+        sink.set_chain_id(progress.chain_spec().map(|chain_spec| chain_spec.chain_id()))?;
+        sink.set_preallocation(progress.preallocation())?;
+        sink.set_size_histograms(progress.size_histograms());
+        Ok(sink)
+    }
+
+    /// Records the id of the chain of the appended rows. Rows are never appended to a static file
+    /// of another chain.
+    pub(crate) fn set_chain_id(&mut self, chain_id: Option<u64>) -> ProviderResult<()> {
+        let Some(chain_id) = chain_id else { return Ok(()) };
+        let header = self.static_file_writer.user_header_mut();
+        if let Some(found) = header.chain_id().filter(|found| *found != chain_id) {
+            return Err(ProviderError::NippyJar(format!(
+                "cannot append rows of chain {chain_id} to a static file of chain {found}"
+            )))
+        }
+        header.set_chain_id(Some(chain_id));
+        self.chain_id = Some(chain_id);
+        Ok(())
+    }
+
+    /// Sets whether the appended transactions are tallied per type. A static file that already
+    /// has rows without statistics gets none, as they wouldn't cover all of its rows.
+    pub(crate) fn set_tx_type_stats(&mut self, enabled: bool) {
+        let header = self.static_file_writer.user_header_mut();
+        if !enabled {
+            header.set_tx_type_stats(None);
+        } else if header.tx_range().is_none() {
+            header.set_tx_type_stats(Some(TxTypeStats::default()));
+        }
+        self.tx_type_stats = enabled;
+    }
+
+    /// Sets the estimated size of the static files, preallocating the files of the static file
+    /// that's appended to, and of every static file started afterwards.
+    pub(crate) fn set_preallocation(
+        &mut self,
+        preallocation: Option<FileSizeEstimate>,
+    ) -> ProviderResult<()> {
+        self.preallocation = preallocation;
+        let block_start = self.static_file_writer.user_header().expected_block_start();
+        self.preallocate(block_start)
+    }
+
+    /// Preallocates the files of the static file starting at the block, if enabled.
+    fn preallocate(&self, block_start: BlockNumber) -> ProviderResult<()> {
+        let Some(preallocation) = &self.preallocation else { return Ok(()) };
+        let block_range = find_fixed_range(block_start);
+        preallocation
+            .preallocate(&self.data_path(block_start), block_range.len())
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))
+    }
+
+    /// Releases the space left over in the files of the sealed static file starting at the
+    /// block, and preallocates the files of the static file that's started.
+    fn seal_preallocated(&self, block_start: BlockNumber) -> ProviderResult<()> {
+        if self.preallocation.is_none() {
+            return Ok(())
+        }
+        release_preallocated(&self.data_path(block_start))
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        self.preallocate(self.static_file_writer.user_header().expected_block_start())
+    }
+
+    /// Returns the path of the data file of the static file starting at the block.
+    fn data_path(&self, block_start: BlockNumber) -> PathBuf {
+        let segment = self.static_file_writer.user_header().segment();
+        self.static_file_provider.directory().join(segment.filename(&find_fixed_range(block_start)))
+    }
+
+    /// Sets whether the gas usage of the appended receipts is tallied. A static file that already
+    /// has rows without aggregates gets none, as they wouldn't cover all of its rows.
+    pub(crate) fn set_receipt_stats(&mut self, enabled: bool) {
+        let header = self.static_file_writer.user_header_mut();
+        if !enabled {
+            header.set_receipt_stats(None);
+        } else if header.tx_range().is_none() {
+            header.set_receipt_stats(Some(ReceiptStats::default()));
+        }
+        self.receipt_stats = enabled;
+    }
+
+    /// Sets whether the sizes of the values of the appended rows are tallied per column. A static
+    /// file that already has rows without histograms gets none, as they wouldn't cover all of its
+    /// rows.
+    pub(crate) fn set_size_histograms(&mut self, enabled: bool) {
+        let header = self.static_file_writer.user_header_mut();
+        if !enabled {
+            header.set_column_sizes(None);
+        } else if match header.segment() {
+            StaticFileSegment::Headers => header.block_start().is_none(),
+            StaticFileSegment::Transactions | StaticFileSegment::Receipts => {
+                header.tx_start().is_none()
+            }
+        } {
+            header.set_column_sizes(Some(Vec::new()));
+        }
+        self.size_histograms = enabled;
+    }
+}
+
+/// Records the build of the producer and the codecs of the columns appended by the static file
+/// writer in the header of a static file it created. Headers are always appended with their total
+/// difficulty.
+fn record_created_file(header: &mut SegmentHeader) {
+    header.set_build(Some(build_metadata()));
+    header.set_column_codecs(match header.segment() {
+        StaticFileSegment::Headers => {
+            &[ColumnCodec::Header, ColumnCodec::TotalDifficulty, ColumnCodec::BlockHash]
+        }
+        StaticFileSegment::Transactions => &[ColumnCodec::Transaction],
+        StaticFileSegment::Receipts => &[ColumnCodec::Receipt],
+    });
+}
+
+impl StaticFileSink for WriterSink<'_> {
+    fn append_header(
+        &mut self,
+        header: Header,
+        total_difficulty: U256,
+        hash: BlockHash,
+    ) -> ProviderResult<BlockNumber> {
+        // Total difficulty no longer grows after the merge, so it's also recorded once in the
+        // header of the static file
+        let post_merge = header.difficulty.is_zero();
+
+        // The row following the last block of the static file starts a new one, whose header is
+        // only created by the writer while appending
+        let user_header = self.static_file_writer.user_header();
+        if user_header.block_end() == Some(user_header.expected_block_end()) {
+            let sealed_block_start = user_header.expected_block_start();
+            let block = self.static_file_writer.append_header(header, total_difficulty, hash)?;
+            self.seal_preallocated(sealed_block_start)?;
+
+            let user_header = self.static_file_writer.user_header_mut();
+            record_created_file(user_header);
+            user_header.set_chain_id(self.chain_id);
+            user_header.set_column_sizes(self.size_histograms.then(Vec::new));
+            user_header.record_total_difficulty(post_merge, total_difficulty);
+            return Ok(block)
+        }
+
+        self.static_file_writer
+            .user_header_mut()
+            .record_total_difficulty(post_merge, total_difficulty);
+        self.static_file_writer.append_header(header, total_difficulty, hash)
+    }
+
+    fn increment_block(
+        &mut self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> ProviderResult<BlockNumber> {
+        let expected_block_start = self.static_file_writer.user_header().expected_block_start();
+        let block = self.static_file_writer.increment_block(segment, block)?;
+        // The block following the last block of the static file starts a new one
+        if self.static_file_writer.user_header().expected_block_start() != expected_block_start {
+            let user_header = self.static_file_writer.user_header_mut();
+            record_created_file(user_header);
+            user_header.set_chain_id(self.chain_id);
+            user_header.set_tx_type_stats(self.tx_type_stats.then(TxTypeStats::default));
+            user_header.set_receipt_stats(self.receipt_stats.then(ReceiptStats::default));
+            user_header.set_column_sizes(self.size_histograms.then(Vec::new));
+            self.seal_preallocated(expected_block_start)?;
+        }
+        Ok(block)
+    }
+
+    fn append_transaction(
+        &mut self,
+        tx_num: TxNumber,
+        transaction: TransactionSignedNoHash,
+    ) -> ProviderResult<TxNumber> {
+        self.static_file_writer.append_transaction(tx_num, transaction)
+    }
+
+    fn record_transaction_type(&mut self, tx_type: u8, size: usize) -> ProviderResult<()> {
+        if let Some(stats) = self.static_file_writer.user_header_mut().tx_type_stats_mut() {
+            stats.add(tx_type, size as u64);
+        }
+        Ok(())
+    }
+
+    fn record_receipt(
+        &mut self,
+        gas_used: u64,
+        logs: usize,
+        contract_creation: bool,
+    ) -> ProviderResult<()> {
+        if let Some(stats) = self.static_file_writer.user_header_mut().receipt_stats_mut() {
+            stats.add(gas_used, logs as u64, contract_creation);
+        }
+        Ok(())
+    }
+
+    fn append_receipt(&mut self, tx_num: TxNumber, receipt: Receipt) -> ProviderResult<TxNumber> {
+        self.static_file_writer.append_receipt(tx_num, receipt)
+    }
+
+    fn record_row_sizes(&mut self, sizes: &[usize]) -> ProviderResult<()> {
+        if let Some(histograms) = self.static_file_writer.user_header_mut().column_sizes_mut() {
+            if histograms.len() < sizes.len() {
+                histograms.resize(sizes.len(), SizeHistogram::default());
+            }
+            for (histogram, size) in histograms.iter_mut().zip(sizes) {
+                histogram.add(*size as u64);
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits the static file writer once the commit interval of the segment elapsed, so copied
+    /// blocks are not lost if the run is interrupted, and no longer rolled back if the disk fills
+    /// up. The snapshot of the new commit supersedes the previous one in the write-ahead log.
+    fn commit_if_due(&mut self, progress: &SegmentProgress) -> ProviderResult<()> {
+        if progress.is_commit_due() {
+            let _span = debug_span!(target: "static_file", "commit", segment = %progress.segment(), block = ?progress.last_block()).entered();
+            self.static_file_writer.commit()?;
+            progress.committed();
+            let tip = CommittedTip::of(self.static_file_provider, progress.segment());
+            TailSnapshot::take(self.static_file_provider.directory(), progress.segment())
+                .and_then(|tail| progress.set_tail(tail.with_tip(tip)))
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+            publish_committed_rows(self.static_file_provider, [progress.segment()])
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn directory(&self) -> Option<&Path> {
+        Some(self.static_file_provider.directory())
+    }
+}
+
+/// Hashes the keys of the rows with the [`FilterHash`] of the segment, before they're added to the
+/// inclusion filter and perfect hashing function.
+///
+/// [`FilterHash::Identity`] keys are passed through as they're read. Otherwise, keys are hashed in
+/// parallel on the current rayon thread pool, which also builds the perfect hashing function, in
+/// batches of [`FILTER_KEYS_BATCH`] keys read at a time, see
+/// [`WorkersConfig::filter_threads`](crate::WorkersConfig::filter_threads).
+pub(crate) fn filter_keys<'a, K: AsRef<[u8]> + Send + 'a>(
+    filter_hash: FilterHash,
+    filter_key: Option<FilterKey>,
+    keys: impl Iterator<Item = ColumnResult<K>> + 'a,
+) -> FilterKeys<'a> {
+    if filter_hash == FilterHash::Identity {
+        return Box::new(keys.map(|key| Ok(key?.as_ref().to_vec())))
+    }
+
+    Box::new(par_map_batched(keys, move |key| -> ColumnResult<Vec<u8>> {
+        hash_filter_key(filter_hash, filter_key.as_ref(), key?.as_ref())
+    }))
+}
+
+/// Number of filter keys read, hashed and handed to the filter builders at a time.
+pub(crate) const FILTER_KEYS_BATCH: usize = 1 << 16;
+
+/// Maps the items in parallel on the current rayon thread pool, in batches of
+/// [`FILTER_KEYS_BATCH`] items read at a time, so only one batch is held in memory.
+fn par_map_batched<'a, T: Send + 'a, U: Send + 'a>(
+    mut items: impl Iterator<Item = T> + 'a,
+    f: impl Fn(T) -> U + Send + Sync + 'a,
+) -> impl Iterator<Item = U> + 'a {
+    let mut batch = Vec::new().into_iter();
+    std::iter::from_fn(move || loop {
+        if let Some(item) = batch.next() {
+            return Some(item)
+        }
+        let items = items.by_ref().take(FILTER_KEYS_BATCH).collect::<Vec<_>>();
+        if items.is_empty() {
+            return None
+        }
+        batch = items.into_par_iter().map(&f).collect::<Vec<_>>().into_iter();
+    })
+}
+
+/// Hashes the key of a row with the [`FilterHash`] of the segment, failing if its secret is
+/// missing.
+fn hash_filter_key(
+    filter_hash: FilterHash,
+    filter_key: Option<&FilterKey>,
+    key: &[u8],
+) -> ColumnResult<Vec<u8>> {
+    match filter_hash.hash_key(key, filter_key) {
+        Some(key) => Ok(key.into_owned()),
+        None => Err(Box::new(SegmentConfigError::MissingFilterKey)),
+    }
+}
+
+/// Returns the filter keys of the transactions of the range, hashed with the [`FilterHash`] of the
+/// segment, see [`filter_keys`].
+///
+/// Transactions are read with [`batched_rows`] as the keys are consumed, and hashed in parallel in
+/// batches of [`FILTER_KEYS_BATCH`], so the hashes of the range are never collected before they're
+/// handed to the filter builders. The `NippyJar` still holds every key while it builds the perfect
+/// hashing function.
+pub(crate) fn transaction_filter_keys<'a, P: SegmentProvider>(
+    provider: &'a P,
+    tx_range: &RangeInclusive<TxNumber>,
+    filter_hash: FilterHash,
+    filter_key: Option<FilterKey>,
+) -> ProviderResult<FilterKeys<'a>> {
+    let transactions =
+        batched_rows(tx_range.clone(), |range| provider.transactions_by_tx_range(range));
+    let hashes = par_map_batched(transactions, |transaction| -> ColumnResult<TxHash> {
+        Ok(transaction?.hash())
+    });
+    Ok(filter_keys(filter_hash, filter_key, hashes))
+}
+
+/// Number of rows read from the provider at a time by [`batched_rows`].
+pub(crate) const ROWS_BATCH: u64 = 10_000;
+
+/// Reads the rows of the range with `read` in batches of [`ROWS_BATCH`] rows as they're consumed,
+/// so only one batch is held in memory. Stops after the first error.
+pub(crate) fn batched_rows<'a, T: 'a>(
+    range: RangeInclusive<u64>,
+    mut read: impl FnMut(RangeInclusive<u64>) -> ProviderResult<Vec<T>> + 'a,
+) -> impl Iterator<Item = ColumnResult<T>> + 'a {
+    let (mut start, end) = range.into_inner();
+    let mut done = start > end;
+    let mut batch = Vec::new().into_iter();
+    std::iter::from_fn(move || loop {
+        if let Some(row) = batch.next() {
+            return Some(Ok(row))
+        }
+        if done {
+            return None
+        }
+        let batch_end = start.saturating_add(ROWS_BATCH - 1).min(end);
+        done = batch_end >= end;
+        match read(start..=batch_end) {
+            Ok(rows) => batch = rows.into_iter(),
+            Err(err) => {
+                done = true;
+                return Some(Err(err.into()))
+            }
+        }
+        start = batch_end.saturating_add(1);
+    })
+}
+
+/// Column of encoded values frozen into a `NippyJar`.
+pub(crate) type Column<'a> = Box<dyn Iterator<Item = ColumnResult<Vec<u8>>> + 'a>;
+
+/// Returns the column of the rows, encoded as they're stored in the database.
+pub(crate) fn encoded_column<'a, T: Compress + 'a>(
+    rows: impl Iterator<Item = ColumnResult<T>> + 'a,
+) -> Column<'a> {
+    Box::new(rows.map(|row| Ok(row?.compress().into())))
+}
+
+/// Builds the inclusion filter and perfect hashing function of the `NippyJar` from the keys, if
+/// any, and freezes the columns into it.
+pub(crate) fn freeze_jar(
+    mut jar: NippyJar<SegmentHeader>,
+    keys: Option<FilterKeys<'_>>,
+    columns: Vec<Column<'_>>,
+    rows: usize,
+) -> ProviderResult<()> {
+    if let Some(keys) = keys {
+        jar.prepare_index(keys, rows).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    }
+    jar.freeze(columns, rows as u64).map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+    Ok(())
+}
+
+/// Generates the dataset for compression using the most recent rows of the range, read with
+/// `read`, most recent first.
+pub(crate) fn dataset_for_compression<T: Compress>(
     range: &RangeInclusive<u64>,
     range_len: usize,
+    read: impl FnOnce(RangeInclusive<u64>) -> ProviderResult<Vec<T>>,
 ) -> ProviderResult<Vec<Vec<u8>>> {
-    let mut cursor = provider.tx_ref().cursor_read::<RawTable<T>>()?;
-    Ok(cursor.walk_back(Some(RawKey::from(*range.end())))?
-        .take(range_len.min(1000))
-        .map(|row| row.map(|(_key, value)| value.into_value()).expect("should exist"))
-        .collect::<Vec<_>>())
+    let rows = range_len.min(1000) as u64;
+    if rows == 0 {
+        return Ok(Vec::new())
+    }
+    let start = range.end().saturating_sub(rows - 1).max(*range.start());
+    Ok(read(start..=*range.end())?.into_iter().rev().map(|row| row.compress().into()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chd_index::read_chd_index, test_utils::StaticFileTestHarness, CHD_INDEX_EXTENSION,
+    };
+    use alloy_primitives::B256;
+    use reth_db_api::table::Decompress;
+    use reth_nippy_jar::NippyJarCursor;
+    use reth_provider::{HeaderProvider, ReceiptProvider, TransactionsProvider};
+    use reth_stages::test_utils::{StorageKind, TestStageDB};
+    use reth_static_file_types::HeadersLayout;
+    use reth_testing_utils::generators::{self, random_block_range, random_receipt};
+    use std::fs;
+
+    /// Tests that static files of every compression and filters combination are reproduced byte
+    /// for byte from the same rows, and decode back to them.
+    #[test]
+    fn deterministic_static_files() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let segments: [Box<dyn Segment<_>>; 3] = [
+            Box::new(Headers::default()),
+            Box::new(Transactions::default()),
+            Box::new(Receipts::default()),
+        ];
+        let block_range = find_fixed_range(3);
+
+        for compression in [
+            Compression::Uncompressed,
+            Compression::Lz4,
+            Compression::Zstd,
+            Compression::ZstdWithDictionary,
+        ] {
+            for filters in [
+                Filters::WithoutFilters,
+                Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Fmph),
+                Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::GoFmph),
+            ] {
+                let config = SegmentConfig {
+                    filters,
+                    compression,
+                    filter_fpp: None,
+                    filter_hash: FilterHash::Identity,
+                    filter_key: None,
+                };
+                let directories = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+
+                for segment in &segments {
+                    let segment_name = segment.segment().filename(&block_range);
+                    for directory in &directories {
+                        segment
+                            .create_static_file_file(&provider, directory.path(), config, 0..=3)
+                            .unwrap();
+                    }
+
+                    // Data file and every companion file of the static file
+                    for entry in fs::read_dir(directories[0].path()).unwrap() {
+                        let path = entry.unwrap().path();
+                        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                        if !name.starts_with(&segment_name) {
+                            continue
+                        }
+                        assert_eq!(
+                            fs::read(&path).unwrap(),
+                            fs::read(directories[1].path().join(&name)).unwrap(),
+                            "{name} with {compression:?} and {filters:?} is not reproduced"
+                        );
+                    }
+
+                    // Rows decode back to the ones of the database
+                    let jar =
+                        NippyJar::<SegmentHeader>::load(&directories[0].path().join(&segment_name))
+                            .unwrap();
+                    assert_eq!(jar.user_header().check_columns(jar.columns()), Ok(()));
+                    let mut cursor = NippyJarCursor::new(&jar).unwrap();
+                    for row in 0..jar.rows() {
+                        let columns = cursor.row_by_number(row).unwrap().unwrap();
+                        let number = row as u64;
+                        match segment.segment() {
+                            StaticFileSegment::Headers => assert_eq!(
+                                Some(Header::decompress(columns[0]).unwrap()),
+                                provider.header_by_number(number).unwrap()
+                            ),
+                            StaticFileSegment::Transactions => assert_eq!(
+                                Some(TransactionSignedNoHash::decompress(columns[0]).unwrap()),
+                                provider.transaction_by_id_no_hash(number).unwrap()
+                            ),
+                            StaticFileSegment::Receipts => assert_eq!(
+                                Some(Receipt::decompress(columns[0]).unwrap()),
+                                provider.receipt(number).unwrap()
+                            ),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Directory of the reference static files, with a directory per version of the on-disk
+    /// format. The static files of a version are frozen: a purposeful format change bumps
+    /// [`GOLDEN_VERSION`], and running the tests with `UPDATE_GOLDEN_FILES` set then adds the
+    /// static files of the new version alongside the previous ones.
+    const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/golden");
+
+    /// Version of the on-disk format static files are currently written in.
+    const GOLDEN_VERSION: &str = "v2";
+
+    /// Version of the reference static files written from [`golden_rows`].
+    const GOLDEN_ROWS_VERSION: &str = "v1";
+
+    /// Returns the rows of the reference static files of [`GOLDEN_ROWS_VERSION`].
+    fn golden_rows(segment: StaticFileSegment) -> Vec<Vec<Vec<u8>>> {
+        (0..3u8)
+            .map(|row| match segment {
+                StaticFileSegment::Headers => vec![
+                    format!("header {row}").into_bytes(),
+                    vec![row + 1],
+                    format!("hash {row}").into_bytes(),
+                ],
+                StaticFileSegment::Transactions => vec![format!("tx {row}").into_bytes()],
+                StaticFileSegment::Receipts => vec![format!("receipt {row}").into_bytes()],
+            })
+            .collect()
+    }
+
+    /// Tests that the static files every segment creates from the deterministic fixtures, with
+    /// every compression and filters combination, are the reference static files of
+    /// [`GOLDEN_VERSION`] byte for byte.
+    #[test]
+    fn golden_files() {
+        let update = std::env::var_os("UPDATE_GOLDEN_FILES").is_some();
+        let golden_dir = Path::new(GOLDEN_DIR).join(GOLDEN_VERSION);
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let segments: [Box<dyn Segment<_>>; 3] = [
+            Box::new(Headers::default()),
+            Box::new(Transactions::default()),
+            Box::new(Receipts::default()),
+        ];
+        let block_range = find_fixed_range(3);
+
+        for compression in [
+            Compression::Uncompressed,
+            Compression::Lz4,
+            Compression::Zstd,
+            Compression::ZstdWithDictionary,
+        ] {
+            for filters in [
+                Filters::WithoutFilters,
+                Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Fmph),
+                Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::GoFmph),
+            ] {
+                let config = SegmentConfig {
+                    filters,
+                    compression,
+                    filter_fpp: None,
+                    filter_hash: FilterHash::Identity,
+                    filter_key: None,
+                };
+                let directory = tempfile::tempdir().unwrap();
+
+                for segment in &segments {
+                    segment
+                        .create_static_file_file(&provider, directory.path(), config, 0..=3)
+                        .unwrap();
+                    let segment_name = segment.segment().filename(&block_range);
+                    let golden_name = segment.segment().filename_with_configuration(
+                        filters,
+                        compression,
+                        &block_range,
+                    );
+
+                    // Data file and every companion file of the static file
+                    for entry in fs::read_dir(directory.path()).unwrap() {
+                        let path = entry.unwrap().path();
+                        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                        let Some(extension) = name.strip_prefix(&segment_name) else { continue };
+                        let golden_file = golden_dir.join(format!("{golden_name}{extension}"));
+                        let written = fs::read(&path).unwrap();
+
+                        // Missing reference static files are added, existing ones never rewritten
+                        if update && !golden_file.exists() {
+                            fs::create_dir_all(&golden_dir).unwrap();
+                            fs::write(&golden_file, &written).unwrap();
+                        }
+                        let golden = fs::read(&golden_file).unwrap_or_else(|err| {
+                            panic!(
+                                "{}: {err}, run the tests with UPDATE_GOLDEN_FILES set to add it",
+                                golden_file.display()
+                            )
+                        });
+                        assert_eq!(written, golden, "{} is not reproduced", golden_file.display());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Tests that the reference static files of every format version still open with the current
+    /// reader, and that the ones of [`GOLDEN_ROWS_VERSION`] decode back to their rows.
+    #[test]
+    fn golden_files_open() {
+        use reth_static_file_types::{JarConfig, JarRows, Offsets, SegmentRangeInclusive};
+
+        let mut versions = 0;
+        for version in fs::read_dir(GOLDEN_DIR).unwrap() {
+            let version = version.unwrap().path();
+            versions += 1;
+            for entry in fs::read_dir(&version).unwrap() {
+                let path = entry.unwrap().path();
+                // Companion files are opened with their data file
+                if path.extension().is_some() {
+                    continue
+                }
+                let jar = NippyJar::<SegmentHeader>::load(&path)
+                    .unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+                assert_eq!(jar.user_header().check_columns(jar.columns()), Ok(()));
+                let mut cursor = NippyJarCursor::new(&jar).unwrap();
+                for row in 0..jar.rows() {
+                    assert!(
+                        cursor.row_by_number(row).unwrap().is_some(),
+                        "{} misses row {row}",
+                        path.display()
+                    );
+                }
+            }
+        }
+        assert!(versions > 0, "no reference static files in {GOLDEN_DIR}");
+
+        let golden_dir = Path::new(GOLDEN_DIR).join(GOLDEN_ROWS_VERSION);
+        let block_range = find_fixed_range(0);
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            let rows = golden_rows(segment);
+            let tx_range =
+                (segment != StaticFileSegment::Headers).then_some(SegmentRangeInclusive::new(0, 2));
+            let header = SegmentHeader::new(
+                block_range,
+                Some(SegmentRangeInclusive::new(0, 2)),
+                tx_range,
+                segment,
+            );
+
+            for compression in [Compression::Uncompressed, Compression::Lz4] {
+                let golden_path = golden_dir.join(segment.filename_with_configuration(
+                    Filters::WithoutFilters,
+                    compression,
+                    &block_range,
+                ));
+
+                // The reference static file opens with its header and rows
+                let golden = NippyJar::<SegmentHeader>::load(&golden_path).unwrap();
+                assert_eq!(golden.user_header(), &header);
+                let mut cursor = NippyJarCursor::new(&golden).unwrap();
+                for (number, row) in rows.iter().enumerate() {
+                    let columns = cursor.row_by_number(number).unwrap().unwrap();
+                    assert_eq!(columns, row.iter().map(Vec::as_slice).collect::<Vec<_>>());
+                }
+
+                // And decodes from bytes
+                let config =
+                    JarConfig::decode(&fs::read(golden_path.with_extension("conf")).unwrap())
+                        .unwrap();
+                assert_eq!(config.header, header);
+                let offsets = fs::read(golden_path.with_extension("off")).unwrap();
+                let data = fs::read(&golden_path).unwrap();
+                let jar_rows = JarRows::new(
+                    &data,
+                    Offsets::new(&offsets).unwrap(),
+                    config.columns,
+                    compression,
+                );
+                assert_eq!(jar_rows.rows(), rows.len());
+                for (number, row) in rows.iter().enumerate() {
+                    assert_eq!(&jar_rows.row(number).unwrap(), row);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn receipts_with_chd() {
+        let mut rng = generators::rng();
+        let db = TestStageDB::default();
+        let blocks = random_block_range(&mut rng, 0..=3, B256::ZERO, 1..3);
+        db.insert_blocks(blocks.iter(), StorageKind::Database(None)).unwrap();
+        let mut receipts = Vec::new();
+        for block in &blocks {
+            for transaction in &block.body {
+                receipts
+                    .push((receipts.len() as u64, random_receipt(&mut rng, transaction, Some(0))));
+            }
+        }
+        db.insert_receipts(receipts).unwrap();
+
+        let provider = db.factory.provider().unwrap();
+        let config = SegmentConfig::builder()
+            .inclusion_filter(Some(InclusionFilter::Cuckoo))
+            .perfect_hashing_function(Some(PerfectHashingFunction::Chd))
+            .compression(Compression::Lz4)
+            .build()
+            .unwrap();
+        let directory = tempfile::tempdir().unwrap();
+
+        // Transactions are looked up by the static file provider with the function of the jar
+        assert!(Transactions::default()
+            .create_static_file_file(&provider, directory.path(), config, 0..=3)
+            .is_err());
+
+        Receipts::default()
+            .create_static_file_file(&provider, directory.path(), config, 0..=3)
+            .unwrap();
+        let name = StaticFileSegment::Receipts.filename(&find_fixed_range(3));
+        assert!(directory.path().join(format!("{name}.{CHD_INDEX_EXTENSION}")).exists());
+
+        // Every receipt is found by the hash of its transaction
+        let index = read_chd_index(&directory.path().join(&name)).unwrap();
+        let hashes = provider.transaction_hashes_by_range(0..index.len() as u64).unwrap();
+        assert!(!hashes.is_empty());
+        for (hash, tx_number) in hashes {
+            assert_eq!(index.row(hash.as_slice()), Some(tx_number));
+        }
+    }
+
+    #[test]
+    fn headers_without_total_difficulty() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        HeadersWithoutTotalDifficulty
+            .create_static_file_file(
+                &provider,
+                directory.path(),
+                StaticFileSegment::Headers.config(),
+                0..=3,
+            )
+            .unwrap();
+
+        let jar = NippyJar::<SegmentHeader>::load(
+            &directory.path().join(StaticFileSegment::Headers.filename(&find_fixed_range(3))),
+        )
+        .unwrap();
+        assert_eq!(jar.columns(), 2);
+        assert_eq!(jar.user_header().headers_layout(), HeadersLayout::NoTotalDifficulty);
+        // The static file provider would look the rows up by hash with the filters
+        assert_eq!(jar.user_header().filter_fpp(), None);
+        assert_eq!(jar.user_header().start(), None);
+
+        // Rows hold the header and the canonical hash only
+        let mut cursor = NippyJarCursor::new(&jar).unwrap();
+        for (row, block) in harness.blocks.iter().enumerate() {
+            let columns = cursor.row_by_number(row).unwrap().unwrap();
+            assert_eq!(&Header::decompress(columns[0]).unwrap(), block.header.header());
+            assert_eq!(columns[1], block.hash().as_slice());
+        }
+    }
+
+    #[test]
+    fn header_envelope() {
+        use reth_provider::BlockHashReader;
+
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        EnvelopedHeaders
+            .create_static_file_file(
+                &provider,
+                directory.path(),
+                StaticFileSegment::Headers.config(),
+                0..=3,
+            )
+            .unwrap();
+
+        let jar = NippyJar::<SegmentHeader>::load(
+            &directory.path().join(StaticFileSegment::Headers.filename(&find_fixed_range(3))),
+        )
+        .unwrap();
+        let header = jar.user_header();
+        assert_eq!(header.header_envelope(), Some(reth_static_file_types::HEADER_ENVELOPE_VERSION));
+        assert_eq!(header.check_columns(jar.columns()), Ok(()));
+        // The envelope column doesn't hide the static file from the static file provider
+        assert!(header.filter_fpp().is_some());
+        assert_eq!(header.start(), Some(0));
+
+        // Headers are stored as is, followed by an empty envelope
+        let mut cursor = NippyJarCursor::new(&jar).unwrap();
+        for (row, block) in harness.blocks.iter().enumerate() {
+            let columns = cursor.row_by_number(row).unwrap().unwrap();
+            assert_eq!(&Header::decompress(columns[0]).unwrap(), block.header.header());
+            assert_eq!(columns[2], block.hash().as_slice());
+            assert_eq!(
+                reth_static_file_types::HeaderEnvelope::decode(columns[3]),
+                Ok(reth_static_file_types::HeaderEnvelope::new())
+            );
+        }
+        drop(cursor);
+
+        // Both the static file provider and the reader read all columns of the layout
+        let static_file_provider = StaticFileProvider::read_only(directory.path()).unwrap();
+        let reader = crate::StaticFileReader::new(static_file_provider.clone()).unwrap();
+        for block in &harness.blocks {
+            let number = block.header.number;
+            assert_eq!(
+                static_file_provider.header_by_number(number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+            assert_eq!(
+                static_file_provider.header(&block.header.hash()).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+            assert_eq!(static_file_provider.block_hash(number).unwrap(), Some(block.header.hash()));
+            assert_eq!(
+                reader.header_by_number(number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+            assert!(reader.header_td_by_number(number).unwrap().is_some());
+            assert_eq!(reader.block_hash(number).unwrap(), Some(block.header.hash()));
+        }
+    }
+
+    #[test]
+    fn header_envelope_unknown_fields() {
+        use crate::StaticFileReader;
+        use reth_db_api::{models::CompactU256, table::Compress};
+        use reth_provider::BlockHashReader;
+        use reth_static_file_types::{HeaderEnvelope, SegmentRangeInclusive, HEADER_ENVELOPE_VERSION};
+
+        // Static file written by a later version of the envelope, with an extra field
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let directory = tempfile::tempdir().unwrap();
+        let mut header = SegmentHeader::new(
+            find_fixed_range(3),
+            Some(SegmentRangeInclusive::new(0, 3)),
+            None,
+            StaticFileSegment::Headers,
+        );
+        header.set_header_envelope(Some(HEADER_ENVELOPE_VERSION + 1));
+        let path = directory.path().join(StaticFileSegment::Headers.filename(&find_fixed_range(3)));
+        let mut jar = NippyJar::new(4, &path, header);
+        record_columns(
+            &mut jar,
+            &[
+                ColumnCodec::Header,
+                ColumnCodec::TotalDifficulty,
+                ColumnCodec::BlockHash,
+                ColumnCodec::HeaderEnvelope,
+            ],
+        )
+        .unwrap();
+        let headers = harness
+            .blocks
+            .iter()
+            .map(|block| Ok(block.header.header().clone().compress()))
+            .collect::<Vec<_>>();
+        let header_tds = harness
+            .blocks
+            .iter()
+            .map(|block| Ok(CompactU256::from(block.header.difficulty).compress()))
+            .collect::<Vec<_>>();
+        let hashes =
+            harness.blocks.iter().map(|block| Ok(block.header.hash().to_vec())).collect::<Vec<_>>();
+        let envelopes = harness
+            .blocks
+            .iter()
+            .map(|_| Ok(HeaderEnvelope::new().with_extra_field(&[7; 32]).encode()))
+            .collect::<Vec<_>>();
+        jar.freeze(vec![headers, header_tds, hashes, envelopes], 4).unwrap();
+
+        // The envelope column with the unknown field is ignored, by number and by hash
+        let static_file_provider = StaticFileProvider::read_only(directory.path()).unwrap();
+        let reader = StaticFileReader::new(static_file_provider.clone()).unwrap();
+        for block in &harness.blocks {
+            let number = block.header.number;
+            assert_eq!(
+                static_file_provider.header_by_number(number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+            assert_eq!(
+                reader.header_by_number(number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+            assert_eq!(
+                reader.header(&block.header.hash()).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+            assert!(reader.header_td_by_number(number).unwrap().is_some());
+            assert_eq!(reader.block_hash(number).unwrap(), Some(block.header.hash()));
+        }
+    }
+
+    #[test]
+    fn deduplicated_transactions() {
+        use crate::{
+            MultiSegmentReader, SegmentValue, StaticFileReader, DEDUP_EXTENSION, DEDUP_MAGIC,
+        };
+        use reth_db::tables;
+        use reth_db_api::transaction::DbTxMut;
+
+        let harness = StaticFileTestHarness::new(3, 2..4);
+
+        // Every transaction deploys the same bytecode, with its own constructor arguments
+        let bytecode = (0..4096u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8);
+        let bytecode = bytecode.collect::<Vec<_>>();
+        let tx = harness.provider_factory.db_ref().tx_mut().unwrap();
+        let mut expected = Vec::new();
+        for (tx_number, transaction) in
+            harness.blocks.iter().flat_map(|block| &block.body).enumerate()
+        {
+            let mut transaction = TransactionSignedNoHash {
+                signature: transaction.signature,
+                transaction: transaction.transaction.clone(),
+            };
+            let input = [bytecode.as_slice(), &(tx_number as u64).to_be_bytes()].concat();
+            transaction.transaction.set_input(input.into());
+            tx.put::<tables::Transactions>(tx_number as u64, transaction.clone()).unwrap();
+            expected.push(transaction);
+        }
+        tx.commit().unwrap();
+
+        let provider = harness.provider_factory.provider().unwrap();
+        let directory = tempfile::tempdir().unwrap();
+        DeduplicatedTransactions
+            .create_static_file_file(
+                &provider,
+                directory.path(),
+                StaticFileSegment::Transactions.config(),
+                0..=harness.tip(),
+            )
+            .unwrap();
+
+        let block_range = find_fixed_range(harness.tip());
+        let name = StaticFileSegment::Transactions.filename(&block_range);
+        assert!(directory.path().join(format!("{name}.{DEDUP_EXTENSION}")).exists());
+        let path = directory.path().join(name);
+        let jar = NippyJar::<SegmentHeader>::load(&path).unwrap();
+        assert!(jar.user_header().deduplicated());
+        // The static file provider would look the rows up by hash with the filters
+        assert_eq!(jar.user_header().filter_fpp(), None);
+        assert_eq!(jar.user_header().start(), None);
+
+        // Only the first transaction stores the bytecode
+        let mut cursor = NippyJarCursor::new(&jar).unwrap();
+        let stored = (0..expected.len())
+            .map(|row| {
+                let columns = cursor.row_by_number(row).unwrap().unwrap();
+                TransactionSignedNoHash::decompress(columns[0]).unwrap()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(stored[0], expected[0]);
+        assert!(stored[1..].iter().all(|transaction| {
+            transaction.transaction.input().starts_with(&DEDUP_MAGIC) &&
+                transaction.transaction.input().len() < bytecode.len() / 2
+        }));
+        drop(cursor);
+
+        // The static file provider finds none of them, rather than the deduplicated inputs
+        let static_file_provider = StaticFileProvider::read_only(directory.path()).unwrap();
+        let tx_range = 0..expected.len() as u64;
+        for tx_number in tx_range.clone() {
+            assert_eq!(static_file_provider.transaction_by_id_no_hash(tx_number).unwrap(), None);
+        }
+        assert_eq!(static_file_provider.transaction_by_hash(expected[0].hash()).unwrap(), None);
+
+        // Transactions are reassembled when read by number and found by their hash
+        let reader = StaticFileReader::new(static_file_provider).unwrap();
+        assert_eq!(reader.transactions_by_tx_range(tx_range).unwrap(), expected);
+        for (tx_number, transaction) in expected.iter().enumerate() {
+            let hash = transaction.hash();
+            assert_eq!(
+                reader.transaction_by_id_no_hash(tx_number as u64).unwrap().as_ref(),
+                Some(transaction)
+            );
+            assert_eq!(
+                reader.transaction_by_hash(hash).unwrap(),
+                Some(transaction.clone().with_hash())
+            );
+            assert_eq!(reader.transaction_id(hash).unwrap(), Some(tx_number as u64));
+        }
+        assert_eq!(reader.transaction_by_hash(B256::ZERO).unwrap(), None);
+        let transactions = MultiSegmentReader::new(&reader, StaticFileSegment::Transactions);
+        let rows = transactions
+            .range(0..=harness.tip())
+            .unwrap()
+            .map(|row| row.unwrap().value)
+            .collect::<Vec<_>>();
+        let expected_rows = expected.iter().cloned().map(SegmentValue::Transaction);
+        assert_eq!(rows, expected_rows.collect::<Vec<_>>());
+
+        // Filters would let the static file provider find the deduplicated inputs by hash
+        let filters = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Fmph);
+        assert!(rebuild_filters(
+            directory.path(),
+            StaticFileSegment::Transactions,
+            block_range,
+            filters,
+            FilterHash::Identity,
+            None,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn transaction_filter_keys_without_secret() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let tx_range = provider.transaction_range_by_block_range(1..=2).unwrap();
+
+        let filter_key = Some(FilterKey::new([7; 16]));
+        let keys =
+            transaction_filter_keys(&provider, &tx_range, FilterHash::KeyedSipHash, filter_key)
+                .unwrap();
+        assert_eq!(keys.map(Result::unwrap).count(), tx_range.clone().count());
+
+        // Keys hashed with a secret fail without it
+        let mut keys =
+            transaction_filter_keys(&provider, &tx_range, FilterHash::KeyedSipHash, None).unwrap();
+        assert!(keys.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn transaction_filter_keys_streamed() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let tx_range = provider.transaction_range_by_block_range(1..=2).unwrap();
+
+        // Keys are the hashes of the transactions of the range only, in order
+        let keys = transaction_filter_keys(&provider, &tx_range, FilterHash::Identity, None)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let hashes =
+            provider.transaction_hashes_by_range(*tx_range.start()..*tx_range.end() + 1).unwrap();
+        assert!(!hashes.is_empty());
+        assert_eq!(keys, hashes.iter().map(|(hash, _)| hash.to_vec()).collect::<Vec<_>>());
+
+        // Items of every batch are mapped in order
+        let items = 0..FILTER_KEYS_BATCH * 2 + 1;
+        let mapped = par_map_batched(items.clone(), |item| item * 2).collect::<Vec<_>>();
+        assert_eq!(mapped, items.map(|item| item * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn batched_rows() {
+        // Rows of every batch are read in order, one batch at a time
+        let mut batches = Vec::new();
+        let rows = super::batched_rows(0..=ROWS_BATCH * 2, |range| {
+            batches.push(range.clone());
+            Ok(range.collect())
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+        assert_eq!(rows, (0..=ROWS_BATCH * 2).collect::<Vec<_>>());
+        assert_eq!(
+            batches,
+            vec![
+                0..=ROWS_BATCH - 1,
+                ROWS_BATCH..=ROWS_BATCH * 2 - 1,
+                ROWS_BATCH * 2..=ROWS_BATCH * 2
+            ]
+        );
+
+        // Reading stops at the first error
+        let mut rows = super::batched_rows(0..=ROWS_BATCH, |_| -> ProviderResult<Vec<u64>> {
+            Err(ProviderError::HeaderNotFound(0.into()))
+        });
+        assert!(rows.next().unwrap().is_err());
+        assert!(rows.next().is_none());
+    }
+
+    /// Tests that the sizes rows are stored with in the database are the sizes of the encoded
+    /// rows, so providers that don't report them account for the same bytes.
+    #[test]
+    fn stored_sizes() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let provider = harness.provider_factory.provider().unwrap();
+        let stored = provider.stored_sizes().unwrap();
+
+        let header = provider.header_by_number(1).unwrap().unwrap();
+        let total_difficulty = provider.header_td_by_number(1).unwrap().unwrap();
+        let hash = provider.block_hash(1).unwrap().unwrap();
+        assert_eq!(
+            stored.header_sizes(1).unwrap(),
+            [
+                header.compress().len(),
+                reth_db_api::models::CompactU256::from(total_difficulty).compress().len(),
+                hash.len(),
+            ]
+        );
+
+        let tx_range = provider.transaction_range_by_block_range(1..=3).unwrap();
+        let tx_range = *tx_range.start()..*tx_range.end() + 1;
+        let transactions = provider.transactions_by_tx_range(tx_range.clone()).unwrap();
+        assert!(!transactions.is_empty());
+        assert_eq!(
+            stored.transaction_sizes(tx_range.clone()).unwrap(),
+            row_sizes(None, &transactions).unwrap()
+        );
+        let receipts = provider.receipts_by_tx_range(tx_range.clone()).unwrap();
+        assert_eq!(stored.receipt_sizes(tx_range).unwrap(), row_sizes(None, &receipts).unwrap());
+    }
 }
@@ -0,0 +1,121 @@
+//! Static files created whole from the database, offline, into directories no node produces
+//! static files in, as the static file writer can't append to them. Their types don't implement
+//! [`Segment`](super::Segment), so they can't be passed to the
+//! [`StaticFileProducer`](crate::StaticFileProducer).
+//!
+//! Headers static files without total difficulty and deduplicated Transactions static files
+//! [need the static file reader](SegmentHeader::needs_static_file_reader): the static file
+//! provider finds no rows in them, and only the [`StaticFileReader`](crate::StaticFileReader) and
+//! the [`MultiSegmentReader`](crate::MultiSegmentReader) read them. Headers of static files with a
+//! [`HeaderEnvelope`](reth_static_file_types::HeaderEnvelope) column are read by the static file
+//! provider as well.
+
+use crate::{
+    segments::{
+        batched_rows, headers::create_headers_file, transactions::prepare_transactions_jar,
+        SegmentProvider,
+    },
+    DedupWriter,
+};
+use alloy_primitives::BlockNumber;
+use reth_db_api::table::Compress;
+use reth_nippy_jar::ColumnResult;
+use reth_static_file_types::{
+    Filters, HeadersLayout, SegmentConfig, SegmentHeader, StaticFileSegment,
+};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{ops::RangeInclusive, path::Path};
+use tracing::debug_span;
+
+/// Creates [`StaticFileSegment::Headers`] static files with the
+/// [`HeadersLayout::NoTotalDifficulty`], for chains without total difficulty. They're created
+/// without filters, as the static file provider would look their rows up by hash with them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HeadersWithoutTotalDifficulty;
+
+impl HeadersWithoutTotalDifficulty {
+    /// Creates a static file of the headers of the block range in the directory.
+    pub fn create_static_file_file<P: SegmentProvider>(
+        &self,
+        provider: &P,
+        directory: &Path,
+        config: SegmentConfig,
+        block_range: RangeInclusive<BlockNumber>,
+    ) -> ProviderResult<()> {
+        create_headers_file(
+            provider,
+            directory,
+            config,
+            block_range,
+            HeadersLayout::NoTotalDifficulty,
+            false,
+        )
+    }
+}
+
+/// Creates [`StaticFileSegment::Headers`] static files with a
+/// [`HeaderEnvelope`](reth_static_file_types::HeaderEnvelope) column of the
+/// [`HEADER_ENVELOPE_VERSION`](reth_static_file_types::HEADER_ENVELOPE_VERSION) after the other
+/// columns, so header fields of later versions can be added to them without producing them again.
+/// The headers themselves are stored as is, so the static file provider still reads them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EnvelopedHeaders;
+
+impl EnvelopedHeaders {
+    /// Creates a static file of the headers of the block range in the directory.
+    pub fn create_static_file_file<P: SegmentProvider>(
+        &self,
+        provider: &P,
+        directory: &Path,
+        config: SegmentConfig,
+        block_range: RangeInclusive<BlockNumber>,
+    ) -> ProviderResult<()> {
+        create_headers_file(
+            provider,
+            directory,
+            config,
+            block_range,
+            HeadersLayout::WithTotalDifficulty,
+            true,
+        )
+    }
+}
+
+/// Creates [`StaticFileSegment::Transactions`] static files whose repeated chunks of the inputs of
+/// transactions are deduplicated into the [`DedupTable`](crate::DedupTable) sidecar written next
+/// to them. They're created without filters, as the static file provider would look their rows up
+/// by hash with them, and return the deduplicated inputs.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeduplicatedTransactions;
+
+impl DeduplicatedTransactions {
+    /// Creates a static file of the transactions of the block range in the directory.
+    pub fn create_static_file_file<P: SegmentProvider>(
+        &self,
+        provider: &P,
+        directory: &Path,
+        config: SegmentConfig,
+        block_range: RangeInclusive<BlockNumber>,
+    ) -> ProviderResult<()> {
+        let _span = debug_span!(target: "static_file", "create_static_file", segment = %StaticFileSegment::Transactions, ?block_range, compression = ?config.compression, deduplicated = true).entered();
+        let config = SegmentConfig { filters: Filters::WithoutFilters, ..config };
+        let (mut jar, tx_range) =
+            prepare_transactions_jar(provider, directory, config, &block_range)?;
+        jar.user_header_mut().set_deduplicated(true);
+
+        let rows = tx_range.clone().count();
+        let block = *block_range.end();
+        let mut dedup = DedupWriter::new(directory);
+        let transactions =
+            batched_rows(tx_range.clone(), |range| provider.transactions_by_tx_range(range))
+                .zip(tx_range)
+                .map(|(transaction, tx_number)| -> ColumnResult<Vec<u8>> {
+                    let mut transaction = transaction?;
+                    dedup.dedup(block, tx_number, &mut transaction)?;
+                    Ok(transaction.compress())
+                });
+        jar.freeze(vec![transactions], rows as u64)
+            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        dedup.flush().map_err(|err| ProviderError::NippyJar(err.to_string()))
+    }
+}
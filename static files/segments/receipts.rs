@@ -1,59 +1,134 @@
-use crate::segments::{dataset_for_compression, prepare_jar, Segment};
-use alloy_primitives::{BlockNumber, TxNumber};
-use reth_db::{static_file::create_static_file_T1, tables};
-use reth_db_api::{cursor::DbCursorRO, database::Database, transaction::DbTx};
-use reth_provider::{
-    providers::{StaticFileProvider, StaticFileWriter},
-    BlockReader, DatabaseProviderRO, TransactionsProviderExt,
+use crate::{
+    chd_index::write_chd_index,
+    segments::{
+        batched_rows, collect_chd_keys, dataset_for_compression, encoded_column, freeze_jar,
+        prepare_jar, record_columns, row_sizes, transaction_filter_keys, Segment, SegmentProvider,
+    },
+    BlockBoundariesWriter, CopiedRows, LogIndexWriter, SegmentProgress, StaticFileSink,
 };
-use reth_static_file_types::{SegmentConfig, SegmentHeader, StaticFileSegment};
+use alloy_primitives::BlockNumber;
+use reth_static_file_types::{ColumnCodec, ReceiptKeyMode, SegmentConfig, StaticFileSegment};
 use reth_storage_errors::provider::{ProviderError, ProviderResult};
 use std::{ops::RangeInclusive, path::Path};
+use tracing::debug_span;
 
 /// Static File segment responsible for [`StaticFileSegment::Receipts`] part of data.
 #[derive(Debug, Default)]
-pub struct Receipts;
+pub struct Receipts {
+    /// Whether to build the [`LogIndex`](crate::LogIndex) sidecar of copied receipts.
+    log_index: bool,
+    /// Whether the gas usage of copied receipts is tallied in the
+    /// [`ReceiptStats`](reth_static_file_types::ReceiptStats) of their static files.
+    receipt_stats: bool,
+}
+
+impl Receipts {
+    /// Creates a new [`Receipts`] segment that also builds the [`LogIndex`](crate::LogIndex)
+    /// sidecars of copied receipts, if `log_index` is set.
+    pub const fn new(log_index: bool) -> Self {
+        Self { log_index, receipt_stats: false }
+    }
+
+    /// Sets whether the gas usage of copied receipts is tallied in the
+    /// [`ReceiptStats`](reth_static_file_types::ReceiptStats) of the headers of their static
+    /// files.
+    pub const fn with_receipt_stats(mut self, receipt_stats: bool) -> Self {
+        self.receipt_stats = receipt_stats;
+        self
+    }
+}
 
-impl<DB: Database> Segment<DB> for Receipts {
+impl<P: SegmentProvider> Segment<P> for Receipts {
     /// Returns the specific `StaticFileSegment` that this segment handles (`StaticFileSegment::Receipts`).
     fn segment(&self) -> StaticFileSegment {
         StaticFileSegment::Receipts
     }
 
-    /// Copies data to static files for the provided block range.
-    /// [`StaticFileProvider`] will handle the management of and writing to files.
-    fn copy_to_static_files(
+    /// Returns `true` if the gas usage of the receipts is tallied.
+    fn row_stats(&self) -> bool {
+        self.receipt_stats
+    }
+
+    /// Copies data to the sink for the provided block range.
+    /// The [`StaticFileSink`] will handle the management of and writing to files.
+    fn copy_to_sink(
         &self,
-        provider: DatabaseProviderRO<DB>,
-        static_file_provider: StaticFileProvider,
+        provider: &P,
+        sink: &mut dyn StaticFileSink,
         block_range: RangeInclusive<BlockNumber>,
+        progress: &SegmentProgress,
     ) -> ProviderResult<()> {
-        // Get a writer for the static file segment based on the starting block number
-        let mut static_file_writer =
-            static_file_provider.get_writer(*block_range.start(), StaticFileSegment::Receipts)?;
+        // Sinks without a directory get no sidecars
+        let mut log_index = sink.directory().filter(|_| self.log_index).map(LogIndexWriter::new);
+        let mut block_boundaries = sink.directory().map(BlockBoundariesWriter::new);
 
         // Iterate over each block in the specified range
         for block in block_range {
-            // Increment the block number in the static file writer
-            let _static_file_block =
-                static_file_writer.increment_block(StaticFileSegment::Receipts, block)?;
+            if progress.is_cancelled() {
+                break
+            }
+
+            // Retrieve transaction indices for the current block. Blocks can't be skipped, as
+            // transaction numbers have to be contiguous.
+            let Some(block_body_indices) = provider.block_body_indices(block)? else {
+                let err = ProviderError::BlockBodyIndicesNotFound(block);
+                progress.missing_block(block, err, false)?;
+                break
+            };
+
+            // Increment the block number in the sink
+            let _static_file_block = sink.increment_block(StaticFileSegment::Receipts, block)?;
             debug_assert_eq!(_static_file_block, block);
+            if let Some(block_boundaries) = &mut block_boundaries {
+                block_boundaries
+                    .add(block, block_body_indices.first_tx_num(), block_body_indices.tx_count())
+                    .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+            }
 
-            // Retrieve transaction indices for the current block
-            let block_body_indices = provider
-                .block_body_indices(block)?
-                .ok_or(ProviderError::BlockBodyIndicesNotFound(block))?;
+            // Read the receipts of the block, along with the sizes they're stored with
+            let tx_range = block_body_indices.tx_num_range();
+            let receipts = provider.receipts_by_tx_range(tx_range.clone())?;
+            let stored_sizes =
+                provider.stored_sizes().map(|stored| stored.receipt_sizes(tx_range.clone()));
+            let sizes = row_sizes(stored_sizes, &receipts)?;
 
-            // Create a cursor to read receipts from the database
-            let mut receipts_cursor = provider.tx_ref().cursor_read::<tables::Receipts>()?;
+            // Append receipts to the sink
+            let mut copied = CopiedRows::default();
+            let mut cumulative_gas_used = 0;
+            for ((tx_number, receipt), size) in tx_range.zip(receipts).zip(sizes) {
+                copied.add_row(size);
 
-            // Walk through receipts within the block's transaction range
-            let receipts_walker = receipts_cursor.walk_range(block_body_indices.tx_num_range())?;
+                if let Some(log_index) = &mut log_index {
+                    log_index
+                        .add(block, tx_number, &receipt.logs)
+                        .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+                }
+                let gas_used = receipt.cumulative_gas_used.saturating_sub(cumulative_gas_used);
+                let logs = receipt.logs.len();
+                cumulative_gas_used = receipt.cumulative_gas_used;
+                sink.append_receipt(tx_number, receipt)?;
+                sink.record_row_sizes(&[size])?;
 
-            // Append receipts to the static file using the writer
-            static_file_writer.append_receipts(
-                receipts_walker.map(|result| result.map_err(ProviderError::from)),
-            )?;
+                // Contract creations are only known from the transaction, which isn't read unless
+                // the gas usage is tallied
+                if self.receipt_stats {
+                    let contract_creation = provider
+                        .transaction_by_id_no_hash(tx_number)?
+                        .is_some_and(|transaction| transaction.transaction.kind().is_create());
+                    sink.record_receipt(gas_used, logs, contract_creation)?;
+                }
+            }
+
+            // Report the block as fully copied
+            progress.advance(block, copied);
+            sink.commit_if_due(progress)?;
+        }
+
+        if let Some(mut log_index) = log_index {
+            log_index.flush().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        }
+        if let Some(mut block_boundaries) = block_boundaries {
+            block_boundaries.flush().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
         }
 
         Ok(())
@@ -62,17 +137,18 @@ impl<DB: Database> Segment<DB> for Receipts {
     /// Creates a static file for receipt data based on the block range and configuration provided.
     fn create_static_file_file(
         &self,
-        provider: &DatabaseProviderRO<DB>,
+        provider: &P,
         directory: &Path,
         config: SegmentConfig,
         block_range: RangeInclusive<BlockNumber>,
     ) -> ProviderResult<()> {
+        let _span = debug_span!(target: "static_file", "create_static_file", segment = %StaticFileSegment::Receipts, ?block_range, filters = ?config.filters, compression = ?config.compression).entered();
         // Retrieve the transaction range for the specified block range
         let tx_range = provider.transaction_range_by_block_range(block_range.clone())?;
         let tx_range_len = tx_range.clone().count();
 
         // Prepare a NippyJar for compression and storage
-        let jar = prepare_jar::<DB, 1>(
+        let mut jar = prepare_jar::<P, 1>(
             provider,
             directory,
             StaticFileSegment::Receipts,
@@ -80,37 +156,40 @@ impl<DB: Database> Segment<DB> for Receipts {
             block_range,
             tx_range_len,
             || {
-                Ok([dataset_for_compression::<DB, tables::Receipts>(
-                    provider,
-                    &tx_range,
-                    tx_range_len,
-                )?])
+                Ok([dataset_for_compression(&tx_range, tx_range_len, |range| {
+                    provider.receipts_by_tx_range(range)
+                })?])
             },
         )?;
+        record_columns(&mut jar, &[ColumnCodec::Receipt])?;
+
+        // Filters map transaction hashes to rows, so readers can look receipts up by hash on
+        // their own
+        if config.filters.has_filters() {
+            jar.user_header_mut().set_receipt_key_mode(ReceiptKeyMode::TxHash);
+        }
 
         // Generate list of hashes for filters & PHF
         let hashes = if config.filters.has_filters() {
-            Some(
-                provider
-                    .transaction_hashes_by_range(*tx_range.start()..(*tx_range.end() + 1))?
-                    .into_iter()
-                    .map(|(tx, _)| Ok(tx)),
-            )
+            Some(transaction_filter_keys(
+                provider,
+                &tx_range,
+                config.filter_hash,
+                config.filter_key,
+            )?)
         } else {
             None
         };
+        let (hashes, chd_keys) = collect_chd_keys(config.filters, hashes)?;
+        let data_path = jar.data_path().to_path_buf();
 
-        // Create the static file using the provided function
-        create_static_file_T1::<tables::Receipts, TxNumber, SegmentHeader>(
-            provider.tx_ref(),
-            tx_range,
-            None,
-            // We already prepared the dictionary beforehand
-            None::<Vec<std::vec::IntoIter<Vec<u8>>>>,
-            hashes,
-            tx_range_len,
-            jar,
-        )?;
+        // Create the static file from the receipts read in batches
+        let receipts = batched_rows(tx_range, |range| provider.receipts_by_tx_range(range));
+        freeze_jar(jar, hashes, vec![encoded_column(receipts)], tx_range_len)?;
+        if let Some(keys) = chd_keys {
+            write_chd_index(&data_path, &keys)
+                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        }
 
         Ok(())
     }
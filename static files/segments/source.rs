@@ -0,0 +1,284 @@
+use crate::{
+    reader,
+    segments::{Segment, SegmentProvider, SegmentProviderFactory, WriterSink},
+    verify_blocks, BlockBoundariesWriter, BlockSource, BlockSourceError, CopiedRows,
+    SegmentProgress, SourceBlock, StaticFileSink, TransactionBoundariesWriter, TrustedCheckpoint,
+};
+use alloy_primitives::{BlockHash, BlockNumber, U256};
+use reth_db_api::{models::CompactU256, table::Compress};
+use reth_primitives::TransactionSignedNoHash;
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::{SegmentConfig, StaticFileSegment};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{ops::RangeInclusive, path::Path, sync::Arc};
+
+/// Number of blocks fetched from the [`BlockSource`] at once.
+const SOURCE_CHUNK_BLOCKS: u64 = 1_000;
+
+/// Static File segment copying rows of any [`StaticFileSegment`] from a [`BlockSource`] instead
+/// of the provider, which is only used to locate the static files.
+///
+/// Fetched blocks are verified with [`verify_blocks`], chained to the header before them in static
+/// files, or to the [`TrustedCheckpoint`] without it. Total difficulties are accumulated from the
+/// difficulties of the headers, starting from the one before them in static files or the
+/// checkpoint. Transaction numbers continue from the highest transaction of the segment in static
+/// files. Block boundaries sidecars are built, but sender and log index sidecars aren't.
+#[derive(Debug)]
+pub struct FromSource {
+    /// Segment of the copied rows.
+    segment: StaticFileSegment,
+    /// Source of the copied blocks.
+    source: Arc<dyn BlockSource>,
+    /// Block the copied blocks are chained to without static files before them.
+    checkpoint: TrustedCheckpoint,
+}
+
+impl FromSource {
+    /// Creates a new [`FromSource`] segment copying rows of the segment from the source, chained
+    /// to the checkpoint.
+    pub fn new(
+        segment: StaticFileSegment,
+        source: Arc<dyn BlockSource>,
+        checkpoint: TrustedCheckpoint,
+    ) -> Self {
+        Self { segment, source, checkpoint }
+    }
+
+    /// Returns the trusted hash and total difficulty of the block before the first copied block,
+    /// from static files or the checkpoint.
+    ///
+    /// Fails if neither has the hash, unless the first copied block is the checkpoint itself,
+    /// whose hash is then checked by [`verify_blocks`]. The total difficulty is only needed for
+    /// [`StaticFileSegment::Headers`], and is zero before the genesis block.
+    fn parent(
+        &self,
+        static_file_provider: &StaticFileProvider,
+        first_block: BlockNumber,
+    ) -> ProviderResult<(Option<BlockHash>, Option<U256>)> {
+        let number = first_block.checked_sub(1);
+        if let Some(number) = number {
+            if let Some(hash) = reader::block_hash(static_file_provider, number)? {
+                let total_difficulty = if self.segment == StaticFileSegment::Headers {
+                    reader::header_td_by_number(static_file_provider, number)?
+                } else {
+                    None
+                };
+                return Ok((Some(hash), total_difficulty))
+            }
+        }
+
+        if Some(self.checkpoint.number) == number {
+            Ok((Some(self.checkpoint.hash), self.checkpoint.total_difficulty))
+        } else if self.checkpoint.number == first_block {
+            Ok((None, number.is_none().then_some(U256::ZERO)))
+        } else {
+            Err(ProviderError::NippyJar(format!(
+                "no trusted hash to chain block {first_block} from the block source to, the \
+                 checkpoint is block {}",
+                self.checkpoint.number
+            )))
+        }
+    }
+
+    /// Fetches and verifies the blocks of the chunk from the source.
+    ///
+    /// If the source doesn't have a block of the chunk, it's reported to the progress, which
+    /// fails with [`MissingDataPolicy::Fail`](crate::MissingDataPolicy::Fail). Otherwise, only
+    /// the blocks before it are returned.
+    fn fetch_blocks(
+        &self,
+        chunk: RangeInclusive<BlockNumber>,
+        parent: Option<BlockHash>,
+        progress: &SegmentProgress,
+    ) -> ProviderResult<Vec<SourceBlock>> {
+        let fetch = |chunk: RangeInclusive<BlockNumber>| -> Result<_, BlockSourceError> {
+            let blocks = self.source.blocks(chunk.clone(), self.segment)?;
+            verify_blocks(chunk, &blocks, self.segment, parent, Some(&self.checkpoint))?;
+            Ok(blocks)
+        };
+
+        match fetch(chunk.clone()) {
+            Err(BlockSourceError::MissingBlock(block)) if chunk.contains(&block) => {
+                let err = BlockSourceError::MissingBlock(block);
+                progress.missing_block(block, ProviderError::NippyJar(err.to_string()), false)?;
+                if block == *chunk.start() {
+                    return Ok(Vec::new())
+                }
+                fetch(*chunk.start()..=block - 1)
+            }
+            result => result,
+        }
+        .map_err(|err| ProviderError::NippyJar(err.to_string()))
+    }
+}
+
+impl<P: SegmentProvider> Segment<P> for FromSource {
+    fn segment(&self) -> StaticFileSegment {
+        self.segment
+    }
+
+    /// Copies rows to static files, with a single provider.
+    fn copy_to_static_files(
+        &self,
+        provider_factory: &dyn SegmentProviderFactory<Provider = P>,
+        block_range: RangeInclusive<BlockNumber>,
+        progress: &SegmentProgress,
+    ) -> ProviderResult<()> {
+        let static_file_provider = provider_factory.static_file_provider();
+        let mut sink =
+            WriterSink::for_segment(self, &static_file_provider, *block_range.start(), progress)?;
+        // Rows are fetched from the source, the provider is only used to open the static file
+        // provider, so it's never renewed
+        self.copy_to_sink(
+            &provider_factory.segment_provider()?,
+            &mut progress.row_transforms().wrap(&mut sink),
+            block_range,
+            progress,
+        )
+    }
+
+    /// Fetches blocks of the range from the source in chunks, and copies their rows of the
+    /// segment to the sink once they're verified.
+    fn copy_to_sink(
+        &self,
+        provider: &P,
+        sink: &mut dyn StaticFileSink,
+        block_range: RangeInclusive<BlockNumber>,
+        progress: &SegmentProgress,
+    ) -> ProviderResult<()> {
+        let static_file_provider = provider.static_file_provider();
+        let (mut parent, mut parent_total_difficulty) =
+            self.parent(&static_file_provider, *block_range.start())?;
+        let mut tx_num = static_file_provider
+            .get_highest_static_file_tx(self.segment)
+            .map_or(0, |tx_num| tx_num + 1);
+
+        // Sinks without a directory get no sidecars
+        let mut transaction_boundaries = sink
+            .directory()
+            .filter(|_| self.segment == StaticFileSegment::Transactions)
+            .map(TransactionBoundariesWriter::new);
+        let mut block_boundaries =
+            sink.directory().filter(|_| self.segment.is_receipts()).map(BlockBoundariesWriter::new);
+
+        let mut chunk_start = *block_range.start();
+        while chunk_start <= *block_range.end() &&
+            !progress.is_cancelled() &&
+            !progress.stopped_at_gap()
+        {
+            let chunk = chunk_start..=
+                chunk_start.saturating_add(SOURCE_CHUNK_BLOCKS - 1).min(*block_range.end());
+            chunk_start = chunk.end().saturating_add(1);
+
+            let blocks = self.fetch_blocks(chunk, parent, progress)?;
+
+            for block in blocks {
+                if progress.is_cancelled() {
+                    break
+                }
+
+                let (number, hash) = (block.header.number, block.header.hash());
+                let tx_count = block.transactions.len().max(block.receipts.len()) as u64;
+                let mut copied = CopiedRows::default();
+                match self.segment {
+                    StaticFileSegment::Headers => {
+                        let accumulated =
+                            parent_total_difficulty.map(|parent| parent + block.header.difficulty);
+                        let trusted = self
+                            .checkpoint
+                            .total_difficulty
+                            .filter(|_| number == self.checkpoint.number);
+                        let total_difficulty = match trusted.or(accumulated) {
+                            Some(total_difficulty) => total_difficulty,
+                            None => {
+                                return Err(ProviderError::NippyJar(format!(
+                                    "no total difficulty of the parent of block {number}"
+                                )))
+                            }
+                        };
+                        // The source only vouches for the total difficulty it reports
+                        if block
+                            .total_difficulty
+                            .is_some_and(|reported| reported != total_difficulty)
+                        {
+                            let err = BlockSourceError::Unverified {
+                                block: number,
+                                reason: "total difficulty",
+                            };
+                            return Err(ProviderError::NippyJar(err.to_string()))
+                        }
+                        parent_total_difficulty = Some(total_difficulty);
+                        let header = block.header.unseal();
+                        let header_size = header.clone().compress().len();
+                        let td_size = CompactU256::from(total_difficulty).compress().len();
+                        copied.add_row(header_size);
+                        let _static_file_block =
+                            sink.append_header(header, total_difficulty, hash)?;
+                        debug_assert_eq!(_static_file_block, number);
+                        sink.record_row_sizes(&[header_size, td_size, hash.len()])?;
+                    }
+                    StaticFileSegment::Transactions => {
+                        sink.increment_block(StaticFileSegment::Transactions, number)?;
+                        if let Some(boundaries) = &mut transaction_boundaries {
+                            boundaries
+                                .add(number, tx_num, tx_count)
+                                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+                        }
+                        for transaction in block.transactions {
+                            let transaction = TransactionSignedNoHash::from(transaction);
+                            let size = transaction.clone().compress().len();
+                            copied.add_row(size);
+                            sink.append_transaction(tx_num, transaction)?;
+                            sink.record_row_sizes(&[size])?;
+                            tx_num += 1;
+                        }
+                    }
+                    StaticFileSegment::Receipts => {
+                        sink.increment_block(StaticFileSegment::Receipts, number)?;
+                        if let Some(boundaries) = &mut block_boundaries {
+                            boundaries
+                                .add(number, tx_num, tx_count)
+                                .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+                        }
+                        for receipt in block.receipts {
+                            let size = receipt.clone().compress().len();
+                            copied.add_row(size);
+                            sink.append_receipt(tx_num, receipt)?;
+                            sink.record_row_sizes(&[size])?;
+                            tx_num += 1;
+                        }
+                    }
+                }
+                parent = Some(hash);
+
+                // Report the block as fully copied
+                progress.advance(number, copied);
+                sink.commit_if_due(progress)?;
+            }
+        }
+
+        if let Some(mut boundaries) = transaction_boundaries {
+            boundaries.flush().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        }
+        if let Some(mut boundaries) = block_boundaries {
+            boundaries.flush().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Static files are only appended to from a block source, as compressed static files are
+    /// built from a provider.
+    fn create_static_file_file(
+        &self,
+        _provider: &P,
+        _directory: &Path,
+        _config: SegmentConfig,
+        block_range: RangeInclusive<BlockNumber>,
+    ) -> ProviderResult<()> {
+        Err(ProviderError::NippyJar(format!(
+            "cannot create a {} static file for blocks {block_range:?} from a block source",
+            self.segment
+        )))
+    }
+}
@@ -1,63 +1,135 @@
 // Import necessary modules and functions from the crate and external dependencies
-use crate::segments::{dataset_for_compression, prepare_jar, Segment};
-use alloy_primitives::{BlockNumber, TxNumber};
-use reth_db::{static_file::create_static_file_T1, tables}; // Import database and table utilities
-use reth_db_api::{cursor::DbCursorRO, database::Database, transaction::DbTx}; // Import database APIs
-use reth_provider::{ // Import provider-related utilities
-    providers::{StaticFileProvider, StaticFileWriter}, // Static file providers
-    BlockReader, DatabaseProviderRO, TransactionsProviderExt, // Providers for block reading and transactions
+use crate::{
+    segments::{
+        batched_rows, dataset_for_compression, encoded_column, freeze_jar, prepare_jar,
+        record_columns, row_sizes, transaction_filter_keys, Segment, SegmentProvider,
+    },
+    CopiedRows, SegmentProgress, SenderIndexWriter, StaticFileSink, TransactionBoundariesWriter,
 };
-use reth_static_file_types::{SegmentConfig, SegmentHeader, StaticFileSegment}; // Import static file related types
+use alloy_primitives::{BlockNumber, TxNumber};
+use reth_nippy_jar::NippyJar;
+use reth_static_file_types::{
+    ColumnCodec, SegmentConfig, SegmentHeader, StaticFileSegment,
+}; // Import static file related types
 use reth_storage_errors::provider::{ProviderError, ProviderResult}; // Import error handling utilities
 use std::{ops::RangeInclusive, path::Path}; // Import standard library utilities
+use tracing::debug_span;
 
 /// Static File segment responsible for [`StaticFileSegment::Transactions`] part of data.
 #[derive(Debug, Default)]
-pub struct Transactions;
+pub struct Transactions {
+    /// Whether to build the [`SenderIndex`](crate::SenderIndex) sidecar of copied transactions.
+    sender_index: bool,
+    /// Whether copied transactions are tallied per type in the
+    /// [`TxTypeStats`](reth_static_file_types::TxTypeStats) of their static files.
+    tx_type_stats: bool,
+}
 
-impl<DB: Database> Segment<DB> for Transactions {
+impl Transactions {
+    /// Creates a new [`Transactions`] segment that also builds the
+    /// [`SenderIndex`](crate::SenderIndex) sidecars of copied transactions, if `sender_index` is
+    /// set.
+    pub const fn new(sender_index: bool) -> Self {
+        Self { sender_index, tx_type_stats: false }
+    }
+
+    /// Sets whether copied transactions are tallied per type in the
+    /// [`TxTypeStats`](reth_static_file_types::TxTypeStats) of the headers of their static files.
+    pub const fn with_tx_type_stats(mut self, tx_type_stats: bool) -> Self {
+        self.tx_type_stats = tx_type_stats;
+        self
+    }
+}
+
+impl<P: SegmentProvider> Segment<P> for Transactions {
     /// Returns the specific `StaticFileSegment` that this segment handles (`StaticFileSegment::Transactions`).
     fn segment(&self) -> StaticFileSegment {
         StaticFileSegment::Transactions
     }
 
-    /// Copy transactions from the database table [`tables::Transactions`] to static files
-    /// with segment [`StaticFileSegment::Transactions`] for the provided block range.
-    fn copy_to_static_files(
+    /// Returns `true` if the transactions are tallied per type.
+    fn row_stats(&self) -> bool {
+        self.tx_type_stats
+    }
+
+    /// Copy transactions from the provider to the sink with segment
+    /// [`StaticFileSegment::Transactions`] for the provided block range.
+    fn copy_to_sink(
         &self,
-        provider: DatabaseProviderRO<DB>, // Database provider read-only reference
-        static_file_provider: StaticFileProvider, // Static file provider
+        provider: &P, // Read-only provider of the transactions
+        sink: &mut dyn StaticFileSink, // Destination of the copied transactions
         block_range: RangeInclusive<BlockNumber>, // Range of blocks to process
+        progress: &SegmentProgress, // Progress reporting and cancellation
     ) -> ProviderResult<()> {
-        // Get a writer for the static file segment based on the starting block number
-        let mut static_file_writer = static_file_provider
-            .get_writer(*block_range.start(), StaticFileSegment::Transactions)?;
+        // Senders are taken from the provider, or recovered if it doesn't have them. Sinks
+        // without a directory get no sidecars.
+        let mut sender_index =
+            sink.directory().filter(|_| self.sender_index).map(SenderIndexWriter::new);
+        let mut block_boundaries = sink.directory().map(TransactionBoundariesWriter::new);
 
         // Iterate over each block in the specified range
         for block in block_range {
-            // Increment the block number in the static file writer
+            if progress.is_cancelled() {
+                break
+            }
+
+            // Retrieve transaction indices for the current block. Blocks can't be skipped, as
+            // transaction numbers have to be contiguous.
+            let Some(block_body_indices) = provider.block_body_indices(block)? else {
+                let err = ProviderError::BlockBodyIndicesNotFound(block);
+                progress.missing_block(block, err, false)?;
+                break
+            };
+
+            // Increment the block number in the sink
             let _static_file_block =
-                static_file_writer.increment_block(StaticFileSegment::Transactions, block)?;
+                sink.increment_block(StaticFileSegment::Transactions, block)?;
             debug_assert_eq!(_static_file_block, block);
+            if let Some(block_boundaries) = &mut block_boundaries {
+                block_boundaries
+                    .add(block, block_body_indices.first_tx_num(), block_body_indices.tx_count())
+                    .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+            }
 
-            // Retrieve transaction indices for the current block
-            let block_body_indices = provider
-                .block_body_indices(block)?
-                .ok_or(ProviderError::BlockBodyIndicesNotFound(block))?;
-
-            // Create a cursor to read transactions from the database
-            let mut transactions_cursor =
-                provider.tx_ref().cursor_read::<tables::Transactions>()?;
+            // Read the transactions of the block, along with the sizes they're stored with
+            let tx_range = block_body_indices.tx_num_range();
+            let transactions = provider.transactions_by_tx_range(tx_range.clone())?;
+            let stored_sizes =
+                provider.stored_sizes().map(|stored| stored.transaction_sizes(tx_range.clone()));
+            let sizes = row_sizes(stored_sizes, &transactions)?;
 
-            // Walk through transactions within the block's transaction range
-            let transactions_walker =
-                transactions_cursor.walk_range(block_body_indices.tx_num_range())?;
+            // Append each transaction to the sink
+            let mut copied = CopiedRows::default();
+            for ((tx_number, transaction), size) in tx_range.zip(transactions).zip(sizes) {
+                copied.add_row(size);
 
-            // Append each transaction to the static file using the writer
-            for entry in transactions_walker {
-                let (tx_number, transaction) = entry?;
-                static_file_writer.append_transaction(tx_number, transaction)?;
+                if let Some(sender_index) = &mut sender_index {
+                    let sender = match provider.transaction_sender(tx_number)? {
+                        Some(sender) => Some(sender),
+                        None => transaction.recover_signer(),
+                    };
+                    if let Some(sender) = sender {
+                        sender_index
+                            .add(block, tx_number, sender)
+                            .map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+                    }
+                }
+                let tx_type = u8::from(transaction.transaction.tx_type());
+                sink.append_transaction(tx_number, transaction)?;
+                sink.record_transaction_type(tx_type, size)?;
+                sink.record_row_sizes(&[size])?;
             }
+
+            // Report the block as fully copied
+            progress.advance(block, copied);
+            sink.commit_if_due(progress)?;
+        }
+
+        if let Some(mut sender_index) = sender_index {
+            sender_index.flush().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
+        }
+        if let Some(mut block_boundaries) = block_boundaries {
+            block_boundaries.flush().map_err(|err| ProviderError::NippyJar(err.to_string()))?;
         }
 
         Ok(())
@@ -66,56 +138,59 @@ impl<DB: Database> Segment<DB> for Transactions {
     /// Create a static file for transaction data based on the block range and configuration provided.
     fn create_static_file_file(
         &self,
-        provider: &DatabaseProviderRO<DB>, // Database provider read-only reference
+        provider: &P, // Read-only provider of the transactions
         directory: &Path, // Path to the directory where static file will be saved
         config: SegmentConfig, // Configuration for the static file segment
         block_range: RangeInclusive<BlockNumber>, // Range of blocks to process
     ) -> ProviderResult<()> {
-        // Retrieve the transaction range for the specified block range
-        let tx_range = provider.transaction_range_by_block_range(block_range.clone())?;
+        let _span = debug_span!(target: "static_file", "create_static_file", segment = %StaticFileSegment::Transactions, ?block_range, filters = ?config.filters, compression = ?config.compression).entered();
+        let (jar, tx_range) = prepare_transactions_jar(provider, directory, config, &block_range)?;
         let tx_range_len = tx_range.clone().count();
 
-        // Prepare a NippyJar for compression and storage
-        let jar = prepare_jar::<DB, 1>(
-            provider,
-            directory,
-            StaticFileSegment::Transactions,
-            config,
-            block_range,
-            tx_range_len,
-            || {
-                Ok([dataset_for_compression::<DB, tables::Transactions>(
-                    provider,
-                    &tx_range,
-                    tx_range_len,
-                )?])
-            },
-        )?;
-
         // Generate list of hashes for filters & PHF
         let hashes = if config.filters.has_filters() {
-            Some(
-                provider
-                    .transaction_hashes_by_range(*tx_range.start()..(*tx_range.end() + 1))?
-                    .into_iter()
-                    .map(|(tx, _)| Ok(tx)),
-            )
+            Some(transaction_filter_keys(
+                provider,
+                &tx_range,
+                config.filter_hash,
+                config.filter_key,
+            )?)
         } else {
             None
         };
 
-        // Create the static file using the provided function
-        create_static_file_T1::<tables::Transactions, TxNumber, SegmentHeader>(
-            provider.tx_ref(),
-            tx_range,
-            None,
-            // We already prepared the dictionary beforehand
-            None::<Vec<std::vec::IntoIter<Vec<u8>>>>,
-            hashes,
-            tx_range_len,
-            jar,
-        )?;
-
-        Ok(())
+        // Create the static file from the transactions read in batches
+        let transactions = batched_rows(tx_range, |range| provider.transactions_by_tx_range(range));
+        freeze_jar(jar, hashes, vec![encoded_column(transactions)], tx_range_len)
     }
 }
+
+/// Prepares the `NippyJar` of a static file of the transactions of the block range, returning it
+/// along with the range of the transactions.
+pub(crate) fn prepare_transactions_jar<P: SegmentProvider>(
+    provider: &P,
+    directory: &Path,
+    config: SegmentConfig,
+    block_range: &RangeInclusive<BlockNumber>,
+) -> ProviderResult<(NippyJar<SegmentHeader>, RangeInclusive<TxNumber>)> {
+    // Retrieve the transaction range for the specified block range
+    let tx_range = provider.transaction_range_by_block_range(block_range.clone())?;
+    let tx_range_len = tx_range.clone().count();
+
+    // Prepare a NippyJar for compression and storage
+    let mut jar = prepare_jar::<P, 1>(
+        provider,
+        directory,
+        StaticFileSegment::Transactions,
+        config,
+        block_range.clone(),
+        tx_range_len,
+        || {
+            Ok([dataset_for_compression(&tx_range, tx_range_len, |range| {
+                provider.transactions_by_tx_range(range)
+            })?])
+        },
+    )?;
+    record_columns(&mut jar, &[ColumnCodec::Transaction])?;
+    Ok((jar, tx_range))
+}
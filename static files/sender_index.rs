@@ -0,0 +1,126 @@
+//! Index of transaction senders, stored in a sidecar next to every Transactions static file.
+
+use crate::{
+    sidecar::{
+        decode_rows, encode_rows, extend_rows, push_row, rows_in_range, truncate_rows, RowMap,
+    },
+    IndexRow, Sidecar, SidecarWriter,
+};
+use alloy_primitives::{Address, BlockNumber, TxNumber};
+use reth_static_file_types::StaticFileSegment;
+use std::{io, ops::RangeInclusive};
+
+/// Extension of the transaction sender index sidecar.
+pub const SENDER_INDEX_EXTENSION: &str = "senders";
+
+/// Builds [`SenderIndex`] sidecars while transactions are copied to static files.
+pub type SenderIndexWriter = SidecarWriter<SenderIndex>;
+
+/// Index of transaction senders of a single Transactions static file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SenderIndex {
+    /// Transactions sent by the address.
+    senders: RowMap<Address>,
+}
+
+impl SenderIndex {
+    /// Adds the transaction to the index.
+    ///
+    /// Transactions must be added in ascending order of transaction numbers. Transactions that
+    /// are already indexed are skipped.
+    pub fn add(&mut self, block: BlockNumber, tx_number: TxNumber, sender: Address) {
+        push_row(self.senders.entry(sender).or_default(), IndexRow { block, tx_number });
+    }
+
+    /// Returns the transactions in the block range sent by the address.
+    pub fn by_sender(
+        &self,
+        sender: &Address,
+        range: &RangeInclusive<BlockNumber>,
+    ) -> Vec<IndexRow> {
+        rows_in_range(self.senders.get(sender), range)
+    }
+
+    /// Returns the transactions of the block with their senders, in ascending order of
+    /// transaction numbers.
+    pub fn by_block(&self, block: BlockNumber) -> Vec<(TxNumber, Address)> {
+        let mut transactions = self
+            .senders
+            .iter()
+            .flat_map(|(sender, rows)| {
+                rows.iter().filter(|row| row.block == block).map(|row| (row.tx_number, *sender))
+            })
+            .collect::<Vec<_>>();
+        transactions.sort_unstable();
+        transactions
+    }
+}
+
+impl Sidecar for SenderIndex {
+    const SEGMENT: StaticFileSegment = StaticFileSegment::Transactions;
+    const EXTENSION: &'static str = SENDER_INDEX_EXTENSION;
+
+    fn extend(&mut self, other: Self) {
+        extend_rows(&mut self.senders, other.senders);
+    }
+
+    fn truncate(&mut self, last_block: BlockNumber) {
+        truncate_rows(&mut self.senders, last_block);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.senders.is_empty()
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_rows(&self.senders, buf);
+    }
+
+    fn decode(mut buf: &[u8]) -> io::Result<Self> {
+        Ok(Self { senders: decode_rows(&mut buf, Address::len_bytes(), Address::from_slice)? })
+    }
+}
+
+impl SenderIndexWriter {
+    /// Adds the transaction, flushing the pending index once the block belongs to the next
+    /// static file.
+    pub fn add(
+        &mut self,
+        block: BlockNumber,
+        tx_number: TxNumber,
+        sender: Address,
+    ) -> io::Result<()> {
+        self.sidecar(block)?.add(block, tx_number, sender);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sidecar::static_files_in_range;
+
+    #[test]
+    fn sender_index_sidecar() {
+        let directory = tempfile::tempdir().unwrap();
+        let (alice, bob) = (Address::with_last_byte(1), Address::with_last_byte(2));
+
+        let mut writer = SenderIndexWriter::new(directory.path());
+        writer.add(1, 0, alice).unwrap();
+        writer.add(1, 1, bob).unwrap();
+        writer.add(2, 2, alice).unwrap();
+        writer.flush().unwrap();
+
+        let entries =
+            static_files_in_range(directory.path(), StaticFileSegment::Transactions, &(0..=10));
+        let index = SenderIndex::read(&entries[0]).unwrap();
+        assert_eq!(
+            index.by_sender(&alice, &(0..=10)),
+            vec![IndexRow { block: 1, tx_number: 0 }, IndexRow { block: 2, tx_number: 2 }]
+        );
+        assert_eq!(index.by_sender(&bob, &(2..=10)), vec![]);
+        assert_eq!(index.by_block(1), vec![(0, alice), (1, bob)]);
+        assert_eq!(index.by_block(3), vec![]);
+        assert!(entries[0].companion_path(SENDER_INDEX_EXTENSION).exists());
+    }
+}
@@ -0,0 +1,223 @@
+//! Dual reads validating static files against the database rows they were copied from, while
+//! those rows still exist, before pruning of copied ranges is enabled.
+
+use crate::StaticFileReader;
+use alloy_primitives::{BlockHash, BlockNumber, TxNumber, U256};
+use reth_db::tables;
+use reth_db_api::{database::Database, transaction::DbTx, DatabaseError};
+use reth_primitives::{Header, Receipt, TransactionSignedNoHash};
+use reth_provider::{
+    BlockHashReader, HeaderProvider, ProviderFactory, ReceiptProvider, TransactionsProvider,
+};
+use reth_static_file_types::StaticFileSegment;
+use reth_storage_errors::provider::ProviderResult;
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+use tracing::warn;
+
+/// Counters of the reads of a [`ShadowReader`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShadowReadStats {
+    /// Reads served from static files.
+    pub reads: u64,
+    /// Reads that were also served from the database.
+    pub sampled: u64,
+    /// Sampled reads whose row was still in the database, and so compared.
+    pub compared: u64,
+    /// Compared reads whose row in static files differs from the database.
+    pub mismatches: u64,
+}
+
+/// Counters shared between clones of a [`ShadowReader`].
+#[derive(Debug, Default)]
+struct ShadowCounters {
+    reads: AtomicU64,
+    sampled: AtomicU64,
+    compared: AtomicU64,
+    mismatches: AtomicU64,
+}
+
+/// Reader serving rows from static files, and for a sample of reads also from the database, so
+/// mismatches between both are logged and counted.
+///
+/// Rows already pruned from the database are sampled but not compared.
+#[derive(Debug, Clone)]
+pub struct ShadowReader<DB> {
+    /// Reader of the static files that rows are served from.
+    reader: StaticFileReader,
+    /// Provider factory of the database that sampled rows are compared with.
+    provider_factory: ProviderFactory<DB>,
+    /// Share of the reads that are also served from the database, between `0.0` and `1.0`.
+    sample_rate: f64,
+    /// Counters of the reads.
+    counters: Arc<ShadowCounters>,
+}
+
+impl<DB: Database> ShadowReader<DB> {
+    /// Creates a new [`ShadowReader`] comparing the `sample_rate` share of reads, clamped between
+    /// `0.0` and `1.0`, with the database. Sampled reads are spread evenly over all reads.
+    pub fn new(
+        reader: StaticFileReader,
+        provider_factory: ProviderFactory<DB>,
+        sample_rate: f64,
+    ) -> Self {
+        Self {
+            reader,
+            provider_factory,
+            sample_rate: sample_rate.clamp(0.0, 1.0),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Returns the reader of the static files that rows are served from.
+    pub const fn reader(&self) -> &StaticFileReader {
+        &self.reader
+    }
+
+    /// Returns the counters of the reads since the reader was created.
+    pub fn stats(&self) -> ShadowReadStats {
+        ShadowReadStats {
+            reads: self.counters.reads.load(Ordering::Relaxed),
+            sampled: self.counters.sampled.load(Ordering::Relaxed),
+            compared: self.counters.compared.load(Ordering::Relaxed),
+            mismatches: self.counters.mismatches.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the header of the block.
+    pub fn header_by_number(&self, block: BlockNumber) -> ProviderResult<Option<Header>> {
+        self.read(
+            StaticFileSegment::Headers,
+            block,
+            |reader| reader.header_by_number(block),
+            |tx| tx.get::<tables::Headers>(block),
+        )
+    }
+
+    /// Returns the total difficulty of the block.
+    pub fn header_td_by_number(&self, block: BlockNumber) -> ProviderResult<Option<U256>> {
+        self.read(
+            StaticFileSegment::Headers,
+            block,
+            |reader| reader.header_td_by_number(block),
+            |tx| Ok(tx.get::<tables::HeaderTerminalDifficulties>(block)?.map(Into::into)),
+        )
+    }
+
+    /// Returns the canonical hash of the block.
+    pub fn block_hash(&self, block: BlockNumber) -> ProviderResult<Option<BlockHash>> {
+        self.read(
+            StaticFileSegment::Headers,
+            block,
+            |reader| reader.block_hash(block),
+            |tx| tx.get::<tables::CanonicalHeaders>(block),
+        )
+    }
+
+    /// Returns the transaction with the transaction number.
+    pub fn transaction_by_id_no_hash(
+        &self,
+        tx_number: TxNumber,
+    ) -> ProviderResult<Option<TransactionSignedNoHash>> {
+        self.read(
+            StaticFileSegment::Transactions,
+            tx_number,
+            |reader| reader.transaction_by_id_no_hash(tx_number),
+            |tx| tx.get::<tables::Transactions>(tx_number),
+        )
+    }
+
+    /// Returns the receipt of the transaction with the transaction number.
+    pub fn receipt(&self, tx_number: TxNumber) -> ProviderResult<Option<Receipt>> {
+        self.read(
+            StaticFileSegment::Receipts,
+            tx_number,
+            |reader| reader.receipt(tx_number),
+            |tx| tx.get::<tables::Receipts>(tx_number),
+        )
+    }
+
+    /// Returns `true` if the read numbered `n` is sampled, so that the sample rate of all reads
+    /// up to it is met.
+    fn is_sampled(&self, n: u64) -> bool {
+        ((n + 1) as f64 * self.sample_rate) as u64 > (n as f64 * self.sample_rate) as u64
+    }
+
+    /// Serves the row from static files, comparing it with the database if the read is sampled.
+    fn read<T: PartialEq + Debug>(
+        &self,
+        segment: StaticFileSegment,
+        row: u64,
+        static_file: impl FnOnce(&StaticFileReader) -> ProviderResult<Option<T>>,
+        database: impl FnOnce(&DB::TX) -> Result<Option<T>, DatabaseError>,
+    ) -> ProviderResult<Option<T>> {
+        let value = static_file(&self.reader)?;
+
+        let n = self.counters.reads.fetch_add(1, Ordering::Relaxed);
+        if !self.is_sampled(n) {
+            return Ok(value)
+        }
+        self.counters.sampled.fetch_add(1, Ordering::Relaxed);
+
+        let Some(expected) = database(&self.provider_factory.db_ref().tx()?)? else {
+            return Ok(value)
+        };
+        self.counters.compared.fetch_add(1, Ordering::Relaxed);
+        if value.as_ref() != Some(&expected) {
+            self.counters.mismatches.fetch_add(1, Ordering::Relaxed);
+            warn!(target: "static_file", %segment, row, ?expected, got = ?value, "Static file row doesn't match the database");
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_db_api::transaction::DbTxMut;
+    use reth_provider::StaticFileProviderFactory;
+
+    #[test]
+    fn shadow_reads() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+
+        let shadow = ShadowReader::new(reader.clone(), harness.provider_factory.clone(), 0.5);
+        for block in 0..=harness.tip() {
+            assert!(shadow.header_by_number(block).unwrap().is_some());
+        }
+        assert_eq!(
+            shadow.stats(),
+            ShadowReadStats { reads: 4, sampled: 2, compared: 2, mismatches: 0 }
+        );
+
+        // Corrupt the database row of block 1, as if the static file was copied wrongly
+        let tx = harness.provider_factory.db_ref().tx_mut().unwrap();
+        let mut header = harness.blocks[1].header.header().clone();
+        header.gas_used += 1;
+        tx.put::<tables::Headers>(1, header).unwrap();
+        tx.delete::<tables::CanonicalHeaders>(2, None).unwrap();
+        tx.commit().unwrap();
+
+        let shadow = ShadowReader::new(reader, harness.provider_factory.clone(), 1.0);
+        assert_eq!(
+            shadow.header_by_number(1).unwrap().as_ref(),
+            Some(harness.blocks[1].header.header())
+        );
+        assert!(shadow.block_hash(2).unwrap().is_some());
+        assert!(shadow.receipt(0).unwrap().is_some());
+        assert_eq!(
+            shadow.stats(),
+            ShadowReadStats { reads: 3, sampled: 3, compared: 2, mismatches: 1 }
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Deterministic assignment of the fixed ranges of static files to the hosts producing an
+//! archive together, so its production can be spread across machines.
+//!
+//! Each of the hosts produces the static files of the fixed ranges assigned to its
+//! [`ShardAssignment`] straight from its database, and exports them with a [`ShardManifest`], see
+//! [`ShardProducer::produce`]. The exported shards are then merged into the full archive with
+//! [`merge_shards`].
+
+use crate::{
+    commitment::record_row_root, doctor::splitmix64, import::VerifiedFiles, list_static_files,
+    manifest::write_json, segments::SegmentProviderFactory, ManifestError, NamingScheme,
+    StaticFileEntry, StaticFileManifest, StaticFileProducerError, StaticFileProducerInner,
+};
+use alloy_primitives::BlockNumber;
+use reth_static_file_types::{
+    find_fixed_range, HighestStaticFiles, SegmentRangeInclusive, StaticFileSegment,
+    BLOCKS_PER_STATIC_FILE,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeSet,
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Name of the file in the directory of an exported shard holding its [`ShardManifest`].
+pub const SHARD_MANIFEST_FILE_NAME: &str = "shard.json";
+
+/// Shard of the fixed ranges produced by one of `count` hosts.
+///
+/// A fixed range is assigned to the shard of the splitmix64 hash of its first block, modulo the
+/// number of shards, so every host derives the same assignment without coordination. All
+/// segments of a range are assigned to the same shard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardAssignment {
+    /// Index of the shard, below `count`.
+    pub index: u64,
+    /// Number of shards.
+    pub count: u64,
+}
+
+impl ShardAssignment {
+    /// Creates a new [`ShardAssignment`] of the `index`th of `count` shards. Returns `None` if
+    /// the index is out of bounds.
+    pub const fn new(index: u64, count: u64) -> Option<Self> {
+        if index < count {
+            Some(Self { index, count })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the index of the shard the fixed range is assigned to, out of `count` shards.
+    pub fn shard_of(count: u64, fixed_range: &SegmentRangeInclusive) -> u64 {
+        let mut state = fixed_range.start();
+        splitmix64(&mut state) % count.max(1)
+    }
+
+    /// Returns `true` if the fixed range is assigned to the shard.
+    pub fn contains(&self, fixed_range: &SegmentRangeInclusive) -> bool {
+        Self::shard_of(self.count, fixed_range) == self.index
+    }
+
+    /// Returns the block ranges of the fixed ranges assigned to the shard, up to the tip. The
+    /// range holding the tip ends at it.
+    pub fn ranges(&self, tip: BlockNumber) -> Vec<SegmentRangeInclusive> {
+        (0..=tip / BLOCKS_PER_STATIC_FILE)
+            .map(|n| find_fixed_range(n * BLOCKS_PER_STATIC_FILE))
+            .filter(|fixed_range| self.contains(fixed_range))
+            .map(|fixed_range| {
+                SegmentRangeInclusive::new(fixed_range.start(), fixed_range.end().min(tip))
+            })
+            .collect()
+    }
+}
+
+/// Manifest of an exported shard: the static files of its ranges, listed under their
+/// content-addressed names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShardManifest {
+    /// Shard of the exported ranges.
+    pub shard: ShardAssignment,
+    /// Tip of the archive the shard was produced for.
+    pub tip: BlockNumber,
+    /// Static files of the shard.
+    pub manifest: StaticFileManifest,
+}
+
+impl ShardManifest {
+    /// Reads the shard manifest from a JSON file.
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Writes the shard manifest to a JSON file.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        write_json(self, path)
+    }
+}
+
+/// Producer of the static files of a shard, with the segments and their configuration of a
+/// static file producer.
+///
+/// The static files directory of the producer is left untouched, and the static files of a shard
+/// are never appended to, so the range holding the tip is sealed at it.
+#[derive(Debug)]
+pub struct ShardProducer<'a, F: SegmentProviderFactory> {
+    /// Producer whose segments copy the rows of the shard.
+    producer: &'a StaticFileProducerInner<F>,
+}
+
+impl<'a, F: SegmentProviderFactory> ShardProducer<'a, F> {
+    /// Creates a new [`ShardProducer`] with the segments of the producer.
+    pub const fn new(producer: &'a StaticFileProducerInner<F>) -> Self {
+        Self { producer }
+    }
+
+    /// Produces the static files of the fixed ranges assigned to the shard, up to the tip, from
+    /// the database and exports them to the `output` directory under their content-addressed
+    /// names, with the [`ShardManifest`] listing them in [`SHARD_MANIFEST_FILE_NAME`].
+    ///
+    /// Meant for spreading the production of an archive across hosts: every host produces its
+    /// own shard, and the exported shards are merged with [`merge_shards`].
+    pub fn produce(
+        &self,
+        shard: ShardAssignment,
+        tip: BlockNumber,
+        output: &Path,
+    ) -> Result<ShardManifest, StaticFileProducerError> {
+        let staging_dir = output.join("staging");
+        // Leftovers of an interrupted production would be exported along with this one
+        if staging_dir.exists() {
+            std::fs::remove_dir_all(&staging_dir)?;
+        }
+        std::fs::create_dir_all(&staging_dir)?;
+
+        let producer = self.producer;
+        let provider = producer.provider_factory().segment_provider()?;
+        for block_range in shard.ranges(tip) {
+            for segment in [
+                StaticFileSegment::Headers,
+                StaticFileSegment::Transactions,
+                StaticFileSegment::Receipts,
+            ]
+            .into_iter()
+            .filter(|segment| producer.is_enabled(*segment))
+            {
+                debug!(target: "static_file", %segment, %block_range, shard = shard.index, "Producing static file of shard");
+                producer.install_filters(|| {
+                    producer.segment(segment).create_static_file_file(
+                        &provider,
+                        &staging_dir,
+                        producer.segment_config(segment),
+                        block_range.start()..=block_range.end(),
+                    )
+                })??;
+            }
+        }
+
+        let entries = list_static_files(&staging_dir)?;
+        if producer.row_commitments() {
+            for entry in &entries {
+                record_row_root(entry)?;
+            }
+        }
+        let sealed = HighestStaticFiles {
+            headers: Some(BlockNumber::MAX),
+            receipts: Some(BlockNumber::MAX),
+            transactions: Some(BlockNumber::MAX),
+        };
+        let manifest = StaticFileManifest::new(&entries, sealed, NamingScheme::ContentAddressed)?;
+        manifest.publish(&entries, output)?;
+        std::fs::remove_dir_all(&staging_dir)?;
+
+        let shard_manifest = ShardManifest { shard, tip, manifest };
+        shard_manifest.write(&output.join(SHARD_MANIFEST_FILE_NAME))?;
+        Ok(shard_manifest)
+    }
+}
+
+/// Error returned by [`merge_shards`]. Nothing is merged in every case.
+#[derive(Debug)]
+pub enum ShardError {
+    /// Filesystem error.
+    Io(io::Error),
+    /// Static files of a shard don't match its manifest.
+    Manifest(ManifestError),
+    /// The shard was produced for another number of shards or tip than the first one.
+    Mismatch {
+        /// Directory of the shard.
+        directory: PathBuf,
+    },
+    /// The index of the shard isn't below the number of shards.
+    InvalidShard {
+        /// Directory of the shard.
+        directory: PathBuf,
+    },
+    /// The shard is merged more than once.
+    DuplicateShard(u64),
+    /// The shard is missing, so the ranges assigned to it would be missing from the archive.
+    MissingShard(u64),
+}
+
+impl From<io::Error> for ShardError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ManifestError> for ShardError {
+    fn from(value: ManifestError) -> Self {
+        Self::Manifest(value)
+    }
+}
+
+impl fmt::Display for ShardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::Manifest(err) => fmt::Display::fmt(err, f),
+            Self::Mismatch { directory } => write!(
+                f,
+                "shard in {} was produced for another number of shards or tip",
+                directory.display()
+            ),
+            Self::InvalidShard { directory } => {
+                write!(f, "shard in {} has an out of bounds index", directory.display())
+            }
+            Self::DuplicateShard(index) => write!(f, "shard {index} is merged more than once"),
+            Self::MissingShard(index) => write!(f, "shard {index} is missing"),
+        }
+    }
+}
+
+impl std::error::Error for ShardError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Manifest(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Merges the shards exported to the directories into the static files directory of the full
+/// archive.
+///
+/// Every shard of the same number of shards and tip has to be merged exactly once, and all
+/// static files are checked against the manifests of their shards before any of them is copied.
+/// Manifests of static files produced with another schema version are rejected. Returns the
+/// merged static files.
+pub fn merge_shards(
+    shards: &[PathBuf],
+    static_files_dir: &Path,
+) -> Result<Vec<StaticFileEntry>, ShardError> {
+    let manifests = shards
+        .iter()
+        .map(|directory| ShardManifest::read(&directory.join(SHARD_MANIFEST_FILE_NAME)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let Some(first) = manifests.first() else { return Ok(Vec::new()) };
+
+    // The number of shards comes from the manifests, so merged shards are tracked by index
+    // rather than in a table of that size
+    let mut merged = BTreeSet::new();
+    for (directory, manifest) in shards.iter().zip(&manifests) {
+        if (manifest.shard.count, manifest.tip) != (first.shard.count, first.tip) {
+            return Err(ShardError::Mismatch { directory: directory.clone() })
+        }
+        if ShardAssignment::new(manifest.shard.index, manifest.shard.count).is_none() {
+            return Err(ShardError::InvalidShard { directory: directory.clone() })
+        }
+        if !merged.insert(manifest.shard.index) {
+            return Err(ShardError::DuplicateShard(manifest.shard.index))
+        }
+        if let Some(build) = &manifest.manifest.build {
+            build.check_schema().map_err(ManifestError::IncompatibleSchema)?;
+        }
+    }
+    // Merged indices are distinct and below the number of shards, so a missing one is found
+    // among the first `merged.len() + 1` indices
+    if let Some(index) = (0..first.shard.count).find(|index| !merged.contains(index)) {
+        return Err(ShardError::MissingShard(index))
+    }
+
+    let verified = shards
+        .iter()
+        .zip(&manifests)
+        .map(|(directory, manifest)| VerifiedFiles::check(&manifest.manifest, directory))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut entries = Vec::new();
+    for verified in verified {
+        entries.extend(verified.copy_to(static_files_dir)?);
+    }
+    entries.sort_unstable_by_key(|entry| (entry.segment, entry.block_range.start()));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{doctor::diagnose, list_static_files, test_utils::StaticFileTestHarness};
+
+    #[test]
+    fn sharded_archive() {
+        let fixed_range = |n: u64| find_fixed_range(n * BLOCKS_PER_STATIC_FILE);
+        let shards = [0, 1, 2].map(|index| ShardAssignment::new(index, 3).unwrap());
+        assert_eq!(ShardAssignment::new(3, 3), None);
+
+        // Every fixed range is assigned to exactly one shard
+        let tip = 8 * BLOCKS_PER_STATIC_FILE - 1;
+        let mut ranges = shards.iter().flat_map(|shard| shard.ranges(tip)).collect::<Vec<_>>();
+        ranges.sort_by_key(|range| range.start());
+        assert_eq!(ranges, (0..8).map(fixed_range).collect::<Vec<_>>());
+        let owner = ShardAssignment::shard_of(3, &fixed_range(0)) as usize;
+        assert_eq!(shards[owner].ranges(10), [SegmentRangeInclusive::new(0, 10)]);
+
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let producer = harness.producer();
+        let (hosts, archive) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let directories = [0, 1].map(|index| {
+            let directory = hosts.path().join(index.to_string());
+            let shard = ShardAssignment::new(index, 2).unwrap();
+            producer.produce_shard(shard, harness.tip(), &directory).unwrap();
+            directory
+        });
+
+        // Only the shard of the first range has static files, one per segment
+        let owner = ShardAssignment::shard_of(2, &fixed_range(0)) as usize;
+        let read = |directory: &PathBuf| {
+            ShardManifest::read(&directory.join(SHARD_MANIFEST_FILE_NAME)).unwrap()
+        };
+        assert_eq!(read(&directories[owner]).manifest.files.len(), 3);
+        assert!(read(&directories[1 - owner]).manifest.files.is_empty());
+
+        // Every shard has to be merged, once
+        assert!(matches!(
+            merge_shards(&directories[owner..=owner], archive.path()),
+            Err(ShardError::MissingShard(_))
+        ));
+        let duplicated = [directories[0].clone(), directories[0].clone()];
+        assert!(matches!(
+            merge_shards(&duplicated, archive.path()),
+            Err(ShardError::DuplicateShard(0))
+        ));
+        assert!(list_static_files(archive.path()).unwrap().is_empty());
+
+        // Shards with an out of bounds index, or claiming more shards than merged, are rejected
+        let tampered = hosts.path().join("tampered");
+        std::fs::create_dir_all(&tampered).unwrap();
+        let merge_tampered = |index, count| {
+            let mut manifest = read(&directories[0]);
+            manifest.shard = ShardAssignment { index, count };
+            manifest.write(&tampered.join(SHARD_MANIFEST_FILE_NAME)).unwrap();
+            merge_shards(std::slice::from_ref(&tampered), archive.path())
+        };
+        assert!(matches!(merge_tampered(2, 2), Err(ShardError::InvalidShard { .. })));
+        assert!(matches!(merge_tampered(0, 0), Err(ShardError::InvalidShard { .. })));
+        assert!(matches!(merge_tampered(0, u64::MAX), Err(ShardError::MissingShard(1))));
+        assert!(list_static_files(archive.path()).unwrap().is_empty());
+
+        let merged = merge_shards(&directories, archive.path()).unwrap();
+        assert_eq!(merged, list_static_files(archive.path()).unwrap());
+        assert_eq!(merged.len(), 3);
+        for entry in &merged {
+            assert!(diagnose(entry, None).unwrap().is_none());
+        }
+    }
+}
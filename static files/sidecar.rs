@@ -0,0 +1,206 @@
+//! Index sidecars stored next to static files, mapping keys to the rows of their static file.
+
+use crate::StaticFileEntry;
+use alloy_primitives::{BlockNumber, TxNumber};
+use reth_static_file_types::{find_fixed_range, StaticFileSegment};
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// Row of a static file referenced by an index sidecar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IndexRow {
+    /// Block of the row.
+    pub block: BlockNumber,
+    /// Transaction number of the row.
+    pub tx_number: TxNumber,
+}
+
+/// Rows of a static file, by key.
+pub(crate) type RowMap<K> = BTreeMap<K, Vec<IndexRow>>;
+
+/// Index sidecar of a single static file.
+pub trait Sidecar: Default + Sized {
+    /// Segment of the indexed static files.
+    const SEGMENT: StaticFileSegment;
+
+    /// Extension of the sidecar, next to the data file of the static file.
+    const EXTENSION: &'static str;
+
+    /// Adds all rows of the other sidecar to the sidecar.
+    fn extend(&mut self, other: Self);
+
+    /// Removes the rows of the blocks after `last_block`, e.g. unwound by a reorg.
+    fn truncate(&mut self, last_block: BlockNumber);
+
+    /// Returns `true` if nothing is indexed.
+    fn is_empty(&self) -> bool;
+
+    /// Encodes the sidecar.
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Decodes the sidecar.
+    fn decode(buf: &[u8]) -> io::Result<Self>;
+
+    /// Reads the sidecar of the static file. Returns an empty sidecar if it doesn't exist.
+    fn read(entry: &StaticFileEntry) -> io::Result<Self> {
+        match std::fs::read(entry.companion_path(Self::EXTENSION)) {
+            Ok(data) => Self::decode(&data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Writes the sidecar of the static file, replacing it atomically.
+    fn write(&self, entry: &StaticFileEntry) -> io::Result<()> {
+        let path = entry.companion_path(Self::EXTENSION);
+        let tmp_path = path.with_extension(format!("{}.tmp", Self::EXTENSION));
+
+        let mut buf = Vec::new();
+        self.encode(&mut buf);
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(&buf)?;
+        file.sync_all()?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+/// Builds [`Sidecar`]s while rows are copied to static files, merging them with the existing
+/// sidecars of static files that are appended to.
+#[derive(Debug)]
+pub struct SidecarWriter<S> {
+    /// Static files directory.
+    directory: PathBuf,
+    /// Static file being indexed and its pending sidecar.
+    current: Option<(StaticFileEntry, S)>,
+}
+
+impl<S: Sidecar> SidecarWriter<S> {
+    /// Creates a new [`SidecarWriter`] for the static files directory.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self { directory: directory.into(), current: None }
+    }
+
+    /// Returns the pending sidecar of the static file holding the block, flushing the previous
+    /// one once the block belongs to the next static file.
+    pub(crate) fn sidecar(&mut self, block: BlockNumber) -> io::Result<&mut S> {
+        let block_range = find_fixed_range(block);
+        if self.current.as_ref().is_some_and(|(entry, _)| entry.block_range != block_range) {
+            self.flush()?;
+        }
+
+        let (_, sidecar) = self.current.get_or_insert_with(|| {
+            let path = self.directory.join(S::SEGMENT.filename(&block_range));
+            (StaticFileEntry { segment: S::SEGMENT, block_range, path }, S::default())
+        });
+        Ok(sidecar)
+    }
+
+    /// Merges the pending sidecar into the sidecar of its static file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        let Some((entry, sidecar)) = self.current.take() else { return Ok(()) };
+        if sidecar.is_empty() {
+            return Ok(())
+        }
+
+        let mut merged = S::read(&entry)?;
+        merged.extend(sidecar);
+        merged.write(&entry)
+    }
+}
+
+/// Returns the static files of the segment overlapping the block range.
+pub(crate) fn static_files_in_range(
+    directory: &Path,
+    segment: StaticFileSegment,
+    range: &RangeInclusive<BlockNumber>,
+) -> Vec<StaticFileEntry> {
+    let mut entries = Vec::new();
+    let mut block = *range.start();
+    while block <= *range.end() {
+        let block_range = find_fixed_range(block);
+        let path = directory.join(segment.filename(&block_range));
+        entries.push(StaticFileEntry { segment, block_range, path });
+
+        let Some(next) = block_range.end().checked_add(1) else { break };
+        block = next;
+    }
+    entries
+}
+
+/// Adds the row to the rows of a key, unless it's already indexed.
+pub(crate) fn push_row(rows: &mut Vec<IndexRow>, row: IndexRow) {
+    if rows.last().is_none_or(|last| last.tx_number < row.tx_number) {
+        rows.push(row);
+    }
+}
+
+/// Adds all rows of the other map to the map.
+pub(crate) fn extend_rows<K: Ord>(map: &mut RowMap<K>, other: RowMap<K>) {
+    for (key, rows) in other {
+        let indexed = map.entry(key).or_default();
+        rows.into_iter().for_each(|row| push_row(indexed, row));
+    }
+}
+
+/// Removes the rows of the blocks after `last_block` from the map, and the keys left without rows.
+pub(crate) fn truncate_rows<K: Ord>(map: &mut RowMap<K>, last_block: BlockNumber) {
+    map.retain(|_, rows| {
+        rows.retain(|row| row.block <= last_block);
+        !rows.is_empty()
+    });
+}
+
+/// Returns the rows of a key in the block range.
+pub(crate) fn rows_in_range(
+    rows: Option<&Vec<IndexRow>>,
+    range: &RangeInclusive<BlockNumber>,
+) -> Vec<IndexRow> {
+    rows.map(|rows| rows.iter().filter(|row| range.contains(&row.block)).copied().collect())
+        .unwrap_or_default()
+}
+
+/// Encodes the rows as the number of keys, followed by every key with its number of rows and the
+/// rows, all integers in little endian.
+pub(crate) fn encode_rows<K: AsRef<[u8]>>(map: &RowMap<K>, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(map.len() as u64).to_le_bytes());
+    for (key, rows) in map {
+        buf.extend_from_slice(key.as_ref());
+        buf.extend_from_slice(&(rows.len() as u64).to_le_bytes());
+        for row in rows {
+            buf.extend_from_slice(&row.block.to_le_bytes());
+            buf.extend_from_slice(&row.tx_number.to_le_bytes());
+        }
+    }
+}
+
+/// Decodes the rows encoded with [`encode_rows`], with keys of `key_len` bytes.
+pub(crate) fn decode_rows<K: Ord>(
+    buf: &mut &[u8],
+    key_len: usize,
+    from_slice: impl Fn(&[u8]) -> K,
+) -> io::Result<RowMap<K>> {
+    let mut map = BTreeMap::new();
+    for _ in 0..read_u64(buf)? {
+        let mut key = vec![0; key_len];
+        buf.read_exact(&mut key)?;
+
+        let len = read_u64(buf)?;
+        let mut rows = Vec::with_capacity(len.min(buf.len() as u64 / 16) as usize);
+        for _ in 0..len {
+            rows.push(IndexRow { block: read_u64(buf)?, tx_number: read_u64(buf)? });
+        }
+        map.insert(from_slice(&key), rows);
+    }
+    Ok(map)
+}
+
+/// Reads a little endian 64-bit integer.
+pub(crate) fn read_u64(buf: &mut &[u8]) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    buf.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
@@ -0,0 +1,229 @@
+//! Destinations of the rows copied by segments, either the static file writers of the
+//! [`StaticFileProvider`](reth_provider::providers::StaticFileProvider) or memory, so the
+//! producer can run in tests and embedders without touching disk.
+
+use crate::SegmentProgress;
+use alloy_primitives::{BlockHash, BlockNumber, TxNumber, U256};
+use parking_lot::RwLock;
+use reth_primitives::{Header, Receipt, TransactionSignedNoHash};
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+/// Destination of the rows copied by a [`Segment`](crate::segments::Segment).
+pub trait StaticFileSink {
+    /// Appends the header with its total difficulty and hash, returning its block number.
+    fn append_header(
+        &mut self,
+        header: Header,
+        total_difficulty: U256,
+        hash: BlockHash,
+    ) -> ProviderResult<BlockNumber>;
+
+    /// Starts the next block of the segment, returning its block number.
+    fn increment_block(
+        &mut self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> ProviderResult<BlockNumber>;
+
+    /// Appends the transaction to the current block, returning its transaction number.
+    fn append_transaction(
+        &mut self,
+        tx_num: TxNumber,
+        transaction: TransactionSignedNoHash,
+    ) -> ProviderResult<TxNumber>;
+
+    /// Appends the receipt to the current block, returning its transaction number.
+    fn append_receipt(&mut self, tx_num: TxNumber, receipt: Receipt) -> ProviderResult<TxNumber>;
+
+    /// Tallies the type and encoded size of the last appended transaction, if the sink keeps
+    /// [`TxTypeStats`](reth_static_file_types::TxTypeStats). Does nothing by default.
+    fn record_transaction_type(&mut self, _tx_type: u8, _size: usize) -> ProviderResult<()> {
+        Ok(())
+    }
+
+    /// Tallies the gas used and logs of the last appended receipt, if the sink keeps
+    /// [`ReceiptStats`](reth_static_file_types::ReceiptStats). Does nothing by default.
+    fn record_receipt(
+        &mut self,
+        _gas_used: u64,
+        _logs: usize,
+        _contract_creation: bool,
+    ) -> ProviderResult<()> {
+        Ok(())
+    }
+
+    /// Tallies the sizes of the values of every column of the last appended row, as encoded in
+    /// the database, if the sink keeps [`SizeHistogram`](reth_static_file_types::SizeHistogram)s.
+    /// Does nothing by default.
+    fn record_row_sizes(&mut self, _sizes: &[usize]) -> ProviderResult<()> {
+        Ok(())
+    }
+
+    /// Commits the copied rows once the commit interval of the segment elapsed.
+    fn commit_if_due(&mut self, progress: &SegmentProgress) -> ProviderResult<()>;
+
+    /// Returns the static files directory, where sidecars of the copied rows are written. If
+    /// `None`, no sidecars are built.
+    fn directory(&self) -> Option<&Path>;
+}
+
+/// Rows copied into memory, shared between an [`InMemorySink`] and its [`InMemoryReader`]s.
+#[derive(Debug, Default)]
+struct InMemoryStaticFiles {
+    headers: BTreeMap<BlockNumber, (Header, U256, BlockHash)>,
+    transactions: BTreeMap<TxNumber, TransactionSignedNoHash>,
+    receipts: BTreeMap<TxNumber, Receipt>,
+    highest: HighestStaticFiles,
+}
+
+impl InMemoryStaticFiles {
+    /// Returns the next transaction number of the transactions or receipts segment.
+    fn next_tx_num(&self, segment: StaticFileSegment) -> TxNumber {
+        let last = match segment {
+            StaticFileSegment::Headers => None,
+            StaticFileSegment::Transactions => self.transactions.keys().next_back(),
+            StaticFileSegment::Receipts => self.receipts.keys().next_back(),
+        };
+        last.map_or(0, |tx_num| tx_num + 1)
+    }
+
+    /// Starts the next block of the segment, failing if it's not contiguous to the highest one.
+    fn increment_block(
+        &mut self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> ProviderResult<BlockNumber> {
+        let highest = self.highest.as_mut(segment);
+        let next = highest.map_or(0, |highest| highest + 1);
+        if block != next {
+            return Err(ProviderError::UnexpectedStaticFileBlockNumber(segment, block, next))
+        }
+        *highest = Some(block);
+        Ok(block)
+    }
+
+    /// Checks that the transaction number is the next one of the segment.
+    fn check_tx_num(&self, segment: StaticFileSegment, tx_num: TxNumber) -> ProviderResult<()> {
+        let next = self.next_tx_num(segment);
+        if tx_num != next {
+            return Err(ProviderError::UnexpectedStaticFileTxNumber(segment, tx_num, next))
+        }
+        Ok(())
+    }
+}
+
+/// [`StaticFileSink`] that keeps the copied rows in memory, for tests and embedded use.
+///
+/// Rows are visible to its [readers](InMemorySink::reader) as soon as they're appended.
+#[derive(Debug, Clone, Default)]
+pub struct InMemorySink {
+    static_files: Arc<RwLock<InMemoryStaticFiles>>,
+}
+
+impl InMemorySink {
+    /// Creates a new empty [`InMemorySink`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a reader of the rows copied into the sink.
+    pub fn reader(&self) -> InMemoryReader {
+        InMemoryReader { static_files: self.static_files.clone() }
+    }
+}
+
+impl StaticFileSink for InMemorySink {
+    fn append_header(
+        &mut self,
+        header: Header,
+        total_difficulty: U256,
+        hash: BlockHash,
+    ) -> ProviderResult<BlockNumber> {
+        let mut static_files = self.static_files.write();
+        let block = static_files.increment_block(StaticFileSegment::Headers, header.number)?;
+        static_files.headers.insert(block, (header, total_difficulty, hash));
+        Ok(block)
+    }
+
+    fn increment_block(
+        &mut self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> ProviderResult<BlockNumber> {
+        self.static_files.write().increment_block(segment, block)
+    }
+
+    fn append_transaction(
+        &mut self,
+        tx_num: TxNumber,
+        transaction: TransactionSignedNoHash,
+    ) -> ProviderResult<TxNumber> {
+        let mut static_files = self.static_files.write();
+        static_files.check_tx_num(StaticFileSegment::Transactions, tx_num)?;
+        static_files.transactions.insert(tx_num, transaction);
+        Ok(tx_num)
+    }
+
+    fn append_receipt(&mut self, tx_num: TxNumber, receipt: Receipt) -> ProviderResult<TxNumber> {
+        let mut static_files = self.static_files.write();
+        static_files.check_tx_num(StaticFileSegment::Receipts, tx_num)?;
+        static_files.receipts.insert(tx_num, receipt);
+        Ok(tx_num)
+    }
+
+    fn commit_if_due(&mut self, progress: &SegmentProgress) -> ProviderResult<()> {
+        // Appended rows are never lost, so there's nothing to commit
+        if progress.is_commit_due() {
+            progress.committed();
+        }
+        Ok(())
+    }
+
+    fn directory(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Reader of the rows copied into an [`InMemorySink`].
+#[derive(Debug, Clone)]
+pub struct InMemoryReader {
+    static_files: Arc<RwLock<InMemoryStaticFiles>>,
+}
+
+impl InMemoryReader {
+    /// Returns the highest block of every segment.
+    pub fn highest_static_files(&self) -> HighestStaticFiles {
+        self.static_files.read().highest
+    }
+
+    /// Returns the header of the block.
+    pub fn header_by_number(&self, block: BlockNumber) -> Option<Header> {
+        self.static_files.read().headers.get(&block).map(|(header, _, _)| header.clone())
+    }
+
+    /// Returns the total difficulty of the block.
+    pub fn header_td_by_number(&self, block: BlockNumber) -> Option<U256> {
+        self.static_files
+            .read()
+            .headers
+            .get(&block)
+            .map(|(_, total_difficulty, _)| *total_difficulty)
+    }
+
+    /// Returns the canonical hash of the block.
+    pub fn block_hash(&self, block: BlockNumber) -> Option<BlockHash> {
+        self.static_files.read().headers.get(&block).map(|(_, _, hash)| *hash)
+    }
+
+    /// Returns the transaction with the transaction number.
+    pub fn transaction_by_id(&self, tx_num: TxNumber) -> Option<TransactionSignedNoHash> {
+        self.static_files.read().transactions.get(&tx_num).cloned()
+    }
+
+    /// Returns the receipt of the transaction with the transaction number.
+    pub fn receipt(&self, tx_num: TxNumber) -> Option<Receipt> {
+        self.static_files.read().receipts.get(&tx_num).cloned()
+    }
+}
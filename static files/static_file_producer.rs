@@ -1,46 +1,86 @@
 //! Support for producing static files.
 
-use crate::{segments, segments::Segment, StaticFileProducerEvent};
-use alloy_primitives::BlockNumber;
-use parking_lot::Mutex;
-use rayon::prelude::*;
-use reth_db_api::database::Database;
-use reth_provider::{
-    providers::StaticFileWriter, ProviderFactory, StageCheckpointReader as _,
-    StaticFileProviderFactory,
+use crate::{
+    accumulator::{append_epoch_roots, epoch_end, read_epoch_roots, EPOCH_SIZE, MERGE_BLOCK},
+    check_chain_id,
+    commitment::record_row_root,
+    committed::publish_committed_rows,
+    content_hash,
+    coordinator::CoordinatorMembership,
+    estimate_bytes,
+    health::RunOutcomes,
+    history::append_run_record,
+    hooks::PendingSealed,
+    list_static_files, lowest_static_files,
+    prune_checkpoint::{confirm_pruned, record_prunable},
+    read_run_history,
+    reader::epoch_accumulator,
+    reorg::unwind_segment,
+    repair::RepairService,
+    rewrite::{complete_rewrites, rewrite_static_file},
+    rollback::{is_disk_full, recover_tails, CommittedTip, TailSnapshot},
+    scan_static_files_against, segments,
+    segments::{CustomSegments, Segment, SegmentProviderFactory},
+    textfile::MetricsSnapshot,
+    tuning::{benchmark_compression, sample_rows},
+    warmup::{warmup, warmup_newest},
+    BatchHooks, BlockSource, CompressionReport, DataGap, DictionaryStore, DirectoryScan, DiskQuota,
+    EventReceiver, FailureKind, FileSizeEstimate, InMemorySink, MissingDataPolicy, NamingScheme,
+    OverflowPolicy, PauseHandle, PreallocationConfig, ProducerConfig, ProducerCoordinator,
+    ProducerHealth, PruneCheckpoint, PruneCheckpoints, RepairMirror, RetentionOutcome,
+    RetentionPolicy, RetentionService, RowTransforms, RunRecord, RunTimings, ScanIssue, ScanPolicy,
+    SealHooks, SealedFile, SegmentProgress, SegmentsConfig, ShardAssignment, ShardManifest,
+    ShardProducer, StallWatchdog, StaticFileChainSpec, StaticFileEntry, StaticFileEventSender,
+    StaticFileManifest, StaticFileProducerError, StaticFileProducerEvent, StaticFileWatcher,
+    TieringOutcome, TieringPolicy, TieringService, TrickleScheduler, TrustedCheckpoint,
+    WarmedFiles, WarmupConfig, WarmupMode, WorkersConfig,
 };
+use alloy_primitives::BlockNumber;
+use parking_lot::{Mutex, RwLock};
+use reth_provider::{providers::StaticFileWriter, TransactionsProviderExt};
 use reth_prune_types::PruneModes;
 use reth_stages_types::StageId;
-use reth_static_file_types::HighestStaticFiles;
-use reth_storage_errors::provider::ProviderResult;
-use reth_tokio_util::{EventSender, EventStream};
+use reth_static_file_types::{
+    Compression, FilterHash, FilterKey, Filters, HighestStaticFiles, LowestStaticFiles,
+    SegmentConfig, SegmentRangeInclusive, StaticFileSegment,
+};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use reth_tokio_util::EventStream;
+use serde::{Deserialize, Serialize};
 use std::{
+    io,
     ops::{Deref, RangeInclusive},
-    sync::Arc,
-    time::Instant,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, RecvTimeoutError},
+        Arc,
+    },
+    thread::Scope,
+    time::{Instant, SystemTime},
 };
-use tracing::{debug, trace};
+use tracing::{debug, debug_span, info, trace, warn, Span};
 
 /// Result of [`StaticFileProducerInner::run`] execution.
 pub type StaticFileProducerResult = ProviderResult<StaticFileTargets>;
 
 /// The [`StaticFileProducer`] instance itself with the result of [`StaticFileProducerInner::run`]
-pub type StaticFileProducerWithResult<DB> = (StaticFileProducer<DB>, StaticFileProducerResult);
+pub type StaticFileProducerWithResult<F> = (StaticFileProducer<F>, StaticFileProducerResult);
 
 /// Static File producer. It's a wrapper around [`StaticFileProducer`] that allows to share it
 /// between threads.
 #[derive(Debug, Clone)]
-pub struct StaticFileProducer<DB>(Arc<Mutex<StaticFileProducerInner<DB>>>);
+pub struct StaticFileProducer<F: SegmentProviderFactory>(Arc<Mutex<StaticFileProducerInner<F>>>);
 
-impl<DB: Database> StaticFileProducer<DB> {
+impl<F: SegmentProviderFactory> StaticFileProducer<F> {
     /// Creates a new [`StaticFileProducer`].
-    pub fn new(provider_factory: ProviderFactory<DB>, prune_modes: PruneModes) -> Self {
+    pub fn new(provider_factory: F, prune_modes: PruneModes) -> Self {
         Self(Arc::new(Mutex::new(StaticFileProducerInner::new(provider_factory, prune_modes))))
     }
 }
 
-impl<DB> Deref for StaticFileProducer<DB> {
-    type Target = Arc<Mutex<StaticFileProducerInner<DB>>>;
+impl<F: SegmentProviderFactory> Deref for StaticFileProducer<F> {
+    type Target = Arc<Mutex<StaticFileProducerInner<F>>>;
 
     fn deref(&self) -> &Self::Target {
         &self.0
@@ -50,15 +90,141 @@ impl<DB> Deref for StaticFileProducer<DB> {
 /// Static File producer routine. See [`StaticFileProducerInner::run`] for more detailed
 /// description.
 #[derive(Debug)]
-pub struct StaticFileProducerInner<DB> {
+pub struct StaticFileProducerInner<F: SegmentProviderFactory> {
     /// Provider factory
-    provider_factory: ProviderFactory<DB>,
+    provider_factory: F,
     /// Pruning configuration for every part of the data that can be pruned. Set by user, and
     /// needed in [`StaticFileProducerInner`] to prevent attempting to move prunable data to static
     /// files. See [`StaticFileProducerInner::get_static_file_targets`].
     prune_modes: PruneModes,
     /// Event sender to notify about the progress and state of the static file production
-    event_sender: EventSender<StaticFileProducerEvent>,
+    event_sender: StaticFileEventSender,
+    /// Watchdog detecting segments that make no progress during
+    /// [`StaticFileProducerInner::run`]. Disabled by default.
+    watchdog: Option<StallWatchdog>,
+    /// Order in which segments are copied during [`StaticFileProducerInner::run`].
+    run_order: RunOrder,
+    /// Hooks notified about copied batches, and pausing the run.
+    batch_hooks: BatchHooks,
+    /// Retention service applied after every [`StaticFileProducerInner::run`]. Disabled by
+    /// default.
+    retention: Option<RetentionService>,
+    /// Tiering service applied after every [`StaticFileProducerInner::run`]. Disabled by default.
+    tiering: Option<TieringService>,
+    /// Lowest static file blocks, lazily loaded from the static files directory on first access
+    /// and kept up to date by [`StaticFileProducerInner::run`] and retention afterwards.
+    lowest_static_files: RwLock<Option<LowestStaticFiles>>,
+    /// Whether epoch roots of the pre-merge header accumulator are recorded after every
+    /// [`StaticFileProducerInner::run`]. Disabled by default.
+    epoch_accumulator: bool,
+    /// Whether the receipt log index sidecar is built while copying receipts. Disabled by
+    /// default.
+    receipt_log_index: bool,
+    /// Whether the gas usage of copied receipts is tallied in the headers of their static files.
+    /// Disabled by default.
+    receipt_stats: bool,
+    /// Whether the Merkle root over the rows of static files is recorded in their headers when
+    /// they're sealed. Disabled by default.
+    row_commitments: bool,
+    /// Whether the sizes of the values of copied rows are tallied per column in the headers of
+    /// their static files. Disabled by default.
+    size_histograms: bool,
+    /// Whether the transaction sender index sidecar is built while copying transactions.
+    /// Disabled by default.
+    sender_index: bool,
+    /// Whether the zstd dictionaries of static files are stored in the shared
+    /// [`DictionaryStore`] and referenced by their headers when they're sealed. Disabled by
+    /// default.
+    shared_dictionaries: bool,
+    /// Whether copied transactions are tallied per type in the headers of their static files.
+    /// Disabled by default.
+    transaction_tx_type_stats: bool,
+    /// Number of copied blocks after which every segment commits to static files during
+    /// [`StaticFileProducerInner::run`]. `None` commits only at the end of the run.
+    commit_interval_blocks: Option<u64>,
+    /// Hooks notified about static files sealed by [`StaticFileProducerInner::run`].
+    seal_hooks: SealHooks,
+    /// Watcher of external changes to the static files directory. Disabled by default.
+    watcher: Option<StaticFileWatcher>,
+    /// Disk quota checked before every [`StaticFileProducerInner::run`]. Disabled by default.
+    disk_quota: Option<DiskQuota>,
+    /// Whether to collect [`RunTimings`] for every [`StaticFileProducerInner::run`]. Disabled by
+    /// default.
+    timing_summary: bool,
+    /// Number of most recent runs kept in the persisted run history. Disabled by default.
+    run_history: Option<usize>,
+    /// Directory the Prometheus textfile metrics snapshot is written to after every
+    /// [`StaticFileProducerInner::run`]. Disabled by default.
+    metrics_textfile_dir: Option<PathBuf>,
+    /// Instruments the run metrics are recorded with after every
+    /// [`StaticFileProducerInner::run`]. Disabled by default.
+    #[cfg(feature = "opentelemetry")]
+    otel_metrics: Option<crate::otel::OtelMetrics>,
+    /// Outcomes of the runs, shared with the [`ProducerHealth`] handles.
+    run_outcomes: RunOutcomes,
+    /// Service fetching quarantined static files from a mirror in
+    /// [`StaticFileProducerInner::repair_quarantined`]. Disabled by default.
+    repair: Option<RepairService>,
+    /// Configuration of every segment. Every segment is enabled with its default configuration
+    /// by default.
+    segments: SegmentsConfig,
+    /// Secret of the [`FilterHash::KeyedSipHash`] of the filter keys of the segments. Kept out of
+    /// the [`SegmentsConfig`], so it's never serialized.
+    filter_key: Option<FilterKey>,
+    /// Maximum number of blocks copied per second by every segment during
+    /// [`StaticFileProducerInner::run`]. Disabled by default.
+    throttle_blocks_per_second: Option<u64>,
+    /// Number of blocks after which segments renew their database read transaction during
+    /// [`StaticFileProducerInner::run`]. Disabled by default.
+    read_tx_renewal_blocks: Option<u64>,
+    /// Worker threads copying segments with [`RunOrder::Parallel`].
+    workers: WorkersConfig,
+    /// Preallocation of the files of the static files copied during
+    /// [`StaticFileProducerInner::run`]. Disabled by default.
+    preallocation: PreallocationConfig,
+    /// Behavior of segments when data of a block is missing from the database during
+    /// [`StaticFileProducerInner::run`]. Fails the run by default.
+    missing_data: MissingDataPolicy,
+    /// Chain static files are produced for. If `None`, the chain id isn't recorded or checked.
+    chain_spec: Option<Arc<dyn StaticFileChainSpec>>,
+    /// Set once every static file of the directory was checked to belong to the chain.
+    chain_checked: AtomicBool,
+    /// Transforms applied to the rows of every segment before they're appended. None by default.
+    row_transforms: RowTransforms,
+    /// Source of the blocks copied by [`StaticFileProducerInner::run`], with the checkpoint
+    /// they're chained to. If `None`, blocks are copied from the database.
+    block_source: Option<(Arc<dyn BlockSource>, TrustedCheckpoint)>,
+    /// Segments copying the rows of their static file segments instead of the built-in ones.
+    /// None by default.
+    custom_segments: CustomSegments<F::Provider>,
+    /// Attachment to the [`ProducerCoordinator`] shared with the producers of other networks.
+    coordinator: Option<CoordinatorMembership>,
+    /// Warmup of the newest static files by [`StaticFileProducerInner::recover_tails`] on
+    /// startup. Disabled by default.
+    warmup: Option<WarmupConfig>,
+    /// Static files warmed up on startup, holding their locked regions.
+    warmed_files: Mutex<Option<WarmedFiles>>,
+}
+
+/// Order in which segments are copied to static files during [`StaticFileProducerInner::run`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOrder {
+    /// All segments are copied in parallel.
+    #[default]
+    Parallel,
+    /// Segments are copied one after another, in the provided order. Segments that are not
+    /// listed are copied last, in the default order.
+    ///
+    /// For example, putting [`StaticFileSegment::Headers`] first allows header pruning to proceed
+    /// before the other segments are done.
+    Sequential(Vec<StaticFileSegment>),
+    /// Segments are copied one after another, in round-robin chunks of `chunk` blocks, so all
+    /// segments advance at a similar pace.
+    Interleaved {
+        /// Number of blocks copied from one segment before switching to the next one.
+        chunk: u64,
+    },
 }
 
 /// Static File targets, per data segment, measured in [`BlockNumber`].
@@ -73,6 +239,55 @@ pub struct StaticFileTargets {
 }
 
 impl StaticFileTargets {
+    /// Returns a [`StaticFileTargetsBuilder`] that validates targets against the provided
+    /// highest static files.
+    pub const fn builder(highest_static_files: HighestStaticFiles) -> StaticFileTargetsBuilder {
+        StaticFileTargetsBuilder {
+            highest_static_files,
+            headers: None,
+            receipts: None,
+            transactions: None,
+        }
+    }
+
+    /// Returns the target block range for a given segment, if it exists.
+    pub const fn target(&self, segment: StaticFileSegment) -> Option<&RangeInclusive<BlockNumber>> {
+        match segment {
+            StaticFileSegment::Headers => self.headers.as_ref(),
+            StaticFileSegment::Transactions => self.transactions.as_ref(),
+            StaticFileSegment::Receipts => self.receipts.as_ref(),
+        }
+    }
+
+    /// Returns the transaction ranges of the targets of the segments keyed by transaction
+    /// number, derived from their block ranges with the provider, e.g. to correlate a run with
+    /// tooling reporting transaction numbers. Targets whose blocks have no transactions are
+    /// skipped.
+    pub fn tx_ranges(
+        &self,
+        provider: &impl TransactionsProviderExt,
+    ) -> ProviderResult<Vec<(StaticFileSegment, SegmentRangeInclusive)>> {
+        let mut tx_ranges = Vec::new();
+        for segment in [StaticFileSegment::Transactions, StaticFileSegment::Receipts] {
+            let Some(block_range) = self.target(segment) else { continue };
+            let tx_range = provider.transaction_range_by_block_range(block_range.clone())?;
+            if let Ok(tx_range) = SegmentRangeInclusive::try_new(*tx_range.start(), *tx_range.end())
+            {
+                tx_ranges.push((segment, tx_range));
+            }
+        }
+        Ok(tx_ranges)
+    }
+
+    /// Returns a mutable reference to the target block range for a given segment.
+    fn as_mut(&mut self, segment: StaticFileSegment) -> &mut Option<RangeInclusive<BlockNumber>> {
+        match segment {
+            StaticFileSegment::Headers => &mut self.headers,
+            StaticFileSegment::Transactions => &mut self.transactions,
+            StaticFileSegment::Receipts => &mut self.receipts,
+        }
+    }
+
     /// Returns `true` if any of the targets are [Some].
     pub const fn any(&self) -> bool {
         self.headers.is_some() || self.receipts.is_some() || self.transactions.is_some()
@@ -98,321 +313,3505 @@ impl StaticFileTargets {
     }
 }
 
-impl<DB: Database> StaticFileProducerInner<DB> {
+/// Builder for [`StaticFileTargets`], validated against the current [`HighestStaticFiles`].
+///
+/// Each segment can be given either a desired tip, in which case the target range starts right
+/// after the highest static file block, or an explicit block range. Targets that would go
+/// backwards or leave a gap after the highest static file block are rejected on
+/// [`StaticFileTargetsBuilder::build`], instead of failing in the middle of
+/// [`StaticFileProducerInner::run`].
+#[derive(Debug, Clone)]
+pub struct StaticFileTargetsBuilder {
+    /// Highest static file blocks the targets are validated against.
+    highest_static_files: HighestStaticFiles,
+    /// Requested target for headers segment
+    headers: Option<TargetRequest>,
+    /// Requested target for receipts segment
+    receipts: Option<TargetRequest>,
+    /// Requested target for transactions segment
+    transactions: Option<TargetRequest>,
+}
+
+/// Target requested for a single segment in [`StaticFileTargetsBuilder`].
+#[derive(Debug, Clone)]
+enum TargetRequest {
+    /// Produce everything up to and including this block.
+    Tip(BlockNumber),
+    /// Produce exactly this block range.
+    Range(RangeInclusive<BlockNumber>),
+}
+
+impl StaticFileTargetsBuilder {
+    /// Sets the tip up to which the segment should be moved to static files.
+    pub fn tip(mut self, segment: StaticFileSegment, tip: BlockNumber) -> Self {
+        *self.request_mut(segment) = Some(TargetRequest::Tip(tip));
+        self
+    }
+
+    /// Sets the exact block range of the segment that should be moved to static files.
+    pub fn range(mut self, segment: StaticFileSegment, range: RangeInclusive<BlockNumber>) -> Self {
+        *self.request_mut(segment) = Some(TargetRequest::Range(range));
+        self
+    }
+
+    /// Validates the requested targets and returns normalized [`StaticFileTargets`].
+    ///
+    /// A tip equal to the highest static file block results in no target for the segment.
+    pub fn build(self) -> Result<StaticFileTargets, StaticFileTargetsError> {
+        let highest = self.highest_static_files;
+        Ok(StaticFileTargets {
+            headers: Self::validate(StaticFileSegment::Headers, highest, self.headers)?,
+            receipts: Self::validate(StaticFileSegment::Receipts, highest, self.receipts)?,
+            transactions: Self::validate(
+                StaticFileSegment::Transactions,
+                highest,
+                self.transactions,
+            )?,
+        })
+    }
+
+    fn request_mut(&mut self, segment: StaticFileSegment) -> &mut Option<TargetRequest> {
+        match segment {
+            StaticFileSegment::Headers => &mut self.headers,
+            StaticFileSegment::Transactions => &mut self.transactions,
+            StaticFileSegment::Receipts => &mut self.receipts,
+        }
+    }
+
+    fn validate(
+        segment: StaticFileSegment,
+        highest_static_files: HighestStaticFiles,
+        request: Option<TargetRequest>,
+    ) -> Result<Option<RangeInclusive<BlockNumber>>, StaticFileTargetsError> {
+        let highest = highest_static_files.highest(segment);
+        let expected_start = highest.map_or(0, |block| block + 1);
+
+        match request {
+            None => Ok(None),
+            Some(TargetRequest::Tip(tip)) => match highest {
+                Some(highest) if tip < highest => {
+                    Err(StaticFileTargetsError::BelowHighest { segment, highest, block: tip })
+                }
+                Some(highest) if tip == highest => Ok(None),
+                _ => Ok(Some(expected_start..=tip)),
+            },
+            Some(TargetRequest::Range(range)) => {
+                let (start, end) = (*range.start(), *range.end());
+                if start > end {
+                    return Err(StaticFileTargetsError::InvalidRange { segment, start, end })
+                }
+                if let Some(highest) = highest.filter(|highest| start <= *highest) {
+                    return Err(StaticFileTargetsError::BelowHighest {
+                        segment,
+                        highest,
+                        block: start,
+                    })
+                }
+                if start != expected_start {
+                    return Err(StaticFileTargetsError::Gap { segment, expected_start, start })
+                }
+                Ok(Some(range))
+            }
+        }
+    }
+}
+
+/// Error returned by [`StaticFileTargetsBuilder::build`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StaticFileTargetsError {
+    /// Target goes backwards, below the highest block already in static files. Sealed static
+    /// files can be reproduced with [`StaticFileProducerInner::force_rewrite`] instead.
+    BelowHighest {
+        /// Segment of the target.
+        segment: StaticFileSegment,
+        /// Highest block of the segment already in static files.
+        highest: BlockNumber,
+        /// Requested tip or range start.
+        block: BlockNumber,
+    },
+    /// Target range doesn't start right after the highest block already in static files.
+    Gap {
+        /// Segment of the target.
+        segment: StaticFileSegment,
+        /// Block the target range is expected to start at.
+        expected_start: BlockNumber,
+        /// Requested range start.
+        start: BlockNumber,
+    },
+    /// Target range has start greater than end.
+    InvalidRange {
+        /// Segment of the target.
+        segment: StaticFileSegment,
+        /// Requested range start.
+        start: BlockNumber,
+        /// Requested range end.
+        end: BlockNumber,
+    },
+}
+
+impl std::fmt::Display for StaticFileTargetsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BelowHighest { segment, highest, block } => write!(
+                f,
+                "{segment} target at block {block} is below highest static file block {highest}"
+            ),
+            Self::Gap { segment, expected_start, start } => write!(
+                f,
+                "{segment} target starts at block {start}, leaving a gap before expected start {expected_start}"
+            ),
+            Self::InvalidRange { segment, start, end } => {
+                write!(f, "{segment} target range {start}..={end} is inverted")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StaticFileTargetsError {}
+
+impl<F: SegmentProviderFactory> StaticFileProducerInner<F> {
     /// Creates a new instance of [`StaticFileProducerInner`].
-    fn new(provider_factory: ProviderFactory<DB>, prune_modes: PruneModes) -> Self {
-        Self { provider_factory, prune_modes, event_sender: Default::default() }
+    fn new(provider_factory: F, prune_modes: PruneModes) -> Self {
+        Self {
+            provider_factory,
+            prune_modes,
+            event_sender: Default::default(),
+            watchdog: None,
+            run_order: RunOrder::default(),
+            batch_hooks: BatchHooks::default(),
+            retention: None,
+            tiering: None,
+            lowest_static_files: RwLock::new(None),
+            epoch_accumulator: false,
+            receipt_log_index: false,
+            receipt_stats: false,
+            row_commitments: false,
+            size_histograms: false,
+            sender_index: false,
+            shared_dictionaries: false,
+            transaction_tx_type_stats: false,
+            commit_interval_blocks: None,
+            seal_hooks: SealHooks::default(),
+            watcher: None,
+            disk_quota: None,
+            timing_summary: false,
+            run_history: None,
+            metrics_textfile_dir: None,
+            #[cfg(feature = "opentelemetry")]
+            otel_metrics: None,
+            run_outcomes: RunOutcomes::default(),
+            repair: None,
+            segments: SegmentsConfig::default(),
+            filter_key: None,
+            throttle_blocks_per_second: None,
+            read_tx_renewal_blocks: None,
+            workers: WorkersConfig::default(),
+            preallocation: PreallocationConfig::default(),
+            missing_data: MissingDataPolicy::default(),
+            chain_spec: None,
+            chain_checked: AtomicBool::new(false),
+            row_transforms: RowTransforms::default(),
+            block_source: None,
+            custom_segments: CustomSegments::default(),
+            coordinator: None,
+            warmup: None,
+            warmed_files: Mutex::new(None),
+        }
+    }
+
+    /// Sets the [`StallWatchdog`] used during [`StaticFileProducerInner::run`]. `None` disables it.
+    pub fn set_watchdog(&mut self, watchdog: Option<StallWatchdog>) {
+        self.watchdog = watchdog;
+    }
+
+    /// Sets the [`RunOrder`] of segments during [`StaticFileProducerInner::run`].
+    pub fn set_run_order(&mut self, run_order: RunOrder) {
+        self.run_order = run_order;
+    }
+
+    /// Sets the [`BatchHooks`] notified about batches copied during
+    /// [`StaticFileProducerInner::run`].
+    ///
+    /// Replaces the [`PauseHandle`], so handles returned by
+    /// [`StaticFileProducerInner::pause_handle`] before have no effect anymore.
+    pub fn set_batch_hooks(&mut self, batch_hooks: BatchHooks) {
+        self.batch_hooks = batch_hooks;
+    }
+
+    /// Sets the [`RetentionPolicy`] applied after every [`StaticFileProducerInner::run`]. `None`
+    /// disables it.
+    pub fn set_retention(&mut self, retention: Option<RetentionPolicy>) {
+        self.retention = retention.map(RetentionService::new);
+    }
+
+    /// Sets the [`TieringPolicy`] applied after every [`StaticFileProducerInner::run`]. `None`
+    /// disables it, leaving cold static files where they are.
+    pub fn set_tiering(&mut self, tiering: Option<TieringPolicy>) {
+        self.tiering = tiering.map(TieringService::new);
+    }
+
+    /// Pins the block range to the static files directory, see [`TieringPolicy::pin`]. Without a
+    /// tiering policy, nothing is pinned.
+    pub fn pin(&mut self, range: RangeInclusive<BlockNumber>) {
+        if let Some(tiering) = &mut self.tiering {
+            tiering.pin(range);
+        }
+    }
+
+    /// Sets whether epoch roots of the pre-merge header accumulator are recorded in a sidecar
+    /// after every [`StaticFileProducerInner::run`], so headers can be proven with
+    /// [`StaticFileReader::prove_header`](crate::StaticFileReader::prove_header).
+    pub fn set_epoch_accumulator(&mut self, enabled: bool) {
+        self.epoch_accumulator = enabled;
+    }
+
+    /// Sets whether the [`LogIndex`](crate::LogIndex) sidecar is built while copying receipts, so
+    /// logs can be queried with
+    /// [`StaticFileReader::logs_by_address`](crate::StaticFileReader::logs_by_address).
+    pub fn set_receipt_log_index(&mut self, enabled: bool) {
+        self.receipt_log_index = enabled;
+    }
+
+    /// Sets whether the gas used, logs and contract creations of copied receipts are tallied into
+    /// the [`ReceiptStats`](reth_static_file_types::ReceiptStats) of the headers of their static
+    /// files, for chain analytics without decoding the rows.
+    ///
+    /// Static files that already have rows without aggregates get none, as they wouldn't cover
+    /// all of their rows.
+    pub fn set_receipt_stats(&mut self, enabled: bool) {
+        self.receipt_stats = enabled;
+    }
+
+    /// Sets whether the Merkle root over the rows of static files is computed when they're
+    /// sealed and recorded in their headers, so their rows can be served with inclusion proofs,
+    /// see [`RowCommitment`](crate::RowCommitment). Static files of
+    /// [shards](StaticFileProducerInner::produce_shard) get it as well.
+    ///
+    /// Computing the root reads back every row of the sealed static file.
+    pub fn set_row_commitments(&mut self, enabled: bool) {
+        self.row_commitments = enabled;
+    }
+
+    /// Sets whether the sizes of the values of copied rows are tallied per column into the
+    /// [`SizeHistogram`](reth_static_file_types::SizeHistogram)s of the headers of their static
+    /// files, reporting their median, 90th and 99th percentiles and maximum for compression
+    /// tuning, and revealing outliers such as a single huge receipt.
+    ///
+    /// Static files that already have rows without histograms get none, as they wouldn't cover
+    /// all of their rows.
+    pub fn set_size_histograms(&mut self, enabled: bool) {
+        self.size_histograms = enabled;
+    }
+
+    /// Sets whether the [`SenderIndex`](crate::SenderIndex) sidecar is built while copying
+    /// transactions, so transactions can be queried with
+    /// [`StaticFileReader::transactions_by_sender`](crate::StaticFileReader::transactions_by_sender).
+    pub fn set_sender_index(&mut self, enabled: bool) {
+        self.sender_index = enabled;
+    }
+
+    /// Sets whether the zstd dictionaries of static files compressed with
+    /// [`Compression::ZstdWithDictionary`](reth_static_file_types::Compression::ZstdWithDictionary)
+    /// are stored in the shared [`DictionaryStore`] when they're sealed, and referenced by id in
+    /// their headers, so manifests list every dictionary once.
+    pub fn set_shared_dictionaries(&mut self, enabled: bool) {
+        self.shared_dictionaries = enabled;
+    }
+
+    /// Sets whether the number and size of copied transactions are tallied per transaction type
+    /// into the [`TxTypeStats`](reth_static_file_types::TxTypeStats) of the headers of their
+    /// static files, so aggregates are available without decoding the rows.
+    ///
+    /// Static files that already have rows without statistics get none, as they wouldn't cover
+    /// all of their rows.
+    pub fn set_transaction_tx_type_stats(&mut self, enabled: bool) {
+        self.transaction_tx_type_stats = enabled;
+    }
+
+    /// Sets the chain static files are produced for, passed to segments to make fork-aware
+    /// decisions. Its chain id is recorded in every produced static file, and
+    /// [`StaticFileProducerInner::run`] refuses to run with
+    /// [`StaticFileProducerError::ChainMismatch`] if the directory holds static files of another
+    /// chain. `None` disables both.
+    pub fn set_chain_spec(&mut self, chain_spec: Option<Arc<dyn StaticFileChainSpec>>) {
+        self.chain_spec = chain_spec;
+        *self.chain_checked.get_mut() = false;
+    }
+
+    /// Sets the [`RowTransforms`] applied to the rows of every segment before they're appended,
+    /// e.g. to redact data of a private chain. The same transforms have to be set on the
+    /// [`StaticFileReader`](crate::StaticFileReader) for the rows to be inverted when read.
+    pub fn set_row_transforms(&mut self, row_transforms: RowTransforms) {
+        self.row_transforms = row_transforms;
+    }
+
+    /// Sets the source of the blocks copied by [`StaticFileProducerInner::run`], so static files
+    /// can be produced on a machine that never ran a full sync. Targets have to be passed
+    /// explicitly, as the database has no stage checkpoints to derive them from. `None` copies
+    /// blocks from the database.
+    ///
+    /// The source isn't trusted: copied blocks have to chain to the static files before them, or
+    /// to the [`TrustedCheckpoint`], e.g. the genesis block of the chain.
+    pub fn set_block_source(
+        &mut self,
+        block_source: Option<(Arc<dyn BlockSource>, TrustedCheckpoint)>,
+    ) {
+        self.block_source = block_source;
+    }
+
+    /// Sets the segments copying the rows of their static file segments instead of the built-in
+    /// ones, e.g. to derive the rows from other tables. Takes precedence over the block source.
+    ///
+    /// Unlike the built-in segments, custom segments can skip blocks whose data is missing with
+    /// [`MissingDataPolicy::SkipMissing`], if their rows don't have to be contiguous.
+    pub fn set_custom_segments(&mut self, custom_segments: Vec<Arc<dyn Segment<F::Provider>>>) {
+        self.custom_segments = CustomSegments::new(custom_segments);
+    }
+
+    /// Sets the number of copied blocks after which every segment commits to static files during
+    /// [`StaticFileProducerInner::run`], emitting [`StaticFileProducerEvent::Committed`]. `None`
+    /// commits only at the end of the run.
+    ///
+    /// Smaller intervals lose less work if the run is interrupted, at the cost of more fsyncs.
+    pub fn set_commit_interval_blocks(&mut self, commit_interval_blocks: Option<u64>) {
+        self.commit_interval_blocks = commit_interval_blocks;
+    }
+
+    /// Sets the [`SealHooks`] notified about static files sealed by
+    /// [`StaticFileProducerInner::run`].
+    pub fn set_seal_hooks(&mut self, seal_hooks: SealHooks) {
+        self.seal_hooks = seal_hooks;
+    }
+
+    /// Sets whether the static files directory is watched for static files modified or removed
+    /// outside of the producer, emitting [`StaticFileProducerEvent::ExternalChange`] and marking
+    /// them dirty. See [`StaticFileProducerInner::take_dirty_static_files`].
+    ///
+    /// Changes made by the producer itself, during [`StaticFileProducerInner::run`] and
+    /// retention, are ignored.
+    pub fn set_watcher(&mut self, enabled: bool) -> Result<(), StaticFileProducerError> {
+        self.watcher = if enabled {
+            let event_sender = self.event_sender.clone();
+            Some(StaticFileWatcher::new(
+                self.provider_factory.static_file_provider().directory(),
+                move |event| event_sender.notify(event),
+            )?)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
+    /// Sets the [`DiskQuota`] checked before every [`StaticFileProducerInner::run`]. `None`
+    /// disables it.
+    ///
+    /// Runs that would exceed the quota are refused with
+    /// [`StaticFileProducerError::QuotaExceeded`], before anything is written.
+    pub fn set_disk_quota(&mut self, disk_quota: Option<DiskQuota>) {
+        self.disk_quota = disk_quota;
+    }
+
+    /// Sets whether to collect a [`RunTimings`] summary for every
+    /// [`StaticFileProducerInner::run`]. The summary is logged and attached to the
+    /// [`StaticFileProducerEvent::Finished`] event.
+    pub fn set_timing_summary(&mut self, timing_summary: bool) {
+        self.timing_summary = timing_summary;
+    }
+
+    /// Sets the number of most recent runs kept in the run history persisted in the static files
+    /// directory, see [`StaticFileProducerInner::run_history`]. `None` disables the history.
+    pub fn set_run_history(&mut self, runs: Option<usize>) {
+        self.run_history = runs;
+    }
+
+    /// Sets the directory a [`MetricsSnapshot`] is written to as a Prometheus textfile, named
+    /// [`METRICS_TEXTFILE_NAME`](crate::METRICS_TEXTFILE_NAME), after every
+    /// [`StaticFileProducerInner::run`], e.g. the directory of the textfile collector of
+    /// node_exporter. `None` disables the textfile.
+    pub fn set_metrics_textfile_dir(&mut self, directory: Option<PathBuf>) {
+        self.metrics_textfile_dir = directory;
+    }
+
+    /// Sets the OpenTelemetry instruments the outcome, duration and produced blocks of every
+    /// [`StaticFileProducerInner::run`] are recorded with. `None` disables them.
+    ///
+    /// Spans of the runs are exported separately, with
+    /// [`otel::tracing_layer`](crate::otel::tracing_layer).
+    #[cfg(feature = "opentelemetry")]
+    pub fn set_otel_metrics(&mut self, otel_metrics: Option<crate::otel::OtelMetrics>) {
+        self.otel_metrics = otel_metrics;
+    }
+
+    /// Returns the persisted history of the most recent runs, oldest first, with their targets,
+    /// durations and errors. Returns the whole persisted history if it's disabled.
+    pub fn run_history(&self) -> Result<Vec<RunRecord>, StaticFileProducerError> {
+        let mut records =
+            read_run_history(self.provider_factory.static_file_provider().directory())?;
+        if let Some(runs) = self.run_history {
+            records.drain(..records.len().saturating_sub(runs));
+        }
+        Ok(records)
+    }
+
+    /// Sets the [`RepairMirror`] that static files quarantined by the [doctor](crate::doctor) are
+    /// fetched from by [`StaticFileProducerInner::repair_quarantined`]. `None` disables repairs.
+    pub fn set_repair_mirror(&mut self, repair_mirror: Option<RepairMirror>) {
+        self.repair = repair_mirror.map(RepairService::new);
+    }
+
+    /// Sets the maximum number of blocks copied per second by every segment during
+    /// [`StaticFileProducerInner::run`]. `None` disables the throttle.
+    pub fn set_throttle_blocks_per_second(&mut self, throttle_blocks_per_second: Option<u64>) {
+        self.throttle_blocks_per_second = throttle_blocks_per_second;
+    }
+
+    /// Sets the number of blocks after which segments renew their database read transaction
+    /// during [`StaticFileProducerInner::run`], so copying large block ranges doesn't block page
+    /// reclamation of the database. `None` keeps one transaction per segment.
+    pub fn set_read_tx_renewal_blocks(&mut self, read_tx_renewal_blocks: Option<u64>) {
+        self.read_tx_renewal_blocks = read_tx_renewal_blocks;
+    }
+
+    /// Sets the [`SegmentsConfig`], enabling or disabling production of every segment.
+    ///
+    /// Disabled segments get no targets from [`StaticFileProducerInner::get_static_file_targets`],
+    /// and [`StaticFileProducerInner::run`] refuses targets for them.
+    pub fn set_segments(&mut self, segments: SegmentsConfig) {
+        self.segments = segments;
+    }
+
+    /// Sets the secret of the [`FilterHash::KeyedSipHash`] of the filter keys, required by
+    /// segments configured with it. The secret is never written to static files or configuration
+    /// files, so readers are supplied it separately, see
+    /// [`StaticFileReader::set_filter_key`](crate::StaticFileReader::set_filter_key).
+    pub fn set_filter_key(&mut self, filter_key: Option<FilterKey>) {
+        self.filter_key = filter_key;
+    }
+
+    /// Returns the [`SegmentConfig`] static files of the segment are created with, holding the
+    /// secret of its filter hash.
+    pub(crate) fn segment_config(&self, segment: StaticFileSegment) -> SegmentConfig {
+        SegmentConfig { filter_key: self.filter_key, ..self.segments.get(segment).config() }
+    }
+
+    /// Benchmarks every [`Compression`] on the rows of the segment for the sample block range,
+    /// read from the database, reporting their ratio and speed.
+    pub fn auto_tune(
+        &self,
+        segment: StaticFileSegment,
+        sample_range: RangeInclusive<BlockNumber>,
+    ) -> ProviderResult<CompressionReport> {
+        let provider = self.provider_factory.database_provider_ro()?;
+        let rows = sample_rows(&provider, segment, sample_range)?;
+        Ok(benchmark_compression(segment, &rows))
+    }
+
+    /// Sets the compression of the segment of the report to its best candidate, see
+    /// [`CompressionReport::best`], returning it. Leaves the compression as is if no candidate
+    /// decompresses at least `min_decompress_speed` bytes per second.
+    pub fn apply_auto_tune(
+        &mut self,
+        report: &CompressionReport,
+        min_decompress_speed: f64,
+    ) -> Option<Compression> {
+        let compression = report.best(min_decompress_speed)?.compression;
+        debug!(target: "static_file", segment = %report.segment, ?compression, "Auto-tuned compression");
+        self.segments.get_mut(report.segment).compression = compression;
+        Some(compression)
+    }
+
+    /// Sets the [`WorkersConfig`], naming and pinning the worker threads copying segments with
+    /// [`RunOrder::Parallel`], and bounding the threads building filters.
+    pub fn set_workers(&mut self, workers: WorkersConfig) {
+        self.workers = workers;
+    }
+
+    /// Sets the [`PreallocationConfig`], preallocating the data and offsets files of every static
+    /// file started by [`StaticFileProducerInner::run`] to the size estimated from the sealed
+    /// static files of its segment, and releasing the space left over once it's sealed.
+    pub fn set_preallocation(&mut self, preallocation: PreallocationConfig) {
+        self.preallocation = preallocation;
+    }
+
+    /// Sets the [`MissingDataPolicy`] of segments, when data of a block in the middle of their
+    /// target range is missing from the database, e.g. partially pruned. Fails the run by
+    /// default.
+    ///
+    /// Segments that stop at a gap are committed up to the block before it, and their produced
+    /// target is truncated like with [`StaticFileProducerInner::run_until`]. Custom segments that
+    /// skip a block keep their whole target. Blocks that were stopped at or skipped are reported
+    /// in the [`StaticFileProducerEvent::Finished`] event and the run history.
+    pub fn set_missing_data_policy(&mut self, missing_data: MissingDataPolicy) {
+        self.missing_data = missing_data;
+    }
+
+    /// Attaches the producer to the [`ProducerCoordinator`] shared with the producers of other
+    /// networks in the process, or detaches it with `None`. Disabled by default.
+    ///
+    /// Attached producers build filters on the shared thread pool, copy segments within the
+    /// global IO budget, and commit, rebuild filters and rewrite static files one at a time.
+    /// Fails with [`StaticFileProducerError::DirectoryInUse`] if a producer of the same static
+    /// files directory is already attached. Producers are detached once dropped.
+    pub fn set_coordinator(
+        &mut self,
+        coordinator: Option<ProducerCoordinator>,
+    ) -> Result<(), StaticFileProducerError> {
+        // Detached first, so the producer can be attached to the same coordinator again
+        self.coordinator = None;
+        if let Some(coordinator) = coordinator {
+            let directory = self.provider_factory.static_file_provider().directory().to_path_buf();
+            self.coordinator = Some(
+                coordinator
+                    .attach(&directory)
+                    .ok_or(StaticFileProducerError::DirectoryInUse(directory))?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Sets the [`WarmupConfig`], warming up the offsets and filters of the newest static files
+    /// of every segment the first time [`StaticFileProducerInner::recover_tails`] is called, so
+    /// the first reads after a restart don't fault them in one page at a time. Files warmed up
+    /// before are released.
+    pub fn set_warmup(&mut self, warmup: Option<WarmupConfig>) {
+        self.warmup = warmup;
+        *self.warmed_files.get_mut() = None;
+    }
+
+    /// Warms up the offsets and filters of the static files of the segment overlapping the block
+    /// range, with the [`WarmupMode`] of the [`WarmupConfig`], touching them by default. Locked
+    /// files stay locked until the returned [`WarmedFiles`] are dropped.
+    pub fn warmup(
+        &self,
+        segment: StaticFileSegment,
+        range: RangeInclusive<BlockNumber>,
+    ) -> Result<WarmedFiles, StaticFileProducerError> {
+        let mode = self.warmup.map(|warmup| warmup.mode).unwrap_or_default();
+        Ok(warmup(self.provider_factory.static_file_provider().directory(), segment, range, mode)?)
+    }
+
+    /// Applies the [`ProducerConfig`], replacing the segments configuration, run order, throttle,
+    /// read transaction renewal, retention policy, worker threads and preallocation.
+    ///
+    /// The producer is locked during [`StaticFileProducerInner::run`], so the configuration
+    /// takes effect from the next run.
+    pub fn reload(&mut self, config: ProducerConfig) {
+        let ProducerConfig {
+            segments,
+            run_order,
+            throttle_blocks_per_second,
+            read_tx_renewal_blocks,
+            retention,
+            workers,
+            preallocation,
+        } = config;
+        debug!(
+            target: "static_file",
+            ?segments,
+            ?run_order,
+            ?throttle_blocks_per_second,
+            ?read_tx_renewal_blocks,
+            ?retention,
+            ?workers,
+            ?preallocation,
+            "Reloading configuration"
+        );
+
+        self.segments = segments;
+        self.run_order = run_order;
+        self.throttle_blocks_per_second = throttle_blocks_per_second;
+        self.read_tx_renewal_blocks = read_tx_renewal_blocks;
+        self.set_retention(retention);
+        self.workers = workers;
+        self.preallocation = preallocation;
+    }
+
+    /// Returns the current [`ProducerConfig`], e.g. to persist it.
+    pub fn config(&self) -> ProducerConfig {
+        ProducerConfig {
+            segments: self.segments.clone(),
+            run_order: self.run_order.clone(),
+            throttle_blocks_per_second: self.throttle_blocks_per_second,
+            read_tx_renewal_blocks: self.read_tx_renewal_blocks,
+            retention: self.retention.as_ref().map(|retention| retention.policy().clone()),
+            workers: self.workers.clone(),
+            preallocation: self.preallocation,
+        }
+    }
+
+    /// Returns the static files modified or removed outside of the producer since the last call,
+    /// so they can be checked for consistency. Always empty if the watcher is disabled.
+    pub fn take_dirty_static_files(&self) -> Vec<StaticFileEntry> {
+        self.watcher.as_ref().map(StaticFileWatcher::take_dirty).unwrap_or_default()
+    }
+
+    /// Returns the [`ProducerHealth`] handle evaluating the lag of the enabled segments and the
+    /// outcome of the last [`StaticFileProducerInner::run`], even while the producer is locked
+    /// by the running thread.
+    pub fn health(&self) -> ProducerHealth {
+        let segments = [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ]
+        .into_iter()
+        .filter(|segment| self.segments.is_enabled(*segment))
+        .collect();
+        ProducerHealth::new(
+            self.provider_factory.static_file_provider(),
+            segments,
+            self.run_outcomes.clone(),
+        )
+    }
+
+    /// Returns the provider factory of the database and static files.
+    pub(crate) const fn provider_factory(&self) -> &F {
+        &self.provider_factory
+    }
+
+    /// Returns `true` if the segment is produced.
+    pub(crate) const fn is_enabled(&self, segment: StaticFileSegment) -> bool {
+        self.segments.is_enabled(segment)
+    }
+
+    /// Returns `true` if the Merkle root over the rows of static files is recorded in their
+    /// headers when they're sealed.
+    pub(crate) const fn row_commitments(&self) -> bool {
+        self.row_commitments
+    }
+
+    /// Returns the [`PauseHandle`] pausing and resuming [`StaticFileProducerInner::run`] at block
+    /// boundaries, even while the producer is locked by the running thread.
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.batch_hooks.pause_handle()
     }
 
     /// Listen for events on the `static_file_producer`.
+    ///
+    /// Listeners lagging behind skip events silently, see
+    /// [`StaticFileProducerInner::subscribe_events`] for a subscriber told about lost events.
     pub fn events(&self) -> EventStream<StaticFileProducerEvent> {
         self.event_sender.new_listener()
     }
 
+    /// Subscribes to events on the `static_file_producer` with a queue of up to `capacity`
+    /// events. A slow subscriber never blocks the producer: when its queue is full, events are
+    /// dropped according to the policy and reported with [`StaticFileProducerEvent::Lost`].
+    pub fn subscribe_events(&self, capacity: usize, policy: OverflowPolicy) -> EventReceiver {
+        self.event_sender.subscribe(capacity, policy)
+    }
+
     /// Run the `static_file_producer`.
     ///
     /// For each [Some] target in [`StaticFileTargets`], initializes a corresponding [Segment] and
     /// runs it with the provided block range using [`reth_provider::providers::StaticFileProvider`]
-    /// and a read-only database transaction from [`ProviderFactory`]. All segments are run in
-    /// parallel.
+    /// and a read-only provider from the [`SegmentProviderFactory`]. Segments are run
+    /// according to the configured [`RunOrder`], in parallel by default.
+    ///
+    /// If a segment fails in the middle of a write, the static files and writers of all segments
+    /// are rolled back to their last commit, and a [`StaticFileProducerEvent::Failed`] event is
+    /// emitted, with [`FailureKind::DiskFull`] if the disk filled up. The next run produces the
+    /// rolled back blocks again, e.g. once space is freed.
+    ///
+    /// Errors of the producer itself, e.g. a segment cancelled by the [`StallWatchdog`], are
+    /// returned as [`ProviderError::NippyJar`] with their message. Use
+    /// [`StaticFileProducerInner::try_run`] to tell them apart.
     ///
     /// NOTE: it doesn't delete the data from database, and the actual deleting (aka pruning) logic
     /// lives in the `prune` crate.
     pub fn run(&self, targets: StaticFileTargets) -> StaticFileProducerResult {
+        Ok(self.try_run(targets)?)
+    }
+
+    /// Runs the `static_file_producer` like [`StaticFileProducerInner::run`], returning a
+    /// [`StaticFileProducerError`] that tells errors of the producer apart.
+    ///
+    /// If a [`StallWatchdog`] is set and cancels a stalled segment, the static files and writers of
+    /// all segments are rolled back to their last commit and
+    /// [`StaticFileProducerError::Stalled`] is returned. Blocks committed at the commit interval
+    /// before the stall are kept.
+    pub fn try_run(
+        &self,
+        targets: StaticFileTargets,
+    ) -> Result<StaticFileTargets, StaticFileProducerError> {
+        self.run_with_deadline(targets, None)
+    }
+
+    /// Runs the `static_file_producer` like [`StaticFileProducerInner::try_run`], producing as
+    /// much as possible until the wall-clock deadline, e.g. the end of a maintenance window.
+    ///
+    /// Once the deadline passes, every segment stops at the next block boundary, and the blocks
+    /// copied so far are committed. Returns the targets that were actually produced, truncated
+    /// to the last copied block of every segment, and `None` for segments that copied nothing.
+    /// The rest of the requested targets can be produced by a later run.
+    pub fn run_until(
+        &self,
+        targets: StaticFileTargets,
+        deadline: Instant,
+    ) -> Result<StaticFileTargets, StaticFileProducerError> {
+        self.run_with_deadline(targets, Some(deadline))
+    }
+
+    /// Runs the `static_file_producer`, stopping the segments at the deadline if any, and
+    /// records the outcome of the run, in the run history, the metrics textfile and the
+    /// OpenTelemetry metrics if they're enabled.
+    fn run_with_deadline(
+        &self,
+        targets: StaticFileTargets,
+        deadline: Option<Instant>,
+    ) -> Result<StaticFileTargets, StaticFileProducerError> {
         // If there are no targets, do not produce any static files and return early
         if !targets.any() {
             return Ok(targets)
         }
+        let started_at = SystemTime::now();
+        let start = Instant::now();
+        let result = self.produce(targets.clone(), deadline);
+        self.run_outcomes.record(result.is_ok());
+        let elapsed = start.elapsed();
+        let static_file_provider = self.provider_factory.static_file_provider();
+
+        // The run history is best-effort, failing to record it doesn't fail the run.
+        if let Some(runs) = self.run_history {
+            let record = RunRecord::new(
+                started_at,
+                &targets,
+                result
+                    .as_ref()
+                    .map(|(produced, timings, _)| (produced, timings.clone()))
+                    .map_err(ToString::to_string),
+                elapsed,
+            );
+            let record = match &result {
+                Ok((produced, _, gaps)) => record
+                    .with_produced_tx_ranges(self.target_tx_ranges(produced))
+                    .with_gaps(gaps.clone()),
+                Err(_) => record,
+            };
+            if let Err(err) = append_run_record(static_file_provider.directory(), &record, runs) {
+                warn!(target: "static_file", %err, "Failed to record the run history");
+            }
+        }
+
+        // So is the metrics textfile.
+        if let Some(textfile_dir) = &self.metrics_textfile_dir {
+            let written = list_static_files(static_file_provider.directory()).and_then(|entries| {
+                MetricsSnapshot::collect(
+                    &entries,
+                    &static_file_provider.get_highest_static_files(),
+                    result.as_ref().ok().map(|(produced, _, _)| produced),
+                    started_at,
+                    elapsed,
+                )?
+                .write(textfile_dir)
+            });
+            if let Err(err) = written {
+                warn!(target: "static_file", %err, "Failed to write the metrics textfile");
+            }
+        }
+
+        #[cfg(feature = "opentelemetry")]
+        if let Some(otel_metrics) = &self.otel_metrics {
+            otel_metrics.record_run(
+                result.as_ref().ok().map(|(produced, _, _)| produced),
+                elapsed,
+                &static_file_provider.get_highest_static_files(),
+            );
+        }
+        result.map(|(produced, _, _)| produced)
+    }
+
+    /// Produces the static files of the targets, stopping the segments at the deadline if any.
+    /// Returns the produced targets with the timing summary, if it's enabled, and the blocks
+    /// whose data was missing from the database.
+    fn produce(
+        &self,
+        targets: StaticFileTargets,
+        deadline: Option<Instant>,
+    ) -> Result<(StaticFileTargets, Option<RunTimings>, Vec<DataGap>), StaticFileProducerError>
+    {
+        // Restore static files left inconsistent by a crash in the middle of a commit.
+        self.recover_tails()?;
+        let highest_static_files =
+            self.provider_factory.static_file_provider().get_highest_static_files();
         // Ensure that the targets are contiguous to the highest static files.
         // This debug assertion helps catch logical errors during development.
-        debug_assert!(targets.is_contiguous_to_highest_static_files(
-            self.provider_factory.static_file_provider().get_highest_static_files()
-        ));
+        debug_assert!(targets.is_contiguous_to_highest_static_files(highest_static_files));
+
+        // Refuse to run if any of the targets belongs to a disabled segment.
+        self.ensure_enabled(&targets)?;
 
-        self.event_sender.notify(StaticFileProducerEvent::Started { targets: targets.clone() });
+        // Refuse to run if the directory holds static files of another chain.
+        self.ensure_chain()?;
+
+        // Refuse to run if the produced static files would exceed the disk quota.
+        if let Some(disk_quota) = &self.disk_quota {
+            self.check_disk_quota(disk_quota, &targets, highest_static_files)?;
+        }
+
+        let tx_ranges = self.target_tx_ranges(&targets);
+        self.event_sender.notify(StaticFileProducerEvent::Started {
+            targets: targets.clone(),
+            tx_ranges: tx_ranges.clone(),
+        });
+        let _span = debug_span!(target: "static_file", "run", ?targets, ?tx_ranges).entered();
+        // Static files are modified by the run itself.
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
         // Log debug information indicating that the StaticFileProducer has started,
         // including the targets.
         debug!(target: "static_file", ?targets, "StaticFileProducer started");
         let start = Instant::now();
         /// Initialize a vector to hold segments and their corresponding block ranges.
-        let mut segments = Vec::<(Box<dyn Segment<DB>>, RangeInclusive<BlockNumber>)>::new();
+        let mut segments =
+            Vec::<(Arc<dyn Segment<F::Provider>>, RangeInclusive<BlockNumber>)>::new();
         // If there is a range of blocks to process for transactions, add it to the segments vector.
         if let Some(block_range) = targets.transactions.clone() {
-            segments.push((Box::new(segments::Transactions), block_range));
+            segments.push((self.segment(StaticFileSegment::Transactions), block_range));
         }
         // If there is a range of blocks to process for headers, add it to the segments vector.
         if let Some(block_range) = targets.headers.clone() {
-            segments.push((Box::new(segments::Headers), block_range));
+            segments.push((self.segment(StaticFileSegment::Headers), block_range));
         }
         // If there is a range of blocks to process for receipts, add it to the segments vector.
         if let Some(block_range) = targets.receipts.clone() {
-            segments.push((Box::new(segments::Receipts), block_range));
+            segments.push((self.segment(StaticFileSegment::Receipts), block_range));
+        }
+        // Put prioritized segments first, keeping the default order for the rest.
+        if let RunOrder::Sequential(order) = &self.run_order {
+            segments.sort_by_key(|(segment, _)| {
+                order.iter().position(|s| *s == segment.segment()).unwrap_or(usize::MAX)
+            });
+        }
+
+        // Files of the static files are preallocated to the size of the sealed ones
+        let preallocation = segments
+            .iter()
+            .map(|(segment, _)| self.preallocation_estimate(segment.segment()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // Progress of every segment, watched by the watchdog if it's set.
+        let progress = segments
+            .iter()
+            .zip(preallocation)
+            .map(|((segment, _), preallocation)| {
+                SegmentProgress::new(segment.segment())
+                    .with_hooks(self.batch_hooks.clone())
+                    .with_commit_interval(self.commit_interval_blocks)
+                    .with_throttle(self.throttle_blocks_per_second)
+                    .with_read_tx_renewal(self.read_tx_renewal_blocks)
+                    .with_events(self.event_sender.clone())
+                    .with_chain_spec(self.chain_spec.clone())
+                    .with_row_transforms(self.row_transforms.clone())
+                    .with_preallocation(preallocation)
+                    .with_missing_data(self.missing_data)
+                    .with_size_histograms(self.size_histograms)
+                    .with_io_throttle(
+                        self.coordinator.as_ref().and_then(CoordinatorMembership::io_throttle),
+                    )
+                    .with_clock(
+                        self.watchdog
+                            .as_ref()
+                            .map(|watchdog| watchdog.clock.clone())
+                            .unwrap_or_default(),
+                    )
+            })
+            .collect::<Vec<_>>();
+        // Snapshot the static files of every segment, to roll back to if the disk fills up or
+        // the process crashes before the run finishes.
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let directory = static_file_provider.directory().to_path_buf();
+        for segment_progress in &progress {
+            let tip = CommittedTip::of(&static_file_provider, segment_progress.segment());
+            if let Err(err) = TailSnapshot::take(&directory, segment_progress.segment())
+                .and_then(|tail| segment_progress.set_tail(tail.with_tip(tip)))
+            {
+                return Err(self.fail(err, targets, &progress))
+            }
         }
 
-        segments.par_iter().try_for_each(|(segment, block_range)| -> ProviderResult<()> {
-            debug!(target: "static_file", segment = %segment.segment(), ?block_range, "StaticFileProducer segment");
-            let start = Instant::now();
+        // Set once the deadline passed and the segments were cancelled.
+        let deadline_reached = AtomicBool::new(false);
+        let result = std::thread::scope(|scope| {
+            // Disconnected once all segments are done, which stops the watchdog.
+            let (done_tx, done_rx) = channel();
+            if let Some(watchdog) = &self.watchdog {
+                let progress = &progress;
+                scope.spawn(move || {
+                    watchdog.watch(progress, done_rx, |event| self.event_sender.notify(event))
+                });
+            }
+            // Disconnected once all segments are done, which stops the deadline timer.
+            let (deadline_done_tx, deadline_done_rx) = channel::<()>();
+            if let Some(deadline) = deadline {
+                let (progress, deadline_reached) = (&progress, &deadline_reached);
+                scope.spawn(move || {
+                    let timeout = deadline.saturating_duration_since(Instant::now());
+                    if deadline_done_rx.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                        deadline_reached.store(true, Ordering::Relaxed);
+                        progress.iter().for_each(SegmentProgress::cancel);
+                    }
+                });
+            }
+
+            let result = match self.run_order {
+                RunOrder::Parallel => self.copy_parallel(scope, &segments, &progress),
+                RunOrder::Sequential(_) => self.copy_interleaved(&segments, &progress, u64::MAX),
+                RunOrder::Interleaved { chunk } => {
+                    self.copy_interleaved(&segments, &progress, chunk)
+                }
+            };
+            drop(done_tx);
+            drop(deadline_done_tx);
+            result
+        });
+        let deadline_reached = deadline_reached.into_inner();
+        if let Err(err) = result {
+            return Err(self.fail(err, targets, &progress))
+        }
 
-            // Create a new database transaction on every segment to prevent long-lived read-only
-            // transactions
-            let provider = self.provider_factory.provider()?.disable_long_read_transaction_safety();
-            segment.copy_to_static_files(provider, self.provider_factory.static_file_provider(), block_range.clone())?;
+        // Segments stopped by the deadline or at a gap in the database are committed up to their
+        // last copied block, which is always a block boundary.
+        let gaps = progress.iter().flat_map(SegmentProgress::gaps).collect::<Vec<_>>();
+        let stopped_at_gap = progress.iter().any(SegmentProgress::stopped_at_gap);
+        let (segments, targets, tx_ranges) = if deadline_reached || stopped_at_gap {
+            let mut produced =
+                StaticFileTargets { headers: None, receipts: None, transactions: None };
+            let segments = segments
+                .into_iter()
+                .zip(&progress)
+                .filter_map(|((segment, block_range), progress)| {
+                    let last_block =
+                        progress.last_block().filter(|last| last >= block_range.start())?;
+                    let block_range = *block_range.start()..=last_block;
+                    *produced.as_mut(segment.segment()) = Some(block_range.clone());
+                    Some((segment, block_range))
+                })
+                .collect::<Vec<_>>();
+            debug!(target: "static_file", requested = ?targets, produced = ?produced, deadline_reached, ?gaps, "StaticFileProducer stopped early");
+            let tx_ranges = self.target_tx_ranges(&produced);
+            (segments, produced, tx_ranges)
+        } else {
+            (segments, targets, tx_ranges)
+        };
 
-            let elapsed = start.elapsed(); // TODO(alexey): track in metrics
-            debug!(target: "static_file", segment = %segment.segment(), ?block_range, ?elapsed, "Finished StaticFileProducer segment");
+        // A segment cancelled by the watchdog didn't copy its whole range, so nothing is committed.
+        // The copied rows are rolled back, so a later commit doesn't persist them.
+        if let Some(stalled) =
+            progress.iter().find(|progress| progress.is_cancelled() && !deadline_reached)
+        {
+            let err = StaticFileProducerError::Stalled {
+                segment: stalled.segment(),
+                last_block: stalled.last_block(),
+                since: stalled.stalled_for(),
+            };
+            self.roll_back(&progress)?;
+            self.event_sender
+                .notify(StaticFileProducerEvent::Failed { targets, kind: FailureKind::Stalled });
+            return Err(err)
+        }
 
-            Ok(())
-        })?;
-        /// Commit the current state of the static file provider.
-        self.provider_factory.static_file_provider().commit()?;
-        /// Iterate over each segment and its corresponding block range
-        for (segment, block_range) in segments {
-            // Update the index of the static file provider for each segment with the end of the block range
+        // Producers sharing a coordinator commit one at a time, along with the post-commit work.
+        let _disk_phase = self.coordinator.as_ref().map(CoordinatorMembership::disk_phase);
+        let commit_start = Instant::now();
+        // Commit the current state of the static file provider. The last commit of every segment
+        // is kept in the write-ahead log until the final commit finishes, as no rows are written
+        // after it. The disk can fill up while the writers flush their rows too, which rolls the
+        // run back like any other write.
+        if let Err(err) = debug_span!(target: "static_file", "commit")
+            .in_scope(|| self.provider_factory.static_file_provider().commit())
+        {
+            return Err(self.fail(err, targets, &progress))
+        }
+        progress.iter().for_each(SegmentProgress::committed);
+        // Segments that had no static files before now start at their target.
+        if let Some(lowest) = self.lowest_static_files.write().as_mut() {
+            for (segment, block_range) in &segments {
+                lowest.as_mut(segment.segment()).get_or_insert(*block_range.start());
+            }
+        }
+        // Update the index of the static file provider for each segment with the end of the block
+        // range. The blocks are committed even if it fails, so the rows are still published and
+        // the write-ahead logs cleared before the error is reported. The static file provider may
+        // not serve the committed blocks until its index is reloaded, so they aren't recorded as
+        // prunable then.
+        let index_updated = segments.iter().try_for_each(|(segment, block_range)| {
             self.provider_factory
                 .static_file_provider()
-                .update_index(segment.segment(), Some(*block_range.end()))?;
+                .update_index(segment.segment(), Some(*block_range.end()))
+        });
+        let committed = CommittedRun {
+            segments: segments.iter().map(|(segment, _)| segment.segment()).collect(),
+            progress: &progress,
+            highest_before: highest_static_files,
+        };
+        self.run_post_commit_steps(&[&PublishCommittedRows, &ClearTailLogs], &committed);
+        if let Err(err) = index_updated {
+            self.event_sender
+                .notify(StaticFileProducerEvent::Failed { targets, kind: FailureKind::Error });
+            return Err(err.into())
         }
+        let commit = commit_start.elapsed();
+
+        let post_commit_start = Instant::now();
+        self.run_post_commit_steps(
+            &[
+                &RecordPrunable,
+                &RecordRowRoots,
+                &ShareDictionaries,
+                &NotifySealed,
+                &UpdateEpochRoots,
+                &ApplyRetention,
+                &ApplyTiering,
+            ],
+            &committed,
+        );
+        let post_commit = post_commit_start.elapsed();
+
         /// Measure the elapsed time since the start of the operation.
         let elapsed = start.elapsed(); // TODO(alexey): track in metrics
-        debug!(target: "static_file", ?targets, ?elapsed, "StaticFileProducer finished");
+        debug!(target: "static_file", ?targets, ?tx_ranges, ?elapsed, "StaticFileProducer finished");
+        let timings = self.timing_summary.then(|| RunTimings {
+            segments: progress
+                .iter()
+                .map(|progress| (progress.segment(), progress.copy_time()))
+                .collect(),
+            commit,
+            post_commit,
+        });
+        if let Some(timings) = &timings {
+            info!(target: "static_file", ?elapsed, segments = ?timings.segments, commit = ?timings.commit, post_commit = ?timings.post_commit, "StaticFileProducer timing summary");
+        }
         /// Notify event listeners that the StaticFileProducer has finished processing,
         /// including the targets and the elapsed time.
-        self.event_sender
-            .notify(StaticFileProducerEvent::Finished { targets: targets.clone(), elapsed });
+        self.event_sender.notify(StaticFileProducerEvent::Finished {
+            targets: targets.clone(),
+            tx_ranges,
+            elapsed,
+            timings: timings.clone(),
+            gaps: gaps.clone(),
+        });
 
-        Ok(targets)
+        Ok((targets, timings, gaps))
     }
 
-    /// Copies data from database to static files according to
-    /// [stage checkpoints](reth_stages_types::StageCheckpoint).
+    /// Returns the transaction ranges of the targets, see [`StaticFileTargets::tx_ranges`].
     ///
-    /// Returns highest block numbers for all static file segments.
-    pub fn copy_to_static_files(&self) -> ProviderResult<HighestStaticFiles> {
-        let provider = self.provider_factory.provider()?;
-        let stages_checkpoints = [StageId::Headers, StageId::Execution, StageId::Bodies]
-            .into_iter()
+    /// They're only reported, so none are returned if the database can't derive them, or blocks
+    /// are copied from a [`BlockSource`].
+    fn target_tx_ranges(
+        &self,
+        targets: &StaticFileTargets,
+    ) -> Vec<(StaticFileSegment, SegmentRangeInclusive)> {
+        if self.block_source.is_some() {
+            return Vec::new()
+        }
+        let tx_ranges = self
+            .provider_factory
+            .database_provider_ro()
+            .and_then(|provider| targets.tx_ranges(&provider));
+        tx_ranges.unwrap_or_else(|err| {
+            warn!(target: "static_file", %err, "Failed to derive the transaction ranges");
+            Vec::new()
+        })
+    }
+
+    /// Runs the StaticFileProducer like [`StaticFileProducerInner::run`], but copies the targets
+    /// into the [`InMemorySink`] instead of static files, without touching disk.
+    ///
+    /// Targets have to be contiguous to the highest blocks of the sink, see
+    /// [`InMemoryReader::highest_static_files`](crate::InMemoryReader::highest_static_files).
+    /// Segments are copied one after another, without sidecars or any post-commit work.
+    pub fn run_in_memory(
+        &self,
+        targets: StaticFileTargets,
+        sink: &mut InMemorySink,
+    ) -> Result<StaticFileTargets, StaticFileProducerError> {
+        if !targets.any() {
+            return Ok(targets)
+        }
+        debug_assert!(
+            targets.is_contiguous_to_highest_static_files(sink.reader().highest_static_files())
+        );
+        self.ensure_enabled(&targets)?;
+
+        let tx_ranges = self.target_tx_ranges(&targets);
+        self.event_sender.notify(StaticFileProducerEvent::Started {
+            targets: targets.clone(),
+            tx_ranges: tx_ranges.clone(),
+        });
+        let _span =
+            debug_span!(target: "static_file", "run_in_memory", ?targets, ?tx_ranges).entered();
+        let start = Instant::now();
+
+        let provider = self.provider_factory.segment_provider()?;
+        let (mut produced, mut gaps) = (targets.clone(), Vec::new());
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            let Some(block_range) = targets.target(segment).cloned() else { continue };
+            let segment: Arc<dyn Segment<F::Provider>> = match self.custom_segments.get(segment) {
+                Some(custom) => custom,
+                None => match segment {
+                    StaticFileSegment::Headers => Arc::new(segments::Headers::default()),
+                    StaticFileSegment::Transactions => Arc::new(segments::Transactions::default()),
+                    StaticFileSegment::Receipts => Arc::new(segments::Receipts::default()),
+                },
+            };
+            let progress = SegmentProgress::new(segment.segment())
+                .with_throttle(self.throttle_blocks_per_second)
+                .with_events(self.event_sender.clone())
+                .with_chain_spec(self.chain_spec.clone())
+                .with_row_transforms(self.row_transforms.clone())
+                .with_missing_data(self.missing_data);
+            let mut sink = self.row_transforms.wrap(sink);
+            progress.start();
+            if let Err(err) =
+                segment.copy_to_sink(&provider, &mut sink, block_range.clone(), &progress)
+            {
+                self.event_sender
+                    .notify(StaticFileProducerEvent::Failed { targets, kind: FailureKind::Error });
+                return Err(err.into())
+            }
+            progress.finish();
+            // Segments stopped at a gap end at their last copied block
+            if progress.stopped_at_gap() {
+                *produced.as_mut(segment.segment()) = progress
+                    .last_block()
+                    .filter(|last| last >= block_range.start())
+                    .map(|last| *block_range.start()..=last);
+            }
+            gaps.extend(progress.gaps());
+        }
+
+        let elapsed = start.elapsed();
+        debug!(target: "static_file", targets = ?produced, ?gaps, ?elapsed, "StaticFileProducer finished in memory");
+        let tx_ranges =
+            if produced == targets { tx_ranges } else { self.target_tx_ranges(&produced) };
+        self.event_sender.notify(StaticFileProducerEvent::Finished {
+            targets: produced.clone(),
+            tx_ranges,
+            elapsed,
+            timings: None,
+            gaps,
+        });
+
+        Ok(produced)
+    }
+
+    /// Returns the segment copying rows of the static file segment: the custom segment if it's
+    /// set, or from the block source if it's set.
+    pub(crate) fn segment(&self, segment: StaticFileSegment) -> Arc<dyn Segment<F::Provider>> {
+        if let Some(custom) = self.custom_segments.get(segment) {
+            return custom
+        }
+        if let Some((block_source, checkpoint)) = &self.block_source {
+            return Arc::new(segments::FromSource::new(segment, block_source.clone(), *checkpoint))
+        }
+        match segment {
+            StaticFileSegment::Headers => Arc::new(segments::Headers),
+            StaticFileSegment::Transactions => Arc::new(
+                segments::Transactions::new(self.sender_index)
+                    .with_tx_type_stats(self.transaction_tx_type_stats),
+            ),
+            StaticFileSegment::Receipts => Arc::new(
+                segments::Receipts::new(self.receipt_log_index)
+                    .with_receipt_stats(self.receipt_stats),
+            ),
+        }
+    }
+
+    /// Returns an error if any of the targets belongs to a disabled segment.
+    fn ensure_enabled(&self, targets: &StaticFileTargets) -> Result<(), StaticFileProducerError> {
+        match [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ]
+        .into_iter()
+        .find(|segment| targets.target(*segment).is_some() && !self.segments.is_enabled(*segment))
+        {
+            Some(segment) => Err(StaticFileProducerError::SegmentDisabled(segment)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that every static file of the directory belongs to the chain, once per chain. Static
+    /// files appended to afterwards are checked by the segments.
+    fn ensure_chain(&self) -> Result<(), StaticFileProducerError> {
+        let Some(chain_spec) = &self.chain_spec else { return Ok(()) };
+        if self.chain_checked.load(Ordering::Relaxed) {
+            return Ok(())
+        }
+
+        check_chain_id(
+            self.provider_factory.static_file_provider().directory(),
+            chain_spec.chain_id(),
+        )?;
+        self.chain_checked.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Applies the [`RetentionService`], removing sealed static files that are entirely outside of
+    /// the retention window, and reloading the static file index afterwards.
+    ///
+    /// Without a retention policy, nothing is removed.
+    pub fn apply_retention(&self) -> Result<RetentionOutcome, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+
+        let outcome = match &self.retention {
+            Some(retention) => retention.apply(&static_file_provider)?,
+            None => RetentionOutcome::new(
+                Vec::new(),
+                &list_static_files(static_file_provider.directory())?,
+            ),
+        };
+        *self.lowest_static_files.write() = Some(outcome.lowest);
+        Ok(outcome)
+    }
+
+    /// Applies the [`TieringService`], moving sealed static files old enough to the cold directory,
+    /// and cold static files that were pinned since back to the static files directory.
+    ///
+    /// Static files keep their paths in the static files directory, so the static file index
+    /// doesn't need to be reloaded. Without a tiering policy, nothing is moved.
+    pub fn apply_tiering(&self) -> Result<TieringOutcome, StaticFileProducerError> {
+        let Some(tiering) = &self.tiering else { return Ok(TieringOutcome::default()) };
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+        tiering.apply(&self.provider_factory.static_file_provider())
+    }
+
+    /// Scans the static files directory for static files of the same segment and block range,
+    /// e.g. left behind by a change of the compression, emitting a
+    /// [`StaticFileProducerEvent::DuplicateRange`] for every static file that lost against
+    /// another one.
+    ///
+    /// The static file listed in the manifest wins, falling back to the newest one, see
+    /// [`scan_static_files`](crate::scan_static_files).
+    pub fn check_duplicate_ranges(
+        &self,
+        manifest: Option<&StaticFileManifest>,
+    ) -> Result<DirectoryScan, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let scan = scan_static_files_against(
+            static_file_provider.directory(),
+            ScanPolicy::Permissive,
+            manifest,
+        )
+        .map_err(io::Error::from)?;
+
+        for issue in &scan.issues {
+            if let ScanIssue::DuplicateRange { segment, block_range, kept, skipped } = issue {
+                self.event_sender.notify(StaticFileProducerEvent::DuplicateRange {
+                    segment: *segment,
+                    block_range: *block_range,
+                    kept: kept.clone(),
+                    skipped: skipped.clone(),
+                });
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Removes the static files that lost against another static file of the same range, as
+    /// found by [`Self::check_duplicate_ranges`], and reloads the static file index.
+    ///
+    /// Returns the removed static files.
+    pub fn remove_duplicate_ranges(
+        &self,
+        manifest: Option<&StaticFileManifest>,
+    ) -> Result<Vec<StaticFileEntry>, StaticFileProducerError> {
+        let scan = self.check_duplicate_ranges(manifest)?;
+        if scan.duplicates.is_empty() {
+            return Ok(Vec::new())
+        }
+
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+        for entry in &scan.duplicates {
+            debug!(target: "static_file", segment = %entry.segment, block_range = %entry.block_range, path = ?entry.path, "Removing duplicate static file");
+        }
+        scan.remove_duplicates()?;
+        self.provider_factory.static_file_provider().initialize_index()?;
+        Ok(scan.duplicates)
+    }
+
+    /// Fetches the static files quarantined by the [doctor](crate::doctor) from the
+    /// [`RepairMirror`], verifying them against the local headers, and reloads the static file
+    /// index. Repaired static files are removed from the
+    /// [`QuarantineReport`](crate::doctor::QuarantineReport).
+    ///
+    /// Meant for ranges that were already pruned from the database, which can't be produced again
+    /// without a full resync. Headers are repaired first, so transactions and receipts of the same
+    /// range can be verified against them. Without a repair mirror, nothing is repaired.
+    ///
+    /// Returns the repaired static files.
+    pub fn repair_quarantined(&self) -> Result<Vec<StaticFileEntry>, StaticFileProducerError> {
+        let Some(repair) = &self.repair else { return Ok(Vec::new()) };
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+        repair.repair_quarantined(&self.provider_factory.static_file_provider(), || {
+            self.provider_factory.database_provider_ro()
+        })
+    }
+
+    /// Runs `f` building filters on the thread pool of the [`ProducerCoordinator`] if the producer
+    /// is attached to one with a shared thread pool, or within the [`WorkersConfig`] otherwise.
+    pub(crate) fn install_filters<T: Send>(&self, f: impl FnOnce() -> T + Send) -> io::Result<T> {
+        match &self.coordinator {
+            Some(coordinator) => coordinator.install_filters(&self.workers, f),
+            None => self.workers.install_filters(f),
+        }
+    }
+
+    /// Regenerates the inclusion filter and perfect hashing function of the sealed static file of
+    /// the segment and block range with the new [`Filters`], and reloads the static file index.
+    ///
+    /// Meant for upgrading old static files to new filter types cheaply: the compressed columns
+    /// are not rewritten. Receipts are keyed by transaction hashes, so the transactions static
+    /// file of the same range has to be present. Keys hashed with [`FilterHash::KeyedSipHash`]
+    /// need the [filter key](StaticFileProducerInner::set_filter_key). Receipts indexed with
+    /// [`PerfectHashingFunction::Chd`](reth_static_file_types::PerfectHashingFunction::Chd) get
+    /// its sidecar, which is removed once they're indexed with another function.
+    pub fn rebuild_filters(
+        &self,
+        segment: StaticFileSegment,
+        block_range: SegmentRangeInclusive,
+        filters: Filters,
+        filter_hash: FilterHash,
+    ) -> Result<(), StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+
+        debug!(target: "static_file", %segment, %block_range, ?filters, ?filter_hash, "Rebuilding static file filters");
+        let _disk_phase = self.coordinator.as_ref().map(CoordinatorMembership::disk_phase);
+        self.install_filters(|| {
+            segments::rebuild_filters(
+                static_file_provider.directory(),
+                segment,
+                block_range,
+                filters,
+                filter_hash,
+                self.filter_key,
+            )
+        })??;
+        static_file_provider.initialize_index()?;
+        Ok(())
+    }
+
+    /// Reproduces the sealed static file of the segment and fixed block range from the database,
+    /// with the current [`SegmentsConfig`] of the segment, and reloads the static file index.
+    ///
+    /// Unlike [`StaticFileProducerInner::run`], whose targets can't go below the highest static
+    /// files, the block range is already covered by static files: meant for repairing a static
+    /// file or changing its compression and filters while its rows are still in the database.
+    /// The static file is built aside and verified before it atomically replaces the existing
+    /// one, see [`rewrite_static_file`]. The last static file of a segment is still appended to,
+    /// so it can't be rewritten.
+    pub fn force_rewrite(
+        &self,
+        segment: StaticFileSegment,
+        block_range: SegmentRangeInclusive,
+    ) -> Result<StaticFileEntry, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+
+        let entry = StaticFileEntry {
+            segment,
+            block_range,
+            path: static_file_provider.directory().join(segment.filename(&block_range)),
+        };
+        let config = self.segment_config(segment);
+        debug!(target: "static_file", %segment, %block_range, ?config, "Rewriting static file");
+        let provider = self.provider_factory.segment_provider()?;
+        let highest_static_files = static_file_provider.get_highest_static_files();
+        let _disk_phase = self.coordinator.as_ref().map(CoordinatorMembership::disk_phase);
+        let rewritten = self.install_filters(|| {
+            rewrite_static_file(
+                self.segment(segment).as_ref(),
+                &provider,
+                config,
+                &entry,
+                &highest_static_files,
+            )
+        })??;
+        static_file_provider.initialize_index()?;
+        Ok(rewritten)
+    }
+
+    /// Produces the static files of the fixed ranges assigned to the shard, up to the tip, with a
+    /// [`ShardProducer`], see [`ShardProducer::produce`]. The static files directory of the
+    /// producer is left untouched.
+    pub fn produce_shard(
+        &self,
+        shard: ShardAssignment,
+        tip: BlockNumber,
+        output: &Path,
+    ) -> Result<ShardManifest, StaticFileProducerError> {
+        ShardProducer::new(self).produce(shard, tip, output)
+    }
+
+    /// Restores the static files of segments whose commit was interrupted by a crash, leaving
+    /// their data and offsets files inconsistent, to their last finished commit from the
+    /// write-ahead log, and reloads the static file index. Rolled back rows are copied again by
+    /// the next run. Swaps of [rewritten](StaticFileProducerInner::force_rewrite) static files
+    /// interrupted by a crash are completed.
+    ///
+    /// Called at the start of every [run](StaticFileProducerInner::run), but should also be
+    /// called on startup, before targets are derived from the highest static files. The first
+    /// call warms up the newest static files, if configured with
+    /// [`StaticFileProducerInner::set_warmup`].
+    ///
+    /// Returns the restored segments.
+    pub fn recover_tails(&self) -> Result<Vec<StaticFileSegment>, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+
+        let recovered = recover_tails(static_file_provider.directory())?;
+        if !recovered.is_empty() {
+            info!(target: "static_file", segments = ?recovered, "Restored static files of interrupted commits");
+            static_file_provider.initialize_index()?;
+            publish_committed_rows(&static_file_provider, recovered.iter().copied())?;
+        }
+        if !complete_rewrites(static_file_provider.directory())?.is_empty() {
+            static_file_provider.initialize_index()?;
+        }
+
+        if let Some(config) = self.warmup {
+            let mut warmed_files = self.warmed_files.lock();
+            if warmed_files.is_none() {
+                // Warming up is best-effort, and retried by the next call if it failed
+                match warmup_newest(static_file_provider.directory(), config) {
+                    Ok(warmed) => {
+                        debug!(target: "static_file", files = warmed.entries.len(), bytes = warmed.bytes, locked_bytes = warmed.locked_bytes(), "Warmed up newest static files");
+                        *warmed_files = Some(warmed);
+                    }
+                    Err(err) => {
+                        warn!(target: "static_file", %err, "Failed to warm up static files");
+                    }
+                }
+            }
+        }
+        Ok(recovered)
+    }
+
+    /// Unwinds the static files of every segment holding blocks after `unwound_to`, the last
+    /// block kept by a reorg to the new tip `new_tip`, emitting
+    /// [`StaticFileProducerEvent::Unwound`] for every unwound segment. Index sidecars of the kept
+    /// static files are truncated, and blocks of the new chain are copied by the next run.
+    ///
+    /// Static files are unwound on their own, so it can be called both before and after the
+    /// database is unwound to the same block.
+    ///
+    /// Returns the unwound segments.
+    pub fn on_reorg(
+        &self,
+        new_tip: BlockNumber,
+        unwound_to: BlockNumber,
+    ) -> Result<Vec<StaticFileSegment>, StaticFileProducerError> {
+        debug_assert!(new_tip >= unwound_to, "new tip {new_tip} is below the unwound block");
+        self.recover_tails()?;
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let provider = self.provider_factory.database_provider_ro()?;
+        let _watcher_pause = self.watcher.as_ref().map(StaticFileWatcher::pause);
+
+        let mut unwound = Vec::new();
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            let Some(from) = unwind_segment(&static_file_provider, &provider, segment, unwound_to)?
+            else {
+                continue
+            };
+            info!(target: "static_file", %segment, from, to = unwound_to, new_tip, "Unwound static files past reorg");
+            self.event_sender.notify(StaticFileProducerEvent::Unwound {
+                segment,
+                from,
+                to: unwound_to,
+            });
+            unwound.push(segment);
+        }
+        if !unwound.is_empty() {
+            publish_committed_rows(&static_file_provider, unwound.iter().copied())?;
+            self.record_prunable(unwound.iter().copied())?;
+        }
+        Ok(unwound)
+    }
+
+    /// Returns the prune checkpoints of the segments, recorded after every commit and advanced by
+    /// [`StaticFileProducerInner::confirm_pruned`].
+    pub fn prune_checkpoints(&self) -> io::Result<PruneCheckpoints> {
+        PruneCheckpoints::read(self.provider_factory.static_file_provider().directory())
+    }
+
+    /// Confirms that the rows of the segment were pruned from the database up to `block`,
+    /// advancing its [`PruneCheckpoint::pruned_up_to`]. Should be called by the pruner only after
+    /// the prune was committed to the database, so a crash in between leaves the blocks
+    /// [pending](PruneCheckpoint::pending) and they're pruned again.
+    ///
+    /// Returns [`StaticFileProducerError::NotPrunable`] if the segment isn't in static files up
+    /// to the block.
+    pub fn confirm_pruned(
+        &self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> Result<PruneCheckpoint, StaticFileProducerError> {
+        let directory = self.provider_factory.static_file_provider().directory().to_path_buf();
+        confirm_pruned(&directory, segment, block)?.ok_or_else(|| {
+            let prunable_up_to = PruneCheckpoints::read(&directory)
+                .ok()
+                .and_then(|checkpoints| checkpoints.get(segment))
+                .map(|checkpoint| checkpoint.prunable_up_to);
+            StaticFileProducerError::NotPrunable { segment, block, prunable_up_to }
+        })
+    }
+
+    /// Runs the enabled [`PostCommitStep`]s of a committed run in order, logging the failed ones.
+    fn run_post_commit_steps(
+        &self,
+        steps: &[&dyn PostCommitStep<F>],
+        committed: &CommittedRun<'_>,
+    ) {
+        for step in steps.iter().filter(|step| step.enabled(self)) {
+            if let Err(err) = step.run(self, committed) {
+                warn!(target: "static_file", step = step.name(), %err, "Post-commit step failed");
+            }
+        }
+    }
+
+    /// Records the highest static file blocks of the segments as prunable from the database.
+    fn record_prunable(
+        &self,
+        segments: impl IntoIterator<Item = StaticFileSegment>,
+    ) -> io::Result<PruneCheckpoints> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        record_prunable(
+            static_file_provider.directory(),
+            segments.into_iter().map(|segment| {
+                (segment, static_file_provider.get_highest_static_file_block(segment))
+            }),
+        )
+    }
+
+    /// [Rolls back](Self::roll_back) all segments to their last commit, notifies listeners that
+    /// the run failed with the error, and returns it. Rows copied before the error would
+    /// otherwise stay in the writers, and be persisted by a later commit.
+    fn fail(
+        &self,
+        err: impl Into<StaticFileProducerError>,
+        targets: StaticFileTargets,
+        progress: &[SegmentProgress],
+    ) -> StaticFileProducerError {
+        let err = err.into();
+        let kind = if is_disk_full(&err) { FailureKind::DiskFull } else { FailureKind::Error };
+        if let Err(err) = self.roll_back(progress) {
+            return err
+        }
+        self.event_sender.notify(StaticFileProducerEvent::Failed { targets, kind });
+        err
+    }
+
+    /// Rolls back the static file writers and static files of all segments to their last commit,
+    /// after a segment failed in the middle of a write or stalled, and reloads the static file
+    /// index.
+    fn roll_back(&self, progress: &[SegmentProgress]) -> Result<(), StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        for progress in progress {
+            if let Some(tail) = progress.take_tail() {
+                debug!(target: "static_file", segment = %progress.segment(), "Rolling back static files to the last commit");
+                tail.reset_writer(&static_file_provider)?;
+                tail.restore()?;
+            }
+        }
+        for progress in progress {
+            TailSnapshot::clear_log(static_file_provider.directory(), progress.segment())?;
+        }
+        static_file_provider.initialize_index()?;
+        publish_committed_rows(
+            &static_file_provider,
+            progress.iter().map(SegmentProgress::segment),
+        )?;
+        Ok(())
+    }
+
+    /// Returns the estimated size of the static files of the segment to preallocate, if
+    /// preallocation is enabled and the segment has sealed static files to estimate it from.
+    fn preallocation_estimate(
+        &self,
+        segment: StaticFileSegment,
+    ) -> io::Result<Option<FileSizeEstimate>> {
+        if !self.preallocation.enabled {
+            return Ok(None)
+        }
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let entries = list_static_files(static_file_provider.directory())?;
+        let highest_static_files = static_file_provider.get_highest_static_files();
+        let estimate =
+            FileSizeEstimate::from_static_files(&entries, &highest_static_files, segment)?;
+        Ok(estimate.map(|estimate| estimate.with_max_file_bytes(self.preallocation.max_file_bytes)))
+    }
+
+    /// Checks that the static files produced for the targets stay within the disk quota,
+    /// estimating their size from the existing static files.
+    fn check_disk_quota(
+        &self,
+        disk_quota: &DiskQuota,
+        targets: &StaticFileTargets,
+        highest_static_files: HighestStaticFiles,
+    ) -> Result<(), StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let entries = list_static_files(static_file_provider.directory())?;
+
+        let mut estimated_bytes = 0u64;
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            if let Some(block_range) = targets.target(segment) {
+                let blocks = block_range.end() - block_range.start() + 1;
+                estimated_bytes = estimated_bytes.saturating_add(estimate_bytes(
+                    &entries,
+                    &highest_static_files,
+                    segment,
+                    blocks,
+                )?);
+            }
+        }
+
+        if let Some(violation) =
+            disk_quota.check(static_file_provider.directory(), &entries, estimated_bytes)?
+        {
+            debug!(target: "static_file", %violation, "Refusing to exceed disk quota");
+            self.event_sender.notify(StaticFileProducerEvent::QuotaExceeded { violation });
+            return Err(StaticFileProducerError::QuotaExceeded(violation))
+        }
+        Ok(())
+    }
+
+    /// Records the row roots of static files that were not sealed with the highest static files
+    /// before the run, but are now, and drops their cached providers, so the static file provider
+    /// loads their updated headers. Static files whose root can't be recorded are logged and
+    /// skipped. Returns the number of recorded roots.
+    fn record_row_roots(
+        &self,
+        highest_before: HighestStaticFiles,
+    ) -> Result<usize, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let highest = static_file_provider.get_highest_static_files();
+
+        let mut recorded = 0;
+        for entry in list_static_files(static_file_provider.directory())? {
+            if !entry.is_sealed(&highest) || entry.is_sealed(&highest_before) {
+                continue
+            }
+
+            match record_row_root(&entry) {
+                Ok(root) => {
+                    debug!(target: "static_file", segment = %entry.segment, block_range = %entry.block_range, %root, "Recorded row root");
+                    static_file_provider
+                        .remove_cached_provider(entry.segment, entry.block_range.end());
+                    recorded += 1;
+                }
+                Err(err) => {
+                    warn!(target: "static_file", %err, segment = %entry.segment, block_range = %entry.block_range, "Failed to record row root")
+                }
+            }
+        }
+        Ok(recorded)
+    }
+
+    /// Stores the dictionaries of static files that were not sealed with the highest static files
+    /// before the run, but are now, in the [`DictionaryStore`] and references them in their
+    /// headers, dropping their cached providers, so the static file provider loads their updated
+    /// headers. Static files whose dictionaries can't be shared are logged and skipped. Returns
+    /// the number of static files with shared dictionaries.
+    fn share_dictionaries(
+        &self,
+        highest_before: HighestStaticFiles,
+    ) -> Result<usize, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let highest = static_file_provider.get_highest_static_files();
+        let store = DictionaryStore::new(static_file_provider.directory());
+
+        let mut shared = 0;
+        for entry in list_static_files(static_file_provider.directory())? {
+            if !entry.is_sealed(&highest) || entry.is_sealed(&highest_before) {
+                continue
+            }
+
+            match store.share(&entry) {
+                Ok(ids) if ids.is_empty() => {}
+                Ok(ids) => {
+                    debug!(target: "static_file", segment = %entry.segment, block_range = %entry.block_range, dictionaries = ids.len(), "Shared dictionaries");
+                    static_file_provider
+                        .remove_cached_provider(entry.segment, entry.block_range.end());
+                    shared += 1;
+                }
+                Err(err) => {
+                    warn!(target: "static_file", %err, segment = %entry.segment, block_range = %entry.block_range, "Failed to share dictionaries")
+                }
+            }
+        }
+        Ok(shared)
+    }
+
+    /// Notifies the [`SealHooks`] about static files that were not sealed with the highest static
+    /// files before the run, but are now, and about the [pending](PendingSealed) ones of earlier
+    /// runs. Static files whose content hash can't be computed are logged and left pending for
+    /// the next run. Returns the number of notified static files.
+    fn notify_sealed(
+        &self,
+        highest_before: HighestStaticFiles,
+    ) -> Result<usize, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let highest = static_file_provider.get_highest_static_files();
+        let directory = static_file_provider.directory();
+        let pending = PendingSealed::read(directory)?;
+
+        let mut sealed = 0;
+        let mut still_pending = PendingSealed::default();
+        for entry in list_static_files(directory)? {
+            let newly_sealed = entry.is_sealed(&highest) && !entry.is_sealed(&highest_before);
+            if !newly_sealed && !pending.contains(&entry) {
+                continue
+            }
+
+            let checksum = match content_hash(&entry) {
+                Ok(checksum) => checksum,
+                Err(err) => {
+                    warn!(target: "static_file", %err, segment = %entry.segment, block_range = %entry.block_range, "Failed to hash sealed static file, notifying about it on the next run");
+                    still_pending.push(&entry);
+                    continue
+                }
+            };
+            self.seal_hooks.notify(&SealedFile {
+                segment: entry.segment,
+                block_range: entry.block_range,
+                path: entry.path,
+                checksum,
+            });
+            sealed += 1;
+        }
+        still_pending.write(directory)?;
+        Ok(sealed)
+    }
+
+    /// Records the roots of pre-merge header accumulator epochs that were completed in static
+    /// files since the last update, returning the number of recorded epochs.
+    pub fn update_epoch_roots(&self) -> Result<usize, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let Some(highest) = static_file_provider.get_highest_static_files().headers else {
+            return Ok(0)
+        };
+
+        let directory = static_file_provider.directory();
+        let mut epoch = read_epoch_roots(directory)?.len() as u64;
+        let mut roots = Vec::new();
+        while epoch * EPOCH_SIZE < MERGE_BLOCK && epoch_end(epoch) <= highest {
+            roots.push(epoch_accumulator(&static_file_provider, epoch)?.root());
+            epoch += 1;
+        }
+
+        append_epoch_roots(directory, &roots)?;
+        Ok(roots.len())
+    }
+
+    /// Returns the lowest block available in static files, per segment.
+    ///
+    /// The static files directory is scanned only on first access, afterwards the lowest blocks
+    /// are tracked in memory.
+    pub fn get_lowest_static_files(&self) -> Result<LowestStaticFiles, StaticFileProducerError> {
+        if let Some(lowest) = *self.lowest_static_files.read() {
+            return Ok(lowest)
+        }
+
+        let entries = list_static_files(self.provider_factory.static_file_provider().directory())?;
+        let lowest = lowest_static_files(&entries);
+        *self.lowest_static_files.write() = Some(lowest);
+        Ok(lowest)
+    }
+
+    /// Returns the [`StaticFileManifest`] of sealed static files, named according to the
+    /// [`NamingScheme`].
+    pub fn manifest(
+        &self,
+        naming: NamingScheme,
+    ) -> Result<StaticFileManifest, StaticFileProducerError> {
+        let static_file_provider = self.provider_factory.static_file_provider();
+        let entries = list_static_files(static_file_provider.directory())?;
+        Ok(StaticFileManifest::new(
+            &entries,
+            static_file_provider.get_highest_static_files(),
+            naming,
+        )?)
+    }
+
+    /// Returns `true` if the block of a given segment is available in static files, without
+    /// probing the filesystem after the first access.
+    pub fn is_block_available(
+        &self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> Result<bool, StaticFileProducerError> {
+        let highest = self.provider_factory.static_file_provider().get_highest_static_files();
+        Ok(self.get_lowest_static_files()?.is_available(segment, block, &highest))
+    }
+
+    /// Copies the block range of a single segment to static files.
+    fn copy_segment(
+        &self,
+        segment: &dyn Segment<F::Provider>,
+        block_range: RangeInclusive<BlockNumber>,
+        progress: &SegmentProgress,
+    ) -> ProviderResult<()> {
+        let _span = debug_span!(target: "static_file", "copy_segment", segment = %segment.segment(), ?block_range).entered();
+        debug!(target: "static_file", segment = %segment.segment(), ?block_range, "StaticFileProducer segment");
+        let start = Instant::now();
+        progress.start();
+
+        // A new provider is opened on every segment, and every renewal interval
+        // within the segment, to prevent long-lived read-only transactions
+        segment.copy_to_static_files(&self.provider_factory, block_range.clone(), progress)?;
+        progress.finish();
+
+        let elapsed = start.elapsed(); // TODO(alexey): track in metrics
+        debug!(target: "static_file", segment = %segment.segment(), ?block_range, ?elapsed, "Finished StaticFileProducer segment");
+
+        Ok(())
+    }
+
+    /// Copies every segment on its own worker thread of the scope, named and pinned according to
+    /// the [`WorkersConfig`].
+    fn copy_parallel<'scope>(
+        &'scope self,
+        scope: &'scope Scope<'scope, '_>,
+        segments: &'scope [(Arc<dyn Segment<F::Provider>>, RangeInclusive<BlockNumber>)],
+        progress: &'scope [SegmentProgress],
+    ) -> ProviderResult<()> {
+        // Segments copied on worker threads are still traced within the run.
+        let run_span = Span::current();
+        let workers = segments
+            .iter()
+            .zip(progress)
+            .enumerate()
+            .map(|(n, ((segment, block_range), progress))| {
+                let run_span = run_span.clone();
+                self.workers.spawn_scoped(scope, segment.segment(), n, move || {
+                    let _span = run_span.enter();
+                    self.copy_segment(segment.as_ref(), block_range.clone(), progress)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Every spawned worker is joined before the first error is returned.
+        let mut result = Ok(());
+        for worker in workers {
+            let worker_result = match worker {
+                Ok(worker) => {
+                    worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+                }
+                Err(err) => Err(ProviderError::NippyJar(err.to_string())),
+            };
+            if result.is_ok() {
+                result = worker_result;
+            }
+        }
+        result
+    }
+
+    /// Copies segments one after another, in round-robin chunks of `chunk` blocks. With
+    /// `chunk == u64::MAX`, every segment is copied in full before moving to the next one.
+    ///
+    /// Stops early if any of the segments was cancelled.
+    fn copy_interleaved(
+        &self,
+        segments: &[(Arc<dyn Segment<F::Provider>>, RangeInclusive<BlockNumber>)],
+        progress: &[SegmentProgress],
+        chunk: u64,
+    ) -> ProviderResult<()> {
+        let chunk = chunk.max(1);
+        // Next block to copy, per segment
+        let mut next_blocks = segments.iter().map(|(_, range)| *range.start()).collect::<Vec<_>>();
+
+        loop {
+            let mut copied_any = false;
+            for (((segment, block_range), segment_progress), next_block) in
+                segments.iter().zip(progress).zip(next_blocks.iter_mut())
+            {
+                // Segments that stopped at a gap can't copy the blocks after it
+                if *next_block > *block_range.end() || segment_progress.stopped_at_gap() {
+                    continue
+                }
+                if progress.iter().any(SegmentProgress::is_cancelled) {
+                    return Ok(())
+                }
+
+                let chunk_end = next_block.saturating_add(chunk - 1).min(*block_range.end());
+                self.copy_segment(segment.as_ref(), *next_block..=chunk_end, segment_progress)?;
+                *next_block = chunk_end + 1;
+                copied_any = true;
+            }
+
+            if !copied_any {
+                return Ok(())
+            }
+        }
+    }
+
+    /// Copies data from database to static files according to
+    /// [stage checkpoints](reth_stages_types::StageCheckpoint).
+    ///
+    /// Returns highest block numbers for all static file segments, or `None` for disabled
+    /// segments.
+    ///
+    /// Errors of the producer itself are returned as [`ProviderError::NippyJar`] with their
+    /// message. Use [`StaticFileProducerInner::try_copy_to_static_files`] to tell them apart.
+    pub fn copy_to_static_files(&self) -> ProviderResult<HighestStaticFiles> {
+        Ok(self.try_copy_to_static_files()?)
+    }
+
+    /// Copies data from database to static files like
+    /// [`StaticFileProducerInner::copy_to_static_files`], returning a [`StaticFileProducerError`]
+    /// that tells errors of the producer apart.
+    pub fn try_copy_to_static_files(&self) -> Result<HighestStaticFiles, StaticFileProducerError> {
+        let provider = self.provider_factory.database_provider_ro()?;
+        let stages_checkpoints = [StageId::Headers, StageId::Execution, StageId::Bodies]
+            .into_iter()
             .map(|stage| provider.get_stage_checkpoint(stage).map(|c| c.map(|c| c.block_number)))
             .collect::<Result<Vec<_>, _>>()?;
 
-        let highest_static_files = HighestStaticFiles {
-            headers: stages_checkpoints[0],
-            receipts: stages_checkpoints[1],
-            transactions: stages_checkpoints[2],
-        };
-        let targets = self.get_static_file_targets(highest_static_files)?;
-        self.run(targets)?;
+        let highest_static_files = HighestStaticFiles {
+            headers: stages_checkpoints[0],
+            receipts: stages_checkpoints[1],
+            transactions: stages_checkpoints[2],
+        };
+        let targets = self.get_static_file_targets(highest_static_files)?;
+        self.try_run(targets)?;
+
+        // Disabled segments weren't copied, so they must not be pruned from the database.
+        Ok(self.enabled_only(highest_static_files))
+    }
+
+    /// Copies data up to the finalized block numbers per segment, limited to the daily block budget
+    /// of the [`TrickleScheduler`] left today, and records the copied blocks in its persisted
+    /// progress.
+    ///
+    /// Meant to be called periodically during the initial conversion of an archive node, which
+    /// then spreads over days. Returns empty targets once the budget of today is spent.
+    pub fn run_trickle(
+        &self,
+        scheduler: &mut TrickleScheduler,
+        finalized_block_numbers: HighestStaticFiles,
+    ) -> Result<StaticFileTargets, StaticFileProducerError> {
+        let now = SystemTime::now();
+        let highest_static_files =
+            self.provider_factory.static_file_provider().get_highest_static_files();
+        let targets = self.get_static_file_targets(scheduler.clamp(
+            highest_static_files,
+            finalized_block_numbers,
+            now,
+        ))?;
+        let targets = self.try_run(targets)?;
+        scheduler.record(&targets, now)?;
+        Ok(targets)
+    }
+
+    /// Returns the block numbers of enabled segments, and `None` for disabled segments.
+    fn enabled_only(&self, blocks: HighestStaticFiles) -> HighestStaticFiles {
+        HighestStaticFiles {
+            headers: blocks
+                .headers
+                .filter(|_| self.segments.is_enabled(StaticFileSegment::Headers)),
+            receipts: blocks
+                .receipts
+                .filter(|_| self.segments.is_enabled(StaticFileSegment::Receipts)),
+            transactions: blocks
+                .transactions
+                .filter(|_| self.segments.is_enabled(StaticFileSegment::Transactions)),
+        }
+    }
+
+    /// Returns a static file targets at the provided finalized block numbers per segment.
+    /// The target is determined by the check against highest `static_files` using
+    /// [`reth_provider::providers::StaticFileProvider::get_highest_static_files`].
+    pub fn get_static_file_targets(
+        &self,
+        finalized_block_numbers: HighestStaticFiles,
+    ) -> ProviderResult<StaticFileTargets> {
+        let highest_static_files =
+            self.provider_factory.static_file_provider().get_highest_static_files();
+        // Disabled segments are never produced, so their missing static files aren't a gap.
+        let finalized_block_numbers = self.enabled_only(finalized_block_numbers);
+
+        let targets = StaticFileTargets {
+            headers: finalized_block_numbers.headers.and_then(|finalized_block_number| {
+                self.get_static_file_target(highest_static_files.headers, finalized_block_number)
+            }),
+            // StaticFile receipts only if they're not pruned according to the user configuration
+            receipts: if self.prune_modes.receipts.is_none() &&
+                self.prune_modes.receipts_log_filter.is_empty()
+            {
+                finalized_block_numbers.receipts.and_then(|finalized_block_number| {
+                    self.get_static_file_target(
+                        highest_static_files.receipts,
+                        finalized_block_number,
+                    )
+                })
+            } else {
+                None
+            },
+            transactions: finalized_block_numbers.transactions.and_then(|finalized_block_number| {
+                // For each finalized block number, determine the range of block numbers for static files.
+                self.get_static_file_target(
+                    highest_static_files.transactions,// The highest static file already processed.
+                    finalized_block_number, // The current finalized block number.
+                )
+            }),
+        };
+
+        trace!(
+            target: "static_file",
+            ?finalized_block_numbers, // Log the finalized block numbers.
+            ?highest_static_files, // Log the highest static files processed so far.
+            ?targets, // Log the targets for static files to process.
+            any = %targets.any(), // Log if there are any targets.
+            "StaticFile targets" // Log message.
+        );
+
+        Ok(targets)
+    }
+    /// Determines the range of block numbers for static files based on the highest processed block
+    /// and the current finalized block number.
+
+    /// * `highest_static_file` - The highest block number already processed for static files.
+    /// * `finalized_block_number` - The current finalized block number.
+
+
+    /// Returns an optional inclusive range of block numbers to be processed. Returns None if the range is empty.
+    fn get_static_file_target(
+        &self,
+        highest_static_file: Option<BlockNumber>,
+        finalized_block_number: BlockNumber,
+    ) -> Option<RangeInclusive<BlockNumber>> {
+        let range = highest_static_file.map_or(0, |block| block + 1)..=finalized_block_number;
+        (!range.is_empty()).then_some(range)
+    }
+}
+
+/// Blocks committed by a run, that the [`PostCommitStep`]s work on.
+struct CommittedRun<'a> {
+    /// Segments whose blocks were committed.
+    segments: Vec<StaticFileSegment>,
+    /// Progress of all segments of the run.
+    progress: &'a [SegmentProgress],
+    /// Highest static files before the run.
+    highest_before: HighestStaticFiles,
+}
+
+/// Work done by a run once its blocks are committed.
+///
+/// The blocks stay committed whatever a step does, so a failed step doesn't fail the run: it's
+/// logged, and the rest of the steps still run. Every step is safe to skip, as the next run or
+/// the readers of the static files catch up on what it didn't do.
+trait PostCommitStep<F: SegmentProviderFactory> {
+    /// Returns the name of the step, logged when it fails.
+    fn name(&self) -> &'static str;
+
+    /// Returns `true` if the producer is configured to run the step.
+    fn enabled(&self, _producer: &StaticFileProducerInner<F>) -> bool {
+        true
+    }
+
+    /// Runs the step on the committed blocks.
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError>;
+}
+
+/// Lets readers see the committed rows. Until they're published, readers keep seeing the rows of
+/// the previous commit.
+struct PublishCommittedRows;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for PublishCommittedRows {
+    fn name(&self) -> &'static str {
+        "publish_committed_rows"
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let static_file_provider = producer.provider_factory.static_file_provider();
+        publish_committed_rows(&static_file_provider, committed.segments.iter().copied())?;
+        Ok(())
+    }
+}
+
+/// Clears the write-ahead logs of the tails, which aren't needed once the rows are committed. A
+/// log left behind is older than the published committed rows, so recovery drops it.
+struct ClearTailLogs;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for ClearTailLogs {
+    fn name(&self) -> &'static str {
+        "clear_tail_logs"
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let static_file_provider = producer.provider_factory.static_file_provider();
+        let mut result = Ok(());
+        for progress in committed.progress {
+            let segment = progress.segment();
+            if let Err(err) = TailSnapshot::clear_log(static_file_provider.directory(), segment) {
+                result = Err(io::Error::new(err.kind(), format!("{segment}: {err}")));
+            }
+        }
+        Ok(result?)
+    }
+}
+
+/// Lets the pruner know the committed blocks may be pruned from the database. Failing to record
+/// them only delays the prune.
+struct RecordPrunable;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for RecordPrunable {
+    fn name(&self) -> &'static str {
+        "record_prunable"
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        producer.record_prunable(committed.segments.iter().copied())?;
+        Ok(())
+    }
+}
+
+/// Records the row roots of static files sealed by the run. Manifests compute the roots that
+/// weren't recorded.
+struct RecordRowRoots;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for RecordRowRoots {
+    fn name(&self) -> &'static str {
+        "record_row_roots"
+    }
+
+    fn enabled(&self, producer: &StaticFileProducerInner<F>) -> bool {
+        producer.row_commitments
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let recorded = producer.record_row_roots(committed.highest_before)?;
+        debug!(target: "static_file", recorded, "Recorded row roots of sealed static files");
+        Ok(())
+    }
+}
+
+/// Stores the dictionaries of static files sealed by the run. Their headers keep referencing
+/// none if it fails, and the static files still embed them.
+struct ShareDictionaries;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for ShareDictionaries {
+    fn name(&self) -> &'static str {
+        "share_dictionaries"
+    }
+
+    fn enabled(&self, producer: &StaticFileProducerInner<F>) -> bool {
+        producer.shared_dictionaries
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let shared = producer.share_dictionaries(committed.highest_before)?;
+        debug!(target: "static_file", shared, "Shared dictionaries of sealed static files");
+        Ok(())
+    }
+}
+
+/// Notifies the [`SealHooks`] about static files sealed by the run. Static files that weren't
+/// notified about are still listed in the static files directory.
+struct NotifySealed;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for NotifySealed {
+    fn name(&self) -> &'static str {
+        "notify_sealed"
+    }
+
+    fn enabled(&self, producer: &StaticFileProducerInner<F>) -> bool {
+        producer.seal_hooks.has_hooks()
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let sealed = producer.notify_sealed(committed.highest_before)?;
+        debug!(target: "static_file", sealed, "Notified about sealed static files");
+        Ok(())
+    }
+}
+
+/// Records the roots of header accumulator epochs completed by the run. The next run records the
+/// epochs that weren't.
+struct UpdateEpochRoots;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for UpdateEpochRoots {
+    fn name(&self) -> &'static str {
+        "update_epoch_roots"
+    }
+
+    fn enabled(&self, producer: &StaticFileProducerInner<F>) -> bool {
+        producer.epoch_accumulator
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        _committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let epochs = producer.update_epoch_roots()?;
+        debug!(target: "static_file", epochs, "Recorded header accumulator epoch roots");
+        Ok(())
+    }
+}
+
+/// Removes static files that fell out of the retention window. The next run removes the ones
+/// that weren't.
+struct ApplyRetention;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for ApplyRetention {
+    fn name(&self) -> &'static str {
+        "apply_retention"
+    }
+
+    fn enabled(&self, producer: &StaticFileProducerInner<F>) -> bool {
+        producer.retention.is_some()
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        _committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let outcome = producer.apply_retention()?;
+        debug!(target: "static_file", removed = outcome.removed.len(), lowest = ?outcome.lowest, "Applied static file retention");
+        Ok(())
+    }
+}
+
+/// Moves old static files to the cold directory, and pinned ones back. The next run moves the
+/// ones that weren't.
+struct ApplyTiering;
+
+impl<F: SegmentProviderFactory> PostCommitStep<F> for ApplyTiering {
+    fn name(&self) -> &'static str {
+        "apply_tiering"
+    }
+
+    fn enabled(&self, producer: &StaticFileProducerInner<F>) -> bool {
+        producer.tiering.is_some()
+    }
+
+    fn run(
+        &self,
+        producer: &StaticFileProducerInner<F>,
+        _committed: &CommittedRun<'_>,
+    ) -> Result<(), StaticFileProducerError> {
+        let outcome = producer.apply_tiering()?;
+        debug!(target: "static_file", cold = outcome.cold.len(), hot = outcome.hot.len(), "Applied static file tiering");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        list_static_files,
+        segments::{self, Segment},
+        static_file_producer::{
+            RunOrder, StaticFileProducer, StaticFileProducerInner, StaticFileTargets,
+            StaticFileTargetsError,
+        },
+        test_utils::StaticFileTestHarness,
+        BatchHooks, CommittedRows, FailureKind, FileSizeEstimate, InMemorySink, OverflowPolicy,
+        PreallocationConfig, SegmentProgress, SegmentsConfig, StallClock, StallWatchdog,
+        StaticFileEntry, StaticFileProducerError, StaticFileProducerEvent, StaticFileReader,
+        StaticFileReaderError, WarmupConfig, WarmupMode, WorkersConfig, COMPANION_EXTENSIONS,
+    };
+    use assert_matches::assert_matches;
+    use reth_db::{test_utils::TempDatabase, DatabaseEnv};
+    use reth_nippy_jar::{NippyJar, NippyJarCursor};
+    use reth_provider::{
+        providers::StaticFileProvider, BlockHashReader, HeaderProvider, ProviderError,
+        ProviderFactory, ReceiptProvider, StaticFileProviderFactory, TransactionsProvider,
+        TransactionsProviderExt,
+    };
+    use reth_prune_types::PruneModes;
+    use reth_static_file_types::{
+        find_fixed_range, BuildMetadata, FilterHash, FilterKey, Filters, HighestStaticFiles,
+        InclusionFilter, LowestStaticFiles, PerfectHashingFunction, ReceiptStats, SegmentHeader,
+        SegmentRangeInclusive, SizeHistogram, StaticFileSegment,
+    };
+    use std::{
+        sync::{mpsc::channel, Arc},
+        time::{Duration, Instant, UNIX_EPOCH},
+    };
+    use tempfile::TempDir;
+    /// Sets up the testing environment.
+    ///
+    /// Returns a tuple containing the provider factory and a temporary directory.
+    fn setup() -> (ProviderFactory<Arc<TempDatabase<DatabaseEnv>>>, TempDir) {
+        let StaticFileTestHarness { provider_factory, static_files_dir, .. } =
+            StaticFileTestHarness::new(3, 2..3);
+        (provider_factory, static_files_dir)
+    }
+    
+    /// Test for running the static file producer.
+    #[test]
+    fn run() {
+        let (provider_factory, _temp_static_files_dir) = setup(); // Set up the testing environment.
+ 
+        // Create a new StaticFileProducerInner instance.
+        let static_file_producer =
+            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+ 
+        // Get static file targets based on the highest static files processed.
+        let targets = static_file_producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(1),
+                receipts: Some(1),
+                transactions: Some(1),
+            })
+            .expect("get static file targets");
+        assert_eq!(
+            targets,
+            StaticFileTargets {
+                headers: Some(0..=1),
+                receipts: Some(0..=1),
+                transactions: Some(0..=1)
+            }
+        );
+        // Run the static file producer and check the result.
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(
+            provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(1), receipts: Some(1), transactions: Some(1) }
+        );
+ 
+        // Repeat the process with different highest static files.
+        let targets = static_file_producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(3),
+                receipts: Some(3),
+                transactions: Some(3),
+            })
+            .expect("get static file targets");
+        assert_eq!(
+            targets,
+            StaticFileTargets {
+                headers: Some(2..=3),
+                receipts: Some(2..=3),
+                transactions: Some(2..=3)
+            }
+        );
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(
+            provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+        );
+ 
+        // Test error handling when the block body indices are not found.
+        let targets = static_file_producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(4),
+                receipts: Some(4),
+                transactions: Some(4),
+            })
+            .expect("get static file targets");
+        assert_eq!(
+            targets,
+            StaticFileTargets {
+                headers: Some(4..=4),
+                receipts: Some(4..=4),
+                transactions: Some(4..=4)
+            }
+        );
+        assert_matches!(
+            static_file_producer.run(targets),
+            Err(ProviderError::BlockBodyIndicesNotFound(4))
+        );
+        assert_eq!(
+            provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+        );
+    }
+        
+    /// Tests that the lowest static files are tracked across runs.
+    #[test]
+    fn lowest_static_files() {
+        let (provider_factory, _temp_static_files_dir) = setup();
+        let static_file_producer =
+            StaticFileProducerInner::new(provider_factory, PruneModes::default());
+
+        // Headers were pruned from static files in `setup`
+        assert!(!static_file_producer
+            .is_block_available(StaticFileSegment::Headers, 0)
+            .expect("is block available"));
+
+        let targets = static_file_producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(3),
+                receipts: Some(3),
+                transactions: Some(3),
+            })
+            .expect("get static file targets");
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+
+        assert_eq!(
+            static_file_producer.get_lowest_static_files().expect("get lowest static files"),
+            LowestStaticFiles { headers: Some(0), receipts: Some(0), transactions: Some(0) }
+        );
+        for segment in [StaticFileSegment::Headers, StaticFileSegment::Receipts] {
+            assert!(static_file_producer.is_block_available(segment, 3).unwrap());
+            assert!(!static_file_producer.is_block_available(segment, 4).unwrap());
+        }
+    }
+
+    /// Tests that non-parallel run orders copy all segments in full.
+    #[test]
+    fn run_order() {
+        for run_order in [
+            RunOrder::Sequential(vec![StaticFileSegment::Receipts, StaticFileSegment::Headers]),
+            RunOrder::Interleaved { chunk: 1 },
+        ] {
+            let (provider_factory, _temp_static_files_dir) = setup();
+
+            let mut static_file_producer =
+                StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+            static_file_producer.set_run_order(run_order);
+
+            let targets = static_file_producer
+                .get_static_file_targets(HighestStaticFiles {
+                    headers: Some(3),
+                    receipts: Some(3),
+                    transactions: Some(3),
+                })
+                .expect("get static file targets");
+            assert_matches!(static_file_producer.run(targets), Ok(_));
+            assert_eq!(
+                provider_factory.static_file_provider().get_highest_static_files(),
+                HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+            );
+        }
+    }
+
+    /// Tests that segments copied in parallel on pinned worker threads are all copied.
+    #[test]
+    fn pinned_workers() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+
+        let mut static_file_producer = harness.producer();
+        static_file_producer.set_workers(WorkersConfig {
+            thread_name_prefix: "sf-test".to_string(),
+            pin_to_cores: vec![0],
+            filter_threads: None,
+        });
+
+        let targets = static_file_producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(3),
+                receipts: Some(3),
+                transactions: Some(3),
+            })
+            .expect("get static file targets");
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(
+            harness.provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+        );
+    }
+
+    /// Tests that segments renewing their read transaction every block copy all rows and
+    /// sidecars.
+    #[test]
+    fn read_tx_renewal() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+
+        let mut static_file_producer = harness.producer();
+        static_file_producer.set_read_tx_renewal_blocks(Some(1));
+        assert_eq!(static_file_producer.config().read_tx_renewal_blocks, Some(1));
+
+        let targets = static_file_producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(3),
+                receipts: Some(3),
+                transactions: Some(3),
+            })
+            .expect("get static file targets");
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(
+            harness.provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+        );
+
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        for segment in [StaticFileSegment::Transactions, StaticFileSegment::Receipts] {
+            assert_eq!(reader.block_tx_range(segment, 2).unwrap(), Some(4..6));
+        }
+    }
+
+    /// Tests that a run stopped by its deadline commits the copied blocks and returns them.
+    #[test]
+    fn run_until_deadline() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        let static_file_producer = harness.producer();
+        let all = HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) };
+
+        // Every segment copies its first block, then waits while paused until the deadline
+        let pause = static_file_producer.pause_handle();
+        pause.pause();
+        let events = static_file_producer.subscribe_events(8, OverflowPolicy::DropOldest);
+        let targets = static_file_producer.get_static_file_targets(all).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(100);
+        let produced = static_file_producer.run_until(targets.clone(), deadline).unwrap();
+        pause.resume();
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            assert_eq!(produced.target(segment), Some(&(0..=0)));
+        }
+        // Transaction ranges are reported for the produced blocks
+        let provider = harness.provider_factory.provider().unwrap();
+        let finished = std::iter::from_fn(|| events.try_recv())
+            .find_map(|event| match event {
+                StaticFileProducerEvent::Finished { tx_ranges, .. } => Some(tx_ranges),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(finished, produced.tx_ranges(&provider).unwrap());
+        assert_ne!(finished, targets.tx_ranges(&provider).unwrap());
+        drop(provider);
+        assert_eq!(
+            harness.provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(0), receipts: Some(0), transactions: Some(0) }
+        );
+
+        // The rest is produced by the next run, and a run within its deadline produces it all
+        let targets = static_file_producer.get_static_file_targets(all).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        assert_eq!(static_file_producer.run_until(targets.clone(), deadline).unwrap(), targets);
+        assert_eq!(harness.provider_factory.static_file_provider().get_highest_static_files(), all);
+    }
+
+    /// Tests that a run cancelled by the watchdog keeps the blocks committed before the stall, and
+    /// that the next run copies the rest without the rolled back rows.
+    #[test]
+    fn stalled_rollback() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        let mut static_file_producer = harness.producer();
+        let all = HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) };
+
+        // Headers stall after block 2 is copied, past the last commit at block 1. The clock of
+        // the watchdog is moved past the timeout, and the segment waits until it's reported.
+        let clock = StallClock::manual();
+        static_file_producer.set_commit_interval_blocks(Some(2));
+        static_file_producer.set_run_order(RunOrder::Sequential(vec![StaticFileSegment::Headers]));
+        static_file_producer.set_watchdog(Some(
+            StallWatchdog::new(Duration::from_secs(60)).with_cancel(true).with_clock(clock.clone()),
+        ));
+        let events = static_file_producer.subscribe_events(16, OverflowPolicy::DropOldest);
+        static_file_producer.set_batch_hooks(BatchHooks::new(1).on_batch(move |stats| {
+            if stats.segment == StaticFileSegment::Headers && stats.blocks.start() == 2 {
+                clock.advance(Duration::from_secs(60));
+                while let Some(event) = events.recv_timeout(Duration::from_secs(60)) {
+                    if matches!(event, StaticFileProducerEvent::Stalled { .. }) {
+                        break
+                    }
+                }
+            }
+        }));
+        let targets = static_file_producer.get_static_file_targets(all).unwrap();
+        assert_matches!(
+            static_file_producer.try_run(targets),
+            Err(StaticFileProducerError::Stalled { segment: StaticFileSegment::Headers, .. })
+        );
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        assert_eq!(
+            static_file_provider.get_highest_static_file_block(StaticFileSegment::Headers),
+            Some(1)
+        );
+
+        static_file_producer.set_watchdog(None);
+        static_file_producer.set_batch_hooks(BatchHooks::default());
+        let targets = static_file_producer.get_static_file_targets(all).unwrap();
+        assert_eq!(targets.headers, Some(2..=3));
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(static_file_provider.get_highest_static_files(), all);
+        let reader = StaticFileReader::new(static_file_provider).unwrap();
+        for block in &harness.blocks {
+            assert_eq!(
+                reader.header_by_number(block.number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+        }
+    }
+
+    /// Tests that a run failing in the middle of a segment rolls back the rows copied by every
+    /// segment, so the next run appends every block once.
+    #[test]
+    fn error_rollback() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        let static_file_producer = harness.producer();
+        let events = static_file_producer.subscribe_events(16, OverflowPolicy::DropOldest);
+
+        // Blocks after the tip are missing from the database
+        let targets = StaticFileTargets::builder(HighestStaticFiles::default())
+            .range(StaticFileSegment::Headers, 0..=5)
+            .range(StaticFileSegment::Transactions, 0..=5)
+            .range(StaticFileSegment::Receipts, 0..=5)
+            .build()
+            .unwrap();
+        assert!(static_file_producer.run(targets).is_err());
+        assert!(std::iter::from_fn(|| events.try_recv()).any(|event| matches!(
+            event,
+            StaticFileProducerEvent::Failed { kind: FailureKind::Error, .. }
+        )));
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        assert_eq!(static_file_provider.get_highest_static_files(), HighestStaticFiles::default());
+
+        let all = HighestStaticFiles {
+            headers: Some(harness.tip()),
+            receipts: Some(harness.tip()),
+            transactions: Some(harness.tip()),
+        };
+        let targets = static_file_producer.get_static_file_targets(all).unwrap();
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(static_file_provider.get_highest_static_files(), all);
+        let reader = StaticFileReader::new(static_file_provider).unwrap();
+        for block in &harness.blocks {
+            assert_eq!(
+                reader.header_by_number(block.number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+        }
+    }
+
+    /// Tests that a run filling up the disk rolls back its static files and writers, so the next
+    /// run produces the rolled back blocks again once space is freed. Runs on a quota-limited
+    /// filesystem, e.g. `mount -t tmpfs -o size=1m tmpfs <dir>`, whose path is set in
+    /// `STATIC_FILES_TMPFS`.
+    #[test]
+    #[ignore = "requires a quota-limited tmpfs in STATIC_FILES_TMPFS"]
+    fn disk_full_rollback() {
+        let tmpfs = std::env::var("STATIC_FILES_TMPFS").unwrap();
+        let harness = StaticFileTestHarness::new(300, 2..3);
+        let static_files_dir = tempfile::tempdir_in(&tmpfs).unwrap();
+        let provider_factory = ProviderFactory::new(
+            harness.provider_factory.db_ref().clone(),
+            harness.provider_factory.chain_spec(),
+            StaticFileProvider::read_write(static_files_dir.path()).unwrap(),
+        );
+        let static_file_producer =
+            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+        let events = static_file_producer.subscribe_events(16, OverflowPolicy::DropOldest);
+        let tip = Some(harness.tip());
+        let all = HighestStaticFiles { headers: tip, receipts: tip, transactions: tip };
+
+        // Fill the filesystem, leaving room for the rows of a few blocks only
+        let ballast_path = tempfile::NamedTempFile::new_in(&tmpfs).unwrap().into_temp_path();
+        let mut ballast = std::fs::OpenOptions::new().append(true).open(&ballast_path).unwrap();
+        while std::io::Write::write_all(&mut ballast, &[0; 4096]).is_ok() {}
+        ballast.set_len(ballast.metadata().unwrap().len().saturating_sub(64 * 1024)).unwrap();
+
+        let targets = static_file_producer.get_static_file_targets(all).unwrap();
+        assert!(static_file_producer.run(targets).is_err());
+        assert!(std::iter::from_fn(|| events.try_recv()).any(|event| matches!(
+            event,
+            StaticFileProducerEvent::Failed { kind: FailureKind::DiskFull, .. }
+        )));
+        let static_file_provider = provider_factory.static_file_provider();
+        assert_eq!(static_file_provider.get_highest_static_files(), HighestStaticFiles::default());
+        assert!(list_static_files(static_file_provider.directory()).unwrap().is_empty());
+
+        // The writers don't hold the rolled back rows, so the next run appends every block once
+        drop(ballast);
+        ballast_path.close().unwrap();
+        let targets = static_file_producer.get_static_file_targets(all).unwrap();
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(static_file_provider.get_highest_static_files(), all);
+        let reader = StaticFileReader::new(static_file_provider).unwrap();
+        for block in &harness.blocks {
+            assert_eq!(
+                reader.header_by_number(block.number).unwrap().as_ref(),
+                Some(block.header.header())
+            );
+        }
+    }
+
+    /// Tests that disabled segments get no targets, and runs with targets for them are refused.
+    #[test]
+    fn disabled_segments() {
+        let (provider_factory, _temp_static_files_dir) = setup();
+
+        let mut static_file_producer =
+            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+        let mut segments = SegmentsConfig::default();
+        segments.receipts.enabled = false;
+        static_file_producer.set_segments(segments);
+
+        let finalized =
+            HighestStaticFiles { headers: Some(1), receipts: Some(1), transactions: Some(1) };
+        let targets = static_file_producer
+            .get_static_file_targets(finalized)
+            .expect("get static file targets");
+        assert_eq!(
+            targets,
+            StaticFileTargets { headers: Some(0..=1), receipts: None, transactions: Some(0..=1) }
+        );
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+        assert_eq!(
+            provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(1), receipts: None, transactions: Some(1) }
+        );
+
+        let targets = StaticFileTargets::builder(
+            provider_factory.static_file_provider().get_highest_static_files(),
+        )
+        .tip(StaticFileSegment::Receipts, 1)
+        .build()
+        .unwrap();
+        assert_matches!(
+            static_file_producer.try_run(targets),
+            Err(StaticFileProducerError::SegmentDisabled(StaticFileSegment::Receipts))
+        );
+    }
+
+    /// Tests that committed rows are published after a run, and readers are clamped to them.
+    #[test]
+    fn committed_rows() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        harness.run().unwrap();
+
+        let last_tx = harness.blocks.iter().map(|block| block.body.len() as u64).sum::<u64>() - 1;
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let committed = CommittedRows::read(static_file_provider.directory()).unwrap().unwrap();
+        assert_eq!(
+            (committed.headers, committed.transactions, committed.receipts),
+            (Some(3), Some(last_tx), Some(last_tx))
+        );
+
+        let reader = StaticFileReader::new(static_file_provider).unwrap();
+        assert_eq!(reader.committed_rows(), Some(committed));
+        assert!(reader.header_by_number(3).unwrap().is_some());
+        assert!(reader.receipt(last_tx).unwrap().is_some());
+        assert_eq!(reader.receipts_by_tx_range(0..).unwrap().len() as u64, last_tx + 1);
+    }
+
+    /// Tests that readers of secondary processes pick up commits published after they were
+    /// created.
+    #[test]
+    fn refresh_secondary_reader() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        let static_file_producer = harness.producer();
+        let headers = |block| HighestStaticFiles { headers: Some(block), ..Default::default() };
+        static_file_producer
+            .run(static_file_producer.get_static_file_targets(headers(1)).unwrap())
+            .unwrap();
+
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let mut reader = StaticFileReader::new(static_file_provider).unwrap();
+        assert!(!reader.refresh().unwrap());
+
+        static_file_producer
+            .run(static_file_producer.get_static_file_targets(headers(3)).unwrap())
+            .unwrap();
+        assert!(reader.header_by_number(3).unwrap().is_none());
+        assert!(reader.refresh().unwrap());
+        assert!(reader.header_by_number(3).unwrap().is_some());
+
+        let committed = reader.committed_rows().unwrap();
+        assert_eq!(committed.get(StaticFileSegment::Headers), Some(3));
+        assert_eq!(committed.get(StaticFileSegment::Receipts), None);
+    }
+
+    /// Tests that older static files of the same range are reported and removed.
+    #[test]
+    fn remove_duplicate_ranges() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        harness.run().unwrap();
+        let static_file_producer = harness.producer();
+        assert!(static_file_producer.check_duplicate_ranges(None).unwrap().issues.is_empty());
+
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let entry = list_static_files(static_file_provider.directory()).unwrap().remove(0);
+        let duplicate = StaticFileEntry {
+            path: entry
+                .path
+                .with_file_name(format!("{}_none_lz4", entry.segment.filename(&entry.block_range))),
+            ..entry.clone()
+        };
+        std::fs::copy(&entry.path, &duplicate.path).unwrap();
+        for extension in COMPANION_EXTENSIONS {
+            if entry.companion_path(extension).exists() {
+                std::fs::copy(entry.companion_path(extension), duplicate.companion_path(extension))
+                    .unwrap();
+            }
+        }
+        std::fs::File::options()
+            .write(true)
+            .open(&duplicate.path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH)
+            .unwrap();
+
+        let scan = static_file_producer.check_duplicate_ranges(None).unwrap();
+        assert_eq!(scan.duplicates, vec![duplicate.clone()]);
+        assert_eq!(static_file_producer.remove_duplicate_ranges(None).unwrap(), vec![duplicate]);
+        assert_eq!(list_static_files(static_file_provider.directory()).unwrap()[0], entry);
+        assert!(static_file_producer.check_duplicate_ranges(None).unwrap().duplicates.is_empty());
+        assert!(static_file_provider.header_by_number(3).unwrap().is_some());
+    }
+
+    /// Tests that produced static files record the build of the producer.
+    #[test]
+    fn build_metadata() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        harness.run().unwrap();
+
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let path = static_file_provider
+            .directory()
+            .join(StaticFileSegment::Headers.filename(&find_fixed_range(3)));
+        let jar = NippyJar::<SegmentHeader>::load(&path).unwrap();
+        assert_eq!(jar.user_header().build(), Some(&crate::build_metadata()));
+
+        // Rows of static files of another schema version are never decoded, even without
+        // checking the schema versions first
+        let path = static_file_provider
+            .directory()
+            .join(StaticFileSegment::Transactions.filename(&find_fixed_range(3)));
+        let mut jar = NippyJar::<SegmentHeader>::load(&path).unwrap();
+        let build = BuildMetadata { schema_version: 0, ..crate::build_metadata() };
+        jar.user_header_mut().set_build(Some(build));
+        jar.freeze_config().unwrap();
+
+        let reader = StaticFileReader::new(static_file_provider).unwrap();
+        assert!(reader.header_by_number(3).unwrap().is_some());
+        assert!(reader.transaction_by_id(0).is_err());
+        assert!(reader.transactions_by_tx_range(0..1).is_err());
+        assert_matches!(
+            reader.check_schema_versions(),
+            Err(StaticFileReaderError::IncompatibleSchema {
+                segment: StaticFileSegment::Transactions,
+                ..
+            })
+        );
+    }
+
+    #[test]
+    fn post_merge_total_difficulty() {
+        use alloy_primitives::U256;
+        use reth_db::tables;
+        use reth_db_api::{database::Database, models::CompactU256, transaction::DbTxMut};
+
+        let harness = StaticFileTestHarness::new(3, 1..2);
+        let terminal = U256::from(1_000);
+
+        // Blocks 2 and 3 are post-merge, so their total difficulty doesn't grow
+        let tx = harness.provider_factory.db_ref().tx_mut().unwrap();
+        for (block, difficulty, total_difficulty) in
+            [(0, 1, 998), (1, 1, 999), (2, 0, 1_000), (3, 0, 1_000)]
+        {
+            let mut header = harness.blocks[block as usize].header.header().clone();
+            header.difficulty = U256::from(difficulty);
+            tx.put::<tables::Headers>(block, header).unwrap();
+            tx.put::<tables::HeaderTerminalDifficulties>(
+                block,
+                CompactU256::from(U256::from(total_difficulty)),
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+        harness.run().unwrap();
+
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let jar = static_file_provider
+            .get_segment_provider_from_block(StaticFileSegment::Headers, 3, None)
+            .unwrap();
+        assert_eq!(jar.user_header().terminal_difficulty(), Some(terminal));
+        drop(jar);
+        // Rows keep the total difficulty, so it's read through the provider as well
+        assert_eq!(static_file_provider.header_td_by_number(1).unwrap(), Some(U256::from(999)));
+        assert_eq!(static_file_provider.header_td_by_number(3).unwrap(), Some(terminal));
+
+        let reader = StaticFileReader::new(static_file_provider).unwrap();
+        assert_eq!(reader.header_td_by_number(1).unwrap(), Some(U256::from(999)));
+        assert_eq!(reader.header_td_by_number(3).unwrap(), Some(terminal));
+    }
+
+    #[test]
+    fn target_tx_ranges() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        producer.set_run_history(Some(10));
+        let targets = producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(harness.tip()),
+                receipts: Some(harness.tip()),
+                transactions: Some(harness.tip()),
+            })
+            .unwrap();
+
+        // Transactions and receipts of the same blocks share their transaction range
+        let provider = harness.provider_factory.provider().unwrap();
+        let tx_range = provider.transaction_range_by_block_range(0..=harness.tip()).unwrap();
+        let tx_range = SegmentRangeInclusive::from(tx_range);
+        let tx_ranges = vec![
+            (StaticFileSegment::Transactions, tx_range),
+            (StaticFileSegment::Receipts, tx_range),
+        ];
+        assert_eq!(targets.tx_ranges(&provider).unwrap(), tx_ranges);
+        drop(provider);
 
-        Ok(highest_static_files)
+        producer.run(targets).unwrap();
+        assert_eq!(producer.run_history().unwrap()[0].produced_tx_ranges, tx_ranges);
     }
 
-    /// Returns a static file targets at the provided finalized block numbers per segment.
-    /// The target is determined by the check against highest `static_files` using
-    /// [`reth_provider::providers::StaticFileProvider::get_highest_static_files`].
-    pub fn get_static_file_targets(
-        &self,
-        finalized_block_numbers: HighestStaticFiles,
-    ) -> ProviderResult<StaticFileTargets> {
-        let highest_static_files =
-            self.provider_factory.static_file_provider().get_highest_static_files();
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn preallocated_segments() {
+        use std::os::unix::fs::MetadataExt;
 
-        let targets = StaticFileTargets {
-            headers: finalized_block_numbers.headers.and_then(|finalized_block_number| {
-                self.get_static_file_target(highest_static_files.headers, finalized_block_number)
-            }),
-            // StaticFile receipts only if they're not pruned according to the user configuration
-            receipts: if self.prune_modes.receipts.is_none() &&
-                self.prune_modes.receipts_log_filter.is_empty()
-            {
-                finalized_block_numbers.receipts.and_then(|finalized_block_number| {
-                    self.get_static_file_target(
-                        highest_static_files.receipts,
-                        finalized_block_number,
-                    )
-                })
-            } else {
-                None
-            },
-            transactions: finalized_block_numbers.transactions.and_then(|finalized_block_number| {
-                // For each finalized block number, determine the range of block numbers for static files.
-                self.get_static_file_target(
-                    highest_static_files.transactions,// The highest static file already processed.
-                    finalized_block_number, // The current finalized block number.
-                )
-            }),
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let directory = harness.static_files_dir.path();
+        let mut producer = harness.producer();
+        producer.set_preallocation(PreallocationConfig { enabled: true, max_file_bytes: None });
+        // No static file is sealed yet to estimate the size of the next ones from
+        assert_eq!(producer.preallocation_estimate(StaticFileSegment::Receipts).unwrap(), None);
+
+        let estimate = FileSizeEstimate {
+            data_bytes_per_block: 1 << 20,
+            offsets_bytes_per_block: 0,
+            max_file_bytes: Some(1 << 20),
         };
+        let allocated = |path: &std::path::Path| path.metadata().unwrap().blocks() * 512;
+        // Filesystems without preallocation reserve nothing
+        let probe = tempfile::NamedTempFile::new_in(directory).unwrap();
+        estimate.preallocate(probe.path(), 1).unwrap();
+        let supported = allocated(probe.path()) >= 1 << 20;
+        drop(probe);
 
-        trace!(
-            target: "static_file",
-            ?finalized_block_numbers, // Log the finalized block numbers.
-            ?highest_static_files, // Log the highest static files processed so far.
-            ?targets, // Log the targets for static files to process.
-            any = %targets.any(), // Log if there are any targets.
-            "StaticFile targets" // Log message.
+        // The files of every segment are preallocated when they're started
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            let progress = SegmentProgress::new(segment).with_preallocation(Some(estimate));
+            producer
+                .copy_segment(producer.segment(segment).as_ref(), 0..=harness.tip(), &progress)
+                .unwrap();
+            let data_path = directory.join(segment.filename(&find_fixed_range(0)));
+            assert_eq!(allocated(&data_path) >= 1 << 20, supported, "{segment}");
+        }
+    }
+
+    #[test]
+    fn chain_spec() {
+        use crate::ChainMismatch;
+        use reth_chainspec::{MAINNET, SEPOLIA};
+
+        let harness = StaticFileTestHarness::new(3, 1..2);
+        let mut producer = harness.producer();
+        producer.set_chain_spec(Some(MAINNET.clone()));
+        let targets = producer
+            .get_static_file_targets(HighestStaticFiles {
+                headers: Some(harness.tip()),
+                ..Default::default()
+            })
+            .unwrap();
+        producer.run(targets).unwrap();
+
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let jar = static_file_provider
+            .get_segment_provider_from_block(StaticFileSegment::Headers, 3, None)
+            .unwrap();
+        assert_eq!(jar.user_header().chain_id(), Some(MAINNET.chain.id()));
+        drop(jar);
+
+        // Static files of another chain are refused before anything is written
+        let mut producer = harness.producer();
+        producer.set_chain_spec(Some(SEPOLIA.clone()));
+        let targets = producer
+            .get_static_file_targets(HighestStaticFiles {
+                transactions: Some(harness.tip()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_matches!(
+            producer.try_run(targets),
+            Err(StaticFileProducerError::ChainMismatch(ChainMismatch {
+                segment: StaticFileSegment::Headers,
+                found: 1,
+                ..
+            }))
         );
+        assert_eq!(
+            static_file_provider.get_highest_static_block(StaticFileSegment::Transactions),
+            None
+        );
+    }
 
-        Ok(targets)
+    #[test]
+    fn transaction_type_stats() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        producer.set_transaction_tx_type_stats(true);
+        let targets = producer
+            .get_static_file_targets(HighestStaticFiles {
+                transactions: Some(harness.tip()),
+                ..Default::default()
+            })
+            .unwrap();
+        producer.run(targets).unwrap();
+
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let jar = static_file_provider
+            .get_segment_provider_from_block(StaticFileSegment::Transactions, 3, None)
+            .unwrap();
+        let stats = jar.user_header().tx_type_stats().unwrap().clone();
+        drop(jar);
+        let transactions = harness.blocks.iter().flat_map(|block| &block.body).collect::<Vec<_>>();
+        assert_eq!(stats.count(), transactions.len() as u64);
+        for count in stats.iter() {
+            let expected = transactions
+                .iter()
+                .filter(|transaction| u8::from(transaction.tx_type()) == count.tx_type)
+                .count();
+            assert_eq!(count.count, expected as u64);
+            assert!(count.bytes > 0);
+        }
+
+        // Unwound transactions are no longer covered by the statistics
+        let mut writer =
+            static_file_provider.latest_writer(StaticFileSegment::Transactions).unwrap();
+        writer.prune_transactions(1, 3).unwrap();
+        writer.commit().unwrap();
+        assert_eq!(writer.user_header().tx_type_stats(), None);
     }
-    /// Determines the range of block numbers for static files based on the highest processed block
-    /// and the current finalized block number.
 
-    /// * `highest_static_file` - The highest block number already processed for static files.
-    /// * `finalized_block_number` - The current finalized block number.
+    #[test]
+    fn receipt_stats() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        producer.set_receipt_stats(true);
+        let targets = producer
+            .get_static_file_targets(HighestStaticFiles {
+                receipts: Some(harness.tip()),
+                ..Default::default()
+            })
+            .unwrap();
+        producer.run(targets).unwrap();
 
+        let provider = harness.provider_factory.provider().unwrap();
+        let mut expected = ReceiptStats::default();
+        for block in &harness.blocks {
+            let receipts = provider.receipts_by_block(block.number.into()).unwrap().unwrap();
+            let mut cumulative_gas_used = 0;
+            for (receipt, transaction) in receipts.iter().zip(&block.body) {
+                expected.add(
+                    receipt.cumulative_gas_used - cumulative_gas_used,
+                    receipt.logs.len() as u64,
+                    transaction.kind().is_create(),
+                );
+                cumulative_gas_used = receipt.cumulative_gas_used;
+            }
+        }
+        assert!(expected.gas_used > 0);
 
-    /// Returns an optional inclusive range of block numbers to be processed. Returns None if the range is empty.
-    fn get_static_file_target(
-        &self,
-        highest_static_file: Option<BlockNumber>,
-        finalized_block_number: BlockNumber,
-    ) -> Option<RangeInclusive<BlockNumber>> {
-        let range = highest_static_file.map_or(0, |block| block + 1)..=finalized_block_number;
-        (!range.is_empty()).then_some(range)
+        let jar = harness
+            .provider_factory
+            .static_file_provider()
+            .get_segment_provider_from_block(StaticFileSegment::Receipts, 3, None)
+            .unwrap();
+        assert_eq!(jar.user_header().receipt_stats(), Some(&expected));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::static_file_producer::{
-        StaticFileProducer, StaticFileProducerInner, StaticFileTargets,
-    };
-    use alloy_primitives::{B256, U256};
-    use assert_matches::assert_matches;
-    use reth_db::{test_utils::TempDatabase, DatabaseEnv};
-    use reth_db_api::{database::Database, transaction::DbTx};
-    use reth_provider::{
-        providers::StaticFileWriter, ProviderError, ProviderFactory, StaticFileProviderFactory,
-    };
-    use reth_prune_types::PruneModes;
-    use reth_stages::test_utils::{StorageKind, TestStageDB};
-    use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
-    use reth_testing_utils::{
-        generators,
-        generators::{random_block_range, random_receipt},
-    };
-    use std::{
-        sync::{mpsc::channel, Arc},
-        time::Duration,
-    };
-    use tempfile::TempDir;
-    /// Sets up the testing environment.
-    
-    /// Returns a tuple containing the provider factory and a temporary directory.
+    #[test]
+    fn size_histograms() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        producer.set_size_histograms(true);
+        harness.run_with(&producer).unwrap();
 
-    fn setup() -> (ProviderFactory<Arc<TempDatabase<DatabaseEnv>>>, TempDir) {
-        let mut rng = generators::rng();// Create a random number generator.
-        let db = TestStageDB::default(); // Create a default test database.
-         // Generate random blocks and insert them into the database.
-        let blocks = random_block_range(&mut rng, 0..=3, B256::ZERO, 2..3);
-        db.insert_blocks(blocks.iter(), StorageKind::Database(None)).expect("insert blocks");
-        // Unwind headers from static_files and manually insert them into the database, so we're
-        // able to check that static_file_producer works
-        let static_file_provider = db.factory.static_file_provider();
-        let mut static_file_writer = static_file_provider
-            .latest_writer(StaticFileSegment::Headers)
-            .expect("get static file writer for headers");
-        static_file_writer.prune_headers(blocks.len() as u64).unwrap();
-        static_file_writer.commit().expect("prune headers");
-
-        let tx = db.factory.db_ref().tx_mut().expect("init tx");
-        blocks.iter().for_each(|block| {
-            TestStageDB::insert_header(None, &tx, &block.header, U256::ZERO)
-                .expect("insert block header");
-        });
-        tx.commit().expect("commit tx");
+        for entry in list_static_files(harness.static_files_dir.path()).unwrap() {
+            let jar = NippyJar::<SegmentHeader>::load(&entry.path).unwrap();
+            let column_sizes = jar.user_header().column_sizes().unwrap();
+            assert_eq!(column_sizes.len(), jar.columns());
+            assert!(column_sizes.iter().all(|histogram| histogram.count() == jar.rows() as u64));
+            if entry.segment.is_headers() {
+                continue
+            }
 
-        let mut receipts = Vec::new();
-        for block in &blocks {
-            for transaction in &block.body {
-                receipts
-                    .push((receipts.len() as u64, random_receipt(&mut rng, transaction, Some(0))));
+            // Transactions and receipts are stored as encoded in the database
+            let mut expected = SizeHistogram::default();
+            let mut cursor = NippyJarCursor::new(&jar).unwrap();
+            for row in 0..jar.rows() {
+                expected.add(cursor.row_by_number(row).unwrap().unwrap()[0].len() as u64);
             }
+            assert_eq!(column_sizes, [expected]);
         }
-        db.insert_receipts(receipts).expect("insert receipts");
 
-        let provider_factory = db.factory;
-        (provider_factory, db.temp_static_files_dir)
+        // Static files appended to without histograms get none
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let headers = |block| HighestStaticFiles { headers: Some(block), ..Default::default() };
+        let without_histograms = harness.producer();
+        without_histograms
+            .run(without_histograms.get_static_file_targets(headers(1)).unwrap())
+            .unwrap();
+        let mut with_histograms = harness.producer();
+        with_histograms.set_size_histograms(true);
+        with_histograms.run(with_histograms.get_static_file_targets(headers(3)).unwrap()).unwrap();
+        let jar = harness
+            .provider_factory
+            .static_file_provider()
+            .get_segment_provider_from_block(StaticFileSegment::Headers, 3, None)
+            .unwrap();
+        assert_eq!(jar.user_header().column_sizes(), None);
     }
-    
-    /// Test for running the static file producer.
+
     #[test]
-    fn run() {
-        let (provider_factory, _temp_static_files_dir) = setup(); // Set up the testing environment.
- 
-        // Create a new StaticFileProducerInner instance.
-        let static_file_producer =
-            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
- 
-        // Get static file targets based on the highest static files processed.
-        let targets = static_file_producer
-            .get_static_file_targets(HighestStaticFiles {
-                headers: Some(1),
-                receipts: Some(1),
-                transactions: Some(1),
+    fn warmup_on_startup() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let mut producer = harness.producer();
+        producer.recover_tails().unwrap();
+        assert!(producer.warmed_files.lock().is_none());
+
+        // Newest static files of every segment are warmed up, and released once disabled
+        producer.set_warmup(Some(WarmupConfig { newest_files: 1, mode: WarmupMode::Lock }));
+        producer.recover_tails().unwrap();
+        {
+            let warmed_files = producer.warmed_files.lock();
+            let warmed = warmed_files.as_ref().unwrap();
+            let entries = list_static_files(harness.static_files_dir.path()).unwrap();
+            assert_eq!(warmed.entries, entries);
+            assert!(warmed.bytes > 0);
+        }
+        producer.set_warmup(None);
+        assert!(producer.warmed_files.lock().is_none());
+    }
+
+    #[test]
+    fn block_source() {
+        use crate::{
+            test_utils::{fixture_blocks, fixture_receipts},
+            BlockSource, BlockSourceError, SourceBlock, TrustedCheckpoint,
+        };
+        use alloy_primitives::{BlockNumber, Bloom, B256, U256};
+        use reth_primitives::proofs::calculate_receipt_root_no_memo;
+        use reth_provider::test_utils::create_test_provider_factory;
+        use std::ops::RangeInclusive;
+
+        /// Fixture blocks, whose headers commit to their fixture receipts.
+        #[derive(Debug)]
+        struct FixtureSource(Vec<SourceBlock>);
+
+        impl BlockSource for FixtureSource {
+            fn blocks(
+                &self,
+                block_range: RangeInclusive<BlockNumber>,
+                _segment: StaticFileSegment,
+            ) -> Result<Vec<SourceBlock>, BlockSourceError> {
+                Ok(self.0[*block_range.start() as usize..=*block_range.end() as usize].to_vec())
+            }
+        }
+
+        let blocks = fixture_blocks(0..=3, B256::ZERO, 1..3);
+        let mut receipts = fixture_receipts(&blocks, 0, 1).into_iter().map(|(_, receipt)| receipt);
+        let (mut parent, mut total_difficulty) = (B256::ZERO, U256::ZERO);
+        let source = blocks
+            .into_iter()
+            .map(|block| {
+                let receipts = receipts.by_ref().take(block.body.len()).collect::<Vec<_>>();
+                let mut header = block.header.unseal();
+                header.parent_hash = parent;
+                header.gas_used = receipts.last().map_or(0, |receipt| receipt.cumulative_gas_used);
+                header.logs_bloom = receipts.iter().fold(Bloom::ZERO, |mut logs_bloom, receipt| {
+                    logs_bloom.accrue_bloom(&receipt.bloom_slow());
+                    logs_bloom
+                });
+                header.receipts_root =
+                    calculate_receipt_root_no_memo(&receipts.iter().collect::<Vec<_>>());
+                total_difficulty += header.difficulty;
+                let header = header.seal_slow();
+                parent = header.hash();
+                SourceBlock {
+                    header,
+                    total_difficulty: Some(total_difficulty),
+                    transactions: block.body,
+                    receipts,
+                }
             })
-            .expect("get static file targets");
+            .collect::<Vec<_>>();
+        let targets = StaticFileTargets {
+            headers: Some(0..=3),
+            receipts: Some(0..=3),
+            transactions: Some(0..=3),
+        };
+
+        let genesis =
+            TrustedCheckpoint { number: 0, hash: source[0].header.hash(), total_difficulty: None };
+
+        // Blocks of another chain are refused
+        let provider_factory = create_test_provider_factory();
+        let mut producer =
+            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+        let checkpoint = TrustedCheckpoint { hash: B256::ZERO, ..genesis };
+        producer.set_block_source(Some((Arc::new(FixtureSource(source.clone())), checkpoint)));
+        assert_matches!(
+            producer.try_run(targets.clone()),
+            Err(StaticFileProducerError::Provider(ProviderError::NippyJar(_)))
+        );
+
+        // Blocks are copied without any of them in the database
+        let provider_factory = create_test_provider_factory();
+        let mut producer =
+            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+        producer.set_block_source(Some((Arc::new(FixtureSource(source.clone())), genesis)));
+        producer.run(targets.clone()).unwrap();
+
+        let static_file_provider = provider_factory.static_file_provider();
+        assert_eq!(static_file_provider.block_hash(3).unwrap(), Some(source[3].header.hash()));
+        // Total difficulties are accumulated from the headers
         assert_eq!(
-            targets,
-            StaticFileTargets {
-                headers: Some(0..=1),
-                receipts: Some(0..=1),
-                transactions: Some(0..=1)
-            }
+            static_file_provider.header_td_by_number(3).unwrap(),
+            source[3].total_difficulty
         );
-        // Run the static file producer and check the result.
-        assert_matches!(static_file_producer.run(targets), Ok(_));
+        let transactions = source.iter().flat_map(|block| &block.transactions).collect::<Vec<_>>();
+        let last_tx = transactions.len() as u64 - 1;
         assert_eq!(
-            provider_factory.static_file_provider().get_highest_static_files(),
-            HighestStaticFiles { headers: Some(1), receipts: Some(1), transactions: Some(1) }
+            static_file_provider.transaction_by_id(last_tx).unwrap().as_ref(),
+            transactions.last().copied()
         );
- 
-        // Repeat the process with different highest static files.
-        let targets = static_file_producer
-            .get_static_file_targets(HighestStaticFiles {
-                headers: Some(3),
-                receipts: Some(3),
-                transactions: Some(3),
-            })
-            .expect("get static file targets");
         assert_eq!(
-            targets,
-            StaticFileTargets {
-                headers: Some(2..=3),
-                receipts: Some(2..=3),
-                transactions: Some(2..=3)
-            }
+            static_file_provider.receipt(last_tx).unwrap().as_ref(),
+            source[3].receipts.last()
         );
-        assert_matches!(static_file_producer.run(targets), Ok(_));
+
+        // Receipts that don't match their header, and reported total difficulties that don't
+        // match the headers, are refused
+        let mut tampered_receipts = source.clone();
+        tampered_receipts[2].receipts[0].success = !tampered_receipts[2].receipts[0].success;
+        let mut tampered_total_difficulty = source;
+        tampered_total_difficulty[2].total_difficulty = Some(U256::MAX);
+        for (tampered, segment) in [
+            (tampered_receipts, StaticFileSegment::Receipts),
+            (tampered_total_difficulty, StaticFileSegment::Headers),
+        ] {
+            let provider_factory = create_test_provider_factory();
+            let mut producer =
+                StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+            producer.set_block_source(Some((Arc::new(FixtureSource(tampered)), genesis)));
+            let targets = StaticFileTargets {
+                headers: (segment == StaticFileSegment::Headers).then_some(0..=3),
+                receipts: segment.is_receipts().then_some(0..=3),
+                transactions: None,
+            };
+            assert_matches!(
+                producer.try_run(targets),
+                Err(StaticFileProducerError::Provider(ProviderError::NippyJar(_)))
+            );
+            assert_eq!(
+                provider_factory.static_file_provider().get_highest_static_block(segment),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn block_boundaries() {
+        let harness = StaticFileTestHarness::new(3, 2..3);
+        harness.run().unwrap();
+
+        // Rows of a block are found without the block body indices of the database
+        let reader =
+            StaticFileReader::new(harness.provider_factory.static_file_provider()).unwrap();
+        for segment in [StaticFileSegment::Transactions, StaticFileSegment::Receipts] {
+            assert_eq!(reader.block_tx_range(segment, 2).unwrap(), Some(4..6));
+            assert_eq!(reader.block_tx_range(segment, 4).unwrap(), None);
+        }
+        assert_eq!(reader.block_tx_range(StaticFileSegment::Headers, 2).unwrap(), None);
         assert_eq!(
-            provider_factory.static_file_provider().get_highest_static_files(),
+            reader.receipts_by_block(2.into()).unwrap(),
+            Some(reader.receipts_by_tx_range(4..6).unwrap())
+        );
+        assert_eq!(
+            reader.transactions_by_block(2.into()).unwrap(),
+            Some(harness.blocks[2].body.clone())
+        );
+    }
+
+    /// Tests that targets are copied into memory without touching static files on disk.
+    #[test]
+    fn run_in_memory() {
+        let (provider_factory, _temp_static_files_dir) = setup();
+        let static_file_provider = provider_factory.static_file_provider();
+        let highest_static_files = static_file_provider.get_highest_static_files();
+
+        let static_file_producer =
+            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
+        let mut sink = InMemorySink::new();
+        let reader = sink.reader();
+        let targets = StaticFileTargets::builder(reader.highest_static_files())
+            .tip(StaticFileSegment::Headers, 3)
+            .tip(StaticFileSegment::Transactions, 3)
+            .tip(StaticFileSegment::Receipts, 3)
+            .build()
+            .unwrap();
+        assert_matches!(static_file_producer.run_in_memory(targets, &mut sink), Ok(_));
+
+        assert_eq!(
+            reader.highest_static_files(),
             HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
         );
- 
-        // Test error handling when the block body indices are not found.
+        let provider = provider_factory.provider().unwrap();
+        assert_eq!(reader.header_by_number(2), provider.header_by_number(2).unwrap());
+        assert_eq!(reader.receipt(0), provider.receipt(0).unwrap());
+        assert!(reader.transaction_by_id(0).is_some());
+        assert_eq!(static_file_provider.get_highest_static_files(), highest_static_files);
+    }
+
+    /// Tests that filters of sealed static files are rebuilt without touching their data.
+    #[test]
+    fn rebuild_filters() {
+        let (provider_factory, _temp_static_files_dir) = setup();
+
+        let mut static_file_producer =
+            StaticFileProducerInner::new(provider_factory.clone(), PruneModes::default());
         let targets = static_file_producer
             .get_static_file_targets(HighestStaticFiles {
-                headers: Some(4),
-                receipts: Some(4),
-                transactions: Some(4),
+                headers: Some(3),
+                receipts: Some(3),
+                transactions: Some(3),
             })
             .expect("get static file targets");
-        assert_eq!(
-            targets,
-            StaticFileTargets {
-                headers: Some(4..=4),
-                receipts: Some(4..=4),
-                transactions: Some(4..=4)
-            }
+        assert_matches!(static_file_producer.run(targets), Ok(_));
+
+        let static_file_provider = provider_factory.static_file_provider();
+        let block_range = find_fixed_range(3);
+        let path = static_file_provider
+            .directory()
+            .join(StaticFileSegment::Headers.filename(&block_range));
+        let data = std::fs::read(&path).unwrap();
+        let header = static_file_provider.header_by_number(2).unwrap();
+
+        // Only receipts may be indexed with CHD, whose sidecar is removed once they're indexed
+        // with another function
+        let chd = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Chd);
+        assert_matches!(
+            static_file_producer.rebuild_filters(
+                StaticFileSegment::Headers,
+                block_range,
+                chd,
+                FilterHash::Identity
+            ),
+            Err(StaticFileProducerError::Provider(ProviderError::NippyJar(_)))
         );
         assert_matches!(
-            static_file_producer.run(targets),
-            Err(ProviderError::BlockBodyIndicesNotFound(4))
+            static_file_producer.rebuild_filters(
+                StaticFileSegment::Receipts,
+                block_range,
+                chd,
+                FilterHash::Identity
+            ),
+            Ok(())
         );
-        assert_eq!(
-            provider_factory.static_file_provider().get_highest_static_files(),
-            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+        let chd_path = static_file_provider.directory().join(format!(
+            "{}.{}",
+            StaticFileSegment::Receipts.filename(&block_range),
+            crate::CHD_INDEX_EXTENSION
+        ));
+        assert!(chd_path.exists());
+
+        let filters = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::GoFmph);
+        for segment in [StaticFileSegment::Headers, StaticFileSegment::Receipts] {
+            assert_matches!(
+                static_file_producer.rebuild_filters(
+                    segment,
+                    block_range,
+                    filters,
+                    FilterHash::Identity
+                ),
+                Ok(())
+            );
+        }
+        assert!(!chd_path.exists());
+        let jar = NippyJar::<SegmentHeader>::load(&path).unwrap();
+        assert!(jar.user_header().filter_fpp().is_some());
+        assert_eq!(jar.user_header().filter_hash(), FilterHash::Identity);
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert_eq!(static_file_provider.header_by_number(2).unwrap(), header);
+
+        // Headers are looked up by hash by the static file provider, so their keys can't be
+        // hashed, and keyed hashes need their secret
+        let keyed = FilterHash::KeyedSipHash;
+        for segment in [StaticFileSegment::Headers, StaticFileSegment::Receipts] {
+            assert_matches!(
+                static_file_producer.rebuild_filters(segment, block_range, filters, keyed),
+                Err(StaticFileProducerError::Provider(ProviderError::NippyJar(_)))
+            );
+        }
+        static_file_producer.set_filter_key(Some(FilterKey::new([7; 16])));
+        assert_matches!(
+            static_file_producer.rebuild_filters(
+                StaticFileSegment::Receipts,
+                block_range,
+                filters,
+                keyed
+            ),
+            Ok(())
+        );
+        let path = static_file_provider
+            .directory()
+            .join(StaticFileSegment::Receipts.filename(&block_range));
+        let config = std::fs::read(path.with_extension("conf")).unwrap();
+        let jar = NippyJar::<SegmentHeader>::load(&path).unwrap();
+        assert_eq!(jar.user_header().filter_hash(), keyed);
+        assert!(!config.windows(16).any(|window| window == [7; 16]));
+
+        assert_matches!(
+            static_file_producer.rebuild_filters(
+                StaticFileSegment::Headers,
+                block_range,
+                Filters::WithoutFilters,
+                FilterHash::Identity
+            ),
+            Err(StaticFileProducerError::Provider(ProviderError::NippyJar(_)))
         );
     }
-        
+
     /// Tests that a cloneable [`StaticFileProducer`] type is not susceptible to any race condition.
     #[test]
     fn only_one() {
@@ -454,5 +3853,62 @@ mod tests {
             assert!(only_one.take().is_some_and(|_| target.any()) || !target.any())
         }
     }
+
+    /// Tests that [`StaticFileTargets::builder`] normalizes tips and rejects invalid targets.
+    #[test]
+    fn targets_builder() {
+        let highest =
+            HighestStaticFiles { headers: Some(9), receipts: None, transactions: Some(4) };
+
+        let targets = StaticFileTargets::builder(highest)
+            .tip(StaticFileSegment::Headers, 20)
+            .range(StaticFileSegment::Receipts, 0..=3)
+            .tip(StaticFileSegment::Transactions, 4)
+            .build()
+            .expect("valid targets");
+        assert_eq!(
+            targets,
+            StaticFileTargets { headers: Some(10..=20), receipts: Some(0..=3), transactions: None }
+        );
+        assert!(targets.is_contiguous_to_highest_static_files(highest));
+
+        // Tip goes backwards
+        assert_eq!(
+            StaticFileTargets::builder(highest).tip(StaticFileSegment::Headers, 5).build(),
+            Err(StaticFileTargetsError::BelowHighest {
+                segment: StaticFileSegment::Headers,
+                highest: 9,
+                block: 5
+            })
+        );
+        // Range overlaps with existing static files
+        assert_eq!(
+            StaticFileTargets::builder(highest).range(StaticFileSegment::Headers, 9..=12).build(),
+            Err(StaticFileTargetsError::BelowHighest {
+                segment: StaticFileSegment::Headers,
+                highest: 9,
+                block: 9
+            })
+        );
+        // Range leaves a gap
+        assert_eq!(
+            StaticFileTargets::builder(highest).range(StaticFileSegment::Receipts, 1..=3).build(),
+            Err(StaticFileTargetsError::Gap {
+                segment: StaticFileSegment::Receipts,
+                expected_start: 0,
+                start: 1
+            })
+        );
+        // Inverted range
+        #[allow(clippy::reversed_empty_ranges)]
+        let inverted = 12..=10;
+        assert_eq!(
+            StaticFileTargets::builder(highest).range(StaticFileSegment::Headers, inverted).build(),
+            Err(StaticFileTargetsError::InvalidRange {
+                segment: StaticFileSegment::Headers,
+                start: 12,
+                end: 10
+            })
+        );
+    }
 }
-    
\ No newline at end of file
@@ -0,0 +1,168 @@
+//! Deterministic fixtures of headers, transactions and receipts, and a temporary database with
+//! static files, for tests of crates that produce or read static files.
+
+use crate::{StaticFileProducerInner, StaticFileProducerResult};
+use alloy_primitives::{BlockNumber, TxNumber, B256, U256};
+use rand::rngs::StdRng;
+use reth_db::{test_utils::TempDatabase, DatabaseEnv};
+use reth_db_api::{database::Database, transaction::DbTx};
+use reth_primitives::{Receipt, SealedBlock, SealedHeader};
+use reth_provider::{providers::StaticFileWriter, ProviderFactory, StaticFileProviderFactory};
+use reth_prune_types::PruneModes;
+use reth_stages::test_utils::{StorageKind, TestStageDB};
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use reth_testing_utils::generators::{random_block_range, random_receipt, rng_with_seed};
+use std::{
+    ops::{Range, RangeInclusive},
+    sync::Arc,
+};
+use tempfile::TempDir;
+
+/// Seed of the fixture generators, so the same fixtures are generated on every run.
+pub const FIXTURE_SEED: &[u8] = b"reth-static-file-fixtures";
+
+/// Returns a generator seeded with [`FIXTURE_SEED`] and the first block, so fixtures of
+/// different ranges don't repeat each other.
+fn fixture_rng(first_block: BlockNumber) -> StdRng {
+    rng_with_seed(&[FIXTURE_SEED, &first_block.to_be_bytes()].concat())
+}
+
+/// Returns sealed blocks of the range with `tx_count` transactions each, chained to the
+/// `parent` hash.
+pub fn fixture_blocks(
+    blocks: RangeInclusive<BlockNumber>,
+    parent: B256,
+    tx_count: Range<u8>,
+) -> Vec<SealedBlock> {
+    random_block_range(&mut fixture_rng(*blocks.start()), blocks, parent, tx_count)
+}
+
+/// Returns sealed headers of the range, chained to the `parent` hash.
+pub fn fixture_headers(blocks: RangeInclusive<BlockNumber>, parent: B256) -> Vec<SealedHeader> {
+    fixture_blocks(blocks, parent, 0..1).into_iter().map(|block| block.header).collect()
+}
+
+/// Returns a receipt with `logs_count` logs for every transaction of the blocks, numbered from
+/// `first_tx_num`.
+pub fn fixture_receipts(
+    blocks: &[SealedBlock],
+    first_tx_num: TxNumber,
+    logs_count: u8,
+) -> Vec<(TxNumber, Receipt)> {
+    let mut rng = fixture_rng(blocks.first().map_or(0, |block| block.number));
+    blocks
+        .iter()
+        .flat_map(|block| &block.body)
+        .zip(first_tx_num..)
+        .map(|(transaction, tx_num)| {
+            (tx_num, random_receipt(&mut rng, transaction, Some(logs_count)))
+        })
+        .collect()
+}
+
+/// Temporary MDBX database and static files directory, holding fixture blocks that are only in
+/// the database, so the static file producer has something to copy.
+///
+/// Both are removed once the harness is dropped.
+#[derive(Debug)]
+pub struct StaticFileTestHarness {
+    /// Provider factory of the database and static files.
+    pub provider_factory: ProviderFactory<Arc<TempDatabase<DatabaseEnv>>>,
+    /// Fixture blocks inserted into the database.
+    pub blocks: Vec<SealedBlock>,
+    /// Temporary static files directory.
+    pub static_files_dir: TempDir,
+}
+
+impl StaticFileTestHarness {
+    /// Creates a new [`StaticFileTestHarness`] with the headers, transactions and receipts of
+    /// fixture blocks `0..=tip`, with `tx_count` transactions each.
+    pub fn new(tip: BlockNumber, tx_count: Range<u8>) -> Self {
+        let db = TestStageDB::default();
+        let blocks = fixture_blocks(0..=tip, B256::ZERO, tx_count);
+        db.insert_blocks(blocks.iter(), StorageKind::Database(None)).expect("insert blocks");
+
+        // Headers are written to static files when blocks are inserted, so they're unwound and
+        // inserted into the database instead
+        let static_file_provider = db.factory.static_file_provider();
+        let mut static_file_writer = static_file_provider
+            .latest_writer(StaticFileSegment::Headers)
+            .expect("get static file writer for headers");
+        static_file_writer.prune_headers(blocks.len() as u64).expect("prune headers");
+        static_file_writer.commit().expect("commit pruned headers");
+
+        let tx = db.factory.db_ref().tx_mut().expect("init tx");
+        for block in &blocks {
+            TestStageDB::insert_header(None, &tx, &block.header, U256::ZERO)
+                .expect("insert block header");
+        }
+        tx.commit().expect("commit tx");
+
+        db.insert_receipts(fixture_receipts(&blocks, 0, 0)).expect("insert receipts");
+
+        Self { provider_factory: db.factory, blocks, static_files_dir: db.temp_static_files_dir }
+    }
+
+    /// Returns the highest fixture block.
+    pub fn tip(&self) -> BlockNumber {
+        self.blocks.last().map_or(0, |block| block.number)
+    }
+
+    /// Returns a new static file producer of the harness.
+    pub fn producer(
+        &self,
+    ) -> StaticFileProducerInner<ProviderFactory<Arc<TempDatabase<DatabaseEnv>>>> {
+        StaticFileProducerInner::new(self.provider_factory.clone(), PruneModes::default())
+    }
+
+    /// Copies all fixture blocks to static files with a new producer of the harness.
+    pub fn run(&self) -> StaticFileProducerResult {
+        self.run_with(&self.producer())
+    }
+
+    /// Copies all fixture blocks to static files with the producer.
+    pub fn run_with(
+        &self,
+        producer: &StaticFileProducerInner<ProviderFactory<Arc<TempDatabase<DatabaseEnv>>>>,
+    ) -> StaticFileProducerResult {
+        let tip = Some(self.tip());
+        let targets = producer.get_static_file_targets(HighestStaticFiles {
+            headers: tip,
+            receipts: tip,
+            transactions: tip,
+        })?;
+        producer.run(targets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deterministic() {
+        let blocks = fixture_blocks(0..=3, B256::ZERO, 1..3);
+        assert_eq!(blocks, fixture_blocks(0..=3, B256::ZERO, 1..3));
+        assert_eq!(fixture_receipts(&blocks, 0, 2), fixture_receipts(&blocks, 0, 2));
+
+        let tx_count = blocks.iter().map(|block| block.body.len()).sum::<usize>();
+        assert_eq!(fixture_receipts(&blocks, 0, 2).len(), tx_count);
+        assert_eq!(
+            fixture_headers(4..=7, B256::ZERO)
+                .iter()
+                .map(|header| header.number)
+                .collect::<Vec<_>>(),
+            vec![4, 5, 6, 7]
+        );
+    }
+
+    #[test]
+    fn harness() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        assert_eq!(
+            harness.provider_factory.static_file_provider().get_highest_static_files(),
+            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) }
+        );
+    }
+}
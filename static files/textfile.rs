@@ -0,0 +1,209 @@
+//! Snapshot of the static files metrics, written as a Prometheus textfile after every
+//! [`StaticFileProducerInner::run`](crate::StaticFileProducerInner::run), for operators collecting
+//! them with the textfile collector of node_exporter instead of scraping the node.
+//!
+//! The snapshot is written to [`METRICS_TEXTFILE_NAME`] in the configured directory, through a
+//! temporary file renamed over it, so the collector never reads a partially written file.
+
+use crate::{quota::static_files_size, StaticFileEntry, StaticFileTargets};
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use std::{
+    fmt::Write as _,
+    io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the Prometheus textfile written to the configured directory.
+pub const METRICS_TEXTFILE_NAME: &str = "static_files.prom";
+
+/// Gauges of a segment in a [`MetricsSnapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SegmentMetrics {
+    /// Highest block in the static files of the segment, if any.
+    pub highest_block: Option<u64>,
+    /// Number of static files of the segment.
+    pub files: u64,
+    /// Size of the static files of the segment, with their companion files, in bytes.
+    pub size_bytes: u64,
+    /// Number of blocks produced by the last run.
+    pub produced_blocks: u64,
+}
+
+/// Metrics of the static files directory and of the last run, written as a Prometheus textfile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Gauges of every segment.
+    pub segments: Vec<(StaticFileSegment, SegmentMetrics)>,
+    /// Start of the last run.
+    pub last_run_started_at: SystemTime,
+    /// Time the last run took.
+    pub last_run_elapsed: Duration,
+    /// Whether the last run succeeded.
+    pub last_run_success: bool,
+}
+
+impl MetricsSnapshot {
+    /// Collects the gauges of every segment from the static files and the targets produced by
+    /// the last run, `None` if it failed.
+    pub(crate) fn collect(
+        entries: &[StaticFileEntry],
+        highest_static_files: &HighestStaticFiles,
+        produced: Option<&StaticFileTargets>,
+        last_run_started_at: SystemTime,
+        last_run_elapsed: Duration,
+    ) -> io::Result<Self> {
+        let mut segments = Vec::with_capacity(3);
+        for segment in [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ] {
+            let mut metrics = SegmentMetrics {
+                highest_block: highest_static_files.highest(segment),
+                produced_blocks: produced
+                    .and_then(|produced| produced.target(segment))
+                    .map_or(0, |range| range.end() - range.start() + 1),
+                ..Default::default()
+            };
+            for entry in entries.iter().filter(|entry| entry.segment == segment) {
+                metrics.files += 1;
+                metrics.size_bytes += static_files_size([entry])?;
+            }
+            segments.push((segment, metrics));
+        }
+        Ok(Self {
+            segments,
+            last_run_started_at,
+            last_run_elapsed,
+            last_run_success: produced.is_some(),
+        })
+    }
+
+    /// Encodes the snapshot in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let mut text = String::new();
+        let segment_gauges: [(&str, &str, fn(&SegmentMetrics) -> Option<u64>); 4] = [
+            (
+                "reth_static_files_highest_block",
+                "Highest block in the static files of the segment.",
+                |metrics| metrics.highest_block,
+            ),
+            ("reth_static_files_files", "Number of static files of the segment.", |metrics| {
+                Some(metrics.files)
+            }),
+            (
+                "reth_static_files_size_bytes",
+                "Size of the static files of the segment, with their companion files.",
+                |metrics| Some(metrics.size_bytes),
+            ),
+            (
+                "reth_static_files_last_run_produced_blocks",
+                "Number of blocks of the segment produced by the last run.",
+                |metrics| Some(metrics.produced_blocks),
+            ),
+        ];
+        for (name, help, value) in segment_gauges {
+            write_gauge_header(&mut text, name, help);
+            for (segment, metrics) in &self.segments {
+                // Gauges without a value, e.g. the highest block of an empty segment, are omitted
+                if let Some(value) = value(metrics) {
+                    let _ = writeln!(text, "{name}{{segment=\"{segment}\"}} {value}");
+                }
+            }
+        }
+
+        let started_at =
+            self.last_run_started_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        for (name, help, value) in [
+            (
+                "reth_static_files_last_run_timestamp_seconds",
+                "Start of the last run, in seconds since the Unix epoch.",
+                started_at.to_string(),
+            ),
+            (
+                "reth_static_files_last_run_duration_seconds",
+                "Time the last run took.",
+                self.last_run_elapsed.as_secs_f64().to_string(),
+            ),
+            (
+                "reth_static_files_last_run_success",
+                "Whether the last run succeeded.",
+                u8::from(self.last_run_success).to_string(),
+            ),
+        ] {
+            write_gauge_header(&mut text, name, help);
+            let _ = writeln!(text, "{name} {value}");
+        }
+        text
+    }
+
+    /// Writes the snapshot to [`METRICS_TEXTFILE_NAME`] in the directory, replacing the previous
+    /// one atomically.
+    pub(crate) fn write(&self, directory: &Path) -> io::Result<()> {
+        let path = directory.join(METRICS_TEXTFILE_NAME);
+        // The collector only reads `.prom` files, so the temporary file is ignored
+        let tmp_path = path.with_extension("prom.tmp");
+        std::fs::write(&tmp_path, self.encode())?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+/// Writes the help and type lines of a gauge.
+fn write_gauge_header(text: &mut String, name: &str, help: &str) {
+    let _ = writeln!(text, "# HELP {name} {help}");
+    let _ = writeln!(text, "# TYPE {name} gauge");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::list_static_files;
+
+    #[test]
+    fn metrics_textfile() {
+        let directory = tempfile::tempdir().unwrap();
+        let data = directory.path().join("static_file_headers_0_499999");
+        std::fs::write(&data, [0; 100]).unwrap();
+        std::fs::write(data.with_extension("off"), [0; 20]).unwrap();
+        let entries = list_static_files(directory.path()).unwrap();
+        let highest = HighestStaticFiles { headers: Some(99), ..Default::default() };
+        let targets = StaticFileTargets::builder(HighestStaticFiles::default())
+            .range(StaticFileSegment::Headers, 0..=99)
+            .build()
+            .unwrap();
+
+        let snapshot = MetricsSnapshot::collect(
+            &entries,
+            &highest,
+            Some(&targets),
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            Duration::from_millis(1500),
+        )
+        .unwrap();
+        snapshot.write(directory.path()).unwrap();
+        let text = std::fs::read_to_string(directory.path().join(METRICS_TEXTFILE_NAME)).unwrap();
+
+        let samples = text.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>();
+        // Segments without static files have no highest block
+        assert_eq!(
+            samples,
+            [
+                "reth_static_files_highest_block{segment=\"headers\"} 99",
+                "reth_static_files_files{segment=\"headers\"} 1",
+                "reth_static_files_files{segment=\"transactions\"} 0",
+                "reth_static_files_files{segment=\"receipts\"} 0",
+                "reth_static_files_size_bytes{segment=\"headers\"} 120",
+                "reth_static_files_size_bytes{segment=\"transactions\"} 0",
+                "reth_static_files_size_bytes{segment=\"receipts\"} 0",
+                "reth_static_files_last_run_produced_blocks{segment=\"headers\"} 100",
+                "reth_static_files_last_run_produced_blocks{segment=\"transactions\"} 0",
+                "reth_static_files_last_run_produced_blocks{segment=\"receipts\"} 0",
+                "reth_static_files_last_run_timestamp_seconds 1700000000",
+                "reth_static_files_last_run_duration_seconds 1.5",
+                "reth_static_files_last_run_success 1",
+            ]
+        );
+        assert!(!directory.path().join("static_files.prom.tmp").exists());
+    }
+}
@@ -0,0 +1,419 @@
+//! Age-based tiering of sealed static files to a cold directory, e.g. on a slower mount.
+//!
+//! Cold static files are replaced in the static files directory by symbolic links to their new
+//! location, so they keep their paths and readers resolve them without reloading the static file
+//! index. The [`TierLocations`] persisted in the static files directory record where they went.
+//!
+//! Whatever removes a static file from the static files directory, e.g. retention, quarantine or
+//! rollback, removes or moves its cold files as well, see [`remove_cold`] and [`move_cold_to`].
+
+use crate::{
+    files::list_static_files, manifest::write_json, retention::move_file, rollback::sync_directory,
+    StaticFileEntry, StaticFileProducerError,
+};
+use alloy_primitives::BlockNumber;
+use reth_provider::providers::StaticFileProvider;
+use reth_static_file_types::{HighestStaticFiles, SegmentRangeInclusive, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    fs::File,
+    io::{self, BufReader},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// Name of the file in the static files directory that the [`TierLocations`] are persisted to.
+pub const TIER_LOCATIONS_FILE_NAME: &str = "tier_locations.json";
+
+/// Tiering policy moving sealed static files to a cold directory once they're old enough.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TieringPolicy {
+    /// Directory cold static files are moved to, e.g. on another mount.
+    pub cold_directory: PathBuf,
+    /// Minimum number of blocks between the end of a static file and the highest static file
+    /// block of its segment, for the static file to be moved to the cold directory.
+    pub min_age_blocks: u64,
+    /// Block ranges kept in the static files directory regardless of their age, in all segments.
+    #[serde(default)]
+    pub pinned: Vec<SegmentRangeInclusive>,
+}
+
+impl TieringPolicy {
+    /// Creates a new [`TieringPolicy`] without pinned ranges.
+    pub fn new(cold_directory: impl Into<PathBuf>, min_age_blocks: u64) -> Self {
+        Self { cold_directory: cold_directory.into(), min_age_blocks, pinned: Vec::new() }
+    }
+
+    /// Keeps the static files overlapping the block range in the static files directory. Static
+    /// files that were already moved to the cold directory are moved back by the next
+    /// [`StaticFileProducerInner::apply_tiering`](crate::StaticFileProducerInner::apply_tiering).
+    pub fn pin(&mut self, range: RangeInclusive<BlockNumber>) {
+        self.pinned.push(SegmentRangeInclusive::new(*range.start(), *range.end()));
+    }
+
+    /// Returns `true` if the block range overlaps a pinned range.
+    pub fn is_pinned(&self, block_range: &SegmentRangeInclusive) -> bool {
+        self.pinned.iter().any(|pinned| {
+            pinned.start() <= block_range.end() && block_range.start() <= pinned.end()
+        })
+    }
+
+    /// Returns the static files that are old enough for the cold directory and not pinned. The
+    /// age of a static file is counted from its last block to the highest static file block of
+    /// its segment, so the static file holding the highest block is never cold.
+    pub fn cold<'a>(
+        &self,
+        entries: &'a [StaticFileEntry],
+        highest_static_files: HighestStaticFiles,
+    ) -> Vec<&'a StaticFileEntry> {
+        entries
+            .iter()
+            .filter(|entry| {
+                highest_static_files.highest(entry.segment).is_some_and(|highest| {
+                    highest > entry.block_range.end() &&
+                        highest - entry.block_range.end() >= self.min_age_blocks
+                }) && !self.is_pinned(&entry.block_range)
+            })
+            .collect()
+    }
+}
+
+/// Service applying a [`TieringPolicy`] to the static files directory, held by the
+/// [`StaticFileProducer`](crate::StaticFileProducer) and applied after every run.
+#[derive(Debug, Clone)]
+pub struct TieringService {
+    policy: TieringPolicy,
+}
+
+impl TieringService {
+    /// Creates a new [`TieringService`] applying the policy.
+    pub const fn new(policy: TieringPolicy) -> Self {
+        Self { policy }
+    }
+
+    /// Returns the applied [`TieringPolicy`].
+    pub const fn policy(&self) -> &TieringPolicy {
+        &self.policy
+    }
+
+    /// Pins the block range to the static files directory, see [`TieringPolicy::pin`].
+    pub fn pin(&mut self, range: RangeInclusive<BlockNumber>) {
+        self.policy.pin(range);
+    }
+
+    /// Moves sealed static files old enough to the cold directory, and cold static files that
+    /// were pinned since back to the static files directory.
+    pub fn apply(
+        &self,
+        static_file_provider: &StaticFileProvider,
+    ) -> Result<TieringOutcome, StaticFileProducerError> {
+        let directory = static_file_provider.directory();
+        let entries = list_static_files(directory)?;
+        let mut locations = TierLocations::read(directory)?;
+
+        let mut outcome = TieringOutcome::default();
+        for entry in &entries {
+            if locations.get(entry).is_some() && self.policy.is_pinned(&entry.block_range) {
+                debug!(target: "static_file", segment = %entry.segment, block_range = %entry.block_range, "Moving pinned static file back from cold directory");
+                locations.move_hot(directory, entry)?;
+                outcome.hot.push(entry.clone());
+            }
+        }
+        for entry in self.policy.cold(&entries, static_file_provider.get_highest_static_files()) {
+            if locations.get(entry).is_none() {
+                debug!(target: "static_file", segment = %entry.segment, block_range = %entry.block_range, "Moving static file to cold directory");
+                locations.move_cold(directory, entry, &self.policy.cold_directory)?;
+                outcome.cold.push(entry.clone());
+            }
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// Static file moved to the cold directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierLocation {
+    /// Segment of the static file.
+    pub segment: StaticFileSegment,
+    /// Fixed block range of the static file.
+    pub block_range: SegmentRangeInclusive,
+    /// Path to the data file in the cold directory. Companion files share the name, with an
+    /// extension.
+    pub path: PathBuf,
+}
+
+/// Locations of the static files moved to the cold directory, persisted to
+/// [`TIER_LOCATIONS_FILE_NAME`] after every change.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TierLocations {
+    /// Cold static files, sorted by segment and block range.
+    pub files: Vec<TierLocation>,
+}
+
+impl TierLocations {
+    /// Reads the locations persisted in the static files directory. Returns no locations if
+    /// nothing was persisted yet.
+    pub fn read(directory: &Path) -> io::Result<Self> {
+        let path = directory.join(TIER_LOCATIONS_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default())
+        }
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    /// Persists the locations to the static files directory, replacing the previous locations
+    /// atomically and durably.
+    fn write(&self, directory: &Path) -> io::Result<()> {
+        let path = directory.join(TIER_LOCATIONS_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+        write_json(self, &tmp_path)?;
+        File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(tmp_path, path)?;
+        sync_directory(directory)
+    }
+
+    /// Returns the location of the static file, if it's cold.
+    pub fn get(&self, entry: &StaticFileEntry) -> Option<&TierLocation> {
+        self.files
+            .iter()
+            .find(|file| file.segment == entry.segment && file.block_range == entry.block_range)
+    }
+
+    /// Returns the static file as moved to the cold directory, if it's cold.
+    fn cold_entry(&self, entry: &StaticFileEntry) -> Option<StaticFileEntry> {
+        let location = self.get(entry)?;
+        Some(StaticFileEntry { path: location.path.clone(), ..entry.clone() })
+    }
+
+    /// Forgets the location of the static file and persists the remaining locations.
+    fn forget(&mut self, static_files_dir: &Path, entry: &StaticFileEntry) -> io::Result<()> {
+        self.files
+            .retain(|file| file.segment != entry.segment || file.block_range != entry.block_range);
+        self.write(static_files_dir)
+    }
+
+    /// Moves the static file with its companion files to the cold directory, leaving symbolic
+    /// links in their place, and records its location.
+    pub(crate) fn move_cold(
+        &mut self,
+        static_files_dir: &Path,
+        entry: &StaticFileEntry,
+        cold_directory: &Path,
+    ) -> io::Result<()> {
+        // Copying an already cold static file would truncate it through its links
+        if self.get(entry).is_some() {
+            return Ok(())
+        }
+
+        // Files are copied, synced and the location recorded first, so the static file stays
+        // readable until the links replace it, even after a crash
+        std::fs::create_dir_all(cold_directory)?;
+        let mut links = Vec::new();
+        for path in entry.paths() {
+            let Some(file_name) = path.file_name() else { continue };
+            let cold_path = cold_directory.join(file_name);
+            std::fs::copy(&path, &cold_path)?;
+            File::open(&cold_path)?.sync_all()?;
+            links.push((cold_path, path));
+        }
+        sync_directory(cold_directory)?;
+
+        self.files.push(TierLocation {
+            segment: entry.segment,
+            block_range: entry.block_range,
+            path: cold_directory.join(entry.path.file_name().unwrap_or_default()),
+        });
+        self.files.sort_unstable_by_key(|file| (file.segment, file.block_range.start()));
+        self.write(static_files_dir)?;
+
+        for (cold_path, path) in links {
+            let tmp_path = tmp_path(&path);
+            symlink(&cold_path, &tmp_path)?;
+            std::fs::rename(tmp_path, path)?;
+        }
+        sync_directory(static_files_dir)
+    }
+
+    /// Moves the cold static file with its companion files back to the static files directory,
+    /// replacing the symbolic links, and forgets its location.
+    pub(crate) fn move_hot(
+        &mut self,
+        static_files_dir: &Path,
+        entry: &StaticFileEntry,
+    ) -> io::Result<()> {
+        let Some(cold_entry) = self.cold_entry(entry) else { return Ok(()) };
+
+        // Cold files are only removed once the copies replacing the links are durable
+        let cold_paths = cold_entry.paths();
+        for cold_path in &cold_paths {
+            let Some(file_name) = cold_path.file_name() else { continue };
+            let path = static_files_dir.join(file_name);
+            let tmp_path = tmp_path(&path);
+            std::fs::copy(cold_path, &tmp_path)?;
+            File::open(&tmp_path)?.sync_all()?;
+            std::fs::rename(tmp_path, path)?;
+        }
+        sync_directory(static_files_dir)?;
+        for cold_path in cold_paths {
+            std::fs::remove_file(cold_path)?;
+        }
+
+        self.forget(static_files_dir, entry)
+    }
+}
+
+/// Removes the files of the static file from the cold directory and forgets its location, if it
+/// was moved there. Called once the static file is removed from the static files directory, so
+/// its cold files aren't left behind.
+pub(crate) fn remove_cold(static_files_dir: &Path, entry: &StaticFileEntry) -> io::Result<()> {
+    let mut locations = TierLocations::read(static_files_dir)?;
+    let Some(cold_entry) = locations.cold_entry(entry) else { return Ok(()) };
+    for path in cold_entry.paths() {
+        std::fs::remove_file(path)?;
+    }
+    locations.forget(static_files_dir, entry)
+}
+
+/// Moves the files of the static file from the cold directory into the directory, removing its
+/// links from the static files directory and forgetting its location. Returns `false` without
+/// moving anything if the static file isn't cold.
+pub(crate) fn move_cold_to(
+    static_files_dir: &Path,
+    entry: &StaticFileEntry,
+    directory: &Path,
+) -> io::Result<bool> {
+    let mut locations = TierLocations::read(static_files_dir)?;
+    let Some(cold_entry) = locations.cold_entry(entry) else { return Ok(false) };
+    for path in entry.paths() {
+        std::fs::remove_file(path)?;
+    }
+    for path in cold_entry.paths() {
+        move_file(&path, directory)?;
+    }
+    locations.forget(static_files_dir, entry)?;
+    Ok(true)
+}
+
+/// Outcome of applying a [`TieringPolicy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TieringOutcome {
+    /// Static files moved to the cold directory.
+    pub cold: Vec<StaticFileEntry>,
+    /// Pinned static files moved back to the static files directory.
+    pub hot: Vec<StaticFileEntry>,
+}
+
+/// Returns the temporary path the file is prepared at before it atomically replaces the path.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = OsString::from(path.as_os_str());
+    tmp_path.push(".tmp");
+    tmp_path.into()
+}
+
+/// Creates a symbolic link at `link` pointing to `original`.
+#[cfg(unix)]
+fn symlink(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+/// Creates a symbolic link at `link` pointing to `original`.
+#[cfg(not(unix))]
+fn symlink(_original: &Path, _link: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "tiering requires symbolic links"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::list_static_files;
+
+    #[test]
+    fn cold_static_files() {
+        let entry = |start: u64| StaticFileEntry {
+            segment: StaticFileSegment::Headers,
+            block_range: SegmentRangeInclusive::new(start, start + 499_999),
+            path: PathBuf::default(),
+        };
+        let entries = [entry(0), entry(500_000), entry(1_000_000), entry(1_500_000)];
+        let highest = HighestStaticFiles { headers: Some(1_600_000), ..Default::default() };
+
+        let mut policy = TieringPolicy::new("cold", 600_000);
+        assert_eq!(policy.cold(&entries, highest), vec![&entries[0], &entries[1]]);
+
+        policy.pin(400_000..=400_000);
+        assert_eq!(policy.cold(&entries, highest), vec![&entries[1]]);
+
+        // Static file with the highest block is never cold
+        let policy = TieringPolicy::new("cold", 0);
+        assert_eq!(policy.cold(&entries, highest).len(), 3);
+    }
+
+    #[test]
+    fn moves_between_tiers() {
+        let hot = tempfile::tempdir().unwrap();
+        let cold = tempfile::tempdir().unwrap();
+        for (name, content) in [
+            ("static_file_headers_0_499999", b"headers".as_slice()),
+            ("static_file_headers_0_499999.off", b"offsets"),
+        ] {
+            std::fs::write(hot.path().join(name), content).unwrap();
+        }
+        let entries = list_static_files(hot.path()).unwrap();
+
+        let mut locations = TierLocations::default();
+        locations.move_cold(hot.path(), &entries[0], cold.path()).unwrap();
+        let cold_path = cold.path().join("static_file_headers_0_499999");
+        assert_eq!(locations.get(&entries[0]).unwrap().path, cold_path);
+        assert_eq!(TierLocations::read(hot.path()).unwrap(), locations);
+        assert!(entries[0].path.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read(cold_path.with_extension("off")).unwrap(), b"offsets");
+
+        // Cold static files are still listed and readable through their links
+        assert_eq!(list_static_files(hot.path()).unwrap(), entries);
+        assert_eq!(std::fs::read(&entries[0].path).unwrap(), b"headers");
+
+        locations.move_hot(hot.path(), &entries[0]).unwrap();
+        assert!(TierLocations::read(hot.path()).unwrap().files.is_empty());
+        assert!(entries[0].path.symlink_metadata().unwrap().file_type().is_file());
+        assert_eq!(std::fs::read(entries[0].companion_path("off")).unwrap(), b"offsets");
+        assert!(!cold_path.exists());
+    }
+
+    #[test]
+    fn removes_cold_files() {
+        let hot = tempfile::tempdir().unwrap();
+        let cold = tempfile::tempdir().unwrap();
+        let destination = tempfile::tempdir().unwrap();
+        for name in ["static_file_headers_0_499999", "static_file_headers_500000_999999"] {
+            std::fs::write(hot.path().join(name), b"headers").unwrap();
+            std::fs::write(hot.path().join(format!("{name}.off")), b"offsets").unwrap();
+        }
+        let entries = list_static_files(hot.path()).unwrap();
+        let mut locations = TierLocations::default();
+        for entry in &entries {
+            locations.move_cold(hot.path(), entry, cold.path()).unwrap();
+        }
+
+        // Removed static files take their cold files with them
+        for path in entries[0].paths() {
+            std::fs::remove_file(path).unwrap();
+        }
+        remove_cold(hot.path(), &entries[0]).unwrap();
+        assert!(!cold.path().join("static_file_headers_0_499999").exists());
+        assert!(!cold.path().join("static_file_headers_0_499999.off").exists());
+
+        // Moved static files are moved from the cold directory, not as links
+        assert!(move_cold_to(hot.path(), &entries[1], destination.path()).unwrap());
+        let moved = destination.path().join("static_file_headers_500000_999999.off");
+        assert!(moved.symlink_metadata().unwrap().file_type().is_file());
+        assert_eq!(std::fs::read(moved).unwrap(), b"offsets");
+        assert!(!entries[1].path.exists());
+        assert_eq!(std::fs::read_dir(cold.path()).unwrap().count(), 0);
+
+        assert!(TierLocations::read(hot.path()).unwrap().files.is_empty());
+        assert!(!move_cold_to(hot.path(), &entries[1], destination.path()).unwrap());
+    }
+}
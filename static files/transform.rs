@@ -0,0 +1,232 @@
+//! Transforms of the rows of segments, applied before the rows are appended to static files and
+//! inverted when they're read back, e.g. to reorder fields, redact data of private chains or try
+//! out encodings without forking the segments.
+
+use crate::{SegmentProgress, StaticFileSink};
+use alloy_primitives::{BlockHash, BlockNumber, TxNumber, U256};
+use reth_primitives::{Header, Receipt, TransactionSignedNoHash};
+use reth_static_file_types::StaticFileSegment;
+use reth_storage_errors::provider::ProviderResult;
+use std::{fmt, path::Path, sync::Arc};
+
+/// Transform of the rows of a segment, registered with [`RowTransforms`].
+///
+/// Rows are transformed after sidecars and statistics of the static file were built from them.
+pub trait RowTransform<T>: Send + Sync {
+    /// Transforms the row before it's appended to static files.
+    fn apply(&self, row: &mut T) -> ProviderResult<()>;
+
+    /// Reverts [`RowTransform::apply`] on a row read from static files. Transforms that can't be
+    /// reverted, e.g. redactions, leave the row as stored, which is the default.
+    fn invert(&self, _row: &mut T) -> ProviderResult<()> {
+        Ok(())
+    }
+}
+
+/// [`RowTransform`]s registered per segment, applied by the
+/// [`StaticFileProducer`](crate::StaticFileProducer) and inverted by the
+/// [`StaticFileReader`](crate::StaticFileReader).
+///
+/// Rows are only inverted when read by block or transaction number through the reader. Lookups by
+/// hash and other readers of static files return rows as stored.
+#[derive(Clone, Default)]
+pub struct RowTransforms {
+    headers: Option<Arc<dyn RowTransform<Header>>>,
+    transactions: Option<Arc<dyn RowTransform<TransactionSignedNoHash>>>,
+    receipts: Option<Arc<dyn RowTransform<Receipt>>>,
+}
+
+impl RowTransforms {
+    /// Creates new [`RowTransforms`] without any transform.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the transform of the rows of the Headers segment.
+    pub fn with_headers(mut self, transform: impl RowTransform<Header> + 'static) -> Self {
+        self.headers = Some(Arc::new(transform));
+        self
+    }
+
+    /// Registers the transform of the rows of the Transactions segment.
+    pub fn with_transactions(
+        mut self,
+        transform: impl RowTransform<TransactionSignedNoHash> + 'static,
+    ) -> Self {
+        self.transactions = Some(Arc::new(transform));
+        self
+    }
+
+    /// Registers the transform of the rows of the Receipts segment.
+    pub fn with_receipts(mut self, transform: impl RowTransform<Receipt> + 'static) -> Self {
+        self.receipts = Some(Arc::new(transform));
+        self
+    }
+
+    /// Reverts the transform of the Headers segment on a header read from static files.
+    pub(crate) fn invert_header(&self, header: &mut Header) -> ProviderResult<()> {
+        invert(&self.headers, header)
+    }
+
+    /// Reverts the transform of the Transactions segment on a transaction read from static
+    /// files.
+    pub(crate) fn invert_transaction(
+        &self,
+        transaction: &mut TransactionSignedNoHash,
+    ) -> ProviderResult<()> {
+        invert(&self.transactions, transaction)
+    }
+
+    /// Reverts the transform of the Receipts segment on a receipt read from static files.
+    pub(crate) fn invert_receipt(&self, receipt: &mut Receipt) -> ProviderResult<()> {
+        invert(&self.receipts, receipt)
+    }
+
+    /// Wraps the sink, so rows are transformed before they're appended to it.
+    pub(crate) fn wrap<'a>(&'a self, sink: &'a mut dyn StaticFileSink) -> TransformSink<'a> {
+        TransformSink { sink, transforms: self }
+    }
+}
+
+impl fmt::Debug for RowTransforms {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RowTransforms")
+            .field("headers", &self.headers.is_some())
+            .field("transactions", &self.transactions.is_some())
+            .field("receipts", &self.receipts.is_some())
+            .finish()
+    }
+}
+
+/// Applies the transform to the row, if any.
+fn apply<T>(transform: &Option<Arc<dyn RowTransform<T>>>, row: &mut T) -> ProviderResult<()> {
+    transform.as_ref().map_or(Ok(()), |transform| transform.apply(row))
+}
+
+/// Reverts the transform on the row, if any.
+fn invert<T>(transform: &Option<Arc<dyn RowTransform<T>>>, row: &mut T) -> ProviderResult<()> {
+    transform.as_ref().map_or(Ok(()), |transform| transform.invert(row))
+}
+
+/// [`StaticFileSink`] applying the [`RowTransforms`] to the rows appended to the wrapped sink.
+pub(crate) struct TransformSink<'a> {
+    sink: &'a mut dyn StaticFileSink,
+    transforms: &'a RowTransforms,
+}
+
+impl StaticFileSink for TransformSink<'_> {
+    fn append_header(
+        &mut self,
+        mut header: Header,
+        total_difficulty: U256,
+        hash: BlockHash,
+    ) -> ProviderResult<BlockNumber> {
+        apply(&self.transforms.headers, &mut header)?;
+        self.sink.append_header(header, total_difficulty, hash)
+    }
+
+    fn increment_block(
+        &mut self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+    ) -> ProviderResult<BlockNumber> {
+        self.sink.increment_block(segment, block)
+    }
+
+    fn append_transaction(
+        &mut self,
+        tx_num: TxNumber,
+        mut transaction: TransactionSignedNoHash,
+    ) -> ProviderResult<TxNumber> {
+        apply(&self.transforms.transactions, &mut transaction)?;
+        self.sink.append_transaction(tx_num, transaction)
+    }
+
+    fn append_receipt(
+        &mut self,
+        tx_num: TxNumber,
+        mut receipt: Receipt,
+    ) -> ProviderResult<TxNumber> {
+        apply(&self.transforms.receipts, &mut receipt)?;
+        self.sink.append_receipt(tx_num, receipt)
+    }
+
+    fn record_transaction_type(&mut self, tx_type: u8, size: usize) -> ProviderResult<()> {
+        self.sink.record_transaction_type(tx_type, size)
+    }
+
+    fn record_receipt(
+        &mut self,
+        gas_used: u64,
+        logs: usize,
+        contract_creation: bool,
+    ) -> ProviderResult<()> {
+        self.sink.record_receipt(gas_used, logs, contract_creation)
+    }
+
+    fn record_row_sizes(&mut self, sizes: &[usize]) -> ProviderResult<()> {
+        self.sink.record_row_sizes(sizes)
+    }
+
+    fn commit_if_due(&mut self, progress: &SegmentProgress) -> ProviderResult<()> {
+        self.sink.commit_if_due(progress)
+    }
+
+    fn directory(&self) -> Option<&Path> {
+        self.sink.directory()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{fixture_receipts, StaticFileTestHarness},
+        StaticFileReader,
+    };
+    use reth_provider::{ReceiptProvider, StaticFileProviderFactory};
+    use reth_static_file_types::HighestStaticFiles;
+
+    /// Flips the status of receipts, so stored receipts differ from the database.
+    #[derive(Debug)]
+    struct FlipStatus;
+
+    impl RowTransform<Receipt> for FlipStatus {
+        fn apply(&self, receipt: &mut Receipt) -> ProviderResult<()> {
+            receipt.success = !receipt.success;
+            Ok(())
+        }
+
+        fn invert(&self, receipt: &mut Receipt) -> ProviderResult<()> {
+            self.apply(receipt)
+        }
+    }
+
+    #[test]
+    fn row_transforms() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        let transforms = RowTransforms::new().with_receipts(FlipStatus);
+        producer.set_row_transforms(transforms.clone());
+        let targets = producer
+            .get_static_file_targets(HighestStaticFiles {
+                receipts: Some(harness.tip()),
+                ..Default::default()
+            })
+            .unwrap();
+        producer.run(targets).unwrap();
+
+        let static_file_provider = harness.provider_factory.static_file_provider();
+        let mut reader = StaticFileReader::new(static_file_provider.clone()).unwrap();
+        for (tx_number, receipt) in fixture_receipts(&harness.blocks, 0, 0) {
+            let stored = static_file_provider.receipt(tx_number).unwrap().unwrap();
+            assert_eq!(stored.success, !receipt.success);
+
+            // Inverted by readers with the transforms only
+            assert_eq!(reader.receipt(tx_number).unwrap(), Some(stored));
+            reader.set_row_transforms(transforms.clone());
+            assert_eq!(reader.receipt(tx_number).unwrap(), Some(receipt));
+            reader.set_row_transforms(RowTransforms::new());
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Trickle mode for the initial conversion of an existing archive node, spreading the copy of its
+//! history to static files over days with a daily block budget.
+
+use crate::{manifest::write_json, StaticFileTargets};
+use alloy_primitives::BlockNumber;
+use reth_static_file_types::{HighestStaticFiles, StaticFileSegment};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the file in the static files directory that the [`TrickleProgress`] is persisted to.
+pub const TRICKLE_PROGRESS_FILE_NAME: &str = "trickle_progress.json";
+
+/// Length of the period of the daily block budget.
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Progress of the trickle conversion, persisted to [`TRICKLE_PROGRESS_FILE_NAME`] after every
+/// run, so a restarted node resumes within the budget of the current day.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrickleProgress {
+    /// Start of the current budget day, in seconds since the unix epoch. Zero before the first
+    /// run.
+    pub day_started_at: u64,
+    /// Blocks copied since the start of the current budget day.
+    pub blocks_today: u64,
+    /// Blocks copied since the start of the conversion.
+    pub copied_blocks: u64,
+}
+
+impl TrickleProgress {
+    /// Reads the progress persisted in the static files directory. Returns `None` if nothing was
+    /// persisted yet.
+    pub fn read(directory: &Path) -> io::Result<Option<Self>> {
+        let path = directory.join(TRICKLE_PROGRESS_FILE_NAME);
+        if !path.exists() {
+            return Ok(None)
+        }
+        Ok(Some(serde_json::from_reader(BufReader::new(File::open(path)?))?))
+    }
+
+    /// Persists the progress to the static files directory, replacing the previous progress
+    /// atomically.
+    fn write(&self, directory: &Path) -> io::Result<()> {
+        let path = directory.join(TRICKLE_PROGRESS_FILE_NAME);
+        let tmp_path = path.with_extension("json.tmp");
+        write_json(self, &tmp_path)?;
+        std::fs::rename(tmp_path, path)
+    }
+}
+
+/// Status of the trickle conversion, as returned by [`TrickleScheduler::status`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrickleStatus {
+    /// Maximum number of blocks copied per day.
+    pub daily_block_budget: u64,
+    /// Blocks copied since the start of the current budget day.
+    pub blocks_today: u64,
+    /// Blocks copied since the start of the conversion.
+    pub copied_blocks: u64,
+    /// Blocks left to copy until every segment reaches its finalized block.
+    pub remaining_blocks: u64,
+    /// Estimated time until the remaining blocks are copied, assuming the budget of every day is
+    /// used up as soon as the day starts. `None` if the budget is zero and blocks remain.
+    pub eta: Option<Duration>,
+}
+
+/// Scheduler of the trickle conversion, limiting the blocks copied by
+/// [`StaticFileProducerInner::run_trickle`](crate::StaticFileProducerInner::run_trickle) to a
+/// daily block budget.
+///
+/// A block counts against the budget once, no matter how many segments it's copied to. The
+/// conversion resumes from the highest static files, and the budget spent today from the
+/// [`TrickleProgress`] persisted in the static files directory.
+#[derive(Debug)]
+pub struct TrickleScheduler {
+    /// Static files directory the progress is persisted to.
+    directory: PathBuf,
+    /// Maximum number of blocks copied per day.
+    daily_block_budget: u64,
+    /// Progress of the conversion.
+    progress: TrickleProgress,
+}
+
+impl TrickleScheduler {
+    /// Opens the scheduler of the static files directory with the daily block budget, resuming
+    /// from the persisted [`TrickleProgress`], if any.
+    pub fn open(directory: impl Into<PathBuf>, daily_block_budget: u64) -> io::Result<Self> {
+        let directory = directory.into();
+        let progress = TrickleProgress::read(&directory)?.unwrap_or_default();
+        Ok(Self { directory, daily_block_budget, progress })
+    }
+
+    /// Returns the progress of the conversion.
+    pub const fn progress(&self) -> &TrickleProgress {
+        &self.progress
+    }
+
+    /// Returns the finalized block numbers clamped to the budget left today, on top of the
+    /// highest static files. Segments that already reached the budget get their highest static
+    /// file, so they get no target.
+    pub fn clamp(
+        &mut self,
+        highest_static_files: HighestStaticFiles,
+        finalized_block_numbers: HighestStaticFiles,
+        now: SystemTime,
+    ) -> HighestStaticFiles {
+        let budget_left = self.budget_left(now);
+        let clamp = |segment| {
+            let finalized = finalized_block_numbers.highest(segment)?;
+            let limit = match highest_static_files.highest(segment) {
+                Some(highest) => highest.saturating_add(budget_left),
+                None => budget_left.checked_sub(1)?,
+            };
+            Some(finalized.min(limit))
+        };
+
+        HighestStaticFiles {
+            headers: clamp(StaticFileSegment::Headers),
+            receipts: clamp(StaticFileSegment::Receipts),
+            transactions: clamp(StaticFileSegment::Transactions),
+        }
+    }
+
+    /// Records the blocks of the targets as copied, and persists the progress.
+    pub fn record(&mut self, targets: &StaticFileTargets, now: SystemTime) -> io::Result<()> {
+        self.roll_day(now);
+        let blocks = [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ]
+        .into_iter()
+        .filter_map(|segment| targets.target(segment))
+        .map(|range| range.end() - range.start() + 1)
+        .max()
+        .unwrap_or_default();
+
+        self.progress.blocks_today += blocks;
+        self.progress.copied_blocks += blocks;
+        self.progress.write(&self.directory)
+    }
+
+    /// Returns the status of the conversion towards the finalized block numbers.
+    pub fn status(
+        &mut self,
+        highest_static_files: HighestStaticFiles,
+        finalized_block_numbers: HighestStaticFiles,
+        now: SystemTime,
+    ) -> TrickleStatus {
+        let budget_left = self.budget_left(now);
+        let remaining_blocks = [
+            StaticFileSegment::Headers,
+            StaticFileSegment::Transactions,
+            StaticFileSegment::Receipts,
+        ]
+        .into_iter()
+        .filter_map(|segment| {
+            let finalized = finalized_block_numbers.highest(segment)?;
+            let next = highest_static_files.highest(segment).map_or(0, |highest| highest + 1);
+            Some((finalized + 1).saturating_sub(next))
+        })
+        .max()
+        .unwrap_or_default();
+
+        let eta = if remaining_blocks <= budget_left {
+            Some(Duration::ZERO)
+        } else if self.daily_block_budget == 0 {
+            None
+        } else {
+            // The rest of today, and the whole days needed for the blocks left after today
+            let days = (remaining_blocks - budget_left).div_ceil(self.daily_block_budget);
+            let day_ends_at = UNIX_EPOCH +
+                Duration::from_secs(self.progress.day_started_at) +
+                DAY * days.try_into().unwrap_or(u32::MAX);
+            Some(day_ends_at.duration_since(now).unwrap_or_default())
+        };
+
+        TrickleStatus {
+            daily_block_budget: self.daily_block_budget,
+            blocks_today: self.progress.blocks_today,
+            copied_blocks: self.progress.copied_blocks,
+            remaining_blocks,
+            eta,
+        }
+    }
+
+    /// Returns the number of blocks that can still be copied today.
+    fn budget_left(&mut self, now: SystemTime) -> BlockNumber {
+        self.roll_day(now);
+        self.daily_block_budget.saturating_sub(self.progress.blocks_today)
+    }
+
+    /// Starts a new budget day if the current one is over. The first day starts at the first run.
+    fn roll_day(&mut self, now: SystemTime) {
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        if self.progress.day_started_at == 0 {
+            self.progress.day_started_at = now;
+        }
+
+        let elapsed_days = now.saturating_sub(self.progress.day_started_at) / DAY.as_secs();
+        if elapsed_days > 0 {
+            self.progress.day_started_at += elapsed_days * DAY.as_secs();
+            self.progress.blocks_today = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+    use reth_provider::StaticFileProviderFactory;
+
+    #[test]
+    fn spreads_conversion_over_days() {
+        let harness = StaticFileTestHarness::new(3, 1..2);
+        let producer = harness.producer();
+        let finalized =
+            HighestStaticFiles { headers: Some(3), receipts: Some(3), transactions: Some(3) };
+        let highest = || harness.provider_factory.static_file_provider().get_highest_static_files();
+
+        let mut scheduler = TrickleScheduler::open(harness.static_files_dir.path(), 3).unwrap();
+        let targets = producer.run_trickle(&mut scheduler, finalized).unwrap();
+        assert_eq!(targets.target(StaticFileSegment::Headers), Some(&(0..=2)));
+        assert_eq!(highest().headers, Some(2));
+
+        // Budget of today is spent, and persisted across restarts
+        let mut scheduler = TrickleScheduler::open(harness.static_files_dir.path(), 3).unwrap();
+        assert_eq!(scheduler.progress().blocks_today, 3);
+        assert!(!producer.run_trickle(&mut scheduler, finalized).unwrap().any());
+        assert_eq!(highest().headers, Some(2));
+
+        let now = SystemTime::now();
+        let status = scheduler.status(highest(), finalized, now);
+        assert_eq!(status.remaining_blocks, 1);
+        assert!(status.eta.unwrap() > Duration::ZERO && status.eta.unwrap() <= DAY);
+
+        // Next day has a fresh budget
+        let tomorrow = now + DAY;
+        assert_eq!(scheduler.clamp(highest(), finalized, tomorrow).headers, Some(3));
+        assert_eq!(scheduler.status(highest(), finalized, tomorrow).eta, Some(Duration::ZERO));
+        assert_eq!(scheduler.progress().blocks_today, 0);
+    }
+}
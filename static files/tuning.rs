@@ -0,0 +1,208 @@
+//! Benchmark of the compressions of a segment on a sample of its rows, to pick the compression
+//! with the best ratio that still decodes fast enough.
+//!
+//! Rows are compressed one at a time, like static files compress their columns, at the level
+//! static files are compressed with, since segment configurations don't set levels. The
+//! dictionary of [`Compression::ZstdWithDictionary`] is trained on the sample and not accounted
+//! in its ratio, as it's amortized over the rows of a whole static file.
+
+use crate::segments::SegmentProvider;
+use reth_db_api::table::Compress;
+use reth_nippy_jar::compression::{Compression as _, Lz4};
+use reth_static_file_types::{Compression, StaticFileSegment};
+use reth_storage_errors::provider::{ProviderError, ProviderResult};
+use std::{
+    io,
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// Maximum size of the zstd dictionary trained on the sample, as used by static files.
+const MAX_DICTIONARY_SIZE: usize = 5_000_000;
+
+/// Outcome of a compression benchmark on a sample of the rows of a segment, returned by
+/// [`StaticFileProducerInner::auto_tune`](crate::StaticFileProducerInner::auto_tune).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionReport {
+    /// Segment the sample was taken from.
+    pub segment: StaticFileSegment,
+    /// Number of rows in the sample.
+    pub rows: usize,
+    /// Uncompressed size of the sample in bytes.
+    pub bytes: u64,
+    /// Every benchmarked compression. Compressions that can't be used on the sample, e.g. when
+    /// it's too small to train a dictionary, are left out.
+    pub candidates: Vec<CompressionCandidate>,
+}
+
+impl CompressionReport {
+    /// Returns the candidate with the best ratio among those decompressing at least
+    /// `min_decompress_speed` bytes per second, if any.
+    pub fn best(&self, min_decompress_speed: f64) -> Option<&CompressionCandidate> {
+        self.candidates
+            .iter()
+            .filter(|candidate| candidate.decompress_speed >= min_decompress_speed)
+            .max_by(|a, b| a.ratio.total_cmp(&b.ratio))
+    }
+}
+
+/// Ratio and speed of a compression on the sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressionCandidate {
+    /// Benchmarked compression.
+    pub compression: Compression,
+    /// Uncompressed size of the sample divided by its compressed size.
+    pub ratio: f64,
+    /// Uncompressed bytes compressed per second.
+    pub compress_speed: f64,
+    /// Uncompressed bytes decompressed per second.
+    pub decompress_speed: f64,
+}
+
+/// Reads the rows of the segment for the block range from the provider, encoded as they're
+/// stored in the database, failing if there are none.
+pub(crate) fn sample_rows<P: SegmentProvider>(
+    provider: &P,
+    segment: StaticFileSegment,
+    block_range: RangeInclusive<u64>,
+) -> ProviderResult<Vec<Vec<u8>>> {
+    let rows = match segment {
+        StaticFileSegment::Headers => encoded_rows(provider.headers_range(block_range.clone())?),
+        StaticFileSegment::Transactions => {
+            let tx_range = provider.transaction_range_by_block_range(block_range.clone())?;
+            encoded_rows(provider.transactions_by_tx_range(tx_range)?)
+        }
+        StaticFileSegment::Receipts => {
+            let tx_range = provider.transaction_range_by_block_range(block_range.clone())?;
+            encoded_rows(provider.receipts_by_tx_range(tx_range)?)
+        }
+    };
+    if rows.is_empty() {
+        return Err(ProviderError::NippyJar(format!(
+            "no {segment} rows in the database for blocks {block_range:?} to sample"
+        )))
+    }
+    Ok(rows)
+}
+
+/// Encodes the rows as they're stored in the database.
+fn encoded_rows<T: Compress>(rows: Vec<T>) -> Vec<Vec<u8>> {
+    rows.into_iter().map(|row| row.compress().into()).collect()
+}
+
+/// Benchmarks every [`Compression`] on the rows of the segment.
+pub fn benchmark_compression(segment: StaticFileSegment, rows: &[Vec<u8>]) -> CompressionReport {
+    let bytes = rows.iter().map(|row| row.len() as u64).sum::<u64>();
+    let mut candidates = Vec::new();
+    for compression in [
+        Compression::Uncompressed,
+        Compression::Lz4,
+        Compression::Zstd,
+        Compression::ZstdWithDictionary,
+    ] {
+        match benchmark(compression, rows, bytes) {
+            Ok(candidate) => candidates.push(candidate),
+            Err(err) => {
+                debug!(target: "static_file", %segment, ?compression, %err, "Compression can't be benchmarked")
+            }
+        }
+    }
+    CompressionReport { segment, rows: rows.len(), bytes, candidates }
+}
+
+/// Compresses and decompresses every row with the compression, timing both.
+fn benchmark(
+    compression: Compression,
+    rows: &[Vec<u8>],
+    bytes: u64,
+) -> io::Result<CompressionCandidate> {
+    let level = zstd::DEFAULT_COMPRESSION_LEVEL;
+    let (mut compressor, mut decompressor) = match compression {
+        Compression::ZstdWithDictionary => {
+            let dictionary = zstd::dict::from_samples(rows, MAX_DICTIONARY_SIZE)?;
+            (
+                zstd::bulk::Compressor::with_dictionary(level, &dictionary)?,
+                zstd::bulk::Decompressor::with_dictionary(&dictionary)?,
+            )
+        }
+        _ => (zstd::bulk::Compressor::new(level)?, zstd::bulk::Decompressor::new()?),
+    };
+
+    let start = Instant::now();
+    let compressed = rows
+        .iter()
+        .map(|row| match compression {
+            Compression::Uncompressed => Ok(row.clone()),
+            Compression::Lz4 => Lz4::default().compress(row).map_err(io::Error::other),
+            Compression::Zstd | Compression::ZstdWithDictionary => compressor.compress(row),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    let compress_time = start.elapsed();
+
+    let start = Instant::now();
+    for (row, compressed) in rows.iter().zip(&compressed) {
+        let decompressed = match compression {
+            Compression::Uncompressed => compressed.clone(),
+            Compression::Lz4 => Lz4::default().decompress(compressed).map_err(io::Error::other)?,
+            Compression::Zstd | Compression::ZstdWithDictionary => {
+                decompressor.decompress(compressed, row.len())?
+            }
+        };
+        if decompressed != *row {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "row doesn't round-trip"))
+        }
+    }
+    let decompress_time = start.elapsed();
+
+    let compressed_bytes = compressed.iter().map(|row| row.len() as u64).sum::<u64>();
+    Ok(CompressionCandidate {
+        compression,
+        ratio: bytes as f64 / compressed_bytes.max(1) as f64,
+        compress_speed: speed(bytes, compress_time),
+        decompress_speed: speed(bytes, decompress_time),
+    })
+}
+
+/// Returns the number of bytes processed per second, taking at least a nanosecond.
+fn speed(bytes: u64, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.max(Duration::from_nanos(1)).as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+
+    #[test]
+    fn compression_benchmark() {
+        // Repetitive rows compress well with every compression
+        let rows = (0..1_000u32)
+            .map(|i| [i.to_be_bytes().as_slice(), &[0xab; 60]].concat())
+            .collect::<Vec<_>>();
+        let report = benchmark_compression(StaticFileSegment::Receipts, &rows);
+        assert_eq!(report.rows, 1_000);
+        assert_eq!(report.bytes, 64_000);
+
+        let ratio = |compression| {
+            let candidate = report.candidates.iter().find(|c| c.compression == compression);
+            candidate.unwrap().ratio
+        };
+        assert_eq!(ratio(Compression::Uncompressed), 1.0);
+        assert!(ratio(Compression::Zstd) > 1.0);
+        assert!(ratio(Compression::Lz4) > 1.0);
+
+        // Best ratio without a constraint, nothing decodes infinitely fast
+        let best = report.best(0.0).unwrap();
+        assert!(report.candidates.iter().all(|candidate| candidate.ratio <= best.ratio));
+        assert_eq!(report.best(f64::INFINITY), None);
+
+        // Sampled from the database
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        let mut producer = harness.producer();
+        let report = producer.auto_tune(StaticFileSegment::Transactions, 0..=3).unwrap();
+        assert!(report.rows > 0);
+        let compression = producer.apply_auto_tune(&report, 0.0).unwrap();
+        assert_eq!(producer.config().segments.transactions.compression, compression);
+    }
+}
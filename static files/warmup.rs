@@ -0,0 +1,243 @@
+//! Warmup of the offsets and filters of static files, so the first reads after a node restart
+//! don't pay for page faults on every lookup.
+//!
+//! Readers map the offsets of a static file and load its filters from disk, both of which are
+//! cold in the page cache after a restart. Warming them up reads their companion files through,
+//! or maps and locks them in memory with [`WarmupMode::Lock`], until the returned
+//! [`WarmedFiles`] is dropped. The data files themselves are left alone, as only the rows that
+//! are read are worth caching. The newest static files of every segment, which serve most reads,
+//! can be warmed up on startup, see
+//! [`StaticFileProducerInner::set_warmup`](crate::StaticFileProducerInner::set_warmup).
+
+use crate::{list_static_files, StaticFileEntry};
+use alloy_primitives::BlockNumber;
+use reth_static_file_types::StaticFileSegment;
+use serde::{Deserialize, Serialize};
+use std::{io, ops::RangeInclusive, path::Path};
+use tracing::warn;
+
+/// Extensions of the companion files warmed up: offsets, index holding the filters and perfect
+/// hashing function, and configuration.
+pub const WARMUP_EXTENSIONS: [&str; 3] = ["off", "idx", "conf"];
+
+/// How the companion files of static files are warmed up.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarmupMode {
+    /// Files are read through, loading them in the page cache, from which they may still be
+    /// evicted under memory pressure.
+    #[default]
+    Touch,
+    /// Files are mapped and locked in memory until the [`WarmedFiles`] are dropped. Files are
+    /// only locked on Linux, and are touched instead if the limit of locked memory is reached.
+    Lock,
+}
+
+/// Configuration of the warmup of the newest static files on startup, see
+/// [`StaticFileProducerInner::set_warmup`](crate::StaticFileProducerInner::set_warmup).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WarmupConfig {
+    /// Number of the newest static files of every segment warmed up.
+    pub newest_files: usize,
+    /// How the static files are warmed up.
+    #[serde(default)]
+    pub mode: WarmupMode,
+}
+
+/// Static files whose companion files were warmed up. Locked regions are unlocked once dropped.
+#[derive(Debug, Default)]
+#[must_use = "locked files are unlocked once dropped"]
+pub struct WarmedFiles {
+    /// Warmed up static files.
+    pub entries: Vec<StaticFileEntry>,
+    /// Number of bytes of the companion files warmed up.
+    pub bytes: u64,
+    /// Regions locked in memory.
+    locked: Vec<LockedRegion>,
+}
+
+impl WarmedFiles {
+    /// Returns the number of bytes locked in memory.
+    pub fn locked_bytes(&self) -> u64 {
+        self.locked.iter().map(|region| region.len as u64).sum()
+    }
+
+    /// Warms up the companion files of the static file.
+    fn warm(&mut self, entry: &StaticFileEntry, mode: WarmupMode) -> io::Result<()> {
+        for extension in WARMUP_EXTENSIONS {
+            let path = entry.companion_path(extension);
+            if !path.exists() {
+                continue
+            }
+            if mode == WarmupMode::Lock {
+                match LockedRegion::lock(&path) {
+                    Ok(Some(region)) => {
+                        self.bytes += region.len as u64;
+                        self.locked.push(region);
+                        continue
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        warn!(target: "static_file", ?path, %err, "Failed to lock static file in memory, touching it instead");
+                    }
+                }
+            }
+            self.bytes += io::copy(&mut std::fs::File::open(&path)?, &mut io::sink())?;
+        }
+        self.entries.push(entry.clone());
+        Ok(())
+    }
+}
+
+/// Warms up the offsets and filters of the static files of the segment overlapping the block
+/// range.
+pub fn warmup(
+    directory: &Path,
+    segment: StaticFileSegment,
+    range: RangeInclusive<BlockNumber>,
+    mode: WarmupMode,
+) -> io::Result<WarmedFiles> {
+    let mut warmed = WarmedFiles::default();
+    for entry in list_static_files(directory)?.iter().filter(|entry| {
+        entry.segment == segment &&
+            entry.block_range.start() <= *range.end() &&
+            *range.start() <= entry.block_range.end()
+    }) {
+        warmed.warm(entry, mode)?;
+    }
+    Ok(warmed)
+}
+
+/// Warms up the offsets and filters of the newest static files of every segment.
+pub(crate) fn warmup_newest(directory: &Path, config: WarmupConfig) -> io::Result<WarmedFiles> {
+    let entries = list_static_files(directory)?;
+    let mut warmed = WarmedFiles::default();
+    for segment in
+        [StaticFileSegment::Headers, StaticFileSegment::Transactions, StaticFileSegment::Receipts]
+    {
+        // Static files are listed by block range, so the newest ones come last
+        for entry in
+            entries.iter().rev().filter(|entry| entry.segment == segment).take(config.newest_files)
+        {
+            warmed.warm(entry, config.mode)?;
+        }
+    }
+    Ok(warmed)
+}
+
+/// File mapped and locked in memory, until dropped.
+#[derive(Debug)]
+struct LockedRegion {
+    /// Start of the mapping.
+    ptr: *mut std::ffi::c_void,
+    /// Length of the mapping.
+    len: usize,
+}
+
+// SAFETY: the mapping is read-only and owned by the region.
+unsafe impl Send for LockedRegion {}
+// SAFETY: the mapping is read-only and owned by the region.
+unsafe impl Sync for LockedRegion {}
+
+impl LockedRegion {
+    /// Maps the file and locks it in memory. Returns `None` if the file is empty.
+    #[cfg(target_os = "linux")]
+    fn lock(path: &Path) -> io::Result<Option<Self>> {
+        use std::os::fd::AsRawFd;
+
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            return Ok(None)
+        }
+        // SAFETY: the descriptor is owned by `file`, which outlives the call. The mapping is
+        // kept alive after the file is closed.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error())
+        }
+        let region = Self { ptr, len };
+        // SAFETY: the region was just mapped with this length. It's unmapped when dropped.
+        if unsafe { libc::mlock(region.ptr, region.len) } != 0 {
+            return Err(io::Error::last_os_error())
+        }
+        Ok(Some(region))
+    }
+
+    /// Maps the file and locks it in memory. Always returns `None`, as files are only locked on
+    /// Linux.
+    #[cfg(not(target_os = "linux"))]
+    fn lock(_path: &Path) -> io::Result<Option<Self>> {
+        Ok(None)
+    }
+}
+
+impl Drop for LockedRegion {
+    fn drop(&mut self) {
+        // Unmapping the region unlocks it
+        // SAFETY: the region is mapped with this length, and isn't used anymore.
+        #[cfg(target_os = "linux")]
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::StaticFileTestHarness;
+
+    #[test]
+    fn warmup_static_files() {
+        let harness = StaticFileTestHarness::new(3, 1..3);
+        harness.run().unwrap();
+        let directory = harness.static_files_dir.path();
+        let entries = list_static_files(directory).unwrap();
+        let size = |entry: &StaticFileEntry| {
+            WARMUP_EXTENSIONS
+                .iter()
+                .map(|extension| entry.companion_path(extension))
+                .filter(|path| path.exists())
+                .map(|path| path.metadata().unwrap().len())
+                .sum::<u64>()
+        };
+
+        // Only static files of the segment overlapping the range are warmed up
+        let warmed =
+            warmup(directory, StaticFileSegment::Receipts, 0..=3, WarmupMode::Touch).unwrap();
+        let receipts =
+            entries.iter().find(|entry| entry.segment == StaticFileSegment::Receipts).unwrap();
+        assert_eq!(warmed.entries, [receipts.clone()]);
+        assert_eq!(warmed.bytes, size(receipts));
+        assert_eq!(warmed.locked_bytes(), 0);
+        let warmed = warmup(
+            directory,
+            StaticFileSegment::Receipts,
+            receipts.block_range.end() + 1..=u64::MAX,
+            WarmupMode::Touch,
+        )
+        .unwrap();
+        assert!(warmed.entries.is_empty());
+
+        // Files that can't be locked are touched instead
+        let config = WarmupConfig { newest_files: 1, mode: WarmupMode::Lock };
+        let warmed = warmup_newest(directory, config).unwrap();
+        assert_eq!(warmed.entries.len(), 3);
+        assert_eq!(warmed.bytes, entries.iter().map(size).sum::<u64>());
+        assert!(warmed.locked_bytes() <= warmed.bytes);
+        drop(warmed);
+
+        let warmed = harness.producer().warmup(StaticFileSegment::Headers, 0..=0).unwrap();
+        assert_eq!(warmed.entries.len(), 1);
+    }
+}
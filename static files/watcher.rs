@@ -0,0 +1,221 @@
+//! Watcher detecting modifications of static files made outside of the producer.
+
+use crate::{StaticFileEntry, StaticFileProducerEvent, COMPANION_EXTENSIONS};
+use notify::{event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use reth_static_file_types::StaticFileSegment;
+use std::{
+    fmt,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Time after the watcher is resumed during which changes are still ignored, as filesystem events
+/// of changes made while paused may be delivered late.
+const RESUME_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Kind of a modification of a static file made outside of the producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExternalChangeKind {
+    /// The data file or one of its companion files was modified.
+    Modified,
+    /// The data file or one of its companion files was removed, or renamed away.
+    Removed,
+}
+
+/// Watcher of the static files directory, reporting static files that were modified or removed
+/// while the producer wasn't writing to them, e.g. by an accidental `rm`.
+///
+/// Affected static files are emitted as [`StaticFileProducerEvent::ExternalChange`] and marked
+/// dirty, until they're taken with [`StaticFileWatcher::take_dirty`] to be checked for
+/// consistency.
+pub struct StaticFileWatcher {
+    /// Underlying filesystem watcher, stops watching on drop.
+    _watcher: RecommendedWatcher,
+    /// State shared with the filesystem watcher thread.
+    state: Arc<WatcherState>,
+}
+
+#[derive(Debug, Default)]
+struct WatcherState {
+    /// Number of active [`WatcherPause`] guards. Changes are ignored while it's not zero.
+    paused: AtomicUsize,
+    /// Time the watcher was last resumed at.
+    resumed_at: Mutex<Option<Instant>>,
+    /// Static files affected by external changes.
+    dirty: Mutex<Vec<StaticFileEntry>>,
+}
+
+impl StaticFileWatcher {
+    /// Starts watching the static files directory, calling `notify` with an
+    /// [`StaticFileProducerEvent::ExternalChange`] for every external change.
+    pub fn new(
+        directory: &Path,
+        notify: impl Fn(StaticFileProducerEvent) + Send + 'static,
+    ) -> notify::Result<Self> {
+        let state = Arc::new(WatcherState::default());
+        let mut watcher = RecommendedWatcher::new(
+            {
+                let state = state.clone();
+                move |event: notify::Result<Event>| {
+                    let Ok(event) = event else { return };
+                    if state.is_paused() {
+                        return
+                    }
+
+                    for (entry, kind) in external_changes(&event) {
+                        let mut dirty = state.dirty.lock();
+                        if !dirty.contains(&entry) {
+                            dirty.push(entry.clone());
+                        }
+                        drop(dirty);
+
+                        notify(StaticFileProducerEvent::ExternalChange {
+                            segment: entry.segment,
+                            block_range: entry.block_range,
+                            path: entry.path,
+                            kind,
+                        });
+                    }
+                }
+            },
+            notify::Config::default(),
+        )?;
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+
+        Ok(Self { _watcher: watcher, state })
+    }
+
+    /// Ignores all changes until the returned guard is dropped, while the producer modifies
+    /// static files itself.
+    pub(crate) fn pause(&self) -> WatcherPause {
+        self.state.paused.fetch_add(1, Ordering::SeqCst);
+        WatcherPause(self.state.clone())
+    }
+
+    /// Returns the static files affected by external changes.
+    pub fn dirty(&self) -> Vec<StaticFileEntry> {
+        self.state.dirty.lock().clone()
+    }
+
+    /// Returns the static files affected by external changes, and clears them.
+    pub fn take_dirty(&self) -> Vec<StaticFileEntry> {
+        std::mem::take(&mut *self.state.dirty.lock())
+    }
+}
+
+impl WatcherState {
+    /// Returns `true` if changes are ignored, because the watcher is paused or was resumed just
+    /// now.
+    fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst) > 0 ||
+            self.resumed_at.lock().is_some_and(|at| at.elapsed() < RESUME_GRACE_PERIOD)
+    }
+}
+
+impl fmt::Debug for StaticFileWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticFileWatcher").field("state", &self.state).finish()
+    }
+}
+
+/// Guard returned by [`StaticFileWatcher::pause`], resuming the watcher on drop.
+#[derive(Debug)]
+pub(crate) struct WatcherPause(Arc<WatcherState>);
+
+impl Drop for WatcherPause {
+    fn drop(&mut self) {
+        *self.0.resumed_at.lock() = Some(Instant::now());
+        self.0.paused.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Returns the static files affected by the filesystem event.
+fn external_changes(event: &Event) -> Vec<(StaticFileEntry, ExternalChangeKind)> {
+    event
+        .paths
+        .iter()
+        .filter_map(|path| {
+            let kind = match event.kind {
+                EventKind::Remove(_) => ExternalChangeKind::Removed,
+                EventKind::Modify(ModifyKind::Name(_)) if !path.exists() => {
+                    ExternalChangeKind::Removed
+                }
+                EventKind::Modify(ModifyKind::Metadata(_)) => return None,
+                EventKind::Modify(_) => ExternalChangeKind::Modified,
+                _ => return None,
+            };
+            Some((static_file_entry(path)?, kind))
+        })
+        .collect()
+}
+
+/// Returns the static file of a data or companion file path.
+fn static_file_entry(path: &Path) -> Option<StaticFileEntry> {
+    let name = path.file_name()?.to_str()?;
+    let data_name = match name.split_once('.') {
+        Some((data_name, extension)) if COMPANION_EXTENSIONS.contains(&extension) => data_name,
+        Some(_) => return None,
+        None => name,
+    };
+
+    let (segment, block_range) = StaticFileSegment::parse_filename(data_name)?;
+    Some(StaticFileEntry { segment, block_range, path: path.with_file_name(data_name) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn reports_removed_static_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let data_path = directory.path().join("static_file_headers_0_499999");
+        for path in [&data_path, &data_path.with_extension("off"), &directory.path().join("other")]
+        {
+            std::fs::write(path, [0]).unwrap();
+        }
+
+        let (tx, rx) = channel();
+        let watcher = StaticFileWatcher::new(directory.path(), move |event| {
+            let _ = tx.send(event);
+        })
+        .unwrap();
+
+        // Changes made while paused are ignored
+        let pause = watcher.pause();
+        std::fs::write(&data_path, [1]).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        drop(pause);
+        std::thread::sleep(RESUME_GRACE_PERIOD);
+        assert!(rx.try_recv().is_err());
+
+        std::fs::remove_file(directory.path().join("other")).unwrap();
+        std::fs::remove_file(data_path.with_extension("off")).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let event = loop {
+            match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())).unwrap() {
+                event @ StaticFileProducerEvent::ExternalChange {
+                    kind: ExternalChangeKind::Removed,
+                    ..
+                } => break event,
+                _ => continue,
+            }
+        };
+        assert!(matches!(
+            event,
+            StaticFileProducerEvent::ExternalChange { segment: StaticFileSegment::Headers, .. }
+        ));
+        assert_eq!(
+            watcher.take_dirty().iter().map(|entry| entry.path.clone()).collect::<Vec<_>>(),
+            vec![data_path]
+        );
+        assert!(watcher.dirty().is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+//! Worker threads copying segments in parallel, named after their segment and optionally pinned
+//! to CPU cores, so they can be told apart from and kept off the cores used by execution.
+//!
+//! Inclusion filters and perfect hashing functions are built on a separate rayon thread pool,
+//! bounded by [`WorkersConfig::filter_threads`].
+
+use reth_static_file_types::StaticFileSegment;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    thread::{Scope, ScopedJoinHandle},
+};
+use tracing::warn;
+
+/// Default prefix of the names of worker threads.
+pub const DEFAULT_THREAD_NAME_PREFIX: &str = "sf";
+
+/// Configuration of the worker threads copying segments with
+/// [`RunOrder::Parallel`](crate::RunOrder::Parallel).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkersConfig {
+    /// Prefix of the names of worker threads, followed by the segment and the index of the
+    /// worker within it, e.g. `sf-headers-0`.
+    pub thread_name_prefix: String,
+    /// CPU cores that workers are pinned to, assigned round-robin in the order workers are
+    /// spawned. If empty, workers run on any core.
+    ///
+    /// Pinning is only supported on Linux, where pages are allocated on the NUMA node of the core
+    /// that first touches them, so compression buffers of pinned workers stay local. Elsewhere,
+    /// it's ignored with a warning.
+    pub pin_to_cores: Vec<usize>,
+    /// Number of threads hashing the keys and building the perfect hashing functions of
+    /// inclusion filters, when static files are rewritten or their filters rebuilt. If `None`,
+    /// the global rayon thread pool is used, with a thread per core.
+    pub filter_threads: Option<usize>,
+}
+
+impl Default for WorkersConfig {
+    fn default() -> Self {
+        Self {
+            thread_name_prefix: DEFAULT_THREAD_NAME_PREFIX.to_string(),
+            pin_to_cores: Vec::new(),
+            filter_threads: None,
+        }
+    }
+}
+
+impl WorkersConfig {
+    /// Returns the name of the `index`th worker thread of the segment.
+    pub fn thread_name(&self, segment: StaticFileSegment, index: usize) -> String {
+        format!("{}-{}-{index}", self.thread_name_prefix, segment.as_str())
+    }
+
+    /// Returns the core that the `n`th spawned worker is pinned to, if any.
+    pub fn core(&self, n: usize) -> Option<usize> {
+        (!self.pin_to_cores.is_empty()).then(|| self.pin_to_cores[n % self.pin_to_cores.len()])
+    }
+
+    /// Spawns the `n`th worker of the run on a named thread of the scope, pinned to its core if
+    /// configured. Every segment is copied by a single worker.
+    pub(crate) fn spawn_scoped<'scope, T: Send + 'scope>(
+        &self,
+        scope: &'scope Scope<'scope, '_>,
+        segment: StaticFileSegment,
+        n: usize,
+        f: impl FnOnce() -> T + Send + 'scope,
+    ) -> io::Result<ScopedJoinHandle<'scope, T>> {
+        let core = self.core(n);
+        std::thread::Builder::new().name(self.thread_name(segment, 0)).spawn_scoped(scope, move || {
+            if let Some(core) = core {
+                if let Err(err) = pin_current_thread(core) {
+                    warn!(target: "static_file", %segment, core, %err, "Failed to pin worker thread");
+                }
+            }
+            f()
+        })
+    }
+
+    /// Runs `f` on a rayon thread pool of [`WorkersConfig::filter_threads`] threads, named
+    /// `<prefix>-filters-<index>`, so the filters built by `f` stay within the thread budget.
+    /// Without a budget, `f` runs on the current thread with the global thread pool.
+    pub(crate) fn install_filters<T: Send>(&self, f: impl FnOnce() -> T + Send) -> io::Result<T> {
+        let Some(threads) = self.filter_threads else { return Ok(f()) };
+        let prefix = self.thread_name_prefix.clone();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.max(1))
+            .thread_name(move |index| format!("{prefix}-filters-{index}"))
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(pool.install(f))
+    }
+}
+
+/// Pins the current thread to the CPU core.
+#[cfg(target_os = "linux")]
+fn pin_current_thread(core: usize) -> io::Result<()> {
+    if core >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no CPU core {core}")))
+    }
+    // SAFETY: a zeroed `cpu_set_t` is an empty CPU set.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    // SAFETY: `core` is within the CPU set.
+    unsafe { libc::CPU_SET(core, &mut set) };
+    // SAFETY: `set` is a valid pointer to a `cpu_set_t` of the passed size, and 0 is the current
+    // thread.
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error())
+    }
+    Ok(())
+}
+
+/// Pins the current thread to the CPU core.
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_core: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "pinning threads is only supported on Linux"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spawn_scoped() {
+        let workers = WorkersConfig { pin_to_cores: vec![0], ..Default::default() };
+        assert_eq!(workers.core(0), Some(0));
+        assert_eq!(workers.core(3), Some(0));
+        assert_eq!(WorkersConfig::default().core(0), None);
+
+        let name = std::thread::scope(|scope| {
+            workers
+                .spawn_scoped(scope, StaticFileSegment::Receipts, 0, || {
+                    std::thread::current().name().map(ToString::to_string)
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        });
+        assert_eq!(name.as_deref(), Some("sf-receipts-0"));
+
+        // Filters are built within the thread budget
+        let workers = WorkersConfig { filter_threads: Some(2), ..Default::default() };
+        let (threads, name) = workers
+            .install_filters(|| {
+                (
+                    rayon::current_num_threads(),
+                    std::thread::current().name().map(ToString::to_string),
+                )
+            })
+            .unwrap();
+        assert_eq!(threads, 2);
+        assert!(name.unwrap().starts_with("sf-filters-"));
+    }
+}
@@ -0,0 +1,250 @@
+//! CHD (compress, hash and displace) minimal perfect hashing of the keys of a static file.
+//!
+//! Keys are hashed into buckets of [`KEYS_PER_BUCKET`] keys on average, and every bucket gets
+//! the displacement that moves all of its keys to free slots of the table, largest buckets
+//! first. A lookup hashes the key once and reads a single displacement and slot, which keeps
+//! cache misses low on the millions of keys of a full static file.
+
+use alloc::vec::Vec;
+use core::fmt;
+use xxhash_rust::xxh3::xxh3_128_with_seed;
+
+/// Average number of keys per bucket.
+const KEYS_PER_BUCKET: usize = 5;
+
+/// Number of seeds tried before the build gives up.
+const MAX_SEEDS: u64 = 16;
+
+/// Minimal perfect hash of the keys of a static file, mapping each of them to its row.
+///
+/// Keys that weren't built into the index are mapped to an arbitrary row, so the row has to be
+/// checked against the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChdIndex {
+    /// Seed of the hash of the keys.
+    seed: u64,
+    /// Displacements of the buckets.
+    displacements: Vec<(u32, u32)>,
+    /// Rows of the keys, by slot.
+    rows: Vec<u32>,
+}
+
+impl ChdIndex {
+    /// Builds the index of the keys, in row order. Of keys that appear several times, the first
+    /// row is kept.
+    pub fn build<K: AsRef<[u8]>>(keys: &[K]) -> Result<Self, ChdError> {
+        if keys.len() > u32::MAX as usize {
+            return Err(ChdError::Build)
+        }
+        for seed in 0..MAX_SEEDS {
+            if let Some(index) = Self::try_build(keys, seed) {
+                return Ok(index)
+            }
+        }
+        Err(ChdError::Build)
+    }
+
+    /// Builds the index of the keys with the seed, or returns `None` if a bucket can't be
+    /// displaced to free slots.
+    fn try_build<K: AsRef<[u8]>>(keys: &[K], seed: u64) -> Option<Self> {
+        let hashes = keys.iter().map(|key| KeyHash::new(key.as_ref(), seed)).collect::<Vec<_>>();
+        let buckets_len = keys.len().div_ceil(KEYS_PER_BUCKET).max(1);
+        let mut buckets = (0..buckets_len).map(|bucket| (bucket, Vec::new())).collect::<Vec<_>>();
+        for (row, hash) in hashes.iter().enumerate() {
+            let bucket = &mut buckets[(hash.bucket % buckets_len as u64) as usize].1;
+            // Duplicated keys can't be told apart, so only the first one is indexed
+            if !bucket.iter().any(|other: &usize| keys[*other].as_ref() == keys[row].as_ref()) {
+                bucket.push(row);
+            }
+        }
+        buckets.sort_by(|(_, a), (_, b)| b.len().cmp(&a.len()));
+
+        let slots = buckets.iter().map(|(_, rows)| rows.len()).sum::<usize>();
+        let mut rows = alloc::vec![None; slots];
+        let mut displacements = alloc::vec![(0, 0); buckets_len];
+        // Slots taken by the keys of the bucket for the displacement that's tried
+        let mut tried = alloc::vec![0u64; slots];
+        let mut attempt = 0u64;
+        let mut placed = Vec::new();
+        let max_attempts = (slots as u64).max(1 << 10) * 8;
+
+        'buckets: for (bucket, bucket_rows) in &buckets {
+            if bucket_rows.is_empty() {
+                break
+            }
+            let first_attempt = attempt;
+            for d0 in 0..slots as u32 {
+                'displacements: for d1 in 0..slots as u32 {
+                    if attempt - first_attempt >= max_attempts {
+                        return None
+                    }
+                    attempt += 1;
+                    placed.clear();
+                    for row in bucket_rows {
+                        let slot = hashes[*row].slot(d0, d1, slots as u64);
+                        if rows[slot].is_some() || tried[slot] == attempt {
+                            continue 'displacements
+                        }
+                        tried[slot] = attempt;
+                        placed.push((slot, *row));
+                    }
+                    displacements[*bucket] = (d0, d1);
+                    for (slot, row) in &placed {
+                        rows[*slot] = Some(*row as u32);
+                    }
+                    continue 'buckets
+                }
+            }
+            return None
+        }
+
+        Some(Self {
+            seed,
+            displacements,
+            rows: rows.into_iter().map(Option::unwrap_or_default).collect(),
+        })
+    }
+
+    /// Returns the number of indexed keys.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Returns `true` if no keys are indexed.
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Returns the row of the key, or `None` if no keys are indexed. Keys that weren't indexed
+    /// get an arbitrary row.
+    pub fn row(&self, key: &[u8]) -> Option<u64> {
+        if self.rows.is_empty() {
+            return None
+        }
+        let hash = KeyHash::new(key, self.seed);
+        let (d0, d1) = self.displacements[(hash.bucket % self.displacements.len() as u64) as usize];
+        Some(self.rows[hash.slot(d0, d1, self.rows.len() as u64)] as u64)
+    }
+
+    /// Encodes the index: the seed, and the displacements and rows, each prefixed by their
+    /// count. Integers are little-endian.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.reserve(16 + self.displacements.len() * 8 + self.rows.len() * 4);
+        buf.extend_from_slice(&self.seed.to_le_bytes());
+        buf.extend_from_slice(&(self.displacements.len() as u32).to_le_bytes());
+        for (d0, d1) in &self.displacements {
+            buf.extend_from_slice(&d0.to_le_bytes());
+            buf.extend_from_slice(&d1.to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.rows.len() as u32).to_le_bytes());
+        for row in &self.rows {
+            buf.extend_from_slice(&row.to_le_bytes());
+        }
+    }
+
+    /// Decodes an index encoded with [`ChdIndex::encode`].
+    pub fn decode(mut buf: &[u8]) -> Result<Self, ChdError> {
+        let seed = u64::from_le_bytes(*read_chunk(&mut buf)?);
+        let len = u32::from_le_bytes(*read_chunk(&mut buf)?);
+        let displacements = (0..len)
+            .map(|_| {
+                let d0 = u32::from_le_bytes(*read_chunk(&mut buf)?);
+                let d1 = u32::from_le_bytes(*read_chunk(&mut buf)?);
+                Ok((d0, d1))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let len = u32::from_le_bytes(*read_chunk(&mut buf)?);
+        let rows = (0..len)
+            .map(|_| Ok(u32::from_le_bytes(*read_chunk(&mut buf)?)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !buf.is_empty() || (displacements.is_empty() && !rows.is_empty()) {
+            return Err(ChdError::Corrupted)
+        }
+        Ok(Self { seed, displacements, rows })
+    }
+}
+
+/// Hash of a key: its bucket, and the two values its slot is displaced from.
+struct KeyHash {
+    bucket: u64,
+    f0: u32,
+    f1: u32,
+}
+
+impl KeyHash {
+    fn new(key: &[u8], seed: u64) -> Self {
+        let hash = xxh3_128_with_seed(key, seed);
+        Self { bucket: hash as u64, f0: (hash >> 64) as u32, f1: (hash >> 96) as u32 }
+    }
+
+    /// Returns the slot of the key in a table of `len` slots, with the displacement of its
+    /// bucket. Every slot is reached by some `d1`, so a bucket of a single key always fits.
+    fn slot(&self, d0: u32, d1: u32, len: u64) -> usize {
+        let displaced = self.f1 as u64 % len + (self.f0 as u64 * d0 as u64) % len + d1 as u64;
+        (displaced % len) as usize
+    }
+}
+
+/// Reads a chunk of `N` bytes from the start of the buffer.
+fn read_chunk<'a, const N: usize>(buf: &mut &'a [u8]) -> Result<&'a [u8; N], ChdError> {
+    let (chunk, rest) = buf.split_first_chunk::<N>().ok_or(ChdError::Corrupted)?;
+    *buf = rest;
+    Ok(chunk)
+}
+
+/// Error returned by [`ChdIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChdError {
+    /// No displacements were found for the keys.
+    Build,
+    /// The encoded index is truncated or has trailing bytes.
+    Corrupted,
+}
+
+impl fmt::Display for ChdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Build => write!(f, "chd perfect hashing function can't be built for the keys"),
+            Self::Corrupted => write!(f, "chd perfect hashing function is corrupted"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ChdError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chd_index() {
+        let keys = (0..10_000u32).map(|key| key.to_be_bytes()).collect::<Vec<_>>();
+        let index = ChdIndex::build(&keys).unwrap();
+        assert_eq!(index.len(), keys.len());
+        for (row, key) in keys.iter().enumerate() {
+            assert_eq!(index.row(key), Some(row as u64));
+        }
+        // Unknown keys get some row, to be checked by the caller
+        assert!(index.row(b"unknown").unwrap() < keys.len() as u64);
+
+        let mut buf = Vec::new();
+        index.encode(&mut buf);
+        assert_eq!(ChdIndex::decode(&buf), Ok(index));
+        assert_eq!(ChdIndex::decode(&buf[..buf.len() - 1]), Err(ChdError::Corrupted));
+        buf.push(0);
+        assert_eq!(ChdIndex::decode(&buf), Err(ChdError::Corrupted));
+    }
+
+    #[test]
+    fn chd_index_duplicates_and_empty() {
+        let index = ChdIndex::build(&[b"a", b"b", b"a"]).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.row(b"a"), Some(0));
+        assert_eq!(index.row(b"b"), Some(1));
+
+        let index = ChdIndex::build::<&[u8]>(&[]).unwrap();
+        assert!(index.is_empty());
+        assert_eq!(index.row(b"a"), None);
+    }
+}
@@ -1,9 +1,13 @@
-use strum::AsRefStr;
+use serde::{Deserialize, Serialize};
+use strum::{AsRefStr, EnumString};
 
 /// Static File compression types.
 /// Defines the different types of compression that can be applied to static files.
-#[derive(Debug, Copy, Clone, Default, AsRefStr)]
+#[derive(
+    Debug, Copy, Clone, Default, PartialEq, Eq, AsRefStr, EnumString, Serialize, Deserialize,
+)]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 pub enum Compression {
     /// LZ4 compression algorithm.
     /// LZ4 is a lossless data compression algorithm that is focused on compression and decompression speed. 
@@ -21,6 +25,7 @@ pub enum Compression {
     /// Zstd with dictionary is an enhanced compression method using a predefined dictionary for better compression performance
     /// When utilizing a dictionary, Zstd can effectively compress data by referencing pre-sampled data patterns contained within the dictionary
     #[strum(serialize = "zstd-dict")]
+    #[serde(rename = "zstd-dict")]
     ZstdWithDictionary,
     /// No compression.
     /// Indicates that the static file is not compressed.
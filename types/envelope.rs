@@ -0,0 +1,113 @@
+//! Forward-compatible envelope of the headers of Headers static files.
+//!
+//! Header fields introduced by future EIPs are stored as extra fields of the envelope, instead of
+//! changing the encoding of the header, so existing static files don't need to be produced
+//! again. The envelope is stored in a column of its own, after the other columns of the
+//! [`HeadersLayout`](crate::HeadersLayout), and the header column keeps the compact encoded
+//! header. So readers that don't know about the envelope, e.g. the static file provider, read the
+//! header as is and ignore the trailing column, and readers of the envelope decode the fields
+//! they know and ignore the trailing ones they don't.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Version of the [`HeaderEnvelope`] written by this build, recorded in the
+/// [`SegmentHeader`](crate::SegmentHeader) of the static file. Version 1 has no extra fields.
+pub const HEADER_ENVELOPE_VERSION: u8 = 1;
+
+/// Envelope column of a row of a Headers static file: the extra fields of the header, each
+/// prefixed by its length as a little-endian `u32`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderEnvelope<'a> {
+    /// Extra fields, in the order they were introduced by the versions of the envelope.
+    pub extra_fields: Vec<&'a [u8]>,
+}
+
+impl<'a> HeaderEnvelope<'a> {
+    /// Creates a new [`HeaderEnvelope`] without extra fields.
+    pub const fn new() -> Self {
+        Self { extra_fields: Vec::new() }
+    }
+
+    /// Appends an extra field to the envelope.
+    pub fn with_extra_field(mut self, field: &'a [u8]) -> Self {
+        self.extra_fields.push(field);
+        self
+    }
+
+    /// Returns the extra field at the index, or `None` if the envelope was written by a version
+    /// without it.
+    pub fn extra_field(&self, index: usize) -> Option<&'a [u8]> {
+        self.extra_fields.get(index).copied()
+    }
+
+    /// Encodes the envelope.
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.extra_fields.iter().map(|field| 4 + field.len()).sum::<usize>();
+        let mut bytes = Vec::with_capacity(len);
+        for field in &self.extra_fields {
+            bytes.extend_from_slice(&(field.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(field);
+        }
+        bytes
+    }
+
+    /// Decodes the envelope, with all of its extra fields, known or not.
+    pub fn decode(mut bytes: &'a [u8]) -> Result<Self, EnvelopeError> {
+        let mut envelope = Self::new();
+        while !bytes.is_empty() {
+            envelope.extra_fields.push(read_field(&mut bytes)?);
+        }
+        Ok(envelope)
+    }
+}
+
+/// Reads a length-prefixed field from the start of the bytes.
+fn read_field<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], EnvelopeError> {
+    let (len, rest) = bytes.split_first_chunk::<4>().ok_or(EnvelopeError)?;
+    let len = u32::from_le_bytes(*len) as usize;
+    if rest.len() < len {
+        return Err(EnvelopeError)
+    }
+    let (field, rest) = rest.split_at(len);
+    *bytes = rest;
+    Ok(field)
+}
+
+/// Error returned when a [`HeaderEnvelope`] is truncated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvelopeError;
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "header envelope is truncated")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EnvelopeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_envelope() {
+        // Envelopes of the first version have no extra fields
+        let envelope = HeaderEnvelope::new();
+        assert!(envelope.encode().is_empty());
+        assert_eq!(HeaderEnvelope::decode(&[]), Ok(envelope));
+
+        // Fields of later versions are decoded without being understood
+        let envelope = HeaderEnvelope::new().with_extra_field(b"field").with_extra_field(&[7; 32]);
+        let encoded = envelope.encode();
+        assert_eq!(encoded[..9], [5, 0, 0, 0, b'f', b'i', b'e', b'l', b'd']);
+        let decoded = HeaderEnvelope::decode(&encoded).unwrap();
+        assert_eq!(decoded, envelope);
+        assert_eq!(decoded.extra_field(1), Some([7; 32].as_slice()));
+        assert_eq!(decoded.extra_field(2), None);
+
+        assert_eq!(HeaderEnvelope::decode(&encoded[..8]), Err(EnvelopeError));
+        assert_eq!(HeaderEnvelope::decode(&[1, 0]), Err(EnvelopeError));
+    }
+}
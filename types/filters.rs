@@ -1,8 +1,15 @@
-use strum::AsRefStr;
+use crate::StaticFileSegment;
+use alloc::borrow::Cow;
+use core::{fmt, hash::Hasher};
+use serde::{Deserialize, Serialize};
+use siphasher::sip128::{Hasher128, SipHasher13};
+use strum::{AsRefStr, EnumString};
+use xxhash_rust::xxh3::xxh3_128;
 
 /// Static File filters.
 /// Enum representing whether static files use filters or not.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Filters {
     /// Static File uses filters with `InclusionFilter` and `PerfectHashingFunction`.
     WithFilters(InclusionFilter, PerfectHashingFunction),
@@ -15,12 +22,124 @@ impl Filters {
     pub const fn has_filters(&self) -> bool {
         matches!(self, Self::WithFilters(_, _))
     }
+
+    /// Returns the ids of the filters recorded in static files, or `None` without filters.
+    pub const fn ids(&self) -> Option<FilterIds> {
+        match self {
+            Self::WithFilters(inclusion_filter, phf) => {
+                Some(FilterIds::new(*inclusion_filter, *phf))
+            }
+            Self::WithoutFilters => None,
+        }
+    }
+}
+
+impl InclusionFilter {
+    /// Returns the capacity the filter has to be sized with to hold `rows` at the target false
+    /// positive rate. Without a target, the filter is sized to hold exactly `rows`.
+    pub fn capacity(&self, rows: usize, target_fpp: Option<f64>) -> usize {
+        match (self, target_fpp) {
+            (Self::Cuckoo, Some(fpp)) if fpp < CUCKOO_FULL_LOAD_FPP => {
+                // Rounded up by hand, `f64::ceil` requires `std`
+                let capacity = rows as f64 * CUCKOO_FULL_LOAD_FPP / fpp;
+                let truncated = capacity as usize;
+                if (truncated as f64) < capacity {
+                    truncated + 1
+                } else {
+                    truncated
+                }
+            }
+            _ => rows,
+        }
+    }
+
+    /// Returns the false positive rate of the filter sized with `capacity` and holding `rows`.
+    pub fn fpp(&self, rows: usize, capacity: usize) -> f64 {
+        match self {
+            Self::Cuckoo => CUCKOO_FULL_LOAD_FPP * rows as f64 / capacity.max(1) as f64,
+        }
+    }
+}
+
+/// False positive rate of a [`InclusionFilter::Cuckoo`] filled to capacity, with one byte
+/// fingerprints and four entries per bucket: `2 * 4 / 2^8`.
+///
+/// The false positive rate falls linearly with the load of the filter, so oversizing its capacity
+/// lowers it.
+pub const CUCKOO_FULL_LOAD_FPP: f64 = 0.031_25;
+
+/// Hash applied to the keys of the rows, e.g. transaction hashes, before they're added to the
+/// inclusion filter and perfect hashing function of a static file. Lookup keys have to be hashed
+/// with the same function, recorded in the [`SegmentHeader`](crate::SegmentHeader) of every
+/// static file.
+///
+/// Only the kind of hash is recorded: the secret of [`FilterHash::KeyedSipHash`] is supplied to
+/// producers and readers at run time as a [`FilterKey`], so it never ends up in static files or
+/// configuration files.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FilterHash {
+    /// Keys are used as is. Transaction hashes are uniformly distributed, but whoever crafts a
+    /// transaction can grind its hash against the filter.
+    #[default]
+    Identity,
+    /// Keys are hashed with 128-bit XXH3.
+    Xxh3,
+    /// Keys are hashed with 128-bit SipHash-1-3 under the secret [`FilterKey`], so collisions in
+    /// the filter can't be crafted without knowing it.
+    ///
+    /// The static file provider looks up keys as is, so only segments whose lookups by hash are
+    /// served by this crate may hash their keys, see [`FilterHash::is_supported_by`].
+    KeyedSipHash,
+}
+
+impl FilterHash {
+    /// Hashes the key of a row, or a lookup key. Returns `None` if the keys are hashed with
+    /// [`FilterHash::KeyedSipHash`] and the secret is missing.
+    pub fn hash_key<'a>(&self, key: &'a [u8], secret: Option<&FilterKey>) -> Option<Cow<'a, [u8]>> {
+        match self {
+            Self::Identity => Some(Cow::Borrowed(key)),
+            Self::Xxh3 => Some(Cow::Owned(xxh3_128(key).to_le_bytes().to_vec())),
+            Self::KeyedSipHash => {
+                let mut hasher = SipHasher13::new_with_key(&secret?.0);
+                hasher.write(key);
+                Some(Cow::Owned(hasher.finish128().as_bytes().to_vec()))
+            }
+        }
+    }
+
+    /// Returns `true` if the keys of the segment may be hashed with this function. The static
+    /// file provider looks up headers and transactions by hash with the keys as is, so only
+    /// [`StaticFileSegment::Receipts`], looked up by this crate, may hash them.
+    pub const fn is_supported_by(&self, segment: StaticFileSegment) -> bool {
+        matches!(self, Self::Identity) || matches!(segment, StaticFileSegment::Receipts)
+    }
+}
+
+/// Secret key of [`FilterHash::KeyedSipHash`], supplied at run time to the producers and readers
+/// of static files. It's never serialized, and never logged.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct FilterKey([u8; 16]);
+
+impl FilterKey {
+    /// Creates a new [`FilterKey`] from the secret bytes.
+    pub const fn new(secret: [u8; 16]) -> Self {
+        Self(secret)
+    }
+}
+
+impl fmt::Debug for FilterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FilterKey(..)")
+    }
 }
 
 /// Static File inclusion filter. Also see [Filters].
 /// Enum representing different types of inclusion filters for static files.
-#[derive(Debug, Copy, Clone, AsRefStr)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, AsRefStr, EnumString, Serialize, Deserialize)]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 pub enum InclusionFilter {
     #[strum(serialize = "cuckoo")]
     /// Cuckoo filter
@@ -31,8 +150,9 @@ pub enum InclusionFilter {
 
 /// Static File perfect hashing function. Also see [Filters].
 /// Enum representing different types of perfect hashing functions for static files.
-#[derive(Debug, Copy, Clone, AsRefStr)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, AsRefStr, EnumString, Serialize, Deserialize)]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
 pub enum PerfectHashingFunction {
     #[strum(serialize = "fmph")]
     /// Fingerprint-Based Minimal Perfect Hash Function (a specialized hashing technique used to achieve minimal perfect hashing for a set of keys or elements)
@@ -42,4 +162,142 @@ pub enum PerfectHashingFunction {
     #[strum(serialize = "gofmph")]
     /// Fingerprint-Based Minimal Perfect Hash Function with Group Optimization (designed to achieve minimal perfect hashing for a given set of keys or elements)
     GoFmph,
+    #[strum(serialize = "chd")]
+    /// Compress, hash and displace minimal perfect hash function, building faster and querying
+    /// with fewer cache misses than [`PerfectHashingFunction::Fmph`] on the keys of a full static
+    /// file. It's stored in a sidecar of the static file, see [`ChdIndex`](crate::ChdIndex), so
+    /// only segments whose lookups by hash are served by this crate may use it, see
+    /// [`PerfectHashingFunction::is_supported_by`].
+    Chd,
 }
+
+/// Version of the [`InclusionFilter`] and [`PerfectHashingFunction`] ids understood by this
+/// build, bumped whenever one is added. Static files with filters record the version of their
+/// producer with the ids of their filters, see [`FilterIds`].
+pub const FILTERS_VERSION: u64 = 1;
+
+impl InclusionFilter {
+    /// Returns the stable id of the filter, recorded in static files.
+    pub const fn id(&self) -> u16 {
+        match self {
+            Self::Cuckoo => 0,
+        }
+    }
+
+    /// Returns the filter with the id, or `None` if it's not understood by this build.
+    pub const fn from_id(id: u16) -> Option<Self> {
+        match id {
+            0 => Some(Self::Cuckoo),
+            _ => None,
+        }
+    }
+}
+
+impl PerfectHashingFunction {
+    /// Returns the stable id of the perfect hashing function, recorded in static files.
+    pub const fn id(&self) -> u16 {
+        match self {
+            Self::Fmph => 0,
+            Self::GoFmph => 1,
+            Self::Chd => 2,
+        }
+    }
+
+    /// Returns the perfect hashing function with the id, or `None` if it's not understood by
+    /// this build.
+    pub const fn from_id(id: u16) -> Option<Self> {
+        match id {
+            0 => Some(Self::Fmph),
+            1 => Some(Self::GoFmph),
+            2 => Some(Self::Chd),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the segment may be indexed with this function. The static file provider
+    /// looks up headers and transactions by hash with the perfect hashing function of the
+    /// `NippyJar`, so only [`StaticFileSegment::Receipts`], looked up by this crate, may be
+    /// indexed with [`PerfectHashingFunction::Chd`].
+    pub const fn is_supported_by(&self, segment: StaticFileSegment) -> bool {
+        !matches!(self, Self::Chd) || matches!(segment, StaticFileSegment::Receipts)
+    }
+}
+
+/// Ids of the inclusion filter and perfect hashing function of a static file, recorded in its
+/// [`SegmentHeader`](crate::SegmentHeader) with the [`FILTERS_VERSION`] of its producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct FilterIds {
+    /// Id of the inclusion filter, see [`InclusionFilter::id`].
+    pub inclusion_filter: u16,
+    /// Id of the perfect hashing function, see [`PerfectHashingFunction::id`].
+    pub phf: u16,
+    /// Filters version of the producer, which readers need to understand the ids.
+    pub version: u64,
+}
+
+impl FilterIds {
+    /// Returns the ids of the filters, with the current [`FILTERS_VERSION`].
+    pub const fn new(inclusion_filter: InclusionFilter, phf: PerfectHashingFunction) -> Self {
+        Self { inclusion_filter: inclusion_filter.id(), phf: phf.id(), version: FILTERS_VERSION }
+    }
+
+    /// Returns the filters with the ids, failing at the first id that's not understood by this
+    /// build.
+    pub const fn filters(&self) -> Result<Filters, UnsupportedFeature> {
+        let Some(inclusion_filter) = InclusionFilter::from_id(self.inclusion_filter) else {
+            return Err(UnsupportedFeature {
+                feature: StaticFileFeature::InclusionFilter(self.inclusion_filter),
+                required_version: self.version,
+            })
+        };
+        let Some(phf) = PerfectHashingFunction::from_id(self.phf) else {
+            return Err(UnsupportedFeature {
+                feature: StaticFileFeature::PerfectHashingFunction(self.phf),
+                required_version: self.version,
+            })
+        };
+        Ok(Filters::WithFilters(inclusion_filter, phf))
+    }
+}
+
+/// Feature of a static file that may not be understood by the build reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StaticFileFeature {
+    /// Inclusion filter with the id.
+    InclusionFilter(u16),
+    /// Perfect hashing function with the id.
+    PerfectHashingFunction(u16),
+}
+
+impl fmt::Display for StaticFileFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InclusionFilter(id) => write!(f, "inclusion filter {id}"),
+            Self::PerfectHashingFunction(id) => write!(f, "perfect hashing function {id}"),
+        }
+    }
+}
+
+/// Error returned when a static file uses a feature that was introduced after this build, e.g.
+/// by a newer producer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedFeature {
+    /// The feature that's not understood.
+    pub feature: StaticFileFeature,
+    /// Version the feature requires, see [`FILTERS_VERSION`].
+    pub required_version: u64,
+}
+
+impl fmt::Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requires filters version {}, but only {} is supported",
+            self.feature, self.required_version, FILTERS_VERSION
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for UnsupportedFeature {}
@@ -0,0 +1,417 @@
+//! Decoding of the files of a static file from byte slices, without memory maps or file system
+//! access, so it compiles to targets like `wasm32-unknown-unknown`, e.g. for browser tools
+//! inspecting small exported static files.
+//!
+//! A static file is made of a data file holding the possibly compressed columns of every row, an
+//! offsets file (`.off`) with the position of every column in the data file, and a configuration
+//! file (`.conf`) starting with the [`SegmentHeader`]. Reading the files from disk is gated
+//! behind the `fs` feature, see [`JarFiles`].
+
+use crate::{Compression, SegmentHeader};
+use std::{fmt, io::Read};
+
+/// Highest size of a decompressed column, past which LZ4 decompression gives up.
+const MAX_COLUMN_SIZE: usize = 256 * 1024 * 1024;
+
+/// Prefix of the configuration file of a static file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JarConfig {
+    /// Version of the file format.
+    pub version: u64,
+    /// Header of the segment.
+    pub header: SegmentHeader,
+    /// Number of columns of every row.
+    pub columns: usize,
+    /// Number of rows.
+    pub rows: usize,
+}
+
+impl JarConfig {
+    /// Decodes the prefix of the configuration file: version, [`SegmentHeader`], columns and
+    /// rows. The rest of the file, e.g. the compression dictionaries, is not decoded.
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        Self::decode_prefix(&mut bytes)
+    }
+
+    /// Decodes the raw zstd dictionaries of the columns embedded in the configuration file, to be
+    /// passed to [`JarRows::with_dictionaries`]. Returns no dictionaries if the static file isn't
+    /// compressed with [`Compression::ZstdWithDictionary`].
+    pub fn decode_dictionaries(mut bytes: &[u8]) -> Result<Vec<Vec<u8>>, DecodeError> {
+        Self::decode_prefix(&mut bytes)?;
+        // The compressor is optional, and zstd is its first variant
+        let (&has_compressor, mut bytes) = bytes.split_first().ok_or(DecodeError::Truncated)?;
+        if has_compressor == 0 || read_u32(&mut bytes)? != 0 {
+            return Ok(Vec::new())
+        }
+        // State, level, whether dictionaries are used, their highest size and the dictionaries
+        let (_, _, _, _, dictionaries): (u32, i32, bool, u64, Option<Vec<Vec<u8>>>) =
+            bincode::deserialize_from(&mut bytes)
+                .map_err(|err| DecodeError::Config(err.to_string()))?;
+        Ok(dictionaries.unwrap_or_default())
+    }
+
+    /// Decodes the prefix of the configuration file, advancing past it.
+    fn decode_prefix(bytes: &mut &[u8]) -> Result<Self, DecodeError> {
+        let version = read_u64(bytes)?;
+        let header = bincode::deserialize_from(&mut *bytes)
+            .map_err(|err| DecodeError::Config(err.to_string()))?;
+        let columns = read_u64(bytes)? as usize;
+        let rows = read_u64(bytes)? as usize;
+        Ok(Self { version, header, columns, rows })
+    }
+}
+
+/// Offsets file of a static file: the size of every offset in its first byte, followed by the
+/// offset of every column of every row in the data file, and the size of the data file.
+///
+/// It's the only format: offsets files are read and appended to by the `NippyJar` of reth.
+#[derive(Debug, Clone, Copy)]
+pub struct Offsets<'a> {
+    /// Size of every offset in bytes, from 1 to 8.
+    offset_size: usize,
+    /// Offsets, without the first byte.
+    bytes: &'a [u8],
+}
+
+impl<'a> Offsets<'a> {
+    /// Creates the offsets from the bytes of the offsets file.
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        let (&offset_size, bytes) = bytes.split_first().ok_or(DecodeError::Truncated)?;
+        let offset_size = offset_size as usize;
+        if !(1..=8).contains(&offset_size) {
+            return Err(DecodeError::OffsetSize(offset_size))
+        }
+        Ok(Self { offset_size, bytes })
+    }
+
+    /// Returns the number of offsets.
+    pub const fn len(&self) -> usize {
+        self.bytes.len() / self.offset_size
+    }
+
+    /// Returns `true` if there are no offsets.
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the offset at the index, if any.
+    pub fn get(&self, index: usize) -> Option<u64> {
+        let start = index.checked_mul(self.offset_size)?;
+        let offset = self.bytes.get(start..start + self.offset_size)?;
+        let mut buf = [0; 8];
+        buf[..self.offset_size].copy_from_slice(offset);
+        Some(u64::from_le_bytes(buf))
+    }
+}
+
+/// Rows of a static file decoded from the bytes of its data and offsets files.
+#[derive(Debug, Clone)]
+pub struct JarRows<'a> {
+    /// Bytes of the data file.
+    data: &'a [u8],
+    /// Offsets of the columns in the data file.
+    offsets: Offsets<'a>,
+    /// Number of columns of every row.
+    columns: usize,
+    /// Compression of the columns.
+    compression: Compression,
+    /// Zstd dictionaries of the columns, if compressed with [`Compression::ZstdWithDictionary`].
+    dictionaries: Vec<&'a [u8]>,
+}
+
+impl<'a> JarRows<'a> {
+    /// Creates the rows of a static file with the number of columns of its [`JarConfig`], and the
+    /// compression of its configuration, e.g. parsed from its file name.
+    pub fn new(
+        data: &'a [u8],
+        offsets: Offsets<'a>,
+        columns: usize,
+        compression: Compression,
+    ) -> Self {
+        Self { data, offsets, columns: columns.max(1), compression, dictionaries: Vec::new() }
+    }
+
+    /// Sets the raw zstd dictionaries of the columns, required by
+    /// [`Compression::ZstdWithDictionary`].
+    pub fn with_dictionaries(mut self, dictionaries: Vec<&'a [u8]>) -> Self {
+        self.dictionaries = dictionaries;
+        self
+    }
+
+    /// Returns the number of rows.
+    pub fn rows(&self) -> usize {
+        self.offsets.len().saturating_sub(1) / self.columns
+    }
+
+    /// Returns the decompressed column of the row.
+    pub fn column(&self, row: usize, column: usize) -> Result<Vec<u8>, DecodeError> {
+        if row >= self.rows() || column >= self.columns {
+            return Err(DecodeError::OutOfBounds { row, column })
+        }
+        let index = row * self.columns + column;
+        let (Some(start), Some(end)) = (self.offsets.get(index), self.offsets.get(index + 1))
+        else {
+            return Err(DecodeError::Truncated)
+        };
+        let bytes = self.data.get(start as usize..end as usize).ok_or(DecodeError::Truncated)?;
+        decompress(self.compression, bytes, self.dictionaries.get(column).copied())
+    }
+
+    /// Returns the decompressed columns of the row.
+    pub fn row(&self, row: usize) -> Result<Vec<Vec<u8>>, DecodeError> {
+        (0..self.columns).map(|column| self.column(row, column)).collect()
+    }
+}
+
+/// Decompresses a column of a static file, with its zstd dictionary for
+/// [`Compression::ZstdWithDictionary`].
+pub fn decompress(
+    compression: Compression,
+    bytes: &[u8],
+    dictionary: Option<&[u8]>,
+) -> Result<Vec<u8>, DecodeError> {
+    let zstd = |dictionary: &[u8]| -> Result<Vec<u8>, DecodeError> {
+        let mut decompressed = Vec::new();
+        zstd::stream::read::Decoder::with_dictionary(bytes, dictionary)
+            .and_then(|mut decoder| decoder.read_to_end(&mut decompressed))
+            .map_err(|err| DecodeError::Decompress(err.to_string()))?;
+        Ok(decompressed)
+    };
+
+    match compression {
+        Compression::Uncompressed => Ok(bytes.to_vec()),
+        Compression::Zstd => zstd(&[]),
+        Compression::ZstdWithDictionary => zstd(dictionary.ok_or(DecodeError::MissingDictionary)?),
+        Compression::Lz4 => {
+            // The decompressed size is not stored, so the buffer grows until it fits
+            let mut size = bytes.len().saturating_mul(4).max(64);
+            loop {
+                let mut decompressed = vec![0; size];
+                match lz4_flex::block::decompress_into(bytes, &mut decompressed) {
+                    Ok(len) => {
+                        decompressed.truncate(len);
+                        return Ok(decompressed)
+                    }
+                    Err(err) if size >= MAX_COLUMN_SIZE => {
+                        return Err(DecodeError::Decompress(err.to_string()))
+                    }
+                    Err(_) => size = size.saturating_mul(2).min(MAX_COLUMN_SIZE),
+                }
+            }
+        }
+    }
+}
+
+/// Reads a little-endian `u64` from the start of the bytes.
+fn read_u64(bytes: &mut &[u8]) -> Result<u64, DecodeError> {
+    let mut buf = [0; 8];
+    bytes.read_exact(&mut buf).map_err(|_| DecodeError::Truncated)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Reads a little-endian `u32` from the start of the bytes.
+fn read_u32(bytes: &mut &[u8]) -> Result<u32, DecodeError> {
+    let mut buf = [0; 4];
+    bytes.read_exact(&mut buf).map_err(|_| DecodeError::Truncated)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+/// Files of a static file read into memory, to be decoded with [`JarConfig`], [`Offsets`] and
+/// [`JarRows`].
+#[cfg(feature = "fs")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JarFiles {
+    /// Bytes of the configuration file.
+    pub config: Vec<u8>,
+    /// Bytes of the data file.
+    pub data: Vec<u8>,
+    /// Bytes of the offsets file.
+    pub offsets: Vec<u8>,
+}
+
+#[cfg(feature = "fs")]
+impl JarFiles {
+    /// Reads the data file at the path with its configuration and offsets files.
+    pub fn read(path: &std::path::Path) -> std::io::Result<Self> {
+        Ok(Self {
+            config: std::fs::read(path.with_extension("conf"))?,
+            data: std::fs::read(path)?,
+            offsets: std::fs::read(path.with_extension("off"))?,
+        })
+    }
+}
+
+/// Error returned when the files of a static file can't be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// A file ends before the decoded value.
+    Truncated,
+    /// The size of the offsets is not between 1 and 8 bytes.
+    OffsetSize(usize),
+    /// The [`SegmentHeader`] of the configuration file can't be decoded.
+    Config(String),
+    /// The row or column is past the end of the static file.
+    OutOfBounds {
+        /// Requested row.
+        row: usize,
+        /// Requested column.
+        column: usize,
+    },
+    /// The column is compressed with a dictionary that wasn't provided.
+    MissingDictionary,
+    /// The column can't be decompressed.
+    Decompress(String),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "static file is truncated"),
+            Self::OffsetSize(size) => write!(f, "invalid offset size {size}"),
+            Self::Config(err) => write!(f, "invalid static file configuration: {err}"),
+            Self::OutOfBounds { row, column } => {
+                write!(f, "column {column} of row {row} is out of bounds")
+            }
+            Self::MissingDictionary => write!(f, "missing zstd dictionary"),
+            Self::Decompress(err) => write!(f, "failed to decompress column: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SegmentRangeInclusive, StaticFileSegment};
+
+    #[test]
+    fn decodes_dictionaries() {
+        let header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            Some(SegmentRangeInclusive::new(0, 1)),
+            Some(SegmentRangeInclusive::new(0, 1)),
+            StaticFileSegment::Receipts,
+        );
+        let dictionaries = vec![b"first".to_vec(), b"second".to_vec()];
+        let zstd = (0u32, (0u32, 0i32, true, 5000u64, Some(&dictionaries), 2u64));
+        let config = bincode::serialize(&(1u64, &header, 2u64, 2u64, Some(zstd), 100u64)).unwrap();
+        assert_eq!(JarConfig::decode_dictionaries(&config).unwrap(), dictionaries);
+        assert_eq!(JarConfig::decode(&config).unwrap().rows, 2);
+
+        // LZ4 and uncompressed static files have no dictionaries
+        let lz4 = bincode::serialize(&(1u64, &header, 2u64, 2u64, Some(1u32), 100u64)).unwrap();
+        assert!(JarConfig::decode_dictionaries(&lz4).unwrap().is_empty());
+        let none = bincode::serialize(&(1u64, &header, 2u64, 2u64, None::<u32>, 100u64)).unwrap();
+        assert!(JarConfig::decode_dictionaries(&none).unwrap().is_empty());
+        assert!(matches!(
+            JarConfig::decode_dictionaries(&config[..config.len() - 30]),
+            Err(DecodeError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn decodes_rows_from_bytes() {
+        let header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            Some(SegmentRangeInclusive::new(0, 1)),
+            None,
+            StaticFileSegment::Headers,
+        );
+        let config = bincode::serialize(&(1u64, &header, 2u64, 2u64, "rest")).unwrap();
+        assert_eq!(
+            JarConfig::decode(&config).unwrap(),
+            JarConfig { version: 1, header, columns: 2, rows: 2 }
+        );
+        assert_eq!(JarConfig::decode(&config[..4]), Err(DecodeError::Truncated));
+
+        let columns = [b"first".as_slice(), b"1", b"second", b"2"]
+            .map(|column| lz4_flex::block::compress(&column.repeat(20)));
+        let mut data = Vec::new();
+        let mut offsets = vec![4];
+        for column in &columns {
+            offsets.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            data.extend_from_slice(column);
+        }
+        offsets.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+        let rows = JarRows::new(&data, Offsets::new(&offsets).unwrap(), 2, Compression::Lz4);
+        assert_eq!(rows.rows(), 2);
+        assert_eq!(rows.row(1).unwrap(), vec![b"second".repeat(20), b"2".repeat(20)]);
+        assert_eq!(rows.column(2, 0), Err(DecodeError::OutOfBounds { row: 2, column: 0 }));
+        assert_eq!(Offsets::new(&[9]).unwrap_err(), DecodeError::OffsetSize(9));
+    }
+
+    #[test]
+    fn decodes_baseline_config() {
+        use crate::{HeadersLayout, SEGMENT_HEADER_VERSION};
+        use serde::Serialize;
+
+        /// Segment header of the static files written before it was versioned.
+        #[derive(Serialize)]
+        struct BaselineSegmentHeader {
+            expected_block_range: SegmentRangeInclusive,
+            block_range: Option<SegmentRangeInclusive>,
+            tx_range: Option<SegmentRangeInclusive>,
+            segment: StaticFileSegment,
+        }
+
+        let baseline = BaselineSegmentHeader {
+            expected_block_range: SegmentRangeInclusive::new(0, 499_999),
+            block_range: Some(SegmentRangeInclusive::new(0, 9)),
+            tx_range: Some(SegmentRangeInclusive::new(0, 99)),
+            segment: StaticFileSegment::Transactions,
+        };
+        let header = SegmentHeader::new(
+            baseline.expected_block_range,
+            baseline.block_range,
+            baseline.tx_range,
+            baseline.segment,
+        );
+
+        // Configuration files of the original layout decode with default extensions, followed by
+        // the rest of the configuration
+        let config = bincode::serialize(&(1u64, &baseline, 1u64, 100u64, "rest")).unwrap();
+        assert_eq!(
+            JarConfig::decode(&config).unwrap(),
+            JarConfig { version: 1, header: header.clone(), columns: 1, rows: 100 }
+        );
+
+        // Versioned headers round trip with their extensions
+        let mut header = header;
+        header.set_chain_id(Some(1));
+        header.set_headers_layout(HeadersLayout::NoTotalDifficulty);
+        let config = bincode::serialize(&(1u64, &header, 1u64, 100u64, "rest")).unwrap();
+        assert_eq!(JarConfig::decode(&config).unwrap().header, header);
+
+        // Extensions added by newer versions are ignored, but newer layouts are rejected
+        let layout = |version: u16, extensions: &[u8]| {
+            let fields = (
+                u64::MAX,
+                version,
+                baseline.expected_block_range,
+                baseline.block_range,
+                baseline.tx_range,
+                baseline.segment,
+                extensions.to_vec(),
+            );
+            bincode::serialize(&(1u64, fields, 1u64, 100u64)).unwrap()
+        };
+        let extensions = br#"{"chain_id":1,"added_later":[1,2]}"#;
+        let decoded =
+            JarConfig::decode(&layout(SEGMENT_HEADER_VERSION, extensions)).unwrap().header;
+        assert_eq!(decoded.chain_id(), Some(1));
+        assert!(matches!(
+            JarConfig::decode(&layout(SEGMENT_HEADER_VERSION + 1, extensions)),
+            Err(DecodeError::Config(_))
+        ));
+
+        // The schema version is checked before the other extensions are decoded
+        let extensions =
+            br#"{"build":{"version":"0.1.0","git_sha":null,"schema_version":0},"chain_id":"1"}"#;
+        let decoded =
+            JarConfig::decode(&layout(SEGMENT_HEADER_VERSION, extensions)).unwrap().header;
+        assert_eq!(decoded.chain_id(), None);
+        assert!(decoded.check_schema().is_err());
+    }
+}
@@ -1,3 +1,16 @@
+//! Commonly used types for static file usage.
+//!
+//! ## Feature Flags
+//!
+//! - `std` (default): enables the [`std::error::Error`] implementations of the errors, and the
+//!   decoding of static files from byte slices. Without it, the crate is `no_std` and only
+//!   requires `alloc`, e.g. for embedded verifiers parsing segment metadata. The manifest
+//!   declares it as a default feature, so dependents that don't opt out keep `std`.
+//! - `fs`: reads the files of a static file from disk, see `JarFiles`. Implies `std`.
+//! - `schemars`: derives the JSON schema of the [`SegmentHeader`], see
+//!   [`SegmentHeader::json_schema`].
+//! - `clap`: derives `clap::ValueEnum` for the configuration enums. Implies `std`.
+
 #![doc(
     html_logo_url = "https://raw.githubusercontent.com/paradigmxyz/reth/main/assets/reth-docs.png",
     html_favicon_url = "https://avatars0.githubusercontent.com/u/97369466?s=256",
@@ -5,15 +18,40 @@
 )]
 #![cfg_attr(not(test), warn(unused_crate_dependencies))]
 #![cfg_attr(docsrs, feature(doc_cfg, doc_auto_cfg))]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
 
+mod chd;
 mod compression;
+mod envelope;
 mod filters;
+#[cfg(feature = "std")]
+mod jar;
+mod metadata;
 mod segment;
 
 use alloy_primitives::BlockNumber;
+pub use chd::{ChdError, ChdIndex};
 pub use compression::Compression;
-pub use filters::{Filters, InclusionFilter, PerfectHashingFunction};
-pub use segment::{SegmentConfig, SegmentHeader, SegmentRangeInclusive, StaticFileSegment};
+pub use envelope::{EnvelopeError, HeaderEnvelope, HEADER_ENVELOPE_VERSION};
+pub use filters::{
+    FilterHash, FilterIds, FilterKey, Filters, InclusionFilter, PerfectHashingFunction,
+    StaticFileFeature, UnsupportedFeature, CUCKOO_FULL_LOAD_FPP, FILTERS_VERSION,
+};
+#[cfg(feature = "fs")]
+pub use jar::JarFiles;
+#[cfg(feature = "std")]
+pub use jar::{decompress, DecodeError, JarConfig, JarRows, Offsets};
+pub use metadata::{
+    BuildMetadata, IncompatibleSchemaVersion, ReceiptStats, SizeHistogram, TxTypeCount,
+    TxTypeStats, STATIC_FILE_SCHEMA_VERSION,
+};
+pub use segment::{
+    ColumnCodec, ColumnMismatch, HeadersLayout, InvalidSegmentRange, ParseSegmentRangeError,
+    ReceiptKeyMode, SegmentConfig, SegmentConfigBuilder, SegmentConfigError, SegmentHeader,
+    SegmentRangeInclusive, StaticFileSegment, CONTENT_HASH_LEN, SEGMENT_HEADER_VERSION,
+};
 
 /// Default static file block count.
 /// Specifies the number of blocks contained in each static file.
@@ -65,6 +103,62 @@ impl HighestStaticFiles {
     }
 }
 
+/// Lowest static file block numbers, per data segment.
+/// This struct keeps track of the lowest block numbers still available for each type of static
+/// file segment, once older static files are removed by retention.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct LowestStaticFiles {
+    /// Lowest static file block of headers.
+    /// If `None`, no static file is available for headers.
+    pub headers: Option<BlockNumber>,
+    /// Lowest static file block of receipts.
+    /// If `None`, no static file is available for receipts.
+    pub receipts: Option<BlockNumber>,
+    /// Lowest static file block of transactions.
+    /// If `None`, no static file is available for transactions.
+    pub transactions: Option<BlockNumber>,
+}
+
+impl LowestStaticFiles {
+    /// Returns the lowest static file block number for a given segment, if it exists.
+    pub const fn lowest(&self, segment: StaticFileSegment) -> Option<BlockNumber> {
+        match segment {
+            StaticFileSegment::Headers => self.headers,
+            StaticFileSegment::Transactions => self.transactions,
+            StaticFileSegment::Receipts => self.receipts,
+        }
+    }
+
+    /// Returns a mutable reference to the lowest static file block number for a given segment.
+    pub fn as_mut(&mut self, segment: StaticFileSegment) -> &mut Option<BlockNumber> {
+        match segment {
+            StaticFileSegment::Headers => &mut self.headers,
+            StaticFileSegment::Transactions => &mut self.transactions,
+            StaticFileSegment::Receipts => &mut self.receipts,
+        }
+    }
+
+    /// Returns the range of blocks available in static files for a given segment, bounded by the
+    /// highest static file block.
+    pub fn available_range(
+        &self,
+        segment: StaticFileSegment,
+        highest: &HighestStaticFiles,
+    ) -> Option<SegmentRangeInclusive> {
+        SegmentRangeInclusive::try_new(self.lowest(segment)?, highest.highest(segment)?).ok()
+    }
+
+    /// Returns `true` if the block of a given segment is available in static files.
+    pub fn is_available(
+        &self,
+        segment: StaticFileSegment,
+        block: BlockNumber,
+        highest: &HighestStaticFiles,
+    ) -> bool {
+        self.available_range(segment, highest).is_some_and(|range| range.contains(block))
+    }
+}
+
 /// Each static file has a fixed number of blocks. This function calculates the range
 /// where the requested block is positioned. Used for determining the segment filename.
 pub const fn find_fixed_range(block: BlockNumber) -> SegmentRangeInclusive {
@@ -0,0 +1,269 @@
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// Version of the encoding of the rows copied to static files, bumped whenever the codec of a
+/// copied table changes. Rows of static files produced with another version can't be decoded.
+pub const STATIC_FILE_SCHEMA_VERSION: u64 = 1;
+
+/// Version and build of the producer of a static file, recorded at production time.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct BuildMetadata {
+    /// Version of the producer crate.
+    pub version: String,
+    /// Git commit the producer was built from, if it was available at build time.
+    pub git_sha: Option<String>,
+    /// Version of the encoding of the rows, see [`STATIC_FILE_SCHEMA_VERSION`].
+    pub schema_version: u64,
+}
+
+impl BuildMetadata {
+    /// Creates a new [`BuildMetadata`] of the producer, with the current
+    /// [`STATIC_FILE_SCHEMA_VERSION`].
+    pub fn new(version: impl Into<String>, git_sha: Option<impl Into<String>>) -> Self {
+        Self {
+            version: version.into(),
+            git_sha: git_sha.map(Into::into),
+            schema_version: STATIC_FILE_SCHEMA_VERSION,
+        }
+    }
+
+    /// Checks that the rows were encoded with the current [`STATIC_FILE_SCHEMA_VERSION`], so
+    /// they're not misdecoded.
+    pub const fn check_schema(&self) -> Result<(), IncompatibleSchemaVersion> {
+        if self.schema_version != STATIC_FILE_SCHEMA_VERSION {
+            return Err(IncompatibleSchemaVersion {
+                found: self.schema_version,
+                expected: STATIC_FILE_SCHEMA_VERSION,
+            })
+        }
+        Ok(())
+    }
+}
+
+/// Number and size of the transactions of a static file, per transaction type, tallied while they
+/// were copied.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct TxTypeStats {
+    /// Statistics of every transaction type seen, in ascending order of types.
+    types: Vec<TxTypeCount>,
+}
+
+/// Number and size of the transactions of a single type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct TxTypeCount {
+    /// EIP-2718 type of the transactions, `0` for legacy transactions.
+    pub tx_type: u8,
+    /// Number of transactions.
+    pub count: u64,
+    /// Total size of the transactions, as encoded in the database.
+    pub bytes: u64,
+}
+
+impl TxTypeStats {
+    /// Tallies a transaction of the type and size.
+    pub fn add(&mut self, tx_type: u8, bytes: u64) {
+        let index = match self.types.binary_search_by_key(&tx_type, |count| count.tx_type) {
+            Ok(index) => index,
+            Err(index) => {
+                self.types.insert(index, TxTypeCount { tx_type, count: 0, bytes: 0 });
+                index
+            }
+        };
+        self.types[index].count += 1;
+        self.types[index].bytes += bytes;
+    }
+
+    /// Returns the statistics of the transaction type, if any transaction of the type was seen.
+    pub fn get(&self, tx_type: u8) -> Option<&TxTypeCount> {
+        self.types.iter().find(|count| count.tx_type == tx_type)
+    }
+
+    /// Returns the statistics of every transaction type seen, in ascending order of types.
+    pub fn iter(&self) -> impl Iterator<Item = &TxTypeCount> {
+        self.types.iter()
+    }
+
+    /// Returns the total number of transactions.
+    pub fn count(&self) -> u64 {
+        self.types.iter().map(|count| count.count).sum()
+    }
+}
+
+/// Gas usage aggregates of the receipts of a static file, tallied while they were copied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct ReceiptStats {
+    /// Total gas used by the transactions.
+    pub gas_used: u64,
+    /// Total number of logs emitted.
+    pub logs: u64,
+    /// Number of transactions that created a contract.
+    pub contract_creations: u64,
+}
+
+impl ReceiptStats {
+    /// Tallies the receipt of a transaction that used `gas_used` and emitted `logs` logs.
+    pub fn add(&mut self, gas_used: u64, logs: u64, contract_creation: bool) {
+        self.gas_used += gas_used;
+        self.logs += logs;
+        self.contract_creations += u64::from(contract_creation);
+    }
+}
+
+/// Bits of the number of linear buckets per power of two of a [`SizeHistogram`].
+const SIZE_SUB_BUCKET_BITS: u64 = 2;
+
+/// Number of linear buckets per power of two of a [`SizeHistogram`].
+const SIZE_SUB_BUCKETS: u64 = 1 << SIZE_SUB_BUCKET_BITS;
+
+/// Histogram of the sizes of the values of a column of a static file, tallied while its rows were
+/// copied.
+///
+/// Sizes are counted in four linear buckets per power of two, so quantiles are at most 25% above
+/// the exact size, and a histogram of any number of values takes at most a few hundred counts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+pub struct SizeHistogram {
+    /// Number of values per bucket, up to the last non-empty bucket.
+    buckets: Vec<u64>,
+    /// Largest size, in bytes.
+    max: u64,
+}
+
+impl SizeHistogram {
+    /// Tallies a value of `size` bytes.
+    pub fn add(&mut self, size: u64) {
+        let bucket = size_bucket(size);
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.max = self.max.max(size);
+    }
+
+    /// Returns the number of tallied values.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Returns the largest size, in bytes.
+    pub const fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Returns the size below or at which the `numerator / denominator` fraction of the values
+    /// are, rounded up to the upper bound of its bucket, in bytes. `0` if no values were tallied.
+    pub fn quantile(&self, numerator: u64, denominator: u64) -> u64 {
+        let count = self.count();
+        let rank = (count as u128 * numerator.min(denominator) as u128)
+            .div_ceil(denominator.max(1) as u128)
+            .clamp(1, count.max(1) as u128) as u64;
+        let mut seen = 0;
+        for (bucket, values) in self.buckets.iter().enumerate() {
+            seen += values;
+            if seen >= rank {
+                return bucket_upper_bound(bucket).min(self.max)
+            }
+        }
+        0
+    }
+
+    /// Returns the median size, in bytes.
+    pub fn p50(&self) -> u64 {
+        self.quantile(50, 100)
+    }
+
+    /// Returns the 90th percentile of the sizes, in bytes.
+    pub fn p90(&self) -> u64 {
+        self.quantile(90, 100)
+    }
+
+    /// Returns the 99th percentile of the sizes, in bytes.
+    pub fn p99(&self) -> u64 {
+        self.quantile(99, 100)
+    }
+}
+
+/// Returns the bucket of the size: sizes below [`SIZE_SUB_BUCKETS`] have their own bucket, and
+/// every power of two above is split in [`SIZE_SUB_BUCKETS`] linear buckets.
+const fn size_bucket(size: u64) -> usize {
+    if size < SIZE_SUB_BUCKETS {
+        return size as usize
+    }
+    let power = 63 - size.leading_zeros() as u64;
+    let sub_bucket = (size >> (power - SIZE_SUB_BUCKET_BITS)) & (SIZE_SUB_BUCKETS - 1);
+    (SIZE_SUB_BUCKETS * (power - SIZE_SUB_BUCKET_BITS + 1) + sub_bucket) as usize
+}
+
+/// Returns the largest size of the bucket.
+const fn bucket_upper_bound(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < SIZE_SUB_BUCKETS {
+        return bucket
+    }
+    let (power, sub_bucket) =
+        (bucket / SIZE_SUB_BUCKETS + SIZE_SUB_BUCKET_BITS - 1, bucket % SIZE_SUB_BUCKETS);
+    let width = 1 << (power - SIZE_SUB_BUCKET_BITS);
+    (SIZE_SUB_BUCKETS + sub_bucket) * width + (width - 1)
+}
+
+/// Error returned when a static file was produced with another [`STATIC_FILE_SCHEMA_VERSION`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncompatibleSchemaVersion {
+    /// Schema version the static file was produced with.
+    pub found: u64,
+    /// Schema version supported by this build.
+    pub expected: u64,
+}
+
+impl fmt::Display for IncompatibleSchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "static file was produced with schema version {}, but only {} is supported",
+            self.found, self.expected
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IncompatibleSchemaVersion {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_histogram() {
+        let mut histogram = SizeHistogram::default();
+        assert_eq!(histogram.p50(), 0);
+        assert_eq!(histogram.quantile(1, 0), 0);
+        for size in (1..=99).chain([1_000_000]) {
+            histogram.add(size);
+        }
+        // Quantiles are rounded up to their bucket, within 25% of the exact size
+        assert_eq!((histogram.count(), histogram.max()), (100, 1_000_000));
+        assert!((50..=63).contains(&histogram.p50()));
+        assert!((90..=99).contains(&histogram.p90()));
+        assert!((99..=99 * 5 / 4).contains(&histogram.p99()));
+        assert_eq!(histogram.quantile(1, 1), 1_000_000);
+        assert_eq!(histogram.quantile(2, 1), 1_000_000);
+        assert_eq!(histogram.quantile(0, 1), 1);
+    }
+
+    #[test]
+    fn size_buckets() {
+        // Every size falls within the bounds of its bucket, which are contiguous
+        for size in (0..1_000).chain([u32::MAX as u64, u64::MAX - 1, u64::MAX]) {
+            let bucket = size_bucket(size);
+            assert!(size <= bucket_upper_bound(bucket));
+            if bucket > 0 {
+                assert!(bucket_upper_bound(bucket - 1) < size);
+            }
+        }
+    }
+}
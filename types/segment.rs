@@ -1,13 +1,27 @@
 /// The segments refer to different categories or types of data that can be stored in static files.
 /// These segments are defined by the StaticFileSegment enum, which categorizes various types of data that can 
 /// be serialized and stored in a static file format for efficient access and retrieval.
-use crate::{BlockNumber, Compression, Filters, InclusionFilter};
-use alloy_primitives::TxNumber;
+use crate::{
+    BlockNumber, BuildMetadata, Compression, FilterHash, FilterIds, FilterKey, Filters,
+    InclusionFilter, IncompatibleSchemaVersion, PerfectHashingFunction, ReceiptStats,
+    SizeHistogram, TxTypeStats, UnsupportedFeature,
+};
+use alloc::{
+    borrow::Cow,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use alloy_primitives::{hex, TxNumber, B256, B64, U256};
+use core::{fmt, ops::RangeInclusive, str::FromStr};
 use derive_more::Display;
-use serde::{Deserialize, Serialize};
-use std::{ops::RangeInclusive, str::FromStr};
+use serde::{de, ser, ser::SerializeTuple, Deserialize, Deserializer, Serialize, Serializer};
 use strum::{AsRefStr, EnumIter, EnumString};
 
+/// Length of the content hash suffix of content-addressed static file names, in bytes.
+pub const CONTENT_HASH_LEN: usize = 8;
+
 /// Segment of the data that can be moved to static files.
 #[derive(
     Debug,
@@ -26,6 +40,7 @@ use strum::{AsRefStr, EnumIter, EnumString};
     Display,
 )]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub enum StaticFileSegment {
     #[strum(serialize = "headers")]
     /// Static File segment responsible for the `CanonicalHeaders`, `Headers`,
@@ -54,11 +69,11 @@ impl StaticFileSegment {
     /// Returns the default configuration of the segment.
     pub const fn config(&self) -> SegmentConfig {
         let default_config = SegmentConfig {
-            filters: Filters::WithFilters(
-                InclusionFilter::Cuckoo,
-                crate::PerfectHashingFunction::Fmph,
-            ),
+            filters: Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Fmph),
             compression: Compression::Lz4,
+            filter_fpp: None,
+            filter_hash: FilterHash::Identity,
+            filter_key: None,
         };
 
         match self {
@@ -66,7 +81,9 @@ impl StaticFileSegment {
         }
     }
 
-    /// Returns the number of columns for the segment.
+    /// Returns the number of columns for the segment, with the default [`HeadersLayout`]. The
+    /// columns of a static file are checked against its header with
+    /// [`SegmentHeader::check_columns`].
     pub const fn columns(&self) -> usize {
         match self {
             Self::Headers => 3,
@@ -98,7 +115,75 @@ impl StaticFileSegment {
         format!("{prefix}_{}_{}", filters_name, compression.as_ref())
     }
 
+    /// Returns the content-addressed file name for the provided segment and range, suffixed with
+    /// the truncated content hash of the static file.
+    pub fn filename_with_content_hash(
+        &self,
+        block_range: &SegmentRangeInclusive,
+        content_hash: &B64,
+    ) -> String {
+        format!("{}_{}", self.filename(block_range), hex::encode(content_hash))
+    }
+
+    /// Parses a filename into a `StaticFileSegment`, its expected block range and the content hash
+    /// if the file name is content-addressed.
+    pub fn parse_content_addressed_filename(
+        name: &str,
+    ) -> Option<(Self, SegmentRangeInclusive, Option<B64>)> {
+        let (segment, block_range) = Self::parse_filename(name)?;
+
+        // Content hash is the only part following the block range
+        let mut parts = name.split('_').skip(5);
+        let content_hash = match (parts.next(), parts.next()) {
+            (Some(hash), None) if hash.len() == CONTENT_HASH_LEN * 2 => {
+                Some(hex::decode_to_array(hash).ok()?.into())
+            }
+            _ => None,
+        };
+
+        Some((segment, block_range, content_hash))
+    }
+
+    /// Parses a filename into a `StaticFileSegment`, its expected block range, and the filters
+    /// and compression if the file name was generated by
+    /// [`StaticFileSegment::filename_with_configuration`].
+    pub fn parse_configured_filename(
+        name: &str,
+    ) -> Option<(Self, SegmentRangeInclusive, Option<SegmentConfig>)> {
+        let (segment, block_range) = Self::parse_filename(name)?;
+
+        // Filters and compression are the only parts following the block range
+        let mut parts = name.split('_').skip(5);
+        let configuration = match (parts.next(), parts.next(), parts.next()) {
+            (Some(filters), Some(compression), None) => {
+                let filters = match filters.split_once('-') {
+                    Some((inclusion_filter, phf)) => Filters::WithFilters(
+                        InclusionFilter::from_str(inclusion_filter).ok()?,
+                        PerfectHashingFunction::from_str(phf).ok()?,
+                    ),
+                    None if filters == "none" => Filters::WithoutFilters,
+                    None => return None,
+                };
+                let compression = Compression::from_str(compression).ok()?;
+                Some(SegmentConfig {
+                    filters,
+                    compression,
+                    filter_fpp: None,
+                    filter_hash: FilterHash::Identity,
+                    filter_key: None,
+                })
+            }
+            _ => None,
+        };
+
+        Some((segment, block_range, configuration))
+    }
+
     /// Parses a filename into a `StaticFileSegment` and its expected block range.
+    ///
+    /// Configuration and content hash suffixes following the block range are accepted, see
+    /// [`StaticFileSegment::parse_configured_filename`] and
+    /// [`StaticFileSegment::parse_content_addressed_filename`] to parse them.
     pub fn parse_filename(name: &str) -> Option<(Self, SegmentRangeInclusive)> {
         let mut parts = name.split('_');
         if !(parts.next() == Some("static") && parts.next() == Some("file")) {
@@ -126,13 +211,262 @@ impl StaticFileSegment {
     }
 }
 
+/// Columns of the rows of a Headers static file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum HeadersLayout {
+    /// Header, total difficulty and block hash.
+    #[default]
+    WithTotalDifficulty,
+    /// Header and block hash, for chains where total difficulty is irrelevant, e.g. post-merge
+    /// only networks and OP chains. Static files with this layout
+    /// [need the static file reader](SegmentHeader::needs_static_file_reader), so they're never
+    /// written to directories a node reads from.
+    NoTotalDifficulty,
+}
+
+impl HeadersLayout {
+    /// Returns the number of columns of the rows.
+    pub const fn columns(&self) -> usize {
+        match self {
+            Self::WithTotalDifficulty => 3,
+            Self::NoTotalDifficulty => 2,
+        }
+    }
+
+    /// Returns `true` if the rows store the total difficulty of the block.
+    pub const fn has_total_difficulty(&self) -> bool {
+        matches!(self, Self::WithTotalDifficulty)
+    }
+
+    /// Returns the index of the column of the block hash, the last column of the layout. A
+    /// [`HeaderEnvelope`](crate::HeaderEnvelope) column may follow it.
+    pub const fn block_hash_column(&self) -> usize {
+        self.columns() - 1
+    }
+}
+
+/// Keys of the perfect hashing function of a Receipts static file, recorded in its
+/// [`SegmentHeader`] so readers know which lookups it supports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptKeyMode {
+    /// Receipts are looked up by transaction number. Lookups by hash go through the
+    /// Transactions static file of the same block range.
+    #[default]
+    TxNumber,
+    /// The perfect hashing function maps transaction hashes to rows, so receipts are looked up
+    /// by hash without the Transactions static file or the database index.
+    TxHash,
+}
+
+impl ReceiptKeyMode {
+    /// Returns `true` if receipts can be looked up by transaction hash on their own.
+    pub const fn is_tx_hash(&self) -> bool {
+        matches!(self, Self::TxHash)
+    }
+}
+
+/// Codec of a column of a static file, recorded by id in its [`SegmentHeader`] so columns added
+/// to a segment later are detected instead of misdecoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnCodec {
+    /// Compact encoding of a header.
+    Header,
+    /// Compact encoding of the total difficulty of a block.
+    TotalDifficulty,
+    /// Hash of a block.
+    BlockHash,
+    /// Compact encoding of a transaction, without its hash.
+    Transaction,
+    /// Compact encoding of a receipt.
+    Receipt,
+    /// Extra fields of a header, encoded as a [`HeaderEnvelope`](crate::HeaderEnvelope).
+    HeaderEnvelope,
+}
+
+impl ColumnCodec {
+    /// Returns the id of the codec recorded in the [`SegmentHeader`].
+    pub const fn id(&self) -> u16 {
+        match self {
+            Self::Header => 0,
+            Self::TotalDifficulty => 1,
+            Self::BlockHash => 2,
+            Self::Transaction => 3,
+            Self::Receipt => 4,
+            Self::HeaderEnvelope => 5,
+        }
+    }
+
+    /// Returns the codec with the id, or `None` if it was introduced after this build.
+    pub const fn from_id(id: u16) -> Option<Self> {
+        match id {
+            0 => Some(Self::Header),
+            1 => Some(Self::TotalDifficulty),
+            2 => Some(Self::BlockHash),
+            3 => Some(Self::Transaction),
+            4 => Some(Self::Receipt),
+            5 => Some(Self::HeaderEnvelope),
+            _ => None,
+        }
+    }
+}
+
+/// Marker written by the versioned binary layout of the [`SegmentHeader`] in place of the start of
+/// the expected block range, which no static file starts at.
+const SEGMENT_HEADER_MARKER: u64 = u64::MAX;
+
+/// Version of the binary layout of the [`SegmentHeader`]. Static files written before the layout
+/// was versioned only have the block and transaction ranges and the segment, and decode with
+/// default extensions.
+pub const SEGMENT_HEADER_VERSION: u16 = 1;
+
 /// A segment header that contains information common to all segments. Used for storage.
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash, Clone)]
+///
+/// Binary serializers write the [`SEGMENT_HEADER_VERSION`] and the fields of the original layout,
+/// followed by the fields added since as a JSON encoded extension map. Extensions missing from
+/// older static files decode to their default, and the ones added by newer versions are ignored,
+/// so adding a field never breaks the configuration files already on disk. Human-readable
+/// serializers inline the extensions with the other fields.
+#[derive(Debug, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct SegmentHeader {
     expected_block_range: SegmentRangeInclusive,
     block_range: Option<SegmentRangeInclusive>,
     tx_range: Option<SegmentRangeInclusive>,
     segment: StaticFileSegment,
+    /// Fields added after the original layout.
+    #[cfg_attr(feature = "schemars", schemars(flatten, with = "HeaderExtensions<f64>"))]
+    extensions: HeaderExtensions,
+}
+
+/// Fields of the [`SegmentHeader`] added after its original layout, all of which default when
+/// missing.
+///
+/// The false positive rate is kept as its bits, so the header stays [`Eq`] and [`Hash`], and only
+/// human-readable formats show it as a number.
+#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash, Clone)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[serde(default)]
+struct HeaderExtensions<Fpp = u64> {
+    /// Effective false positive rate of the inclusion filter, if the static file has one and it
+    /// was recorded.
+    filter_fpp: Option<Fpp>,
+    /// Hash applied to the keys of the inclusion filter and perfect hashing function.
+    filter_hash: FilterHash,
+    /// Ids of the inclusion filter and perfect hashing function, if the static file has them
+    /// and they were recorded.
+    filter_ids: Option<FilterIds>,
+    /// Version and build of the producer that created the static file, if recorded. Decoded
+    /// before the other extensions, so static files of another schema version are reported as
+    /// such even if their other extensions don't decode.
+    build: Option<BuildMetadata>,
+    /// Total difficulty of the post-merge blocks of the static file, which no longer grows, if
+    /// recorded. Their rows store it as well.
+    #[cfg_attr(feature = "schemars", schemars(with = "Option<String>"))]
+    terminal_difficulty: Option<U256>,
+    /// Columns of the rows, if the segment is [`StaticFileSegment::Headers`].
+    headers_layout: HeadersLayout,
+    /// Id of the chain the rows belong to, if recorded.
+    chain_id: Option<u64>,
+    /// Statistics of the transactions per type, if the segment is
+    /// [`StaticFileSegment::Transactions`] and they were tallied for all of its rows.
+    tx_type_stats: Option<TxTypeStats>,
+    /// Gas usage aggregates of the receipts, if the segment is [`StaticFileSegment::Receipts`]
+    /// and they were tallied for all of its rows.
+    receipt_stats: Option<ReceiptStats>,
+    /// Keys of the perfect hashing function, if the segment is [`StaticFileSegment::Receipts`].
+    receipt_key_mode: ReceiptKeyMode,
+    /// Ids of the [`ColumnCodec`] of every column, if recorded. Static files produced before
+    /// they were recorded have the columns expected by their segment and [`HeadersLayout`].
+    column_codecs: Option<Vec<u16>>,
+    /// Version of the [`HeaderEnvelope`](crate::HeaderEnvelope) with the extra fields of the
+    /// headers, if the segment is [`StaticFileSegment::Headers`] and they have an envelope column.
+    header_envelope: Option<u8>,
+    /// Merkle root over the hashes of the rows, if it was computed when the static file was
+    /// sealed.
+    row_root: Option<B256>,
+    /// Histograms of the sizes of the values of every column, if they were tallied for all rows.
+    column_sizes: Option<Vec<SizeHistogram>>,
+    /// Ids of the zstd dictionaries of the columns in the shared dictionary store, if the static
+    /// file is compressed with [`Compression::ZstdWithDictionary`] and they were stored there.
+    dictionary_ids: Option<Vec<B64>>,
+    /// Whether rows were copied with deduplication, if the segment is
+    /// [`StaticFileSegment::Transactions`]: their inputs may reference chunks of the dedup table
+    /// of the static file.
+    deduplicated: bool,
+}
+
+impl<Fpp> HeaderExtensions<Fpp> {
+    /// Extensions of static files written before they were recorded.
+    const DEFAULT: Self = Self {
+        filter_fpp: None,
+        filter_hash: FilterHash::Identity,
+        filter_ids: None,
+        build: None,
+        terminal_difficulty: None,
+        headers_layout: HeadersLayout::WithTotalDifficulty,
+        chain_id: None,
+        tx_type_stats: None,
+        receipt_stats: None,
+        receipt_key_mode: ReceiptKeyMode::TxNumber,
+        column_codecs: None,
+        header_envelope: None,
+        row_root: None,
+        column_sizes: None,
+        dictionary_ids: None,
+        deduplicated: false,
+    };
+
+    /// Converts the false positive rate to another representation.
+    fn map_fpp<T>(self, f: impl FnOnce(Fpp) -> T) -> HeaderExtensions<T> {
+        let Self {
+            filter_fpp,
+            filter_hash,
+            filter_ids,
+            build,
+            terminal_difficulty,
+            headers_layout,
+            chain_id,
+            tx_type_stats,
+            receipt_stats,
+            receipt_key_mode,
+            column_codecs,
+            header_envelope,
+            row_root,
+            column_sizes,
+            dictionary_ids,
+            deduplicated,
+        } = self;
+        HeaderExtensions {
+            filter_fpp: filter_fpp.map(f),
+            filter_hash,
+            filter_ids,
+            build,
+            terminal_difficulty,
+            headers_layout,
+            chain_id,
+            tx_type_stats,
+            receipt_stats,
+            receipt_key_mode,
+            column_codecs,
+            header_envelope,
+            row_root,
+            column_sizes,
+            dictionary_ids,
+            deduplicated,
+        }
+    }
+}
+
+impl<Fpp> Default for HeaderExtensions<Fpp> {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
 }
 
 impl SegmentHeader {
@@ -148,7 +482,277 @@ impl SegmentHeader {
             block_range,
             tx_range,
             segment,
+            extensions: HeaderExtensions::DEFAULT,
+        }
+    }
+
+    /// Returns the effective false positive rate of the inclusion filter, if it was recorded.
+    pub fn filter_fpp(&self) -> Option<f64> {
+        self.extensions.filter_fpp.map(f64::from_bits)
+    }
+
+    /// Records the effective false positive rate of the inclusion filter.
+    pub fn set_filter_fpp(&mut self, fpp: Option<f64>) {
+        self.extensions.filter_fpp = fpp.map(f64::to_bits);
+    }
+
+    /// Returns the hash applied to the keys of the inclusion filter and perfect hashing function.
+    pub const fn filter_hash(&self) -> FilterHash {
+        self.extensions.filter_hash
+    }
+
+    /// Records the hash applied to the keys of the inclusion filter and perfect hashing function.
+    pub fn set_filter_hash(&mut self, filter_hash: FilterHash) {
+        self.extensions.filter_hash = filter_hash;
+    }
+
+    /// Returns the ids of the inclusion filter and perfect hashing function, if recorded.
+    pub const fn filter_ids(&self) -> Option<FilterIds> {
+        self.extensions.filter_ids
+    }
+
+    /// Records the ids of the inclusion filter and perfect hashing function of the static file.
+    pub fn set_filter_ids(&mut self, filter_ids: Option<FilterIds>) {
+        self.extensions.filter_ids = filter_ids;
+    }
+
+    /// Checks that the inclusion filter and perfect hashing function of the static file are
+    /// understood by this build. Static files without recorded filter ids predate them, and are
+    /// assumed supported.
+    pub const fn check_filters(&self) -> Result<(), UnsupportedFeature> {
+        match &self.extensions.filter_ids {
+            Some(filter_ids) => match filter_ids.filters() {
+                Ok(_) => Ok(()),
+                Err(err) => Err(err),
+            },
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the version and build of the producer that created the static file, if recorded.
+    pub const fn build(&self) -> Option<&BuildMetadata> {
+        self.extensions.build.as_ref()
+    }
+
+    /// Records the version and build of the producer creating the static file.
+    pub fn set_build(&mut self, build: Option<BuildMetadata>) {
+        self.extensions.build = build;
+    }
+
+    /// Checks that the rows of the static file can be decoded by this build. Static files
+    /// without recorded build metadata predate it, and are assumed compatible.
+    pub const fn check_schema(&self) -> Result<(), IncompatibleSchemaVersion> {
+        match &self.extensions.build {
+            Some(build) => build.check_schema(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the total difficulty of the post-merge blocks of the static file, if recorded.
+    pub const fn terminal_difficulty(&self) -> Option<U256> {
+        self.extensions.terminal_difficulty
+    }
+
+    /// Records the total difficulty of the first post-merge block of the static file in the
+    /// header. Rows keep storing the total difficulty of every block, as the static file provider
+    /// reads it from them.
+    pub fn record_total_difficulty(&mut self, post_merge: bool, total_difficulty: U256) {
+        if post_merge && self.extensions.terminal_difficulty.is_none() {
+            self.extensions.terminal_difficulty = Some(total_difficulty);
+        }
+    }
+
+    /// Returns the columns of the rows of a Headers static file.
+    pub const fn headers_layout(&self) -> HeadersLayout {
+        self.extensions.headers_layout
+    }
+
+    /// Records the columns of the rows of a Headers static file.
+    pub fn set_headers_layout(&mut self, headers_layout: HeadersLayout) {
+        self.extensions.headers_layout = headers_layout;
+    }
+
+    /// Returns the id of the chain the rows belong to, if recorded.
+    pub const fn chain_id(&self) -> Option<u64> {
+        self.extensions.chain_id
+    }
+
+    /// Records the id of the chain the rows belong to.
+    pub fn set_chain_id(&mut self, chain_id: Option<u64>) {
+        self.extensions.chain_id = chain_id;
+    }
+
+    /// Returns the statistics of the transactions per type, if they were tallied for all rows.
+    pub const fn tx_type_stats(&self) -> Option<&TxTypeStats> {
+        self.extensions.tx_type_stats.as_ref()
+    }
+
+    /// Returns the statistics of the transactions per type to tally appended rows, if they're
+    /// tallied.
+    pub fn tx_type_stats_mut(&mut self) -> Option<&mut TxTypeStats> {
+        self.extensions.tx_type_stats.as_mut()
+    }
+
+    /// Records the statistics of the transactions per type. `None` if they weren't tallied for
+    /// all rows.
+    pub fn set_tx_type_stats(&mut self, tx_type_stats: Option<TxTypeStats>) {
+        self.extensions.tx_type_stats = tx_type_stats;
+    }
+
+    /// Returns the gas usage aggregates of the receipts, if they were tallied for all rows.
+    pub const fn receipt_stats(&self) -> Option<&ReceiptStats> {
+        self.extensions.receipt_stats.as_ref()
+    }
+
+    /// Returns the gas usage aggregates of the receipts to tally appended rows, if they're
+    /// tallied.
+    pub fn receipt_stats_mut(&mut self) -> Option<&mut ReceiptStats> {
+        self.extensions.receipt_stats.as_mut()
+    }
+
+    /// Records the gas usage aggregates of the receipts. `None` if they weren't tallied for all
+    /// rows.
+    pub fn set_receipt_stats(&mut self, receipt_stats: Option<ReceiptStats>) {
+        self.extensions.receipt_stats = receipt_stats;
+    }
+
+    /// Returns the keys of the perfect hashing function of a Receipts static file.
+    pub const fn receipt_key_mode(&self) -> ReceiptKeyMode {
+        self.extensions.receipt_key_mode
+    }
+
+    /// Records the keys of the perfect hashing function of a Receipts static file.
+    pub fn set_receipt_key_mode(&mut self, receipt_key_mode: ReceiptKeyMode) {
+        self.extensions.receipt_key_mode = receipt_key_mode;
+    }
+
+    /// Returns the version of the [`HeaderEnvelope`](crate::HeaderEnvelope) of the headers, or
+    /// `None` if the static file has no envelope column.
+    pub const fn header_envelope(&self) -> Option<u8> {
+        self.extensions.header_envelope
+    }
+
+    /// Records the version of the [`HeaderEnvelope`](crate::HeaderEnvelope) of the headers.
+    pub fn set_header_envelope(&mut self, header_envelope: Option<u8>) {
+        self.extensions.header_envelope = header_envelope;
+    }
+
+    /// Returns the Merkle root over the hashes of the rows, if it was computed when the static
+    /// file was sealed.
+    pub const fn row_root(&self) -> Option<B256> {
+        self.extensions.row_root
+    }
+
+    /// Records the Merkle root over the hashes of the rows. `None` if it doesn't cover all rows.
+    pub fn set_row_root(&mut self, row_root: Option<B256>) {
+        self.extensions.row_root = row_root;
+    }
+
+    /// Returns the histograms of the sizes of the values of every column, if they were tallied
+    /// for all rows.
+    pub fn column_sizes(&self) -> Option<&[SizeHistogram]> {
+        self.extensions.column_sizes.as_deref()
+    }
+
+    /// Returns the histograms of the sizes of the values of every column to tally appended rows,
+    /// if they're tallied.
+    pub fn column_sizes_mut(&mut self) -> Option<&mut Vec<SizeHistogram>> {
+        self.extensions.column_sizes.as_mut()
+    }
+
+    /// Records the histograms of the sizes of the values of every column. `None` if they weren't
+    /// tallied for all rows.
+    pub fn set_column_sizes(&mut self, column_sizes: Option<Vec<SizeHistogram>>) {
+        self.extensions.column_sizes = column_sizes;
+    }
+
+    /// Returns the ids of the zstd dictionaries of the columns in the shared dictionary store, if
+    /// they were stored there.
+    pub fn dictionary_ids(&self) -> Option<&[B64]> {
+        self.extensions.dictionary_ids.as_deref()
+    }
+
+    /// Records the ids of the zstd dictionaries of the columns in the shared dictionary store.
+    pub fn set_dictionary_ids(&mut self, dictionary_ids: Option<Vec<B64>>) {
+        self.extensions.dictionary_ids = dictionary_ids;
+    }
+
+    /// Returns `true` if transactions were copied with deduplication, so their inputs may reference
+    /// chunks of the dedup table of the static file.
+    pub const fn deduplicated(&self) -> bool {
+        self.extensions.deduplicated
+    }
+
+    /// Records that transactions were copied with deduplication.
+    pub fn set_deduplicated(&mut self, deduplicated: bool) {
+        self.extensions.deduplicated = deduplicated;
+    }
+
+    /// Returns the codecs of the columns expected by this build, from the segment, the
+    /// [`HeadersLayout`] and the header envelope.
+    pub fn expected_column_codecs(&self) -> Vec<ColumnCodec> {
+        match self.segment {
+            StaticFileSegment::Headers => {
+                let mut codecs = vec![ColumnCodec::Header];
+                if self.extensions.headers_layout.has_total_difficulty() {
+                    codecs.push(ColumnCodec::TotalDifficulty);
+                }
+                codecs.push(ColumnCodec::BlockHash);
+                // The envelope is the last column, so readers of the other columns ignore it
+                if self.extensions.header_envelope.is_some() {
+                    codecs.push(ColumnCodec::HeaderEnvelope);
+                }
+                codecs
+            }
+            StaticFileSegment::Transactions => vec![ColumnCodec::Transaction],
+            StaticFileSegment::Receipts => vec![ColumnCodec::Receipt],
+        }
+    }
+
+    /// Returns the ids of the codecs of the columns, if they were recorded.
+    pub fn column_codecs(&self) -> Option<&[u16]> {
+        self.extensions.column_codecs.as_deref()
+    }
+
+    /// Records the codecs of the columns written to the static file, which are checked against
+    /// the columns expected from its segment and [`HeadersLayout`] by
+    /// [`SegmentHeader::check_columns`].
+    pub fn set_column_codecs(&mut self, codecs: &[ColumnCodec]) {
+        self.extensions.column_codecs = Some(codecs.iter().map(ColumnCodec::id).collect());
+    }
+
+    /// Checks the codecs recorded in the header, if any, against the columns expected by this
+    /// build.
+    pub fn check_column_codecs(&self) -> Result<(), ColumnMismatch> {
+        let Some(found) = &self.extensions.column_codecs else { return Ok(()) };
+        let expected =
+            self.expected_column_codecs().iter().map(ColumnCodec::id).collect::<Vec<_>>();
+        if *found != expected {
+            return Err(ColumnMismatch::Codecs { expected, found: found.clone() })
+        }
+        Ok(())
+    }
+
+    /// Checks the number of columns of the static file, and the codecs recorded in the header,
+    /// against the columns expected by this build, so rows with other columns aren't misdecoded.
+    pub fn check_columns(&self, columns: usize) -> Result<(), ColumnMismatch> {
+        self.check_column_codecs()?;
+        let expected = self.expected_column_codecs().len();
+        if columns != expected {
+            return Err(ColumnMismatch::Count { expected, found: columns })
         }
+        Ok(())
+    }
+
+    /// Hashes the lookup key with the [`FilterHash`] of the static file, before querying its
+    /// inclusion filter and perfect hashing function. Returns `None` if the keys are hashed with
+    /// a secret that's missing, see [`FilterHash::hash_key`].
+    pub fn filter_key<'a>(
+        &self,
+        key: &'a [u8],
+        secret: Option<&FilterKey>,
+    ) -> Option<Cow<'a, [u8]>> {
+        self.extensions.filter_hash.hash_key(key, secret)
     }
 
     /// Returns the static file segment kind.
@@ -198,16 +802,12 @@ impl SegmentHeader {
 
     /// Number of transactions.
     pub fn tx_len(&self) -> Option<u64> {
-        self.tx_range
-            .as_ref()
-            .map(|r| (r.end() + 1) - r.start())
+        self.tx_range.as_ref().map(SegmentRangeInclusive::len)
     }
 
     /// Number of blocks.
     pub fn block_len(&self) -> Option<u64> {
-        self.block_range
-            .as_ref()
-            .map(|r| (r.end() + 1) - r.start())
+        self.block_range.as_ref().map(SegmentRangeInclusive::len)
     }
 
     /// Increments block end range depending on segment.
@@ -270,8 +870,20 @@ impl SegmentHeader {
                         range.end = range.end.saturating_sub(num);
                     }
                 };
+                // Types and gas usage of the removed rows are unknown, and the perfect hashing
+                // function still maps their hashes
+                if num > 0 {
+                    self.extensions.tx_type_stats = None;
+                    self.extensions.receipt_stats = None;
+                    self.extensions.receipt_key_mode = ReceiptKeyMode::TxNumber;
+                }
             }
         };
+        // The root and the sizes covered the removed rows too
+        if num > 0 {
+            self.extensions.row_root = None;
+            self.extensions.column_sizes = None;
+        }
     }
 
     /// Sets a new `block_range`.
@@ -294,5 +906,1184 @@ impl SegmentHeader {
         }
     }
 
+    /// Returns `true` if the rows can only be decoded by readers that know about the extensions
+    /// of the header, e.g. Headers without the total difficulty column, whose block hash isn't in
+    /// the column the static file provider reads it from, and Transactions copied with
+    /// deduplication. The [`HeaderEnvelope`](crate::HeaderEnvelope) column is ignored by readers
+    /// that don't know about it.
+    pub const fn needs_static_file_reader(&self) -> bool {
+        match self.segment {
+            StaticFileSegment::Headers => !self.extensions.headers_layout.has_total_difficulty(),
+            StaticFileSegment::Transactions => self.extensions.deduplicated,
+            StaticFileSegment::Receipts => false,
+        }
+    }
+
     /// Returns the row offset which depends on whether the segment is block or transaction based.
-   
+    ///
+    /// `None` if the static file [needs the static file reader](Self::needs_static_file_reader):
+    /// the static file provider reads rows by number from this offset, so it finds none in such
+    /// static files instead of decoding them wrong.
+    pub fn start(&self) -> Option<u64> {
+        if self.needs_static_file_reader() {
+            return None
+        }
+        match self.segment {
+            StaticFileSegment::Headers => self.block_start(),
+            StaticFileSegment::Transactions | StaticFileSegment::Receipts => self.tx_start(),
+        }
+    }
+
+    /// Returns all fields of the header as indented JSON, e.g. to be inspected by humans or
+    /// validated against [`SegmentHeader::json_schema`] by external tooling.
+    pub fn to_pretty_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("segment header serializes to JSON")
+    }
+
+    /// Returns the JSON schema of the output of [`SegmentHeader::to_pretty_json`].
+    #[cfg(feature = "schemars")]
+    pub fn json_schema() -> String {
+        serde_json::to_string_pretty(&schemars::schema_for!(Self))
+            .expect("JSON schema serializes to JSON")
+    }
+}
+
+/// Layout of [`SegmentHeader`] used by human-readable serializers, with the extensions inlined.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "SegmentHeader")]
+struct SegmentHeaderFields<E> {
+    expected_block_range: SegmentRangeInclusive,
+    block_range: Option<SegmentRangeInclusive>,
+    tx_range: Option<SegmentRangeInclusive>,
+    segment: StaticFileSegment,
+    #[serde(flatten)]
+    extensions: E,
+}
+
+impl Serialize for SegmentHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return SegmentHeaderFields {
+                expected_block_range: self.expected_block_range,
+                block_range: self.block_range,
+                tx_range: self.tx_range,
+                segment: self.segment,
+                extensions: self.extensions.clone().map_fpp(f64::from_bits),
+            }
+            .serialize(serializer)
+        }
+
+        let extensions = serde_json::to_vec(&self.extensions).map_err(ser::Error::custom)?;
+        let mut tuple = serializer.serialize_tuple(7)?;
+        tuple.serialize_element(&SEGMENT_HEADER_MARKER)?;
+        tuple.serialize_element(&SEGMENT_HEADER_VERSION)?;
+        tuple.serialize_element(&self.expected_block_range)?;
+        tuple.serialize_element(&self.block_range)?;
+        tuple.serialize_element(&self.tx_range)?;
+        tuple.serialize_element(&self.segment)?;
+        tuple.serialize_element(&extensions)?;
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for SegmentHeader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let SegmentHeaderFields {
+                expected_block_range,
+                block_range,
+                tx_range,
+                segment,
+                extensions,
+            } = SegmentHeaderFields::<HeaderExtensions<f64>>::deserialize(deserializer)?;
+            let extensions = extensions.map_fpp(f64::to_bits);
+            return Ok(Self { expected_block_range, block_range, tx_range, segment, extensions })
+        }
+        deserializer.deserialize_tuple(7, SegmentHeaderVisitor)
+    }
+}
+
+/// Visitor of the binary layouts of the [`SegmentHeader`], versioned or not.
+struct SegmentHeaderVisitor;
+
+impl<'de> de::Visitor<'de> for SegmentHeaderVisitor {
+    type Value = SegmentHeader;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a segment header")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let marker: u64 = next_element(&mut seq, 0)?;
+        if marker != SEGMENT_HEADER_MARKER {
+            // Original layout, starting with the expected block range. Its tuple is shorter, so
+            // the remaining elements are never read.
+            let expected_block_range =
+                SegmentRangeInclusive::new(marker, next_element(&mut seq, 1)?);
+            return Ok(SegmentHeader::new(
+                expected_block_range,
+                next_element(&mut seq, 2)?,
+                next_element(&mut seq, 3)?,
+                next_element(&mut seq, 4)?,
+            ))
+        }
+
+        let version: u16 = next_element(&mut seq, 1)?;
+        if version > SEGMENT_HEADER_VERSION {
+            return Err(de::Error::custom(format!(
+                "segment header version {version} is newer than the supported version {SEGMENT_HEADER_VERSION}"
+            )))
+        }
+        let expected_block_range = next_element(&mut seq, 2)?;
+        let block_range = next_element(&mut seq, 3)?;
+        let tx_range = next_element(&mut seq, 4)?;
+        let segment = next_element(&mut seq, 5)?;
+        let extensions: Vec<u8> = next_element(&mut seq, 6)?;
+        // The schema version is checked before the other extensions are decoded, as their
+        // encoding may have changed along with it
+        let BuildExtension { build } =
+            serde_json::from_slice(&extensions).map_err(de::Error::custom)?;
+        let extensions = match &build {
+            Some(metadata) if metadata.check_schema().is_err() => {
+                HeaderExtensions { build, ..HeaderExtensions::DEFAULT }
+            }
+            _ => serde_json::from_slice(&extensions).map_err(de::Error::custom)?,
+        };
+        Ok(SegmentHeader { expected_block_range, block_range, tx_range, segment, extensions })
+    }
+}
+
+/// Build metadata of the [`HeaderExtensions`], decoded on its own.
+#[derive(Deserialize)]
+struct BuildExtension {
+    #[serde(default)]
+    build: Option<BuildMetadata>,
+}
+
+/// Returns the next element of the binary layout of the [`SegmentHeader`], at the index.
+fn next_element<'de, A: de::SeqAccess<'de>, T: Deserialize<'de>>(
+    seq: &mut A,
+    index: usize,
+) -> Result<T, A::Error> {
+    seq.next_element()?.ok_or_else(|| de::Error::invalid_length(index, &"a segment header"))
+}
+
+/// Configuration used on the segment.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SegmentConfig {
+    /// Inclusion filters used on the segment
+    pub filters: Filters,
+    /// Compression used on the segment
+    pub compression: Compression,
+    /// Target false positive rate of the inclusion filter. If `None`, the filter is sized to
+    /// hold exactly the rows of the static file.
+    #[serde(default)]
+    pub filter_fpp: Option<f64>,
+    /// Hash applied to the keys of the inclusion filter and perfect hashing function.
+    #[serde(default)]
+    pub filter_hash: FilterHash,
+    /// Secret of the [`FilterHash::KeyedSipHash`] of the keys, supplied at run time. It's never
+    /// serialized.
+    #[serde(skip)]
+    pub filter_key: Option<FilterKey>,
+}
+
+impl SegmentConfig {
+    /// Checks that the keys of the segment may be hashed with the [`FilterHash`] of the
+    /// configuration, and that its secret is supplied if it needs one. The segment also has to
+    /// support the [`PerfectHashingFunction`] of the configuration.
+    pub const fn check_filter_hash(
+        &self,
+        segment: StaticFileSegment,
+    ) -> Result<(), SegmentConfigError> {
+        let Filters::WithFilters(_, phf) = self.filters else { return Ok(()) };
+        if !phf.is_supported_by(segment) {
+            return Err(SegmentConfigError::UnsupportedPerfectHashingFunction(phf, segment))
+        }
+        if !self.filter_hash.is_supported_by(segment) {
+            return Err(SegmentConfigError::UnsupportedFilterHash(segment))
+        }
+        if matches!(self.filter_hash, FilterHash::KeyedSipHash) && self.filter_key.is_none() {
+            return Err(SegmentConfigError::MissingFilterKey)
+        }
+        Ok(())
+    }
+
+    /// Returns a [`SegmentConfigBuilder`] starting without filters and compression.
+    pub const fn builder() -> SegmentConfigBuilder {
+        SegmentConfigBuilder {
+            inclusion_filter: None,
+            perfect_hashing_function: None,
+            compression: Compression::Uncompressed,
+            dictionary_dataset_rows: None,
+            filter_fpp: None,
+            filter_hash: FilterHash::Identity,
+            filter_key: None,
+            segment: None,
+        }
+    }
+
+    /// Returns a [`SegmentConfigBuilder`] starting with the recommended configuration of the
+    /// segment, see [`StaticFileSegment::config`]. Its filter hash is checked against the
+    /// segment with [`SegmentConfig::check_filter_hash`] when it's built.
+    pub const fn builder_for(segment: StaticFileSegment) -> SegmentConfigBuilder {
+        let config = segment.config();
+        let (inclusion_filter, perfect_hashing_function) = match config.filters {
+            Filters::WithFilters(inclusion_filter, phf) => (Some(inclusion_filter), Some(phf)),
+            Filters::WithoutFilters => (None, None),
+        };
+        SegmentConfigBuilder {
+            inclusion_filter,
+            perfect_hashing_function,
+            compression: config.compression,
+            dictionary_dataset_rows: None,
+            filter_fpp: config.filter_fpp,
+            filter_hash: config.filter_hash,
+            filter_key: None,
+            segment: Some(segment),
+        }
+    }
+}
+
+/// Builder for [`SegmentConfig`], rejecting incompatible combinations on
+/// [`SegmentConfigBuilder::build`] instead of failing when the static file is created.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentConfigBuilder {
+    /// Requested inclusion filter.
+    inclusion_filter: Option<InclusionFilter>,
+    /// Requested perfect hashing function.
+    perfect_hashing_function: Option<PerfectHashingFunction>,
+    /// Requested compression.
+    compression: Compression,
+    /// Number of rows available to train the zstd dictionary on, if known.
+    dictionary_dataset_rows: Option<usize>,
+    /// Requested false positive rate of the inclusion filter.
+    filter_fpp: Option<f64>,
+    /// Requested hash of the filter keys.
+    filter_hash: FilterHash,
+    /// Secret of the requested hash of the filter keys.
+    filter_key: Option<FilterKey>,
+    /// Segment the configuration is built for, if known.
+    segment: Option<StaticFileSegment>,
+}
+
+impl SegmentConfigBuilder {
+    /// Sets the inclusion filter. `None` disables filters.
+    pub const fn inclusion_filter(mut self, inclusion_filter: Option<InclusionFilter>) -> Self {
+        self.inclusion_filter = inclusion_filter;
+        self
+    }
+
+    /// Sets the perfect hashing function used alongside the inclusion filter.
+    pub const fn perfect_hashing_function(mut self, phf: Option<PerfectHashingFunction>) -> Self {
+        self.perfect_hashing_function = phf;
+        self
+    }
+
+    /// Sets the compression.
+    pub const fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets the number of rows available to train the dictionary of
+    /// [`Compression::ZstdWithDictionary`] on.
+    pub const fn dictionary_dataset_rows(mut self, rows: usize) -> Self {
+        self.dictionary_dataset_rows = Some(rows);
+        self
+    }
+
+    /// Sets the target false positive rate of the inclusion filter, which is oversized
+    /// accordingly. `None` sizes the filter to hold exactly the rows of the static file.
+    pub const fn filter_fpp(mut self, fpp: Option<f64>) -> Self {
+        self.filter_fpp = fpp;
+        self
+    }
+
+    /// Sets the hash applied to the keys of the inclusion filter and perfect hashing function.
+    pub const fn filter_hash(mut self, filter_hash: FilterHash) -> Self {
+        self.filter_hash = filter_hash;
+        self
+    }
+
+    /// Sets the secret of [`FilterHash::KeyedSipHash`].
+    pub const fn filter_key(mut self, filter_key: Option<FilterKey>) -> Self {
+        self.filter_key = filter_key;
+        self
+    }
+
+    /// Validates the requested combination and returns the [`SegmentConfig`].
+    pub fn build(self) -> Result<SegmentConfig, SegmentConfigError> {
+        let filters = match (self.inclusion_filter, self.perfect_hashing_function) {
+            (Some(inclusion_filter), Some(phf)) => Filters::WithFilters(inclusion_filter, phf),
+            (None, None) => Filters::WithoutFilters,
+            (None, Some(_)) => return Err(SegmentConfigError::PerfectHashingWithoutFilter),
+            (Some(_), None) => return Err(SegmentConfigError::FilterWithoutPerfectHashing),
+        };
+        if matches!(self.compression, Compression::ZstdWithDictionary) &&
+            matches!(self.dictionary_dataset_rows, Some(0))
+        {
+            return Err(SegmentConfigError::EmptyDictionaryDataset)
+        }
+        if let Some(fpp) = self.filter_fpp {
+            if !filters.has_filters() {
+                return Err(SegmentConfigError::FppWithoutFilter)
+            }
+            if !(fpp > 0.0 && fpp < 1.0) {
+                return Err(SegmentConfigError::InvalidFpp(fpp))
+            }
+        }
+
+        let config = SegmentConfig {
+            filters,
+            compression: self.compression,
+            filter_fpp: self.filter_fpp,
+            filter_hash: self.filter_hash,
+            filter_key: self.filter_key,
+        };
+        if let Some(segment) = self.segment {
+            config.check_filter_hash(segment)?;
+        }
+        Ok(config)
+    }
+}
+
+/// Error returned by [`SegmentConfigBuilder::build`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentConfigError {
+    /// A perfect hashing function was requested without an inclusion filter.
+    PerfectHashingWithoutFilter,
+    /// An inclusion filter was requested without a perfect hashing function.
+    FilterWithoutPerfectHashing,
+    /// Zstd with a dictionary was requested, but there are no rows to train the dictionary on.
+    EmptyDictionaryDataset,
+    /// A false positive rate was requested without an inclusion filter.
+    FppWithoutFilter,
+    /// The requested false positive rate is not between 0 and 1, exclusive.
+    InvalidFpp(f64),
+    /// Keys of the segment are looked up as is by the static file provider, so they can't be
+    /// hashed, see [`FilterHash::is_supported_by`].
+    UnsupportedFilterHash(StaticFileSegment),
+    /// Keys of the segment are looked up by the static file provider with the perfect hashing
+    /// function of the `NippyJar`, see [`PerfectHashingFunction::is_supported_by`].
+    UnsupportedPerfectHashingFunction(PerfectHashingFunction, StaticFileSegment),
+    /// Keys are hashed with [`FilterHash::KeyedSipHash`], but its secret wasn't supplied.
+    MissingFilterKey,
+}
+
+impl fmt::Display for SegmentConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PerfectHashingWithoutFilter => {
+                write!(f, "perfect hashing function requires an inclusion filter")
+            }
+            Self::FilterWithoutPerfectHashing => {
+                write!(f, "inclusion filter requires a perfect hashing function")
+            }
+            Self::EmptyDictionaryDataset => {
+                write!(f, "zstd dictionary compression requires a non-empty dataset")
+            }
+            Self::FppWithoutFilter => write!(f, "false positive rate requires an inclusion filter"),
+            Self::InvalidFpp(fpp) => {
+                write!(f, "false positive rate {fpp} is not between 0 and 1, exclusive")
+            }
+            Self::UnsupportedFilterHash(segment) => {
+                write!(f, "filter keys of {segment} static files can't be hashed")
+            }
+            Self::UnsupportedPerfectHashingFunction(phf, segment) => {
+                write!(
+                    f,
+                    "{segment} static files can't use the {} perfect hashing function",
+                    phf.as_ref()
+                )
+            }
+            Self::MissingFilterKey => write!(f, "keyed filter hash requires a secret key"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SegmentConfigError {}
+
+/// Error returned by [`SegmentHeader::check_columns`] when the columns of a static file are not
+/// the ones expected by this build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnMismatch {
+    /// The static file doesn't have the number of columns of its segment.
+    Count {
+        /// Number of columns expected by this build.
+        expected: usize,
+        /// Number of columns of the static file.
+        found: usize,
+    },
+    /// The codecs recorded in the header are not the ones of its segment, e.g. because columns
+    /// were added to the segment by a newer version.
+    Codecs {
+        /// Ids of the codecs expected by this build.
+        expected: Vec<u16>,
+        /// Ids of the codecs recorded in the header.
+        found: Vec<u16>,
+    },
+}
+
+impl fmt::Display for ColumnMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Count { expected, found } => {
+                write!(f, "static file has {found} columns, but {expected} are expected")
+            }
+            Self::Codecs { expected, found } => {
+                write!(f, "static file has column codecs {found:?}, but {expected:?} are expected")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ColumnMismatch {}
+
+/// Helper type to handle segment transaction and block INCLUSIVE ranges.
+///
+/// They can be modified on a hot loop, which makes the `std::ops::RangeInclusive` a poor fit.
+///
+/// Prefer [`SegmentRangeInclusive::try_new`] when the bounds come from user input or arithmetic,
+/// since an inverted range (`start > end`) silently produces wrong lengths and file names.
+///
+/// Human-readable serializers (JSON, TOML) use the compact `start..=end` string form, while
+/// binary serializers keep the `{ start, end }` struct layout stored in existing static files.
+#[derive(Debug, Eq, PartialEq, Hash, Clone, Copy)]
+pub struct SegmentRangeInclusive {
+    start: u64,
+    end: u64,
+}
+
+impl SegmentRangeInclusive {
+    /// Creates a new [`SegmentRangeInclusive`]
+    pub const fn new(start: u64, end: u64) -> Self {
+        Self { start, end }
+    }
+
+    /// Creates a new [`SegmentRangeInclusive`], rejecting ranges where `start > end`.
+    pub const fn try_new(start: u64, end: u64) -> Result<Self, InvalidSegmentRange> {
+        if start > end {
+            return Err(InvalidSegmentRange { start, end })
+        }
+        Ok(Self { start, end })
+    }
+
+    /// Start of the inclusive range
+    pub const fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// End of the inclusive range
+    pub const fn end(&self) -> u64 {
+        self.end
+    }
+
+    /// Number of elements in the range. Returns `0` for an inverted range.
+    pub const fn len(&self) -> u64 {
+        if self.is_empty() {
+            0
+        } else {
+            self.end - self.start + 1
+        }
+    }
+
+    /// Returns `true` if the range is inverted, and therefore contains no elements.
+    pub const fn is_empty(&self) -> bool {
+        self.start > self.end
+    }
+
+    /// Returns `true` if `value` is within the range.
+    pub const fn contains(&self, value: u64) -> bool {
+        self.start <= value && value <= self.end
+    }
+}
+
+impl fmt::Display for SegmentRangeInclusive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..={}", self.start, self.end)
+    }
+}
+
+impl FromStr for SegmentRangeInclusive {
+    type Err = ParseSegmentRangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) =
+            s.split_once("..=").ok_or_else(|| ParseSegmentRangeError::Format(s.to_string()))?;
+        let start = start.parse().map_err(|_| ParseSegmentRangeError::Format(s.to_string()))?;
+        let end = end.parse().map_err(|_| ParseSegmentRangeError::Format(s.to_string()))?;
+
+        Ok(Self::try_new(start, end)?)
+    }
+}
+
+impl TryFrom<(u64, u64)> for SegmentRangeInclusive {
+    type Error = InvalidSegmentRange;
+
+    fn try_from((start, end): (u64, u64)) -> Result<Self, Self::Error> {
+        Self::try_new(start, end)
+    }
+}
+
+impl From<RangeInclusive<u64>> for SegmentRangeInclusive {
+    fn from(value: RangeInclusive<u64>) -> Self {
+        Self { start: *value.start(), end: *value.end() }
+    }
+}
+
+impl From<&SegmentRangeInclusive> for RangeInclusive<u64> {
+    fn from(value: &SegmentRangeInclusive) -> Self {
+        value.start()..=value.end()
+    }
+}
+
+impl From<SegmentRangeInclusive> for RangeInclusive<u64> {
+    fn from(value: SegmentRangeInclusive) -> Self {
+        (&value).into()
+    }
+}
+
+/// Layout of [`SegmentRangeInclusive`] used by binary serializers.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "SegmentRangeInclusive")]
+struct SegmentRangeInclusiveFields {
+    start: u64,
+    end: u64,
+}
+
+impl Serialize for SegmentRangeInclusive {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            SegmentRangeInclusiveFields { start: self.start, end: self.end }.serialize(serializer)
+        }
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl schemars::JsonSchema for SegmentRangeInclusive {
+    fn schema_name() -> String {
+        "SegmentRangeInclusive".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        // Human-readable form, see the `Serialize` implementation
+        let mut schema = <String as schemars::JsonSchema>::json_schema(gen).into_object();
+        schema.string().pattern = Some(r"^\d+\.\.=\d+$".to_string());
+        schema.into()
+    }
+}
+
+impl<'de> Deserialize<'de> for SegmentRangeInclusive {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map_err(de::Error::custom)
+        } else {
+            // Binary layouts are written by this crate only, so they're trusted as is. Rejecting
+            // them here would make static files with pruned (empty) ranges unreadable.
+            let SegmentRangeInclusiveFields { start, end } =
+                SegmentRangeInclusiveFields::deserialize(deserializer)?;
+            Ok(Self { start, end })
+        }
+    }
+}
+
+/// Error returned when constructing a [`SegmentRangeInclusive`] with `start > end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidSegmentRange {
+    /// Requested start of the range.
+    pub start: u64,
+    /// Requested end of the range.
+    pub end: u64,
+}
+
+impl fmt::Display for InvalidSegmentRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid segment range: start {} is greater than end {}", self.start, self.end)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidSegmentRange {}
+
+/// Error returned when parsing a [`SegmentRangeInclusive`] from its `start..=end` string form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseSegmentRangeError {
+    /// The string is not of the `start..=end` form.
+    Format(String),
+    /// The bounds are valid numbers, but `start > end`.
+    Invalid(InvalidSegmentRange),
+}
+
+impl From<InvalidSegmentRange> for ParseSegmentRangeError {
+    fn from(value: InvalidSegmentRange) -> Self {
+        Self::Invalid(value)
+    }
+}
+
+impl fmt::Display for ParseSegmentRangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(s) => write!(f, "expected segment range as `start..=end`, got {s:?}"),
+            Self::Invalid(err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseSegmentRangeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{format, string::ToString, vec};
+
+    #[test]
+    fn test_filename() {
+        let test_vectors = [
+            (StaticFileSegment::Headers, 2..=30, "static_file_headers_2_30", None),
+            (StaticFileSegment::Receipts, 30..=300, "static_file_receipts_30_300", None),
+            (
+                StaticFileSegment::Transactions,
+                1_123_233..=11_223_233,
+                "static_file_transactions_1123233_11223233",
+                None,
+            ),
+            (
+                StaticFileSegment::Headers,
+                2..=30,
+                "static_file_headers_2_30_cuckoo-fmph_lz4",
+                Some((
+                    Compression::Lz4,
+                    Filters::WithFilters(
+                        InclusionFilter::Cuckoo,
+                        crate::PerfectHashingFunction::Fmph,
+                    ),
+                )),
+            ),
+            (
+                StaticFileSegment::Receipts,
+                0..=499_999,
+                "static_file_receipts_0_499999_cuckoo-chd_lz4",
+                Some((
+                    Compression::Lz4,
+                    Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Chd),
+                )),
+            ),
+            (
+                StaticFileSegment::Headers,
+                2..=30,
+                "static_file_headers_2_30_none_zstd",
+                Some((Compression::Zstd, Filters::WithoutFilters)),
+            ),
+        ];
+
+        for (segment, block_range, filename, configuration) in test_vectors {
+            let block_range: SegmentRangeInclusive = block_range.into();
+            if let Some((compression, filters)) = configuration {
+                assert_eq!(
+                    segment.filename_with_configuration(filters, compression, &block_range,),
+                    filename
+                );
+            } else {
+                assert_eq!(segment.filename(&block_range), filename);
+            }
+
+            assert_eq!(StaticFileSegment::parse_filename(filename), Some((segment, block_range)));
+            assert_eq!(
+                StaticFileSegment::parse_configured_filename(filename),
+                Some((
+                    segment,
+                    block_range,
+                    configuration.map(|(compression, filters)| SegmentConfig {
+                        filters,
+                        compression,
+                        filter_fpp: None,
+                        filter_hash: FilterHash::Identity,
+                        filter_key: None,
+                    })
+                ))
+            );
+        }
+
+        // Unknown configuration is rejected
+        assert_eq!(
+            StaticFileSegment::parse_configured_filename("static_file_headers_2_30_bloom-fmph_lz4"),
+            None
+        );
+        assert_eq!(
+            StaticFileSegment::parse_configured_filename("static_file_headers_2_30_none_brotli"),
+            None
+        );
+
+        assert_eq!(StaticFileSegment::parse_filename("static_file_headers_2"), None);
+        assert_eq!(StaticFileSegment::parse_filename("static_file_headers_"), None);
+    }
+
+    #[test]
+    fn test_content_addressed_filename() {
+        let block_range = SegmentRangeInclusive::new(0, 499_999);
+        let content_hash = B64::from([0xaf, 0x13, 0, 0, 0, 0, 0x01, 0xff]);
+        let filename =
+            StaticFileSegment::Headers.filename_with_content_hash(&block_range, &content_hash);
+        assert_eq!(filename, "static_file_headers_0_499999_af130000000001ff");
+
+        assert_eq!(
+            StaticFileSegment::parse_filename(&filename),
+            Some((StaticFileSegment::Headers, block_range))
+        );
+        assert_eq!(
+            StaticFileSegment::parse_content_addressed_filename(&filename),
+            Some((StaticFileSegment::Headers, block_range, Some(content_hash)))
+        );
+        assert_eq!(
+            StaticFileSegment::parse_content_addressed_filename("static_file_headers_0_499999"),
+            Some((StaticFileSegment::Headers, block_range, None))
+        );
+        assert_eq!(
+            StaticFileSegment::parse_content_addressed_filename(
+                "static_file_headers_0_499999_none_zstd"
+            ),
+            Some((StaticFileSegment::Headers, block_range, None))
+        );
+        assert_eq!(
+            StaticFileSegment::parse_content_addressed_filename(
+                "static_file_headers_0_499999_zz130000000001ff"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn segment_config_builder() {
+        assert_eq!(
+            SegmentConfig::builder().build(),
+            Ok(SegmentConfig {
+                filters: Filters::WithoutFilters,
+                compression: Compression::Uncompressed,
+                filter_fpp: None,
+                filter_hash: FilterHash::Identity,
+                filter_key: None,
+            })
+        );
+        assert_eq!(
+            SegmentConfig::builder_for(StaticFileSegment::Headers).build(),
+            Ok(StaticFileSegment::Headers.config())
+        );
+        assert_eq!(
+            SegmentConfig::builder_for(StaticFileSegment::Receipts).inclusion_filter(None).build(),
+            Err(SegmentConfigError::PerfectHashingWithoutFilter)
+        );
+        assert_eq!(
+            SegmentConfig::builder().inclusion_filter(Some(InclusionFilter::Cuckoo)).build(),
+            Err(SegmentConfigError::FilterWithoutPerfectHashing)
+        );
+        assert_eq!(
+            SegmentConfig::builder()
+                .compression(Compression::ZstdWithDictionary)
+                .dictionary_dataset_rows(0)
+                .build(),
+            Err(SegmentConfigError::EmptyDictionaryDataset)
+        );
+        assert_eq!(
+            SegmentConfig::builder().filter_fpp(Some(0.01)).build(),
+            Err(SegmentConfigError::FppWithoutFilter)
+        );
+        assert_eq!(
+            SegmentConfig::builder_for(StaticFileSegment::Headers).filter_fpp(Some(1.5)).build(),
+            Err(SegmentConfigError::InvalidFpp(1.5))
+        );
+        let config = SegmentConfig::builder_for(StaticFileSegment::Headers)
+            .filter_fpp(Some(0.001))
+            .build()
+            .unwrap();
+        assert_eq!(config.filter_fpp, Some(0.001));
+
+        // Configurations are persisted as is
+        let config = StaticFileSegment::Transactions.config();
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            r#"{"filters":{"with_filters":["cuckoo","fmph"]},"compression":"lz4","filter_fpp":null,"filter_hash":"identity"}"#
+        );
+        assert_eq!(serde_json::from_str::<SegmentConfig>(&json).unwrap(), config);
+        // Configurations persisted before the filter hash was configurable hash no keys
+        assert_eq!(
+            serde_json::from_str::<SegmentConfig>(
+                r#"{"filters":{"with_filters":["cuckoo","fmph"]},"compression":"lz4","filter_fpp":null}"#
+            )
+            .unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn post_merge_total_difficulty() {
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            None,
+            None,
+            StaticFileSegment::Headers,
+        );
+        let terminal = U256::from(58_750_000_000_000_000_000_000u128);
+
+        header.record_total_difficulty(false, U256::from(7));
+        assert_eq!(header.terminal_difficulty(), None);
+        header.record_total_difficulty(true, terminal);
+        header.record_total_difficulty(true, terminal + U256::from(1));
+        assert_eq!(header.terminal_difficulty(), Some(terminal));
+    }
+
+    #[test]
+    fn headers_layout() {
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            Some(SegmentRangeInclusive::new(0, 3)),
+            None,
+            StaticFileSegment::Headers,
+        );
+        assert_eq!(header.headers_layout(), HeadersLayout::WithTotalDifficulty);
+        assert_eq!(header.headers_layout().columns(), 3);
+        assert_eq!(header.headers_layout().block_hash_column(), 2);
+        assert_eq!(header.start(), Some(0));
+
+        // The static file provider finds no rows by number in static files without the column
+        header.set_headers_layout(HeadersLayout::NoTotalDifficulty);
+        assert_eq!(header.headers_layout().columns(), 2);
+        assert_eq!(header.headers_layout().block_hash_column(), 1);
+        assert!(!header.headers_layout().has_total_difficulty());
+        assert!(header.needs_static_file_reader());
+        assert_eq!(header.start(), None);
+        assert_eq!(header.block_start(), Some(0));
+        assert_eq!(
+            serde_json::to_string(&HeadersLayout::NoTotalDifficulty).unwrap(),
+            r#""no_total_difficulty""#
+        );
+    }
+
+    #[test]
+    fn deduplicated() {
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            Some(SegmentRangeInclusive::new(0, 3)),
+            Some(SegmentRangeInclusive::new(0, 9)),
+            StaticFileSegment::Transactions,
+        );
+        assert!(!header.deduplicated());
+        assert_eq!(header.start(), Some(0));
+
+        // The static file provider finds no rows by number in deduplicated static files
+        header.set_deduplicated(true);
+        assert!(header.needs_static_file_reader());
+        assert_eq!(header.start(), None);
+        assert_eq!(header.tx_start(), Some(0));
+        let decoded: SegmentHeader =
+            serde_json::from_str(&serde_json::to_string(&header).unwrap()).unwrap();
+        assert!(decoded.deduplicated());
+    }
+
+    #[test]
+    fn receipt_key_mode() {
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            Some(SegmentRangeInclusive::new(0, 10)),
+            Some(SegmentRangeInclusive::new(0, 99)),
+            StaticFileSegment::Receipts,
+        );
+        assert_eq!(header.receipt_key_mode(), ReceiptKeyMode::TxNumber);
+
+        header.set_receipt_key_mode(ReceiptKeyMode::TxHash);
+        assert!(header.receipt_key_mode().is_tx_hash());
+        assert_eq!(serde_json::to_string(&ReceiptKeyMode::TxHash).unwrap(), r#""tx_hash""#);
+
+        // Hashes of removed rows are still keyed by the perfect hashing function
+        header.prune(0);
+        assert!(header.receipt_key_mode().is_tx_hash());
+        header.prune(1);
+        assert_eq!(header.receipt_key_mode(), ReceiptKeyMode::TxNumber);
+    }
+
+    #[test]
+    fn column_codecs() {
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            Some(SegmentRangeInclusive::new(0, 10)),
+            None,
+            StaticFileSegment::Headers,
+        );
+
+        // Columns of static files without recorded codecs are checked against their segment
+        assert_eq!(header.column_codecs(), None);
+        assert_eq!(header.check_columns(3), Ok(()));
+        assert_eq!(header.check_columns(2), Err(ColumnMismatch::Count { expected: 3, found: 2 }));
+
+        header.set_headers_layout(HeadersLayout::NoTotalDifficulty);
+        header.set_column_codecs(&[ColumnCodec::Header, ColumnCodec::BlockHash]);
+        assert_eq!(header.column_codecs(), Some([0, 2].as_slice()));
+        assert_eq!(header.check_columns(2), Ok(()));
+
+        // Columns written for another layout are detected
+        header.set_column_codecs(&[
+            ColumnCodec::Header,
+            ColumnCodec::TotalDifficulty,
+            ColumnCodec::BlockHash,
+        ]);
+        assert_eq!(
+            header.check_columns(3),
+            Err(ColumnMismatch::Codecs { expected: vec![0, 2], found: vec![0, 1, 2] })
+        );
+
+        // Columns added to the segment by a newer version are detected
+        header.extensions.column_codecs = Some(vec![0, 2, 9]);
+        assert_eq!(
+            header.check_columns(3),
+            Err(ColumnMismatch::Codecs { expected: vec![0, 2], found: vec![0, 2, 9] })
+        );
+
+        // The header envelope is an extra trailing column
+        header.set_header_envelope(Some(1));
+        header.set_column_codecs(&[
+            ColumnCodec::Header,
+            ColumnCodec::BlockHash,
+            ColumnCodec::HeaderEnvelope,
+        ]);
+        assert_eq!(header.check_columns(3), Ok(()));
+        let decoded: SegmentHeader =
+            serde_json::from_str(&serde_json::to_string(&header).unwrap()).unwrap();
+        assert_eq!(decoded.header_envelope(), Some(1));
+
+        // The static file provider reads the other columns of enveloped headers
+        header.set_headers_layout(HeadersLayout::WithTotalDifficulty);
+        header.set_column_codecs(&[
+            ColumnCodec::Header,
+            ColumnCodec::TotalDifficulty,
+            ColumnCodec::BlockHash,
+            ColumnCodec::HeaderEnvelope,
+        ]);
+        assert_eq!(header.check_columns(4), Ok(()));
+        assert_eq!(header.start(), Some(0));
+    }
+
+    #[test]
+    fn build_metadata() {
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            None,
+            None,
+            StaticFileSegment::Headers,
+        );
+        assert_eq!(header.check_schema(), Ok(()));
+
+        let build = BuildMetadata::new("1.0.0", Some("0123abcd"));
+        assert_eq!(build.schema_version, crate::STATIC_FILE_SCHEMA_VERSION);
+        header.set_build(Some(build.clone()));
+        assert_eq!(header.build(), Some(&build));
+        assert_eq!(header.check_schema(), Ok(()));
+
+        header.set_build(Some(BuildMetadata { schema_version: 0, ..build }));
+        assert_eq!(
+            header.check_schema(),
+            Err(IncompatibleSchemaVersion {
+                found: 0,
+                expected: crate::STATIC_FILE_SCHEMA_VERSION
+            })
+        );
+    }
+
+    #[test]
+    fn filter_hash() {
+        let key = [7u8; 32];
+        let (first, second) = (FilterKey::new([1; 16]), FilterKey::new([2; 16]));
+        assert_eq!(FilterHash::Identity.hash_key(&key, None).unwrap().as_ref(), key.as_slice());
+        assert_eq!(FilterHash::Xxh3.hash_key(&key, None).unwrap().len(), 16);
+        assert_eq!(FilterHash::Xxh3.hash_key(&key, None), FilterHash::Xxh3.hash_key(&key, None));
+        assert_ne!(
+            FilterHash::KeyedSipHash.hash_key(&key, Some(&first)),
+            FilterHash::KeyedSipHash.hash_key(&key, Some(&second))
+        );
+        assert_eq!(FilterHash::KeyedSipHash.hash_key(&key, None), None);
+        assert_eq!(format!("{first:?}"), "FilterKey(..)");
+
+        // Only the kind of hash is recorded in the header, never its secret
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            None,
+            None,
+            StaticFileSegment::Receipts,
+        );
+        assert_eq!(header.filter_key(&key, None).unwrap().as_ref(), key.as_slice());
+        header.set_filter_hash(FilterHash::KeyedSipHash);
+        assert_eq!(
+            header.filter_key(&key, Some(&first)),
+            FilterHash::KeyedSipHash.hash_key(&key, Some(&first))
+        );
+        assert!(!serde_json::to_string(&header).unwrap().contains("[1,1,"));
+
+        // Keys looked up as is by the static file provider can't be hashed, and keyed hashes
+        // need their secret
+        let builder =
+            |segment| SegmentConfig::builder_for(segment).filter_hash(FilterHash::KeyedSipHash);
+        assert_eq!(
+            builder(StaticFileSegment::Transactions).filter_key(Some(first)).build(),
+            Err(SegmentConfigError::UnsupportedFilterHash(StaticFileSegment::Transactions))
+        );
+        assert_eq!(
+            builder(StaticFileSegment::Receipts).build(),
+            Err(SegmentConfigError::MissingFilterKey)
+        );
+        let config = builder(StaticFileSegment::Receipts).filter_key(Some(first)).build().unwrap();
+        assert_eq!(config.filter_key, Some(first));
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(serde_json::from_str::<SegmentConfig>(&json).unwrap().filter_key, None);
+    }
+
+    #[test]
+    fn filter_fpp() {
+        use crate::CUCKOO_FULL_LOAD_FPP;
+
+        let filter = InclusionFilter::Cuckoo;
+        assert_eq!(filter.capacity(1000, None), 1000);
+        // Targets above the full load rate don't shrink the filter
+        assert_eq!(filter.capacity(1000, Some(0.5)), 1000);
+        assert_eq!(filter.capacity(1000, Some(CUCKOO_FULL_LOAD_FPP / 4.0)), 4000);
+        assert_eq!(filter.fpp(1000, 4000), CUCKOO_FULL_LOAD_FPP / 4.0);
+
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            None,
+            None,
+            StaticFileSegment::Headers,
+        );
+        assert_eq!(header.filter_fpp(), None);
+        header.set_filter_fpp(Some(0.001));
+        assert_eq!(header.filter_fpp(), Some(0.001));
+
+        // Human-readable formats show the rate, the binary encoding keeps its bits
+        let json = header.to_pretty_json();
+        assert!(json.contains("\"filter_fpp\": 0.001"));
+        assert_eq!(serde_json::from_str::<SegmentHeader>(&json).unwrap(), header);
+        let bytes = bincode::serialize(&header).unwrap();
+        let bits = format!("\"filter_fpp\":{}", 0.001f64.to_bits());
+        assert!(bytes.windows(bits.len()).any(|window| window == bits.as_bytes()));
+        assert_eq!(bincode::deserialize::<SegmentHeader>(&bytes).unwrap(), header);
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn json_schema() {
+        let schema =
+            serde_json::from_str::<serde_json::Value>(&SegmentHeader::json_schema()).unwrap();
+        assert_eq!(
+            schema["properties"]["filter_fpp"]["type"],
+            serde_json::json!(["number", "null"])
+        );
+    }
+
+    #[test]
+    fn filter_ids() {
+        use crate::{StaticFileFeature, FILTERS_VERSION};
+
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            None,
+            None,
+            StaticFileSegment::Transactions,
+        );
+        assert_eq!(header.check_filters(), Ok(()));
+
+        let filters = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::GoFmph);
+        header.set_filter_ids(filters.ids());
+        assert_eq!(header.filter_ids().unwrap().filters(), Ok(filters));
+        assert_eq!(header.check_filters(), Ok(()));
+
+        // Perfect hashing function added by a newer producer
+        header.set_filter_ids(Some(FilterIds { inclusion_filter: 0, phf: 9, version: 4 }));
+        assert_eq!(
+            header.check_filters(),
+            Err(UnsupportedFeature {
+                feature: StaticFileFeature::PerfectHashingFunction(9),
+                required_version: 4
+            })
+        );
+        assert!(header
+            .check_filters()
+            .unwrap_err()
+            .to_string()
+            .ends_with(&format!("but only {FILTERS_VERSION} is supported")));
+
+        assert_eq!(Filters::WithoutFilters.ids(), None);
+
+        // Only receipts, looked up by hash by this crate, may be indexed with CHD
+        let filters = Filters::WithFilters(InclusionFilter::Cuckoo, PerfectHashingFunction::Chd);
+        header.set_filter_ids(filters.ids());
+        assert_eq!(header.filter_ids().unwrap().filters(), Ok(filters));
+        let builder = |segment| {
+            SegmentConfig::builder_for(segment)
+                .inclusion_filter(Some(InclusionFilter::Cuckoo))
+                .perfect_hashing_function(Some(PerfectHashingFunction::Chd))
+                .build()
+        };
+        assert_eq!(
+            builder(StaticFileSegment::Transactions),
+            Err(SegmentConfigError::UnsupportedPerfectHashingFunction(
+                PerfectHashingFunction::Chd,
+                StaticFileSegment::Transactions
+            ))
+        );
+        assert_eq!(builder(StaticFileSegment::Receipts).unwrap().filters, filters);
+    }
+
+    #[test]
+    fn segment_range_validation() {
+        assert_eq!(SegmentRangeInclusive::try_new(5, 5), Ok(SegmentRangeInclusive::new(5, 5)));
+        assert_eq!(
+            SegmentRangeInclusive::try_new(6, 5),
+            Err(InvalidSegmentRange { start: 6, end: 5 })
+        );
+        assert!(SegmentRangeInclusive::try_from((1, 0)).is_err());
+
+        let range = SegmentRangeInclusive::new(10, 19);
+        assert_eq!(range.len(), 10);
+        assert!(!range.is_empty());
+        assert!(range.contains(10) && range.contains(19));
+        assert!(!range.contains(9) && !range.contains(20));
+        assert_eq!(SegmentRangeInclusive::new(1, 0).len(), 0);
+    }
+
+    #[test]
+    fn segment_range_string_form() {
+        let range = SegmentRangeInclusive::new(0, 499_999);
+        assert_eq!(range.to_string(), "0..=499999");
+        assert_eq!("0..=499999".parse(), Ok(range));
+
+        assert!(matches!(
+            "5..=4".parse::<SegmentRangeInclusive>(),
+            Err(ParseSegmentRangeError::Invalid(_))
+        ));
+        assert!(matches!(
+            "5..4".parse::<SegmentRangeInclusive>(),
+            Err(ParseSegmentRangeError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn pretty_json() {
+        let mut header = SegmentHeader::new(
+            SegmentRangeInclusive::new(0, 499_999),
+            Some(SegmentRangeInclusive::new(0, 10)),
+            None,
+            StaticFileSegment::Headers,
+        );
+        header.set_chain_id(Some(1));
+
+        let json = header.to_pretty_json();
+        assert!(json.contains("\"expected_block_range\": \"0..=499999\""));
+        assert!(json.contains("\"chain_id\": 1"));
+        assert_eq!(serde_json::from_str::<SegmentHeader>(&json).unwrap(), header);
+    }
+}